@@ -0,0 +1,54 @@
+//! DISTINCT benchmark
+//!
+//! Compares the executor's hashing DISTINCT with the former quadratic scan.
+//!
+//! Run with `cargo bench --bench distinct`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use photondb::query::QueryExecutor;
+use photondb::reql::{Datum, Term, TermType};
+use photondb::storage::{MockStorage, Storage};
+use std::sync::Arc;
+
+/// Sequence of `len` documents with `len / 10` distinct values
+fn sequence(len: usize) -> Vec<Datum> {
+    (0..len)
+        .map(|i| Datum::from(serde_json::json!({"id": i % (len / 10), "tag": "bench"})))
+        .collect()
+}
+
+/// The previous implementation: a linear `contains` scan per element
+fn distinct_quadratic(arr: &[Datum]) -> Vec<Datum> {
+    let mut seen = Vec::new();
+    let mut distinct = Vec::new();
+    for item in arr {
+        if !seen.contains(item) {
+            seen.push(item.clone());
+            distinct.push(item.clone());
+        }
+    }
+    distinct
+}
+
+fn bench_distinct(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let executor = QueryExecutor::new(Arc::new(Storage::new(Box::new(MockStorage::new()))));
+    let mut group = c.benchmark_group("distinct");
+
+    for len in [1_000, 10_000] {
+        let items = sequence(len);
+        let term = Term::new(TermType::Distinct).with_arg(Term::datum(Datum::Array(items.clone())));
+
+        group.bench_with_input(BenchmarkId::new("quadratic", len), &items, |b, items| {
+            b.iter(|| distinct_quadratic(black_box(items)))
+        });
+        group.bench_with_input(BenchmarkId::new("hashed", len), &term, |b, term| {
+            b.iter(|| runtime.block_on(executor.execute(black_box(term))).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_distinct);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+//! Minimal dynamically loaded plugin
+//!
+//! Build with `cargo build --manifest-path examples/plugins/hello_plugin/Cargo.toml`
+//! and load the resulting `cdylib` through `PluginManager::load_plugin`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use photondb::error::{Error, Result};
+use photondb::plugin::{Plugin, PluginCapability, PluginMetadata, PLUGIN_ABI_VERSION};
+use photondb::reql::Datum;
+
+#[derive(Default)]
+pub struct HelloPlugin;
+
+impl Plugin for HelloPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: "hello".to_string(),
+            version: "0.1.0".to_string(),
+            abi_version: PLUGIN_ABI_VERSION,
+            author: "PhotonDB".to_string(),
+            description: "Greets from a shared library".to_string(),
+            capabilities: vec![PluginCapability::QueryOperations],
+        }
+    }
+
+    fn execute(
+        &self,
+        function: &str,
+        args: Vec<Datum>,
+    ) -> Pin<Box<dyn Future<Output = Result<Datum>> + Send + '_>> {
+        let function = function.to_string();
+        Box::pin(async move {
+            match function.as_str() {
+                "greet" => {
+                    let name = args.first().and_then(|d| d.as_string()).unwrap_or("World");
+                    Ok(Datum::String(format!("Hello from a plugin, {}!", name)))
+                }
+                _ => Err(Error::Plugin(format!("Unknown function: {}", function))),
+            }
+        })
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        vec!["greet".to_string()]
+    }
+}
+
+photondb::declare_plugin!(HelloPlugin::default);
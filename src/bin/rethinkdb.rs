@@ -23,13 +23,21 @@
 //! rethinkdb export --db myapp --output backup.json
 //! ```
 
-use clap::{Args, Parser, Subcommand};
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rethinkdb::network::AuthManager;
 use rethinkdb::server::{start_server, SecurityConfig, ServerConfig};
-use rethinkdb::storage::{DefaultStorageEngine, StorageEngine};
-use rethinkdb::Storage;
-use std::path::PathBuf;
+use rethinkdb::storage::slab::{fsck, FsckOptions};
+use rethinkdb::storage::ttl::DEFAULT_SWEEP_INTERVAL;
+use rethinkdb::storage::{
+    export_table, import_table, migrate_btree_to_slab, spawn_ttl_sweeper, BTreeStorage,
+    DatabaseEngine, DefaultStorageEngine, DropMode, ExportFormat, ImportOptions, OverflowPolicy,
+    StorageDatabaseEngine, StorageEngine,
+};
+use rethinkdb::{PluginManager, Storage};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
@@ -128,6 +136,58 @@ struct ServeArgs {
     /// Maximum request body size (MB)
     #[arg(long, default_value = "10")]
     max_body_size: usize,
+
+    /// HTTP queries run at once before further ones are rejected (0 = no limit)
+    #[arg(long, default_value = "256")]
+    max_concurrent_queries: usize,
+
+    /// Changes a websocket changefeed subscriber buffers before it overflows
+    #[arg(long, default_value = "1024", env = "RETHINKDB_CHANGEFEED_BUFFER")]
+    changefeed_buffer: usize,
+
+    /// What happens to a changefeed subscriber that overflows: `disconnect`
+    /// closes it, `coalesce` merges its pending changes per document
+    #[arg(
+        long,
+        default_value = "disconnect",
+        env = "RETHINKDB_CHANGEFEED_OVERFLOW"
+    )]
+    changefeed_overflow: OverflowPolicy,
+
+    /// Close TCP driver connections idle for this many seconds (0 = never)
+    #[arg(long, default_value = "300")]
+    idle_timeout: u64,
+
+    /// Storage backend; `memory` keeps all data in RAM and never touches disk
+    #[arg(long, value_enum, default_value = "slab", env = "RETHINKDB_STORAGE")]
+    storage: StorageBackend,
+
+    /// Threads for parallel slab storage work (0 = one per core)
+    #[arg(long, default_value = "0", env = "RETHINKDB_STORAGE_THREADS")]
+    storage_threads: usize,
+
+    /// Queued storage writes at which writes are refused with 503 (0 = no limit)
+    #[arg(long, default_value = "0", env = "RETHINKDB_MAX_PENDING_WRITES")]
+    max_pending_writes: usize,
+
+    /// Require TCP driver clients to authenticate; `admin` signs in with
+    /// this password
+    #[arg(long, env = "RETHINKDB_ADMIN_PASSWORD", hide_env_values = true)]
+    admin_password: Option<String>,
+
+    /// Load every plugin library in this directory; plugins providing
+    /// authentication check TCP driver clients, falling back to `admin`
+    #[arg(long, env = "RETHINKDB_PLUGIN_DIR")]
+    plugin_dir: Option<PathBuf>,
+}
+
+/// Storage backend for the server
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum StorageBackend {
+    /// Persistent slab storage in the data directory
+    Slab,
+    /// Ephemeral in-memory storage, lost on shutdown
+    Memory,
 }
 
 /// Administrative commands
@@ -149,6 +209,10 @@ enum AdminCommands {
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
+        /// Also drop the database's tables; without it a database that has
+        /// tables is left alone
+        #[arg(long)]
+        cascade: bool,
     },
 
     /// Show database information
@@ -162,6 +226,24 @@ enum AdminCommands {
 
     /// Show storage statistics
     Stats,
+
+    /// Copy a legacy B-Tree store into this slab store
+    ///
+    /// Safe to re-run: an interrupted migration resumes where it stopped.
+    MigrateBtree {
+        /// Path to the B-Tree data file
+        source: PathBuf,
+    },
+
+    /// Check the slab store for inconsistencies, e.g. after a crash
+    ///
+    /// Run it while no server uses the data directory.
+    Fsck {
+        /// Cut a torn metadata log, drop keys pointing to bad slots and free
+        /// orphaned slots
+        #[arg(long)]
+        repair: bool,
+    },
 }
 
 /// Database commands
@@ -180,6 +262,9 @@ enum DbCommands {
         /// Skip confirmation
         #[arg(short, long)]
         force: bool,
+        /// Also drop the database's tables
+        #[arg(long)]
+        cascade: bool,
     },
 
     /// List all databases
@@ -247,11 +332,11 @@ struct ExportArgs {
     #[arg(short, long)]
     table: Option<String>,
 
-    /// Output file path
+    /// Output file path (a directory with one file per table when no table is given)
     #[arg(short, long)]
     output: PathBuf,
 
-    /// Export format (json, csv)
+    /// Export format (json, ndjson, csv)
     #[arg(short, long, default_value = "json")]
     format: String,
 }
@@ -271,13 +356,30 @@ struct ImportArgs {
     #[arg(short, long)]
     input: PathBuf,
 
-    /// Import format (json, csv)
+    /// Import format (json, ndjson, csv)
     #[arg(short, long, default_value = "json")]
     format: String,
 
     /// Skip existing documents
     #[arg(long)]
     skip_existing: bool,
+
+    /// CSV field delimiter
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+
+    /// CSV input has no header row; columns are named by position ("0", "1", ...)
+    #[arg(long)]
+    no_header: bool,
+
+    /// Convert CSV cells that look like numbers, booleans or JSON into those
+    /// types; without it every cell is a string
+    #[arg(long)]
+    type_infer: bool,
+
+    /// Abort on the first malformed row instead of skipping it
+    #[arg(long)]
+    strict: bool,
 }
 
 #[tokio::main]
@@ -335,9 +437,22 @@ async fn serve_command(data_dir: PathBuf, args: ServeArgs) -> anyhow::Result<()>
     info!(version = %rethinkdb::VERSION, "Version information");
 
     // Initialize storage
-    let storage_engine = DefaultStorageEngine::with_defaults(data_dir.to_str().unwrap())?;
-    let storage = Arc::new(Storage::new(Box::new(storage_engine)));
-    info!("✅ Storage initialized at {}", data_dir.display());
+    let storage = match args.storage {
+        StorageBackend::Slab => {
+            let storage_engine = DefaultStorageEngine::with_defaults(data_dir.to_str().unwrap())?
+                .with_threads(args.storage_threads)
+                .with_max_pending_writes(args.max_pending_writes);
+            info!("✅ Storage initialized at {}", data_dir.display());
+            Arc::new(Storage::new(Box::new(storage_engine)))
+        }
+        StorageBackend::Memory => {
+            warn!("⚠️  In-memory storage: data is lost on shutdown");
+            Arc::new(Storage::in_memory())
+        }
+    };
+
+    // Remove expired documents in the background
+    spawn_ttl_sweeper(storage.clone(), DEFAULT_SWEEP_INTERVAL);
 
     // Security configuration
     let security_config = if !args.dev_mode {
@@ -348,6 +463,22 @@ async fn serve_command(data_dir: PathBuf, args: ServeArgs) -> anyhow::Result<()>
         None
     };
 
+    // Authentication of TCP driver clients
+    let auth = match (&args.admin_password, &args.plugin_dir) {
+        (None, None) => None,
+        (password, plugin_dir) => {
+            let mut auth = match password {
+                Some(password) => AuthManager::with_admin(password),
+                None => AuthManager::new(),
+            };
+            if let Some(dir) = plugin_dir {
+                auth = auth.with_plugins(load_plugins(dir).await?);
+            }
+            info!("🔑 TCP clients must authenticate");
+            Some(Arc::new(auth))
+        }
+    };
+
     // Server configuration
     let server_config = ServerConfig {
         http_addr: args.bind.clone(),
@@ -355,30 +486,29 @@ async fn serve_command(data_dir: PathBuf, args: ServeArgs) -> anyhow::Result<()>
         enable_cors: args.cors,
         timeout_secs: args.timeout,
         max_body_size: args.max_body_size * 1024 * 1024,
+        max_concurrent_queries: args.max_concurrent_queries,
+        changefeed_buffer: args.changefeed_buffer,
+        changefeed_overflow: args.changefeed_overflow,
+        ..ServerConfig::default()
     };
 
     info!("🌐 HTTP API starting on {}:{}", args.bind, args.port);
 
-    // Start TCP protocol server (port 28015)
-    let tcp_storage = storage.clone();
-    let tcp_handle = tokio::spawn(async move {
-        use rethinkdb::network::{ProtocolServer, ServerConfig as TcpConfig};
-        
-        let tcp_config = TcpConfig {
-            bind_addr: "0.0.0.0:28015".parse().unwrap(),
-            max_connections: 1024,
-            tls_enabled: false,
-            tls_cert_path: None,
-            tls_key_path: None,
-        };
-        
-        let tcp_server = ProtocolServer::new(tcp_config, tcp_storage);
-        info!("🔌 TCP protocol server starting on port 28015");
-        
-        if let Err(e) = tcp_server.serve().await {
-            error!("TCP server error: {}", e);
-        }
-    });
+    // TCP protocol server (port 28015), started with the HTTP server so
+    // that both run queries on the same executor
+    let idle_timeout = (args.idle_timeout > 0).then(|| std::time::Duration::from_secs(args.idle_timeout));
+    let tcp_config = rethinkdb::network::ServerConfig {
+        bind_addr: "0.0.0.0:28015".parse().unwrap(),
+        max_connections: 1024,
+        max_parallel_queries: 64,
+        idle_timeout,
+        keepalive: Some(rethinkdb::network::server::DEFAULT_KEEPALIVE),
+        tls_enabled: false,
+        tls_cert_path: None,
+        tls_key_path: None,
+        auth,
+        executor: None,
+    };
 
     // Start QUIC protocol server (port 28016) if feature enabled
     #[cfg(feature = "quic")]
@@ -393,38 +523,56 @@ async fn serve_command(data_dir: PathBuf, args: ServeArgs) -> anyhow::Result<()>
                 cert_path: None,
                 key_path: None,
                 auto_cert: true,
+                client_ca_path: None,
             };
             
             let quic_server = QuicProtocolServer::new(quic_config, quic_storage);
             info!("⚡ QUIC protocol server starting on port 28016");
             
             if let Err(e) = quic_server.serve().await {
-                error!("QUIC server error: {}", e);
+                tracing::error!("QUIC server error: {}", e);
             }
         })
     };
 
-    // Start HTTP server
-    let http_result = start_server(server_config, storage, security_config).await;
+    // Start HTTP and TCP servers; returns once SIGINT/SIGTERM shut both down,
+    // after open TCP connections answered their running queries
+    let http_result = start_server(server_config, storage, security_config, Some(tcp_config)).await;
 
-    // Wait for protocol servers
+    // Stop QUIC protocol server
     #[cfg(feature = "quic")]
-    {
-        tokio::select! {
-            _ = tcp_handle => {},
-            _ = quic_handle => {},
-        }
-    }
-    #[cfg(not(feature = "quic"))]
-    {
-        tcp_handle.await?;
-    }
+    quic_handle.abort();
 
     http_result
 }
 
+/// Load every dynamic library in `dir` as a plugin
+async fn load_plugins(dir: &Path) -> anyhow::Result<Arc<PluginManager>> {
+    let plugins = PluginManager::new();
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("reading plugin directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+    for path in paths {
+        if path.extension() != Some(std::ffi::OsStr::new(std::env::consts::DLL_EXTENSION)) {
+            continue;
+        }
+        info!("🔌 Loading plugin {}", path.display());
+        plugins
+            .load_plugin(path.clone())
+            .await
+            .with_context(|| format!("loading plugin {}", path.display()))?;
+    }
+    Ok(Arc::new(plugins))
+}
+
 /// Administrative commands
 async fn admin_command(data_dir: PathBuf, command: AdminCommands) -> anyhow::Result<()> {
+    // Opening the store would already reuse orphaned slots
+    if let AdminCommands::Fsck { repair } = command {
+        return fsck_command(&data_dir, repair);
+    }
     let engine = DefaultStorageEngine::with_defaults(data_dir.to_str().unwrap())?;
 
     match command {
@@ -448,7 +596,11 @@ async fn admin_command(data_dir: PathBuf, command: AdminCommands) -> anyhow::Res
             println!("✅ Database '{}' created", name);
             Ok(())
         }
-        AdminCommands::DropDb { name, force } => {
+        AdminCommands::DropDb {
+            name,
+            force,
+            cascade,
+        } => {
             if !force {
                 print!(
                     "Are you sure you want to drop database '{}'? (yes/no): ",
@@ -463,8 +615,8 @@ async fn admin_command(data_dir: PathBuf, command: AdminCommands) -> anyhow::Res
                     return Ok(());
                 }
             }
-            info!(db = %name, "Dropping database...");
-            engine.drop_database(&name).await?;
+            info!(db = %name, cascade, "Dropping database...");
+            drop_database(engine, &name, cascade).await?;
             println!("✅ Database '{}' dropped", name);
             Ok(())
         }
@@ -485,17 +637,147 @@ async fn admin_command(data_dir: PathBuf, command: AdminCommands) -> anyhow::Res
         }
         AdminCommands::Compact => {
             info!("Compacting storage...");
-            // TODO: Implement compaction
-            println!("⚠️  Compaction not yet implemented");
+            let report = engine.compact()?;
+            println!("✅ Compaction complete");
+            println!(
+                "  Metadata log: {} → {} bytes",
+                report.metadata_bytes_before, report.metadata_bytes_after
+            );
+            println!(
+                "  Slab files:   {} → {} bytes ({} values moved)",
+                report.slab_bytes_before, report.slab_bytes_after, report.slots_moved
+            );
+            println!("  Reclaimed: {} bytes", report.bytes_reclaimed());
             Ok(())
         }
         AdminCommands::Stats => {
             info!("Getting storage statistics...");
-            // TODO: Implement stats
-            println!("⚠️  Statistics not yet implemented");
+            let tables = engine.table_stats();
+            let stats = engine.stats();
+
+            println!("Documents");
+            println!("───────────────────────────────");
+            if tables.is_empty() {
+                println!("  No tables.");
+            }
+            for table in &tables {
+                println!("  {}.{}: {}", table.db, table.table, table.documents);
+            }
+
+            println!();
+            println!("Storage");
+            println!("───────────────────────────────");
+            println!("  Keys: {}", stats.key_count);
+            println!("  On disk: {} bytes", stats.disk_bytes);
+            println!("  Metadata log: {} bytes", stats.metadata_bytes);
+            println!("  Allocated slots: {} bytes", stats.total_allocated);
+            println!(
+                "  Overflow: {} values, {} bytes",
+                stats.overflow_objects, stats.overflow_bytes
+            );
+            println!(
+                "  Values: {} bytes, {} compressed ({:.1}% saved)",
+                stats.compression.original_size,
+                stats.compression.compressed_size,
+                stats.compression.space_saved_percent()
+            );
+            println!(
+                "  Stored uncompressed: {} of {} values",
+                stats.compression.skipped, stats.compression.values
+            );
+            println!(
+                "  Cache: {:.1}% hit rate ({} hits, {} misses)",
+                stats.cache_hit_rate * 100.0,
+                stats.cache_hits,
+                stats.cache_misses
+            );
+
+            println!();
+            println!("Size classes (used/total slots)");
+            println!("───────────────────────────────");
+            for class in stats
+                .size_class_stats
+                .iter()
+                .filter(|class| class.total_slots > 0)
+            {
+                println!(
+                    "  {:>6} B: {}/{}",
+                    class.slot_size, class.allocated_slots, class.total_slots
+                );
+            }
+            Ok(())
+        }
+        AdminCommands::MigrateBtree { source } => {
+            info!(source = %source.display(), "Migrating B-Tree store...");
+            let btree = BTreeStorage::new(source.to_string_lossy().into_owned(), None)?;
+            let summary = migrate_btree_to_slab(&btree, &engine).await?;
+            if summary.resumed {
+                println!("Resumed an interrupted migration");
+            }
+            println!("✅ Migration complete");
+            println!("  Databases: {}", summary.databases);
+            println!("  Tables: {}", summary.tables);
+            println!("  Documents: {}", summary.documents);
             Ok(())
         }
+        AdminCommands::Fsck { .. } => unreachable!("fsck runs without opening the store"),
+    }
+}
+
+/// Check the slab store in `data_dir`, repairing it if asked to
+fn fsck_command(data_dir: &Path, repair: bool) -> anyhow::Result<()> {
+    info!(path = %data_dir.display(), repair, "Checking storage...");
+    let options = FsckOptions {
+        repair,
+        ..FsckOptions::default()
+    };
+    let report = fsck(data_dir, options)?;
+
+    println!("Metadata batches: {}", report.batches);
+    println!("Keys checked:     {}", report.keys_checked);
+    println!("Slots scanned:    {}", report.slots_scanned);
+    if report.torn_log_bytes > 0 {
+        println!(
+            "⚠️  {} bytes after the last valid metadata batch",
+            report.torn_log_bytes
+        );
+    }
+    for issue in &report.issues {
+        println!("⚠️  {}", issue);
     }
+
+    if report.is_clean() {
+        println!("✅ No inconsistencies found");
+    } else if report.repaired {
+        println!(
+            "✅ Repaired; reclaimed {} bytes",
+            report.reclaimable_bytes()
+        );
+    } else {
+        println!(
+            "❌ {} inconsistencies found ({} bytes reclaimable); run with --repair to fix them",
+            report.issues.len() + usize::from(report.torn_log_bytes > 0),
+            report.reclaimable_bytes()
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Drop database `name`, together with its tables only if `cascade` is set
+async fn drop_database(
+    engine: DefaultStorageEngine,
+    name: &str,
+    cascade: bool,
+) -> anyhow::Result<()> {
+    let mode = if cascade {
+        DropMode::Cascade
+    } else {
+        DropMode::Restrict
+    };
+    let databases = StorageDatabaseEngine::new(Arc::new(Storage::new(Box::new(engine))));
+    databases.drop_database_with(name, mode).await?;
+    Ok(())
 }
 
 /// Database commands
@@ -508,7 +790,11 @@ async fn db_command(data_dir: PathBuf, command: DbCommands) -> anyhow::Result<()
             println!("✅ Created database '{}'", name);
             Ok(())
         }
-        DbCommands::Drop { name, force } => {
+        DbCommands::Drop {
+            name,
+            force,
+            cascade,
+        } => {
             if !force {
                 print!("Drop database '{}'? (yes/no): ", name);
                 use std::io::{self, Write};
@@ -519,7 +805,7 @@ async fn db_command(data_dir: PathBuf, command: DbCommands) -> anyhow::Result<()
                     return Ok(());
                 }
             }
-            engine.drop_database(&name).await?;
+            drop_database(engine, &name, cascade).await?;
             println!("✅ Dropped database '{}'", name);
             Ok(())
         }
@@ -608,16 +894,69 @@ async fn table_command(data_dir: PathBuf, command: TableCommands) -> anyhow::Res
 }
 
 /// Export command
-async fn export_command(_data_dir: PathBuf, _args: ExportArgs) -> anyhow::Result<()> {
-    println!("⚠️  Export functionality not yet implemented");
-    // TODO: Implement export
+async fn export_command(data_dir: PathBuf, args: ExportArgs) -> anyhow::Result<()> {
+    let format: ExportFormat = args.format.parse()?;
+    let engine = DefaultStorageEngine::with_defaults(data_dir.to_str().unwrap())?;
+    let storage = Storage::new(Box::new(engine));
+
+    let targets = match &args.table {
+        Some(table) => vec![(table.clone(), args.output.clone())],
+        None => {
+            std::fs::create_dir_all(&args.output)?;
+            storage
+                .list_tables_in_db(&args.db)
+                .await?
+                .into_iter()
+                .map(|table| {
+                    let path = args
+                        .output
+                        .join(format!("{}.{}", table, format.extension()));
+                    (table, path)
+                })
+                .collect()
+        }
+    };
+
+    for (table, path) in targets {
+        info!(db = %args.db, table = %table, path = %path.display(), "Exporting table...");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let count = export_table(&storage, &args.db, &table, format, &mut writer).await?;
+        println!(
+            "✅ Exported {} documents from '{}.{}' to {}",
+            count,
+            args.db,
+            table,
+            path.display()
+        );
+    }
+
     Ok(())
 }
 
 /// Import command
-async fn import_command(_data_dir: PathBuf, _args: ImportArgs) -> anyhow::Result<()> {
-    println!("⚠️  Import functionality not yet implemented");
-    // TODO: Implement import
+async fn import_command(data_dir: PathBuf, args: ImportArgs) -> anyhow::Result<()> {
+    let format: ExportFormat = args.format.parse()?;
+    let engine = DefaultStorageEngine::with_defaults(data_dir.to_str().unwrap())?;
+    let databases = StorageDatabaseEngine::new(Arc::new(Storage::new(Box::new(engine))));
+
+    info!(db = %args.db, table = %args.table, path = %args.input.display(), "Importing table...");
+    let reader = std::io::BufReader::new(std::fs::File::open(&args.input)?);
+    let options = ImportOptions {
+        skip_existing: args.skip_existing,
+        delimiter: args.delimiter,
+        header: !args.no_header,
+        type_infer: args.type_infer,
+        strict: args.strict,
+    };
+    let summary = import_table(&databases, &args.db, &args.table, format, reader, &options).await?;
+
+    println!(
+        "✅ Imported into '{}.{}': {} inserted, {} skipped, {} errors",
+        args.db, args.table, summary.inserted, summary.skipped, summary.errors
+    );
+    for row in &summary.row_errors {
+        println!("   line {}: {}", row.line, row.message);
+    }
     Ok(())
 }
 
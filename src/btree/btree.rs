@@ -12,6 +12,9 @@ use std::path::Path;
 pub const MAX_BRANCHING_FACTOR: usize = 200;
 pub const NODE_KEYS_LIMIT: usize = MAX_BRANCHING_FACTOR - 1;
 
+/// The write-ahead log is checkpointed once it grows beyond this many bytes.
+pub const CHECKPOINT_THRESHOLD: u64 = 8 * 1024 * 1024;
+
 /// BTree struct represents an on-disk B+tree.
 /// Each node is persisted in the table file, the leaf nodes contain the values.
 pub struct BTree {
@@ -54,20 +57,48 @@ impl BTreeBuilder {
         if self.b == 0 {
             return Err(Error::UnexpectedError);
         }
+        open(self.path, self.b)
+    }
+}
+
+/// open opens the tree stored at path, creating it if it does not exist.
+///
+/// If the write-ahead log shows the tree was not shut down cleanly, the pages
+/// of every committed operation are replayed from the log and anything
+/// written after the last commit is discarded before the tree is returned.
+pub fn open(path: &Path, b: usize) -> Result<BTree, Error> {
+    let mut pager = Pager::new(path)?;
+    let mut wal = Wal::open(&Wal::path(path))?;
+    let recovery = wal.recover()?;
 
-        let mut pager = Pager::new(self.path)?;
-        let root = Node::new(NodeType::Leaf(vec![]), true, None);
-        let root_offset = pager.write_page(Page::try_from(&root)?)?;
-        let parent_directory = self.path.parent().unwrap_or_else(|| Path::new("/tmp"));
-        let mut wal = Wal::new(parent_directory.to_path_buf())?;
-        wal.set_root(root_offset)?;
-
-        Ok(BTree {
-            pager,
-            b: self.b,
-            wal,
-        })
+    let (root_offset, end) = match recovery.commit.clone() {
+        Some(commit) => commit,
+        // A tree file without a log cannot be interpreted.
+        None if pager.end() > 0 => return Err(Error::UnexpectedError),
+        None => {
+            let root = Node::new(NodeType::Leaf(vec![]), true, None);
+            let root_offset = pager.write_page(Page::try_from(&root)?)?;
+            (root_offset, pager.end())
+        }
+    };
+
+    if !recovery.is_clean() {
+        tracing::warn!(
+            path = %path.display(),
+            replayed = recovery.pages.len(),
+            discarded = recovery.discarded,
+            "Recovering B-Tree from write-ahead log"
+        );
+        for (offset, page) in recovery.pages {
+            pager.write_page_at_offset(page, &offset)?;
+        }
     }
+    // Drop pages appended after the last commit.
+    pager.truncate(end)?;
+    pager.sync()?;
+    wal.checkpoint(root_offset, end)?;
+
+    Ok(BTree { pager, b, wal })
 }
 
 impl Default for BTreeBuilder {
@@ -99,6 +130,34 @@ impl BTree {
         }
     }
 
+    /// write_page logs a page and appends it to the tree file.
+    fn write_page(&mut self, page: Page) -> Result<Offset, Error> {
+        self.wal.log_page(&self.pager.next_offset(), &page)?;
+        self.pager.write_page(page)
+    }
+
+    /// write_page_at_offset logs a page and writes it at offset.
+    fn write_page_at_offset(&mut self, page: Page, offset: &Offset) -> Result<(), Error> {
+        self.wal.log_page(offset, &page)?;
+        self.pager.write_page_at_offset(page, offset)
+    }
+
+    /// set_root commits the pages written so far with offset as the new root.
+    fn set_root(&mut self, offset: Offset) -> Result<(), Error> {
+        self.wal.commit(offset, self.pager.end())?;
+        if self.wal.size() > CHECKPOINT_THRESHOLD {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// checkpoint syncs the tree file and truncates the write-ahead log.
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        let root = self.wal.get_root()?;
+        self.pager.sync()?;
+        self.wal.checkpoint(root, self.pager.end())
+    }
+
     /// insert a key value pair possibly splitting nodes along the way.
     pub fn insert(&mut self, kv: KeyValuePair) -> Result<(), Error> {
         let root_offset = self.wal.get_root()?;
@@ -110,30 +169,29 @@ impl BTree {
             // split the root creating a new root and child nodes along the way.
             new_root = Node::new(NodeType::Internal(vec![], vec![]), true, None);
             // write the new root to disk to aquire an offset for the new root.
-            new_root_offset = self.pager.write_page(Page::try_from(&new_root)?)?;
+            new_root_offset = self.write_page(Page::try_from(&new_root)?)?;
             // set the old roots parent to the new root.
             root.parent_offset = Some(new_root_offset.clone());
             root.is_root = false;
             // split the old root.
             let (median, sibling) = root.split(self.b)?;
             // write the old root with its new data to disk in a *new* location.
-            let old_root_offset = self.pager.write_page(Page::try_from(&root)?)?;
+            let old_root_offset = self.write_page(Page::try_from(&root)?)?;
             // write the newly created sibling to disk.
-            let sibling_offset = self.pager.write_page(Page::try_from(&sibling)?)?;
+            let sibling_offset = self.write_page(Page::try_from(&sibling)?)?;
             // update the new root with its children and key.
             new_root.node_type =
                 NodeType::Internal(vec![old_root_offset, sibling_offset], vec![median]);
             // write the new_root to disk.
-            self.pager
-                .write_page_at_offset(Page::try_from(&new_root)?, &new_root_offset)?;
+            self.write_page_at_offset(Page::try_from(&new_root)?, &new_root_offset)?;
         } else {
             new_root = root.clone();
-            new_root_offset = self.pager.write_page(Page::try_from(&new_root)?)?;
+            new_root_offset = self.write_page(Page::try_from(&new_root)?)?;
         }
         // continue recursively.
         self.insert_non_full(&mut new_root, new_root_offset.clone(), kv)?;
         // finish by setting the root to its new copy.
-        self.wal.set_root(new_root_offset)
+        self.set_root(new_root_offset)
     }
 
     /// insert_non_full (recursively) finds a node rooted at a given non-full node.
@@ -149,8 +207,7 @@ impl BTree {
             NodeType::Leaf(ref mut pairs) => {
                 let idx = pairs.binary_search(&kv).unwrap_or_else(|x| x);
                 pairs.insert(idx, kv);
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, &node_offset)
+                self.write_page_at_offset(Page::try_from(&*node)?, &node_offset)
             }
             NodeType::Internal(ref mut children, ref mut keys) => {
                 let idx = keys
@@ -161,25 +218,23 @@ impl BTree {
                 let mut child = Node::try_from(child_page)?;
                 // Copy each branching-node on the root-to-leaf walk.
                 // write_page appends the given page to the db file thus creating a new node.
-                let new_child_offset = self.pager.write_page(Page::try_from(&child)?)?;
+                let new_child_offset = self.write_page(Page::try_from(&child)?)?;
                 // Assign copied child at the proper place.
                 children[idx] = new_child_offset.to_owned();
                 if self.is_node_full(&child)? {
                     // split will split the child at b leaving the [0, b-1] keys
                     // while moving the set of [b, 2b-1] keys to the sibling.
                     let (median, mut sibling) = child.split(self.b)?;
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&child)?, &new_child_offset)?;
+                    self.write_page_at_offset(Page::try_from(&child)?, &new_child_offset)?;
                     // Write the newly created sibling to disk.
-                    let sibling_offset = self.pager.write_page(Page::try_from(&sibling)?)?;
+                    let sibling_offset = self.write_page(Page::try_from(&sibling)?)?;
                     // Siblings keys are larger than the splitted child thus need to be inserted
                     // at the next index.
                     children.insert(idx + 1, sibling_offset.clone());
                     keys.insert(idx, median.clone());
 
                     // Write the parent page to disk.
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
+                    self.write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
                     // Continue recursively.
                     if kv.key <= median.0 {
                         self.insert_non_full(&mut child, new_child_offset, kv)
@@ -187,8 +242,7 @@ impl BTree {
                         self.insert_non_full(&mut sibling, sibling_offset, kv)
                     }
                 } else {
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
+                    self.write_page_at_offset(Page::try_from(&*node)?, &node_offset)?;
                     self.insert_non_full(&mut child, new_child_offset, kv)
                 }
             }
@@ -229,6 +283,32 @@ impl BTree {
         }
     }
 
+    /// scan returns every key value pair in the tree in key order.
+    pub fn scan(&mut self) -> Result<Vec<KeyValuePair>, Error> {
+        let root_offset = self.wal.get_root()?;
+        let mut pairs = Vec::new();
+        self.scan_node(&root_offset, &mut pairs)?;
+        Ok(pairs)
+    }
+
+    /// scan_node appends the pairs of the sub tree rooted at offset to pairs.
+    fn scan_node(&mut self, offset: &Offset, pairs: &mut Vec<KeyValuePair>) -> Result<(), Error> {
+        let page = self.pager.get_page(offset)?;
+        match Node::try_from(page)?.node_type {
+            NodeType::Internal(children, _) => {
+                for child_offset in &children {
+                    self.scan_node(child_offset, pairs)?;
+                }
+                Ok(())
+            }
+            NodeType::Leaf(leaf_pairs) => {
+                pairs.extend(leaf_pairs);
+                Ok(())
+            }
+            NodeType::Unexpected => Err(Error::UnexpectedError),
+        }
+    }
+
     /// delete deletes a given key from the tree.
     pub fn delete(&mut self, key: Key) -> Result<(), Error> {
         let root_offset = self.wal.get_root()?;
@@ -236,9 +316,9 @@ impl BTree {
         // Shadow the new root and rewrite it.
         let mut new_root = Node::try_from(root_page)?;
         let new_root_page = Page::try_from(&new_root)?;
-        let new_root_offset = self.pager.write_page(new_root_page)?;
+        let new_root_offset = self.write_page(new_root_page)?;
         self.delete_key_from_subtree(key, &mut new_root, &new_root_offset)?;
-        self.wal.set_root(new_root_offset)
+        self.set_root(new_root_offset)
     }
 
     /// delete key from subtree recursively traverses a tree rooted at a node in certain offset
@@ -256,8 +336,7 @@ impl BTree {
                     .binary_search_by_key(&key, |kv| Key(kv.key.clone()))
                     .map_err(|_| Error::KeyNotFound)?;
                 pairs.remove(key_idx);
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
+                self.write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
                 // Check for underflow - if it occures,
                 // we need to merge with a sibling.
                 // this can only occur if node is not the root (as it cannot "underflow").
@@ -276,11 +355,10 @@ impl BTree {
                 // This is important for the case of a node underflow which might require a leaf to root traversal.
                 child_node.parent_offset = Some(node_offset.to_owned());
                 let new_child_page = Page::try_from(&child_node)?;
-                let new_child_offset = self.pager.write_page(new_child_page)?;
+                let new_child_offset = self.write_page(new_child_page)?;
                 // Assign the new pointer in the parent and continue reccoursively.
                 children[node_idx] = new_child_offset.to_owned();
-                self.pager
-                    .write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
+                self.write_page_at_offset(Page::try_from(&*node)?, node_offset)?;
                 return self.delete_key_from_subtree(key, &mut child_node, &new_child_offset);
             }
             NodeType::Unexpected => return Err(Error::UnexpectedError),
@@ -316,7 +394,7 @@ impl BTree {
                     let sibling = Node::try_from(sibling_page)?;
                     let merged_node = self.merge(node, sibling)?;
                     let merged_node_offset =
-                        self.pager.write_page(Page::try_from(&merged_node)?)?;
+                        self.write_page(Page::try_from(&merged_node)?)?;
                     let merged_node_idx = cmp::min(idx, sibling_idx);
                     // remove the old nodes.
                     children.remove(merged_node_idx);
@@ -325,7 +403,7 @@ impl BTree {
                     // if the parent is the root, and there is a single child - the merged node -
                     // we can safely replace the root with the child.
                     if parent_node.is_root && children.is_empty() {
-                        self.wal.set_root(merged_node_offset)?;
+                        self.set_root(merged_node_offset)?;
                         return Ok(());
                     }
                     // remove the keys that separated the two nodes from each other:
@@ -333,8 +411,7 @@ impl BTree {
                     // write the new node in place.
                     children.insert(merged_node_idx, merged_node_offset);
                     // write the updated parent back to disk and continue up the tree.
-                    self.pager
-                        .write_page_at_offset(Page::try_from(&parent_node)?, &parent_offset)?;
+                    self.write_page_at_offset(Page::try_from(&parent_node)?, &parent_offset)?;
                     return self.borrow_if_needed(parent_node, key);
                 }
                 _ => return Err(Error::UnexpectedError),
@@ -412,3 +489,87 @@ impl BTree {
         self.print_sub_tree("".to_string(), root_offset)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn tree_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("btree_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("tree")
+    }
+
+    #[test]
+    fn open_recovers_committed_writes_after_crash() -> Result<(), Error> {
+        let path = tree_path("recovery");
+        let keys: Vec<String> = (0..20).map(|i| format!("k{:02}", i)).collect();
+
+        let (checkpointed_end, committed_end) = {
+            let mut tree = open(&path, 2)?;
+            let checkpointed_end = tree.pager.end();
+            for key in &keys {
+                tree.insert(KeyValuePair::new(key.clone(), format!("v{}", key)))?;
+            }
+
+            // An operation that never committed: a new root holding a key
+            // that must not survive, plus a torn page at the end of the file.
+            let root_offset = tree.wal.get_root()?;
+            let ghost = Node::new(
+                NodeType::Leaf(vec![KeyValuePair::new("ghost".into(), "boo".into())]),
+                true,
+                None,
+            );
+            tree.wal.log_page(&root_offset, &Page::try_from(&ghost)?)?;
+            let end = tree.pager.end();
+            let mut file = OpenOptions::new().append(true).open(&path)?;
+            file.write_all(&[0xFF; 100])?;
+            (checkpointed_end, end)
+            // Dropped without a checkpoint.
+        };
+
+        // Lose every page written since the last checkpoint.
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(checkpointed_end as u64)?;
+
+        let mut tree = open(&path, 2)?;
+        for key in &keys {
+            assert_eq!(tree.search(key.clone())?.value, format!("v{}", key));
+        }
+        assert!(tree.search("ghost".to_string()).is_err());
+        assert_eq!(std::fs::metadata(&path)?.len() as usize, committed_end);
+        // The log was checkpointed down to a single commit record.
+        assert!(std::fs::metadata(Wal::path(&path))?.len() < 64);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_checkpoint_keeps_old_log() -> Result<(), Error> {
+        let path = tree_path("interrupted_checkpoint");
+        {
+            let mut tree = open(&path, 2)?;
+            tree.insert(KeyValuePair::new("a".into(), "1".into()))?;
+            tree.checkpoint()?;
+            tree.insert(KeyValuePair::new("b".into(), "2".into()))?;
+        }
+        // A crash while the next checkpoint was writing its new log.
+        let temp_path = Wal::path(&path).with_extension("wal.tmp");
+        std::fs::write(&temp_path, [0xFF; 10])?;
+
+        let mut tree = open(&path, 2)?;
+        assert_eq!(tree.search("a".to_string())?.value, "1");
+        assert_eq!(tree.search("b".to_string())?.value, "2");
+        assert!(!temp_path.exists());
+        assert!(std::fs::metadata(Wal::path(&path))?.len() < 64);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+        Ok(())
+    }
+}
@@ -7,6 +7,8 @@ pub enum Error {
     ValueOverflowError,
     TryFromSliceError(&'static str),
     UTF8Error,
+    /// The page at the given offset does not match its checksum.
+    ChecksumMismatch(usize),
 }
 
 impl std::convert::From<std::io::Error> for Error {
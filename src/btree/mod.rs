@@ -1,8 +1,11 @@
 pub mod btree;
+mod checksum;
 pub mod error;
 pub mod node;
 pub mod types;
 pub mod page;
 mod page_layout;
 mod pager;
-mod wal;
\ No newline at end of file
+mod wal;
+
+pub use btree::open;
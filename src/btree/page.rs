@@ -4,8 +4,8 @@ use super::types::{Key, NodeType, Offset};
 use super::page_layout::{
     ToByte, INTERNAL_NODE_HEADER_SIZE, INTERNAL_NODE_NUM_CHILDREN_OFFSET,
     INTERNAL_NODE_NUM_CHILDREN_SIZE, IS_ROOT_OFFSET, KEY_SIZE, LEAF_NODE_HEADER_SIZE,
-    LEAF_NODE_NUM_PAIRS_OFFSET, LEAF_NODE_NUM_PAIRS_SIZE, NODE_TYPE_OFFSET, PAGE_SIZE,
-    PARENT_POINTER_OFFSET, PARENT_POINTER_SIZE, PTR_SIZE, VALUE_SIZE,
+    LEAF_NODE_NUM_PAIRS_OFFSET, LEAF_NODE_NUM_PAIRS_SIZE, NODE_TYPE_OFFSET, PAGE_CHECKSUM_OFFSET,
+    PAGE_SIZE, PARENT_POINTER_OFFSET, PARENT_POINTER_SIZE, PTR_SIZE, VALUE_SIZE,
 };
 use std::convert::TryFrom;
 
@@ -115,6 +115,14 @@ impl TryFrom<&Node> for Page {
                     ..INTERNAL_NODE_NUM_CHILDREN_OFFSET + INTERNAL_NODE_NUM_CHILDREN_SIZE]
                     .clone_from_slice(&child_offsets.len().to_be_bytes());
 
+                // The node must not spill into the checksum field.
+                let end = INTERNAL_NODE_HEADER_SIZE
+                    + child_offsets.len() * PTR_SIZE
+                    + keys.len() * KEY_SIZE;
+                if end > PAGE_CHECKSUM_OFFSET {
+                    return Err(Error::UnexpectedError);
+                }
+
                 let mut page_offset = INTERNAL_NODE_HEADER_SIZE;
                 for Offset(child_offset) in child_offsets {
                     data[page_offset..page_offset + PTR_SIZE]
@@ -142,6 +150,12 @@ impl TryFrom<&Node> for Page {
                     ..LEAF_NODE_NUM_PAIRS_OFFSET + LEAF_NODE_NUM_PAIRS_SIZE]
                     .clone_from_slice(&kv_pairs.len().to_be_bytes());
 
+                // The node must not spill into the checksum field.
+                let end = LEAF_NODE_HEADER_SIZE + kv_pairs.len() * (KEY_SIZE + VALUE_SIZE);
+                if end > PAGE_CHECKSUM_OFFSET {
+                    return Err(Error::UnexpectedError);
+                }
+
                 let mut page_offset = LEAF_NODE_HEADER_SIZE;
                 for pair in kv_pairs {
                     let key_bytes = pair.key.as_bytes();
@@ -7,6 +7,13 @@ pub const PAGE_SIZE: usize = 4096;
 
 pub const PTR_SIZE: usize = size_of::<usize>();
 
+/// Page checksum layout (Four bytes at the end of every page)
+///
+/// A CRC32C of the bytes before it, stamped by the pager on write and verified on read.
+/// Node data must end before PAGE_CHECKSUM_OFFSET.
+pub const PAGE_CHECKSUM_SIZE: usize = 4;
+pub const PAGE_CHECKSUM_OFFSET: usize = PAGE_SIZE - PAGE_CHECKSUM_SIZE;
+
 /// Common Node header layout (Ten bytes in total)
 pub const IS_ROOT_SIZE: usize = 1;
 pub const IS_ROOT_OFFSET: usize = 0;
@@ -1,7 +1,8 @@
 use crate::btree::error::Error;
 use super::types::Offset;
 use super::page::Page;
-use super::page_layout::PAGE_SIZE;
+use super::checksum::crc32c;
+use super::page_layout::{PAGE_CHECKSUM_OFFSET, PAGE_SIZE};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::io::{Read, Seek, SeekFrom};
@@ -13,30 +14,32 @@ pub struct Pager {
 }
 
 impl Pager {
+    /// new opens (or creates) the tree file, appending after its current end.
     pub fn new(path: &Path) -> Result<Pager, Error> {
         let fd = OpenOptions::new()
             .create(true)
             .read(true)
             .write(true)
-            .truncate(true)
+            .truncate(false)
             .open(path)?;
+        let curser = fd.metadata()?.len() as usize;
 
-        Ok(Pager {
-            file: fd,
-            curser: 0,
-        })
+        Ok(Pager { file: fd, curser })
     }
 
     pub fn get_page(&mut self, offset: &Offset) -> Result<Page, Error> {
         let mut page: [u8; PAGE_SIZE] = [0x00; PAGE_SIZE];
         self.file.seek(SeekFrom::Start(offset.0 as u64))?;
         self.file.read_exact(&mut page)?;
+        if checksum(&page) != stored_checksum(&page) {
+            return Err(Error::ChecksumMismatch(offset.0));
+        }
         Ok(Page::new(page))
     }
 
     pub fn write_page(&mut self, page: Page) -> Result<Offset, Error> {
         self.file.seek(SeekFrom::Start(self.curser as u64))?;
-        self.file.write_all(&page.get_data())?;
+        self.file.write_all(&seal(page))?;
         let res = Offset(self.curser);
         self.curser += PAGE_SIZE;
         Ok(res)
@@ -44,7 +47,93 @@ impl Pager {
 
     pub fn write_page_at_offset(&mut self, page: Page, offset: &Offset) -> Result<(), Error> {
         self.file.seek(SeekFrom::Start(offset.0 as u64))?;
-        self.file.write_all(&page.get_data())?;
+        self.file.write_all(&seal(page))?;
+        Ok(())
+    }
+
+    /// next_offset returns the offset the next appended page will get.
+    pub fn next_offset(&self) -> Offset {
+        Offset(self.curser)
+    }
+
+    /// end returns the end of the used part of the file.
+    pub fn end(&self) -> usize {
+        self.curser
+    }
+
+    /// truncate cuts the file at end, dropping pages appended after it.
+    pub fn truncate(&mut self, end: usize) -> Result<(), Error> {
+        self.file.set_len(end as u64)?;
+        self.curser = end;
+        Ok(())
+    }
+
+    /// sync flushes all written pages to disk.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// checksum computes the CRC32C of everything but the checksum field.
+fn checksum(data: &[u8; PAGE_SIZE]) -> u32 {
+    crc32c(&data[..PAGE_CHECKSUM_OFFSET])
+}
+
+fn stored_checksum(data: &[u8; PAGE_SIZE]) -> u32 {
+    let mut buff = [0x00; 4];
+    buff.copy_from_slice(&data[PAGE_CHECKSUM_OFFSET..]);
+    u32::from_be_bytes(buff)
+}
+
+/// seal returns the page data with its checksum field filled in.
+fn seal(page: Page) -> [u8; PAGE_SIZE] {
+    let mut data = page.get_data();
+    let sum = checksum(&data);
+    data[PAGE_CHECKSUM_OFFSET..].copy_from_slice(&sum.to_be_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn page_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pager_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("pages")
+    }
+
+    #[test]
+    fn corrupt_page_fails_checksum() -> Result<(), Error> {
+        let path = page_file("checksum");
+        let mut pager = Pager::new(&path)?;
+        let offsets: Vec<Offset> = (0..3u8)
+            .map(|i| pager.write_page(Page::new([i; PAGE_SIZE])))
+            .collect::<Result<_, _>>()?;
+        pager.sync()?;
+        drop(pager);
+
+        // Flip one byte in the middle page.
+        let mut data = std::fs::read(&path)?;
+        data[offsets[1].0 + 100] ^= 0xFF;
+        std::fs::write(&path, data)?;
+
+        let mut pager = Pager::new(&path)?;
+        assert!(matches!(
+            pager.get_page(&offsets[1]),
+            Err(Error::ChecksumMismatch(offset)) if offset == offsets[1].0
+        ));
+        for (i, offset) in [(0u8, &offsets[0]), (2, &offsets[2])] {
+            let page = pager.get_page(offset)?;
+            assert_eq!(
+                page.get_data()[..PAGE_CHECKSUM_OFFSET],
+                [i; PAGE_CHECKSUM_OFFSET]
+            );
+        }
+
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
         Ok(())
     }
 }
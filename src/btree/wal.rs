@@ -1,42 +1,233 @@
 use crate::btree::error::Error;
+use super::checksum::crc32c;
+use super::page::Page;
+use super::page_layout::PAGE_SIZE;
 use super::types::Offset;
-use super::page_layout::PTR_SIZE;
-use std::convert::TryFrom;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
+/// Record tags.
+const PAGE_RECORD: u8 = 0x01;
+const COMMIT_RECORD: u8 = 0x02;
+
+/// Record layout: [tag: 1][payload length: 4][payload][crc32c of tag, length and payload: 4].
+const RECORD_HEADER_SIZE: usize = 1 + 4;
+const RECORD_CHECKSUM_SIZE: usize = 4;
+
+/// A page record payload: [page offset: 8][page data: PAGE_SIZE].
+const PAGE_RECORD_SIZE: usize = 8 + PAGE_SIZE;
+/// A commit record payload: [root offset: 8][end of the tree file: 8].
+const COMMIT_RECORD_SIZE: usize = 8 + 8;
+
+/// Wal is the write-ahead log of a BTree.
+///
+/// Every page is logged before it is written to the tree file, and every
+/// operation ends with a commit record naming the new root and the end of the
+/// tree file. A checkpoint syncs the tree file and replaces the log with a
+/// single commit record, so a log holding page records means the tree was not
+/// shut down cleanly.
 pub struct Wal {
+    path: PathBuf,
     file: File,
+    root: Option<Offset>,
+    /// Bytes written since the last checkpoint.
+    size: u64,
+}
+
+/// The outcome of reading a log on open.
+pub struct Recovery {
+    /// Root and end of the tree file as of the last commit.
+    pub commit: Option<(Offset, usize)>,
+    /// Pages of committed operations, in log order.
+    pub pages: Vec<(Offset, Page)>,
+    /// Page records after the last commit, which are discarded.
+    pub discarded: usize,
+}
+
+impl Recovery {
+    /// is_clean reports whether the log held nothing beyond a checkpoint.
+    pub fn is_clean(&self) -> bool {
+        self.pages.is_empty() && self.discarded == 0
+    }
 }
 
 impl Wal {
-    pub fn new(parent_directoy: PathBuf) -> Result<Self, Error> {
-        let fd = OpenOptions::new()
+    /// path returns the log path of the tree file at tree_path.
+    pub fn path(tree_path: &Path) -> PathBuf {
+        let mut name = tree_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".wal");
+        tree_path.with_file_name(name)
+    }
+
+    /// open opens (or creates) the log without modifying it.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new()
             .create(true)
             .read(true)
             .write(true)
-            .truncate(true)
-            .open(parent_directoy.join("wal"))?;
+            .truncate(false)
+            .open(path)?;
+        let size = file.metadata()?.len();
 
-        Ok(Self { file: fd })
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            root: None,
+            size,
+        })
     }
 
-    pub fn get_root(&mut self) -> Result<Offset, Error> {
-        let mut buff: [u8; PTR_SIZE] = [0x00; PTR_SIZE];
-        let file_len = self.file.seek(SeekFrom::End(0))? as usize;
-        let mut root_offset: usize = 0;
-        if file_len > 0 {
-            root_offset = (file_len / PTR_SIZE - 1) * PTR_SIZE;
+    /// recover reads every intact record up to the first torn or corrupt one.
+    pub fn recover(&mut self) -> Result<Recovery, Error> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        let mut recovery = Recovery {
+            commit: None,
+            pages: Vec::new(),
+            discarded: 0,
+        };
+        let mut pending = Vec::new();
+
+        while let Some((tag, payload)) = read_record(&mut reader)? {
+            match (tag, payload.len()) {
+                (PAGE_RECORD, PAGE_RECORD_SIZE) => {
+                    let offset = Offset(read_u64(&payload[..8]) as usize);
+                    let mut data = [0x00; PAGE_SIZE];
+                    data.copy_from_slice(&payload[8..]);
+                    pending.push((offset, Page::new(data)));
+                }
+                (COMMIT_RECORD, COMMIT_RECORD_SIZE) => {
+                    let root = Offset(read_u64(&payload[..8]) as usize);
+                    let end = read_u64(&payload[8..]) as usize;
+                    recovery.commit = Some((root, end));
+                    recovery.pages.append(&mut pending);
+                }
+                _ => break,
+            }
+        }
+        recovery.discarded = pending.len();
+
+        if let Some((root, _)) = &recovery.commit {
+            self.root = Some(root.clone());
         }
-        self.file.seek(SeekFrom::Start(root_offset as u64))?;
-        self.file.read_exact(&mut buff)?;
-        Offset::try_from(buff)
+        Ok(recovery)
+    }
+
+    /// get_root returns the root of the last commit.
+    pub fn get_root(&mut self) -> Result<Offset, Error> {
+        self.root.clone().ok_or(Error::UnexpectedError)
     }
 
-    pub fn set_root(&mut self, offset: Offset) -> Result<(), Error> {
+    /// log_page appends a page record; it becomes durable with the next commit.
+    ///
+    /// Records are checksummed on their own, so the page checksum field is
+    /// left to the pager, which stamps it when the page is applied or replayed.
+    pub fn log_page(&mut self, offset: &Offset, page: &Page) -> Result<(), Error> {
+        let mut payload = Vec::with_capacity(PAGE_RECORD_SIZE);
+        payload.extend_from_slice(&(offset.0 as u64).to_be_bytes());
+        payload.extend_from_slice(&page.get_data());
+        self.append(PAGE_RECORD, &payload)
+    }
+
+    /// commit durably records root as the new root and end as the end of the tree file.
+    pub fn commit(&mut self, root: Offset, end: usize) -> Result<(), Error> {
+        self.append(COMMIT_RECORD, &commit_payload(&root, end))?;
+        self.file.sync_data()?;
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// checkpoint replaces the log with a single commit record.
+    ///
+    /// The tree file must be synced first. The new log is written and synced
+    /// beside the old one, then renamed over it, so a crash at any point
+    /// leaves a whole log ending in a commit.
+    pub fn checkpoint(&mut self, root: Offset, end: usize) -> Result<(), Error> {
+        let temp_path = self.path.with_extension("wal.tmp");
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_path)?;
+        let record = encode_record(COMMIT_RECORD, &commit_payload(&root, end));
+        file.write_all(&record)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+        // Make the rename itself durable.
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+
+        self.file = file;
+        self.size = record.len() as u64;
+        self.root = Some(root);
+        Ok(())
+    }
+
+    /// size returns the number of bytes written since the last checkpoint.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn append(&mut self, tag: u8, payload: &[u8]) -> Result<(), Error> {
+        let record = encode_record(tag, payload);
         self.file.seek(SeekFrom::End(0))?;
-        self.file.write_all(&offset.0.to_be_bytes())?;
+        self.file.write_all(&record)?;
+        self.size += record.len() as u64;
         Ok(())
     }
 }
+
+fn encode_record(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len() + RECORD_CHECKSUM_SIZE);
+    record.push(tag);
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(payload);
+    let checksum = crc32c(&record);
+    record.extend_from_slice(&checksum.to_le_bytes());
+    record
+}
+
+fn commit_payload(root: &Offset, end: usize) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(COMMIT_RECORD_SIZE);
+    payload.extend_from_slice(&(root.0 as u64).to_be_bytes());
+    payload.extend_from_slice(&(end as u64).to_be_bytes());
+    payload
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buff = [0x00; 8];
+    buff.copy_from_slice(bytes);
+    u64::from_be_bytes(buff)
+}
+
+/// read_record reads the next record, returning None at the end of the log
+/// or at the first torn or corrupt record.
+fn read_record<R: Read>(reader: &mut R) -> Result<Option<(u8, Vec<u8>)>, Error> {
+    let mut header = [0x00; RECORD_HEADER_SIZE];
+    if reader.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > PAGE_RECORD_SIZE {
+        return Ok(None);
+    }
+
+    let mut rest = vec![0x00; len + RECORD_CHECKSUM_SIZE];
+    if reader.read_exact(&mut rest).is_err() {
+        return Ok(None);
+    }
+    let (payload, checksum) = rest.split_at(len);
+    let mut record = header.to_vec();
+    record.extend_from_slice(payload);
+    let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if crc32c(&record) != checksum {
+        return Ok(None);
+    }
+
+    Ok(Some((header[0], payload.to_vec())))
+}
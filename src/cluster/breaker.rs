@@ -0,0 +1,191 @@
+//! Per-node circuit breakers for replication calls
+//!
+//! A node that keeps failing would otherwise cost every write a full request
+//! timeout. After `failure_threshold` consecutive failures its breaker opens
+//! and calls fail immediately for `cooldown`. Then a single trial call is let
+//! through (half-open): success closes the breaker, failure reopens it for
+//! another cooldown.
+
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Consecutive failures that open a breaker unless configured otherwise
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Time an open breaker rejects calls unless configured otherwise
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// State of one node's breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through
+    Closed,
+    /// Calls are rejected until the cooldown ends
+    Open,
+    /// One trial call is in flight; others are rejected until it finishes
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Instant,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            failures: 0,
+            opened_at: Instant::now(),
+        }
+    }
+}
+
+/// Circuit breakers of all nodes, cheap to clone and share between tasks
+#[derive(Debug, Clone)]
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    cooldown: Duration,
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+}
+
+impl CircuitBreakers {
+    /// Breakers opening after `failure_threshold` (at least one) consecutive
+    /// failures for `cooldown`
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Ask to call `node_id`
+    ///
+    /// Fails without touching the node while its breaker is open. Once the
+    /// cooldown is over the caller gets the half-open trial and must report
+    /// its outcome.
+    pub fn check(&self, node_id: &str) -> Result<(), String> {
+        let mut breakers = self.breakers.lock();
+        let Some(breaker) = breakers.get_mut(node_id) else {
+            return Ok(());
+        };
+        match breaker.state {
+            CircuitState::Closed => Ok(()),
+            // A trial that never reported back does not block the node forever
+            _ if breaker.opened_at.elapsed() >= self.cooldown => {
+                breaker.state = CircuitState::HalfOpen;
+                breaker.opened_at = Instant::now();
+                Ok(())
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {
+                Err(format!("Circuit open for node {}", node_id))
+            }
+        }
+    }
+
+    /// Report a successful call, closing the breaker
+    pub fn record_success(&self, node_id: &str) {
+        self.breakers.lock().remove(node_id);
+    }
+
+    /// Report a failed call; returns whether the breaker opened because of it
+    pub fn record_failure(&self, node_id: &str) -> bool {
+        let mut breakers = self.breakers.lock();
+        let breaker = breakers.entry(node_id.to_string()).or_default();
+        breaker.failures += 1;
+        let open = match breaker.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => breaker.failures >= self.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if open {
+            breaker.state = CircuitState::Open;
+            breaker.opened_at = Instant::now();
+        }
+        open
+    }
+
+    /// Current state of `node_id`'s breaker
+    pub fn state(&self, node_id: &str) -> CircuitState {
+        self.breakers
+            .lock()
+            .get(node_id)
+            .map_or(CircuitState::Closed, |b| b.state)
+    }
+
+    /// Forget `node_id`, e.g. when it leaves the cluster
+    pub fn remove(&self, node_id: &str) {
+        self.breakers.lock().remove(node_id);
+    }
+
+    /// Nodes whose breaker is not closed, sorted
+    pub fn tripped(&self) -> Vec<String> {
+        let mut tripped: Vec<String> = self
+            .breakers
+            .lock()
+            .iter()
+            .filter(|(_, b)| b.state != CircuitState::Closed)
+            .map(|(id, _)| id.clone())
+            .collect();
+        tripped.sort();
+        tripped
+    }
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breakers = CircuitBreakers::new(3, Duration::from_secs(60));
+        assert!(!breakers.record_failure("a"));
+        assert!(!breakers.record_failure("a"));
+        // A success resets the count
+        breakers.record_success("a");
+        assert!(!breakers.record_failure("a"));
+        assert!(!breakers.record_failure("a"));
+        assert!(breakers.check("a").is_ok());
+        assert!(breakers.record_failure("a"));
+
+        assert_eq!(breakers.state("a"), CircuitState::Open);
+        assert_eq!(breakers.check("a").unwrap_err(), "Circuit open for node a");
+        assert!(breakers.check("b").is_ok());
+        assert_eq!(breakers.tripped(), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_half_open_after_cooldown() {
+        let breakers = CircuitBreakers::new(1, Duration::from_millis(20));
+        breakers.record_failure("a");
+        assert!(breakers.check("a").is_err());
+        std::thread::sleep(Duration::from_millis(30));
+
+        // Only one trial call at a time
+        assert!(breakers.check("a").is_ok());
+        assert_eq!(breakers.state("a"), CircuitState::HalfOpen);
+        assert!(breakers.check("a").is_err());
+
+        // A failed trial reopens for another cooldown
+        assert!(breakers.record_failure("a"));
+        assert!(breakers.check("a").is_err());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breakers.check("a").is_ok());
+        breakers.record_success("a");
+        assert_eq!(breakers.state("a"), CircuitState::Closed);
+        assert!(breakers.tripped().is_empty());
+    }
+}
@@ -5,7 +5,15 @@
 //! - Peer list management
 //! - Health monitoring and failover
 //! - StatefulSet-aware (stable network identities)
-
+//!
+//! Peers are found by resolving the SRV record of the named cluster port
+//! (`_<port>._tcp.<service>.<namespace>.svc.cluster.local`), falling back to
+//! the A/AAAA records of the headless service. Each round makes the cluster's
+//! node set match the resolved addresses.
+
+use async_trait::async_trait;
+use hickory_resolver::TokioAsyncResolver;
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -23,6 +31,8 @@ pub struct DiscoveryConfig {
     pub namespace: String,
     /// Port for cluster communication
     pub cluster_port: u16,
+    /// Name of the cluster port in the service spec, used for SRV lookups
+    pub cluster_port_name: String,
     /// Discovery interval in seconds
     pub discovery_interval_secs: u64,
     /// Enable Kubernetes-based discovery
@@ -35,6 +45,7 @@ impl Default for DiscoveryConfig {
             service_name: "rethinkdb".to_string(),
             namespace: "default".to_string(),
             cluster_port: 29015,
+            cluster_port_name: "cluster".to_string(),
             discovery_interval_secs: 30,
             enabled: false,
         }
@@ -64,6 +75,9 @@ impl DiscoveryConfig {
             .parse()
             .unwrap_or(29015);
 
+        let cluster_port_name = std::env::var("RETHINKDB_CLUSTER_PORT_NAME")
+            .unwrap_or_else(|_| "cluster".to_string());
+
         let discovery_interval_secs = std::env::var("RETHINKDB_DISCOVERY_INTERVAL")
             .unwrap_or_else(|_| "30".to_string())
             .parse()
@@ -73,6 +87,7 @@ impl DiscoveryConfig {
             service_name,
             namespace,
             cluster_port,
+            cluster_port_name,
             discovery_interval_secs,
             enabled,
         }
@@ -86,6 +101,11 @@ impl DiscoveryConfig {
         )
     }
 
+    /// Get SRV record name for the cluster port
+    pub fn get_srv_name(&self) -> String {
+        format!("_{}._tcp.{}", self.cluster_port_name, self.get_dns_name())
+    }
+
     /// Get DNS name for a specific pod
     pub fn get_pod_dns_name(&self, pod_index: usize) -> String {
         format!(
@@ -95,23 +115,85 @@ impl DiscoveryConfig {
     }
 }
 
+/// Error type of discovery rounds
+pub type DiscoveryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Resolves the current set of peer addresses
+#[async_trait]
+pub trait PeerResolver: Send + Sync {
+    async fn resolve(&self, config: &DiscoveryConfig) -> Result<Vec<SocketAddr>, DiscoveryError>;
+}
+
+/// Resolves peers through DNS SRV records, falling back to A/AAAA records
+pub struct DnsPeerResolver;
+
+#[async_trait]
+impl PeerResolver for DnsPeerResolver {
+    async fn resolve(&self, config: &DiscoveryConfig) -> Result<Vec<SocketAddr>, DiscoveryError> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+
+        let srv_name = config.get_srv_name();
+        match resolver.srv_lookup(srv_name.as_str()).await {
+            Ok(srv) => {
+                let mut addrs = Vec::new();
+                for record in srv.iter() {
+                    let target = record.target().to_utf8();
+                    for ip in resolver.lookup_ip(target.as_str()).await?.iter() {
+                        addrs.push(SocketAddr::new(ip, record.port()));
+                    }
+                }
+                if !addrs.is_empty() {
+                    return Ok(addrs);
+                }
+            }
+            Err(e) => debug!(srv = %srv_name, error = %e, "SRV lookup failed, trying A records"),
+        }
+
+        let dns_name = config.get_dns_name();
+        let ips = resolver.lookup_ip(dns_name.as_str()).await.map_err(|e| {
+            warn!(error = %e, dns = %dns_name, "DNS resolution failed");
+            e
+        })?;
+        Ok(ips
+            .iter()
+            .map(|ip| SocketAddr::new(ip, config.cluster_port))
+            .collect())
+    }
+}
+
 /// Service discovery manager
 pub struct DiscoveryManager {
     config: DiscoveryConfig,
     cluster: Arc<ClusterState>,
+    resolver: Arc<dyn PeerResolver>,
 }
 
 impl DiscoveryManager {
     pub fn new(config: DiscoveryConfig, cluster: Arc<ClusterState>) -> Self {
-        Self { config, cluster }
+        Self::with_resolver(config, cluster, Arc::new(DnsPeerResolver))
+    }
+
+    /// Create a discovery manager that finds peers through `resolver`
+    pub fn with_resolver(
+        config: DiscoveryConfig,
+        cluster: Arc<ClusterState>,
+        resolver: Arc<dyn PeerResolver>,
+    ) -> Self {
+        Self {
+            config,
+            cluster,
+            resolver,
+        }
     }
 
     /// Start background discovery task
+    ///
+    /// Returns the task handle, or `None` when discovery is disabled.
     #[instrument(skip(self))]
-    pub async fn start(&self) {
+    pub async fn start(&self) -> Option<tokio::task::JoinHandle<()>> {
         if !self.config.enabled {
             info!("Service discovery is DISABLED");
-            return;
+            return None;
         }
 
         info!(
@@ -122,14 +204,15 @@ impl DiscoveryManager {
 
         let config = self.config.clone();
         let cluster = self.cluster.clone();
+        let resolver = self.resolver.clone();
 
-        tokio::spawn(async move {
+        let handle = tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(config.discovery_interval_secs));
 
             loop {
                 interval.tick().await;
 
-                if let Err(e) = Self::discover_peers(&config, &cluster).await {
+                if let Err(e) = Self::discover_peers(&config, &cluster, resolver.as_ref()).await {
                     let error_msg = e.to_string();
                     error!(error = %error_msg, "Failed to discover peers");
                 }
@@ -137,34 +220,43 @@ impl DiscoveryManager {
         });
 
         info!("Service discovery background task started");
+        Some(handle)
     }
 
-    /// Discover peers using DNS
-    #[instrument(skip(config, cluster))]
+    /// Run one discovery round
+    pub async fn discover_once(&self) -> Result<(), DiscoveryError> {
+        Self::discover_peers(&self.config, &self.cluster, self.resolver.as_ref()).await
+    }
+
+    /// Discover peers and reconcile the cluster's node set with them
+    ///
+    /// Nodes that are no longer resolved are removed. An empty answer is
+    /// treated as a transient DNS problem and leaves the node set alone.
+    #[instrument(skip(config, cluster, resolver))]
     async fn discover_peers(
         config: &DiscoveryConfig,
         cluster: &Arc<ClusterState>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let dns_name = config.get_dns_name();
-        debug!(dns = %dns_name, "Resolving DNS for peer discovery");
-
-        // Resolve DNS to get all pod IPs
-        let resolver = tokio::net::lookup_host(format!("{}:{}", dns_name, config.cluster_port))
-            .await
-            .map_err(|e| {
-                warn!(error = %e, dns = %dns_name, "DNS resolution failed");
-                e
-            })?;
-
-        let mut discovered_count = 0;
+        resolver: &dyn PeerResolver,
+    ) -> Result<(), DiscoveryError> {
+        debug!(dns = %config.get_dns_name(), "Resolving DNS for peer discovery");
+        let addrs = resolver.resolve(config).await?;
+
+        if addrs.is_empty() {
+            warn!(dns = %config.get_dns_name(), "Peer discovery returned no addresses");
+            return Ok(());
+        }
 
-        for addr in resolver {
-            discovered_count += 1;
+        let existing_nodes = cluster.get_nodes().await;
+        let mut discovered = HashSet::new();
+        let mut added = 0;
 
-            // Check if node already exists
+        for addr in addrs {
             let node_id = format!("node-{}", addr.ip());
-            let existing_nodes = cluster.get_nodes().await;
+            if !discovered.insert(node_id.clone()) {
+                continue;
+            }
 
+            // Check if node already exists
             if existing_nodes.iter().any(|n| n.id == node_id) {
                 debug!(node_id = %node_id, addr = %addr, "Node already registered");
                 continue;
@@ -180,12 +272,24 @@ impl DiscoveryManager {
             };
 
             cluster.add_node(node).await;
+            added += 1;
             info!(node_id = %node_id, addr = %addr, "Discovered new peer");
         }
 
+        let mut removed = 0;
+        for node in existing_nodes {
+            if !discovered.contains(&node.id) {
+                cluster.remove_node(&node.id).await;
+                removed += 1;
+                info!(node_id = %node.id, "Peer no longer resolved");
+            }
+        }
+
         let node_count = cluster.get_nodes().await.len();
         info!(
-            discovered = discovered_count,
+            discovered = discovered.len(),
+            added = added,
+            removed = removed,
             total = node_count,
             "Peer discovery completed"
         );
@@ -293,6 +397,7 @@ mod tests {
             service_name: "rethinkdb".to_string(),
             namespace: "production".to_string(),
             cluster_port: 29015,
+            cluster_port_name: "cluster".to_string(),
             discovery_interval_secs: 30,
             enabled: true,
         };
@@ -301,6 +406,10 @@ mod tests {
             config.get_dns_name(),
             "rethinkdb.production.svc.cluster.local"
         );
+        assert_eq!(
+            config.get_srv_name(),
+            "_cluster._tcp.rethinkdb.production.svc.cluster.local"
+        );
     }
 
     #[test]
@@ -309,6 +418,7 @@ mod tests {
             service_name: "rethinkdb".to_string(),
             namespace: "production".to_string(),
             cluster_port: 29015,
+            cluster_port_name: "cluster".to_string(),
             discovery_interval_secs: 30,
             enabled: true,
         };
@@ -361,4 +471,85 @@ mod tests {
         let peers = manager.get_peers().await;
         assert_eq!(peers.len(), 0);
     }
+
+    /// Returns the queued address sets in order, repeating the last one
+    struct StubResolver {
+        rounds: std::sync::Mutex<Vec<Vec<SocketAddr>>>,
+    }
+
+    #[async_trait]
+    impl PeerResolver for StubResolver {
+        async fn resolve(
+            &self,
+            _config: &DiscoveryConfig,
+        ) -> Result<Vec<SocketAddr>, DiscoveryError> {
+            let mut rounds = self.rounds.lock().unwrap();
+            if rounds.len() > 1 {
+                Ok(rounds.remove(0))
+            } else {
+                Ok(rounds[0].clone())
+            }
+        }
+    }
+
+    fn addrs(ips: &[&str]) -> Vec<SocketAddr> {
+        ips.iter()
+            .map(|ip| format!("{}:29015", ip).parse().unwrap())
+            .collect()
+    }
+
+    async fn node_ids(cluster: &ClusterState) -> Vec<String> {
+        let mut ids: Vec<String> = cluster
+            .get_nodes()
+            .await
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_discovery_converges_on_resolved_peers() {
+        let cluster = Arc::new(ClusterState::new(
+            "node1".to_string(),
+            ReplicationConfig::default(),
+        ));
+        let resolver = StubResolver {
+            rounds: std::sync::Mutex::new(vec![
+                addrs(&["10.0.0.1", "10.0.0.2"]),
+                addrs(&["10.0.0.2", "10.0.0.3", "10.0.0.3"]),
+                vec![],
+                addrs(&["10.0.0.3"]),
+            ]),
+        };
+        let manager = DiscoveryManager::with_resolver(
+            DiscoveryConfig::default(),
+            cluster.clone(),
+            Arc::new(resolver),
+        );
+
+        manager.discover_once().await.unwrap();
+        assert_eq!(
+            node_ids(&cluster).await,
+            vec!["node-10.0.0.1", "node-10.0.0.2"]
+        );
+
+        manager.discover_once().await.unwrap();
+        assert_eq!(
+            node_ids(&cluster).await,
+            vec!["node-10.0.0.2", "node-10.0.0.3"]
+        );
+
+        // An empty answer keeps the current peers
+        manager.discover_once().await.unwrap();
+        assert_eq!(
+            node_ids(&cluster).await,
+            vec!["node-10.0.0.2", "node-10.0.0.3"]
+        );
+
+        manager.discover_once().await.unwrap();
+        manager.discover_once().await.unwrap();
+        assert_eq!(node_ids(&cluster).await, vec!["node-10.0.0.3"]);
+    }
 }
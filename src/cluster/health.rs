@@ -4,10 +4,19 @@
 //! - Liveness probe (is the process alive?)
 //! - Readiness probe (ready to accept traffic?)
 //! - Startup probe (has initialization completed?)
-
+//!
+//! Readiness also actively probes the node's dependencies: every registered
+//! [`HealthProbe`] runs on each readiness check and must succeed within the
+//! probe timeout. A wedged storage engine or a cluster without a master
+//! makes the node not ready while it stays alive.
+
+use crate::cluster::ClusterState;
+use crate::storage::Storage;
+use async_trait::async_trait;
 use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{info, instrument, warn};
 
@@ -30,6 +39,93 @@ pub struct HealthStatus {
     pub database: DatabaseHealth,
     /// Cluster status
     pub cluster: ClusterHealth,
+    /// Results of the dependency probes, in registration order
+    #[serde(default)]
+    pub checks: Vec<ProbeResult>,
+}
+
+/// Outcome of one dependency probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub name: String,
+    pub healthy: bool,
+    /// Why the probe failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: f64,
+}
+
+/// Time a probe may take before it counts as failed, unless configured
+/// otherwise
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A dependency that must respond for the node to be ready
+#[async_trait]
+pub trait HealthProbe: std::fmt::Debug + Send + Sync {
+    /// Name reported in the health status
+    fn name(&self) -> &str;
+
+    /// Perform a lightweight check of the dependency
+    async fn probe(&self) -> Result<(), String>;
+}
+
+/// Probe that reads a key from the storage engine
+#[derive(Debug)]
+pub struct StorageProbe {
+    storage: Arc<Storage>,
+}
+
+impl StorageProbe {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self { storage }
+    }
+}
+
+#[async_trait]
+impl HealthProbe for StorageProbe {
+    fn name(&self) -> &str {
+        "storage"
+    }
+
+    async fn probe(&self) -> Result<(), String> {
+        self.storage
+            .get(b"__health_probe__")
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Probe that requires the cluster to have a master
+pub struct ClusterProbe {
+    cluster: Arc<ClusterState>,
+}
+
+impl std::fmt::Debug for ClusterProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClusterProbe").finish_non_exhaustive()
+    }
+}
+
+impl ClusterProbe {
+    pub fn new(cluster: Arc<ClusterState>) -> Self {
+        Self { cluster }
+    }
+}
+
+#[async_trait]
+impl HealthProbe for ClusterProbe {
+    fn name(&self) -> &str {
+        "cluster"
+    }
+
+    async fn probe(&self) -> Result<(), String> {
+        if self.cluster.is_master().await || !self.cluster.get_masters().await.is_empty() {
+            Ok(())
+        } else {
+            Err("Cluster has no master".to_string())
+        }
+    }
 }
 
 /// Database health
@@ -52,13 +148,15 @@ pub struct ClusterHealth {
 }
 
 /// Health check manager
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HealthChecker {
     start_time: Arc<RwLock<std::time::Instant>>,
     is_ready: Arc<RwLock<bool>>,
     is_startup_complete: Arc<RwLock<bool>>,
     database_health: Arc<RwLock<DatabaseHealth>>,
     cluster_health: Arc<RwLock<ClusterHealth>>,
+    probes: Arc<RwLock<Vec<Arc<dyn HealthProbe>>>>,
+    probe_timeout: Duration,
 }
 
 impl HealthChecker {
@@ -80,9 +178,23 @@ impl HealthChecker {
                 replicas: 0,
                 replication_lag_ms: 0.0,
             })),
+            probes: Arc::new(RwLock::new(Vec::new())),
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
         }
     }
 
+    /// Fail probes that take longer than `timeout`
+    pub fn with_probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Register a dependency probe, run on every readiness check
+    pub async fn add_probe(&self, probe: Arc<dyn HealthProbe>) {
+        info!(probe = probe.name(), "Health checker: probe registered");
+        self.probes.write().await.push(probe);
+    }
+
     /// Mark as ready
     pub async fn set_ready(&self) {
         let mut is_ready = self.is_ready.write().await;
@@ -129,9 +241,41 @@ impl HealthChecker {
         true
     }
 
+    /// Run all dependency probes
+    pub async fn run_probes(&self) -> Vec<ProbeResult> {
+        let probes = self.probes.read().await.clone();
+        let mut results = Vec::with_capacity(probes.len());
+        for probe in probes {
+            let started = std::time::Instant::now();
+            let outcome = match tokio::time::timeout(self.probe_timeout, probe.probe()).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(format!("Timed out after {:?}", self.probe_timeout)),
+            };
+            if let Err(e) = &outcome {
+                warn!(probe = probe.name(), error = %e, "Health probe failed");
+            }
+            results.push(ProbeResult {
+                name: probe.name().to_string(),
+                healthy: outcome.is_ok(),
+                error: outcome.err(),
+                latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            });
+        }
+        results
+    }
+
     /// Check readiness (can accept traffic)
+    ///
+    /// Runs the dependency probes; any failing probe makes the node not
+    /// ready.
     #[instrument(skip(self))]
     pub async fn check_readiness(&self) -> bool {
+        let probes_healthy = self.run_probes().await.iter().all(|r| r.healthy);
+        self.static_readiness().await && probes_healthy
+    }
+
+    /// Readiness from the reported state alone, without probing
+    async fn static_readiness(&self) -> bool {
         let is_ready = *self.is_ready.read().await;
         let db_health = self.database_health.read().await;
         let cluster_health = self.cluster_health.read().await;
@@ -157,7 +301,8 @@ impl HealthChecker {
         let db_health = self.database_health.read().await.clone();
         let cluster_health = self.cluster_health.read().await.clone();
         
-        let ready = self.check_readiness().await;
+        let checks = self.run_probes().await;
+        let ready = self.static_readiness().await && checks.iter().all(|r| r.healthy);
         let alive = self.check_liveness().await;
         let startup = self.check_startup().await;
 
@@ -178,6 +323,7 @@ impl HealthChecker {
             uptime_seconds: uptime,
             database: db_health,
             cluster: cluster_health,
+            checks,
         }
     }
 }
@@ -364,4 +510,90 @@ mod tests {
         assert_eq!(status.status, "healthy");
         assert_eq!(status.cluster.nodes, 3);
     }
+
+    #[derive(Debug)]
+    struct FailingProbe;
+
+    #[async_trait]
+    impl HealthProbe for FailingProbe {
+        fn name(&self) -> &str {
+            "storage"
+        }
+
+        async fn probe(&self) -> Result<(), String> {
+            Err("Storage engine unavailable".to_string())
+        }
+    }
+
+    #[derive(Debug)]
+    struct HangingProbe;
+
+    #[async_trait]
+    impl HealthProbe for HangingProbe {
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        async fn probe(&self) -> Result<(), String> {
+            std::future::pending().await
+        }
+    }
+
+    async fn ready_checker() -> HealthChecker {
+        let checker = HealthChecker::new().with_probe_timeout(Duration::from_millis(50));
+        checker.set_startup_complete().await;
+        checker.set_ready().await;
+        checker.update_database_health(DatabaseHealth {
+            status: "healthy".to_string(),
+            tables_count: 0,
+            active_queries: 0,
+            connections: 0,
+        }).await;
+        checker.update_cluster_health(ClusterHealth {
+            status: "healthy".to_string(),
+            nodes: 1,
+            masters: 1,
+            replicas: 0,
+            replication_lag_ms: 0.0,
+        }).await;
+        checker
+    }
+
+    #[tokio::test]
+    async fn test_failing_probe_makes_not_ready() {
+        let checker = ready_checker().await;
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        checker.add_probe(Arc::new(StorageProbe::new(storage))).await;
+        assert!(checker.check_readiness().await);
+
+        checker.add_probe(Arc::new(FailingProbe)).await;
+        assert!(!checker.check_readiness().await);
+        assert!(checker.check_liveness().await);
+
+        let status = checker.get_status().await;
+        assert!(!status.ready);
+        assert!(status.alive);
+        assert_eq!(status.status, "degraded");
+        assert_eq!(status.checks.len(), 2);
+        assert!(status.checks[0].healthy);
+        assert_eq!(status.checks[1].error.as_deref(), Some("Storage engine unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_timeout_and_cluster_master() {
+        let checker = ready_checker().await;
+        checker.add_probe(Arc::new(HangingProbe)).await;
+        let checks = checker.run_probes().await;
+        assert!(!checks[0].healthy);
+        assert!(checks[0].error.as_ref().unwrap().starts_with("Timed out"));
+
+        let cluster = Arc::new(ClusterState::new(
+            "node-1".to_string(),
+            crate::cluster::ReplicationConfig::default(),
+        ));
+        let probe = ClusterProbe::new(cluster.clone());
+        assert_eq!(probe.probe().await.unwrap_err(), "Cluster has no master");
+        cluster.init_as_master().await;
+        assert!(probe.probe().await.is_ok());
+    }
 }
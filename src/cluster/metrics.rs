@@ -4,13 +4,19 @@
 //! - Resource metrics (CPU, Memory, Disk, Network)
 //! - Database metrics (QPS, connections, latency)
 //! - Cluster metrics (replication lag, shard distribution)
+//! - Slab utilization per size class, for sizing the classes
 //! - Custom metrics for HPA
 
+use crate::storage::slab::SizeClassStats;
 use prometheus::{
     core::{AtomicU64, GenericCounter, GenericGauge},
-    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
 };
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tracing::{error, info, instrument};
 
@@ -124,8 +130,188 @@ lazy_static::lazy_static! {
         Opts::new("rethinkdb_reads_total", "Total read operations"),
         &["database", "table", "status"]
     ).unwrap();
+
+    pub static ref STORAGE_OP_DURATION: HistogramVec = HistogramVec::new(
+        prometheus::HistogramOpts::new(
+            "photondb_storage_op_duration_seconds",
+            "Storage operation duration in seconds"
+        ).buckets(vec![0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]),
+        &["operation"]
+    ).unwrap();
+
+    pub static ref SLAB_SLOTS_USED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("photondb_slab_slots_used", "Allocated slots per slab size class"),
+        &["class"]
+    ).unwrap();
+
+    pub static ref SLAB_SLOTS_FREE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("photondb_slab_slots_free", "Free slots per slab size class"),
+        &["class"]
+    ).unwrap();
+
+    pub static ref STORAGE_PENDING_WRITES: IntGauge = IntGauge::new(
+        "photondb_storage_pending_writes",
+        "Writes accepted by storage but not yet committed"
+    ).unwrap();
+
+    /// Tables that currently have labeled per-table series
+    static ref TABLE_SERIES: TableSeries = TableSeries::new(MAX_TABLE_SERIES);
+}
+
+/// Most tables tracked in the per-table read/write/row metrics
+///
+/// Tables beyond the cap are not recorded, so a workload creating many
+/// tables cannot blow up the number of series Prometheus has to scrape.
+pub const MAX_TABLE_SERIES: usize = 1000;
+
+/// How far back [`MetricsCollector::query_rates`] looks
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Start timing a storage operation (`get`, `set`, `delete` or `scan`)
+///
+/// The duration is recorded when the returned timer is dropped.
+pub fn storage_op_timer(operation: &str) -> prometheus::HistogramTimer {
+    STORAGE_OP_DURATION
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+/// Why a client connection failed, the `reason` label of
+/// `rethinkdb_connection_errors_total`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionErrorReason {
+    HandshakeFailed,
+    AuthFailed,
+    IoError,
+}
+
+impl ConnectionErrorReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionErrorReason::HandshakeFailed => "handshake_failed",
+            ConnectionErrorReason::AuthFailed => "auth_failed",
+            ConnectionErrorReason::IoError => "io_error",
+        }
+    }
+}
+
+/// Count a failed client connection
+pub fn record_connection_error(reason: ConnectionErrorReason) {
+    CONNECTION_ERRORS.with_label_values(&[reason.as_str()]).inc();
+}
+
+/// Holds a place in `rethinkdb_active_connections` for as long as it lives
+///
+/// Protocol servers take one as soon as a connection is accepted, so the
+/// gauge drops again however the connection ends.
+#[derive(Debug)]
+pub struct ActiveConnection(());
+
+impl ActiveConnection {
+    pub fn open() -> Self {
+        ACTIVE_CONNECTIONS.inc();
+        Self(())
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        ACTIVE_CONNECTIONS.dec();
+    }
+}
+
+/// Bounded set of tables allowed to have labeled series
+#[derive(Debug)]
+struct TableSeries {
+    limit: usize,
+    tracked: Mutex<HashSet<(String, String)>>,
+}
+
+impl TableSeries {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            tracked: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether `database.table` may be recorded, claiming a slot if needed
+    fn track(&self, database: &str, table: &str) -> bool {
+        let mut tracked = self.tracked.lock();
+        let key = (database.to_string(), table.to_string());
+        if tracked.contains(&key) {
+            return true;
+        }
+        if tracked.len() >= self.limit {
+            return false;
+        }
+        tracked.insert(key);
+        true
+    }
+
+    /// Release the slot of `database.table`, returning whether it had one
+    fn forget(&self, database: &str, table: &str) -> bool {
+        self.tracked
+            .lock()
+            .remove(&(database.to_string(), table.to_string()))
+    }
+}
+
+fn status_label(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "error"
+    }
+}
+
+/// Count a document write (insert, update or delete) on a table
+pub fn record_table_write(database: &str, table: &str, success: bool) {
+    if TABLE_SERIES.track(database, table) {
+        WRITES_TOTAL
+            .with_label_values(&[database, table, status_label(success)])
+            .inc();
+    }
+}
+
+/// Count a document read on a table
+pub fn record_table_read(database: &str, table: &str, success: bool) {
+    if TABLE_SERIES.track(database, table) {
+        READS_TOTAL
+            .with_label_values(&[database, table, status_label(success)])
+            .inc();
+    }
+}
+
+/// Set the row count reported for a table
+pub fn set_table_rows(database: &str, table: &str, rows: u64) {
+    if TABLE_SERIES.track(database, table) {
+        ROWS_COUNT
+            .with_label_values(&[database, table])
+            .set(rows as i64);
+    }
+}
+
+/// Drop the series of a deleted table and free its slot
+pub fn forget_table(database: &str, table: &str) {
+    if !TABLE_SERIES.forget(database, table) {
+        return;
+    }
+    ROWS_COUNT.remove_label_values(&[database, table]).ok();
+    for status in ["success", "error"] {
+        WRITES_TOTAL
+            .remove_label_values(&[database, table, status])
+            .ok();
+        READS_TOTAL
+            .remove_label_values(&[database, table, status])
+            .ok();
+    }
 }
 
+/// Serializes tests that assert on the global connection gauge
+#[cfg(test)]
+pub(crate) static CONNECTION_GAUGE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
 /// Initialize metrics registry
 pub fn init_metrics() {
     info!("Initializing Prometheus metrics");
@@ -154,14 +340,43 @@ pub fn init_metrics() {
     METRICS_REGISTRY.register(Box::new(ROWS_COUNT.clone())).ok();
     METRICS_REGISTRY.register(Box::new(WRITES_TOTAL.clone())).ok();
     METRICS_REGISTRY.register(Box::new(READS_TOTAL.clone())).ok();
+    METRICS_REGISTRY.register(Box::new(STORAGE_OP_DURATION.clone())).ok();
+    METRICS_REGISTRY.register(Box::new(SLAB_SLOTS_USED.clone())).ok();
+    METRICS_REGISTRY.register(Box::new(SLAB_SLOTS_FREE.clone())).ok();
+    METRICS_REGISTRY.register(Box::new(STORAGE_PENDING_WRITES.clone())).ok();
 
     info!("Metrics initialized successfully");
 }
 
+/// Queries recorded during one second since the collector was created
+#[derive(Debug, Clone, Copy)]
+struct QueryBucket {
+    second: u64,
+    queries: u64,
+    errors: u64,
+}
+
+/// Query throughput and failures over the last [`RATE_WINDOW`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QueryRates {
+    /// Seconds covered, shorter than the window right after startup
+    pub window_secs: f64,
+    pub queries: u64,
+    pub errors: u64,
+    /// Queries per second
+    pub qps: f64,
+    /// Fraction of queries that failed, 0 without queries
+    pub error_rate: f64,
+}
+
 /// Metrics collector
+#[derive(Debug)]
 pub struct MetricsCollector {
     last_query_count: Arc<RwLock<u64>>,
     last_update: Arc<RwLock<std::time::Instant>>,
+    started: Instant,
+    /// Per-second query counts within [`RATE_WINDOW`], oldest first
+    recent: Mutex<VecDeque<QueryBucket>>,
 }
 
 impl MetricsCollector {
@@ -169,6 +384,8 @@ impl MetricsCollector {
         Self {
             last_query_count: Arc::new(RwLock::new(0)),
             last_update: Arc::new(RwLock::new(std::time::Instant::now())),
+            started: Instant::now(),
+            recent: Mutex::new(VecDeque::new()),
         }
     }
 
@@ -206,6 +423,7 @@ impl MetricsCollector {
         let status = if success { "success" } else { "error" };
         QUERIES_TOTAL.with_label_values(&[query_type, status]).inc();
         QUERY_DURATION.with_label_values(&[query_type]).observe(duration);
+        self.record_recent(success);
 
         // Update QPS
         let mut last_count = self.last_query_count.write().await;
@@ -222,6 +440,55 @@ impl MetricsCollector {
         }
     }
 
+    fn record_recent(&self, success: bool) {
+        let second = self.started.elapsed().as_secs();
+        let mut recent = self.recent.lock();
+        Self::expire(&mut recent, second);
+        match recent.back_mut() {
+            Some(bucket) if bucket.second == second => {
+                bucket.queries += 1;
+                bucket.errors += u64::from(!success);
+            }
+            _ => recent.push_back(QueryBucket {
+                second,
+                queries: 1,
+                errors: u64::from(!success),
+            }),
+        }
+    }
+
+    /// Drop buckets that fell out of the window ending at `second`
+    fn expire(recent: &mut VecDeque<QueryBucket>, second: u64) {
+        while recent
+            .front()
+            .is_some_and(|bucket| bucket.second + RATE_WINDOW.as_secs() <= second)
+        {
+            recent.pop_front();
+        }
+    }
+
+    /// Queries recorded by this collector over the last [`RATE_WINDOW`]
+    pub fn query_rates(&self) -> QueryRates {
+        let elapsed = self.started.elapsed();
+        let mut recent = self.recent.lock();
+        Self::expire(&mut recent, elapsed.as_secs());
+
+        let queries: u64 = recent.iter().map(|bucket| bucket.queries).sum();
+        let errors: u64 = recent.iter().map(|bucket| bucket.errors).sum();
+        let window_secs = elapsed.min(RATE_WINDOW).as_secs_f64().max(1.0);
+        QueryRates {
+            window_secs,
+            queries,
+            errors,
+            qps: queries as f64 / window_secs,
+            error_rate: if queries == 0 {
+                0.0
+            } else {
+                errors as f64 / queries as f64
+            },
+        }
+    }
+
     /// Update connection metrics
     pub fn update_connections(&self, active: u64) {
         ACTIVE_CONNECTIONS.set(active);
@@ -246,6 +513,24 @@ impl MetricsCollector {
         CLUSTER_HEALTH.set(if healthy { 1 } else { 0 });
     }
 
+    /// Update slab utilization; the `class` label is the slot size in bytes
+    pub fn update_slab_metrics(&self, size_classes: &[SizeClassStats]) {
+        for class in size_classes {
+            let label = class.slot_size.to_string();
+            SLAB_SLOTS_USED
+                .with_label_values(&[&label])
+                .set(class.allocated_slots as i64);
+            SLAB_SLOTS_FREE
+                .with_label_values(&[&label])
+                .set(class.free_slots as i64);
+        }
+    }
+
+    /// Update the number of writes storage has accepted but not committed
+    pub fn update_storage_load(&self, pending_writes: usize) {
+        STORAGE_PENDING_WRITES.set(pending_writes as i64);
+    }
+
     /// Update replication lag
     pub fn update_replication_lag(&self, node: &str, lag_seconds: f64) {
         REPLICATION_LAG.with_label_values(&[node]).set(lag_seconds);
@@ -391,14 +676,50 @@ mod tests {
         assert!(metrics.contains("rethinkdb_queries_total"));
     }
 
+    #[tokio::test]
+    async fn test_query_rates() {
+        let collector = MetricsCollector::new();
+        assert_eq!(collector.query_rates().queries, 0);
+        assert_eq!(collector.query_rates().error_rate, 0.0);
+
+        for success in [true, true, true, false] {
+            collector.record_query("GET", 0.01, success).await;
+        }
+        let rates = collector.query_rates();
+        assert_eq!(rates.queries, 4);
+        assert_eq!(rates.errors, 1);
+        assert_eq!(rates.error_rate, 0.25);
+        assert_eq!(rates.window_secs, 1.0);
+        assert_eq!(rates.qps, 4.0);
+
+        // Buckets older than the window are dropped
+        let mut recent = collector.recent.lock();
+        MetricsCollector::expire(&mut recent, RATE_WINDOW.as_secs());
+        assert!(recent.is_empty());
+    }
+
     #[test]
     fn test_update_connections() {
+        let _lock = CONNECTION_GAUGE_LOCK.blocking_lock();
         let collector = MetricsCollector::new();
         collector.update_connections(42);
 
         assert_eq!(ACTIVE_CONNECTIONS.get(), 42);
     }
 
+    #[test]
+    fn test_table_series_cap() {
+        let series = TableSeries::new(2);
+        assert!(series.track("db", "a"));
+        assert!(series.track("db", "b"));
+        assert!(series.track("db", "a"));
+        assert!(!series.track("db", "c"));
+
+        assert!(series.forget("db", "a"));
+        assert!(!series.forget("db", "a"));
+        assert!(series.track("db", "c"));
+    }
+
     #[test]
     fn test_cluster_metrics() {
         let collector = MetricsCollector::new();
@@ -10,18 +10,49 @@
 //! - Prometheus metrics for monitoring
 //! - Health checks for liveness/readiness probes
 
+pub mod breaker;
 pub mod discovery;
 pub mod health;
 pub mod k8s;
 pub mod metrics;
+pub mod ring;
 pub mod scaling;
 
+use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::stream::{FuturesUnordered, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::sync::RwLock;
+use std::{collections::HashMap, fmt, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+use tokio::sync::{watch, RwLock, Semaphore};
 use tracing::{error, info, instrument, warn};
 
+use crate::request_id;
+use crate::reql::Datum;
+use crate::storage::Storage;
+use breaker::{CircuitBreakers, DEFAULT_COOLDOWN, DEFAULT_FAILURE_THRESHOLD};
+use ring::{HashRing, DEFAULT_VIRTUAL_NODES};
+
+/// Time a replicated write may take, retries included, unless configured
+/// otherwise
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Retries of a failed replication call unless configured otherwise
+pub const DEFAULT_WRITE_RETRIES: u32 = 3;
+
+/// Wait before the first retry unless configured otherwise; doubles with
+/// every further retry
+pub const DEFAULT_WRITE_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Replication calls in flight at once, across all writes, unless configured
+/// otherwise
+pub const DEFAULT_MAX_INFLIGHT_REPLICATIONS: usize = 64;
+
+/// Header carrying the cluster secret on calls between nodes
+pub const PEER_SECRET_HEADER: &str = "x-cluster-secret";
+
+/// Time a peer has to answer a vote request
+const VOTE_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Node role in the cluster
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NodeRole {
@@ -50,6 +81,66 @@ pub struct ShardRange {
     pub end: u64,
 }
 
+impl ShardRange {
+    pub fn contains(&self, shard: u64) -> bool {
+        shard >= self.start && shard < self.end
+    }
+}
+
+/// A shard gaining an owner during rebalancing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardMove {
+    pub shard: u64,
+    /// A previous owner still in the cluster; `None` if no live node held
+    /// the shard
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// Shard moves produced by [`ClusterState::rebalance_shards`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationPlan {
+    pub moves: Vec<ShardMove>,
+}
+
+impl MigrationPlan {
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Nodes that newly own `shard`
+    pub fn targets_of(&self, shard: u64) -> impl Iterator<Item = &str> {
+        self.moves
+            .iter()
+            .filter(move |m| m.shard == shard)
+            .map(|m| m.to.as_str())
+    }
+}
+
+/// Readiness of a table's shards, see [`ClusterState::table_readiness`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableReadiness {
+    /// Every shard has a live owner
+    pub ready_for_outdated_reads: bool,
+    /// Every shard has `read_quorum` live owners
+    pub ready_for_reads: bool,
+    /// Every shard has `write_quorum` live owners
+    pub ready_for_writes: bool,
+    /// Every shard has all its `replica_count` owners live, each having
+    /// acknowledged the shard's newest write
+    pub all_replicas_ready: bool,
+}
+
+impl TableReadiness {
+    /// A table served by this node alone
+    pub const STANDALONE: Self = Self {
+        ready_for_outdated_reads: true,
+        ready_for_reads: true,
+        ready_for_writes: true,
+        all_replicas_ready: true,
+    };
+}
+
 /// Replication configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplicationConfig {
@@ -63,6 +154,64 @@ pub struct ReplicationConfig {
     pub enable_read_replicas: bool,
     /// Quorum size for writes (typically replica_count/2 + 1)
     pub write_quorum: usize,
+    /// Number of replicas that must answer a read (read_quorum + write_quorum
+    /// > replica_count guarantees reads see the latest write)
+    #[serde(default = "default_read_quorum")]
+    pub read_quorum: usize,
+    /// Points per node on the consistent-hash ring (see [`ring`])
+    #[serde(default = "default_virtual_nodes")]
+    pub virtual_nodes: usize,
+    /// Consecutive failed calls after which a node is short-circuited
+    #[serde(default = "default_breaker_failure_threshold")]
+    pub breaker_failure_threshold: u32,
+    /// How long a short-circuited node is left alone before it is retried
+    #[serde(default = "default_breaker_cooldown_ms")]
+    pub breaker_cooldown_ms: u64,
+    /// Deadline of a replicated write; failed nodes are retried until then
+    #[serde(default = "default_write_timeout_ms")]
+    pub write_timeout_ms: u64,
+    /// Retries per node after a failed replication call
+    #[serde(default = "default_write_retries")]
+    pub write_retries: u32,
+    /// Wait before the first retry, doubled for every further one
+    #[serde(default = "default_write_retry_backoff_ms")]
+    pub write_retry_backoff_ms: u64,
+    /// Replication calls in flight at once, across all writes; further calls
+    /// wait for a free slot until the write's deadline
+    #[serde(default = "default_max_inflight_replications")]
+    pub max_inflight_replications: usize,
+}
+
+fn default_read_quorum() -> usize {
+    2
+}
+
+fn default_virtual_nodes() -> usize {
+    DEFAULT_VIRTUAL_NODES
+}
+
+fn default_breaker_failure_threshold() -> u32 {
+    DEFAULT_FAILURE_THRESHOLD
+}
+
+fn default_breaker_cooldown_ms() -> u64 {
+    DEFAULT_COOLDOWN.as_millis() as u64
+}
+
+fn default_write_timeout_ms() -> u64 {
+    DEFAULT_WRITE_TIMEOUT.as_millis() as u64
+}
+
+fn default_write_retries() -> u32 {
+    DEFAULT_WRITE_RETRIES
+}
+
+fn default_write_retry_backoff_ms() -> u64 {
+    DEFAULT_WRITE_RETRY_BACKOFF.as_millis() as u64
+}
+
+fn default_max_inflight_replications() -> usize {
+    DEFAULT_MAX_INFLIGHT_REPLICATIONS
 }
 
 impl Default for ReplicationConfig {
@@ -73,25 +222,190 @@ impl Default for ReplicationConfig {
             shard_count: 16,
             enable_read_replicas: true,
             write_quorum: 2,
+            read_quorum: default_read_quorum(),
+            virtual_nodes: DEFAULT_VIRTUAL_NODES,
+            breaker_failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            breaker_cooldown_ms: default_breaker_cooldown_ms(),
+            write_timeout_ms: default_write_timeout_ms(),
+            write_retries: DEFAULT_WRITE_RETRIES,
+            write_retry_backoff_ms: default_write_retry_backoff_ms(),
+            max_inflight_replications: DEFAULT_MAX_INFLIGHT_REPLICATIONS,
         }
     }
 }
 
+/// Storage key holding the replication version of `key`
+pub fn version_key(key: &[u8]) -> Vec<u8> {
+    let mut version_key = b"__meta__:versions:".to_vec();
+    version_key.extend_from_slice(key);
+    version_key
+}
+
+/// New replication version for a write (microseconds since the epoch)
+fn next_version() -> u64 {
+    chrono::Utc::now().timestamp_micros().max(0) as u64
+}
+
+/// Vote request a candidate sends to `/internal/vote`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoteRequest {
+    /// Term the candidate runs in
+    pub term: u64,
+    pub candidate_id: String,
+}
+
+/// Answer to a [`VoteRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteResponse {
+    /// Term of the voter, newer than the candidate's if it must step down
+    pub term: u64,
+    pub granted: bool,
+}
+
+/// Election term and the vote this node cast in it
+#[derive(Debug, Default)]
+struct ElectionState {
+    /// Only ever increases
+    term: u64,
+    voted_for: Option<String>,
+}
+
 /// Cluster state
 pub struct ClusterState {
     config: ReplicationConfig,
     nodes: Arc<RwLock<HashMap<String, Node>>>,
+    /// Ring of the ids in `nodes`; locked after `nodes` when both are held
+    ring: Arc<RwLock<HashRing>>,
+    /// Owners of every shard as of the last rebalance; locked after `ring`
+    placement: Arc<RwLock<Vec<Vec<String>>>>,
+    /// Newest write version each node has acknowledged
+    applied: Arc<RwLock<HashMap<String, u64>>>,
+    /// Newest write version replicated, by shard
+    shard_versions: Arc<RwLock<HashMap<u64, u64>>>,
+    /// Pooled client for all calls to other nodes
+    http: reqwest::Client,
+    /// Slots for replication calls, `max_inflight_replications` in total
+    replication_permits: Arc<Semaphore>,
+    breakers: CircuitBreakers,
     current_node_id: String,
     current_role: Arc<RwLock<NodeRole>>,
+    /// Locked before `current_role` when both are held
+    election: Arc<RwLock<ElectionState>>,
+    sharding: ShardingStrategy,
+    /// Shared by all nodes; proves a call to `/internal/*` comes from a peer
+    secret: Option<String>,
+    /// Bumped whenever a node joins or leaves
+    membership: watch::Sender<u64>,
+}
+
+impl fmt::Debug for ClusterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterState")
+            .field("node_id", &self.current_node_id)
+            .finish()
+    }
+}
+
+/// Feed the outcome of a call to `node_id` into its circuit breaker
+fn note_outcome(breakers: &CircuitBreakers, cooldown_ms: u64, node_id: &str, ok: bool) {
+    if ok {
+        breakers.record_success(node_id);
+    } else if breakers.record_failure(node_id) {
+        warn!(
+            node_id = %node_id,
+            cooldown_ms = cooldown_ms,
+            "Circuit opened, node marked degraded"
+        );
+    }
+}
+
+/// Remember in `applied` that `node_id` has applied the write with `version`
+async fn note_applied(applied: &RwLock<HashMap<String, u64>>, node_id: &str, version: u64) {
+    let mut applied = applied.write().await;
+    let newest = applied.entry(node_id.to_string()).or_insert(0);
+    *newest = (*newest).max(version);
 }
 
 impl ClusterState {
     pub fn new(node_id: String, config: ReplicationConfig) -> Self {
         Self {
+            ring: Arc::new(RwLock::new(HashRing::new(config.virtual_nodes))),
+            placement: Arc::new(RwLock::new(Vec::new())),
+            breakers: CircuitBreakers::new(
+                config.breaker_failure_threshold,
+                Duration::from_millis(config.breaker_cooldown_ms),
+            ),
+            replication_permits: Arc::new(Semaphore::new(config.max_inflight_replications.max(1))),
             config,
             nodes: Arc::new(RwLock::new(HashMap::new())),
+            applied: Arc::new(RwLock::new(HashMap::new())),
+            shard_versions: Arc::new(RwLock::new(HashMap::new())),
+            http: reqwest::Client::new(),
             current_node_id: node_id,
             current_role: Arc::new(RwLock::new(NodeRole::Replica)),
+            election: Arc::new(RwLock::new(ElectionState::default())),
+            sharding: ShardingStrategy::Hash,
+            secret: None,
+            membership: watch::Sender::new(0),
+        }
+    }
+
+    /// Route keys with the given sharding strategy instead of hashing
+    pub fn with_sharding_strategy(mut self, strategy: ShardingStrategy) -> Self {
+        self.sharding = strategy;
+        self
+    }
+
+    /// Authenticate calls between nodes with `secret`
+    ///
+    /// The secret is sent with every call to another node and required on
+    /// every call to this node's `/internal/*` endpoints.
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        let mut headers = reqwest::header::HeaderMap::new();
+        match reqwest::header::HeaderValue::from_str(&secret) {
+            Ok(mut value) => {
+                value.set_sensitive(true);
+                headers.insert(PEER_SECRET_HEADER, value);
+            }
+            Err(_) => warn!("Cluster secret is not a valid header value; peers will reject calls"),
+        }
+        self.http = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap_or_default();
+        self.secret = Some(secret);
+        self
+    }
+
+    /// Whether a call carrying `secret` comes from a cluster peer
+    ///
+    /// Without a configured secret no call can prove that.
+    pub fn is_peer(&self, secret: Option<&str>) -> bool {
+        match (&self.secret, secret) {
+            (Some(expected), Some(given)) => {
+                // Compare in constant time to not leak the secret's prefix
+                expected.len() == given.len()
+                    && expected
+                        .bytes()
+                        .zip(given.bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether calls between nodes are authenticated with a secret
+    pub fn has_secret(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Number of shards keys are routed to
+    pub fn shard_count(&self) -> u64 {
+        match &self.sharding {
+            ShardingStrategy::Range(split_points) => split_points.len() as u64 + 1,
+            _ => self.config.shard_count as u64,
         }
     }
 
@@ -103,6 +417,11 @@ impl ClusterState {
         *role = NodeRole::Master;
     }
 
+    /// Id of this node
+    pub fn node_id(&self) -> &str {
+        &self.current_node_id
+    }
+
     /// Add a node to the cluster
     #[instrument(skip(self))]
     pub async fn add_node(&self, node: Node) {
@@ -114,7 +433,10 @@ impl ClusterState {
         );
 
         let mut nodes = self.nodes.write().await;
-        nodes.insert(node.id.clone(), node);
+        self.ring.write().await.add_node(&node.id);
+        if nodes.insert(node.id.clone(), node).is_none() {
+            self.membership.send_modify(|changes| *changes += 1);
+        }
     }
 
     /// Remove a node from the cluster
@@ -123,7 +445,39 @@ impl ClusterState {
         warn!(node_id = %node_id, "Removing node from cluster");
 
         let mut nodes = self.nodes.write().await;
-        nodes.remove(node_id);
+        let removed = nodes.remove(node_id).is_some();
+        self.ring.write().await.remove_node(node_id);
+        self.applied.write().await.remove(node_id);
+        self.breakers.remove(node_id);
+        if removed {
+            self.membership.send_modify(|changes| *changes += 1);
+        }
+    }
+
+    /// Receiver notified whenever a node joins or leaves
+    pub fn membership_changes(&self) -> watch::Receiver<u64> {
+        self.membership.subscribe()
+    }
+
+    /// Client shared by all calls to other nodes
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// Whether calls to `node_id` are currently short-circuited after
+    /// repeated failures
+    pub fn is_degraded(&self, node_id: &str) -> bool {
+        self.breakers.state(node_id) != breaker::CircuitState::Closed
+    }
+
+    /// Ids of all degraded nodes, sorted
+    pub fn degraded_nodes(&self) -> Vec<String> {
+        self.breakers.tripped()
+    }
+
+    /// Report the outcome of a call to `node_id` to its circuit breaker
+    fn record_outcome(&self, node_id: &str, ok: bool) {
+        note_outcome(&self.breakers, self.config.breaker_cooldown_ms, node_id, ok);
     }
 
     /// Get current node role
@@ -162,37 +516,68 @@ impl ClusterState {
             .collect()
     }
 
-    /// Calculate shard for a given key using consistent hashing
+    /// Calculate shard for a given key using the configured strategy
     pub fn calculate_shard(&self, key: &[u8]) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let hash = hasher.finish();
+        match &self.sharding {
+            ShardingStrategy::Range(split_points) => {
+                split_points.partition_point(|point| point.as_slice() <= key) as u64
+            }
+            ShardingStrategy::Custom(shard_fn) => {
+                shard_fn(key) % (self.config.shard_count.max(1) as u64)
+            }
+            ShardingStrategy::Hash => self.hash_shard(key),
+        }
+    }
 
-        hash % self.config.shard_count as u64
+    /// Calculate shard for a given key by hashing it
+    ///
+    /// XXH3 gives the same shard on every node and across releases, unlike
+    /// `DefaultHasher`, whose output may change with the Rust version.
+    fn hash_shard(&self, key: &[u8]) -> u64 {
+        xxhash_rust::xxh3::xxh3_64(key) % self.config.shard_count.max(1) as u64
     }
 
     /// Get nodes responsible for a shard
+    ///
+    /// Shards are routed through the consistent-hash ring to
+    /// `replica_count` distinct nodes, so membership changes only move the
+    /// shards next to the node that joined or left. The first node is the
+    /// shard's primary.
     pub async fn get_shard_nodes(&self, shard: u64) -> Vec<Node> {
         let nodes = self.nodes.read().await;
-        nodes
-            .values()
-            .filter(|n| {
-                if let Some(range) = &n.shard_range {
-                    shard >= range.start && shard < range.end
-                } else {
-                    false
-                }
-            })
-            .cloned()
+        let ring = self.ring.read().await;
+        Self::ring_owners(&nodes, &ring, shard, self.config.replica_count.max(1))
+            .iter()
+            .filter_map(|id| nodes.get(id).cloned())
+            .collect()
+    }
+
+    /// Ids of the nodes the ring places `shard` on, at most `count`
+    fn ring_owners(
+        nodes: &HashMap<String, Node>,
+        ring: &HashRing,
+        shard: u64,
+        count: usize,
+    ) -> Vec<String> {
+        ring.nodes_for(&shard.to_be_bytes(), count)
+            .into_iter()
+            .filter(|id| nodes.contains_key(*id))
+            .map(str::to_string)
             .collect()
     }
 
     /// Replicate data to replica nodes
-    #[instrument(skip(self))]
-    pub async fn replicate(&self, key: &[u8], _data: &[u8]) -> Result<(), String> {
+    ///
+    /// Returns the token of the write once `write_quorum` nodes applied it.
+    /// Nodes that fail are retried with exponential backoff, at most
+    /// `write_retries` times and only until `write_timeout_ms` has passed.
+    #[instrument(skip(self, data))]
+    pub async fn replicate(&self, key: &[u8], data: &[u8]) -> Result<WriteToken, String> {
+        let version = next_version();
+        let deadline =
+            tokio::time::Instant::now() + Duration::from_millis(self.config.write_timeout_ms);
+        let retries = self.config.write_retries;
+        let backoff = Duration::from_millis(self.config.write_retry_backoff_ms);
         let shard = self.calculate_shard(key);
         let nodes = self.get_shard_nodes(shard).await;
 
@@ -211,29 +596,79 @@ impl ClusterState {
             );
             return Err("Insufficient replicas for write quorum".to_string());
         }
+        self.shard_versions.write().await.insert(shard, version);
 
         // Replicate to all nodes in parallel
-        let mut replication_tasks = Vec::new();
+        let mut replication_tasks = FuturesUnordered::new();
         
         for node in nodes.iter() {
+            // Degraded nodes fail fast and count against the quorum
+            if let Err(e) = self.breakers.check(&node.id) {
+                warn!(node_id = %node.id, error = %e, "Skipping replication");
+                continue;
+            }
+
+            let client = self.http.clone();
+            let permits = self.replication_permits.clone();
+            let breakers = self.breakers.clone();
+            let cooldown_ms = self.config.breaker_cooldown_ms;
+            let applied = self.applied.clone();
             let node_addr = node.addr;
             let node_id = node.id.clone();
             let key = key.to_vec();
-            let data = _data.to_vec();
+            let data = data.to_vec();
             
-            // Spawn replication task for each node
-            let task = tokio::spawn(async move {
-                Self::replicate_to_node(node_addr, &node_id, &key, &data).await
-            });
+            // Spawn replication task for each node; it records its own
+            // outcome, so it may outlive the write
+            let task = tokio::spawn(request_id::inherit(async move {
+                let mut delay = backoff;
+                let mut attempt = 0;
+                let result = loop {
+                    // The slot is held for the call only, not while backing off
+                    let call = async {
+                        let _permit = permits.acquire().await.map_err(|e| e.to_string())?;
+                        Self::replicate_to_node(&client, node_addr, &node_id, &key, &data, version)
+                            .await
+                    };
+                    let result = tokio::time::timeout_at(deadline, call)
+                        .await
+                        .unwrap_or_else(|_| Err("Write deadline exceeded".to_string()));
+                    match result {
+                        Err(e)
+                            if attempt < retries
+                                && tokio::time::Instant::now() + delay < deadline =>
+                        {
+                            attempt += 1;
+                            warn!(
+                                node_id = %node_id,
+                                error = %e,
+                                attempt = attempt,
+                                "Retrying replication"
+                            );
+                            tokio::time::sleep(delay).await;
+                            delay *= 2;
+                        }
+                        result => break result,
+                    }
+                };
+                note_outcome(&breakers, cooldown_ms, &node_id, result.is_ok());
+                if result.is_ok() {
+                    note_applied(&applied, &node_id, version).await;
+                }
+                result
+            }));
             
             replication_tasks.push(task);
         }
 
-        // Wait for write quorum confirmations
+        // Wait for write quorum confirmations only; the calls to slower
+        // replicas go on in the background
         let mut successful_replications = 0;
-        for task in replication_tasks {
-            if let Ok(Ok(())) = task.await {
-                successful_replications += 1;
+        while successful_replications < self.config.write_quorum {
+            match replication_tasks.next().await {
+                Some(Ok(Ok(()))) => successful_replications += 1,
+                Some(_) => {}
+                None => break,
             }
         }
 
@@ -244,7 +679,7 @@ impl ClusterState {
         );
 
         if successful_replications >= self.config.write_quorum {
-            Ok(())
+            Ok(WriteToken(version))
         } else {
             error!(
                 successful = successful_replications,
@@ -267,12 +702,81 @@ impl ClusterState {
         }
     }
 
+    /// Remember that `node_id` has applied the write with `version`
+    async fn record_applied(&self, node_id: &str, version: u64) {
+        note_applied(&self.applied, node_id, version).await;
+    }
+
+    /// Whether `node_id` is known to have applied the write behind `token`
+    pub async fn has_applied(&self, node_id: &str, token: WriteToken) -> bool {
+        self.applied
+            .read()
+            .await
+            .get(node_id)
+            .is_some_and(|&version| version >= token.0)
+    }
+
+    /// Shards that may hold documents of `db.table`
+    ///
+    /// Under range sharding these are the shards the table's key range
+    /// spans; otherwise its documents may land on any shard.
+    fn table_shards(&self, db: &str, table: &str) -> Vec<u64> {
+        match &self.sharding {
+            ShardingStrategy::Range(split_points) => {
+                let prefix = format!("doc:{}:{}:", db, table).into_bytes();
+                let mut end = prefix.clone();
+                *end.last_mut().unwrap() += 1;
+                let first = self.calculate_shard(&prefix);
+                let last = split_points.partition_point(|point| *point < end) as u64;
+                (first..=last).collect()
+            }
+            _ => (0..self.shard_count()).collect(),
+        }
+    }
+
+    /// Readiness of `db.table`, from the owners of its shards
+    ///
+    /// Owners whose circuit breaker is open do not count as live. A node
+    /// that knows no other nodes serves every table alone and is always
+    /// ready.
+    pub async fn table_readiness(&self, db: &str, table: &str) -> TableReadiness {
+        if self.nodes.read().await.is_empty() {
+            return TableReadiness::STANDALONE;
+        }
+
+        let mut readiness = TableReadiness::STANDALONE;
+        for shard in self.table_shards(db, table) {
+            let owners = self.get_shard_nodes(shard).await;
+            let live: Vec<&Node> = owners
+                .iter()
+                .filter(|node| !self.is_degraded(&node.id))
+                .collect();
+            let newest = self.shard_versions.read().await.get(&shard).copied();
+            let mut acknowledged = true;
+            if let Some(newest) = newest {
+                for node in &owners {
+                    acknowledged &= self.has_applied(&node.id, WriteToken(newest)).await;
+                }
+            }
+
+            readiness.ready_for_outdated_reads &= !live.is_empty();
+            readiness.ready_for_reads &= live.len() >= self.config.read_quorum.max(1);
+            readiness.ready_for_writes &= live.len() >= self.config.write_quorum.max(1);
+            readiness.all_replicas_ready &= live.len() == owners.len()
+                && owners.len() >= self.config.replica_count.max(1)
+                && acknowledged;
+        }
+        readiness
+    }
+
     /// Replicate data to a single node via HTTP
     async fn replicate_to_node(
+        client: &reqwest::Client,
         node_addr: SocketAddr,
         node_id: &str,
         key: &[u8],
         data: &[u8],
+        version: u64,
     ) -> Result<(), String> {
         // Build HTTP request to node's replication endpoint
         let url = format!("http://{}/internal/replicate", node_addr);
@@ -281,14 +785,14 @@ impl ClusterState {
         let payload = serde_json::json!({
             "key": BASE64.encode(key),
             "data": BASE64.encode(data),
+            "version": version,
         });
 
         // Send replication request with timeout
         match tokio::time::timeout(
             tokio::time::Duration::from_secs(5),
             async {
-                reqwest::Client::new()
-                    .post(&url)
+                with_request_id(client.post(&url))
                     .json(&payload)
                     .send()
                     .await
@@ -320,22 +824,254 @@ impl ClusterState {
     }
 
     /// Check for dead nodes and remove them
+    ///
+    /// If the master is among them, the survivors elect a new one with
+    /// [`start_election`](Self::start_election). They stand in id order, one
+    /// vote timeout apart, and a node that has already voted for a live
+    /// candidate does not stand, so usually a single election runs.
     #[instrument(skip(self))]
     pub async fn check_dead_nodes(&self) {
         let timeout = chrono::Duration::seconds(30);
         let now = chrono::Utc::now();
 
-        let mut nodes = self.nodes.write().await;
-        let dead_nodes: Vec<String> = nodes
+        let master_lost = {
+            let mut nodes = self.nodes.write().await;
+            let dead_nodes: Vec<String> = nodes
+                .iter()
+                .filter(|(_, node)| now.signed_duration_since(node.last_heartbeat) > timeout)
+                .map(|(id, _)| id.clone())
+                .collect();
+
+            let mut ring = self.ring.write().await;
+            let mut master_lost = false;
+            for node_id in &dead_nodes {
+                warn!(node_id = %node_id, "Removing dead node");
+                ring.remove_node(node_id);
+                if let Some(node) = nodes.remove(node_id) {
+                    master_lost |= node.role == NodeRole::Master;
+                }
+            }
+            if !dead_nodes.is_empty() {
+                self.membership.send_modify(|changes| *changes += 1);
+            }
+            master_lost && !nodes.values().any(|n| n.role == NodeRole::Master)
+        };
+        if !master_lost || self.is_master().await {
+            return;
+        }
+
+        let rank = self
+            .get_nodes()
+            .await
             .iter()
-            .filter(|(_, node)| now.signed_duration_since(node.last_heartbeat) > timeout)
-            .map(|(id, _)| id.clone())
+            .filter(|n| n.id < self.current_node_id)
+            .count();
+        tokio::time::sleep(VOTE_TIMEOUT * rank as u32).await;
+
+        let voted_for = self.election.read().await.voted_for.clone();
+        let backing = match voted_for {
+            Some(id) if id != self.current_node_id => self.nodes.read().await.contains_key(&id),
+            _ => false,
+        };
+        if backing {
+            info!("Master lost, another candidate is running");
+            return;
+        }
+        self.start_election().await;
+    }
+
+    /// Current election term
+    pub async fn current_term(&self) -> u64 {
+        self.election.read().await.term
+    }
+
+    /// Adopt `term` if it is newer than ours, stepping down to `Replica`
+    ///
+    /// Returns whether the term was newer.
+    pub async fn observe_term(&self, term: u64) -> bool {
+        let mut election = self.election.write().await;
+        self.adopt_term(&mut election, term).await
+    }
+
+    /// [`observe_term`](Self::observe_term) with the election state locked
+    async fn adopt_term(&self, election: &mut ElectionState, term: u64) -> bool {
+        if term <= election.term {
+            return false;
+        }
+        info!(
+            term = term,
+            previous = election.term,
+            "Stepping down for newer term"
+        );
+        election.term = term;
+        election.voted_for = None;
+        *self.current_role.write().await = NodeRole::Replica;
+        true
+    }
+
+    /// Answer a candidate's request for our vote
+    ///
+    /// A newer term is adopted first. The vote goes to the first candidate
+    /// asking in our term; requests from older terms are refused.
+    #[instrument(skip(self))]
+    pub async fn vote(&self, request: &VoteRequest) -> VoteResponse {
+        let mut election = self.election.write().await;
+        self.adopt_term(&mut election, request.term).await;
+
+        let granted = request.term == election.term
+            && election
+                .voted_for
+                .as_ref()
+                .is_none_or(|id| *id == request.candidate_id);
+        if granted {
+            election.voted_for = Some(request.candidate_id.clone());
+        }
+        info!(
+            candidate = %request.candidate_id,
+            term = request.term,
+            granted = granted,
+            "Answered vote request"
+        );
+        VoteResponse {
+            term: election.term,
+            granted,
+        }
+    }
+
+    /// Run for master in a new term
+    ///
+    /// This node becomes `Candidate`, increments the term, votes for itself
+    /// and asks every known node for its vote over `/internal/vote`. Votes
+    /// from a majority of the cluster, this node included, make it `Master`;
+    /// otherwise, or on seeing a newer term, it steps down to `Replica`.
+    /// Unreachable nodes count as refusing. Returns whether it won.
+    #[instrument(skip(self))]
+    pub async fn start_election(&self) -> bool {
+        let term = {
+            let mut election = self.election.write().await;
+            election.term += 1;
+            election.voted_for = Some(self.current_node_id.clone());
+            *self.current_role.write().await = NodeRole::Candidate;
+            election.term
+        };
+        let peers = self.get_nodes().await;
+        info!(term = term, peers = peers.len(), "Starting election");
+
+        let request = VoteRequest {
+            term,
+            candidate_id: self.current_node_id.clone(),
+        };
+        let tasks: Vec<_> = peers
+            .into_iter()
+            .map(|node| {
+                let client = self.http.clone();
+                let request = request.clone();
+                tokio::spawn(request_id::inherit(async move {
+                    Self::request_vote(&client, &node, &request).await
+                }))
+            })
             .collect();
 
-        for node_id in dead_nodes {
-            warn!(node_id = %node_id, "Removing dead node");
-            nodes.remove(&node_id);
+        let mut votes = 1;
+        let voters = tasks.len() + 1;
+        for task in tasks {
+            let Ok(Some(answer)) = task.await else {
+                continue;
+            };
+            if self.observe_term(answer.term).await {
+                return false;
+            }
+            votes += usize::from(answer.granted);
+        }
+
+        // A newer candidate may have taken our vote and term meanwhile
+        let election = self.election.read().await;
+        let mut role = self.current_role.write().await;
+        if election.term != term || *role != NodeRole::Candidate {
+            return false;
+        }
+        let majority = voters / 2 + 1;
+        let won = votes >= majority;
+        *role = if won {
+            NodeRole::Master
+        } else {
+            NodeRole::Replica
+        };
+        info!(
+            term = term,
+            votes = votes,
+            majority = majority,
+            won = won,
+            "Election finished"
+        );
+        won
+    }
+
+    /// Ask `node` for its vote; `None` if it did not answer
+    async fn request_vote(
+        client: &reqwest::Client,
+        node: &Node,
+        request: &VoteRequest,
+    ) -> Option<VoteResponse> {
+        let url = format!("http://{}/internal/vote", node.addr);
+        let response = with_request_id(client.post(&url))
+            .json(request)
+            .timeout(VOTE_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(response) => response.json().await.ok(),
+            Err(e) => {
+                warn!(node_id = %node.id, error = %e, "Vote request failed");
+                None
+            }
+        }
+    }
+
+    /// Find the nodes that gained shards since the last rebalance
+    ///
+    /// Shards live where the consistent-hash ring places them (see
+    /// [`get_shard_nodes`](Self::get_shard_nodes)), so a join moves about
+    /// `1/n` of the shards to the new node and a leave hands the departed
+    /// node's shards to the nodes after it on the ring. The returned plan
+    /// lists every node that newly owns a shard and needs its data; the
+    /// first rebalance lists every owner.
+    ///
+    /// [`ReplicationManager::start_rebalancing`] runs this on every join
+    /// and leave.
+    #[instrument(skip(self))]
+    pub async fn rebalance_shards(&self) -> MigrationPlan {
+        let shard_count = self.shard_count();
+        let replica_count = self.config.replica_count.max(1);
+        let nodes = self.nodes.read().await;
+        let ring = self.ring.read().await;
+        let mut placement = self.placement.write().await;
+        let owners: Vec<Vec<String>> = (0..shard_count)
+            .map(|shard| Self::ring_owners(&nodes, &ring, shard, replica_count))
+            .collect();
+
+        let mut plan = MigrationPlan::default();
+        for (shard, new) in (0..shard_count).zip(&owners) {
+            let old = placement.get(shard as usize).map(Vec::as_slice).unwrap_or_default();
+            let from = old.iter().find(|id| nodes.contains_key(*id));
+            for id in new.iter().filter(|id| !old.contains(id)) {
+                plan.moves.push(ShardMove {
+                    shard,
+                    from: from.cloned(),
+                    to: id.clone(),
+                });
+            }
         }
+        *placement = owners;
+
+        info!(
+            nodes = nodes.len(),
+            shards = shard_count,
+            moves = plan.moves.len(),
+            "Rebalanced shards"
+        );
+        plan
     }
 }
 
@@ -343,65 +1079,196 @@ impl ClusterState {
 pub enum ShardingStrategy {
     /// Hash-based sharding (default)
     Hash,
-    /// Range-based sharding
-    Range,
-    /// Custom sharding function
+    /// Range-based sharding over sorted, distinct split points
+    ///
+    /// Shard `i` holds keys in `[split_points[i - 1], split_points[i])`, so
+    /// `n` split points give `n + 1` shards and ordered keys land on
+    /// contiguous shards. Build with [`ShardingStrategy::range`].
+    Range(Vec<Vec<u8>>),
+    /// Custom sharding function; results are taken modulo `shard_count`
     Custom(Box<ShardingFn>),
 }
 
 /// Type alias for custom sharding functions
 type ShardingFn = dyn Fn(&[u8]) -> u64 + Send + Sync;
 
-/// Replication manager
-pub struct ReplicationManager {
-    cluster: Arc<ClusterState>,
+impl ShardingStrategy {
+    /// Range sharding with the given split points, in any order
+    pub fn range<K: Into<Vec<u8>>>(split_points: impl IntoIterator<Item = K>) -> Self {
+        let mut split_points: Vec<Vec<u8>> = split_points.into_iter().map(Into::into).collect();
+        split_points.sort();
+        split_points.dedup();
+        ShardingStrategy::Range(split_points)
+    }
+
+    /// Shard with a custom function, e.g. to co-locate keys of one tenant
+    pub fn custom<F>(shard_fn: F) -> Self
+    where
+        F: Fn(&[u8]) -> u64 + Send + Sync + 'static,
+    {
+        ShardingStrategy::Custom(Box::new(shard_fn))
+    }
 }
 
-impl ReplicationManager {
-    pub fn new(cluster: Arc<ClusterState>) -> Self {
-        Self { cluster }
+/// A value read from a replica together with its replication version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionedValue {
+    pub data: Vec<u8>,
+    pub version: u64,
+}
+
+/// Consistency of a replicated read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadMode {
+    /// One node that has applied the client's last write
+    Single,
+    /// A majority of the shard's nodes, all of which have applied the
+    /// client's last write
+    Majority,
+    /// Any single node, possibly behind the client's own writes
+    Outdated,
+}
+
+impl ReadMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadMode::Single => "single",
+            ReadMode::Majority => "majority",
+            ReadMode::Outdated => "outdated",
+        }
     }
+}
 
-    /// Start replication background task
-    #[instrument(skip(self))]
-    pub async fn start(&self) {
-        info!("Starting replication manager");
+impl fmt::Display for ReadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
-        // Spawn heartbeat task
-        let cluster = self.cluster.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
-                cluster.check_dead_nodes().await;
-            }
-        });
+impl FromStr for ReadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "single" => Ok(ReadMode::Single),
+            "majority" => Ok(ReadMode::Majority),
+            "outdated" => Ok(ReadMode::Outdated),
+            other => Err(format!(
+                "Invalid read_mode `{}` (expected single, majority or outdated)",
+                other
+            )),
+        }
+    }
+}
 
-        info!("Replication manager started");
+/// Sequence token of an acknowledged write
+///
+/// Tokens are the replication versions of the writes, so a later write
+/// always has a greater token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct WriteToken(pub u64);
+
+impl WriteToken {
+    /// Token of a write acknowledged now, ordered with replicated writes
+    pub fn now() -> Self {
+        WriteToken(next_version())
     }
+}
 
-    /// Perform write with replication
-    #[instrument(skip(self, value))]
-    pub async fn write(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
-        // Check if we're master
-        if !self.cluster.is_master().await {
-            return Err("Not master node".to_string());
+/// How a single read is routed
+///
+/// The default (no mode) is a plain quorum read, see
+/// [`ReplicationManager::read`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    pub mode: Option<ReadMode>,
+    /// The client's last acknowledged write
+    pub after: Option<WriteToken>,
+}
+
+/// Read-your-writes state of one client connection
+///
+/// Tracks the newest write the client saw acknowledged so reads can be
+/// routed to nodes that already applied it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadSession {
+    read_mode: Option<ReadMode>,
+    last_write: Option<WriteToken>,
+}
+
+impl ReadSession {
+    /// Session reading with `read_mode` unless a query overrides it
+    pub fn new(read_mode: Option<ReadMode>) -> Self {
+        Self {
+            read_mode,
+            last_write: None,
         }
+    }
 
-        // Replicate to other nodes
-        self.cluster.replicate(key, value).await?;
+    pub fn read_mode(&self) -> Option<ReadMode> {
+        self.read_mode
+    }
 
-        Ok(())
+    pub fn last_write(&self) -> Option<WriteToken> {
+        self.last_write
     }
 
-    /// Read data from a single node via HTTP
-    async fn read_from_node(
-        node_addr: SocketAddr,
-        node_id: &str,
-        key: &[u8],
-    ) -> Result<Vec<u8>, String> {
+    /// Record an acknowledged write of this client
+    pub fn record_write(&mut self, token: WriteToken) {
+        self.last_write = self.last_write.max(Some(token));
+    }
+
+    /// Options for the next read, with the query's mode taking precedence
+    /// over the connection's
+    pub fn read_options(&self, query_mode: Option<ReadMode>) -> ReadOptions {
+        ReadOptions {
+            mode: query_mode.or(self.read_mode),
+            after: self.last_write,
+        }
+    }
+}
+
+/// Documents fetched per scan page when migrating shards
+const MIGRATION_PAGE_SIZE: usize = 1000;
+
+/// Reads a key from a single node
+///
+/// `Ok(None)` means the node answered but does not hold the key; it still
+/// counts towards the read quorum.
+#[async_trait]
+pub trait NodeReader: Send + Sync {
+    async fn read(&self, node: &Node, key: &[u8]) -> Result<Option<VersionedValue>, String>;
+}
+
+/// `request` tagged with the id of the request it is made for, if any
+fn with_request_id(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match request_id::current() {
+        Some(id) => request.header(request_id::REQUEST_ID_HEADER, id),
+        None => request,
+    }
+}
+
+/// Reads from other nodes through their `/internal/read` endpoint
+pub struct HttpNodeReader {
+    client: reqwest::Client,
+}
+
+impl HttpNodeReader {
+    /// Reader sending its requests through `client`
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl NodeReader for HttpNodeReader {
+    async fn read(&self, node: &Node, key: &[u8]) -> Result<Option<VersionedValue>, String> {
+        let node_id = &node.id;
+
         // Build HTTP request to node's read endpoint
-        let url = format!("http://{}/internal/read", node_addr);
-        
+        let url = format!("http://{}/internal/read", node.addr);
+
         // Create payload with key
         let payload = serde_json::json!({
             "key": BASE64.encode(key),
@@ -411,8 +1278,7 @@ impl ReplicationManager {
         match tokio::time::timeout(
             tokio::time::Duration::from_secs(5),
             async {
-                reqwest::Client::new()
-                    .post(&url)
+                with_request_id(self.client.post(&url))
                     .json(&payload)
                     .send()
                     .await
@@ -424,11 +1290,17 @@ impl ReplicationManager {
                 // Parse response and decode data
                 match response.json::<serde_json::Value>().await {
                     Ok(json) => {
+                        let version = json.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
                         if let Some(data_b64) = json.get("data").and_then(|v| v.as_str()) {
                             match BASE64.decode(data_b64) {
                                 Ok(data) => {
-                                    info!(node_id = %node_id, size = data.len(), "Read successful");
-                                    Ok(data)
+                                    info!(
+                                        node_id = %node_id,
+                                        size = data.len(),
+                                        version = version,
+                                        "Read successful"
+                                    );
+                                    Ok(Some(VersionedValue { data, version }))
                                 }
                                 Err(e) => {
                                     error!(node_id = %node_id, error = %e, "Failed to decode data");
@@ -445,6 +1317,7 @@ impl ReplicationManager {
                     }
                 }
             }
+            Ok(Ok(response)) if response.status() == reqwest::StatusCode::NOT_FOUND => Ok(None),
             Ok(Ok(response)) => {
                 warn!(
                     node_id = %node_id,
@@ -463,39 +1336,440 @@ impl ReplicationManager {
             }
         }
     }
+}
 
-    /// Perform read (can use replica)
-    #[instrument(skip(self))]
-    pub async fn read(&self, key: &[u8]) -> Result<Vec<u8>, String> {
-        let shard = self.cluster.calculate_shard(key);
-        let nodes = self.cluster.get_shard_nodes(shard).await;
+/// Read `key` from `node` unless its circuit breaker is open
+async fn read_node(
+    cluster: &ClusterState,
+    reader: &dyn NodeReader,
+    node: &Node,
+    key: &[u8],
+) -> Result<Option<VersionedValue>, String> {
+    cluster.breakers.check(&node.id)?;
+    let result = reader.read(node, key).await;
+    cluster.record_outcome(&node.id, result.is_ok());
+    result
+}
+
+/// Replication manager
+#[derive(Clone)]
+pub struct ReplicationManager {
+    cluster: Arc<ClusterState>,
+    reader: Arc<dyn NodeReader>,
+}
+
+impl fmt::Debug for ReplicationManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicationManager")
+            .field("cluster", &self.cluster)
+            .finish()
+    }
+}
+
+impl ReplicationManager {
+    pub fn new(cluster: Arc<ClusterState>) -> Self {
+        let reader = HttpNodeReader::new(cluster.http_client().clone());
+        Self::with_reader(cluster, Arc::new(reader))
+    }
+
+    /// Create a replication manager that reads replicas through `reader`
+    pub fn with_reader(cluster: Arc<ClusterState>, reader: Arc<dyn NodeReader>) -> Self {
+        Self { cluster, reader }
+    }
+
+    /// Start replication background task
+    ///
+    /// Returns the handle of the heartbeat task so it can be stopped on shutdown.
+    #[instrument(skip(self))]
+    pub fn start(&self) -> tokio::task::JoinHandle<()> {
+        info!("Starting replication manager");
+
+        // Spawn heartbeat task
+        let cluster = self.cluster.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                cluster.check_dead_nodes().await;
+            }
+        });
+
+        info!("Replication manager started");
+        handle
+    }
+
+    /// Rebalance shards whenever a node joins or leaves
+    ///
+    /// Every node diffs the ring's placement with its previous one; the
+    /// master then sends the moved shards from `storage` to their new
+    /// owners. Returns the handle of the task so it can be stopped on
+    /// shutdown.
+    pub fn start_rebalancing(&self, storage: Arc<Storage>) -> tokio::task::JoinHandle<()> {
+        let manager = self.clone();
+        let mut changes = self.cluster.membership_changes();
+        tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                let plan = manager.cluster.rebalance_shards().await;
+                if plan.is_empty() || !manager.cluster.is_master().await {
+                    continue;
+                }
+                if let Err(e) = manager.apply_migration_plan(&plan, &storage).await {
+                    warn!(error = %e, "Shard migration after membership change failed");
+                }
+            }
+        })
+    }
+
+    /// Whether this node is the master, the only one accepting writes
+    pub async fn is_master(&self) -> bool {
+        self.cluster.is_master().await
+    }
+
+    /// Perform write with replication
+    ///
+    /// The returned token can be passed to later reads (see [`ReadSession`])
+    /// to make them observe this write.
+    #[instrument(skip(self, value))]
+    pub async fn write(&self, key: &[u8], value: &[u8]) -> Result<WriteToken, String> {
+        // Check if we're master
+        if !self.cluster.is_master().await {
+            return Err("Not master node".to_string());
+        }
+
+        // Replicate to other nodes
+        self.cluster.replicate(key, value).await
+    }
+
+    /// Copy locally held documents of moved shards to their new owners
+    ///
+    /// Only the master holds every shard, so only it can act on a plan.
+    /// Returns the number of documents sent.
+    #[instrument(skip(self, plan, storage))]
+    pub async fn apply_migration_plan(
+        &self,
+        plan: &MigrationPlan,
+        storage: &Storage,
+    ) -> Result<usize, String> {
+        if !self.cluster.is_master().await {
+            return Err("Not master node".to_string());
+        }
+        if plan.is_empty() {
+            return Ok(0);
+        }
+
+        let nodes: HashMap<String, Node> = self
+            .cluster
+            .get_nodes()
+            .await
+            .into_iter()
+            .map(|n| (n.id.clone(), n))
+            .collect();
+
+        let mut sent = 0;
+        let mut failed = 0;
+        let storage_err = |e: crate::error::Error| format!("Storage error: {}", e);
+        for db in storage.list_databases().await.map_err(storage_err)? {
+            for table in storage.list_tables_in_db(&db).await.map_err(storage_err)? {
+                let mut after: Option<Vec<u8>> = None;
+                loop {
+                    let page = storage
+                        .scan_table_page(&db, &table, after.as_deref(), MIGRATION_PAGE_SIZE)
+                        .await
+                        .map_err(storage_err)?;
+                    let done = page.len() < MIGRATION_PAGE_SIZE;
+
+                    for (key, doc) in page {
+                        let targets: Vec<&Node> = plan
+                            .targets_of(self.cluster.calculate_shard(&key))
+                            .filter_map(|id| nodes.get(id))
+                            .collect();
+                        if !targets.is_empty() {
+                            let version = Self::document_version(storage, &key)
+                                .await
+                                .map_err(storage_err)?;
+                            let data = serde_json::Value::from(doc).to_string();
+                            for node in targets {
+                                let result = match self.cluster.breakers.check(&node.id) {
+                                    Ok(()) => {
+                                        let result = ClusterState::replicate_to_node(
+                                            &self.cluster.http,
+                                            node.addr,
+                                            &node.id,
+                                            &key,
+                                            data.as_bytes(),
+                                            version,
+                                        )
+                                        .await;
+                                        self.cluster.record_outcome(&node.id, result.is_ok());
+                                        result
+                                    }
+                                    Err(e) => Err(e),
+                                };
+                                match result {
+                                    Ok(()) => sent += 1,
+                                    Err(_) => failed += 1,
+                                }
+                            }
+                        }
+                        after = Some(key);
+                    }
+
+                    if done {
+                        break;
+                    }
+                }
+            }
+        }
+
+        info!(sent = sent, failed = failed, "Shard migration completed");
+        if failed > 0 {
+            return Err(format!(
+                "Shard migration incomplete: {} of {} documents failed",
+                failed,
+                sent + failed
+            ));
+        }
+        Ok(sent)
+    }
+
+    /// Replication version of a locally held document
+    ///
+    /// Documents written before versions were recorded get a new version,
+    /// stored so that every later migration sends the same one.
+    async fn document_version(storage: &Storage, key: &[u8]) -> crate::error::Result<u64> {
+        let version_key = version_key(key);
+        if let Some(version) = storage.get(&version_key).await?.and_then(|d| d.as_integer()) {
+            return Ok(version as u64);
+        }
+        let version = next_version();
+        storage
+            .set(&version_key, Datum::Integer(version as i64))
+            .await?;
+        Ok(version)
+    }
+
+    /// Perform a quorum read
+    ///
+    /// Queries every node of the key's shard and waits for `read_quorum`
+    /// answers; among those, the value with the highest version wins.
+    #[instrument(skip(self))]
+    pub async fn read(&self, key: &[u8]) -> Result<Vec<u8>, String> {
+        self.fetch(key)
+            .await?
+            .ok_or_else(|| "Key not found".to_string())
+    }
+
+    /// [`read`](Self::read), with `Ok(None)` if no node holds `key`
+    async fn fetch(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let read_quorum = self.cluster.config.read_quorum.max(1);
+        let shard = self.cluster.calculate_shard(key);
+        let mut nodes = self.cluster.get_shard_nodes(shard).await;
 
-        if nodes.is_empty() {
-            return Err("No nodes available for shard".to_string());
+        if nodes.len() < read_quorum {
+            error!(
+                available = nodes.len(),
+                required = read_quorum,
+                "Not enough nodes for read quorum"
+            );
+            return Err("Insufficient replicas for read quorum".to_string());
         }
 
         // If read replicas enabled, prefer replica nodes
-        let target_node = if self.cluster.config.enable_read_replicas {
-            nodes
-                .iter()
-                .find(|n| n.role == NodeRole::Replica)
-                .or_else(|| nodes.first())
-        } else {
-            nodes.first()
+        if self.cluster.config.enable_read_replicas {
+            nodes.sort_by_key(|n| n.role != NodeRole::Replica);
+        }
+
+        let (responses, newest) = self.read_all(nodes, key).await;
+
+        info!(
+            shard = shard,
+            responses = responses,
+            required = read_quorum,
+            "Quorum read completed"
+        );
+
+        if responses < read_quorum {
+            error!(
+                responses = responses,
+                required = read_quorum,
+                "Failed to achieve read quorum"
+            );
+            return Err(format!(
+                "Read quorum not achieved: {}/{}",
+                responses, read_quorum
+            ));
+        }
+
+        Ok(newest.map(|value| value.data))
+    }
+
+    /// Perform a read routed by `options`
+    ///
+    /// Without a mode this is a plain [`read`](Self::read). `single` and
+    /// `majority` only ask nodes known to have applied `options.after`, so
+    /// they never miss the client's own last write: `single` settles for the
+    /// first of them that answers, `majority` needs a majority of the
+    /// shard's nodes. `outdated` takes the first answer of any node.
+    #[instrument(skip(self))]
+    pub async fn read_with(&self, key: &[u8], options: ReadOptions) -> Result<Vec<u8>, String> {
+        self.fetch_with(key, options)
+            .await?
+            .ok_or_else(|| "Key not found".to_string())
+    }
+
+    /// [`read_with`](Self::read_with), with `Ok(None)` if the nodes asked
+    /// do not hold `key`
+    pub async fn fetch_with(
+        &self,
+        key: &[u8],
+        options: ReadOptions,
+    ) -> Result<Option<Vec<u8>>, String> {
+        let Some(mode) = options.mode else {
+            return self.fetch(key).await;
+        };
+
+        let shard = self.cluster.calculate_shard(key);
+        let nodes = self.cluster.get_shard_nodes(shard).await;
+        let required = match mode {
+            ReadMode::Majority => nodes.len() / 2 + 1,
+            ReadMode::Single | ReadMode::Outdated => 1,
         };
 
-        if let Some(node) = target_node {
-            info!(
-                shard = shard,
-                node_id = %node.id,
-                node_addr = %node.addr,
-                "Reading from node"
+        let mut candidates = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let caught_up = match (mode, options.after) {
+                (ReadMode::Outdated, _) | (_, None) => true,
+                (_, Some(token)) => self.cluster.has_applied(&node.id, token).await,
+            };
+            if caught_up {
+                candidates.push(node);
+            }
+        }
+
+        if candidates.len() < required {
+            error!(
+                mode = %mode,
+                available = candidates.len(),
+                required = required,
+                "Not enough up-to-date nodes for read"
             );
-            
-            // Read from remote node via HTTP
-            Self::read_from_node(node.addr, &node.id, key).await
-        } else {
-            Err("No target node found".to_string())
+            return Err(format!(
+                "Insufficient up-to-date replicas for {} read: {}/{}",
+                mode,
+                candidates.len(),
+                required
+            ));
+        }
+
+        if mode == ReadMode::Majority {
+            let (responses, newest) = self.read_all(candidates, key).await;
+            if responses < required {
+                return Err(format!(
+                    "Majority read not achieved: {}/{}",
+                    responses, required
+                ));
+            }
+            return Ok(newest.map(|value| value.data));
+        }
+
+        if mode == ReadMode::Outdated && self.cluster.config.enable_read_replicas {
+            candidates.sort_by_key(|n| n.role != NodeRole::Replica);
+        }
+
+        for node in candidates {
+            match read_node(&self.cluster, self.reader.as_ref(), &node, key).await {
+                Ok(value) => return Ok(value.map(|value| value.data)),
+                Err(e) => {
+                    warn!(node_id = %node.id, error = %e, "Replica read failed");
+                }
+            }
+        }
+        Err(format!("No replica answered the {} read", mode))
+    }
+
+    /// Read `key` from all `nodes` in parallel
+    ///
+    /// Returns how many nodes answered and the newest value among the
+    /// answers. Nodes that answered with an older value or none at all are
+    /// sent the newest one in the background (see [`repair`](Self::repair)).
+    async fn read_all(&self, nodes: Vec<Node>, key: &[u8]) -> (usize, Option<VersionedValue>) {
+        let mut read_tasks = Vec::new();
+        for node in nodes {
+            let cluster = self.cluster.clone();
+            let reader = self.reader.clone();
+            let key = key.to_vec();
+            read_tasks.push(tokio::spawn(request_id::inherit(async move {
+                let result = read_node(&cluster, reader.as_ref(), &node, &key).await;
+                (node, result)
+            })));
+        }
+
+        let mut answers = Vec::new();
+        let mut newest: Option<VersionedValue> = None;
+        for task in read_tasks {
+            match task.await {
+                Ok((node, Ok(value))) => {
+                    if let Some(value) = &value {
+                        if newest.as_ref().is_none_or(|n| value.version > n.version) {
+                            newest = Some(value.clone());
+                        }
+                    }
+                    answers.push((node, value.map(|v| v.version)));
+                }
+                Ok((node, Err(e))) => {
+                    warn!(node_id = %node.id, error = %e, "Replica read failed");
+                }
+                Err(e) => {
+                    warn!(error = %e, "Replica read task failed");
+                }
+            }
+        }
+
+        let responses = answers.len();
+        if let Some(newest) = &newest {
+            let stale: Vec<Node> = answers
+                .into_iter()
+                .filter(|(_, version)| version.is_none_or(|v| v < newest.version))
+                .map(|(node, _)| node)
+                .collect();
+            if !stale.is_empty() {
+                self.repair(stale, key, newest.clone());
+            }
+        }
+        (responses, newest)
+    }
+
+    /// Send `value` to the `stale` nodes in the background (read repair)
+    ///
+    /// Best effort: failures are only logged, and the next read of the key
+    /// tries again.
+    fn repair(&self, stale: Vec<Node>, key: &[u8], value: VersionedValue) {
+        for node in stale {
+            let cluster = self.cluster.clone();
+            let key = key.to_vec();
+            let value = value.clone();
+            tokio::spawn(request_id::inherit(async move {
+                if let Err(e) = cluster.breakers.check(&node.id) {
+                    warn!(node_id = %node.id, error = %e, "Skipping read repair");
+                    return;
+                }
+                let result = ClusterState::replicate_to_node(
+                    &cluster.http,
+                    node.addr,
+                    &node.id,
+                    &key,
+                    &value.data,
+                    value.version,
+                )
+                .await;
+                cluster.record_outcome(&node.id, result.is_ok());
+                match result {
+                    Ok(()) => {
+                        info!(node_id = %node.id, version = value.version, "Read repair applied");
+                        cluster.record_applied(&node.id, value.version).await;
+                    }
+                    Err(e) => warn!(node_id = %node.id, error = %e, "Read repair failed"),
+                }
+            }));
         }
     }
 }
@@ -515,6 +1789,19 @@ mod tests {
         assert!(cluster.is_master().await);
     }
 
+    #[test]
+    fn test_config_defaults_match_serde_defaults() {
+        let config: ReplicationConfig = serde_json::from_value(serde_json::json!({
+            "replica_count": 3,
+            "replication_factor": 3,
+            "shard_count": 16,
+            "enable_read_replicas": true,
+            "write_quorum": 2,
+        }))
+        .unwrap();
+        assert_eq!(config.read_quorum, ReplicationConfig::default().read_quorum);
+    }
+
     #[tokio::test]
     async fn test_node_management() {
         let config = ReplicationConfig::default();
@@ -554,6 +1841,299 @@ mod tests {
 
         // Same key should always map to same shard
         assert_eq!(shard1, cluster.calculate_shard(key1));
+
+        // ... on every node and across releases
+        assert_eq!((shard1, shard2), (9, 14));
+    }
+
+    #[tokio::test]
+    async fn test_range_sharding() {
+        let cluster = ClusterState::new("node1".to_string(), ReplicationConfig::default())
+            .with_sharding_strategy(ShardingStrategy::range(["n", "g", "t"]));
+        assert_eq!(cluster.shard_count(), 4);
+
+        assert_eq!(cluster.calculate_shard(b"apple"), 0);
+        assert_eq!(cluster.calculate_shard(b"g"), 1);
+        assert_eq!(cluster.calculate_shard(b"melon"), 1);
+        assert_eq!(cluster.calculate_shard(b"pear"), 2);
+        assert_eq!(cluster.calculate_shard(b"zucchini"), 3);
+
+        // Ordered keys never move back to an earlier shard
+        let keys = [
+            "a", "b", "fig", "grape", "kiwi", "nut", "plum", "tomato", "yam",
+        ];
+        let shards: Vec<u64> = keys
+            .iter()
+            .map(|k| cluster.calculate_shard(k.as_bytes()))
+            .collect();
+        assert!(shards.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(shards.first(), Some(&0));
+        assert_eq!(shards.last(), Some(&3));
+
+        // Range shards are placed on the ring like hashed ones
+        add_nodes(&cluster, &["low", "high"]).await;
+        for key in [&b"melon"[..], b"tomato"] {
+            let nodes = cluster.get_shard_nodes(cluster.calculate_shard(key)).await;
+            assert_eq!(nodes.len(), 2);
+            assert_ne!(nodes[0].id, nodes[1].id);
+        }
+    }
+
+    #[test]
+    fn test_custom_sharding() {
+        let config = ReplicationConfig {
+            shard_count: 16,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config).with_sharding_strategy(
+            ShardingStrategy::custom(|key| key.first().copied().unwrap_or(0) as u64),
+        );
+
+        assert_eq!(cluster.calculate_shard(b"\x03tenant-a:1"), 3);
+        assert_eq!(cluster.calculate_shard(b"\x03tenant-a:2"), 3);
+        assert_eq!(cluster.calculate_shard(b"\x05tenant-b:1"), 5);
+        assert_eq!(cluster.calculate_shard(b""), 0);
+        // Out-of-range results wrap into the configured shards
+        assert_eq!(cluster.calculate_shard(b"\x13x"), 3);
+    }
+
+    async fn add_nodes(cluster: &ClusterState, ids: &[&str]) {
+        for (i, id) in ids.iter().enumerate() {
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr: format!("127.0.0.1:{}", 9100 + i).parse().unwrap(),
+                    role: NodeRole::Replica,
+                    shard_range: None,
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+    }
+
+    /// Owner of every shard, asserting each has exactly one
+    async fn owners(cluster: &ClusterState) -> Vec<String> {
+        let mut owners = Vec::new();
+        for shard in 0..cluster.shard_count() {
+            let nodes = cluster.get_shard_nodes(shard).await;
+            assert_eq!(nodes.len(), 1, "shard {} owners", shard);
+            owners.push(nodes[0].id.clone());
+        }
+        owners
+    }
+
+    /// First node of every shard
+    async fn primaries(cluster: &ClusterState) -> Vec<String> {
+        let mut primaries = Vec::new();
+        for shard in 0..cluster.shard_count() {
+            primaries.push(cluster.get_shard_nodes(shard).await[0].id.clone());
+        }
+        primaries
+    }
+
+    #[tokio::test]
+    async fn test_ring_routing_on_join() {
+        let config = ReplicationConfig {
+            shard_count: 1024,
+            replica_count: 2,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config);
+        add_nodes(&cluster, &["a", "b", "c", "d"]).await;
+
+        // Unassigned shards are routed through the ring, consistently
+        let before = primaries(&cluster).await;
+        assert_eq!(before, primaries(&cluster).await);
+        let replicas = cluster.get_shard_nodes(7).await;
+        assert_eq!(replicas.len(), 2);
+        assert_ne!(replicas[0].id, replicas[1].id);
+
+        // A fifth node takes over about a fifth of the shards
+        add_nodes(&cluster, &["e"]).await;
+        let after = primaries(&cluster).await;
+        let moved: Vec<usize> = (0..before.len())
+            .filter(|&i| before[i] != after[i])
+            .collect();
+        assert!((100..300).contains(&moved.len()), "moved {}", moved.len());
+        assert!(moved.iter().all(|&i| after[i] == "e"));
+
+        // ... and hands them back when it leaves
+        cluster.remove_node("e").await;
+        assert_eq!(before, primaries(&cluster).await);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_on_join() {
+        let config = ReplicationConfig {
+            shard_count: 300,
+            replica_count: 1,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config);
+        add_nodes(&cluster, &["a", "b"]).await;
+
+        let plan = cluster.rebalance_shards().await;
+        assert_eq!(plan.moves.len(), 300);
+        let before = owners(&cluster).await;
+
+        add_nodes(&cluster, &["c"]).await;
+        let plan = cluster.rebalance_shards().await;
+
+        // About a third of the shards move, all of them to the new node
+        assert!((50..150).contains(&plan.moves.len()), "moved {}", plan.moves.len());
+        assert!(plan.moves.iter().all(|m| m.to == "c" && m.from.is_some()));
+        for m in &plan.moves {
+            assert_eq!(before[m.shard as usize], *m.from.as_ref().unwrap());
+        }
+
+        // Shards are where the ring puts them, and only moved ones changed
+        let after = owners(&cluster).await;
+        let ring = cluster.ring.read().await;
+        for shard in 0..300u64 {
+            let placed = ring.node_for(&shard.to_be_bytes()).unwrap();
+            assert_eq!(after[shard as usize], placed, "shard {}", shard);
+            let moved = plan.moves.iter().any(|m| m.shard == shard);
+            assert_eq!(moved, before[shard as usize] != after[shard as usize]);
+        }
+        drop(ring);
+
+        // Nothing to do when membership is unchanged
+        assert!(cluster.rebalance_shards().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_on_leave() {
+        let config = ReplicationConfig {
+            shard_count: 16,
+            replica_count: 1,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config);
+        add_nodes(&cluster, &["a", "b", "c"]).await;
+        cluster.rebalance_shards().await;
+
+        let before = owners(&cluster).await;
+        let orphaned: Vec<u64> = (0..16).filter(|&s| before[s as usize] == "b").collect();
+
+        cluster.remove_node("b").await;
+        let plan = cluster.rebalance_shards().await;
+
+        // Every shard of the removed node is reassigned, the rest stay
+        let after = owners(&cluster).await;
+        assert!(after.iter().all(|o| o == "a" || o == "c"));
+        for shard in &orphaned {
+            let m = plan.moves.iter().find(|m| m.shard == *shard).unwrap();
+            assert_eq!(m.from, None);
+        }
+        assert_eq!(plan.moves.len(), orphaned.len());
+        for shard in (0..16).filter(|s| !orphaned.contains(s)) {
+            assert_eq!(before[shard as usize], after[shard as usize]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_moves_every_replica() {
+        let config = ReplicationConfig {
+            shard_count: 12,
+            replica_count: 2,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config);
+        add_nodes(&cluster, &["a", "b"]).await;
+
+        // Both nodes own every shard, so each needs all of them
+        let plan = cluster.rebalance_shards().await;
+        assert_eq!(plan.moves.len(), 24);
+
+        // A third node becomes primary or replica of some shards and is
+        // sent exactly those
+        add_nodes(&cluster, &["c"]).await;
+        let plan = cluster.rebalance_shards().await;
+        assert!(!plan.is_empty());
+        assert!(plan.moves.iter().all(|m| m.to == "c"));
+        for shard in 0..12 {
+            let owners: Vec<String> =
+                cluster.get_shard_nodes(shard).await.into_iter().map(|n| n.id).collect();
+            assert_eq!(owners.len(), 2);
+            assert_ne!(owners[0], owners[1]);
+            let gained = plan.targets_of(shard).next().is_some();
+            assert_eq!(gained, owners.contains(&"c".to_string()), "shard {}", shard);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_on_membership_change() {
+        let config = ReplicationConfig {
+            shard_count: 8,
+            replica_count: 1,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        let manager = ReplicationManager::new(cluster.clone());
+        let rebalancing = manager.start_rebalancing(Arc::new(Storage::in_memory()));
+
+        let assigned = |expected: &'static [&'static str]| {
+            let cluster = cluster.clone();
+            async move {
+                for _ in 0..100 {
+                    let mut holders: Vec<String> =
+                        cluster.placement.read().await.concat();
+                    holders.sort();
+                    holders.dedup();
+                    if holders == expected {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                panic!("shards never assigned to {:?}", expected);
+            }
+        };
+
+        // Joins and leaves rebalance without being asked to
+        add_nodes(&cluster, &["a", "b"]).await;
+        assigned(&["a", "b"]).await;
+        cluster.remove_node("a").await;
+        assigned(&["b"]).await;
+        assert_eq!(owners(&cluster).await, vec!["b"; 8]);
+
+        rebalancing.abort();
+    }
+
+    #[tokio::test]
+    async fn test_failover_promotes_one_replica() {
+        let stale = chrono::Utc::now() - chrono::Duration::seconds(60);
+        let dead = |id: &str, role: NodeRole| Node {
+            id: id.to_string(),
+            addr: "127.0.0.1:9200".parse().unwrap(),
+            role,
+            shard_range: None,
+            last_heartbeat: stale,
+        };
+
+        // Two surviving replicas that lost their master
+        let nodes = spawn_voters(&["b", "c"]).await;
+        let (b, c) = (&nodes[0], &nodes[1]);
+        for node in &nodes {
+            node.add_node(dead("a", NodeRole::Master)).await;
+        }
+
+        // "b" stands first and wins a new term; "c" voted for it and stays
+        tokio::join!(b.check_dead_nodes(), c.check_dead_nodes());
+        assert!(b.is_master().await);
+        assert_eq!(c.get_role().await, NodeRole::Replica);
+        assert_eq!(b.current_term().await, 1);
+        assert_eq!(c.current_term().await, 1);
+
+        // The new master accepts writes (and fails only on quorum)
+        let manager = ReplicationManager::new(b.clone());
+        let err = manager.write(b"key", b"value").await.unwrap_err();
+        assert_ne!(err, "Not master node");
+
+        // A vote for a master that has since died does not hold "c" back
+        c.add_node(dead("b", NodeRole::Master)).await;
+        c.check_dead_nodes().await;
+        assert!(c.is_master().await);
+        assert_eq!(c.current_term().await, 2);
     }
 
     #[tokio::test]
@@ -572,4 +2152,765 @@ mod tests {
         let result = manager.write(b"key", b"value").await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_migration_sends_document_versions() {
+        let config = ReplicationConfig {
+            shard_count: 4,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        cluster.init_as_master().await;
+
+        // The new owner records the version of every document it is sent
+        let received = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let sink = received.clone();
+        let app = axum::Router::new().route(
+            "/internal/replicate",
+            axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                let sink = sink.clone();
+                async move {
+                    let key = BASE64.decode(body["key"].as_str().unwrap()).unwrap();
+                    let version = body["version"].as_u64().unwrap();
+                    sink.lock().unwrap().insert(key, version);
+                    axum::http::StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        cluster
+            .add_node(Node {
+                id: "new".to_string(),
+                addr,
+                role: NodeRole::Replica,
+                shard_range: None,
+                last_heartbeat: chrono::Utc::now(),
+            })
+            .await;
+
+        let storage = Storage::in_memory();
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let versioned = b"doc:test:users:a".to_vec();
+        let unversioned = b"doc:test:users:b".to_vec();
+        for key in [&versioned, &unversioned] {
+            storage
+                .set(key, Datum::from(serde_json::json!({"id": "x"})))
+                .await
+                .unwrap();
+        }
+        let stored = (1i64 << 53) + 1;
+        storage
+            .set(&version_key(&versioned), Datum::Integer(stored))
+            .await
+            .unwrap();
+
+        let plan = MigrationPlan {
+            moves: (0..4)
+                .map(|shard| ShardMove {
+                    shard,
+                    from: Some("node1".to_string()),
+                    to: "new".to_string(),
+                })
+                .collect(),
+        };
+        let manager = ReplicationManager::new(cluster.clone());
+        assert_eq!(manager.apply_migration_plan(&plan, &storage).await, Ok(2));
+
+        // Stored versions are sent as they are; a document without one gets
+        // a version that later migrations send again
+        let sent = received.lock().unwrap().clone();
+        assert_eq!(sent[&versioned], stored as u64);
+        assert_ne!(sent[&unversioned], 0);
+        let assigned = storage.get(&version_key(&unversioned)).await.unwrap();
+        assert_eq!(assigned, Some(Datum::Integer(sent[&unversioned] as i64)));
+        manager.apply_migration_plan(&plan, &storage).await.unwrap();
+        assert_eq!(received.lock().unwrap().clone(), sent);
+    }
+
+    /// Node answering `/internal/replicate` with an error for its first
+    /// `failures` calls; returns its address and call counter
+    async fn spawn_replica(failures: usize) -> (SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use axum::http::StatusCode;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = axum::Router::new().route(
+            "/internal/replicate",
+            axum::routing::post(move || {
+                let counter = counter.clone();
+                async move {
+                    if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::OK
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (addr, calls)
+    }
+
+    #[tokio::test]
+    async fn test_replication_retries_failed_nodes() {
+        use std::sync::atomic::Ordering;
+
+        let config = ReplicationConfig {
+            shard_count: 4,
+            write_quorum: 2,
+            write_retries: 2,
+            write_retry_backoff_ms: 10,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        let mut calls = HashMap::new();
+        for (id, failures) in [("a", 0), ("b", 1), ("c", usize::MAX)] {
+            let (addr, counter) = spawn_replica(failures).await;
+            calls.insert(id, counter);
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr,
+                    role: NodeRole::Replica,
+                    shard_range: Some(ShardRange { start: 0, end: 4 }),
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+
+        // "b" fails once and succeeds on its retry, making the quorum; "a"
+        // succeeded at once and is not called again
+        let token = cluster.replicate(b"key", b"value").await.unwrap();
+        assert!(cluster.has_applied("a", token).await);
+        assert!(cluster.has_applied("b", token).await);
+        assert_eq!(calls["a"].load(Ordering::SeqCst), 1);
+        assert_eq!(calls["b"].load(Ordering::SeqCst), 2);
+
+        // "c" never succeeds and counts as failed once its retries are
+        // spent, after the write has returned
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while calls["c"].load(Ordering::SeqCst) < 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!cluster.has_applied("c", token).await);
+        assert_eq!(calls["c"].load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_replication_returns_at_quorum() {
+        // "slow" holds every call for longer than the write may take
+        let app = axum::Router::new().route(
+            "/internal/replicate",
+            axum::routing::post(|| async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                axum::http::StatusCode::OK
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let slow = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = ReplicationConfig {
+            shard_count: 4,
+            write_quorum: 2,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        let (fast, _) = spawn_replica(0).await;
+        for (id, addr) in [("a", fast), ("b", fast), ("slow", slow)] {
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr,
+                    role: NodeRole::Replica,
+                    shard_range: Some(ShardRange { start: 0, end: 4 }),
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+
+        let started = tokio::time::Instant::now();
+        let token = cluster.replicate(b"key", b"value").await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(!cluster.has_applied("slow", token).await);
+
+        // The straggler still finishes and is recorded
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert!(cluster.has_applied("slow", token).await);
+    }
+
+    #[tokio::test]
+    async fn test_replication_concurrency_is_bounded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Replica holding every call for a while, recording the most calls
+        // it saw at once
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/internal/replicate",
+            axum::routing::post({
+                let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                move || {
+                    let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                    async move {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        axum::http::StatusCode::OK
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = ReplicationConfig {
+            shard_count: 4,
+            write_quorum: 3,
+            max_inflight_replications: 4,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        for id in ["a", "b", "c"] {
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr,
+                    role: NodeRole::Replica,
+                    shard_range: Some(ShardRange { start: 0, end: 4 }),
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+
+        let writes: Vec<_> = (0..20)
+            .map(|i| {
+                let cluster = cluster.clone();
+                tokio::spawn(async move {
+                    cluster
+                        .replicate(format!("key{}", i).as_bytes(), b"value")
+                        .await
+                })
+            })
+            .collect();
+        for write in writes {
+            assert!(write.await.unwrap().is_ok());
+        }
+
+        // 60 calls went through, never more than 4 at a time
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(peak <= 4, "{} replication calls in flight", peak);
+        assert!(peak > 1);
+        assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replication_retries_stop_at_deadline() {
+        use std::sync::atomic::Ordering;
+
+        let config = ReplicationConfig {
+            shard_count: 4,
+            write_quorum: 1,
+            write_timeout_ms: 150,
+            write_retries: 10,
+            write_retry_backoff_ms: 50,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        let (addr, calls) = spawn_replica(usize::MAX).await;
+        cluster
+            .add_node(Node {
+                id: "a".to_string(),
+                addr,
+                role: NodeRole::Replica,
+                shard_range: Some(ShardRange { start: 0, end: 4 }),
+                last_heartbeat: chrono::Utc::now(),
+            })
+            .await;
+
+        // Backoffs of 50ms and 100ms: the second would pass the deadline
+        let err = cluster.replicate(b"key", b"value").await.unwrap_err();
+        assert_eq!(err, "Write quorum not achieved: 0/1");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Nodes that know each other and answer `/internal/vote`
+    async fn spawn_voters(ids: &[&str]) -> Vec<Arc<ClusterState>> {
+        let mut listeners = Vec::new();
+        for _ in ids {
+            listeners.push(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+        }
+        let addrs: Vec<SocketAddr> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+
+        let mut clusters = Vec::new();
+        for (id, listener) in ids.iter().zip(listeners) {
+            let cluster = Arc::new(ClusterState::new(
+                id.to_string(),
+                ReplicationConfig::default(),
+            ));
+            for (peer, addr) in ids.iter().zip(&addrs) {
+                if peer != id {
+                    cluster
+                        .add_node(Node {
+                            id: peer.to_string(),
+                            addr: *addr,
+                            role: NodeRole::Replica,
+                            shard_range: None,
+                            last_heartbeat: chrono::Utc::now(),
+                        })
+                        .await;
+                }
+            }
+            let voter = cluster.clone();
+            let app = axum::Router::new().route(
+                "/internal/vote",
+                axum::routing::post(move |axum::Json(request): axum::Json<VoteRequest>| {
+                    let voter = voter.clone();
+                    async move { axum::Json(voter.vote(&request).await) }
+                }),
+            );
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            clusters.push(cluster);
+        }
+        clusters
+    }
+
+    #[tokio::test]
+    async fn test_election_round() {
+        let nodes = spawn_voters(&["a", "b", "c"]).await;
+        let (a, b, c) = (&nodes[0], &nodes[1], &nodes[2]);
+
+        // Two candidates in the same term: each votes for itself, so "c"
+        // decides and exactly one wins
+        let (a_won, b_won) = tokio::join!(a.start_election(), b.start_election());
+        assert!(a_won != b_won);
+        let (winner, loser) = if a_won { (a, b) } else { (b, a) };
+        assert_eq!(winner.get_role().await, NodeRole::Master);
+        assert_eq!(loser.get_role().await, NodeRole::Replica);
+        assert_eq!(c.get_role().await, NodeRole::Replica);
+        for node in &nodes {
+            assert_eq!(node.current_term().await, 1);
+        }
+
+        // A vote already cast in a term is not given to another candidate
+        let request = VoteRequest {
+            term: 1,
+            candidate_id: loser.node_id().to_string(),
+        };
+        assert!(!c.vote(&request).await.granted);
+
+        // The loser runs again in a newer term; the master steps down
+        assert!(loser.start_election().await);
+        assert_eq!(loser.get_role().await, NodeRole::Master);
+        assert_eq!(winner.get_role().await, NodeRole::Replica);
+        assert_eq!(winner.current_term().await, 2);
+
+        // A candidate behind the cluster's term learns it and steps down
+        assert!(c.observe_term(5).await);
+        assert!(!c.observe_term(4).await);
+        assert!(!winner.start_election().await);
+        assert_eq!(winner.get_role().await, NodeRole::Replica);
+        assert_eq!(winner.current_term().await, 5);
+    }
+
+    /// Node serving `/internal/read` and `/internal/replicate` from a single
+    /// versioned value
+    async fn spawn_value_node(
+        value: Option<VersionedValue>,
+    ) -> (SocketAddr, Arc<std::sync::Mutex<Option<VersionedValue>>>) {
+        use axum::{http::StatusCode, routing::post, Json};
+
+        let stored = Arc::new(std::sync::Mutex::new(value));
+        let (read, replicate) = (stored.clone(), stored.clone());
+        let app = axum::Router::new()
+            .route(
+                "/internal/read",
+                post(move || {
+                    let value = read.lock().unwrap().clone();
+                    async move {
+                        let value = value.ok_or(StatusCode::NOT_FOUND)?;
+                        Ok::<_, StatusCode>(Json(serde_json::json!({
+                            "data": BASE64.encode(&value.data),
+                            "version": value.version,
+                        })))
+                    }
+                }),
+            )
+            .route(
+                "/internal/replicate",
+                post(move |Json(body): Json<serde_json::Value>| async move {
+                    let data = BASE64.decode(body["data"].as_str().unwrap()).unwrap();
+                    let version = body["version"].as_u64().unwrap();
+                    *replicate.lock().unwrap() = Some(VersionedValue { data, version });
+                    StatusCode::OK
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (addr, stored)
+    }
+
+    #[tokio::test]
+    async fn test_quorum_read_repairs_stale_replica() {
+        let config = ReplicationConfig {
+            shard_count: 4,
+            read_quorum: 3,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        let mut stored = HashMap::new();
+        for (id, value) in [
+            ("a", versioned(b"new", 5)),
+            ("b", versioned(b"new", 5)),
+            ("c", versioned(b"old", 1)),
+        ] {
+            let (addr, value) = spawn_value_node(value).await;
+            stored.insert(id, value);
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr,
+                    role: NodeRole::Replica,
+                    shard_range: Some(ShardRange { start: 0, end: 4 }),
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+        let manager = ReplicationManager::new(cluster.clone());
+
+        // The read answers with the newest value right away...
+        assert_eq!(manager.read(b"key").await.unwrap(), b"new".to_vec());
+
+        // ...and "c" catches up in the background
+        for _ in 0..100 {
+            if cluster.has_applied("c", WriteToken(5)).await {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*stored["c"].lock().unwrap(), versioned(b"new", 5));
+        assert!(cluster.has_applied("c", WriteToken(5)).await);
+        assert!(!cluster.has_applied("a", WriteToken(5)).await);
+    }
+
+    /// Serves canned per-node answers; nodes without an entry are unreachable
+    struct MockNodeReader {
+        values: HashMap<String, Option<VersionedValue>>,
+    }
+
+    #[async_trait]
+    impl NodeReader for MockNodeReader {
+        async fn read(&self, node: &Node, _key: &[u8]) -> Result<Option<VersionedValue>, String> {
+            self.values
+                .get(&node.id)
+                .cloned()
+                .ok_or_else(|| format!("{} unreachable", node.id))
+        }
+    }
+
+    async fn quorum_cluster(read_quorum: usize) -> Arc<ClusterState> {
+        let config = ReplicationConfig {
+            shard_count: 4,
+            read_quorum,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        for (i, id) in ["a", "b", "c"].iter().enumerate() {
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr: format!("127.0.0.1:{}", 9000 + i).parse().unwrap(),
+                    role: NodeRole::Replica,
+                    shard_range: Some(ShardRange { start: 0, end: 4 }),
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+        cluster
+    }
+
+    fn versioned(data: &[u8], version: u64) -> Option<VersionedValue> {
+        Some(VersionedValue {
+            data: data.to_vec(),
+            version,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_quorum_read_returns_newest_value() {
+        let cluster = quorum_cluster(2).await;
+        let reader = MockNodeReader {
+            values: [
+                ("a".to_string(), versioned(b"old", 1)),
+                ("b".to_string(), versioned(b"new", 3)),
+                ("c".to_string(), None),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let manager = ReplicationManager::with_reader(cluster, Arc::new(reader));
+
+        assert_eq!(manager.read(b"key").await.unwrap(), b"new".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_quorum_read_fails_without_enough_replicas() {
+        // Two of three nodes unreachable
+        let cluster = quorum_cluster(2).await;
+        let reader = MockNodeReader {
+            values: [("a".to_string(), versioned(b"value", 1))]
+                .into_iter()
+                .collect(),
+        };
+        let manager = ReplicationManager::with_reader(cluster, Arc::new(reader));
+        let err = manager.read(b"key").await.unwrap_err();
+        assert!(err.contains("Read quorum not achieved: 1/2"), "{}", err);
+
+        // Fewer shard nodes than the quorum
+        let cluster = quorum_cluster(4).await;
+        let reader = MockNodeReader {
+            values: HashMap::new(),
+        };
+        let manager = ReplicationManager::with_reader(cluster, Arc::new(reader));
+        assert_eq!(
+            manager.read(b"key").await.unwrap_err(),
+            "Insufficient replicas for read quorum"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_majority_read_sees_own_write() {
+        // "c" lags behind: it never acknowledged the client's write
+        let cluster = quorum_cluster(1).await;
+        cluster.record_applied("a", 5).await;
+        cluster.record_applied("b", 5).await;
+        cluster.record_applied("c", 1).await;
+        let lagging = |a: bool| MockNodeReader {
+            values: [
+                ("a".to_string(), versioned(b"new", 5)),
+                ("b".to_string(), versioned(b"new", 5)),
+                ("c".to_string(), versioned(b"old", 1)),
+            ]
+            .into_iter()
+            .filter(|(id, _)| a || id != "a")
+            .collect(),
+        };
+
+        let mut session = ReadSession::new(Some(ReadMode::Majority));
+        session.record_write(WriteToken(5));
+        session.record_write(WriteToken(3));
+        assert_eq!(session.last_write(), Some(WriteToken(5)));
+
+        let manager = ReplicationManager::with_reader(cluster.clone(), Arc::new(lagging(true)));
+        for _ in 0..20 {
+            let value = manager.read_with(b"key", session.read_options(None)).await;
+            assert_eq!(value.unwrap(), b"new".to_vec());
+        }
+        let single = session.read_options(Some(ReadMode::Single));
+        assert_eq!(
+            manager.read_with(b"key", single).await.unwrap(),
+            b"new".to_vec()
+        );
+
+        // With "a" down only one up-to-date node is left: majority fails
+        // instead of counting the lagging replica
+        let manager = ReplicationManager::with_reader(cluster.clone(), Arc::new(lagging(false)));
+        let err = manager
+            .read_with(b"key", session.read_options(None))
+            .await
+            .unwrap_err();
+        assert!(err.contains("Majority read not achieved: 1/2"), "{}", err);
+        assert_eq!(
+            manager.read_with(b"key", single).await.unwrap(),
+            b"new".to_vec()
+        );
+
+        // Outdated reads may return the stale value
+        let outdated = session.read_options(Some(ReadMode::Outdated));
+        assert!(manager.read_with(b"key", outdated).await.is_ok());
+
+        // Once "c" catches up it serves up-to-date reads again
+        cluster.record_applied("c", 5).await;
+        assert!(cluster.has_applied("c", WriteToken(5)).await);
+        assert!(!cluster.has_applied("d", WriteToken(5)).await);
+        let reader = MockNodeReader {
+            values: [
+                ("b".to_string(), versioned(b"new", 5)),
+                ("c".to_string(), versioned(b"new", 5)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let manager = ReplicationManager::with_reader(cluster, Arc::new(reader));
+        let value = manager.read_with(b"key", session.read_options(None)).await;
+        assert_eq!(value.unwrap(), b"new".to_vec());
+    }
+
+    /// Fails slowly until marked healthy, counting every call it gets
+    struct FlakyReader {
+        calls: std::sync::atomic::AtomicUsize,
+        healthy: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl NodeReader for FlakyReader {
+        async fn read(&self, _node: &Node, _key: &[u8]) -> Result<Option<VersionedValue>, String> {
+            use std::sync::atomic::Ordering;
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.healthy.load(Ordering::SeqCst) {
+                return Ok(versioned(b"value", 1));
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            Err("timeout".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_short_circuits_failing_node() {
+        use std::sync::atomic::Ordering;
+        use std::time::{Duration, Instant};
+
+        let config = ReplicationConfig {
+            shard_count: 4,
+            read_quorum: 1,
+            breaker_failure_threshold: 2,
+            breaker_cooldown_ms: 200,
+            ..Default::default()
+        };
+        let cluster = Arc::new(ClusterState::new("node1".to_string(), config));
+        cluster
+            .add_node(Node {
+                id: "a".to_string(),
+                addr: "127.0.0.1:9000".parse().unwrap(),
+                role: NodeRole::Replica,
+                shard_range: Some(ShardRange { start: 0, end: 4 }),
+                last_heartbeat: chrono::Utc::now(),
+            })
+            .await;
+        let reader = Arc::new(FlakyReader {
+            calls: Default::default(),
+            healthy: Default::default(),
+        });
+        let manager = ReplicationManager::with_reader(cluster.clone(), reader.clone());
+
+        // Two consecutive failures open the breaker
+        for _ in 0..2 {
+            assert!(manager.read(b"key").await.is_err());
+        }
+        assert!(cluster.is_degraded("a"));
+        assert_eq!(cluster.degraded_nodes(), vec!["a".to_string()]);
+
+        // While open, reads fail without waiting on the node
+        let started = Instant::now();
+        let err = manager.read(b"key").await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert!(err.contains("Read quorum not achieved: 0/1"), "{}", err);
+        assert_eq!(reader.calls.load(Ordering::SeqCst), 2);
+
+        // After the cooldown one trial goes through and closes the breaker
+        reader.healthy.store(true, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(manager.read(b"key").await.unwrap(), b"value".to_vec());
+        assert_eq!(reader.calls.load(Ordering::SeqCst), 3);
+        assert!(!cluster.is_degraded("a"));
+
+        // Leaving the cluster forgets the breaker
+        reader.healthy.store(false, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert!(manager.read(b"key").await.is_err());
+        }
+        cluster.remove_node("a").await;
+        assert!(cluster.degraded_nodes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_table_readiness() {
+        let config = ReplicationConfig {
+            shard_count: 4,
+            replica_count: 2,
+            write_quorum: 2,
+            read_quorum: 1,
+            breaker_failure_threshold: 1,
+            ..Default::default()
+        };
+        let cluster = ClusterState::new("node1".to_string(), config);
+        assert_eq!(
+            cluster.table_readiness("app", "users").await,
+            TableReadiness::STANDALONE
+        );
+
+        // One owner per shard serves reads but not quorum writes
+        add_nodes(&cluster, &["a"]).await;
+        let readiness = cluster.table_readiness("app", "users").await;
+        assert!(readiness.ready_for_reads);
+        assert!(!readiness.ready_for_writes);
+        assert!(!readiness.all_replicas_ready);
+
+        add_nodes(&cluster, &["a", "b"]).await;
+        assert_eq!(
+            cluster.table_readiness("app", "users").await,
+            TableReadiness::STANDALONE
+        );
+
+        // Replicas that have not acknowledged the newest write hold up
+        // all_replicas_ready only
+        cluster.shard_versions.write().await.insert(2, 7);
+        cluster.record_applied("a", 7).await;
+        let readiness = cluster.table_readiness("app", "users").await;
+        assert!(readiness.ready_for_writes);
+        assert!(!readiness.all_replicas_ready);
+        cluster.record_applied("b", 7).await;
+        assert!(
+            cluster
+                .table_readiness("app", "users")
+                .await
+                .all_replicas_ready
+        );
+
+        // A degraded owner no longer counts towards the write quorum
+        cluster.record_outcome("b", false);
+        let readiness = cluster.table_readiness("app", "users").await;
+        assert!(readiness.ready_for_reads);
+        assert!(!readiness.ready_for_writes);
+
+        // Under range sharding only the shards of the table's keys count
+        let ranged = ClusterState::new("node1".to_string(), ReplicationConfig::default())
+            .with_sharding_strategy(ShardingStrategy::range(["doc:app:mid:k"]));
+        assert_eq!(ranged.table_shards("app", "users"), vec![1]);
+        assert_eq!(ranged.table_shards("app", "logs"), vec![0]);
+        assert_eq!(ranged.table_shards("app", "mid"), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_read_mode_parsing() {
+        for mode in [ReadMode::Single, ReadMode::Majority, ReadMode::Outdated] {
+            assert_eq!(mode.to_string().parse::<ReadMode>(), Ok(mode));
+        }
+        assert!("eventual".parse::<ReadMode>().is_err());
+
+        // A query's mode overrides the connection's; neither means a
+        // plain quorum read
+        let session = ReadSession::new(Some(ReadMode::Single));
+        assert_eq!(
+            session.read_options(Some(ReadMode::Outdated)).mode,
+            Some(ReadMode::Outdated)
+        );
+        assert_eq!(
+            ReadSession::default().read_options(None),
+            ReadOptions::default()
+        );
+    }
 }
@@ -0,0 +1,213 @@
+//! Consistent-hash ring for routing shards to nodes
+//!
+//! Every node is placed on a 64-bit ring at `virtual_nodes` pseudo-random
+//! points, and a key belongs to the first point at or after its hash. When a
+//! node joins it only takes over the keys just before its own points, and
+//! when it leaves only those keys move on to the next node: about `1/n` of
+//! the keys either way, instead of the near-total reshuffle of `hash % n`.
+//! More virtual nodes spread the keys more evenly at the cost of a larger
+//! ring.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Virtual nodes per physical node unless configured otherwise
+pub const DEFAULT_VIRTUAL_NODES: usize = 128;
+
+/// Consistent-hash ring of node ids
+#[derive(Debug, Clone)]
+pub struct HashRing {
+    virtual_nodes: usize,
+    points: BTreeMap<u64, String>,
+    nodes: BTreeSet<String>,
+}
+
+impl HashRing {
+    /// Empty ring placing each node at `virtual_nodes` points (at least one)
+    pub fn new(virtual_nodes: usize) -> Self {
+        Self {
+            virtual_nodes: virtual_nodes.max(1),
+            points: BTreeMap::new(),
+            nodes: BTreeSet::new(),
+        }
+    }
+
+    /// Virtual nodes per physical node
+    pub fn virtual_nodes(&self) -> usize {
+        self.virtual_nodes
+    }
+
+    /// Add a node; adding one that is already on the ring does nothing
+    pub fn add_node(&mut self, node_id: &str) {
+        if !self.nodes.insert(node_id.to_string()) {
+            return;
+        }
+        for replica in 0..self.virtual_nodes {
+            self.points
+                .insert(point_hash(node_id, replica), node_id.to_string());
+        }
+    }
+
+    /// Remove a node and all of its points
+    pub fn remove_node(&mut self, node_id: &str) {
+        if self.nodes.remove(node_id) {
+            self.points.retain(|_, id| id != node_id);
+        }
+    }
+
+    /// Whether `node_id` is on the ring
+    pub fn contains(&self, node_id: &str) -> bool {
+        self.nodes.contains(node_id)
+    }
+
+    /// Number of physical nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Node owning `key`
+    pub fn node_for(&self, key: &[u8]) -> Option<&str> {
+        self.walk(key).next()
+    }
+
+    /// Up to `count` distinct nodes for `key`: its owner followed by the
+    /// next nodes clockwise, which hold its replicas
+    pub fn nodes_for(&self, key: &[u8], count: usize) -> Vec<&str> {
+        let mut found: Vec<&str> = Vec::with_capacity(count.min(self.nodes.len()));
+        for id in self.walk(key) {
+            if found.len() == count || found.len() == self.nodes.len() {
+                break;
+            }
+            if !found.contains(&id) {
+                found.push(id);
+            }
+        }
+        found
+    }
+
+    /// Points clockwise from the hash of `key`, wrapping around once
+    fn walk(&self, key: &[u8]) -> impl Iterator<Item = &str> {
+        let hash = ring_hash(key);
+        self.points
+            .range(hash..)
+            .chain(self.points.range(..hash))
+            .map(|(_, id)| id.as_str())
+    }
+}
+
+impl Default for HashRing {
+    fn default() -> Self {
+        Self::new(DEFAULT_VIRTUAL_NODES)
+    }
+}
+
+/// Position of a node's `replica`-th virtual node
+fn point_hash(node_id: &str, replica: usize) -> u64 {
+    ring_hash(format!("{}#{}", node_id, replica).as_bytes())
+}
+
+/// FNV-1a with the MurmurHash3 finalizer for better avalanche
+///
+/// Unlike `DefaultHasher` the result is fixed across processes and Rust
+/// releases, so every node builds the same ring.
+fn ring_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<Vec<u8>> {
+        (0..10_000)
+            .map(|i| format!("user:{}", i).into_bytes())
+            .collect()
+    }
+
+    fn ring(ids: &[&str]) -> HashRing {
+        let mut ring = HashRing::default();
+        for id in ids {
+            ring.add_node(id);
+        }
+        ring
+    }
+
+    #[test]
+    fn test_join_moves_about_one_nth() {
+        let keys = keys();
+        let before = ring(&["a", "b", "c", "d"]);
+        let mut after = before.clone();
+        after.add_node("e");
+
+        let moved = keys
+            .iter()
+            .filter(|k| before.node_for(k) != after.node_for(k))
+            .count();
+        // Ideal is 1/5 of the keys, all of them to the new node
+        assert!((1_000..3_000).contains(&moved), "moved {}", moved);
+        assert!(keys
+            .iter()
+            .filter(|k| before.node_for(k) != after.node_for(k))
+            .all(|k| after.node_for(k) == Some("e")));
+
+        // Modulo placement moves roughly four in five keys
+        let modulo_moved = keys
+            .iter()
+            .filter(|k| ring_hash(k) % 4 != ring_hash(k) % 5)
+            .count();
+        assert!(modulo_moved > 3 * moved, "{} vs {}", modulo_moved, moved);
+
+        // Leaving hands back exactly the keys the node owned
+        after.remove_node("e");
+        assert!(keys.iter().all(|k| before.node_for(k) == after.node_for(k)));
+    }
+
+    #[test]
+    fn test_lookups_are_stable_and_balanced() {
+        let ring = ring(&["a", "b", "c", "d"]);
+        let rebuilt = self::ring(&["d", "c", "b", "a"]);
+        let keys = keys();
+
+        let mut counts = BTreeMap::new();
+        for key in &keys {
+            let owner = ring.node_for(key).unwrap();
+            assert_eq!(ring.node_for(key), Some(owner));
+            assert_eq!(rebuilt.node_for(key), Some(owner));
+            *counts.entry(owner).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 4);
+        assert!(
+            counts.values().all(|&n| (1_500..3_500).contains(&n)),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn test_nodes_for_replicas() {
+        let ring = ring(&["a", "b", "c"]);
+        let nodes = ring.nodes_for(b"key", 2);
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0], ring.node_for(b"key").unwrap());
+        assert_ne!(nodes[0], nodes[1]);
+
+        // Never more than the number of nodes
+        assert_eq!(ring.nodes_for(b"key", 5).len(), 3);
+        assert!(HashRing::default().nodes_for(b"key", 3).is_empty());
+        assert_eq!(ring.len(), 3);
+        assert!(ring.contains("b"));
+    }
+}
@@ -126,6 +126,8 @@ pub struct ResourceMetrics {
     pub queries_per_second: f64,
     pub active_connections: u64,
     pub replication_lag: f64,
+    /// Writes accepted by storage but not yet committed
+    pub storage_pending_writes: u64,
 }
 
 impl Default for ResourceMetrics {
@@ -139,6 +141,7 @@ impl Default for ResourceMetrics {
             queries_per_second: 0.0,
             active_connections: 0,
             replication_lag: 0.0,
+            storage_pending_writes: 0,
         }
     }
 }
@@ -195,6 +198,11 @@ impl AutoScaler {
         );
     }
 
+    /// Update the storage write load, keeping the other metrics
+    pub async fn update_storage_load(&self, pending_writes: u64) {
+        self.metrics.write().await.storage_pending_writes = pending_writes;
+    }
+
     /// Evaluate if scaling is needed
     #[instrument(skip(self))]
     pub async fn evaluate(&self) -> ScalingDecision {
@@ -325,6 +333,7 @@ impl AutoScaler {
                 "queries_per_second" => metrics.queries_per_second,
                 "active_connections" => metrics.active_connections as f64,
                 "replication_lag" => metrics.replication_lag,
+                "storage_pending_writes" => metrics.storage_pending_writes as f64,
                 _ => continue,
             };
 
@@ -525,4 +534,26 @@ mod tests {
 
         assert!(matches!(decision, ScalingDecision::ScaleUp(_)));
     }
+
+    #[tokio::test]
+    async fn test_storage_load_metric() {
+        let config = ScalingStrategy::Horizontal(HorizontalScalingConfig {
+            custom_metrics: vec![CustomMetric {
+                name: "storage_pending_writes".to_string(),
+                target_value: 5000.0,
+                metric_type: MetricType::AverageValue,
+            }],
+            ..Default::default()
+        });
+
+        let scaler = AutoScaler::new(config);
+        scaler.update_storage_load(100).await;
+        assert!(matches!(scaler.evaluate().await, ScalingDecision::NoAction));
+
+        scaler.update_storage_load(8000).await;
+        assert!(matches!(
+            scaler.evaluate().await,
+            ScalingDecision::ScaleUp(_)
+        ));
+    }
 }
@@ -31,6 +31,7 @@ pub mod network;
 pub mod plugin;
 pub mod query;
 pub mod reql;
+pub mod request_id;
 pub mod server;
 pub mod storage;
 
@@ -40,6 +41,10 @@ pub use reql::Datum;
 pub use storage::{Storage, StorageEngine};
 
 /// RethinkDB error types
+///
+/// Every error belongs to a category with a stable numeric [`ErrorCode`],
+/// the ReQL error type sent in the `e` field of error responses. The
+/// response type sent in `t` tells compile errors from runtime errors.
 pub mod error {
     use thiserror::Error;
 
@@ -51,6 +56,9 @@ pub mod error {
         #[error("Query error: {0}")]
         Query(String),
 
+        #[error("Compile error: {0}")]
+        Compile(String),
+
         #[error("Network error: {0}")]
         Network(String),
 
@@ -72,8 +80,90 @@ pub mod error {
         #[error("Serialization error: {0}")]
         SerializationError(String),
 
-        #[error("Storage error: {0}")]
-        StorageError(String),
+        /// Turned away for lack of capacity; retrying later may succeed
+        #[error("Server busy: {0}")]
+        Busy(String),
+    }
+
+    /// Response type of a query that failed to compile
+    pub const COMPILE_ERROR: u64 = 17;
+
+    /// Response type of a query that failed while running
+    pub const RUNTIME_ERROR: u64 = 18;
+
+    /// ReQL error types, numbered as in RethinkDB
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum ErrorCode {
+        Internal,
+        ResourceLimit,
+        QueryLogic,
+        NonExistence,
+        OpFailed,
+        OpIndeterminate,
+        User,
+        Permission,
+    }
+
+    impl ErrorCode {
+        /// Number sent over the wire
+        pub fn as_u64(self) -> u64 {
+            match self {
+                ErrorCode::Internal => 1_000_000,
+                ErrorCode::ResourceLimit => 2_000_000,
+                ErrorCode::QueryLogic => 3_000_000,
+                ErrorCode::NonExistence => 3_100_000,
+                ErrorCode::OpFailed => 4_100_000,
+                ErrorCode::OpIndeterminate => 4_200_000,
+                ErrorCode::User => 5_000_000,
+                ErrorCode::Permission => 6_000_000,
+            }
+        }
+
+        pub fn name(self) -> &'static str {
+            match self {
+                ErrorCode::Internal => "INTERNAL",
+                ErrorCode::ResourceLimit => "RESOURCE_LIMIT",
+                ErrorCode::QueryLogic => "QUERY_LOGIC",
+                ErrorCode::NonExistence => "NON_EXISTENCE",
+                ErrorCode::OpFailed => "OP_FAILED",
+                ErrorCode::OpIndeterminate => "OP_INDETERMINATE",
+                ErrorCode::User => "USER",
+                ErrorCode::Permission => "PERMISSION_ERROR",
+            }
+        }
+    }
+
+    impl Error {
+        /// Category of the error
+        pub fn code(&self) -> ErrorCode {
+            match self {
+                Error::Query(_) | Error::Compile(_) | Error::InvalidArgument(_) => {
+                    ErrorCode::QueryLogic
+                }
+                Error::NotFound(_) => ErrorCode::NonExistence,
+                Error::Storage(_) | Error::Plugin(_) | Error::AlreadyExists(_) => {
+                    ErrorCode::OpFailed
+                }
+                // Rejected before it ran
+                Error::Busy(_) => ErrorCode::OpFailed,
+                // The request may or may not have reached the other node
+                Error::Network(_) => ErrorCode::OpIndeterminate,
+                Error::Internal(_) | Error::SerializationError(_) => ErrorCode::Internal,
+            }
+        }
+
+        /// Response type of a query failing with this error
+        pub fn response_type(&self) -> u64 {
+            match self {
+                Error::Compile(_) => COMPILE_ERROR,
+                _ => RUNTIME_ERROR,
+            }
+        }
+    }
+
+    /// The first [`Error`] in the chain of `error`, if any
+    pub fn find(error: &anyhow::Error) -> Option<&Error> {
+        error.chain().find_map(|e| e.downcast_ref::<Error>())
     }
 
     pub type Result<T> = std::result::Result<T, Error>;
@@ -92,4 +182,20 @@ mod tests {
         let _version: &str = VERSION;
         // Just ensure the constant is accessible
     }
+
+    #[test]
+    fn test_error_codes() {
+        use error::{Error, ErrorCode};
+
+        assert_eq!(Error::NotFound("t".into()).code().as_u64(), 3_100_000);
+        assert_eq!(Error::AlreadyExists("t".into()).code(), ErrorCode::OpFailed);
+        assert_eq!(Error::Storage("io".into()).code(), ErrorCode::OpFailed);
+        assert_eq!(Error::Busy("full".into()).code(), ErrorCode::OpFailed);
+        assert_eq!(Error::Compile("bad".into()).response_type(), error::COMPILE_ERROR);
+        assert_eq!(Error::Query("bad".into()).response_type(), error::RUNTIME_ERROR);
+
+        let wrapped = anyhow::Error::new(Error::NotFound("t".into())).context("Query failed");
+        assert_eq!(error::find(&wrapped).map(Error::code), Some(ErrorCode::NonExistence));
+        assert!(error::find(&anyhow::anyhow!("plain")).is_none());
+    }
 }
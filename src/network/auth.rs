@@ -1,7 +1,22 @@
 //! Authentication and authorization for RethinkDB connections
-
+//!
+//! Read, write and config permissions are scoped to everything, one
+//! database or one table. Before a query runs, [`AuthManager::authorize`]
+//! derives the permissions its terms need (see [`required_permissions`])
+//! and checks them against the user's grants.
+//!
+//! Users are authenticated against the built-in bcrypt hashes unless a
+//! plugin provides authentication, in which case the plugin checks
+//! credentials and supplies permissions instead (see
+//! [`AuthProvider`](crate::plugin::AuthProvider)). Built-in users still
+//! sign in when no provider accepts them, so the admin is never locked out
+//! by a misbehaving provider.
+
+use crate::plugin::PluginManager;
+use crate::reql::{Term, TermType};
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -14,18 +29,233 @@ pub struct User {
 }
 
 /// Permission types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Permission {
-    Read,
-    Write,
+    /// Read documents
+    Read(Scope),
+    /// Insert, update, replace and delete documents
+    Write(Scope),
+    /// Create, drop and reconfigure databases and tables
+    Config(Scope),
+    /// Everything
     Admin,
     Connect,
 }
 
+impl Permission {
+    /// Whether holding `self` grants `required`
+    pub fn grants(&self, required: &Permission) -> bool {
+        match (self, required) {
+            (Permission::Admin, _) => true,
+            (Permission::Read(held), Permission::Read(needed))
+            | (Permission::Write(held), Permission::Write(needed))
+            | (Permission::Config(held), Permission::Config(needed)) => held.covers(needed),
+            (held, needed) => held == needed,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Read(scope) => write!(f, "read on {}", scope),
+            Permission::Write(scope) => write!(f, "write on {}", scope),
+            Permission::Config(scope) => write!(f, "config on {}", scope),
+            Permission::Admin => write!(f, "admin"),
+            Permission::Connect => write!(f, "connect"),
+        }
+    }
+}
+
+/// What a read, write or config permission applies to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Every database and table
+    Global,
+    /// A database and all of its tables
+    Database(String),
+    /// A single table
+    Table { db: String, table: String },
+}
+
+impl Scope {
+    /// Shorthand for [`Scope::Table`]
+    pub fn table(db: &str, table: &str) -> Self {
+        Scope::Table {
+            db: db.to_string(),
+            table: table.to_string(),
+        }
+    }
+
+    /// Whether this scope includes all of `other`
+    pub fn covers(&self, other: &Scope) -> bool {
+        match (self, other) {
+            (Scope::Global, _) => true,
+            (Scope::Database(db), Scope::Database(other_db)) => db == other_db,
+            (Scope::Database(db), Scope::Table { db: other_db, .. }) => db == other_db,
+            (Scope::Table { .. }, Scope::Table { .. }) => self == other,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Global => write!(f, "all databases"),
+            Scope::Database(db) => write!(f, "database `{}`", db),
+            Scope::Table { db, table } => write!(f, "table `{}.{}`", db, table),
+        }
+    }
+}
+
+/// A query needed a permission the user does not hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionDenied {
+    pub username: String,
+    pub permission: Permission,
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "User `{}` does not have {} permission",
+            self.username, self.permission
+        )
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+/// Permissions a user needs to run `term`
+///
+/// Every `TABLE` needs read access and the table a write term modifies
+/// needs write access; creating or dropping databases and tables, and
+/// reconfiguring a table, needs config access. Names that are not literals widen the scope: a
+/// computed table name needs the whole database, a computed database
+/// name needs everything. Since any `DB` term switches the database of
+/// the tables that follow it, a table without its own `DB` needs access
+/// in every database the query names, as well as `default_db`, the one the
+/// query runs in.
+pub fn required_permissions(term: &Term, default_db: &str) -> Vec<Permission> {
+    let mut dbs = vec![Some(default_db.to_string())];
+    collect_dbs(term, &mut dbs);
+
+    let mut required = Vec::new();
+    collect_permissions(term, &dbs, &mut required);
+    required
+}
+
+/// Name of a term that is a string literal
+fn literal(term: Option<&Term>) -> Option<String> {
+    term?.as_datum()?.as_string().map(str::to_string)
+}
+
+fn collect_dbs(term: &Term, dbs: &mut Vec<Option<String>>) {
+    if term.term_type == TermType::Db {
+        let db = literal(term.arg(0));
+        if !dbs.contains(&db) {
+            dbs.push(db);
+        }
+    }
+    for child in term.args.iter().chain(term.optargs.values()) {
+        collect_dbs(child, dbs);
+    }
+}
+
+fn scope_of(db: &Option<String>, table: &Option<String>) -> Scope {
+    match (db, table) {
+        (Some(db), Some(table)) => Scope::table(db, table),
+        (Some(db), None) => Scope::Database(db.clone()),
+        (None, _) => Scope::Global,
+    }
+}
+
+/// Scopes a `TABLE` term may refer to
+fn table_scopes(table: &Term, dbs: &[Option<String>]) -> Vec<Scope> {
+    match table.args.as_slice() {
+        [db, name, ..] if db.term_type == TermType::Db => {
+            vec![scope_of(&literal(db.arg(0)), &literal(Some(name)))]
+        }
+        [name, ..] => dbs
+            .iter()
+            .map(|db| scope_of(db, &literal(Some(name))))
+            .collect(),
+        [] => vec![Scope::Global],
+    }
+}
+
+/// First `TABLE` term in evaluation order
+fn find_table(term: &Term) -> Option<&Term> {
+    if term.term_type == TermType::Table {
+        return Some(term);
+    }
+    term.args.iter().find_map(find_table)
+}
+
+/// Scopes of the table a write or reconfigure term operates on
+fn target_scopes(term: &Term, dbs: &[Option<String>]) -> Vec<Scope> {
+    match term.arg(0).and_then(find_table) {
+        Some(table) => table_scopes(table, dbs),
+        None => vec![Scope::Global],
+    }
+}
+
+fn collect_permissions(term: &Term, dbs: &[Option<String>], required: &mut Vec<Permission>) {
+    let mut require = |permission: Permission| {
+        if !required.contains(&permission) {
+            required.push(permission);
+        }
+    };
+
+    match term.term_type {
+        TermType::Table => {
+            for scope in table_scopes(term, dbs) {
+                require(Permission::Read(scope));
+            }
+        }
+        TermType::Insert | TermType::Update | TermType::Replace | TermType::Delete => {
+            for scope in target_scopes(term, dbs) {
+                require(Permission::Write(scope));
+            }
+        }
+        TermType::Reconfigure => {
+            for scope in target_scopes(term, dbs) {
+                require(Permission::Config(scope));
+            }
+        }
+        TermType::DbCreate | TermType::DbDrop => {
+            require(Permission::Config(scope_of(&literal(term.arg(0)), &None)));
+        }
+        TermType::TableCreate | TermType::TableDrop => {
+            for db in dbs {
+                require(Permission::Config(scope_of(db, &None)));
+            }
+        }
+        _ => {}
+    }
+
+    for child in term.args.iter().chain(term.optargs.values()) {
+        collect_permissions(child, dbs, required);
+    }
+}
+
 /// Authentication manager
 pub struct AuthManager {
     users: Arc<RwLock<HashMap<String, User>>>,
     default_user: Option<String>,
+    /// Plugins that may provide authentication
+    plugins: Option<Arc<PluginManager>>,
+}
+
+impl fmt::Debug for AuthManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthManager")
+            .field("default_user", &self.default_user)
+            .field("plugins", &self.plugins.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 impl AuthManager {
@@ -34,21 +264,26 @@ impl AuthManager {
         Self {
             users: Arc::new(RwLock::new(HashMap::new())),
             default_user: None,
+            plugins: None,
         }
     }
 
+    /// Delegate authentication to the providers among `plugins`
+    ///
+    /// Plugins are looked up on every authentication, so providers loaded
+    /// or unloaded later take effect on the next handshake.
+    pub fn with_plugins(mut self, plugins: Arc<PluginManager>) -> Self {
+        self.plugins = Some(plugins);
+        self
+    }
+
     /// Create with default admin user
     pub fn with_admin(admin_password: &str) -> Self {
         let mut manager = Self::new();
         let admin = User {
             username: "admin".to_string(),
             password_hash: Self::hash_password(admin_password),
-            permissions: vec![
-                Permission::Admin,
-                Permission::Read,
-                Permission::Write,
-                Permission::Connect,
-            ],
+            permissions: vec![Permission::Admin, Permission::Connect],
         };
 
         let mut users = HashMap::new();
@@ -86,7 +321,35 @@ impl AuthManager {
     }
 
     /// Authenticate with username and password
+    ///
+    /// When plugins provide authentication they are asked in turn, ordered
+    /// by plugin name, and the first to accept the credentials supplies the
+    /// user's permissions. If none does, or a provider fails, the password
+    /// is checked against the stored hash of the built-in user.
     pub async fn authenticate(&self, username: &str, password: &str) -> Result<User> {
+        let providers = match &self.plugins {
+            Some(plugins) => plugins.auth_providers(),
+            None => Vec::new(),
+        };
+        for plugin in providers {
+            let Some(provider) = plugin.auth_provider() else {
+                continue;
+            };
+            match provider.authenticate(username, password).await {
+                Ok(true) => {
+                    return Ok(User {
+                        username: username.to_string(),
+                        password_hash: String::new(),
+                        permissions: provider.permissions(username).await?,
+                    });
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!(plugin = %plugin.metadata().name, error = %e, "Auth provider failed")
+                }
+            }
+        }
+
         let users = self.users.read().await;
         let user = users
             .get(username)
@@ -99,6 +362,21 @@ impl AuthManager {
         Ok(user.clone())
     }
 
+    /// Authenticate the auth key a client sent in its handshake
+    ///
+    /// Keys have the form `username:password`; a key without a colon is the
+    /// admin password. The user needs the `Connect` permission.
+    pub async fn authenticate_handshake(&self, auth_key: &str) -> Result<User> {
+        let (username, password) = auth_key.split_once(':').unwrap_or(("admin", auth_key));
+        let user = self.authenticate(username, password).await?;
+
+        if !Self::has_permission(&user, &Permission::Connect) {
+            return Err(anyhow!("User {} may not connect", username));
+        }
+
+        Ok(user)
+    }
+
     /// Authenticate with auth key (simplified)
     pub async fn authenticate_key(&self, auth_key: &str) -> Result<User> {
         // For now, treat empty key as admin if no users configured
@@ -108,12 +386,7 @@ impl AuthManager {
                 return Ok(User {
                     username: "default".to_string(),
                     password_hash: String::new(),
-                    permissions: vec![
-                        Permission::Admin,
-                        Permission::Read,
-                        Permission::Write,
-                        Permission::Connect,
-                    ],
+                    permissions: vec![Permission::Admin, Permission::Connect],
                 });
             }
         }
@@ -129,9 +402,79 @@ impl AuthManager {
         Err(anyhow!("Invalid authentication key"))
     }
 
+    /// Authenticate with a TLS client certificate
+    ///
+    /// The certificate must already be validated against the trusted CA; its
+    /// subject common name is mapped to the user of the same name, who needs
+    /// the `Connect` permission.
+    pub async fn authenticate_certificate(&self, cert_der: &[u8]) -> Result<User> {
+        let subject = certificate_common_name(cert_der)?;
+        let users = self.users.read().await;
+        let user = users
+            .get(&subject)
+            .ok_or_else(|| anyhow!("No user for certificate subject: {}", subject))?;
+
+        if !Self::has_permission(user, &Permission::Connect) {
+            return Err(anyhow!("User {} may not connect", subject));
+        }
+
+        Ok(user.clone())
+    }
+
     /// Check if user has permission
-    pub fn has_permission(user: &User, permission: Permission) -> bool {
-        user.permissions.contains(&permission) || user.permissions.contains(&Permission::Admin)
+    pub fn has_permission(user: &User, permission: &Permission) -> bool {
+        user.permissions.iter().any(|held| held.grants(permission))
+    }
+
+    /// Check that `user` may run `term` with the default database
+    /// `default_db`
+    ///
+    /// Fails with [`PermissionDenied`] for the first missing permission.
+    pub fn authorize(
+        user: &User,
+        term: &Term,
+        default_db: &str,
+    ) -> std::result::Result<(), PermissionDenied> {
+        match required_permissions(term, default_db)
+            .into_iter()
+            .find(|permission| !Self::has_permission(user, permission))
+        {
+            Some(permission) => Err(PermissionDenied {
+                username: user.username.clone(),
+                permission,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Give a user a permission
+    pub async fn grant(&self, username: &str, permission: Permission) -> Result<()> {
+        let mut users = self.users.write().await;
+        let user = users
+            .get_mut(username)
+            .ok_or_else(|| anyhow!("User not found: {}", username))?;
+        if !user.permissions.contains(&permission) {
+            user.permissions.push(permission);
+        }
+        Ok(())
+    }
+
+    /// Take a permission away from a user
+    ///
+    /// Only an identical grant is removed; revoking a table does not narrow
+    /// a grant on its whole database.
+    pub async fn revoke(&self, username: &str, permission: &Permission) -> Result<()> {
+        let mut users = self.users.write().await;
+        let user = users
+            .get_mut(username)
+            .ok_or_else(|| anyhow!("User not found: {}", username))?;
+        user.permissions.retain(|held| held != permission);
+        Ok(())
+    }
+
+    /// Look up a user by name
+    pub async fn get_user(&self, username: &str) -> Option<User> {
+        self.users.read().await.get(username).cloned()
     }
 
     /// Hash a password (using bcrypt for production)
@@ -175,6 +518,70 @@ impl AuthManager {
     }
 }
 
+/// Extract the subject common name (CN) from a DER-encoded X.509 certificate
+pub fn certificate_common_name(cert_der: &[u8]) -> Result<String> {
+    let malformed = || anyhow!("Malformed certificate");
+
+    let (_, cert, _) = der_read(cert_der).ok_or_else(malformed)?;
+    let (_, mut tbs, _) = der_read(cert).ok_or_else(malformed)?;
+
+    // Skip the optional version, then serial number, signature algorithm,
+    // issuer and validity
+    if tbs.first() == Some(&0xA0) {
+        tbs = der_read(tbs).ok_or_else(malformed)?.2;
+    }
+    for _ in 0..4 {
+        tbs = der_read(tbs).ok_or_else(malformed)?.2;
+    }
+
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+    let (_, mut rdns, _) = der_read(tbs).ok_or_else(malformed)?;
+    while !rdns.is_empty() {
+        let (_, mut attrs, rest) = der_read(rdns).ok_or_else(malformed)?;
+        rdns = rest;
+        while !attrs.is_empty() {
+            let (_, attr, rest) = der_read(attrs).ok_or_else(malformed)?;
+            attrs = rest;
+            let (tag, oid, value) = der_read(attr).ok_or_else(malformed)?;
+            if tag == 0x06 && oid == OID_COMMON_NAME {
+                let (_, name, _) = der_read(value).ok_or_else(malformed)?;
+                return Ok(String::from_utf8(name.to_vec())?);
+            }
+        }
+    }
+
+    Err(anyhow!("Certificate subject has no common name"))
+}
+
+/// id-at-commonName (2.5.4.3)
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Read one DER element, returning its tag, contents and the remaining input
+fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let octets = (first & 0x7F) as usize;
+        if octets == 0 || octets > 4 || input.len() < octets {
+            return None;
+        }
+        let len = input[..octets]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | b as usize);
+        input = &input[octets..];
+        len
+    };
+
+    if input.len() < len {
+        return None;
+    }
+    let (contents, rest) = input.split_at(len);
+    Some((tag, contents, rest))
+}
+
 impl Default for AuthManager {
     fn default() -> Self {
         Self::new()
@@ -184,6 +591,7 @@ impl Default for AuthManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::query::executor::DEFAULT_DB;
 
     #[tokio::test]
     async fn test_add_user() {
@@ -191,7 +599,7 @@ mod tests {
         auth.add_user(
             "test_user".to_string(),
             "password123",
-            vec![Permission::Read, Permission::Write],
+            vec![Permission::Read(Scope::Global), Permission::Write(Scope::Global)],
         )
         .await
         .unwrap();
@@ -205,14 +613,14 @@ mod tests {
         auth.add_user(
             "alice".to_string(),
             "secret",
-            vec![Permission::Read],
+            vec![Permission::Read(Scope::Global)],
         )
         .await
         .unwrap();
 
         let user = auth.authenticate("alice", "secret").await.unwrap();
         assert_eq!(user.username, "alice");
-        assert!(user.permissions.contains(&Permission::Read));
+        assert!(user.permissions.contains(&Permission::Read(Scope::Global)));
 
         assert!(auth.authenticate("alice", "wrong").await.is_err());
         assert!(auth.authenticate("bob", "secret").await.is_err());
@@ -229,13 +637,13 @@ mod tests {
         let reader = User {
             username: "reader".to_string(),
             password_hash: String::new(),
-            permissions: vec![Permission::Read],
+            permissions: vec![Permission::Read(Scope::Global)],
         };
 
-        assert!(AuthManager::has_permission(&admin, Permission::Write));
-        assert!(AuthManager::has_permission(&admin, Permission::Read));
-        assert!(!AuthManager::has_permission(&reader, Permission::Write));
-        assert!(AuthManager::has_permission(&reader, Permission::Read));
+        assert!(AuthManager::has_permission(&admin, &Permission::Write(Scope::Global)));
+        assert!(AuthManager::has_permission(&admin, &Permission::Read(Scope::Global)));
+        assert!(!AuthManager::has_permission(&reader, &Permission::Write(Scope::Global)));
+        assert!(AuthManager::has_permission(&reader, &Permission::Read(Scope::Global)));
     }
 
     #[tokio::test]
@@ -248,6 +656,12 @@ mod tests {
         assert!(user.permissions.contains(&Permission::Admin));
     }
 
+    #[test]
+    fn test_certificate_common_name_rejects_garbage() {
+        assert!(certificate_common_name(&[]).is_err());
+        assert!(certificate_common_name(&[0x30, 0x82, 0xFF]).is_err());
+    }
+
     #[tokio::test]
     async fn test_with_admin() {
         let auth = AuthManager::with_admin("admin_password");
@@ -256,4 +670,84 @@ mod tests {
         let admin = auth.authenticate("admin", "admin_password").await.unwrap();
         assert!(admin.permissions.contains(&Permission::Admin));
     }
+
+    #[tokio::test]
+    async fn test_grant_and_revoke() {
+        let auth = AuthManager::new();
+        auth.add_user("carol".to_string(), "secret", vec![Permission::Connect])
+            .await
+            .unwrap();
+        let write_app = Permission::Write(Scope::Database("app".to_string()));
+        let write_users = Permission::Write(Scope::table("app", "users"));
+
+        auth.grant("carol", write_app.clone()).await.unwrap();
+        let carol = auth.get_user("carol").await.unwrap();
+        assert!(AuthManager::has_permission(&carol, &write_users));
+        assert!(!AuthManager::has_permission(
+            &carol,
+            &Permission::Write(Scope::table("other", "users"))
+        ));
+        assert!(!AuthManager::has_permission(
+            &carol,
+            &Permission::Read(Scope::table("app", "users"))
+        ));
+
+        auth.revoke("carol", &write_app).await.unwrap();
+        let carol = auth.get_user("carol").await.unwrap();
+        assert!(!AuthManager::has_permission(&carol, &write_users));
+        assert!(auth.grant("nobody", write_app).await.is_err());
+    }
+
+    #[test]
+    fn test_required_permissions() {
+        let table = |db: Option<&str>, name: &str| {
+            let name = Term::datum(crate::reql::Datum::from(name));
+            let args = match db {
+                Some(db) => vec![
+                    Term::new(TermType::Db)
+                        .with_args(vec![Term::datum(crate::reql::Datum::from(db))]),
+                    name,
+                ],
+                None => vec![name],
+            };
+            Term::new(TermType::Table).with_args(args)
+        };
+
+        let delete = Term::new(TermType::Delete).with_args(vec![table(None, "users")]);
+        assert_eq!(
+            required_permissions(&delete, DEFAULT_DB),
+            vec![
+                Permission::Write(Scope::table("test", "users")),
+                Permission::Read(Scope::table("test", "users")),
+            ]
+        );
+        assert_eq!(
+            required_permissions(&delete, "app"),
+            vec![
+                Permission::Write(Scope::table("app", "users")),
+                Permission::Read(Scope::table("app", "users")),
+            ]
+        );
+
+        let reconfigure = Term::new(TermType::Reconfigure).with_args(vec![table(None, "users")]);
+        assert_eq!(
+            required_permissions(&reconfigure, DEFAULT_DB),
+            vec![
+                Permission::Config(Scope::table("test", "users")),
+                Permission::Read(Scope::table("test", "users")),
+            ]
+        );
+
+        // A DB anywhere in the query may redirect tables without their own
+        let union = Term::new(TermType::Union)
+            .with_args(vec![table(Some("app"), "a"), table(None, "b")]);
+        assert_eq!(
+            required_permissions(&union, DEFAULT_DB),
+            vec![
+                Permission::Read(Scope::table("app", "a")),
+                Permission::Read(Scope::table("test", "b")),
+                Permission::Read(Scope::table("app", "b")),
+            ]
+        );
+    }
 }
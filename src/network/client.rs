@@ -0,0 +1,280 @@
+//! Client side of the RethinkDB wire protocol
+//!
+//! [`Connection::connect`](super::Connection::connect) opens a TCP
+//! connection, performs the client handshake and returns a
+//! [`ClientConnection`] for in-process drivers, embedded users and
+//! end-to-end tests.
+//!
+//! Terms are sent in their JSON wire form, with `ql2.proto` term ids (e.g.
+//! `[59]` for `DB_LIST`):
+//!
+//! ```rust,ignore
+//! let conn = Connection::connect("127.0.0.1:28015", ConnectOptions::default()).await?;
+//! let dbs = conn.run(serde_json::json!([59])).await?;
+//!
+//! let mut cursor = conn.run_cursor(serde_json::json!([15, [[14, ["test"]], "users"]])).await?;
+//! while let Some(doc) = cursor.next().await? {
+//!     println!("{}", doc);
+//! }
+//! ```
+
+use super::protocol::{
+    read_response, write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+};
+use crate::reql::Datum;
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+
+/// Response types (from ql2.proto)
+const SUCCESS_ATOM: u64 = 1;
+const SUCCESS_SEQUENCE: u64 = 2;
+const SUCCESS_PARTIAL: u64 = 3;
+const CLIENT_ERROR: u64 = 16;
+const COMPILE_ERROR: u64 = 17;
+const RUNTIME_ERROR: u64 = 18;
+
+/// Client connection options
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// Auth key sent during the handshake
+    pub auth_key: Option<String>,
+
+    /// Protocol version to negotiate
+    pub version: ProtocolVersion,
+
+    /// Wire protocol to negotiate
+    pub protocol: WireProtocol,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            auth_key: None,
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+        }
+    }
+}
+
+/// An established client connection
+///
+/// Queries are serialized over the single TCP stream, so a connection can be
+/// shared between tasks.
+#[derive(Debug)]
+pub struct ClientConnection {
+    stream: Mutex<TcpStream>,
+    peer_addr: SocketAddr,
+    next_token: AtomicI64,
+}
+
+/// One batch of a query result
+enum Batch {
+    Atom(serde_json::Value),
+    Sequence {
+        items: Vec<serde_json::Value>,
+        more: bool,
+    },
+}
+
+impl ClientConnection {
+    /// Connect and perform the client handshake
+    pub(crate) async fn open<A: ToSocketAddrs>(addr: A, options: ConnectOptions) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let peer_addr = stream.peer_addr()?;
+        stream.set_nodelay(true)?;
+
+        Handshake::connect(
+            &mut stream,
+            options.auth_key,
+            options.version,
+            options.protocol,
+        )
+        .await
+        .map_err(|e| anyhow!("Handshake with {} failed: {}", peer_addr, e))?;
+
+        tracing::debug!("Connected to {}", peer_addr);
+
+        Ok(Self {
+            stream: Mutex::new(stream),
+            peer_addr,
+            next_token: AtomicI64::new(1),
+        })
+    }
+
+    /// Get the server address
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Run a term and return its whole result
+    ///
+    /// Sequences are drained into a single array.
+    pub async fn run(&self, term: serde_json::Value) -> Result<Datum> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        match self.start(token, term).await? {
+            Batch::Atom(value) => Ok(Datum::from(value)),
+            Batch::Sequence { items, more } => {
+                let cursor = Cursor::new(self, token, items, more);
+                Ok(Datum::Array(cursor.collect().await?))
+            }
+        }
+    }
+
+    /// Run a term that produces a sequence and iterate over its results
+    ///
+    /// Atom arrays are returned as a fully buffered cursor.
+    pub async fn run_cursor(&self, term: serde_json::Value) -> Result<Cursor<'_>> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        match self.start(token, term).await? {
+            Batch::Atom(serde_json::Value::Array(items)) => {
+                Ok(Cursor::new(self, token, items, false))
+            }
+            Batch::Atom(value) => Err(anyhow!("Query result is not a sequence: {}", value)),
+            Batch::Sequence { items, more } => Ok(Cursor::new(self, token, items, more)),
+        }
+    }
+
+    async fn start(&self, token: i64, term: serde_json::Value) -> Result<Batch> {
+        self.send(token, serde_json::json!({ "type": "START", "query": term }))
+            .await
+    }
+
+    /// Send a query and wait for its response
+    async fn send(&self, token: i64, query: serde_json::Value) -> Result<Batch> {
+        let mut stream = self.stream.lock().await;
+        write_query(&mut *stream, &QueryMessage { token, query }).await?;
+
+        let response = read_response(&mut *stream).await?;
+        if response.token != token {
+            return Err(anyhow!(
+                "Response token mismatch: expected {}, got {}",
+                token,
+                response.token
+            ));
+        }
+
+        parse_response(response.response)
+    }
+}
+
+/// Decode a response body into a batch or an error
+fn parse_response(mut response: serde_json::Value) -> Result<Batch> {
+    let response_type = response
+        .get("t")
+        .and_then(|t| t.as_u64())
+        .ok_or_else(|| anyhow!("Missing response type"))?;
+    let items = match response.get_mut("r").map(serde_json::Value::take) {
+        Some(serde_json::Value::Array(items)) => items,
+        _ => Vec::new(),
+    };
+
+    match response_type {
+        SUCCESS_ATOM => Ok(Batch::Atom(
+            items.into_iter().next().unwrap_or(serde_json::Value::Null),
+        )),
+        SUCCESS_SEQUENCE => Ok(Batch::Sequence { items, more: false }),
+        SUCCESS_PARTIAL => Ok(Batch::Sequence { items, more: true }),
+        CLIENT_ERROR | COMPILE_ERROR | RUNTIME_ERROR => {
+            let message = response
+                .get("m")
+                .and_then(|m| m.as_str())
+                .or_else(|| items.first().and_then(|m| m.as_str()))
+                .unwrap_or("unknown error");
+            let kind = match response_type {
+                CLIENT_ERROR => "Client error",
+                COMPILE_ERROR => "Compile error",
+                _ => "Runtime error",
+            };
+            Err(anyhow!("{}: {}", kind, message))
+        }
+        other => Err(anyhow!("Unexpected response type: {}", other)),
+    }
+}
+
+/// Results of a sequence query
+///
+/// Further batches are fetched with CONTINUE as the buffer runs dry.
+pub struct Cursor<'a> {
+    conn: &'a ClientConnection,
+    token: i64,
+    buffer: VecDeque<serde_json::Value>,
+    more: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(
+        conn: &'a ClientConnection,
+        token: i64,
+        items: Vec<serde_json::Value>,
+        more: bool,
+    ) -> Self {
+        Self {
+            conn,
+            token,
+            buffer: items.into(),
+            more,
+        }
+    }
+
+    /// Get the next result, or `None` once the sequence is exhausted
+    pub async fn next(&mut self) -> Result<Option<Datum>> {
+        while self.buffer.is_empty() && self.more {
+            let query = serde_json::json!({ "type": "CONTINUE" });
+            match self.conn.send(self.token, query).await? {
+                Batch::Sequence { items, more } => {
+                    self.buffer.extend(items);
+                    self.more = more;
+                }
+                Batch::Atom(_) => return Err(anyhow!("Unexpected atom in CONTINUE response")),
+            }
+        }
+
+        Ok(self.buffer.pop_front().map(Datum::from))
+    }
+
+    /// Drain the remaining results
+    pub async fn collect(mut self) -> Result<Vec<Datum>> {
+        let mut results = Vec::with_capacity(self.buffer.len());
+        while let Some(datum) = self.next().await? {
+            results.push(datum);
+        }
+        Ok(results)
+    }
+
+    /// Stop the query on the server if it has more results
+    pub async fn close(self) -> Result<()> {
+        if self.more {
+            let query = serde_json::json!({ "type": "STOP" });
+            self.conn.send(self.token, query).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let atom = parse_response(serde_json::json!({"t": 1, "r": [["test"]]})).unwrap();
+        assert!(matches!(atom, Batch::Atom(serde_json::Value::Array(ref dbs)) if dbs.len() == 1));
+
+        let partial = parse_response(serde_json::json!({"t": 3, "r": [1, 2]})).unwrap();
+        assert!(matches!(partial, Batch::Sequence { ref items, more: true } if items.len() == 2));
+
+        let error = parse_response(serde_json::json!({
+            "t": 18,
+            "r": [],
+            "e": 1000000,
+            "b": [],
+            "m": "Table `missing` does not exist"
+        }));
+        let message = error.err().unwrap().to_string();
+        assert_eq!(message, "Runtime error: Table `missing` does not exist");
+    }
+}
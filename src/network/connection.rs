@@ -3,7 +3,8 @@
 //! Handles the complete lifecycle of a RethinkDB client connection:
 //!
 //! 1. **Handshake**: Protocol version and authentication
-//! 2. **Query Loop**: Receive queries, execute, send responses
+//! 2. **Query Loop**: Receive queries, execute, send responses (concurrently
+//!    per token from V0_4 on, see [`ConnectionHandler`])
 //! 3. **Error Handling**: Graceful error responses
 //!
 //! # Query Types
@@ -11,9 +12,62 @@
 //! - **START**: Execute a new query
 //! - **CONTINUE**: Fetch more results from a cursor
 //! - **STOP**: Cancel an ongoing query
-//! - **NOREPLY_WAIT**: Wait for all noreply queries to complete
+//! - **NOREPLY_WAIT**: Wait for all noreply queries to complete and their
+//!   writes to be durable
 //! - **SERVER_INFO**: Get server information
 //!
+//! Query types may be sent either by name (`"START"`) or by their numeric
+//! wire id (`1`..`5`).
+//!
+//! # Term Encoding
+//!
+//! Terms are encoded as official drivers send them, with the term ids of
+//! `ql2.proto`: `r.db("test").table("users")` is
+//! `[15, [[14, ["test"]], "users"]]` (see [`QueryCompiler::compile_ql2`]).
+//!
+//! # Authorization
+//!
+//! A [`ConnectionHandler`] given an [`AuthManager`] authenticates clients
+//! by the auth key of their handshake and rejects those it does not accept.
+//!
+//! When the handshake or transport identified a user, every START query is checked
+//! against that user's grants before it runs (see
+//! [`AuthManager::authorize`]). Denied queries fail with a
+//! `PERMISSION_ERROR`.
+//!
+//! # Errors
+//!
+//! Failed queries get a `COMPILE_ERROR` response if the term could not be
+//! compiled and a `RUNTIME_ERROR` one otherwise. The `e` field carries the
+//! ReQL error type of the failure (see [`crate::error::ErrorCode`]), so
+//! clients can tell e.g. a missing table from a failed write without
+//! matching messages.
+//!
+//! # Noreply Writes
+//!
+//! A START query with the global optarg `noreply: true` is executed in the
+//! background and produces no response. Failures are recorded on the
+//! connection and reported by the next NOREPLY_WAIT, which also flushes
+//! storage so that everything it acknowledges survives a crash.
+//!
+//! # Request Ids
+//!
+//! Every query runs under a request id, see [`crate::request_id`]: the
+//! global optarg `request_id` if it is usable, else a generated one. Log
+//! lines of the query carry it together with the query's token.
+//!
+//! # Default Database
+//!
+//! The global optarg `db` of a START query, a `DB` term or a plain name,
+//! sets the database of the tables the query does not qualify with a `DB`
+//! of their own. Without it, they are looked up in
+//! [`DEFAULT_DB`](crate::query::executor::DEFAULT_DB).
+//!
+//! # Explain
+//!
+//! A START query with the global optarg `explain: true` is not executed;
+//! its response is the query plan (see [`QueryExecutor::explain`]).
+//!
 //! # Architecture
 //!
 //! ```text
@@ -23,24 +77,46 @@
 //!                            Handling       Parse         Operations     CRUD
 //! ```
 
+use super::auth::{AuthManager, PermissionDenied, User};
+use super::client::{ClientConnection, ConnectOptions};
 use super::protocol::{
     read_query, write_response, Handshake, ProtocolVersion, QueryMessage, ResponseMessage,
     WireProtocol,
 };
+use crate::cluster::metrics::{record_connection_error, ConnectionErrorReason};
+use crate::cluster::{ReadMode, ReadOptions, ReadSession, WriteToken};
+use crate::error::{self, Error, ErrorCode};
 use crate::query::compiler::QueryCompiler;
-use crate::query::executor::QueryExecutor;
+use crate::query::executor::{QueryExecutor, DEFAULT_DB};
+use crate::reql::{Datum, Term, TermType};
+use crate::request_id;
 use crate::storage::Storage;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot, watch, Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tracing::Instrument;
 
 /// Connection state
 #[derive(Debug)]
 pub struct Connection {
     handshake: Handshake,
+    storage: Arc<Storage>,
     executor: Arc<QueryExecutor>,
-    active_queries: Arc<Mutex<std::collections::HashMap<i64, tokio::sync::oneshot::Sender<()>>>>,
+    /// Running START queries by token, so STOP can cancel them
+    active_queries: Arc<Mutex<HashMap<i64, oneshot::Sender<()>>>>,
+    /// In-flight noreply queries
+    noreply_tasks: Arc<Mutex<JoinSet<Result<()>>>>,
+    /// Errors from finished noreply queries not yet reported by NOREPLY_WAIT
+    noreply_errors: Arc<Mutex<Vec<String>>>,
+    /// Authenticated user, if the transport identified one
+    user: Option<User>,
+    /// Read mode and last acknowledged write of the client
+    session: Arc<Mutex<ReadSession>>,
 }
 
 impl Connection {
@@ -48,11 +124,47 @@ impl Connection {
     pub fn new(handshake: Handshake, storage: Arc<Storage>) -> Self {
         Self {
             handshake,
-            executor: Arc::new(QueryExecutor::new(storage)),
-            active_queries: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            executor: Arc::new(QueryExecutor::new(storage.clone())),
+            storage,
+            active_queries: Arc::new(Mutex::new(HashMap::new())),
+            noreply_tasks: Arc::new(Mutex::new(JoinSet::new())),
+            noreply_errors: Arc::new(Mutex::new(Vec::new())),
+            user: None,
+            session: Arc::new(Mutex::new(ReadSession::default())),
         }
     }
 
+    /// Run queries on `executor` instead of a plain one over the storage
+    ///
+    /// A clustered executor replicates writes, so their tokens are the ones
+    /// `single` and `majority` reads wait for (see [`ReadSession`]).
+    pub fn with_executor(mut self, executor: Arc<QueryExecutor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    /// Attach the user authenticated by the transport (e.g. a TLS client certificate)
+    pub fn with_user(mut self, user: User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Get the authenticated user, if any
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    /// Connect to a server as a client
+    ///
+    /// Performs the client handshake, sending `options.auth_key`, and returns
+    /// a handle for running queries.
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        options: ConnectOptions,
+    ) -> Result<ClientConnection> {
+        ClientConnection::open(addr, options).await
+    }
+
     /// Get protocol version
     pub fn version(&self) -> ProtocolVersion {
         self.handshake.version
@@ -63,6 +175,11 @@ impl Connection {
         self.handshake.protocol
     }
 
+    /// Whether the negotiated version lets the client run queries concurrently
+    pub fn supports_parallel_queries(&self) -> bool {
+        self.handshake.version.supports_parallel_queries()
+    }
+
     /// Check if connection is authenticated
     pub fn is_authenticated(&self) -> bool {
         self.handshake.auth_key.is_some()
@@ -74,14 +191,50 @@ impl Connection {
     }
 
     /// Handle a single query
-    pub async fn handle_query(&self, query: QueryMessage) -> Result<ResponseMessage> {
+    ///
+    /// Returns `None` when the query was sent with `noreply`, or was
+    /// cancelled by STOP, and no response must be written.
+    pub async fn handle_query(&self, query: QueryMessage) -> Result<Option<ResponseMessage>> {
+        let cancelled = self.register(&query).await;
+        self.handle_registered(query, cancelled).await
+    }
+
+    /// Make a START query cancellable by STOP before it runs
+    ///
+    /// Returns the receiver STOP signals, or `None` for other queries.
+    pub async fn register(&self, query: &QueryMessage) -> Option<oneshot::Receiver<()>> {
+        let start = Self::query_type(&query.query).ok() == Some("START");
+        if !start || Self::is_noreply(&query.query) {
+            return None;
+        }
+        let (cancel, cancelled) = oneshot::channel();
+        self.active_queries.lock().await.insert(query.token, cancel);
+        Some(cancelled)
+    }
+
+    /// Handle a query made cancellable by [`Connection::register`]
+    ///
+    /// The query runs under its own request id, see [`Self::request_id`].
+    pub async fn handle_registered(
+        &self,
+        query: QueryMessage,
+        cancelled: Option<oneshot::Receiver<()>>,
+    ) -> Result<Option<ResponseMessage>> {
+        let id = Self::request_id(&query.query);
+        let span = tracing::info_span!("query", request_id = %id, token = query.token);
+        request_id::scope(id, self.run_registered(query, cancelled))
+            .instrument(span)
+            .await
+    }
+
+    /// [`Self::handle_registered`] within the query's request id
+    async fn run_registered(
+        &self,
+        query: QueryMessage,
+        cancelled: Option<oneshot::Receiver<()>>,
+    ) -> Result<Option<ResponseMessage>> {
         let start = std::time::Instant::now();
-        let query_type = query
-            .query
-            .get("type")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow!("Missing query type"))?
-            .to_string();
+        let query_type = Self::query_type(&query.query)?;
         
         tracing::debug!(
             token = query.token,
@@ -89,8 +242,29 @@ impl Connection {
             "Processing query"
         );
 
-        let result = match query_type.as_str() {
-            "START" => self.handle_start_query(query).await,
+        if query_type == "START" && Self::is_noreply(&query.query) {
+            self.spawn_noreply(query).await;
+            return Ok(None);
+        }
+
+        let result = match query_type {
+            "START" => {
+                // A cancelled query is dropped before its response is
+                // written and gets none
+                let token = query.token;
+                let result = match cancelled {
+                    Some(cancelled) => tokio::select! {
+                        result = self.handle_start_query(query) => Some(result),
+                        Ok(()) = cancelled => None,
+                    },
+                    None => Some(self.handle_start_query(query).await),
+                };
+                self.active_queries.lock().await.remove(&token);
+                match result {
+                    Some(result) => result,
+                    None => return Ok(None),
+                }
+            }
             "CONTINUE" => self.handle_continue_query(query).await,
             "STOP" => self.handle_stop_query(query).await,
             "NOREPLY_WAIT" => self.handle_noreply_wait(query).await,
@@ -106,36 +280,251 @@ impl Connection {
             "Query completed"
         );
 
-        result
+        result.map(Some)
     }
 
-    /// Handle START query
-    async fn handle_start_query(&self, query: QueryMessage) -> Result<ResponseMessage> {
+    /// Extract the query type, accepting names or numeric wire ids
+    fn query_type(query: &serde_json::Value) -> Result<&'static str> {
+        let query_type = query
+            .get("type")
+            .ok_or_else(|| anyhow!("Missing query type"))?;
+
+        let name = match (query_type.as_str(), query_type.as_u64()) {
+            (Some("START"), _) | (_, Some(1)) => "START",
+            (Some("CONTINUE"), _) | (_, Some(2)) => "CONTINUE",
+            (Some("STOP"), _) | (_, Some(3)) => "STOP",
+            (Some("NOREPLY_WAIT"), _) | (_, Some(4)) => "NOREPLY_WAIT",
+            (Some("SERVER_INFO"), _) | (_, Some(5)) => "SERVER_INFO",
+            _ => return Err(anyhow!("Unknown query type: {}", query_type)),
+        };
+
+        Ok(name)
+    }
+
+    /// Request id of a query: the `request_id` global optarg if usable,
+    /// else a new one
+    fn request_id(query: &serde_json::Value) -> String {
+        request_id::accept(
+            query
+                .get("optargs")
+                .and_then(|o| o.get("request_id"))
+                .and_then(|v| v.as_str()),
+        )
+    }
+
+    /// Check the `noreply` global optarg
+    fn is_noreply(query: &serde_json::Value) -> bool {
+        Self::global_flag(query, "noreply")
+    }
+
+    /// Whether the boolean global optarg `name` is set
+    fn global_flag(query: &serde_json::Value, name: &str) -> bool {
+        query
+            .get("optargs")
+            .and_then(|o| o.get(name))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Mode named by the `read_mode` global optarg, if set
+    fn read_mode(query: &serde_json::Value) -> Result<Option<ReadMode>> {
+        let Some(mode) = query.get("optargs").and_then(|o| o.get("read_mode")) else {
+            return Ok(None);
+        };
+        let mode = mode.as_str().ok_or_else(|| {
+            Error::Compile("Global optarg `read_mode` must be a string".to_string())
+        })?;
+        Ok(Some(mode.parse().map_err(Error::Compile)?))
+    }
+
+    /// Read options of the connection, with the mode a query names taking
+    /// precedence over the connection's
+    pub async fn read_options(&self, query_mode: Option<ReadMode>) -> ReadOptions {
+        self.session.lock().await.read_options(query_mode)
+    }
+
+    /// Database named by the `db` global optarg, if set
+    ///
+    /// Drivers send a `DB` term, e.g. `[14, ["app"]]`; a plain string is
+    /// accepted as well.
+    fn default_db(query: &serde_json::Value) -> Result<Option<String>> {
+        let Some(db) = query.get("optargs").and_then(|o| o.get("db")) else {
+            return Ok(None);
+        };
+        if let Some(name) = db.as_str() {
+            return Ok(Some(name.to_string()));
+        }
+        let term = QueryCompiler::compile_ql2(db)
+            .map_err(|e| Error::Compile(format!("Invalid global optarg `db`: {:#}", e)))?;
+        match term
+            .arg(0)
+            .and_then(|name| name.as_datum())
+            .and_then(|name| name.as_string())
+        {
+            Some(name) if term.term_type == TermType::Db => Ok(Some(name.to_string())),
+            _ => Err(Error::Compile(
+                "Global optarg `db` must be a database name or a DB term".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    /// Execute a noreply query in the background
+    async fn spawn_noreply(&self, query: QueryMessage) {
+        let mut tasks = self.noreply_tasks.lock().await;
+
+        // Reap finished tasks so the set does not grow without bound
+        while let Some(finished) = tasks.try_join_next() {
+            self.record_noreply_result(finished).await;
+        }
+
+        let executor = self.executor.clone();
+        let user = self.user.clone();
+        let session = self.session.clone();
+        tasks.spawn(request_id::inherit(async move {
+            tracing::trace!(token = query.token, "Executing noreply query");
+            Self::execute_noreply(&executor, user.as_ref(), &session, &query.query).await
+        }));
+    }
+
+    /// Execute a noreply query, recording its writes in `session` like
+    /// [`Self::handle_start_query`] does
+    async fn execute_noreply(
+        executor: &QueryExecutor,
+        user: Option<&User>,
+        session: &Mutex<ReadSession>,
+        query: &serde_json::Value,
+    ) -> Result<()> {
+        let options = session.lock().await.read_options(Self::read_mode(query)?);
+        let (_, token) = Self::execute_start(executor, user, query, options).await?;
+        if let Some(token) = token {
+            session.lock().await.record_write(token);
+        }
+        Ok(())
+    }
+
+    /// Whether noreply queries are still running
+    async fn has_pending_noreply(&self) -> bool {
+        let mut tasks = self.noreply_tasks.lock().await;
+        while let Some(finished) = tasks.try_join_next() {
+            self.record_noreply_result(finished).await;
+        }
+        !tasks.is_empty()
+    }
+
+    /// Wait for every noreply query to finish, so none is aborted when the
+    /// connection closes
+    async fn drain_noreply(&self) {
+        let mut tasks = self.noreply_tasks.lock().await;
+        while let Some(finished) = tasks.join_next().await {
+            self.record_noreply_result(finished).await;
+        }
+    }
+
+    async fn record_noreply_result(&self, result: std::result::Result<Result<()>, tokio::task::JoinError>) {
+        let error = match result {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => format!("{:#}", e),
+            Err(e) => format!("Noreply query aborted: {}", e),
+        };
+        tracing::warn!(error = %error, "Noreply query failed");
+        self.noreply_errors.lock().await.push(error);
+    }
+
+    /// Compile and execute the term of a START query, as `user` if known,
+    /// routing its reads by `options`
+    ///
+    /// Returns the result and the token of the query's newest write, if it
+    /// wrote documents.
+    async fn execute_start(
+        executor: &QueryExecutor,
+        user: Option<&User>,
+        query: &serde_json::Value,
+        options: ReadOptions,
+    ) -> Result<(serde_json::Value, Option<WriteToken>)> {
         let query_term = query
-            .query
             .get("query")
             .ok_or_else(|| anyhow!("Missing query term"))?;
 
         // Compile JSON query to AST
         tracing::trace!("Compiling query to AST");
-        let ast_term = QueryCompiler::compile(query_term)
-            .map_err(|e| anyhow!("Query compilation failed: {}", e))?;
+        let ast_term = QueryCompiler::compile_ql2(query_term)
+            .map_err(|e| Error::Compile(format!("{:#}", e)))?;
+        let db = Self::default_db(query)?;
+        let db = db.as_deref().unwrap_or(DEFAULT_DB);
+
+        if let Some(user) = user {
+            AuthManager::authorize(user, &ast_term, db)?;
+        }
+
+        if Self::global_flag(query, "explain") {
+            let plan = executor
+                .explain_in(&ast_term, db)
+                .await
+                .context("Query explain failed")?;
+            return Ok((QueryCompiler::datum_to_json(&plan), None));
+        }
 
         // Execute query through executor
-        tracing::trace!(term_type = ?ast_term.term_type, "Executing query");
-        let result = self.executor.execute(&ast_term).await
-            .map_err(|e| anyhow!("Query execution failed: {}", e))?;
+        tracing::trace!(term_type = ?ast_term.term_type, db = %db, "Executing query");
+        let (result, token) = if Self::global_flag(query, "atomic") {
+            Self::execute_atomic(executor, &ast_term, db, options).await
+        } else {
+            executor.execute_with(&ast_term, db, options).await
+        }
+        .context("Query execution failed")?;
+
+        Ok((QueryCompiler::datum_to_json(&result), token))
+    }
+
+    /// Execute a query with the `atomic` global optarg set
+    ///
+    /// The writes of an array of terms, e.g. `r.expr([insert, update])`,
+    /// commit together or not at all, and the array of their results is
+    /// returned; any other term runs as a transaction of its own.
+    async fn execute_atomic(
+        executor: &QueryExecutor,
+        term: &Term,
+        db: &str,
+        options: ReadOptions,
+    ) -> Result<(Datum, Option<WriteToken>)> {
+        if term.term_type == TermType::MakeArray {
+            let (results, token) = executor.execute_atomic_with(&term.args, db, options).await?;
+            return Ok((Datum::Array(results), token));
+        }
+        let (mut results, token) = executor
+            .execute_atomic_with(std::slice::from_ref(term), db, options)
+            .await?;
+        Ok((results.pop().unwrap_or(Datum::Null), token))
+    }
+
+    /// Handle START query
+    ///
+    /// Reads run with the connection's read options, so `single` and
+    /// `majority` GETs see the client's last write. The response to a write
+    /// carries its `write_token`, which is recorded as the client's last
+    /// write.
+    async fn handle_start_query(&self, query: QueryMessage) -> Result<ResponseMessage> {
+        let read_mode = Self::read_mode(&query.query)?;
+        let options = self.read_options(read_mode).await;
+        tracing::trace!(read_mode = ?options.mode, after = ?options.after, "Read options");
+        let (result_json, token) =
+            Self::execute_start(&self.executor, self.user.as_ref(), &query.query, options)
+                .await?;
 
-        // Convert result back to JSON
-        let result_json = QueryCompiler::datum_to_json(&result);
         tracing::trace!("Query executed successfully, returning result");
 
+        let mut response = serde_json::json!({
+            "t": 1, // SUCCESS_ATOM
+            "r": [result_json]
+        });
+        if let Some(token) = token {
+            self.session.lock().await.record_write(token);
+            response["write_token"] = token.0.into();
+        }
         Ok(ResponseMessage {
             token: query.token,
-            response: serde_json::json!({
-                "t": 1, // SUCCESS_ATOM
-                "r": [result_json]
-            }),
+            response,
         })
     }
 
@@ -151,13 +540,17 @@ impl Connection {
         })
     }
 
+    /// Cancel the running START query with `token`, if any
+    pub async fn cancel(&self, token: i64) {
+        if let Some(cancel) = self.active_queries.lock().await.remove(&token) {
+            let _ = cancel.send(());
+            tracing::debug!("Cancelled query token={}", token);
+        }
+    }
+
     /// Handle STOP query (cancel ongoing query)
     async fn handle_stop_query(&self, query: QueryMessage) -> Result<ResponseMessage> {
-        let mut queries = self.active_queries.lock().await;
-        if let Some(cancel_tx) = queries.remove(&query.token) {
-            let _ = cancel_tx.send(());
-            tracing::debug!("Cancelled query token={}", query.token);
-        }
+        self.cancel(query.token).await;
 
         Ok(ResponseMessage {
             token: query.token,
@@ -169,8 +562,26 @@ impl Connection {
     }
 
     /// Handle NOREPLY_WAIT (wait for all noreply queries to complete)
+    ///
+    /// Storage is flushed before replying, so the client can rely on every
+    /// write it has been acknowledged being durable.
     async fn handle_noreply_wait(&self, query: QueryMessage) -> Result<ResponseMessage> {
-        // TODO: Track noreply queries
+        self.drain_noreply().await;
+
+        self.storage
+            .flush()
+            .await
+            .map_err(|e| anyhow!("Failed to flush storage: {}", e))?;
+
+        let errors = std::mem::take(&mut *self.noreply_errors.lock().await);
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "{} noreply queries failed: {}",
+                errors.len(),
+                errors.join("; ")
+            ));
+        }
+
         Ok(ResponseMessage {
             token: query.token,
             response: serde_json::json!({
@@ -180,6 +591,38 @@ impl Connection {
         })
     }
 
+    /// ReQL error type reported for a failed query
+    ///
+    /// Errors carrying a [`crate::error::Error`] report its code; anything
+    /// else is an internal error.
+    pub fn error_type(error: &anyhow::Error) -> u64 {
+        let code = if error.downcast_ref::<PermissionDenied>().is_some() {
+            ErrorCode::Permission
+        } else {
+            error::find(error).map_or(ErrorCode::Internal, Error::code)
+        };
+        code.as_u64()
+    }
+
+    /// Response type reported for a failed query
+    pub fn response_type(error: &anyhow::Error) -> u64 {
+        error::find(error).map_or(error::RUNTIME_ERROR, Error::response_type)
+    }
+
+    /// Error response to the query `token` failing with `error`
+    pub fn error_response(token: i64, error: &anyhow::Error) -> ResponseMessage {
+        ResponseMessage {
+            token,
+            response: serde_json::json!({
+                "t": Self::response_type(error),
+                "r": [],
+                "e": Self::error_type(error),
+                "b": [],
+                "m": format!("{:#}", error)
+            }),
+        }
+    }
+
     /// Handle SERVER_INFO query
     async fn handle_server_info(&self, query: QueryMessage) -> Result<ResponseMessage> {
         Ok(ResponseMessage {
@@ -196,14 +639,85 @@ impl Connection {
     }
 }
 
+/// Default bound on in-flight queries per connection
+pub const DEFAULT_MAX_PARALLEL_QUERIES: usize = 64;
+
 /// Connection handler for TCP streams
+///
+/// Clients that negotiated V0_4 or later may pipeline queries: each one runs
+/// as its own task and responses are written as soon as they are ready, so
+/// they can arrive out of order (matched by token). Older clients are served
+/// one query at a time.
 pub struct ConnectionHandler {
     storage: Arc<Storage>,
+    /// Shared by all connections when set; each gets its own otherwise
+    executor: Option<Arc<QueryExecutor>>,
+    max_parallel_queries: usize,
+    idle_timeout: Option<Duration>,
+    auth: Option<Arc<AuthManager>>,
+    shutdown: Option<watch::Receiver<bool>>,
 }
 
 impl ConnectionHandler {
     pub fn new(storage: Arc<Storage>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            executor: None,
+            max_parallel_queries: DEFAULT_MAX_PARALLEL_QUERIES,
+            idle_timeout: None,
+            auth: None,
+            shutdown: None,
+        }
+    }
+
+    /// Require clients to authenticate against `auth` during the handshake
+    ///
+    /// Their queries then run with the permissions of the authenticated user.
+    pub fn with_auth_manager(mut self, auth: Arc<AuthManager>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Run the queries of every connection on `executor`
+    pub fn with_executor(mut self, executor: Arc<QueryExecutor>) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Limit the number of queries a connection may run at once
+    ///
+    /// When the limit is reached the handler stops reading from the client
+    /// until a query finishes; a STOP already sent still cancels its query.
+    pub fn with_max_parallel_queries(mut self, max_parallel_queries: usize) -> Self {
+        self.max_parallel_queries = max_parallel_queries.max(1);
+        self
+    }
+
+    /// Close connections after `idle_timeout` without a query
+    ///
+    /// Queries still running then are finished and answered first.
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Close connections once `shutdown` is set
+    ///
+    /// Like idle ones, they stop reading queries and answer the running ones
+    /// before closing.
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Resolve once `shutdown` is set; never without a shutdown channel
+    async fn stopping(shutdown: &mut Option<watch::Receiver<bool>>) {
+        if let Some(shutdown) = shutdown {
+            if shutdown.wait_for(|stopping| *stopping).await.is_ok() {
+                return;
+            }
+        }
+        std::future::pending().await
     }
 
     /// Handle a new TCP connection
@@ -212,70 +726,199 @@ impl ConnectionHandler {
         tracing::info!("New connection from {}", peer_addr);
 
         // Perform handshake
-        let handshake = match Handshake::accept(&mut stream).await {
-            Ok(h) => h,
+        let accepted = match &self.auth {
+            Some(auth) => Handshake::accept_authenticated(&mut stream, auth)
+                .await
+                .map(|(handshake, user)| (handshake, Some(user))),
+            None => Handshake::accept(&mut stream).await.map(|h| (h, None)),
+        };
+        let (handshake, user) = match accepted {
+            Ok(accepted) => accepted,
             Err(e) => {
                 tracing::error!("Handshake failed from {}: {}", peer_addr, e);
+                record_connection_error(ConnectionErrorReason::HandshakeFailed);
                 return Err(e);
             }
         };
 
         // Create connection state
-        let connection = Connection::new(handshake, self.storage.clone());
+        let mut connection = Connection::new(handshake, self.storage.clone());
+        if let Some(executor) = &self.executor {
+            connection = connection.with_executor(executor.clone());
+        }
+        if let Some(user) = user {
+            tracing::info!("Client {} authenticated as {}", peer_addr, user.username);
+            connection = connection.with_user(user);
+        }
+        let connection = Arc::new(connection);
         tracing::info!("Connection established from {} (authenticated: {})", 
             peer_addr, connection.is_authenticated());
 
+        let parallel = connection.supports_parallel_queries();
+        let (mut reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        // Messages are read by their own task, so giving up on a read when
+        // the idle timeout expires never drops half of a message. STOP
+        // cancels its query as soon as it is read, even while the loop
+        // below waits for a free query slot.
+        let (received, mut incoming) = mpsc::channel(1);
+        let reader_connection = connection.clone();
+        let reading = tokio::spawn(async move {
+            loop {
+                let read = match read_query(&mut reader).await {
+                    Ok(query) => {
+                        if Connection::query_type(&query.query).ok() == Some("STOP") {
+                            reader_connection.cancel(query.token).await;
+                        }
+                        // Registered in message order, so a STOP following
+                        // its query always finds it
+                        let cancelled = reader_connection.register(&query).await;
+                        Ok((query, cancelled))
+                    }
+                    Err(e) => Err(e),
+                };
+                let failed = read.is_err();
+                if received.send(read).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let slots = Arc::new(Semaphore::new(self.max_parallel_queries));
+        let mut in_flight = JoinSet::new();
+
         // Query/response loop
+        let mut shutdown = self.shutdown.clone();
+        let mut idle = false;
+        let mut closing = false;
         loop {
-            match read_query(&mut stream).await {
-                Ok(query) => {
-                    let token = query.token;
-                    match connection.handle_query(query).await {
-                        Ok(response) => {
-                            if let Err(e) = write_response(&mut stream, &response).await {
-                                tracing::error!("Failed to write response: {}", e);
-                                break;
-                            }
+            let next = async {
+                match self.idle_timeout {
+                    Some(timeout) => loop {
+                        if let Ok(read) = tokio::time::timeout(timeout, incoming.recv()).await {
+                            break read;
                         }
-                        Err(e) => {
-                            tracing::error!("Query execution error: {}", e);
-                            // Send error response
-                            let error_response = ResponseMessage {
-                                token,
-                                response: serde_json::json!({
-                                    "t": 18, // RUNTIME_ERROR
-                                    "r": [],
-                                    "e": 1000000, // INTERNAL
-                                    "b": [],
-                                    "m": e.to_string()
-                                }),
-                            };
-                            if let Err(e) = write_response(&mut stream, &error_response).await {
-                                tracing::error!("Failed to write error response: {}", e);
-                                break;
-                            }
+                        // Long-running queries keep the connection open,
+                        // noreply ones included
+                        while in_flight.try_join_next().is_some() {}
+                        if in_flight.is_empty() && !connection.has_pending_noreply().await {
+                            idle = true;
+                            break None;
                         }
-                    }
+                    },
+                    None => incoming.recv().await,
                 }
+            };
+            let read = tokio::select! {
+                read = next => read,
+                _ = Self::stopping(&mut shutdown) => {
+                    closing = true;
+                    None
+                }
+            };
+            let Some(read) = read else {
+                if idle {
+                    tracing::info!("Closing idle connection from {}", peer_addr);
+                } else if closing {
+                    tracing::info!("Closing connection from {} for shutdown", peer_addr);
+                }
+                break;
+            };
+            let (query, cancelled) = match read {
+                Ok(read) => read,
                 Err(e) => {
-                    if e.to_string().contains("UnexpectedEof") {
+                    let disconnected = e
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof);
+                    if disconnected {
                         tracing::info!("Client disconnected: {}", peer_addr);
                     } else {
                         tracing::error!("Failed to read query: {}", e);
+                        record_connection_error(ConnectionErrorReason::IoError);
                     }
                     break;
                 }
+            };
+
+            if !parallel {
+                if Self::respond(&connection, &writer, query, cancelled)
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                continue;
             }
+
+            // A failed write means the client is gone
+            let mut write_failed = false;
+            while let Some(finished) = in_flight.try_join_next() {
+                write_failed |= matches!(finished, Ok(Err(_)));
+            }
+            if write_failed {
+                break;
+            }
+
+            // STOP only answers, so it never waits for a slot
+            let permit = match Connection::query_type(&query.query) {
+                Ok("STOP") => None,
+                _ => Some(slots.clone().acquire_owned().await?),
+            };
+            let connection = connection.clone();
+            let writer = writer.clone();
+            in_flight.spawn(async move {
+                let _permit = permit;
+                Self::respond(&connection, &writer, query, cancelled).await
+            });
+        }
+
+        reading.abort();
+        if idle || closing {
+            // The client is still there to read the results
+            while in_flight.join_next().await.is_some() {}
+        } else {
+            // Nobody is left to read the results
+            in_flight.shutdown().await;
         }
+        // Noreply writes were acknowledged by sending them, so they finish
+        // even when the client is gone
+        connection.drain_noreply().await;
 
         tracing::info!("Connection closed from {}", peer_addr);
         Ok(())
     }
+
+    /// Run a query and write its response, failing only if the write fails
+    async fn respond(
+        connection: &Connection,
+        writer: &Mutex<OwnedWriteHalf>,
+        query: QueryMessage,
+        cancelled: Option<oneshot::Receiver<()>>,
+    ) -> Result<()> {
+        let token = query.token;
+        let response = match connection.handle_registered(query, cancelled).await {
+            Ok(None) => return Ok(()),
+            Ok(Some(response)) => response,
+            Err(e) => {
+                tracing::error!("Query execution error: {:#}", e);
+                Connection::error_response(token, &e)
+            }
+        };
+
+        let mut writer = writer.lock().await;
+        if let Err(e) = write_response(&mut *writer, &response).await {
+            tracing::error!("Failed to write response: {}", e);
+            record_connection_error(ConnectionErrorReason::IoError);
+            return Err(e);
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::auth::{Permission, Scope};
+    use crate::plugin::{AuthProvider, Plugin, PluginCapability, PluginMetadata};
 
     #[tokio::test]
     async fn test_connection_creation() {
@@ -317,8 +960,624 @@ mod tests {
             }),
         };
 
-        let response = conn.handle_query(query).await.unwrap();
+        let response = conn.handle_query(query).await.unwrap().unwrap();
         assert_eq!(response.token, 1);
         assert_eq!(response.response["t"], 4); // SERVER_INFO
     }
+
+    #[tokio::test]
+    async fn test_explain_flag_does_not_execute() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage.clone());
+
+        let query = QueryMessage {
+            token: 1,
+            query: serde_json::json!({
+                "type": 1,
+                "query": [56, [[15, ["users"]], {"id": "a"}]],
+                "optargs": {"explain": true}
+            }),
+        };
+        let response = conn.handle_query(query).await.unwrap().unwrap();
+        assert_eq!(response.response["t"], 1);
+        assert_eq!(response.response["r"][0]["writes"], true);
+        assert_eq!(response.response["r"][0]["plan"]["term"], "INSERT");
+        assert!(storage
+            .scan_table("test", "users")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_atomic_global_optarg() {
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage.clone());
+        let run = |term: serde_json::Value| {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term, "optargs": {"atomic": true} }),
+            };
+            conn.handle_query(query)
+        };
+        let insert = |id: &str| serde_json::json!([56, [[15, ["users"]], {"id": id}]]);
+
+        // Both inserts commit, each with its result
+        let response = run(serde_json::json!([2, [insert("a"), insert("b")]]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.response["r"][0][1]["inserted"], 1.0);
+        assert_eq!(storage.scan_table("test", "users").await.unwrap().len(), 2);
+
+        // The second insert conflicts, so the first is not written either
+        let err = run(serde_json::json!([2, [insert("c"), insert("a")]]))
+            .await
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Transaction aborted"),
+            "{:#}",
+            err
+        );
+        assert_eq!(storage.scan_table("test", "users").await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_db_global_optarg() {
+        let storage = Arc::new(Storage::in_memory());
+        for db in ["test", "app"] {
+            storage.create_database(db).await.unwrap();
+            storage.create_table(db, "users", "id").await.unwrap();
+        }
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage.clone()).with_user(User {
+            username: "bob".to_string(),
+            password_hash: String::new(),
+            permissions: vec![
+                Permission::Read(Scope::Database("app".to_string())),
+                Permission::Write(Scope::Database("app".to_string())),
+            ],
+        });
+        let run = |term: serde_json::Value, db: serde_json::Value| {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term, "optargs": {"db": db} }),
+            };
+            conn.handle_query(query)
+        };
+
+        // An unqualified TABLE resolves in the `db` optarg's database, and
+        // is authorized there
+        let insert = serde_json::json!([56, [[15, ["users"]], {"id": "a"}]]);
+        run(insert, serde_json::json!([14, ["app"]])).await.unwrap();
+        assert_eq!(storage.scan_table("app", "users").await.unwrap().len(), 1);
+        assert!(storage
+            .scan_table("test", "users")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let response = run(serde_json::json!([15, ["users"]]), serde_json::json!("app"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.response["r"][0][0]["id"], "a");
+
+        // A DB of the table's own still takes precedence
+        let qualified = serde_json::json!([15, [[14, ["test"]], "users"]]);
+        let denied = run(qualified, serde_json::json!([14, ["app"]]))
+            .await
+            .unwrap_err();
+        assert_eq!(Connection::error_type(&denied), 6000000);
+
+        let invalid = run(
+            serde_json::json!([15, ["users"]]),
+            serde_json::json!([24, [1, 2]]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(Connection::response_type(&invalid), error::COMPILE_ERROR);
+    }
+
+    #[test]
+    fn test_request_id_global_optarg() {
+        let query = |optargs: serde_json::Value| serde_json::json!({"type": "START", "query": [59, []], "optargs": optargs});
+        assert_eq!(
+            Connection::request_id(&query(serde_json::json!({"request_id": "job-42"}))),
+            "job-42"
+        );
+
+        // Each query without a usable id gets a new one
+        let first = Connection::request_id(&query(serde_json::json!({})));
+        let second = Connection::request_id(&query(serde_json::json!({"request_id": "a b"})));
+        assert!(uuid::Uuid::parse_str(&first).is_ok());
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_read_mode_and_write_tokens() {
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage);
+        let run = |term: serde_json::Value, optargs: serde_json::Value| {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term, "optargs": optargs }),
+            };
+            conn.handle_query(query)
+        };
+
+        // Writes answer with a token the session records
+        let insert = serde_json::json!([56, [[15, ["users"]], {"id": "a"}]]);
+        let first = run(insert, serde_json::json!({})).await.unwrap().unwrap();
+        let first = first.response["write_token"].as_u64().unwrap();
+        let insert = serde_json::json!([56, [[15, ["users"]], {"id": "b"}]]);
+        let second = run(insert, serde_json::json!({})).await.unwrap().unwrap();
+        let second = second.response["write_token"].as_u64().unwrap();
+        assert!(second >= first);
+        let options = conn.read_options(None).await;
+        assert_eq!(options.after, Some(WriteToken(second)));
+
+        // Reads take their mode from the optarg and carry no token
+        let read = serde_json::json!([15, ["users"]]);
+        let response = run(read.clone(), serde_json::json!({"read_mode": "majority"}))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.response.get("write_token").is_none());
+        let invalid = run(read, serde_json::json!({"read_mode": "eventually"}))
+            .await
+            .unwrap_err();
+        assert_eq!(Connection::response_type(&invalid), error::COMPILE_ERROR);
+        assert!(format!("{:#}", invalid).contains("Invalid read_mode"));
+    }
+
+    /// Replica answering reads with what it was sent, or with `stale` and
+    /// rejecting every write when it lags
+    async fn spawn_replica(stale: Option<serde_json::Value>) -> std::net::SocketAddr {
+        use axum::{http::StatusCode, routing::post, Json};
+        use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+        let lagging = stale.is_some();
+        let stored = Arc::new(std::sync::Mutex::new(stale.map(|doc| {
+            serde_json::json!({ "data": BASE64.encode(doc.to_string()), "version": 1 })
+        })));
+        let read = stored.clone();
+        let app = axum::Router::new()
+            .route(
+                "/internal/read",
+                post(move || {
+                    let value = read.lock().unwrap().clone();
+                    async move { value.map(Json).ok_or(StatusCode::NOT_FOUND) }
+                }),
+            )
+            .route(
+                "/internal/replicate",
+                post(move |Json(body): Json<serde_json::Value>| async move {
+                    if lagging {
+                        return StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    *stored.lock().unwrap() = Some(body);
+                    StatusCode::OK
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_reads_skip_lagging_replica() {
+        use crate::cluster::{
+            version_key, ClusterState, Node, NodeRole, ReplicationConfig, ReplicationManager,
+        };
+
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let cluster = Arc::new(ClusterState::new(
+            "local".to_string(),
+            ReplicationConfig {
+                replica_count: 2,
+                write_quorum: 1,
+                write_retries: 0,
+                ..Default::default()
+            },
+        ));
+        cluster.init_as_master().await;
+        let stale = serde_json::json!({"id": "a", "v": 1});
+        for (id, addr) in [
+            ("current", spawn_replica(None).await),
+            ("lagging", spawn_replica(Some(stale)).await),
+        ] {
+            cluster
+                .add_node(Node {
+                    id: id.to_string(),
+                    addr,
+                    role: NodeRole::Replica,
+                    shard_range: None,
+                    last_heartbeat: chrono::Utc::now(),
+                })
+                .await;
+        }
+        let executor = QueryExecutor::new(storage.clone())
+            .with_cluster(cluster.clone())
+            .with_replication(ReplicationManager::new(cluster.clone()));
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage.clone()).with_executor(Arc::new(executor));
+        let run = |term: serde_json::Value, optargs: serde_json::Value| {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term, "optargs": optargs }),
+            };
+            conn.handle_query(query)
+        };
+
+        // The write is acknowledged by the current replica only, and its
+        // token is the one it was replicated with
+        let insert = serde_json::json!([56, [[15, ["users"]], {"id": "a", "v": 2}]]);
+        let response = run(insert, serde_json::json!({})).await.unwrap().unwrap();
+        let token = WriteToken(response.response["write_token"].as_u64().unwrap());
+        assert_eq!(conn.read_options(None).await.after, Some(token));
+        assert!(cluster.has_applied("current", token).await);
+        assert!(!cluster.has_applied("lagging", token).await);
+        let version = storage.get(&version_key(b"doc:test:users:a")).await.unwrap();
+        assert_eq!(version, Some(Datum::Integer(token.0 as i64)));
+
+        // Single reads only ask the replica that applied the write
+        let get = serde_json::json!([16, [[15, ["users"]], "a"]]);
+        for _ in 0..10 {
+            let response = run(get.clone(), serde_json::json!({"read_mode": "single"}))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(response.response["r"][0], serde_json::json!({"id": "a", "v": 2}));
+        }
+
+        // A majority of the shard's replicas has not seen the write yet
+        let majority = run(get, serde_json::json!({"read_mode": "majority"}))
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", majority).contains("Insufficient up-to-date replicas"));
+    }
+
+    #[tokio::test]
+    async fn test_driver_encoded_terms() {
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage);
+        let run = |term: serde_json::Value| {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term }),
+            };
+            let conn = &conn;
+            async move { conn.handle_query(query).await.unwrap().unwrap().response["r"][0].clone() }
+        };
+
+        // r.db("test").table("users").insert({id: "ann", age: 31})
+        let users = serde_json::json!([15, [[14, ["test"]], "users"]]);
+        let insert = serde_json::json!([56, [users.clone(), {"id": "ann", "age": 31}]]);
+        assert_eq!(run(insert).await["inserted"], 1.0);
+        assert_eq!(run(users.clone()).await[0]["id"], "ann");
+
+        // BRACKET is a field lookup for strings and NTH for numbers,
+        // including keys computed at runtime
+        let first = serde_json::json!([170, [users, 0]]);
+        assert_eq!(run(serde_json::json!([170, [first, "age"]])).await, 31);
+        assert_eq!(run(serde_json::json!([170, [[2, [10, 20]], 1]])).await, 20);
+        assert_eq!(run(serde_json::json!([170, [[2, [10, 20]], [24, [0, 1]]]])).await, 20);
+        let key = serde_json::json!([170, [{"key": "a"}, "key"]]);
+        assert_eq!(run(serde_json::json!([170, [{"a": 5}, key]])).await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_noreply_inserts_then_noreply_wait() {
+        use crate::storage::slab::SlabStorageEngine;
+
+        let temp_dir = std::env::temp_dir().join(format!("noreply_test_{}", std::process::id()));
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)
+            .unwrap()
+            .with_sync_writes(false);
+        let storage = Arc::new(Storage::new(Box::new(engine)));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage.clone());
+
+        for i in 0..5 {
+            let query = QueryMessage {
+                token: i,
+                query: serde_json::json!({
+                    "type": 1,
+                    // INSERT(TABLE("users"), {...})
+                    "query": [56, [[15, ["users"]], {"id": format!("user{}", i), "n": i}]],
+                    "optargs": {"noreply": true}
+                }),
+            };
+            assert!(conn.handle_query(query).await.unwrap().is_none());
+        }
+
+        let wait = QueryMessage {
+            token: 100,
+            query: serde_json::json!({ "type": 4 }),
+        };
+        let response = conn.handle_query(wait).await.unwrap().unwrap();
+        assert_eq!(response.token, 100);
+        assert_eq!(response.response["t"], 3); // WAIT_COMPLETE
+
+        let docs = storage.scan_table("test", "users").await.unwrap();
+        assert_eq!(docs.len(), 5);
+
+        // The wait flushed the deferred writes
+        drop(conn);
+        drop(storage);
+        let reopened = Storage::new(Box::new(SlabStorageEngine::with_defaults(&temp_dir).unwrap()));
+        assert_eq!(reopened.scan_table("test", "users").await.unwrap().len(), 5);
+
+        std::fs::remove_dir_all(temp_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_noreply_wait_reports_errors() {
+        use crate::storage::slab::SlabStorageEngine;
+
+        let temp_dir = std::env::temp_dir().join(format!("noreply_err_test_{}", std::process::id()));
+        let storage = Arc::new(Storage::new(Box::new(SlabStorageEngine::with_defaults(&temp_dir).unwrap())));
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage);
+
+        let query = QueryMessage {
+            token: 1,
+            query: serde_json::json!({
+                "type": "START",
+                "query": [999999],
+                "optargs": {"noreply": true}
+            }),
+        };
+        assert!(conn.handle_query(query).await.unwrap().is_none());
+
+        let wait = QueryMessage {
+            token: 2,
+            query: serde_json::json!({ "type": "NOREPLY_WAIT" }),
+        };
+        assert!(conn.handle_query(wait).await.is_err());
+
+        std::fs::remove_dir_all(temp_dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_error_codes_in_responses() {
+        let storage = Arc::new(Storage::in_memory());
+        let handshake = Handshake {
+            version: ProtocolVersion::V1_0,
+            protocol: WireProtocol::Json,
+            auth_key: None,
+        };
+        let conn = Connection::new(handshake, storage);
+        let error = |term: serde_json::Value| {
+            let conn = &conn;
+            async move {
+                let query = QueryMessage {
+                    token: 7,
+                    query: serde_json::json!({ "type": "START", "query": term }),
+                };
+                let e = conn.handle_query(query).await.unwrap_err();
+                Connection::error_response(7, &e).response
+            }
+        };
+
+        // Missing field: NON_EXISTENCE at runtime
+        let response = error(serde_json::json!([31, [{"a": 1}, "b"]])).await;
+        assert_eq!(response["t"], 18);
+        assert_eq!(response["e"], 3100000);
+        assert!(response["m"].as_str().unwrap().contains("No attribute `b`"));
+
+        // Creating a database twice: OP_FAILED
+        assert!(conn.handle_query(QueryMessage {
+            token: 1,
+            query: serde_json::json!({ "type": "START", "query": [57, ["app"]] }),
+        }).await.is_ok());
+        let response = error(serde_json::json!([57, ["app"]])).await;
+        assert_eq!(response["t"], 18);
+        assert_eq!(response["e"], 4100000);
+        assert!(response["m"].as_str().unwrap().contains("Database `app` already exists"));
+
+        // Unknown term: COMPILE_ERROR
+        let response = error(serde_json::json!([99999, []])).await;
+        assert_eq!(response["t"], 17);
+        assert_eq!(response["e"], 3000000);
+        assert!(response["m"].as_str().unwrap().contains("Unknown term type: 99999"));
+    }
+
+    #[tokio::test]
+    async fn test_permissions_enforced() {
+        let storage = Arc::new(Storage::in_memory());
+        for db in ["test", "app", "other"] {
+            storage.create_database(db).await.unwrap();
+            storage.create_table(db, "users", "id").await.unwrap();
+        }
+        let connect = |permissions| {
+            let handshake = Handshake {
+                version: ProtocolVersion::V1_0,
+                protocol: WireProtocol::Json,
+                auth_key: None,
+            };
+            Connection::new(handshake, storage.clone()).with_user(User {
+                username: "bob".to_string(),
+                password_hash: String::new(),
+                permissions,
+            })
+        };
+        async fn run(conn: &Connection, term: serde_json::Value) -> Result<ResponseMessage> {
+            let query = QueryMessage {
+                token: 1,
+                query: serde_json::json!({ "type": "START", "query": term }),
+            };
+            conn.handle_query(query).await.map(|r| r.unwrap())
+        }
+        let denied = |result: Result<ResponseMessage>| {
+            result.is_err_and(|e| Connection::error_type(&e) == 6000000)
+        };
+
+        // Read-only: TABLE("users") works, DELETE does not
+        let reader = connect(vec![Permission::Read(Scope::table("test", "users"))]);
+        let response = run(&reader, serde_json::json!([15, ["users"]])).await.unwrap();
+        assert_eq!(response.response["t"], 1);
+        assert!(denied(run(&reader, serde_json::json!([54, [[15, ["users"]]]])).await));
+
+        // Scoped to `app`: anything in `other` is denied
+        let scoped = connect(vec![
+            Permission::Read(Scope::Database("app".to_string())),
+            Permission::Write(Scope::Database("app".to_string())),
+        ]);
+        let insert = |db: &str| {
+            serde_json::json!([56, [[15, [[14, [db]], "users"]], {"id": "u1"}]])
+        };
+        assert!(run(&scoped, insert("app")).await.is_ok());
+        assert!(denied(run(&scoped, insert("other")).await));
+        assert!(denied(
+            run(&scoped, serde_json::json!([15, [[14, ["other"]], "users"]])).await
+        ));
+        assert!(denied(run(&scoped, serde_json::json!([15, ["users"]])).await));
+        assert_eq!(storage.scan_table("app", "users").await.unwrap().len(), 1);
+        assert!(storage.scan_table("other", "users").await.unwrap().is_empty());
+    }
+
+    /// Accepts `ldap-user` with password `hunter2`, who may connect and read
+    struct StaticAuthPlugin;
+
+    impl Plugin for StaticAuthPlugin {
+        fn metadata(&self) -> PluginMetadata {
+            PluginMetadata {
+                name: "static-auth".to_string(),
+                version: "1.0.0".to_string(),
+                abi_version: crate::plugin::PLUGIN_ABI_VERSION,
+                author: "test".to_string(),
+                description: "test auth provider".to_string(),
+                capabilities: vec![PluginCapability::Authentication],
+            }
+        }
+
+        fn execute(
+            &self,
+            function: &str,
+            _args: Vec<crate::reql::Datum>,
+        ) -> std::pin::Pin<
+            Box<dyn std::future::Future<Output = error::Result<crate::reql::Datum>> + Send + '_>,
+        > {
+            let function = function.to_string();
+            Box::pin(async move { Err(Error::Plugin(format!("Unknown function: {}", function))) })
+        }
+
+        fn auth_provider(&self) -> Option<&dyn AuthProvider> {
+            Some(self)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AuthProvider for StaticAuthPlugin {
+        async fn authenticate(&self, username: &str, password: &str) -> error::Result<bool> {
+            Ok(username == "ldap-user" && password == "hunter2")
+        }
+
+        async fn permissions(&self, _username: &str) -> error::Result<Vec<Permission>> {
+            Ok(vec![Permission::Connect, Permission::Read(Scope::Global)])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_authenticates_through_plugin() {
+        use crate::plugin::PluginManager;
+
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let plugins = Arc::new(PluginManager::new());
+        plugins.register_plugin(Arc::new(StaticAuthPlugin)).unwrap();
+        let auth = AuthManager::with_admin("admin-password").with_plugins(plugins.clone());
+        let handler = Arc::new(ConnectionHandler::new(storage).with_auth_manager(Arc::new(auth)));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move { handler.handle(stream).await });
+            }
+        });
+        let connect = |key: &str| {
+            let options = ConnectOptions {
+                auth_key: Some(key.to_string()),
+                ..Default::default()
+            };
+            Connection::connect(addr, options)
+        };
+
+        // The provider's user gets in, with the provider's permissions
+        let conn = connect("ldap-user:hunter2").await.unwrap();
+        assert!(conn.run(serde_json::json!([15, ["users"]])).await.is_ok());
+        let insert = serde_json::json!([56, [[15, ["users"]], {"id": "u1"}]]);
+        let err = conn.run(insert).await.unwrap_err();
+        assert!(err.to_string().contains("permission"), "{}", err);
+
+        // Wrong passwords are rejected, but built-in users still get in
+        let err = connect("ldap-user:wrong").await.unwrap_err();
+        assert!(err.to_string().contains("error code 4"), "{}", err);
+        assert!(connect("admin:admin-password").await.is_ok());
+
+        // Without a provider the built-in users apply again
+        plugins.unload_plugin("static-auth").await.unwrap();
+        assert!(connect("ldap-user:hunter2").await.is_err());
+        let conn = connect("admin-password").await.unwrap();
+        assert!(conn
+            .run(serde_json::json!([56, [[15, ["users"]], {"id": "u1"}]]))
+            .await
+            .is_ok());
+    }
 }
@@ -18,8 +18,10 @@
 //! - Connection pooling with max connection limits
 //! - Authentication: bcrypt password hashing + TLS certificates
 //! - Parallel query execution (V0_4+)
+//! - In-process client via [`Connection::connect`]
 
 pub mod auth;
+pub mod client;
 pub mod connection;
 pub mod protocol;
 pub mod server;
@@ -27,7 +29,8 @@ pub mod server;
 #[cfg(feature = "quic")]
 pub mod quic;
 
-pub use auth::{AuthManager, Permission, User};
+pub use auth::{AuthManager, Permission, PermissionDenied, Scope, User};
+pub use client::{ClientConnection, ConnectOptions, Cursor};
 pub use connection::{Connection, ConnectionHandler};
 pub use protocol::{
     Handshake, ProtocolVersion, QueryMessage, ResponseMessage, WireProtocol,
@@ -3,6 +3,7 @@
 //! Implements the RethinkDB client protocol with handshake and query/response cycles.
 //! Based on the original C++ implementation and Cap'n Proto schemas.
 
+use super::auth::{AuthManager, User};
 use anyhow::{anyhow, Result};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
@@ -17,6 +18,12 @@ pub const VERSION_V1_0: u32 = 0x34c2bdc3;
 pub const PROTOCOL_PROTOBUF: u32 = 0x271ffc41;
 pub const PROTOCOL_JSON: u32 = 0x7e6970c7;
 
+/// Handshake error codes, sent as `error_code` when a client is rejected
+pub const ERROR_UNSUPPORTED_VERSION: u32 = 1;
+pub const ERROR_UNSUPPORTED_PROTOCOL: u32 = 2;
+pub const ERROR_INVALID_AUTH_KEY: u32 = 3;
+pub const ERROR_AUTHENTICATION_FAILED: u32 = 4;
+
 /// Size limits
 pub const HARD_LIMIT_TOO_LARGE_QUERY_SIZE: u32 = 1024 * 1024 * 1024; // 1 GB
 pub const TOO_LARGE_QUERY_SIZE: u32 = 128 * 1024 * 1024; // 128 MB
@@ -24,7 +31,9 @@ pub const TOO_LONG_QUERY_TIME: u32 = 5 * 60 * 1000; // 5 minutes in ms
 pub const MAX_MESSAGE_SIZE: u32 = 256 * 1024 * 1024; // 256 MB
 
 /// Protocol version information
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Versions are ordered from oldest to newest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ProtocolVersion {
     V0_1,
     V0_2,
@@ -34,6 +43,11 @@ pub enum ProtocolVersion {
 }
 
 impl ProtocolVersion {
+    /// Oldest version the server accepts (the first with JSON)
+    pub const MIN_SUPPORTED: Self = ProtocolVersion::V0_3;
+    /// Newest version the server accepts
+    pub const MAX_SUPPORTED: Self = ProtocolVersion::V1_0;
+
     pub fn from_magic(magic: u32) -> Result<Self> {
         match magic {
             VERSION_V0_1 => Ok(ProtocolVersion::V0_1),
@@ -55,6 +69,22 @@ impl ProtocolVersion {
         }
     }
 
+    /// Version number advertised in the V1_0 handshake (`V0_4` is 4, `V1_0` is 10)
+    pub fn number(self) -> u32 {
+        match self {
+            ProtocolVersion::V0_1 => 1,
+            ProtocolVersion::V0_2 => 2,
+            ProtocolVersion::V0_3 => 3,
+            ProtocolVersion::V0_4 => 4,
+            ProtocolVersion::V1_0 => 10,
+        }
+    }
+
+    /// Whether the server accepts clients speaking this version
+    pub fn is_supported(self) -> bool {
+        (Self::MIN_SUPPORTED..=Self::MAX_SUPPORTED).contains(&self)
+    }
+
     pub fn supports_json(&self) -> bool {
         matches!(self, ProtocolVersion::V0_3 | ProtocolVersion::V0_4 | ProtocolVersion::V1_0)
     }
@@ -102,13 +132,57 @@ pub struct Handshake {
 
 impl Handshake {
     /// Perform server-side handshake
+    ///
+    /// Clients outside [`ProtocolVersion::MIN_SUPPORTED`] ..=
+    /// [`ProtocolVersion::MAX_SUPPORTED`], or asking for Protobuf, are sent
+    /// `{"success": false, "error": ..., "error_code": ...}` and rejected.
+    /// Auth keys are only kept for versions that support authentication.
     pub async fn accept<T>(stream: &mut T) -> Result<Self>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        Ok(Self::negotiate(stream, None).await?.0)
+    }
+
+    /// Perform server-side handshake, requiring the client to authenticate
+    ///
+    /// The auth key is checked with [`AuthManager::authenticate_handshake`];
+    /// clients it does not accept, or on versions without authentication,
+    /// are rejected like in [`accept`](Self::accept).
+    pub async fn accept_authenticated<T>(stream: &mut T, auth: &AuthManager) -> Result<(Self, User)>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (handshake, user) = Self::negotiate(stream, Some(auth)).await?;
+        let user = user.ok_or_else(|| anyhow!("Handshake did not authenticate the client"))?;
+        Ok((handshake, user))
+    }
+
+    /// Server-side handshake, authenticating the client when `auth` is given
+    async fn negotiate<T>(
+        stream: &mut T,
+        auth: Option<&AuthManager>,
+    ) -> Result<(Self, Option<User>)>
     where
         T: AsyncRead + AsyncWrite + Unpin,
     {
         // 1. Read version magic number (4 bytes, little-endian)
         let version_magic = stream.read_u32_le().await?;
-        let version = ProtocolVersion::from_magic(version_magic)?;
+        let version = match ProtocolVersion::from_magic(version_magic) {
+            Ok(version) if version.is_supported() => version,
+            Ok(version) => {
+                let message = format!(
+                    "Protocol version {:?} is not supported (supported: {:?} to {:?})",
+                    version,
+                    ProtocolVersion::MIN_SUPPORTED,
+                    ProtocolVersion::MAX_SUPPORTED
+                );
+                return Err(Self::reject(stream, ERROR_UNSUPPORTED_VERSION, message).await);
+            }
+            Err(e) => {
+                return Err(Self::reject(stream, ERROR_UNSUPPORTED_VERSION, e.to_string()).await)
+            }
+        };
 
         tracing::debug!(
             "Client protocol version: {:?} (0x{:x})",
@@ -116,44 +190,73 @@ impl Handshake {
             version_magic
         );
 
-        // 2. Read auth key (for V0_2 and later)
-        let auth_key = if version != ProtocolVersion::V0_1 {
-            let key_len = stream.read_u32_le().await?;
-            if key_len > 4096 {
-                return Err(anyhow!("Auth key too long: {} bytes", key_len));
-            }
+        // 2. Read auth key (every supported version sends one)
+        let key_len = stream.read_u32_le().await?;
+        if key_len > 4096 {
+            let message = format!("Auth key too long: {} bytes", key_len);
+            return Err(Self::reject(stream, ERROR_INVALID_AUTH_KEY, message).await);
+        }
 
-            let mut key_bytes = vec![0u8; key_len as usize];
-            stream.read_exact(&mut key_bytes).await?;
-            
-            // Remove null terminator if present
-            if key_bytes.last() == Some(&0) {
-                key_bytes.pop();
+        let mut key_bytes = vec![0u8; key_len as usize];
+        stream.read_exact(&mut key_bytes).await?;
+
+        // Remove null terminator if present
+        if key_bytes.last() == Some(&0) {
+            key_bytes.pop();
+        }
+
+        let auth_key = match String::from_utf8(key_bytes) {
+            Ok(key) if version.supports_auth() => Some(key),
+            Ok(_) => None,
+            Err(_) => {
+                let message = "Auth key is not valid UTF-8".to_string();
+                return Err(Self::reject(stream, ERROR_INVALID_AUTH_KEY, message).await);
             }
-            
-            Some(String::from_utf8(key_bytes)?)
-        } else {
-            None
         };
 
-        // 3. Read protocol type (for V0_3 and later)
-        let protocol = if version.supports_json() {
-            let protocol_magic = stream.read_u32_le().await?;
-            WireProtocol::from_magic(protocol_magic)?
-        } else {
-            // V0_1 and V0_2 only supported Protobuf (now deprecated)
-            return Err(anyhow!("PROTOBUF protocol is no longer supported"));
+        // 3. Read protocol type
+        let protocol_magic = stream.read_u32_le().await?;
+        let protocol = match WireProtocol::from_magic(protocol_magic) {
+            Ok(WireProtocol::Json) => WireProtocol::Json,
+            Ok(WireProtocol::Protobuf) => {
+                let message = "PROTOBUF protocol is no longer supported".to_string();
+                return Err(Self::reject(stream, ERROR_UNSUPPORTED_PROTOCOL, message).await);
+            }
+            Err(e) => {
+                return Err(Self::reject(stream, ERROR_UNSUPPORTED_PROTOCOL, e.to_string()).await)
+            }
         };
 
         tracing::debug!("Client wire protocol: {:?}", protocol);
 
-        // 4. Send success response
+        // 4. Authenticate the auth key, if required
+        let user = match (auth, &auth_key) {
+            (None, _) => None,
+            (Some(auth), Some(key)) => match auth.authenticate_handshake(key).await {
+                Ok(user) => Some(user),
+                Err(e) => {
+                    return Err(
+                        Self::reject(stream, ERROR_AUTHENTICATION_FAILED, e.to_string()).await,
+                    )
+                }
+            },
+            (Some(_), None) => {
+                let message = format!(
+                    "Protocol version {:?} does not support authentication",
+                    version
+                );
+                return Err(Self::reject(stream, ERROR_AUTHENTICATION_FAILED, message).await);
+            }
+        };
+
+        // 5. Send success response
         let success_msg = if version == ProtocolVersion::V1_0 {
             // V1_0 sends JSON response with version info
             serde_json::json!({
                 "success": true,
-                "min_protocol_version": 0,
-                "max_protocol_version": 0,
+                "min_protocol_version": ProtocolVersion::MIN_SUPPORTED.number(),
+                "max_protocol_version": ProtocolVersion::MAX_SUPPORTED.number(),
+                "protocol_version": version.number(),
                 "server_version": env!("CARGO_PKG_VERSION")
             })
             .to_string()
@@ -167,11 +270,38 @@ impl Handshake {
 
         tracing::info!("Handshake complete: {:?} / {:?}", version, protocol);
 
-        Ok(Handshake {
+        let handshake = Handshake {
             version,
             protocol,
             auth_key,
+        };
+        Ok((handshake, user))
+    }
+
+    /// Send a handshake error to the client and return it as an error
+    async fn reject<T>(stream: &mut T, error_code: u32, message: String) -> anyhow::Error
+    where
+        T: AsyncWrite + Unpin,
+    {
+        tracing::warn!(error_code, "Rejecting handshake: {}", message);
+
+        let response = serde_json::json!({
+            "success": false,
+            "error": message,
+            "error_code": error_code,
         })
+        .to_string();
+        // The client may already be gone; the handshake fails either way
+        let sent = async {
+            stream.write_all(response.as_bytes()).await?;
+            stream.write_all(b"\0").await?;
+            stream.flush().await
+        };
+        if let Err(e) = sent.await {
+            tracing::debug!("Failed to send handshake error: {}", e);
+        }
+
+        anyhow!("Handshake rejected: {}", message)
     }
 
     /// Perform client-side handshake
@@ -216,11 +346,17 @@ impl Handshake {
 
         let response_str = String::from_utf8(response)?;
 
-        if version == ProtocolVersion::V1_0 {
-            // Parse JSON response
-            let response_json: serde_json::Value = serde_json::from_str(&response_str)?;
+        // Rejections are JSON whatever the version
+        if let Ok(response_json) = serde_json::from_str::<serde_json::Value>(&response_str) {
             if response_json.get("success") != Some(&serde_json::Value::Bool(true)) {
-                return Err(anyhow!("Handshake failed: {}", response_str));
+                return match response_json.get("error").and_then(|e| e.as_str()) {
+                    Some(error) => Err(anyhow!(
+                        "Handshake failed: {} (error code {})",
+                        error,
+                        response_json["error_code"]
+                    )),
+                    None => Err(anyhow!("Handshake failed: {}", response_str)),
+                };
             }
         } else if response_str != "SUCCESS" {
             return Err(anyhow!("Handshake failed: {}", response_str));
@@ -421,4 +557,82 @@ mod tests {
         assert_eq!(decoded.token, msg.token);
         assert_eq!(decoded.response, msg.response);
     }
+
+    /// Client half of a handshake as raw bytes
+    fn client_hello(version_magic: u32, auth_key: &str) -> Vec<u8> {
+        let mut hello = version_magic.to_le_bytes().to_vec();
+        hello.extend_from_slice(&(auth_key.len() as u32).to_le_bytes());
+        hello.extend_from_slice(auth_key.as_bytes());
+        hello.extend_from_slice(&PROTOCOL_JSON.to_le_bytes());
+        hello
+    }
+
+    /// Run the server handshake against `hello`, returning its result and
+    /// the NUL-terminated reply
+    async fn accept_hello(hello: Vec<u8>) -> (Result<Handshake>, String) {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client.write_all(&hello).await.unwrap();
+        let result = Handshake::accept(&mut server).await;
+        drop(server);
+
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply.pop(), Some(0));
+        (result, String::from_utf8(reply).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_negotiates_supported_version() {
+        let (result, reply) = accept_hello(client_hello(VERSION_V1_0, "secret")).await;
+        let handshake = result.unwrap();
+        assert_eq!(handshake.version, ProtocolVersion::V1_0);
+        assert_eq!(handshake.auth_key.as_deref(), Some("secret"));
+
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["success"], true);
+        assert_eq!(reply["min_protocol_version"], ProtocolVersion::MIN_SUPPORTED.number());
+        assert_eq!(reply["max_protocol_version"], ProtocolVersion::MAX_SUPPORTED.number());
+        assert_eq!(reply["protocol_version"], 10);
+
+        // Older versions get the legacy reply and no authentication
+        let (result, reply) = accept_hello(client_hello(VERSION_V0_4, "secret")).await;
+        let handshake = result.unwrap();
+        assert_eq!(handshake.version, ProtocolVersion::V0_4);
+        assert!(handshake.version.supports_parallel_queries());
+        assert_eq!(handshake.auth_key, None);
+        assert_eq!(reply, "SUCCESS");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_unsupported_version() {
+        for magic in [VERSION_V0_1, 0xdead_beef] {
+            let (result, reply) = accept_hello(client_hello(magic, "")).await;
+            assert!(result.is_err());
+
+            let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+            assert_eq!(reply["success"], false);
+            assert_eq!(reply["error_code"], ERROR_UNSUPPORTED_VERSION);
+            assert!(reply["error"].as_str().is_some_and(|e| !e.is_empty()));
+        }
+
+        let mut hello = client_hello(VERSION_V1_0, "");
+        let len = hello.len();
+        hello[len - 4..].copy_from_slice(&PROTOCOL_PROTOBUF.to_le_bytes());
+        let (result, reply) = accept_hello(hello).await;
+        assert!(result.is_err());
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["error_code"], ERROR_UNSUPPORTED_PROTOCOL);
+    }
+
+    #[tokio::test]
+    async fn test_client_reports_rejection() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move { Handshake::accept(&mut server).await });
+
+        let err = Handshake::connect(&mut client, None, ProtocolVersion::V0_2, WireProtocol::Json)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("error code 1"), "{}", err);
+        assert!(server.await.unwrap().is_err());
+    }
 }
@@ -1,10 +1,14 @@
 //! QUIC server for RethinkDB protocol
 
+#[cfg(feature = "quic")]
+use super::auth::{AuthManager, User};
 #[cfg(feature = "quic")]
 use super::connection::Connection;
 #[cfg(feature = "quic")]
 use super::protocol::{Handshake, ProtocolVersion, WireProtocol};
 #[cfg(feature = "quic")]
+use crate::cluster::metrics::{record_connection_error, ActiveConnection, ConnectionErrorReason};
+#[cfg(feature = "quic")]
 use crate::storage::Storage;
 #[cfg(feature = "quic")]
 use anyhow::{anyhow, Result};
@@ -35,6 +39,13 @@ pub struct QuicServerConfig {
     
     /// Auto-generate self-signed certificate for development
     pub auto_cert: bool,
+
+    /// CA certificates (PEM format) for client certificate authentication
+    ///
+    /// When set, clients must present a certificate signed by one of these
+    /// CAs whose subject common name names a user known to the server's
+    /// `AuthManager`.
+    pub client_ca_path: Option<String>,
 }
 
 #[cfg(feature = "quic")]
@@ -46,6 +57,7 @@ impl Default for QuicServerConfig {
             cert_path: None,
             key_path: None,
             auto_cert: true,
+            client_ca_path: None,
         }
     }
 }
@@ -55,6 +67,7 @@ impl Default for QuicServerConfig {
 pub struct QuicProtocolServer {
     config: QuicServerConfig,
     storage: Arc<Storage>,
+    auth: Arc<AuthManager>,
     connection_semaphore: Arc<Semaphore>,
 }
 
@@ -67,10 +80,22 @@ impl QuicProtocolServer {
         Self {
             config,
             storage,
+            auth: Arc::new(AuthManager::new()),
             connection_semaphore,
         }
     }
 
+    /// Use the given users for client certificate authentication
+    pub fn with_auth_manager(mut self, auth: Arc<AuthManager>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Check if clients must authenticate with a certificate
+    pub fn requires_client_cert(&self) -> bool {
+        self.config.client_ca_path.is_some()
+    }
+
     /// Generate a self-signed certificate for development
     fn generate_self_signed_cert() -> Result<(rustls::pki_types::CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>)> {
         let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
@@ -110,6 +135,38 @@ impl QuicProtocolServer {
         }
     }
 
+    /// Load trusted CA certificates for client authentication
+    fn load_client_roots(ca_path: &str) -> Result<rustls::RootCertStore> {
+        #[cfg(feature = "rustls-pemfile")]
+        {
+            let ca_file = std::fs::File::open(ca_path)?;
+            let mut ca_reader = std::io::BufReader::new(ca_file);
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut ca_reader) {
+                roots.add(cert?)?;
+            }
+
+            if roots.is_empty() {
+                return Err(anyhow!("No CA certificates found in {}", ca_path));
+            }
+
+            Ok(roots)
+        }
+
+        #[cfg(not(feature = "rustls-pemfile"))]
+        {
+            let _ = ca_path;
+            Err(anyhow!("rustls-pemfile feature not enabled"))
+        }
+    }
+
+    /// Crypto provider: the process default if installed, otherwise aws-lc-rs
+    fn crypto_provider() -> Arc<rustls::crypto::CryptoProvider> {
+        rustls::crypto::CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+    }
+
     /// Create server configuration with certificates
     fn create_server_config(&self) -> Result<ServerConfig> {
         let (certs, key) = if let (Some(cert_path), Some(key_path)) = (&self.config.cert_path, &self.config.key_path) {
@@ -125,9 +182,25 @@ impl QuicProtocolServer {
             return Err(anyhow!("No certificate configuration provided"));
         };
 
-        let mut crypto = rustls::ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)?;
+        let provider = Self::crypto_provider();
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let mut crypto = if let Some(ca_path) = &self.config.client_ca_path {
+            // Require client certificates chaining to the configured CA
+            tracing::info!("Requiring QUIC client certificates signed by {}", ca_path);
+            let roots = Self::load_client_roots(ca_path)?;
+            let verifier = rustls::server::WebPkiClientVerifier::builder_with_provider(
+                Arc::new(roots),
+                provider,
+            )
+            .build()?;
+            builder
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(certs, key)?
+        } else {
+            builder.with_no_client_auth().with_single_cert(certs, key)?
+        };
 
         // Enable ALPN for RethinkDB protocol
         crypto.alpn_protocols = vec![b"rethinkdb".to_vec()];
@@ -173,14 +246,41 @@ impl QuicProtocolServer {
             };
 
             let storage = self.storage.clone();
+            let auth = self.requires_client_cert().then(|| self.auth.clone());
             
             tokio::spawn(async move {
+                let _active = ActiveConnection::open();
                 match connecting.await {
                     Ok(connection) => {
                         let remote = connection.remote_address();
                         tracing::info!("New QUIC connection from {}", remote);
+
+                        // Map the client certificate to a user before accepting any streams
+                        let user = match auth {
+                            Some(auth) => match Self::authenticate_peer(&connection, &auth).await {
+                                Ok(user) => {
+                                    tracing::info!(
+                                        "QUIC client {} authenticated as {}",
+                                        remote,
+                                        user.username
+                                    );
+                                    Some(user)
+                                }
+                                Err(e) => {
+                                    tracing::warn!(
+                                        "Rejecting QUIC connection from {}: {}",
+                                        remote,
+                                        e
+                                    );
+                                    record_connection_error(ConnectionErrorReason::AuthFailed);
+                                    connection.close(quinn::VarInt::from_u32(1), b"unauthorized");
+                                    return;
+                                }
+                            },
+                            None => None,
+                        };
                         
-                        if let Err(e) = Self::handle_connection(connection, storage).await {
+                        if let Err(e) = Self::handle_connection(connection, storage, user).await {
                             tracing::error!("QUIC connection error from {}: {}", remote, e);
                         }
                         
@@ -188,6 +288,7 @@ impl QuicProtocolServer {
                     }
                     Err(e) => {
                         tracing::error!("Failed to establish QUIC connection: {}", e);
+                        record_connection_error(ConnectionErrorReason::HandshakeFailed);
                     }
                 }
                 
@@ -199,16 +300,40 @@ impl QuicProtocolServer {
         Ok(())
     }
 
+    /// Map the client certificate of a connection to a user
+    async fn authenticate_peer(connection: &quinn::Connection, auth: &AuthManager) -> Result<User> {
+        let certs = connection
+            .peer_identity()
+            .and_then(|identity| {
+                identity
+                    .downcast::<Vec<rustls::pki_types::CertificateDer<'static>>>()
+                    .ok()
+            })
+            .ok_or_else(|| anyhow!("No client certificate presented"))?;
+        let leaf = certs
+            .first()
+            .ok_or_else(|| anyhow!("No client certificate presented"))?;
+
+        auth.authenticate_certificate(leaf).await
+    }
+
     /// Handle a single QUIC connection
-    async fn handle_connection(connection: quinn::Connection, storage: Arc<Storage>) -> Result<()> {
+    async fn handle_connection(
+        connection: quinn::Connection,
+        storage: Arc<Storage>,
+        user: Option<User>,
+    ) -> Result<()> {
         // Create handshake (simplified for QUIC - TLS already done)
         let handshake = Handshake {
             version: ProtocolVersion::V1_0,
             protocol: WireProtocol::Json,
-            auth_key: None, // Auth via TLS client certs or first message
+            auth_key: None, // Auth via TLS client certs
         };
 
-        let conn = Connection::new(handshake, storage);
+        let mut conn = Connection::new(handshake, storage);
+        if let Some(user) = user {
+            conn = conn.with_user(user);
+        }
 
         // Accept bi-directional streams
         loop {
@@ -254,7 +379,11 @@ impl QuicProtocolServer {
 
                     // Handle query
                     match conn.handle_query(query_msg).await {
-                        Ok(response) => {
+                        Ok(None) => {
+                            // noreply query: close the stream without a response
+                            let _ = send.finish();
+                        }
+                        Ok(Some(response)) => {
                             // Write response
                             let response_json = match serde_json::to_vec(&response.response) {
                                 Ok(json) => json,
@@ -270,25 +399,22 @@ impl QuicProtocolServer {
 
                             if let Err(e) = send.write_all(&response_buf).await {
                                 tracing::error!("Failed to write response: {}", e);
+                                record_connection_error(ConnectionErrorReason::IoError);
                                 break;
                             }
                             
                             if let Err(e) = send.finish() {
                                 tracing::error!("Failed to finish stream: {}", e);
+                                record_connection_error(ConnectionErrorReason::IoError);
                                 break;
                             }
                         }
                         Err(e) => {
-                            tracing::error!("Query execution error: {}", e);
+                            tracing::error!("Query execution error: {:#}", e);
                             // Send error response
-                            let error_response = serde_json::json!({
-                                "t": 18, // RUNTIME_ERROR
-                                "r": [],
-                                "e": 1000000,
-                                "m": e.to_string()
-                            });
+                            let error_response = Connection::error_response(token, &e);
                             
-                            if let Ok(response_json) = serde_json::to_vec(&error_response) {
+                            if let Ok(response_json) = serde_json::to_vec(&error_response.response) {
                                 let mut response_buf = Vec::with_capacity(8 + response_json.len());
                                 response_buf.extend_from_slice(&token.to_le_bytes());
                                 response_buf.extend_from_slice(&response_json);
@@ -305,6 +431,7 @@ impl QuicProtocolServer {
                 }
                 Err(e) => {
                     tracing::error!("Failed to accept stream: {}", e);
+                    record_connection_error(ConnectionErrorReason::IoError);
                     break;
                 }
             }
@@ -347,4 +474,159 @@ mod tests {
         let result = QuicProtocolServer::generate_self_signed_cert();
         assert!(result.is_ok());
     }
+
+    #[cfg(feature = "rustls-pemfile")]
+    mod mtls {
+        use super::*;
+        use crate::cluster::metrics::{CONNECTION_ERRORS, CONNECTION_GAUGE_LOCK};
+        use crate::network::auth::{certificate_common_name, Permission, Scope};
+        use crate::storage::slab::SlabStorageEngine;
+        use rcgen::{
+            BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+        };
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        struct TestPki {
+            ca: rcgen::Certificate,
+            ca_key: KeyPair,
+        }
+
+        impl TestPki {
+            fn new() -> Self {
+                let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+                params.distinguished_name.push(DnType::CommonName, "test ca");
+                params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+                let ca_key = KeyPair::generate().unwrap();
+                let ca = params.self_signed(&ca_key).unwrap();
+                Self { ca, ca_key }
+            }
+
+            /// Issue a certificate, returning it with its key
+            fn issue(&self, name: &str, client: bool) -> (rcgen::Certificate, KeyPair) {
+                let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+                params.distinguished_name.push(DnType::CommonName, name);
+                params.extended_key_usages = vec![if client {
+                    ExtendedKeyUsagePurpose::ClientAuth
+                } else {
+                    ExtendedKeyUsagePurpose::ServerAuth
+                }];
+                let key = KeyPair::generate().unwrap();
+                let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+                (cert, key)
+            }
+        }
+
+        fn free_udp_port() -> u16 {
+            std::net::UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port()
+        }
+
+        async fn start_server(pki: &TestPki, dir: &std::path::Path) -> SocketAddr {
+            let (server_cert, server_key) = pki.issue("localhost", false);
+            let write = |name: &str, pem: String| {
+                let path = dir.join(name);
+                std::fs::write(&path, pem).unwrap();
+                Some(path.to_string_lossy().into_owned())
+            };
+
+            let config = QuicServerConfig {
+                bind_addr: format!("127.0.0.1:{}", free_udp_port()).parse().unwrap(),
+                max_connections: 8,
+                cert_path: write("server.pem", server_cert.pem()),
+                key_path: write("server.key", server_key.serialize_pem()),
+                auto_cert: false,
+                client_ca_path: write("ca.pem", pki.ca.pem()),
+            };
+            let addr = config.bind_addr;
+
+            let storage = Arc::new(Storage::new(Box::new(
+                SlabStorageEngine::with_defaults(dir.join("data")).unwrap(),
+            )));
+            let auth = Arc::new(AuthManager::new());
+            let permissions = vec![Permission::Connect, Permission::Read(Scope::Global)];
+            auth.add_user("alice".to_string(), "unused", permissions)
+                .await
+                .unwrap();
+
+            let server = QuicProtocolServer::new(config, storage).with_auth_manager(auth);
+            assert!(server.requires_client_cert());
+            tokio::spawn(async move {
+                let _ = server.serve().await;
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            addr
+        }
+
+        /// Connect and run SERVER_INFO, returning the response type
+        async fn server_info(
+            pki: &TestPki,
+            addr: SocketAddr,
+            client_cert: Option<(rcgen::Certificate, KeyPair)>,
+        ) -> Result<u64> {
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(pki.ca.der().clone())?;
+            let builder =
+                rustls::ClientConfig::builder_with_provider(QuicProtocolServer::crypto_provider())
+                    .with_safe_default_protocol_versions()?
+                    .with_root_certificates(roots);
+            let mut crypto = match client_cert {
+                Some((cert, key)) => builder.with_client_auth_cert(
+                    vec![CertificateDer::from(cert.der().to_vec())],
+                    PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                )?,
+                None => builder.with_no_client_auth(),
+            };
+            crypto.alpn_protocols = vec![b"rethinkdb".to_vec()];
+
+            let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap())?;
+            endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+                quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?,
+            )));
+
+            let connection = endpoint.connect(addr, "localhost")?.await?;
+            let (mut send, mut recv) = connection.open_bi().await?;
+            let mut query = 1i64.to_le_bytes().to_vec();
+            query.extend_from_slice(br#"{"type": "SERVER_INFO"}"#);
+            send.write_all(&query).await?;
+            send.finish()?;
+
+            let response = recv.read_to_end(1024 * 1024).await?;
+            let json: serde_json::Value = serde_json::from_slice(&response[8..])?;
+            json["t"].as_u64().ok_or_else(|| anyhow!("Missing response type"))
+        }
+
+        #[tokio::test]
+        async fn test_client_certificate_authentication() {
+            let dir = std::env::temp_dir().join(format!("quic_mtls_{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let _lock = CONNECTION_GAUGE_LOCK.lock().await;
+            let pki = TestPki::new();
+            let addr = start_server(&pki, &dir).await;
+            let auth_failures = || {
+                CONNECTION_ERRORS
+                    .with_label_values(&[ConnectionErrorReason::AuthFailed.as_str()])
+                    .get()
+            };
+            let failures_before = auth_failures();
+
+            // Valid certificate for a known user
+            let (alice_cert, alice_key) = pki.issue("alice", true);
+            assert_eq!(certificate_common_name(alice_cert.der()).unwrap(), "alice");
+            let response = server_info(&pki, addr, Some((alice_cert, alice_key))).await;
+            assert_eq!(response.unwrap(), 4); // SERVER_INFO
+
+            // No certificate
+            assert!(server_info(&pki, addr, None).await.is_err());
+
+            // Valid certificate, but no such user
+            let mallory = pki.issue("mallory", true);
+            assert!(server_info(&pki, addr, Some(mallory)).await.is_err());
+            assert!(auth_failures() > failures_before);
+
+            std::fs::remove_dir_all(dir).ok();
+        }
+    }
 }
@@ -1,12 +1,25 @@
 //! TCP server for RethinkDB protocol
 
-use super::connection::ConnectionHandler;
+use super::auth::AuthManager;
+use super::connection::{ConnectionHandler, DEFAULT_MAX_PARALLEL_QUERIES};
+use crate::cluster::metrics::ActiveConnection;
+use crate::query::QueryExecutor;
 use crate::storage::Storage;
 use anyhow::Result;
+use socket2::{SockRef, TcpKeepalive};
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio::sync::Semaphore;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+
+/// Time without a query after which a connection is closed, by default
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time a socket is silent before keep-alive probes start, by default
+pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(60);
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -17,6 +30,16 @@ pub struct ServerConfig {
     /// Maximum concurrent connections
     pub max_connections: usize,
     
+    /// Maximum in-flight queries per connection (V0_4 and later)
+    pub max_parallel_queries: usize,
+    
+    /// Close connections that sent no query for this long and have none
+    /// running; `None` keeps them open
+    pub idle_timeout: Option<Duration>,
+
+    /// Silence before TCP keep-alive probes are sent; `None` disables them
+    pub keepalive: Option<Duration>,
+
     /// Enable TLS
     pub tls_enabled: bool,
     
@@ -25,6 +48,13 @@ pub struct ServerConfig {
     
     /// TLS key path
     pub tls_key_path: Option<String>,
+
+    /// Authenticate clients during the handshake; `None` lets anyone in
+    pub auth: Option<Arc<AuthManager>>,
+
+    /// Run queries on this executor, e.g. the node's clustered one that
+    /// replicates writes; `None` runs them on plain ones over the storage
+    pub executor: Option<Arc<QueryExecutor>>,
 }
 
 impl Default for ServerConfig {
@@ -32,9 +62,14 @@ impl Default for ServerConfig {
         Self {
             bind_addr: "127.0.0.1:28015".parse().unwrap(),
             max_connections: 1024,
+            max_parallel_queries: DEFAULT_MAX_PARALLEL_QUERIES,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            keepalive: Some(DEFAULT_KEEPALIVE),
             tls_enabled: false,
             tls_cert_path: None,
             tls_key_path: None,
+            auth: None,
+            executor: None,
         }
     }
 }
@@ -44,38 +79,103 @@ pub struct ProtocolServer {
     config: ServerConfig,
     handler: Arc<ConnectionHandler>,
     connection_semaphore: Arc<Semaphore>,
+    /// Set on shutdown; tells open connections to stop reading queries
+    shutdown: watch::Sender<bool>,
 }
 
 impl ProtocolServer {
     /// Create a new protocol server
     pub fn new(config: ServerConfig, storage: Arc<Storage>) -> Self {
-        let handler = Arc::new(ConnectionHandler::new(storage));
+        let (shutdown, stopping) = watch::channel(false);
+        let mut handler = ConnectionHandler::new(storage)
+            .with_max_parallel_queries(config.max_parallel_queries)
+            .with_idle_timeout(config.idle_timeout)
+            .with_shutdown(stopping);
+        if let Some(auth) = &config.auth {
+            handler = handler.with_auth_manager(auth.clone());
+        }
+        if let Some(executor) = &config.executor {
+            handler = handler.with_executor(executor.clone());
+        }
+        let handler = Arc::new(handler);
         let connection_semaphore = Arc::new(Semaphore::new(config.max_connections));
 
         Self {
             config,
             handler,
             connection_semaphore,
+            shutdown,
         }
     }
 
     /// Start the server
     pub async fn serve(&self) -> Result<()> {
         let listener = TcpListener::bind(self.config.bind_addr).await?;
+        self.serve_listener(listener).await
+    }
+
+    /// Start the server and shut it down once `signal` resolves
+    ///
+    /// See [`serve_listener_with_shutdown`](Self::serve_listener_with_shutdown).
+    pub async fn serve_with_shutdown<F>(&self, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let listener = TcpListener::bind(self.config.bind_addr).await?;
+        self.serve_listener_with_shutdown(listener, signal).await
+    }
+
+    /// Serve connections from an already bound listener
+    ///
+    /// Useful when binding to port 0 and the actual address is needed.
+    pub async fn serve_listener(&self, listener: TcpListener) -> Result<()> {
+        self.serve_listener_with_shutdown(listener, std::future::pending())
+            .await
+    }
+
+    /// Serve connections from `listener` until `signal` resolves
+    ///
+    /// Then no new connections are accepted, open ones stop reading queries
+    /// and are closed once the queries they are running have been answered.
+    /// Returns when every connection is closed.
+    pub async fn serve_listener_with_shutdown<F>(
+        &self,
+        listener: TcpListener,
+        signal: F,
+    ) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
         tracing::info!(
             "RethinkDB protocol server listening on {}",
-            self.config.bind_addr
+            listener.local_addr()?
         );
 
+        let mut signal = std::pin::pin!(signal);
+        let mut connections = JoinSet::new();
         loop {
             // Acquire connection permit
-            let permit = self.connection_semaphore.clone().acquire_owned().await?;
+            let permit = tokio::select! {
+                _ = &mut signal => break,
+                permit = self.connection_semaphore.clone().acquire_owned() => permit?,
+            };
 
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                _ = &mut signal => break,
+                accepted = listener.accept() => accepted,
+            };
+            while connections.try_join_next().is_some() {}
+            match accepted {
                 Ok((stream, addr)) => {
+                    if let Some(time) = self.config.keepalive {
+                        if let Err(e) = set_keepalive(&stream, time) {
+                            tracing::warn!("Failed to enable keep-alive for {}: {}", addr, e);
+                        }
+                    }
                     let handler = self.handler.clone();
                     
-                    tokio::spawn(async move {
+                    connections.spawn(async move {
+                        let _active = ActiveConnection::open();
                         tracing::debug!("Accepted connection from {}", addr);
                         
                         if let Err(e) = handler.handle(stream).await {
@@ -92,6 +192,16 @@ impl ProtocolServer {
                 }
             }
         }
+
+        drop(listener);
+        tracing::info!(
+            "Protocol server shutting down, draining {} connections",
+            connections.len()
+        );
+        self.shutdown.send_replace(true);
+        while connections.join_next().await.is_some() {}
+        tracing::info!("Protocol server stopped");
+        Ok(())
     }
 
     /// Get server address
@@ -110,9 +220,18 @@ impl ProtocolServer {
     }
 }
 
+/// Probe `stream` after `time` of silence, so dead peers are noticed
+fn set_keepalive(stream: &TcpStream, time: Duration) -> std::io::Result<()> {
+    SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::Result as StorageResult;
+    use crate::reql::Datum;
+    use crate::storage::engine::{StorageEngine, TableInfo};
+    use crate::storage::mock::MockStorage;
     use crate::storage::slab::SlabStorageEngine;
 
     #[tokio::test]
@@ -142,4 +261,427 @@ mod tests {
         let server = ProtocolServer::new(config, storage);
         assert_eq!(server.available_connections(), 5);
     }
+
+    /// Poll until `condition` holds, failing after two seconds
+    async fn eventually(condition: impl Fn() -> bool) {
+        for _ in 0..200 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("condition not met within 2s");
+    }
+
+    #[tokio::test]
+    async fn test_connection_metrics() {
+        use crate::cluster::metrics::{
+            ConnectionErrorReason, ACTIVE_CONNECTIONS, CONNECTION_ERRORS, CONNECTION_GAUGE_LOCK,
+        };
+        use crate::network::{ConnectOptions, Connection};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let _lock = CONNECTION_GAUGE_LOCK.lock().await;
+        let temp_dir = std::env::temp_dir().join(format!("rethinkdb_test3_{}", std::process::id()));
+        let storage = Arc::new(Storage::new(Box::new(
+            SlabStorageEngine::with_defaults(temp_dir.to_str().unwrap()).expect("Failed to create storage")
+        )));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ProtocolServer::new(ServerConfig::default(), storage);
+        let serving = tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let idle = ACTIVE_CONNECTIONS.get();
+        let first = Connection::connect(addr, ConnectOptions::default()).await.unwrap();
+        let second = Connection::connect(addr, ConnectOptions::default()).await.unwrap();
+        eventually(|| ACTIVE_CONNECTIONS.get() == idle + 2).await;
+
+        drop(first);
+        drop(second);
+        eventually(|| ACTIVE_CONNECTIONS.get() == idle).await;
+
+        // An unknown protocol version fails the handshake
+        let handshake_failures = || {
+            CONNECTION_ERRORS
+                .with_label_values(&[ConnectionErrorReason::HandshakeFailed.as_str()])
+                .get()
+        };
+        let failures = handshake_failures();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&0xdead_beef_u32.to_le_bytes()).await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.ok();
+
+        eventually(|| handshake_failures() > failures).await;
+        eventually(|| ACTIVE_CONNECTIONS.get() == idle).await;
+
+        serving.abort();
+        std::fs::remove_dir_all(temp_dir).ok();
+    }
+
+    /// Engine whose scans of and writes to the `slow` table take half a second
+    struct SlowScans(MockStorage);
+
+    #[async_trait::async_trait]
+    impl StorageEngine for SlowScans {
+        async fn get(&self, key: &[u8]) -> StorageResult<Option<Datum>> {
+            self.0.get(key).await
+        }
+        async fn set(&self, key: &[u8], value: Datum) -> StorageResult<()> {
+            if key.starts_with(b"doc:test:slow:") {
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            }
+            self.0.set(key, value).await
+        }
+        async fn delete(&self, key: &[u8]) -> StorageResult<()> {
+            self.0.delete(key).await
+        }
+        async fn list_tables(&self) -> StorageResult<Vec<String>> {
+            self.0.list_tables().await
+        }
+        async fn get_table_info(&self, table_name: &str) -> StorageResult<Option<TableInfo>> {
+            self.0.get_table_info(table_name).await
+        }
+        async fn list_databases(&self) -> StorageResult<Vec<String>> {
+            self.0.list_databases().await
+        }
+        async fn create_database(&self, name: &str) -> StorageResult<()> {
+            self.0.create_database(name).await
+        }
+        async fn drop_database(&self, name: &str) -> StorageResult<()> {
+            self.0.drop_database(name).await
+        }
+        async fn list_tables_in_db(&self, db: &str) -> StorageResult<Vec<String>> {
+            self.0.list_tables_in_db(db).await
+        }
+        async fn create_table(
+            &self,
+            db: &str,
+            table: &str,
+            primary_key: &str,
+        ) -> StorageResult<()> {
+            self.0.create_table(db, table, primary_key).await
+        }
+        async fn drop_table(&self, db: &str, table: &str) -> StorageResult<()> {
+            self.0.drop_table(db, table).await
+        }
+        async fn scan_table(&self, db: &str, table: &str) -> StorageResult<Vec<Datum>> {
+            if table == "slow" {
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            }
+            self.0.scan_table(db, table).await
+        }
+        async fn scan_table_page(
+            &self,
+            db: &str,
+            table: &str,
+            after: Option<&[u8]>,
+            limit: usize,
+        ) -> StorageResult<Vec<(Vec<u8>, Datum)>> {
+            self.0.scan_table_page(db, table, after, limit).await
+        }
+        async fn scan_keys(
+            &self,
+            start: std::ops::Bound<&[u8]>,
+            end: std::ops::Bound<&[u8]>,
+        ) -> StorageResult<Vec<(Vec<u8>, Datum)>> {
+            self.0.scan_keys(start, end).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_queries() {
+        use crate::network::protocol::{
+            read_response, write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+        };
+        use std::time::{Duration, Instant};
+
+        let storage = Arc::new(Storage::new(Box::new(SlowScans(MockStorage::new()))));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "slow", "id").await.unwrap();
+        storage.create_table("test", "fast", "id").await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ProtocolServer::new(ServerConfig::default(), storage);
+        let serving = tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        Handshake::connect(&mut stream, None, ProtocolVersion::V1_0, WireProtocol::Json)
+            .await
+            .unwrap();
+        let scan = |token: i64, table: &str| QueryMessage {
+            token,
+            query: serde_json::json!({ "type": "START", "query": [15, [table]] }),
+        };
+
+        // The fast query is answered while the slow one is still running
+        let started = Instant::now();
+        write_query(&mut stream, &scan(1, "slow")).await.unwrap();
+        write_query(&mut stream, &scan(2, "fast")).await.unwrap();
+        let first = read_response(&mut stream).await.unwrap();
+        assert_eq!(first.token, 2);
+        assert!(started.elapsed() < Duration::from_millis(400));
+        let second = read_response(&mut stream).await.unwrap();
+        assert_eq!(second.token, 1);
+        assert_eq!(second.response["t"], 1);
+
+        // STOP cancels only the query with its token
+        write_query(&mut stream, &scan(3, "slow")).await.unwrap();
+        write_query(&mut stream, &scan(4, "slow")).await.unwrap();
+        let stop = QueryMessage {
+            token: 3,
+            query: serde_json::json!({ "type": "STOP" }),
+        };
+        write_query(&mut stream, &stop).await.unwrap();
+        let stopped = read_response(&mut stream).await.unwrap();
+        assert_eq!(stopped.token, 3);
+        assert_eq!(stopped.response["t"], 2);
+        let finished = read_response(&mut stream).await.unwrap();
+        assert_eq!(finished.token, 4);
+        assert_eq!(finished.response["t"], 1);
+        let more = tokio::time::timeout(Duration::from_millis(700), read_response(&mut stream));
+        assert!(more.await.is_err(), "stopped query still answered");
+
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stop_with_every_slot_taken() {
+        use crate::network::protocol::{
+            read_response, write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+        };
+        use std::time::{Duration, Instant};
+
+        let storage = Arc::new(Storage::new(Box::new(SlowScans(MockStorage::new()))));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "slow", "id").await.unwrap();
+        storage.create_table("test", "fast", "id").await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ServerConfig {
+            max_parallel_queries: 1,
+            ..Default::default()
+        };
+        let server = ProtocolServer::new(config, storage);
+        let serving = tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        Handshake::connect(&mut stream, None, ProtocolVersion::V1_0, WireProtocol::Json)
+            .await
+            .unwrap();
+        let scan = |token: i64, table: &str| QueryMessage {
+            token,
+            query: serde_json::json!({ "type": "START", "query": [15, [table]] }),
+        };
+
+        // The fast query waits for the slow one's slot; stopping the slow
+        // one frees it right away
+        let started = Instant::now();
+        write_query(&mut stream, &scan(1, "slow")).await.unwrap();
+        write_query(&mut stream, &scan(2, "fast")).await.unwrap();
+        let stop = QueryMessage {
+            token: 1,
+            query: serde_json::json!({ "type": "STOP" }),
+        };
+        write_query(&mut stream, &stop).await.unwrap();
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            let response = read_response(&mut stream).await.unwrap();
+            responses.push((response.token, response.response["t"].as_i64().unwrap()));
+        }
+        responses.sort();
+        assert_eq!(responses, vec![(1, 2), (2, 1)]);
+        assert!(started.elapsed() < Duration::from_millis(400));
+        let more = tokio::time::timeout(Duration::from_millis(700), read_response(&mut stream));
+        assert!(more.await.is_err(), "stopped query still answered");
+
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        use crate::network::protocol::{
+            read_response, write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+        };
+        use std::time::{Duration, Instant};
+        use tokio::io::AsyncWriteExt;
+
+        let storage = Arc::new(Storage::new(Box::new(SlowScans(MockStorage::new()))));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "slow", "id").await.unwrap();
+        storage.create_table("test", "fast", "id").await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ServerConfig {
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let server = ProtocolServer::new(config, storage);
+        let serving = tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let connect = || async {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            Handshake::connect(&mut stream, None, ProtocolVersion::V1_0, WireProtocol::Json)
+                .await
+                .unwrap();
+            stream
+        };
+        let scan = |token: i64, table: &str| QueryMessage {
+            token,
+            query: serde_json::json!({ "type": "START", "query": [15, [table]] }),
+        };
+        let mut idle = connect().await;
+        let mut active = connect().await;
+        let mut slow = connect().await;
+
+        // A query running past the timeout is still answered
+        write_query(&mut slow, &scan(1, "slow")).await.unwrap();
+
+        // Querying more often than the timeout keeps a connection open
+        let started = Instant::now();
+        for token in 0..8 {
+            write_query(&mut active, &scan(token, "fast"))
+                .await
+                .unwrap();
+            let response = read_response(&mut active).await.unwrap();
+            assert_eq!(response.response["t"], 1);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(started.elapsed() > Duration::from_millis(600));
+
+        let response = read_response(&mut slow).await.unwrap();
+        assert_eq!(response.token, 1);
+        assert_eq!(response.response["t"], 1);
+
+        // Idle connections are closed
+        for stream in [&mut idle, &mut slow] {
+            let closed = tokio::time::timeout(Duration::from_secs(1), read_response(stream));
+            assert!(closed.await.unwrap().is_err(), "idle connection still open");
+        }
+        write_query(&mut active, &scan(9, "fast")).await.unwrap();
+        assert_eq!(read_response(&mut active).await.unwrap().token, 9);
+
+        let mut partial = connect().await;
+        // A message sent in pieces across the timeout is read whole
+        write_query(&mut partial, &scan(1, "slow")).await.unwrap();
+        let mut message = Vec::new();
+        write_query(&mut message, &scan(2, "fast")).await.unwrap();
+        let (head, rest) = message.split_at(6);
+        partial.write_all(head).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        partial.write_all(rest).await.unwrap();
+        let mut tokens = Vec::new();
+        for _ in 0..2 {
+            tokens.push(read_response(&mut partial).await.unwrap().token);
+        }
+        tokens.sort();
+        assert_eq!(tokens, vec![1, 2]);
+
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_noreply_writes_survive_disconnect() {
+        use crate::network::protocol::{
+            write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+        };
+        use std::time::Duration;
+
+        let storage = Arc::new(Storage::new(Box::new(SlowScans(MockStorage::new()))));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "slow", "id").await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ProtocolServer::new(ServerConfig::default(), storage.clone());
+        let serving = tokio::spawn(async move { server.serve_listener(listener).await });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        Handshake::connect(&mut stream, None, ProtocolVersion::V1_0, WireProtocol::Json)
+            .await
+            .unwrap();
+        for token in 0..3 {
+            let insert = QueryMessage {
+                token,
+                query: serde_json::json!({
+                    "type": "START",
+                    "query": [56, [[15, ["slow"]], {"id": token}]],
+                    "optargs": {"noreply": true}
+                }),
+            };
+            write_query(&mut stream, &insert).await.unwrap();
+        }
+        // Disconnect while the writes are still running
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(stream);
+
+        let mut stored = 0;
+        for _ in 0..30 {
+            stored = storage.scan_table("test", "slow").await.unwrap().len();
+            if stored == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(stored, 3);
+
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_connections() {
+        use crate::network::protocol::{
+            read_response, write_query, Handshake, ProtocolVersion, QueryMessage, WireProtocol,
+        };
+        use std::time::Duration;
+
+        let storage = Arc::new(Storage::new(Box::new(SlowScans(MockStorage::new()))));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "slow", "id").await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = ProtocolServer::new(ServerConfig::default(), storage);
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let serving = tokio::spawn(async move {
+            server
+                .serve_listener_with_shutdown(listener, async {
+                    let _ = stopped.await;
+                })
+                .await
+        });
+
+        let connect = || async {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            Handshake::connect(&mut stream, None, ProtocolVersion::V1_0, WireProtocol::Json)
+                .await
+                .unwrap();
+            stream
+        };
+        let mut idle = connect().await;
+        let mut busy = connect().await;
+        let query = QueryMessage {
+            token: 1,
+            query: serde_json::json!({ "type": "START", "query": [15, ["slow"]] }),
+        };
+        write_query(&mut busy, &query).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.send(()).unwrap();
+
+        // The running query is still answered, then both connections close
+        let response = read_response(&mut busy).await.unwrap();
+        assert_eq!(response.token, 1);
+        assert_eq!(response.response["t"], 1);
+        for stream in [&mut idle, &mut busy] {
+            let closed = tokio::time::timeout(Duration::from_secs(1), read_response(stream));
+            assert!(closed.await.unwrap().is_err(), "connection still open");
+        }
+
+        let served = tokio::time::timeout(Duration::from_secs(1), serving).await;
+        served.unwrap().unwrap().unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
 }
@@ -1,15 +1,105 @@
 //! Plugin loader - handles dynamic loading of plugins
+//!
+//! # Symbol contract
+//!
+//! A plugin library is a `cdylib` that exports
+//!
+//! ```text
+//! extern "C" fn _plugin_abi_version() -> u32
+//! extern "C" fn _plugin_create() -> *mut Box<dyn Plugin>
+//! ```
+//!
+//! The first returns the [`PLUGIN_ABI_VERSION`] the plugin was built
+//! against and is checked before anything else of the plugin runs; the
+//! second returns a heap-allocated, boxed trait object. Use
+//! [`declare_plugin!`] rather than writing the exports by hand. The plugin
+//! must be built with the same compiler and the same version of this crate
+//! as the host, since trait objects have no stable ABI.
+//!
+//! [`PLUGIN_ABI_VERSION`]: super::PLUGIN_ABI_VERSION
+//!
+//! [`declare_plugin!`]: crate::declare_plugin
 
-use super::traits::Plugin;
+use super::registry::SUPPORTED_ABI_VERSIONS;
+use super::traits::{Plugin, PluginMetadata};
 use crate::error::{Error, Result};
+use crate::reql::Datum;
+use libloading::Library;
+use std::future::Future;
 use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
 
-/// Plugin loader
-pub struct PluginLoader {
-    // Future: Store loaded libraries for unloading
+/// Name of the constructor symbol every plugin library must export
+pub const PLUGIN_CREATE_SYMBOL: &[u8] = b"_plugin_create";
+
+/// Name of the ABI version symbol every plugin library must export
+pub const PLUGIN_ABI_VERSION_SYMBOL: &[u8] = b"_plugin_abi_version";
+
+/// Signature of the exported constructor
+pub type PluginCreateFn = unsafe extern "C" fn() -> *mut Box<dyn Plugin>;
+
+/// Signature of the exported ABI version
+pub type PluginAbiVersionFn = unsafe extern "C" fn() -> u32;
+
+/// Export a plugin type from a `cdylib`
+///
+/// Takes the path of a zero-argument function that constructs the plugin.
+///
+/// ```rust,ignore
+/// photondb::declare_plugin!(MyPlugin::new);
+/// ```
+#[macro_export]
+macro_rules! declare_plugin {
+    ($constructor:path) => {
+        #[no_mangle]
+        pub extern "C" fn _plugin_abi_version() -> u32 {
+            $crate::plugin::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn _plugin_create() -> *mut ::std::boxed::Box<dyn $crate::plugin::Plugin> {
+            let plugin: ::std::boxed::Box<dyn $crate::plugin::Plugin> =
+                ::std::boxed::Box::new($constructor());
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(plugin))
+        }
+    };
 }
 
+/// A plugin instance together with the library its code lives in
+///
+/// Field order matters: the plugin is dropped before the library is
+/// unloaded, so its vtable and drop glue are still mapped.
+struct DynamicPlugin {
+    plugin: Box<dyn Plugin>,
+    _library: Library,
+}
+
+impl Plugin for DynamicPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        self.plugin.metadata()
+    }
+
+    fn shutdown(&self) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>> {
+        self.plugin.shutdown()
+    }
+
+    fn execute(
+        &self,
+        function: &str,
+        args: Vec<Datum>,
+    ) -> Pin<Box<dyn Future<Output = Result<Datum>> + Send + '_>> {
+        self.plugin.execute(function, args)
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.plugin.list_functions()
+    }
+}
+
+/// Plugin loader
+pub struct PluginLoader {}
+
 impl PluginLoader {
     pub fn new() -> Self {
         Self {}
@@ -17,14 +107,68 @@ impl PluginLoader {
 
     /// Load a plugin from a dynamic library
     ///
+    /// The library stays mapped until the last reference to the returned
+    /// plugin is dropped. A library built against an ABI version this host
+    /// does not support is rejected before its constructor is called.
+    ///
     /// # Safety
     /// This loads external code. Only load trusted plugins!
-    pub async fn load(&self, _path: PathBuf) -> Result<Arc<dyn Plugin>> {
-        // TODO: Implement actual dynamic loading with libloading
-        // For now, return error as placeholder
-        Err(Error::Plugin(
-            "Dynamic plugin loading not yet implemented. Use built-in plugins.".to_string(),
-        ))
+    pub async fn load(&self, path: PathBuf) -> Result<Arc<dyn Plugin>> {
+        // SAFETY: running the library's initializers and calling its
+        // constructor is inherently trusted; the symbol type is the
+        // contract documented at module level.
+        let mut plugin = unsafe {
+            let library = Library::new(&path).map_err(|e| {
+                Error::Plugin(format!("Failed to open plugin {}: {}", path.display(), e))
+            })?;
+
+            let abi_version = library
+                .get::<PluginAbiVersionFn>(PLUGIN_ABI_VERSION_SYMBOL)
+                .map_err(|e| {
+                    Error::Plugin(format!(
+                        "Plugin {} does not export _plugin_abi_version: {}",
+                        path.display(),
+                        e
+                    ))
+                })?();
+            if !SUPPORTED_ABI_VERSIONS.contains(&abi_version) {
+                return Err(Error::Plugin(format!(
+                    "Plugin {} targets ABI version {}, but this host supports {}..={}",
+                    path.display(),
+                    abi_version,
+                    SUPPORTED_ABI_VERSIONS.start(),
+                    SUPPORTED_ABI_VERSIONS.end()
+                )));
+            }
+
+            let create = library
+                .get::<PluginCreateFn>(PLUGIN_CREATE_SYMBOL)
+                .map_err(|e| {
+                    Error::Plugin(format!(
+                        "Plugin {} does not export _plugin_create: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+
+            let raw = create();
+            if raw.is_null() {
+                return Err(Error::Plugin(format!(
+                    "Plugin {} returned a null instance",
+                    path.display()
+                )));
+            }
+
+            DynamicPlugin {
+                plugin: *Box::from_raw(raw),
+                _library: library,
+            }
+        };
+
+        plugin.plugin.initialize().await?;
+
+        tracing::debug!(path = %path.display(), "Loaded plugin library");
+        Ok(Arc::new(plugin))
     }
 
     /// Load a built-in plugin by name
@@ -41,3 +185,61 @@ impl Default for PluginLoader {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::PluginManager;
+    use std::path::Path;
+    use std::process::Command;
+
+    /// Build `examples/plugins/hello_plugin` and return the library path
+    fn build_hello_plugin() -> PathBuf {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let target_dir = root.join("target").join("plugin-tests");
+
+        let status = Command::new(env!("CARGO"))
+            .arg("build")
+            .arg("--quiet")
+            .arg("--manifest-path")
+            .arg(root.join("examples/plugins/hello_plugin/Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .status()
+            .expect("failed to run cargo");
+        assert!(status.success(), "building hello_plugin failed");
+
+        target_dir
+            .join("debug")
+            .join(libloading::library_filename("hello_plugin"))
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_library() {
+        let loader = PluginLoader::new();
+        let result = loader.load(PathBuf::from("/nonexistent/libnope.so")).await;
+        assert!(matches!(result, Err(Error::Plugin(_))));
+    }
+
+    #[tokio::test]
+    #[ignore = "compiles examples/plugins/hello_plugin; run with --ignored"]
+    async fn test_load_execute_unload_dynamic_plugin() {
+        let path = build_hello_plugin();
+        let manager = PluginManager::new();
+
+        manager.load_plugin(path).await.unwrap();
+        assert_eq!(manager.list_plugins(), vec!["hello".to_string()]);
+
+        let result = manager
+            .execute("hello", "greet", vec![Datum::String("Photon".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            Datum::String("Hello from a plugin, Photon!".to_string())
+        );
+
+        manager.unload_plugin("hello").await.unwrap();
+        assert!(manager.get_plugin("hello").is_none());
+    }
+}
@@ -9,23 +9,33 @@
 
 use crate::error::{Error, Result};
 use crate::reql::Datum;
+use parking_lot::RwLock;
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub mod loader;
 pub mod registry;
 pub mod traits;
 
 pub use loader::PluginLoader;
-pub use registry::PluginRegistry;
-pub use traits::{Plugin, PluginCapability, PluginMetadata};
+pub use registry::{PluginRegistry, SUPPORTED_ABI_VERSIONS};
+pub use traits::{AuthProvider, Plugin, PluginCapability, PluginMetadata, PLUGIN_ABI_VERSION};
+
+/// How often a retired plugin is checked for calls still running on it
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 /// Plugin manager - central component for plugin lifecycle
+///
+/// Every call holds a reference to the plugin it runs on, so a plugin that
+/// is unloaded or replaced stays alive until its outstanding calls return,
+/// and is only shut down then.
 pub struct PluginManager {
     registry: Arc<PluginRegistry>,
     loader: PluginLoader,
-    plugins: HashMap<String, Arc<dyn Plugin>>,
+    plugins: RwLock<HashMap<String, Arc<dyn Plugin>>>,
 }
 
 impl PluginManager {
@@ -34,13 +44,32 @@ impl PluginManager {
         Self {
             registry: Arc::new(PluginRegistry::new()),
             loader: PluginLoader::new(),
-            plugins: HashMap::new(),
+            plugins: RwLock::new(HashMap::new()),
         }
     }
 
     /// Load a plugin from a dynamic library
-    pub async fn load_plugin(&mut self, path: PathBuf) -> Result<()> {
+    ///
+    /// A plugin that cannot be registered, e.g. because a plugin of the
+    /// same name is loaded already, is shut down again.
+    pub async fn load_plugin(&self, path: PathBuf) -> Result<()> {
         let plugin = self.loader.load(path).await?;
+        self.register_initialized(plugin).await
+    }
+
+    /// Register a plugin that has been initialized, shutting it down if it
+    /// is rejected
+    async fn register_initialized(&self, plugin: Arc<dyn Plugin>) -> Result<()> {
+        if let Err(e) = self.register_plugin(plugin.clone()) {
+            plugin.shutdown().await?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Register an already constructed plugin, e.g. one compiled into the
+    /// server
+    pub fn register_plugin(&self, plugin: Arc<dyn Plugin>) -> Result<()> {
         let metadata = plugin.metadata();
 
         tracing::info!(
@@ -49,32 +78,98 @@ impl PluginManager {
             "Loading plugin"
         );
 
+        let mut plugins = self.plugins.write();
         self.registry.register(&metadata)?;
-        self.plugins.insert(metadata.name.clone(), plugin);
+        plugins.insert(metadata.name.clone(), plugin);
 
         Ok(())
     }
 
-    /// Unload a plugin by name
-    pub async fn unload_plugin(&mut self, name: &str) -> Result<()> {
-        if let Some(plugin) = self.plugins.remove(name) {
+    /// Replace plugin `name` with a new build loaded from `path`
+    ///
+    /// See [`replace_plugin`](Self::replace_plugin).
+    pub async fn reload_plugin(&self, name: &str, path: PathBuf) -> Result<()> {
+        let plugin = self.loader.load(path).await?;
+        if let Err(e) = self.check_replacement(name, &plugin.metadata()) {
             plugin.shutdown().await?;
-            self.registry.unregister(name)?;
-            tracing::info!(name = %name, "Plugin unloaded");
-            Ok(())
-        } else {
-            Err(Error::Plugin(format!("Plugin '{}' not found", name)))
+            return Err(e);
         }
+        self.replace_plugin(name, plugin).await
+    }
+
+    /// Swap in a new version of plugin `name`
+    ///
+    /// The new plugin must carry the same name, a supported ABI version and
+    /// a different version than the loaded one. Calls starting after the
+    /// swap run on the new version; the old one is shut down once the calls
+    /// still running on it have returned, which this waits for.
+    pub async fn replace_plugin(&self, name: &str, plugin: Arc<dyn Plugin>) -> Result<()> {
+        let metadata = plugin.metadata();
+        self.check_replacement(name, &metadata)?;
+
+        let old = {
+            let mut plugins = self.plugins.write();
+            let old = plugins
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::Plugin(format!("Plugin '{}' not found", name)))?;
+            self.registry.replace(&metadata)?;
+            plugins.insert(name.to_string(), plugin);
+            old
+        };
+
+        tracing::info!(
+            name = %name,
+            old_version = %old.metadata().version,
+            new_version = %metadata.version,
+            "Plugin reloaded"
+        );
+        Self::retire(name, old).await
+    }
+
+    /// Unload a plugin by name
+    ///
+    /// Waits for calls still running on the plugin before shutting it down.
+    pub async fn unload_plugin(&self, name: &str) -> Result<()> {
+        let plugin = {
+            let mut plugins = self.plugins.write();
+            let plugin = plugins
+                .remove(name)
+                .ok_or_else(|| Error::Plugin(format!("Plugin '{}' not found", name)))?;
+            self.registry.unregister(name)?;
+            plugin
+        };
+        Self::retire(name, plugin).await?;
+        tracing::info!(name = %name, "Plugin unloaded");
+        Ok(())
     }
 
     /// Get a plugin by name
+    ///
+    /// Holding on to the returned reference delays the shutdown of the
+    /// plugin when it is unloaded or replaced.
     pub fn get_plugin(&self, name: &str) -> Option<Arc<dyn Plugin>> {
-        self.plugins.get(name).cloned()
+        self.plugins.read().get(name).cloned()
     }
 
     /// List all loaded plugins
     pub fn list_plugins(&self) -> Vec<String> {
-        self.plugins.keys().cloned().collect()
+        self.plugins.read().keys().cloned().collect()
+    }
+
+    /// Loaded plugins that provide authentication, ordered by name
+    pub fn auth_providers(&self) -> Vec<Arc<dyn Plugin>> {
+        let plugins = self.plugins.read();
+        let mut names: Vec<&String> = plugins
+            .iter()
+            .filter(|(_, plugin)| plugin.auth_provider().is_some())
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| plugins[name].clone())
+            .collect()
     }
 
     /// Execute a plugin function
@@ -92,15 +187,50 @@ impl PluginManager {
     }
 
     /// Shutdown all plugins
-    pub async fn shutdown(&mut self) -> Result<()> {
-        for (name, plugin) in self.plugins.drain() {
+    pub async fn shutdown(&self) -> Result<()> {
+        let plugins: Vec<_> = self.plugins.write().drain().collect();
+        for (name, plugin) in plugins {
             tracing::info!(name = %name, "Shutting down plugin");
-            if let Err(e) = plugin.shutdown().await {
+            let _ = self.registry.unregister(&name);
+            if let Err(e) = Self::retire(&name, plugin).await {
                 tracing::error!(name = %name, error = %e, "Plugin shutdown error");
             }
         }
         Ok(())
     }
+
+    /// Check that `metadata` may replace the loaded plugin `name`
+    fn check_replacement(&self, name: &str, metadata: &PluginMetadata) -> Result<()> {
+        if metadata.name != name {
+            return Err(Error::Plugin(format!(
+                "Plugin '{}' cannot replace plugin '{}'",
+                metadata.name, name
+            )));
+        }
+        self.registry.check_replacement(metadata)
+    }
+
+    /// Shut `plugin` down once no call is running on it any more
+    ///
+    /// The plugin must already be out of the map, so that no new call can
+    /// pick it up; the remaining references are the running calls.
+    async fn retire(name: &str, plugin: Arc<dyn Plugin>) -> Result<()> {
+        if Arc::strong_count(&plugin) > 1 {
+            tracing::debug!(name = %name, "Waiting for plugin calls to drain");
+        }
+        while Arc::strong_count(&plugin) > 1 {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        plugin.shutdown().await
+    }
+}
+
+impl fmt::Debug for PluginManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginManager")
+            .field("plugins", &self.list_plugins())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for PluginManager {
@@ -112,10 +242,158 @@ impl Default for PluginManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
     #[tokio::test]
     async fn test_plugin_manager_creation() {
         let manager = PluginManager::new();
         assert_eq!(manager.list_plugins().len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_register_plugin() {
+        let manager = PluginManager::new();
+        manager
+            .register_plugin(Arc::new(traits::ExamplePlugin::new()))
+            .unwrap();
+
+        let result = manager
+            .execute("example", "hello", vec![Datum::String("Rust".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(result, Datum::String("Hello, Rust!".to_string()));
+        assert!(manager
+            .register_plugin(Arc::new(traits::ExamplePlugin::new()))
+            .is_err());
+    }
+
+    /// Reports its version; calls wait for `release` when it is set
+    struct VersionedPlugin {
+        version: &'static str,
+        entered: Arc<tokio::sync::Notify>,
+        release: Option<Arc<tokio::sync::Notify>>,
+        shut_down: Arc<AtomicBool>,
+    }
+
+    impl VersionedPlugin {
+        fn new(version: &'static str) -> Self {
+            Self {
+                version,
+                entered: Arc::new(tokio::sync::Notify::new()),
+                release: None,
+                shut_down: Arc::new(AtomicBool::new(false)),
+            }
+        }
+    }
+
+    impl Plugin for VersionedPlugin {
+        fn metadata(&self) -> PluginMetadata {
+            PluginMetadata {
+                name: "versioned".to_string(),
+                version: self.version.to_string(),
+                abi_version: PLUGIN_ABI_VERSION,
+                author: "test".to_string(),
+                description: "test plugin".to_string(),
+                capabilities: vec![PluginCapability::QueryOperations],
+            }
+        }
+
+        fn shutdown(
+            &self,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + '_>> {
+            self.shut_down.store(true, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn execute(
+            &self,
+            _function: &str,
+            _args: Vec<Datum>,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Datum>> + Send + '_>>
+        {
+            Box::pin(async move {
+                self.entered.notify_one();
+                if let Some(release) = &self.release {
+                    release.notified().await;
+                }
+                Ok(Datum::String(self.version.to_string()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_plugin_is_shut_down() {
+        let manager = PluginManager::new();
+        let first = VersionedPlugin::new("1.0.0");
+        let first_shut_down = first.shut_down.clone();
+        manager.register_initialized(Arc::new(first)).await.unwrap();
+
+        let duplicate = VersionedPlugin::new("1.0.0");
+        let duplicate_shut_down = duplicate.shut_down.clone();
+        assert!(manager
+            .register_initialized(Arc::new(duplicate))
+            .await
+            .is_err());
+        assert!(duplicate_shut_down.load(Ordering::SeqCst));
+        assert!(!first_shut_down.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_reload_drains_in_flight_calls() {
+        let manager = Arc::new(PluginManager::new());
+        let release = Arc::new(tokio::sync::Notify::new());
+        let v1 = VersionedPlugin {
+            release: Some(release.clone()),
+            ..VersionedPlugin::new("1.0.0")
+        };
+        let (v1_entered, v1_shut_down) = (v1.entered.clone(), v1.shut_down.clone());
+        manager.register_plugin(Arc::new(v1)).unwrap();
+
+        // A call is running on 1.0.0
+        let in_flight = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.execute("versioned", "version", vec![]).await }
+        });
+        v1_entered.notified().await;
+
+        // Only a newer version may replace it
+        let same = manager
+            .replace_plugin("versioned", Arc::new(VersionedPlugin::new("1.0.0")))
+            .await
+            .unwrap_err();
+        assert!(matches!(&same, Error::Plugin(msg) if msg.contains("already loaded")));
+        let renamed = manager
+            .replace_plugin("other", Arc::new(VersionedPlugin::new("2.0.0")))
+            .await;
+        assert!(renamed.is_err());
+
+        let v2 = VersionedPlugin::new("2.0.0");
+        let v2_shut_down = v2.shut_down.clone();
+        let reload = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.replace_plugin("versioned", Arc::new(v2)).await }
+        });
+        while manager.get_plugin("versioned").unwrap().metadata().version != "2.0.0" {
+            tokio::task::yield_now().await;
+        }
+
+        // New calls see the new version while the old call is still running
+        let result = manager.execute("versioned", "version", vec![]).await;
+        assert_eq!(result.unwrap(), Datum::String("2.0.0".to_string()));
+        tokio::time::sleep(DRAIN_POLL_INTERVAL * 3).await;
+        assert!(!reload.is_finished());
+        assert!(!v1_shut_down.load(Ordering::SeqCst));
+
+        // The old call completes against 1.0.0, then 1.0.0 is shut down
+        release.notify_one();
+        let result = in_flight.await.unwrap();
+        assert_eq!(result.unwrap(), Datum::String("1.0.0".to_string()));
+        reload.await.unwrap().unwrap();
+        assert!(v1_shut_down.load(Ordering::SeqCst));
+        assert!(!v2_shut_down.load(Ordering::SeqCst));
+        assert_eq!(manager.registry.get("versioned").unwrap().version, "2.0.0");
+
+        manager.unload_plugin("versioned").await.unwrap();
+        assert!(v2_shut_down.load(Ordering::SeqCst));
+    }
 }
@@ -1,33 +1,55 @@
 //! Plugin registry - tracks loaded plugins
 
-use super::traits::{PluginCapability, PluginMetadata};
+use super::traits::{PluginCapability, PluginMetadata, PLUGIN_ABI_VERSION};
 use crate::error::{Error, Result};
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::RwLock;
 
+/// Plugin ABI versions this host can load
+pub const SUPPORTED_ABI_VERSIONS: RangeInclusive<u32> = PLUGIN_ABI_VERSION..=PLUGIN_ABI_VERSION;
+
 /// Plugin registry
 pub struct PluginRegistry {
     plugins: RwLock<HashMap<String, PluginMetadata>>,
+    supported_abi: RangeInclusive<u32>,
 }
 
 impl PluginRegistry {
     pub fn new() -> Self {
+        Self::with_supported_abi(SUPPORTED_ABI_VERSIONS)
+    }
+
+    /// Create a registry accepting a custom range of plugin ABI versions
+    pub fn with_supported_abi(supported_abi: RangeInclusive<u32>) -> Self {
         Self {
             plugins: RwLock::new(HashMap::new()),
+            supported_abi,
         }
     }
 
     /// Register a plugin
+    ///
+    /// Fails if the plugin's ABI version is outside the supported range, or if
+    /// a plugin with the same name is already registered.
     pub fn register(&self, metadata: &PluginMetadata) -> Result<()> {
+        self.check_abi(metadata)?;
+
         let mut plugins = self
             .plugins
             .write()
             .map_err(|e| Error::Internal(format!("Lock error: {}", e)))?;
 
-        if plugins.contains_key(&metadata.name) {
+        if let Some(existing) = plugins.get(&metadata.name) {
+            if existing.version == metadata.version {
+                return Err(Error::Plugin(format!(
+                    "Plugin '{}' {} already registered",
+                    metadata.name, metadata.version
+                )));
+            }
             return Err(Error::Plugin(format!(
-                "Plugin '{}' already registered",
-                metadata.name
+                "Plugin '{}' {} conflicts with registered version {}; unload it first",
+                metadata.name, metadata.version, existing.version
             )));
         }
 
@@ -35,6 +57,44 @@ impl PluginRegistry {
         Ok(())
     }
 
+    /// Check that `metadata` may replace the registered plugin of the same
+    /// name: its ABI version must be supported and its version must differ
+    /// from the registered one
+    pub fn check_replacement(&self, metadata: &PluginMetadata) -> Result<()> {
+        self.check_abi(metadata)?;
+
+        let plugins = self
+            .plugins
+            .read()
+            .map_err(|e| Error::Internal(format!("Lock error: {}", e)))?;
+
+        let existing = plugins
+            .get(&metadata.name)
+            .ok_or_else(|| Error::Plugin(format!("Plugin '{}' not found", metadata.name)))?;
+        if existing.version == metadata.version {
+            return Err(Error::Plugin(format!(
+                "Plugin '{}' {} is already loaded",
+                metadata.name, metadata.version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Replace a registered plugin with a new version of it
+    ///
+    /// Performs the checks of [`check_replacement`](Self::check_replacement).
+    pub fn replace(&self, metadata: &PluginMetadata) -> Result<()> {
+        self.check_replacement(metadata)?;
+
+        let mut plugins = self
+            .plugins
+            .write()
+            .map_err(|e| Error::Internal(format!("Lock error: {}", e)))?;
+
+        plugins.insert(metadata.name.clone(), metadata.clone());
+        Ok(())
+    }
+
     /// Unregister a plugin
     pub fn unregister(&self, name: &str) -> Result<()> {
         let mut plugins = self
@@ -76,6 +136,20 @@ impl PluginRegistry {
             })
             .unwrap_or_default()
     }
+
+    fn check_abi(&self, metadata: &PluginMetadata) -> Result<()> {
+        if !self.supported_abi.contains(&metadata.abi_version) {
+            return Err(Error::Plugin(format!(
+                "Plugin '{}' {} targets ABI version {}, but this host supports {}..={}",
+                metadata.name,
+                metadata.version,
+                metadata.abi_version,
+                self.supported_abi.start(),
+                self.supported_abi.end()
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl Default for PluginRegistry {
@@ -83,3 +157,76 @@ impl Default for PluginRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, version: &str, abi_version: u32) -> PluginMetadata {
+        PluginMetadata {
+            name: name.to_string(),
+            version: version.to_string(),
+            abi_version,
+            author: "test".to_string(),
+            description: "test plugin".to_string(),
+            capabilities: vec![PluginCapability::Transformation],
+        }
+    }
+
+    #[test]
+    fn test_register_compatible_plugin() {
+        let registry = PluginRegistry::new();
+        registry
+            .register(&metadata("geo", "1.0.0", PLUGIN_ABI_VERSION))
+            .unwrap();
+
+        assert_eq!(registry.get("geo").unwrap().version, "1.0.0");
+        assert_eq!(
+            registry.find_by_capability(&PluginCapability::Transformation),
+            vec!["geo".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_reject_out_of_range_abi() {
+        let registry = PluginRegistry::with_supported_abi(2..=3);
+
+        for abi in [0, 1, 4] {
+            let err = registry
+                .register(&metadata("geo", "1.0.0", abi))
+                .unwrap_err();
+            assert!(
+                matches!(&err, Error::Plugin(msg) if msg.contains("ABI version")),
+                "{:?}",
+                err
+            );
+        }
+        assert!(registry.get("geo").is_none());
+
+        registry.register(&metadata("geo", "1.0.0", 3)).unwrap();
+    }
+
+    #[test]
+    fn test_reject_duplicate_and_conflicting_versions() {
+        let registry = PluginRegistry::new();
+        registry
+            .register(&metadata("geo", "1.0.0", PLUGIN_ABI_VERSION))
+            .unwrap();
+
+        let duplicate = registry
+            .register(&metadata("geo", "1.0.0", PLUGIN_ABI_VERSION))
+            .unwrap_err();
+        assert!(matches!(&duplicate, Error::Plugin(msg) if msg.contains("already registered")));
+
+        let conflict = registry
+            .register(&metadata("geo", "2.0.0", PLUGIN_ABI_VERSION))
+            .unwrap_err();
+        assert!(matches!(&conflict, Error::Plugin(msg) if msg.contains("conflicts")));
+        assert_eq!(registry.get("geo").unwrap().version, "1.0.0");
+
+        registry.unregister("geo").unwrap();
+        registry
+            .register(&metadata("geo", "2.0.0", PLUGIN_ABI_VERSION))
+            .unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 //! Plugin trait definitions
 
 use crate::error::Result;
+use crate::network::auth::Permission;
 use crate::reql::Datum;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -20,11 +21,22 @@ pub enum PluginCapability {
     Protocol,
 }
 
+/// Plugin ABI version implemented by this build
+///
+/// Bump whenever the [`Plugin`] trait or the loader symbol contract changes
+/// in a way that breaks plugins built against an older host.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
 /// Plugin metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginMetadata {
     pub name: String,
     pub version: String,
+    /// ABI version the plugin was built against, normally
+    /// [`PLUGIN_ABI_VERSION`]. Missing in serialized metadata means 0,
+    /// which no host accepts.
+    #[serde(default)]
+    pub abi_version: u32,
     pub author: String,
     pub description: String,
     pub capabilities: Vec<PluginCapability>,
@@ -70,6 +82,27 @@ pub trait Plugin: Send + Sync {
     fn list_functions(&self) -> Vec<String> {
         vec![]
     }
+
+    /// Credential checks offered by the plugin, if it provides
+    /// authentication
+    fn auth_provider(&self) -> Option<&dyn AuthProvider> {
+        None
+    }
+}
+
+/// Authentication delegated to a plugin, e.g. an LDAP or OIDC bridge
+///
+/// Plugins offering one declare [`PluginCapability::Authentication`] and
+/// return it from [`Plugin::auth_provider`]. See
+/// [`AuthManager::authenticate`](crate::network::AuthManager::authenticate)
+/// for how providers are consulted.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Whether `password` is valid for `username`
+    async fn authenticate(&self, username: &str, password: &str) -> Result<bool>;
+
+    /// Permissions of a user the provider authenticated
+    async fn permissions(&self, username: &str) -> Result<Vec<Permission>>;
 }
 
 /// Example plugin implementation
@@ -84,6 +117,7 @@ impl ExamplePlugin {
             metadata: PluginMetadata {
                 name: "example".to_string(),
                 version: "1.0.0".to_string(),
+                abi_version: PLUGIN_ABI_VERSION,
                 author: "RethinkDB Team".to_string(),
                 description: "Example plugin".to_string(),
                 capabilities: vec![PluginCapability::QueryOperations],
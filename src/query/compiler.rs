@@ -26,8 +26,23 @@
 //!   ]
 //! ]
 //! ```
+//!
+//! # Validation
+//!
+//! Compiled terms are checked before they are handed to the executor: every
+//! term must get the number of arguments its type takes, only terms with
+//! options may carry optargs, and writes may not be nested in the functions
+//! of stream operations like `filter` or `map` (`for_each` is the way to
+//! write per element). Violations fail compilation with an error naming the
+//! term and where it sits in the query.
+//!
+//! # Driver Encoding
+//!
+//! [`QueryCompiler::compile`] reads the Cap'n Proto ordinals of
+//! [`TermType`]. Official drivers number terms as in `ql2.proto` instead;
+//! [`QueryCompiler::compile_ql2`] decodes their JSON.
 
-use crate::reql::{Datum, Term, TermType};
+use crate::reql::{ql2, Datum, Term, TermType};
 use anyhow::{anyhow, Context, Result};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -39,10 +54,204 @@ use std::collections::HashMap;
 /// `[term_type, [args...], {optargs...}]`
 pub struct QueryCompiler;
 
+/// Number of positional arguments a term type takes
+struct Arity {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Arity {
+    const fn exactly(n: usize) -> Self {
+        Self { min: n, max: Some(n) }
+    }
+    
+    const fn between(min: usize, max: usize) -> Self {
+        Self { min, max: Some(max) }
+    }
+    
+    const fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+    
+    fn admits(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+    
+    fn describe(&self) -> String {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match self.max {
+            Some(max) if max == self.min => format!("{} {}", max, plural(max)),
+            Some(max) => format!("{} to {} arguments", self.min, max),
+            None => format!("at least {} {}", self.min, plural(self.min)),
+        }
+    }
+}
+
 impl QueryCompiler {
     /// Compile a JSON value into a ReQL Term
+    ///
+    /// The term is validated before it is returned, see [`Self::validate`].
     pub fn compile(query: &Value) -> Result<Term> {
-        Self::compile_term(query)
+        let term = Self::compile_term(query)?;
+        Self::validate(&term)?;
+        Ok(term)
+    }
+    
+    /// Compile a query as official drivers encode it
+    ///
+    /// Term ids are those of `ql2.proto` (see [`ql2`]), and the encoding is
+    /// strict:
+    ///
+    /// - An array is always a term, `[id, [args...], {optargs...}]`. Array
+    ///   literals are MAKE_ARRAY terms, e.g. `[2, [1, 2]]`.
+    /// - An object is a literal whose values are terms. It stays a datum
+    ///   unless a value has to be evaluated, then it is a MAKE_OBJ term.
+    /// - Anything else is a datum.
+    ///
+    /// The term is validated like those of [`Self::compile`].
+    pub fn compile_ql2(query: &Value) -> Result<Term> {
+        let term = Self::compile_ql2_term(query)?;
+        Self::validate(&term)?;
+        Ok(term)
+    }
+    
+    /// Check the arity, optargs and nesting of `term` and its arguments
+    pub fn validate(term: &Term) -> Result<()> {
+        Self::validate_term(term, &mut Vec::new(), None)
+    }
+    
+    /// Validate `term` under its `ancestors`; `stream_op` is the stream
+    /// operation whose function `term` is part of, if any
+    fn validate_term(
+        term: &Term,
+        ancestors: &mut Vec<TermType>,
+        stream_op: Option<TermType>,
+    ) -> Result<()> {
+        let term_type = term.term_type;
+        if term_type == TermType::Datum {
+            return Ok(());
+        }
+        let location = |ancestors: &[TermType]| {
+            if ancestors.is_empty() {
+                String::new()
+            } else {
+                let path: Vec<&str> = ancestors.iter().map(|t| t.name()).collect();
+                format!(" (in {})", path.join(" > "))
+            }
+        };
+        
+        let arity = Self::arity(term_type);
+        if !arity.admits(term.args.len()) {
+            return Err(anyhow!(
+                "{} takes {}, got {}{}",
+                term_type,
+                arity.describe(),
+                term.args.len(),
+                location(ancestors)
+            ));
+        }
+        let paired = matches!(term_type, TermType::Object);
+        let branches = matches!(term_type, TermType::Branch);
+        let even = term.args.len().is_multiple_of(2);
+        if (paired && !even) || (branches && even) {
+            return Err(anyhow!(
+                "{} takes an {} number of arguments, got {}{}",
+                term_type,
+                if paired { "even" } else { "odd" },
+                term.args.len(),
+                location(ancestors)
+            ));
+        }
+        if !term.optargs.is_empty() && !Self::takes_optargs(term_type) {
+            let mut names: Vec<&str> = term.optargs.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            return Err(anyhow!(
+                "{} takes no optional arguments, got `{}`{}",
+                term_type,
+                names.join("`, `"),
+                location(ancestors)
+            ));
+        }
+        if let (Some(stream_op), true) = (stream_op, Self::is_write(term_type)) {
+            return Err(anyhow!(
+                "{} cannot be nested in {}, use FOR_EACH to write per element{}",
+                term_type,
+                stream_op,
+                location(ancestors)
+            ));
+        }
+        
+        // Everything but the sequence of a stream operation is evaluated
+        // per element, where writes are not allowed
+        let nested_op = Self::is_stream_op(term_type).then_some(term_type);
+        ancestors.push(term_type);
+        for (i, arg) in term.args.iter().enumerate() {
+            let context = if i > 0 { nested_op.or(stream_op) } else { stream_op };
+            Self::validate_term(arg, ancestors, context)?;
+        }
+        for optarg in term.optargs.values() {
+            Self::validate_term(optarg, ancestors, nested_op.or(stream_op))?;
+        }
+        ancestors.pop();
+        Ok(())
+    }
+    
+    /// Positional arguments `term_type` takes, counting the term it is
+    /// chained on (e.g. the table of `get`)
+    fn arity(term_type: TermType) -> Arity {
+        use TermType::*;
+        match term_type {
+            Datum | MakeArray | And | Or | Union | Object => Arity::at_least(0),
+            Funcall => Arity::at_least(1),
+            DbList | MakeObj | Minval | Maxval => Arity::exactly(0),
+            TableList => Arity::between(0, 1),
+            Var | Javascript | Db | DbCreate | DbDrop | Not | Keys | Values | Distinct | TypeOf
+            | Delete | Config | Status | Wait | Reconfigure | Info | Ungroup | IsEmpty => Arity::exactly(1),
+            Table | TableCreate | TableDrop | Count | Sum | Avg | Min | Max => Arity::between(1, 2),
+            Add | Sub | Mul | OrderBy | Group | Pluck | Without | Merge | HasFields
+            | Contains => Arity::at_least(1),
+            Eq | Ne | Lt | Le | Gt | Ge => Arity::at_least(2),
+            Get | Div | Mod | GetField | Bracket | Filter | ConcatMap | Reduce
+            | Nth | Limit | Skip | Sample | Append | Prepend | Difference | SetInsert
+            | SetIntersection | SetUnion | SetDifference | CoerceTo | Insert | Update | Replace
+            | ForEach | Func | Default => Arity::exactly(2),
+            GetAll | Map | CallPlugin => Arity::at_least(2),
+            Slice | DeleteAt => Arity::between(2, 3),
+            Between | InsertAt | ChangeAt | SpliceAt => Arity::exactly(3),
+            Branch => Arity::at_least(3),
+        }
+    }
+    
+    /// Whether `term_type` has options; plain operators don't
+    fn takes_optargs(term_type: TermType) -> bool {
+        use TermType::*;
+        !matches!(
+            term_type,
+            Var | Add | Sub | Mul | Div | Mod | Eq | Ne | Lt | Le | Gt | Ge | Not | And | Or
+                | GetField | Bracket | Keys | Values | TypeOf | Append | Prepend | Difference
+                | SetInsert | SetIntersection | SetUnion | SetDifference | Minval | Maxval
+        )
+    }
+    
+    /// Whether `term_type` writes documents or changes the schema
+    fn is_write(term_type: TermType) -> bool {
+        use TermType::*;
+        matches!(
+            term_type,
+            Insert | Update | Replace | Delete | DbCreate | DbDrop | TableCreate | TableDrop
+                | Reconfigure
+        )
+    }
+    
+    /// Whether `term_type` evaluates its other arguments once per element
+    /// of the sequence it takes first
+    fn is_stream_op(term_type: TermType) -> bool {
+        use TermType::*;
+        matches!(
+            term_type,
+            Filter | Map | ConcatMap | OrderBy | Group | Reduce | Count | Sum | Avg | Min | Max
+                | Contains
+        )
     }
     
     /// Compile a term from JSON
@@ -110,13 +319,81 @@ impl QueryCompiler {
             .with_optargs(optargs))
     }
     
+    /// Compile a term in the driver encoding, see [`Self::compile_ql2`]
+    fn compile_ql2_term(json: &Value) -> Result<Term> {
+        let arr = match json {
+            Value::Array(arr) => arr,
+            Value::Object(obj) => {
+                let mut fields = HashMap::with_capacity(obj.len());
+                for (key, value) in obj {
+                    let term = Self::compile_ql2_term(value)
+                        .with_context(|| format!("Failed to parse field `{}`", key))?;
+                    fields.insert(key.clone(), term);
+                }
+                if !fields.values().all(Term::is_datum) {
+                    return Ok(Term::new(TermType::MakeObj).with_optargs(fields));
+                }
+                let obj = fields.into_iter()
+                    .map(|(key, term)| (key, term.datum.unwrap_or(Datum::Null)))
+                    .collect();
+                return Ok(Term::datum(Datum::Object(obj)));
+            }
+            other => return Ok(Term::datum(Self::json_to_datum(other)?)),
+        };
+        
+        let (id, parts) = arr.split_first()
+            .ok_or_else(|| anyhow!("Empty term array"))?;
+        let id = id.as_u64()
+            .ok_or_else(|| anyhow!("Invalid term type: expected number, got {}", id))?;
+        if id == ql2::DATUM {
+            return Err(anyhow!("DATUM is not sent as a term, send the value itself"));
+        }
+        let term_type = ql2::term_type(id)
+            .ok_or_else(|| anyhow!("Unknown term type: {}", id))?;
+        if parts.len() > 2 {
+            return Err(anyhow!("{} term has {} elements, expected at most 3", term_type, arr.len()));
+        }
+        
+        let args = match parts.first() {
+            Some(Value::Array(args)) => args.iter()
+                .map(Self::compile_ql2_term)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Failed to parse arguments of {}", term_type))?,
+            Some(other) => return Err(anyhow!("{} arguments must be an array, got {}", term_type, other)),
+            None => Vec::new(),
+        };
+        let optargs = match parts.get(1) {
+            Some(Value::Object(optargs)) => optargs.iter()
+                .map(|(key, value)| Ok((key.clone(), Self::compile_ql2_term(value)?)))
+                .collect::<Result<HashMap<_, _>>>()
+                .with_context(|| format!("Failed to parse optional arguments of {}", term_type))?,
+            Some(other) => {
+                return Err(anyhow!("{} optional arguments must be an object, got {}", term_type, other))
+            }
+            None => HashMap::new(),
+        };
+        
+        // A literal key tells which lookup a BRACKET is
+        let term_type = match (term_type, args.get(1).and_then(Term::as_datum)) {
+            (TermType::Bracket, Some(Datum::String(_))) => TermType::GetField,
+            (TermType::Bracket, Some(key)) if key.as_number().is_some() => TermType::Nth,
+            (term_type, _) => term_type,
+        };
+        
+        Ok(Term::new(term_type)
+            .with_args(args)
+            .with_optargs(optargs))
+    }
+    
     /// Convert JSON value to Datum
     fn json_to_datum(json: &Value) -> Result<Datum> {
         match json {
             Value::Null => Ok(Datum::Null),
             Value::Bool(b) => Ok(Datum::Boolean(*b)),
             Value::Number(n) => {
-                if let Some(f) = n.as_f64() {
+                if let Some(i) = n.as_i64() {
+                    Ok(Datum::Integer(i))
+                } else if let Some(f) = n.as_f64() {
                     Ok(Datum::Number(f))
                 } else {
                     Err(anyhow!("Invalid number: {}", n))
@@ -144,6 +421,7 @@ impl QueryCompiler {
         match datum {
             Datum::Null => Value::Null,
             Datum::Boolean(b) => Value::Bool(*b),
+            Datum::Integer(i) => Value::Number((*i).into()),
             Datum::Number(n) => {
                 serde_json::Number::from_f64(*n)
                     .map(Value::Number)
@@ -278,6 +556,76 @@ mod tests {
         assert_eq!(filter.term_type, TermType::Filter);
     }
     
+    #[test]
+    fn test_compile_between_open_ended() {
+        // r.table("users").between(r.minval, "m")
+        let json = serde_json::json!([49, [[10, ["users"]], [177], "m"]]);
+        let term = QueryCompiler::compile(&json).unwrap();
+        
+        assert_eq!(term.term_type, TermType::Between);
+        assert_eq!(term.args[0].term_type, TermType::Table);
+        assert_eq!(term.args[1].term_type, TermType::Minval);
+        assert_eq!(term.args[2].as_datum().unwrap().as_string(), Some("m"));
+        
+        let json = serde_json::json!([49, [[10, ["users"]], "m", [178]]]);
+        let term = QueryCompiler::compile(&json).unwrap();
+        assert_eq!(term.args[2].term_type, TermType::Maxval);
+        
+        assert!(QueryCompiler::compile(&serde_json::json!([177, [1]])).is_err());
+    }
+    
+    #[test]
+    fn test_validate_arity() {
+        // r.table("users").get() is missing its key
+        let err = QueryCompiler::compile(&serde_json::json!([11, [[10, ["users"]]]])).unwrap_err();
+        assert_eq!(err.to_string(), "GET takes 2 arguments, got 1");
+        
+        // r.div(6, 3, 2) nested in a filter predicate
+        let json = serde_json::json!([53, [
+            [10, ["users"]],
+            [103, [[1, [1]], [13, [[40, [[3, [1]], "score"]], [23, [6, 3, 2]]]]]]
+        ]]);
+        let err = QueryCompiler::compile(&json).unwrap_err();
+        assert_eq!(err.to_string(), "DIV takes 2 arguments, got 3 (in FILTER > FUNC > EQ)");
+        
+        // Operators have no options; BRANCH pairs conditions with values
+        assert!(QueryCompiler::compile(&serde_json::json!([20, [1, 2], {"x": 1}])).is_err());
+        assert!(QueryCompiler::compile(&serde_json::json!([99, [true, 1, false, 2]])).is_err());
+    }
+    
+    #[test]
+    fn test_validate_nested_write() {
+        // r.table("a").filter(row => r.table("b").insert(row))
+        let insert = serde_json::json!([76, [[10, ["b"]], [3, [1]]]]);
+        let json = serde_json::json!([53, [[10, ["a"]], [103, [[1, [1]], insert.clone()]]]]);
+        let err = QueryCompiler::compile(&json).unwrap_err();
+        assert!(err.to_string().starts_with("INSERT cannot be nested in FILTER"));
+        
+        // The same write is fine in FOR_EACH, and on the filtered sequence
+        let json = serde_json::json!([102, [[10, ["a"]], [103, [[1, [1]], insert]]]]);
+        assert!(QueryCompiler::compile(&json).is_ok());
+        let json = serde_json::json!([53, [[74, [[10, ["a"]]]], {"deleted": 1}]]);
+        assert!(QueryCompiler::compile(&json).is_ok());
+    }
+    
+    #[test]
+    fn test_validate_accepts_valid_query() {
+        // r.table("users").between(r.minval, "m", {index: "name"}).limit(10)
+        let json = serde_json::json!([37, [
+            [49, [[10, ["users"]], [177], "m"], {"index": "name"}],
+            10
+        ]]);
+        let term = QueryCompiler::compile(&json).unwrap();
+        assert_eq!(term.term_type, TermType::Limit);
+        assert!(QueryCompiler::validate(&term).is_ok());
+        
+        // r.object() and comparisons of more than two values
+        assert!(QueryCompiler::compile(&serde_json::json!([43])).is_ok());
+        assert!(QueryCompiler::compile(&serde_json::json!([13, [1, 1, 1]])).is_ok());
+        assert!(QueryCompiler::compile(&serde_json::json!([15, [1, 2, 3]])).is_ok());
+        assert!(QueryCompiler::compile(&serde_json::json!([15, [1]])).is_err());
+    }
+    
     #[test]
     fn test_json_to_datum_object() {
         let json = serde_json::json!({
@@ -311,4 +659,116 @@ mod tests {
         assert_eq!(json["name"], "Bob");
         assert_eq!(json["age"], 25.0);
     }
+    
+    /// `r.db("test").table("users")` in a driver term
+    fn ql2_users() -> Value {
+        serde_json::json!([15, [[14, ["test"]], "users"]])
+    }
+    
+    #[test]
+    fn test_compile_ql2_driver_query() {
+        // r.db("test").table("users").filter(r.row("age").gt(25)).count(),
+        // as the JavaScript driver sends it
+        let json = serde_json::json!([43, [[39, [
+            ql2_users(),
+            [69, [[2, [1]], [21, [[170, [[10, [1]], "age"]], 25]]]]
+        ]]]]);
+        let term = QueryCompiler::compile_ql2(&json).unwrap();
+        
+        let datum = |d: Datum| Term::datum(d);
+        let table = Term::new(TermType::Table).with_args(vec![
+            Term::new(TermType::Db).with_arg(datum(Datum::from("test"))),
+            datum(Datum::from("users")),
+        ]);
+        let row = Term::new(TermType::Var).with_arg(datum(Datum::Integer(1)));
+        let predicate = Term::new(TermType::Func).with_args(vec![
+            Term::new(TermType::MakeArray).with_arg(datum(Datum::Integer(1))),
+            Term::new(TermType::Gt).with_args(vec![
+                Term::new(TermType::GetField).with_args(vec![row, datum(Datum::from("age"))]),
+                datum(Datum::Integer(25)),
+            ]),
+        ]);
+        let expected = Term::new(TermType::Count)
+            .with_arg(Term::new(TermType::Filter).with_args(vec![table, predicate]));
+        assert_eq!(term, expected);
+        
+        // The same ids mean other terms to the Cap'n Proto decoder
+        assert!(!matches!(QueryCompiler::compile(&ql2_users()), Ok(t) if t.term_type == TermType::Table));
+    }
+    
+    #[test]
+    fn test_compile_ql2_literals() {
+        // r.expr([1, [2, 3]]): array literals are MAKE_ARRAY terms
+        let term = QueryCompiler::compile_ql2(&serde_json::json!([2, [1, [2, [2, 3]]]])).unwrap();
+        assert_eq!(term.term_type, TermType::MakeArray);
+        assert!(term.args[0].is_datum());
+        assert_eq!(term.args[1].term_type, TermType::MakeArray);
+        
+        // Objects are literals with term values; plain ones stay datums
+        let json = serde_json::json!({"meta": {"x": 1}, "name": "ann"});
+        let term = QueryCompiler::compile_ql2(&json).unwrap();
+        assert_eq!(term.as_datum(), Some(&Datum::from(json)));
+        let json = serde_json::json!({"meta": {"x": 1}, "tags": [2, ["a", "b"]]});
+        let term = QueryCompiler::compile_ql2(&json).unwrap();
+        assert_eq!(term.term_type, TermType::MakeObj);
+        assert_eq!(term.optarg("tags").unwrap().term_type, TermType::MakeArray);
+        assert_eq!(term.optarg("tags").unwrap().args.len(), 2);
+        assert_eq!(term.optarg("meta").unwrap().as_datum(), Some(&Datum::from(serde_json::json!({"x": 1}))));
+        
+        // r.expr([1, 2])(0) and r.row("age") are resolved by their key
+        let nth = QueryCompiler::compile_ql2(&serde_json::json!([170, [[2, [1, 2]], 0]])).unwrap();
+        assert_eq!(nth.term_type, TermType::Nth);
+        let field = QueryCompiler::compile_ql2(&serde_json::json!([170, [{"a": 1}, "a"]])).unwrap();
+        assert_eq!(field.term_type, TermType::GetField);
+        let computed = serde_json::json!([170, [[2, [1, 2]], [24, [0, 1]]]]);
+        assert_eq!(QueryCompiler::compile_ql2(&computed).unwrap().term_type, TermType::Bracket);
+        
+        // A bare array is not a literal
+        for json in [
+            serde_json::json!([1, 2]),
+            serde_json::json!(["a", "b"]),
+            serde_json::json!([12345]),
+            serde_json::json!([43, "users"]),
+            serde_json::json!([15, ["users"], ["use_outdated"]]),
+            serde_json::json!([15, ["users"], {}, {}]),
+        ] {
+            assert!(QueryCompiler::compile_ql2(&json).is_err(), "{} compiled", json);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_execute_ql2_driver_query() {
+        use crate::query::QueryExecutor;
+        use crate::storage::Storage;
+        use std::sync::Arc;
+        
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        let run = |json: Value| {
+            let term = QueryCompiler::compile_ql2(&json).unwrap();
+            let executor = &executor;
+            async move { executor.execute(&term).await.unwrap() }
+        };
+        
+        // r.db("test").table("users").insert([...])
+        run(serde_json::json!([56, [ql2_users(), [2, [
+            {"id": "ann", "age": 31, "active": true, "tags": [2, ["admin"]]},
+            {"id": "bob", "age": 19, "active": true},
+            {"id": "cid", "age": 44, "active": false},
+        ]]]])).await;
+        
+        let older = serde_json::json!([43, [[39, [
+            ql2_users(),
+            [69, [[2, [1]], [21, [[170, [[10, [1]], "age"]], 25]]]]
+        ]]]]);
+        assert_eq!(run(older).await.as_number(), Some(2.0));
+        
+        let active = serde_json::json!([43, [[39, [ql2_users(), {"active": true}]]]]);
+        assert_eq!(run(active).await.as_number(), Some(2.0));
+        
+        let ann = run(serde_json::json!([16, [ql2_users(), "ann"]])).await;
+        assert_eq!(ann.as_object().unwrap()["tags"], Datum::from(serde_json::json!(["admin"])));
+    }
 }
@@ -14,16 +14,17 @@
 //! # Supported Operations (70+)
 //!
 //! - **Database Admin**: DB_LIST, DB_CREATE, DB_DROP
-//! - **Table Admin**: TABLE_CREATE, TABLE_DROP, TABLE_LIST
+//! - **Table Admin**: TABLE_CREATE, TABLE_DROP, TABLE_LIST, CONFIG, RECONFIGURE, INFO,
+//!   STATUS, WAIT
 //! - **Data Access**: GET, GET_ALL, BETWEEN, TABLE
-//! - **Transformations**: FILTER, MAP, CONCAT_MAP, ORDER_BY, DISTINCT, LIMIT, SKIP
-//! - **Aggregations**: COUNT, SUM, AVG, MIN, MAX, GROUP, REDUCE
+//! - **Transformations**: FILTER, MAP, CONCAT_MAP, ORDER_BY, DISTINCT, UNION, SAMPLE, LIMIT, SKIP
+//! - **Aggregations**: COUNT, IS_EMPTY, SUM, AVG, MIN, MAX, GROUP, UNGROUP, REDUCE
 //! - **Mutations**: INSERT, UPDATE, REPLACE, DELETE
 //! - **Math**: ADD, SUB, MUL, DIV, MOD
 //! - **Logic**: EQ, NE, LT, LE, GT, GE, AND, OR, NOT
 //! - **Arrays**: APPEND, PREPEND, SLICE, INSERT_AT, DELETE_AT, CONTAINS
 //! - **Objects**: GET_FIELD, KEYS, VALUES, PLUCK, WITHOUT, MERGE, HAS_FIELDS
-//! - **Control Flow**: BRANCH, FOR_EACH, FUNC
+//! - **Control Flow**: BRANCH, FOR_EACH, FUNC, FUNCALL, DEFAULT
 //! - **Type Operations**: TYPE_OF, COERCE_TO
 //!
 //! # Example
@@ -40,13 +41,37 @@
 //! let result = executor.execute(&term).await?;
 //! ```
 
-use crate::reql::{Datum, Term, TermType};
-use crate::storage::Storage;
-use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use crate::cluster::{
+    version_key, ClusterState, ReadMode, ReadOptions, ReplicationManager, TableReadiness,
+    WriteToken,
+};
+use crate::cluster::metrics::MetricsCollector;
+use crate::error::Error;
+use crate::plugin::PluginManager;
+use crate::query::patch::JsonPatch;
+use crate::reql::{Bound, Datum, Term, TermType};
+use crate::storage::{
+    index, ttl, DatabaseEngine, Storage, StorageDatabaseEngine, TableReconfigure, Transaction,
+};
+use anyhow::{anyhow, Context, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Bound as KeyBound;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tracing::{debug, warn};
 
+/// Database queries use unless they select one, with `DB` or the `db`
+/// global optarg
+pub const DEFAULT_DB: &str = "test";
+
+/// Documents IS_EMPTY reads at a time when filtering a table
+const IS_EMPTY_PAGE_SIZE: usize = 100;
+
 /// Query execution context
 /// 
 /// Maintains state during query execution including variable bindings
@@ -56,13 +81,29 @@ pub struct ExecutionContext {
     
     /// Current database
     current_db: Option<String>,
+    
+    /// Open transaction; document writes are buffered here when set
+    transaction: Option<Transaction>,
+    
+    /// Routing of GET reads when writes are replicated
+    read_options: ReadOptions,
+    
+    /// Token of the newest write replicated by this query
+    write_token: Option<WriteToken>,
+    
+    /// Writes of the open transaction, replicated once it commits
+    unreplicated: Vec<(Vec<u8>, Datum)>,
 }
 
 impl ExecutionContext {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
-            current_db: Some("test".to_string()), // Default database
+            current_db: Some(DEFAULT_DB.to_string()),
+            transaction: None,
+            read_options: ReadOptions::default(),
+            write_token: None,
+            unreplicated: Vec::new(),
         }
     }
     
@@ -71,6 +112,11 @@ impl ExecutionContext {
         self
     }
     
+    pub fn with_read_options(mut self, options: ReadOptions) -> Self {
+        self.read_options = options;
+        self
+    }
+    
     pub fn bind_var(&mut self, id: u64, value: Datum) {
         self.variables.insert(id, value);
     }
@@ -84,18 +130,304 @@ impl ExecutionContext {
 #[derive(Debug)]
 pub struct QueryExecutor {
     storage: Arc<Storage>,
+    
+    /// Seed for SAMPLE; random when unset
+    sample_seed: Option<u64>,
+    
+    /// Records per-query count and latency when set
+    metrics: Option<Arc<MetricsCollector>>,
+    
+    /// Source of table readiness for STATUS and WAIT; always ready when unset
+    cluster: Option<Arc<ClusterState>>,
+    
+    /// Replicates document writes and routes `single`/`majority` GETs; writes
+    /// stay local when unset
+    replication: Option<ReplicationManager>,
+    
+    /// Plugins callable through CALL_PLUGIN; the term errors when unset
+    plugins: Option<Arc<PluginManager>>,
+    
+    /// Slots for queries running at once, with their number; unlimited when unset
+    query_slots: Option<(Arc<Semaphore>, usize)>,
+}
+
+/// A TABLE query, possibly filtered, read a page at a time
+///
+/// Holds a query slot for as long as it lives, so a streamed scan counts
+/// against the query limit like any other query.
+pub struct TableScan {
+    db: String,
+    table: String,
+    predicate: Option<Term>,
+    /// Key of the last document read
+    after: Option<Vec<u8>>,
+    done: bool,
+    _slot: Option<OwnedSemaphorePermit>,
 }
 
 impl QueryExecutor {
     /// Create a new query executor
     pub fn new(storage: Arc<Storage>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            sample_seed: None,
+            metrics: None,
+            cluster: None,
+            replication: None,
+            plugins: None,
+            query_slots: None,
+        }
+    }
+    
+    /// Seed SAMPLE so that each query picks the same elements every run
+    pub fn with_sample_seed(mut self, seed: u64) -> Self {
+        self.sample_seed = Some(seed);
+        self
+    }
+    
+    /// Record every executed query, labeled by its top-level term type
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+    
+    /// Report table readiness from the cluster's shard owners (clustered mode)
+    pub fn with_cluster(mut self, cluster: Arc<ClusterState>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+    
+    /// Replicate document writes through `replication` (clustered mode)
+    ///
+    /// A write is acknowledged once the write quorum applied it; the token
+    /// it is replicated with is returned by [`execute_with`](Self::execute_with).
+    /// GETs run with a `single` or `majority` read mode then only read from
+    /// nodes that applied the write their [`ReadOptions`] name.
+    pub fn with_replication(mut self, replication: ReplicationManager) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+    
+    /// Let queries call functions of the loaded plugins
+    pub fn with_plugins(mut self, plugins: Arc<PluginManager>) -> Self {
+        self.plugins = Some(plugins);
+        self
+    }
+    
+    /// Run at most `limit` queries at once
+    ///
+    /// Queries beyond the limit are not queued but fail right away with
+    /// [`Error::Busy`], so a burst of heavy queries cannot pile up behind
+    /// the ones running. A limit of 0 means no limit.
+    pub fn with_max_concurrent_queries(mut self, limit: usize) -> Self {
+        self.query_slots = (limit > 0).then(|| (Arc::new(Semaphore::new(limit)), limit));
+        self
+    }
+    
+    /// Take a slot for a query, if queries are limited
+    fn query_slot(&self) -> Result<Option<OwnedSemaphorePermit>> {
+        let Some((slots, limit)) = &self.query_slots else {
+            return Ok(None);
+        };
+        match slots.clone().try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => Err(Error::Busy(format!("{} queries already running", limit)).into()),
+        }
     }
     
     /// Execute a ReQL term and return the result
+    ///
+    /// Fails with [`Error::Busy`] when the query limit is reached (see
+    /// [`with_max_concurrent_queries`](Self::with_max_concurrent_queries)).
     pub async fn execute(&self, term: &Term) -> Result<Datum> {
-        let mut ctx = ExecutionContext::new();
-        self.execute_term(term, &mut ctx).await
+        self.execute_in(term, DEFAULT_DB).await
+    }
+    
+    /// Execute a ReQL term whose tables default to the database `db`
+    ///
+    /// `TABLE` and the table admin terms use `db` unless the term selects a
+    /// database of its own, like [`execute`](Self::execute) does with
+    /// [`DEFAULT_DB`].
+    pub async fn execute_in(&self, term: &Term, db: &str) -> Result<Datum> {
+        self.execute_with(term, db, ReadOptions::default())
+            .await
+            .map(|(result, _)| result)
+    }
+    
+    /// Execute a ReQL term in the database `db`, routing its GETs by `options`
+    ///
+    /// Returns the result and, if the query wrote documents, the token of
+    /// the newest write: the token it was replicated with (see
+    /// [`with_replication`](Self::with_replication)), else one taken when
+    /// it was written.
+    pub async fn execute_with(
+        &self,
+        term: &Term,
+        db: &str,
+        options: ReadOptions,
+    ) -> Result<(Datum, Option<WriteToken>)> {
+        let _slot = self.query_slot()?;
+        let start = std::time::Instant::now();
+        let mut ctx = ExecutionContext::new()
+            .with_db(db.to_string())
+            .with_read_options(options);
+        let result = self.execute_term(term, &mut ctx).await;
+        
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .record_query(
+                    term.term_type.name(),
+                    start.elapsed().as_secs_f64(),
+                    result.is_ok(),
+                )
+                .await;
+        }
+        result.map(|result| (result, ctx.write_token))
+    }
+    
+    /// Describe how `term` would be executed, without executing it
+    ///
+    /// See [`explain`](super::explain) for the shape of the plan.
+    pub async fn explain(&self, term: &Term) -> Result<Datum> {
+        self.explain_in(term, DEFAULT_DB).await
+    }
+    
+    /// Describe how `term` would be executed with the default database `db`
+    pub async fn explain_in(&self, term: &Term, db: &str) -> Result<Datum> {
+        super::explain::explain(&self.databases(), term, db).await
+    }
+    
+    /// Start reading `term` page by page, if it is a `TABLE` term or a
+    /// `FILTER` over one
+    ///
+    /// Such a query can be paged through with
+    /// [`next_page`](Self::next_page) instead of being executed at once.
+    /// Fails with [`Error::Busy`] like [`execute`](Self::execute) when the
+    /// query limit is reached.
+    pub async fn scan(&self, term: &Term) -> Result<Option<TableScan>> {
+        self.scan_in(term, DEFAULT_DB).await
+    }
+    
+    /// [`scan`](Self::scan) with tables defaulting to the database `db`
+    pub async fn scan_in(&self, term: &Term, db: &str) -> Result<Option<TableScan>> {
+        let (table, predicate) = match term.term_type {
+            TermType::Table => (term, None),
+            TermType::Filter if term.arg(0).is_some_and(|t| t.term_type == TermType::Table) => {
+                let predicate = term.arg(1).ok_or_else(|| anyhow!("FILTER requires predicate"))?;
+                (&term.args[0], Some(predicate.clone()))
+            }
+            _ => return Ok(None),
+        };
+        let slot = self.query_slot()?;
+        let mut ctx = ExecutionContext::new().with_db(db.to_string());
+        let (db, table) = self.resolve_table(table, &mut ctx).await?;
+        Ok(Some(TableScan {
+            db,
+            table,
+            predicate,
+            after: None,
+            done: false,
+            _slot: slot,
+        }))
+    }
+    
+    /// The next documents of `scan`, reading up to `page_size` documents
+    /// until one matches; empty once the table is exhausted
+    pub async fn next_page(&self, scan: &mut TableScan, page_size: usize) -> Result<Vec<Datum>> {
+        let mut ctx = ExecutionContext::new().with_db(scan.db.clone());
+        let mut rows = Vec::new();
+        while rows.is_empty() && !scan.done {
+            let page = self.storage.scan_table_page(&scan.db, &scan.table, scan.after.as_deref(), page_size).await
+                .map_err(|e| anyhow!("Failed to scan table: {}", e))?;
+            scan.done = page.is_empty();
+            for (key, doc) in page {
+                let matches = match &scan.predicate {
+                    Some(predicate) => self.filter_matches(predicate, &doc, &mut ctx).await?,
+                    None => true,
+                };
+                if matches {
+                    rows.push(doc);
+                }
+                scan.after = Some(key);
+            }
+        }
+        Ok(rows)
+    }
+    
+    /// Execute several write queries as one transaction
+    ///
+    /// Document writes of all terms are buffered and committed together. If
+    /// any term fails or reports write errors, nothing is written. Drivers
+    /// reach this with the `atomic` global optarg.
+    pub async fn execute_atomic(&self, terms: &[Term]) -> Result<Vec<Datum>> {
+        self.execute_atomic_in(terms, DEFAULT_DB).await
+    }
+    
+    /// Execute several write queries as one transaction, their tables
+    /// defaulting to the database `db`
+    pub async fn execute_atomic_in(&self, terms: &[Term], db: &str) -> Result<Vec<Datum>> {
+        self.execute_atomic_with(terms, db, ReadOptions::default())
+            .await
+            .map(|(results, _)| results)
+    }
+    
+    /// Execute several write queries as one transaction, like
+    /// [`execute_with`](Self::execute_with) does a single one
+    ///
+    /// The writes are replicated once the transaction has committed; if that
+    /// fails, the documents it wrote are restored locally.
+    pub async fn execute_atomic_with(
+        &self,
+        terms: &[Term],
+        db: &str,
+        options: ReadOptions,
+    ) -> Result<(Vec<Datum>, Option<WriteToken>)> {
+        let _slot = self.query_slot()?;
+        let mut ctx = ExecutionContext::new()
+            .with_db(db.to_string())
+            .with_read_options(options);
+        ctx.transaction = Some(self.storage.transaction());
+        
+        let mut results = Vec::with_capacity(terms.len());
+        for term in terms {
+            let result = self.execute_term(term, &mut ctx).await?;
+            let errors = result.as_object()
+                .and_then(|obj| obj.get("errors"))
+                .and_then(|d| d.as_number())
+                .unwrap_or(0.0);
+            if errors > 0.0 {
+                let first_error = result.as_object()
+                    .and_then(|obj| obj.get("first_error"))
+                    .and_then(|d| d.as_string())
+                    .unwrap_or("write failed");
+                return Err(anyhow!("Transaction aborted: {}", first_error));
+            }
+            results.push(result);
+        }
+        
+        // What the transaction overwrites, restored if replication fails
+        let unreplicated = std::mem::take(&mut ctx.unreplicated);
+        let mut previous = Vec::new();
+        for (key, _) in &unreplicated {
+            if !previous.iter().any(|(k, _)| k == key) {
+                let doc = self.storage.get(key).await
+                    .map_err(|e| anyhow!("Failed to read document: {}", e))?;
+                previous.push((key.clone(), doc));
+            }
+        }
+        if let Some(tx) = ctx.transaction.take() {
+            tx.commit().await
+                .map_err(|e| anyhow!("Failed to commit transaction: {}", e))?;
+        }
+        for (key, doc) in unreplicated {
+            if let Err(e) = self.replicate(&key, &doc, &mut ctx).await {
+                for (key, doc) in previous {
+                    self.restore(&key, doc).await;
+                }
+                return Err(e);
+            }
+        }
+        Ok((results, ctx.write_token))
     }
     
     /// Execute a term with context
@@ -125,6 +457,7 @@ impl QueryExecutor {
             }
             TermType::MakeArray => self.make_array(term, ctx).await,
             TermType::MakeObj => self.make_obj(term, ctx).await,
+            TermType::Var => self.var(term, ctx),
             
             // === Database Operations ===
             TermType::DbList => self.db_list(ctx).await,
@@ -137,15 +470,24 @@ impl QueryExecutor {
             TermType::TableCreate => self.table_create(term, ctx).await,
             TermType::TableDrop => self.table_drop(term, ctx).await,
             TermType::Table => self.table(term, ctx).await,
+            TermType::Config => self.config(term, ctx).await,
+            TermType::Reconfigure => self.reconfigure(term, ctx).await,
+            TermType::Info => self.info(term, ctx).await,
+            TermType::Status => self.status(term, ctx).await,
+            TermType::Wait => self.wait(term, ctx).await,
             
             // === Data Access ===
             TermType::Get => self.get(term, ctx).await,
             TermType::GetAll => self.get_all(term, ctx).await,
             TermType::Between => self.between(term, ctx).await,
+            TermType::Minval | TermType::Maxval => {
+                Err(anyhow!("{} is only valid as a range bound", term.term_type))
+            }
             
             // === Filtering & Selection ===
             TermType::Filter => self.filter(term, ctx).await,
             TermType::Nth => self.nth(term, ctx).await,
+            TermType::Bracket => self.bracket(term, ctx).await,
             TermType::Limit => self.limit(term, ctx).await,
             TermType::Skip => self.skip(term, ctx).await,
             TermType::Slice => self.slice(term, ctx).await,
@@ -155,17 +497,21 @@ impl QueryExecutor {
             TermType::ConcatMap => self.concat_map(term, ctx).await,
             TermType::OrderBy => self.order_by(term, ctx).await,
             TermType::Distinct => self.distinct(term, ctx).await,
+            TermType::Union => self.union(term, ctx).await,
+            TermType::Sample => self.sample(term, ctx).await,
             TermType::Pluck => self.pluck(term, ctx).await,
             TermType::Without => self.without(term, ctx).await,
             TermType::Merge => self.merge(term, ctx).await,
             
             // === Aggregations ===
             TermType::Count => self.count(term, ctx).await,
+            TermType::IsEmpty => self.is_empty(term, ctx).await,
             TermType::Sum => self.sum(term, ctx).await,
             TermType::Avg => self.avg(term, ctx).await,
             TermType::Min => self.min(term, ctx).await,
             TermType::Max => self.max(term, ctx).await,
             TermType::Group => self.group(term, ctx).await,
+            TermType::Ungroup => self.ungroup(term, ctx).await,
             TermType::Reduce => self.reduce(term, ctx).await,
             
             // === Write Operations ===
@@ -197,6 +543,7 @@ impl QueryExecutor {
             TermType::HasFields => self.has_fields(term, ctx).await,
             TermType::Keys => self.keys(term, ctx).await,
             TermType::Values => self.values(term, ctx).await,
+            TermType::Object => self.object(term, ctx).await,
             
             // === Array Operations ===
             TermType::Append => self.append(term, ctx).await,
@@ -215,12 +562,17 @@ impl QueryExecutor {
             // === Control Flow ===
             TermType::Branch => self.branch(term, ctx).await,
             TermType::ForEach => self.for_each(term, ctx).await,
-            TermType::Func => self.func_call(term, ctx).await,
+            TermType::Funcall => self.func_call(term, ctx).await,
+            TermType::Func => Err(anyhow!("FUNC can only be called, e.g. by FUNCALL or MAP")),
+            TermType::Default => self.default(term, ctx).await,
             
             // === Type Operations ===
             TermType::TypeOf => self.type_of(term, ctx).await,
             TermType::CoerceTo => self.coerce_to(term, ctx).await,
             
+            // === Plugins ===
+            TermType::CallPlugin => self.call_plugin(term, ctx).await,
+            
             // === Unsupported or TODO ===
             _ => {
                 warn!("Unsupported term type: {}", term.term_type);
@@ -260,6 +612,42 @@ impl QueryExecutor {
         Ok(Datum::Object(obj))
     }
     
+    /// OBJECT: an object from alternating key and value expressions
+    ///
+    /// Unlike MAKE_OBJ the keys are computed, and must evaluate to strings.
+    /// A repeated key keeps its last value.
+    async fn object(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        if !term.args.len().is_multiple_of(2) {
+            return Err(anyhow!(
+                "OBJECT expects an even number of arguments, got {}",
+                term.args.len()
+            ));
+        }
+        
+        let mut obj = HashMap::with_capacity(term.args.len() / 2);
+        for pair in term.args.chunks_exact(2) {
+            let key = match self.execute_term(&pair[0], ctx).await? {
+                Datum::String(key) => key,
+                other => return Err(anyhow!("OBJECT keys must be strings, got `{}`", other)),
+            };
+            let value = self.execute_term(&pair[1], ctx).await?;
+            obj.insert(key, value);
+        }
+        
+        Ok(Datum::Object(obj))
+    }
+    
+    fn var(&self, term: &Term, ctx: &ExecutionContext) -> Result<Datum> {
+        let id = term.arg(0)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .ok_or_else(|| anyhow!("VAR requires variable id"))? as u64;
+        
+        ctx.get_var(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Variable {} is not bound", id))
+    }
+    
     // ========================================================================
     // Database Operations
     // ========================================================================
@@ -281,6 +669,12 @@ impl QueryExecutor {
             .and_then(|d| d.as_string())
             .ok_or_else(|| anyhow!("DB_CREATE requires database name"))?;
         
+        let existing = self.storage.list_databases().await
+            .map_err(|e| anyhow!("Failed to list databases: {}", e))?;
+        if existing.iter().any(|db| db == db_name) {
+            return Err(Error::AlreadyExists(format!("Database `{}` already exists", db_name)).into());
+        }
+        
         self.storage.create_database(db_name).await
             .map_err(|e| anyhow!("Failed to create database: {}", e))?;
         
@@ -358,6 +752,12 @@ impl QueryExecutor {
             .and_then(|d| d.as_string())
             .unwrap_or("id");
         
+        let existing = self.storage.list_tables_in_db(db).await
+            .map_err(|e| anyhow!("Failed to list tables: {}", e))?;
+        if existing.iter().any(|table| table == table_name) {
+            return Err(Error::AlreadyExists(format!("Table `{}.{}` already exists", db, table_name)).into());
+        }
+        
         self.storage.create_table(db, table_name, primary_key).await
             .map_err(|e| anyhow!("Failed to create table: {}", e))?;
         
@@ -388,696 +788,2920 @@ impl QueryExecutor {
     }
     
     async fn table(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let table_name = term.arg(0)
-            .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_string())
-            .ok_or_else(|| anyhow!("TABLE requires table name"))?;
-        
-        let db = ctx.current_db.as_ref()
-            .ok_or_else(|| anyhow!("No database selected"))?;
+        let (db, table_name) = self.resolve_table(term, ctx).await?;
         
         // Return table reference with all documents
         // In a real implementation, this would return a lazy stream
-        let docs = self.storage.scan_table(db, table_name).await
+        let docs = self.storage.scan_table(&db, &table_name).await
             .map_err(|e| anyhow!("Failed to scan table: {}", e))?;
         
         Ok(Datum::Array(docs))
     }
     
-    // ========================================================================
-    // Data Access
-    // ========================================================================
+    /// CONFIG: the table's configuration (primary key, indexes, ids)
+    async fn config(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table = term.arg(0)
+            .ok_or_else(|| anyhow!("CONFIG requires a table"))?;
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
+        
+        let config = self.databases()
+            .get_table_config(&db, &table_name).await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, table_name))?;
+        
+        Ok(config.to_datum(&db))
+    }
     
-    async fn get(&self, term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // First arg is table, second is key
-        let _table_term = term.arg(0)
-            .ok_or_else(|| anyhow!("GET requires table"))?;
+    /// RECONFIGURE: add or remove secondary indexes
+    ///
+    /// Optargs `add_indexes` and `remove_indexes` take an index name or an
+    /// array of names. `add_indexes` may instead map the names of compound
+    /// indexes to their fields (see [`Self::compound_indexes`]). A
+    /// `primary_key` optarg is only accepted if it names the current
+    /// primary key.
+    async fn reconfigure(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table = term.arg(0)
+            .ok_or_else(|| anyhow!("RECONFIGURE requires a table"))?;
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
         
-        let key = term.arg(1)
-            .and_then(|t| t.as_datum())
-            .ok_or_else(|| anyhow!("GET requires key"))?;
+        let mut changes = TableReconfigure::default();
+        if let Some(primary_key) = term.optarg("primary_key") {
+            let primary_key = self.execute_term(primary_key, ctx).await?;
+            let primary_key = primary_key.as_string()
+                .ok_or_else(|| anyhow!("RECONFIGURE primary_key must be a string"))?;
+            changes.primary_key = Some(primary_key.to_string());
+        }
+        if let Some(value) = term.optarg("add_indexes") {
+            match self.compound_indexes(value, ctx).await? {
+                Some(compound) => changes.add_compound_indexes = compound,
+                None => {
+                    changes.add_indexes = Self::index_names(&self.execute_term(value, ctx).await?)
+                        .ok_or_else(|| anyhow!("RECONFIGURE add_indexes must be a string, an array of strings or an object"))?;
+                }
+            }
+        }
+        if let Some(value) = term.optarg("remove_indexes") {
+            changes.remove_indexes = Self::index_names(&self.execute_term(value, ctx).await?)
+                .ok_or_else(|| anyhow!("RECONFIGURE remove_indexes must be a string or an array of strings"))?;
+        }
         
-        // TODO: Properly extract table name from table term
-        // For now, use a simplified approach
-        let key_bytes = format!("{:?}", key).into_bytes();
+        let databases = self.databases();
+        let old_config = databases.get_table_config(&db, &table_name).await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, table_name))?;
+        let new_config = databases.reconfigure_table(&db, &table_name, &changes).await
+            .map_err(|e| anyhow!("Failed to reconfigure table: {}", e))?;
         
-        self.storage.get(&key_bytes).await
-            .map_err(|e| anyhow!("Failed to get document: {}", e))?
-            .ok_or_else(|| anyhow!("Document not found"))
+        Ok(Datum::Object({
+            let mut change = HashMap::new();
+            change.insert("old_val".to_string(), old_config.to_datum(&db));
+            change.insert("new_val".to_string(), new_config.to_datum(&db));
+            
+            let mut obj = HashMap::new();
+            obj.insert("reconfigured".to_string(), Datum::Number(1.0));
+            obj.insert("config_changes".to_string(), Datum::Array(vec![Datum::Object(change)]));
+            obj
+        }))
     }
     
-    async fn get_all(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement proper GET_ALL
-        Ok(Datum::Array(Vec::new()))
+    /// Compound index definitions given to RECONFIGURE as an object
+    ///
+    /// Each index name maps to an array of field names, or to a function
+    /// returning an array of the row's fields, e.g.
+    /// `row => [row("last_name"), row("first_name")]`. Returns `None` if
+    /// `value` is not an object.
+    async fn compound_indexes(
+        &self,
+        value: &Term,
+        ctx: &mut ExecutionContext,
+    ) -> Result<Option<BTreeMap<String, Vec<String>>>> {
+        let invalid = |index: &str| {
+            anyhow!("RECONFIGURE compound index `{}` must be an array of field names or a function returning row fields", index)
+        };
+        let mut compound = BTreeMap::new();
+        if value.term_type == TermType::MakeObj {
+            for (index, definition) in &value.optargs {
+                let fields = if definition.term_type == TermType::Func {
+                    function_fields(definition)
+                } else {
+                    Self::index_names(&self.execute_term(definition, ctx).await?)
+                };
+                compound.insert(index.clone(), fields.ok_or_else(|| invalid(index))?);
+            }
+            return Ok(Some(compound));
+        }
+        let Some(Datum::Object(definitions)) = value.as_datum() else {
+            return Ok(None);
+        };
+        for (index, fields) in definitions {
+            compound.insert(index.clone(), Self::index_names(fields).ok_or_else(|| invalid(index))?);
+        }
+        Ok(Some(compound))
     }
     
-    async fn between(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement BETWEEN with range queries
-        Ok(Datum::Array(Vec::new()))
+    /// INFO: metadata of a table or database
+    async fn info(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let target = term.arg(0)
+            .ok_or_else(|| anyhow!("INFO requires a table or database"))?;
+        let databases = self.databases();
+        
+        let obj = match target.term_type {
+            TermType::Table => {
+                let (db, table_name) = self.resolve_table(target, ctx).await?;
+                let config = databases.get_table_config(&db, &table_name).await
+                    .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+                    .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, table_name))?;
+                let doc_count = databases.count_documents(&db, &table_name).await
+                    .unwrap_or(config.doc_count);
+                
+                let mut obj = HashMap::new();
+                obj.insert("type".to_string(), Datum::String("TABLE".to_string()));
+                obj.insert("db".to_string(), Datum::String(db));
+                obj.insert("primary_key".to_string(), Datum::String(config.primary_key));
+                obj.insert("doc_count".to_string(), Datum::Number(doc_count as f64));
+                obj.insert(
+                    "indexes".to_string(),
+                    Datum::Array(config.indexes.into_iter().map(Datum::String).collect()),
+                );
+                obj.insert("name".to_string(), Datum::String(config.name));
+                obj.insert("id".to_string(), Datum::String(config.id.to_string()));
+                obj
+            }
+            TermType::Db => {
+                let db_name = target.arg(0)
+                    .and_then(|t| t.as_datum())
+                    .and_then(|d| d.as_string())
+                    .ok_or_else(|| anyhow!("DB requires database name"))?;
+                let config = databases.get_database_config(db_name).await
+                    .map_err(|e| anyhow!("Failed to read database config: {}", e))?
+                    .ok_or_else(|| anyhow!("Database `{}` does not exist", db_name))?;
+                
+                let mut obj = HashMap::new();
+                obj.insert("type".to_string(), Datum::String("DB".to_string()));
+                obj.insert("name".to_string(), Datum::String(config.name));
+                obj.insert("id".to_string(), Datum::String(config.id.to_string()));
+                obj
+            }
+            other => return Err(anyhow!("INFO requires a table or database, got {}", other)),
+        };
+        
+        Ok(Datum::Object(obj))
     }
     
-    // ========================================================================
-    // Filtering & Selection
-    // ========================================================================
-    
-    async fn filter(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let predicate = term.arg(1).ok_or_else(|| anyhow!("FILTER requires predicate"))?;
+    /// STATUS: whether a table is ready for reads and writes
+    async fn status(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table = term.arg(0)
+            .ok_or_else(|| anyhow!("STATUS requires a table"))?;
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
+        let config = self.databases().get_table_config(&db, &table_name).await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, table_name))?;
+        let readiness = self.readiness(&db, &table_name).await;
         
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("FILTER requires sequence"))?;
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("db".to_string(), Datum::String(db));
+            obj.insert("name".to_string(), Datum::String(config.name));
+            obj.insert("id".to_string(), Datum::String(config.id.to_string()));
+            obj.insert("status".to_string(), Datum::Object(readiness));
+            obj
+        }))
+    }
+    
+    /// WAIT: block until a table is ready
+    ///
+    /// Optarg `wait_for` names the STATUS flag to wait for (default
+    /// `all_replicas_ready`); `timeout` is in seconds.
+    async fn wait(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table = term.arg(0)
+            .ok_or_else(|| anyhow!("WAIT requires a table"))?;
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
+        if self.databases().get_table_config(&db, &table_name).await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .is_none()
+        {
+            return Err(anyhow!("Table `{}.{}` does not exist", db, table_name));
+        }
         
-        let mut filtered = Vec::new();
+        let wait_for = match term.optarg("wait_for") {
+            Some(value) => {
+                let value = self.execute_term(value, ctx).await?;
+                value.as_string()
+                    .filter(|flag| READINESS_FLAGS.contains(flag))
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("WAIT wait_for must be one of {}", READINESS_FLAGS.join(", ")))?
+            }
+            None => "all_replicas_ready".to_string(),
+        };
+        let deadline = match term.optarg("timeout") {
+            Some(value) => {
+                let seconds = self.execute_term(value, ctx).await?.as_number()
+                    .filter(|seconds| *seconds >= 0.0)
+                    .ok_or_else(|| anyhow!("WAIT timeout must be a non-negative number"))?;
+                // A timeout too long to represent never expires
+                Duration::try_from_secs_f64(seconds).ok()
+                    .and_then(|timeout| tokio::time::Instant::now().checked_add(timeout))
+            }
+            None => None,
+        };
         
-        for item in arr {
-            // TODO: Evaluate predicate with item bound to implicit variable
-            // For now, simple implementation
-            if predicate.is_datum() {
-                // Static predicate (object to match)
-                if let Some(pred_obj) = predicate.as_datum().and_then(|d| d.as_object()) {
-                    if let Some(item_obj) = item.as_object() {
-                        let matches = pred_obj.iter().all(|(k, v)| {
-                            item_obj.get(k) == Some(v)
-                        });
-                        if matches {
-                            filtered.push(item.clone());
-                        }
-                    }
-                }
+        loop {
+            let readiness = self.readiness(&db, &table_name).await;
+            if readiness.get(&wait_for) == Some(&Datum::Boolean(true)) {
+                break;
+            }
+            if deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline) {
+                return Err(anyhow!(
+                    "Timed out waiting for table `{}.{}` to be {}",
+                    db, table_name, wait_for.replace('_', " ")
+                ));
             }
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
         }
         
-        Ok(Datum::Array(filtered))
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("ready".to_string(), Datum::Number(1.0));
+            obj
+        }))
     }
     
-    async fn nth(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let index = term.arg(1)
-            .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_number())
-            .ok_or_else(|| anyhow!("NTH requires index"))? as usize;
-        
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("NTH requires sequence"))?;
+    /// Readiness flags of `db.table` reported by STATUS
+    ///
+    /// Without a cluster (standalone mode) tables are ready as soon as they
+    /// exist.
+    async fn readiness(&self, db: &str, table: &str) -> HashMap<String, Datum> {
+        let readiness = match &self.cluster {
+            Some(cluster) => cluster.table_readiness(db, table).await,
+            None => TableReadiness::STANDALONE,
+        };
         
-        arr.get(index)
-            .cloned()
-            .ok_or_else(|| anyhow!("Index out of bounds"))
+        READINESS_FLAGS.iter()
+            .map(|flag| {
+                let value = match *flag {
+                    "ready_for_outdated_reads" => readiness.ready_for_outdated_reads,
+                    "ready_for_reads" => readiness.ready_for_reads,
+                    "ready_for_writes" => readiness.ready_for_writes,
+                    _ => readiness.all_replicas_ready,
+                };
+                (flag.to_string(), Datum::Boolean(value))
+            })
+            .collect()
     }
     
-    async fn limit(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let n = term.arg(1)
-            .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_number())
-            .ok_or_else(|| anyhow!("LIMIT requires number"))? as usize;
-        
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("LIMIT requires sequence"))?;
-        
-        Ok(Datum::Array(arr.iter().take(n).cloned().collect()))
+    /// Database hierarchy view of the storage, for table and database metadata
+    fn databases(&self) -> StorageDatabaseEngine {
+        StorageDatabaseEngine::new(self.storage.clone())
     }
     
-    async fn skip(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let n = term.arg(1)
-            .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_number())
-            .ok_or_else(|| anyhow!("SKIP requires number"))? as usize;
-        
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("SKIP requires sequence"))?;
-        
-        Ok(Datum::Array(arr.iter().skip(n).cloned().collect()))
+    /// Index names given as a single string or an array of strings
+    fn index_names(value: &Datum) -> Option<Vec<String>> {
+        match value {
+            Datum::String(name) => Some(vec![name.clone()]),
+            Datum::Array(names) => names.iter()
+                .map(|name| name.as_string().map(str::to_string))
+                .collect(),
+            _ => None,
+        }
     }
     
-    async fn slice(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let start = term.arg(1)
-            .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_number())
-            .ok_or_else(|| anyhow!("SLICE requires start"))? as usize;
-        let end = term.arg(2)
+    /// Resolve a TABLE term to `(db, table)`
+    ///
+    /// Accepts both `TABLE(name)` (uses the current database) and
+    /// `TABLE(DB(name), name)`.
+    async fn resolve_table(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<(String, String)> {
+        if term.term_type != TermType::Table {
+            return Err(anyhow!("Expected TABLE term, got {}", term.term_type));
+        }
+        
+        let (db, name_term) = if term.args.len() > 1 {
+            // DB selects the current database; it must not outlive this table
+            let outer = ctx.current_db.clone();
+            let db = self.execute_term(&term.args[0], ctx).await;
+            ctx.current_db = outer;
+            let db = db?;
+            let name = db.as_object()
+                .and_then(|obj| obj.get("db"))
+                .and_then(|d| d.as_string())
+                .ok_or_else(|| anyhow!("TABLE requires a database, got {}", db))?;
+            (Some(name.to_string()), term.arg(1))
+        } else {
+            (None, term.arg(0))
+        };
+        
+        let table_name = name_term
             .and_then(|t| t.as_datum())
-            .and_then(|d| d.as_number())
-            .ok_or_else(|| anyhow!("SLICE requires end"))? as usize;
+            .and_then(|d| d.as_string())
+            .ok_or_else(|| anyhow!("TABLE requires table name"))?;
         
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("SLICE requires sequence"))?;
+        let db = match db {
+            Some(db) => db,
+            None => ctx.current_db.clone()
+                .ok_or_else(|| anyhow!("No database selected"))?,
+        };
         
-        Ok(Datum::Array(arr.iter().skip(start).take(end - start).cloned().collect()))
+        Ok((db, table_name.to_string()))
     }
     
-    // ========================================================================
-    // Transformations
-    // ========================================================================
-    
-    async fn map(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement MAP with function evaluation
-        Ok(Datum::Array(Vec::new()))
+    /// Storage key of a document: `doc:{db}:{table}:{primary key}`
+    fn document_key(db: &str, table: &str, id: &Datum) -> Vec<u8> {
+        index::document_key(db, table, id)
     }
     
-    async fn concat_map(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement CONCAT_MAP
-        Ok(Datum::Array(Vec::new()))
+    /// Read a document, seeing uncommitted writes of the open transaction
+    async fn read_document(&self, key: &[u8], ctx: &ExecutionContext) -> Result<Option<Datum>> {
+        let result = match &ctx.transaction {
+            Some(tx) => tx.get(key).await,
+            None => self.storage.get(key).await,
+        };
+        result.map_err(|e| anyhow!("Failed to read document: {}", e))
     }
     
-    async fn order_by(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement ORDER_BY
-        Ok(Datum::Array(Vec::new()))
+    /// Read a document for GET, from the replicas its read mode asks for
+    ///
+    /// `single` and `majority` reads go through the replication manager when
+    /// writes are replicated; every other read is local.
+    async fn read_routed(&self, key: &[u8], ctx: &ExecutionContext) -> Result<Option<Datum>> {
+        let routed = matches!(ctx.read_options.mode, Some(ReadMode::Single | ReadMode::Majority));
+        let (Some(replication), true, None) = (&self.replication, routed, &ctx.transaction) else {
+            return self.read_document(key, ctx).await;
+        };
+        let Some(data) = replication.fetch_with(key, ctx.read_options).await
+            .map_err(|e| anyhow!("Failed to read document: {}", e))? else {
+            return Ok(None);
+        };
+        let doc: serde_json::Value = serde_json::from_slice(&data)
+            .map_err(|e| anyhow!("Failed to decode replicated document: {}", e))?;
+        Ok(Some(Datum::from(doc)))
     }
     
-    async fn distinct(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("DISTINCT requires sequence"))?;
-        
-        let mut seen = Vec::new();
-        let mut distinct = Vec::new();
-        
-        for item in arr {
-            if !seen.contains(item) {
-                seen.push(item.clone());
-                distinct.push(item.clone());
+    /// Write a document, or buffer it if a transaction is open
+    async fn write_document(&self, key: &[u8], doc: Datum, ctx: &mut ExecutionContext) -> Result<()> {
+        // Replicas reject replicated writes, so fail before storing anything
+        if let Some(replication) = &self.replication {
+            if !replication.is_master().await {
+                return Err(anyhow!("Failed to insert document: not the master node"));
             }
         }
-        
-        Ok(Datum::Array(distinct))
+        if let Some(tx) = &mut ctx.transaction {
+            if self.replication.is_some() {
+                ctx.unreplicated.push((key.to_vec(), doc.clone()));
+            } else {
+                ctx.write_token = ctx.write_token.max(Some(WriteToken::now()));
+            }
+            tx.set(key, doc);
+            return Ok(());
+        }
+        if self.replication.is_none() {
+            self.storage.set(key, doc).await
+                .map_err(|e| anyhow!("Failed to insert document: {}", e))?;
+            ctx.write_token = ctx.write_token.max(Some(WriteToken::now()));
+            return Ok(());
+        }
+        let previous = self.storage.get(key).await
+            .map_err(|e| anyhow!("Failed to read document: {}", e))?;
+        self.storage.set(key, doc.clone()).await
+            .map_err(|e| anyhow!("Failed to insert document: {}", e))?;
+        if let Err(e) = self.replicate(key, &doc, ctx).await {
+            self.restore(key, previous).await;
+            return Err(e);
+        }
+        Ok(())
     }
     
-    async fn pluck(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement PLUCK (select specific fields)
-        Ok(Datum::Array(Vec::new()))
+    /// Put back the local copy of a document whose write was not replicated,
+    /// so this node keeps matching the cluster
+    async fn restore(&self, key: &[u8], previous: Option<Datum>) {
+        let restored = match previous {
+            Some(doc) => self.storage.set(key, doc).await,
+            None => self.storage.delete(key).await,
+        };
+        if let Err(e) = restored {
+            warn!(error = %e, "Failed to undo a write that was not replicated");
+        }
     }
     
-    async fn without(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement WITHOUT (remove specific fields)
-        Ok(Datum::Array(Vec::new()))
-    }
-    
-    async fn merge(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement MERGE (merge objects)
-        Ok(Datum::Object(HashMap::new()))
+    /// Replicate a written document and remember the token of the write
+    async fn replicate(&self, key: &[u8], doc: &Datum, ctx: &mut ExecutionContext) -> Result<()> {
+        let Some(replication) = &self.replication else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec(&serde_json::Value::from(doc.clone()))?;
+        let token = replication.write(key, &data).await
+            .map_err(|e| anyhow!("Failed to replicate document: {}", e))?;
+        // Keep the version with the local copy, so migrations send it along
+        if let Err(e) = self.storage.set(&version_key(key), Datum::Integer(token.0 as i64)).await {
+            warn!(error = %e, "Failed to record the version of a replicated document");
+        }
+        ctx.write_token = ctx.write_token.max(Some(token));
+        Ok(())
     }
     
     // ========================================================================
-    // Aggregations
+    // Data Access
     // ========================================================================
     
-    async fn count(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("COUNT requires sequence"))?;
+    /// GET: the document with a primary key, or null if there is none
+    async fn get(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table_term = term.arg(0)
+            .ok_or_else(|| anyhow!("GET requires table"))?;
+        let (db, table) = self.resolve_table(table_term, ctx).await?;
         
-        Ok(Datum::Number(arr.len() as f64))
+        let key_term = term.arg(1)
+            .ok_or_else(|| anyhow!("GET requires key"))?;
+        let id = self.execute_term(key_term, ctx).await?;
+        
+        let key = Self::document_key(&db, &table, &id);
+        Ok(self.read_routed(&key, ctx).await?.unwrap_or(Datum::Null))
     }
     
-    async fn sum(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("SUM requires sequence"))?;
-        
-        let sum: f64 = arr.iter()
-            .filter_map(|d| d.as_number())
-            .sum();
+    /// GET_ALL: documents whose `index` value equals one of the keys
+    ///
+    /// The index defaults to the table's primary key. Keys of a compound
+    /// index are arrays of its fields' values. Documents come in key order;
+    /// a document matching several keys is returned once per key.
+    async fn get_all(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let table = term.arg(0)
+            .ok_or_else(|| anyhow!("GET_ALL requires a table"))?;
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
+        let info = self.storage.get_table_info(&format!("{}.{}", db, table_name)).await
+            .map_err(|e| anyhow!("Failed to get table info: {}", e))?
+            .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, table_name))?;
+        let index = match term.optarg("index") {
+            Some(index) => self.execute_term(index, ctx).await?
+                .as_string()
+                .ok_or_else(|| anyhow!("GET_ALL index must be a string"))?
+                .to_string(),
+            None => info.primary_key.clone(),
+        };
+        if index != info.primary_key && !info.indexes.contains(&index) {
+            return Err(anyhow!("Index `{}` was not found on table `{}.{}`", index, db, table_name));
+        }
         
-        Ok(Datum::Number(sum))
+        let mut docs = Vec::new();
+        for key_term in term.args.iter().skip(1) {
+            let key = self.execute_term(key_term, ctx).await?;
+            if index == info.primary_key {
+                let doc_key = Self::document_key(&db, &table_name, &key);
+                docs.extend(self.read_document(&doc_key, ctx).await?);
+            } else {
+                docs.extend(self.storage.index_lookup(&db, &table_name, &index, &key).await
+                    .map_err(|e| anyhow!("Failed to read index `{}`: {}", index, e))?);
+            }
+        }
+        Ok(Datum::Array(docs))
     }
     
-    async fn avg(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("AVG requires sequence"))?;
+    /// BETWEEN: documents whose `index` field lies between two bounds
+    ///
+    /// The index defaults to the table's primary key. Bounds may be
+    /// `r.minval` / `r.maxval` for open-ended ranges; `left_bound` and
+    /// `right_bound` (`"closed"` or `"open"`) default to a half-open range.
+    /// Over a TABLE with a secondary index of that name, only the range is
+    /// read, and documents come in index order. Ranges of string primary
+    /// keys are read with [`DatabaseEngine::scan_range`].
+    async fn between(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence_term = term.arg(0)
+            .ok_or_else(|| anyhow!("BETWEEN requires a sequence"))?;
+        let lower = self.range_bound(term.arg(1), ctx).await?;
+        let upper = self.range_bound(term.arg(2), ctx).await?;
+        
+        let index = match term.optarg("index") {
+            Some(index) => self.execute_term(index, ctx).await?
+                .as_string()
+                .ok_or_else(|| anyhow!("BETWEEN index must be a string"))?
+                .to_string(),
+            None if sequence_term.term_type == TermType::Table => {
+                let (db, table_name) = self.resolve_table(sequence_term, ctx).await?;
+                self.databases().get_table_config(&db, &table_name).await
+                    .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+                    .map_or_else(|| "id".to_string(), |config| config.primary_key)
+            }
+            None => "id".to_string(),
+        };
+        let left_open = self.bound_is_open(term, "left_bound", false, ctx).await?;
+        let right_open = self.bound_is_open(term, "right_bound", true, ctx).await?;
         
-        if arr.is_empty() {
-            return Ok(Datum::Null);
+        if let Some((db, table)) = self.secondary_index(sequence_term, &index, ctx).await? {
+            let docs = self.storage.index_range(&db, &table, &index, (&lower, left_open), (&upper, right_open)).await
+                .map_err(|e| anyhow!("Failed to read index `{}`: {}", index, e))?;
+            return Ok(Datum::Array(docs));
         }
         
-        let sum: f64 = arr.iter()
-            .filter_map(|d| d.as_number())
-            .sum();
+        let sequence = match self.primary_key_range(sequence_term, &index, (&lower, left_open), (&upper, right_open), ctx).await? {
+            Some(docs) => Datum::Array(docs),
+            None => self.execute_term(sequence_term, ctx).await?,
+        };
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("BETWEEN requires sequence"))?;
+        
+        let in_range = arr.iter()
+            .filter(|doc| {
+                let Some(value) = doc.as_object().and_then(|obj| obj.get(&index)) else {
+                    return false;
+                };
+                let above = match lower.cmp_datum(value) {
+                    Ordering::Less => true,
+                    Ordering::Equal => !left_open,
+                    Ordering::Greater => false,
+                };
+                let below = match upper.cmp_datum(value) {
+                    Ordering::Greater => true,
+                    Ordering::Equal => !right_open,
+                    Ordering::Less => false,
+                };
+                above && below
+            })
+            .cloned()
+            .collect();
         
-        Ok(Datum::Number(sum / arr.len() as f64))
+        Ok(Datum::Array(in_range))
     }
     
-    async fn min(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("MIN requires sequence"))?;
+    /// Documents of `sequence` whose primary keys may lie in a range, read
+    /// from storage in key order
+    ///
+    /// Only ranges of strings or numbers can be read by key: other values
+    /// do not sort like their document keys. `None` means `sequence` is not
+    /// a TABLE keyed by `index` or the range is not one of those, so it must
+    /// be read whole. Documents with keys of other types can still come
+    /// back and are left for the caller to filter out.
+    async fn primary_key_range(
+        &self,
+        sequence: &Term,
+        index: &str,
+        lower: (&Bound, bool),
+        upper: (&Bound, bool),
+        ctx: &mut ExecutionContext,
+    ) -> Result<Option<Vec<Datum>>> {
+        if sequence.term_type != TermType::Table {
+            return Ok(None);
+        }
+        let key = |bound: &Bound| match bound {
+            Bound::Value(id @ (Datum::String(_) | Datum::Integer(_) | Datum::Number(_))) => {
+                Some(index::primary_key_bytes(id))
+            }
+            _ => None,
+        };
+        let start = match (key(lower.0), lower.1) {
+            (Some(start), true) => KeyBound::Excluded(start),
+            (Some(start), false) => KeyBound::Included(start),
+            _ => return Ok(None),
+        };
+        let end = match (key(upper.0), upper) {
+            (Some(end), (_, true)) => KeyBound::Excluded(end),
+            (Some(end), (_, false)) => KeyBound::Included(end),
+            (None, (Bound::MaxVal, _)) => KeyBound::Unbounded,
+            _ => return Ok(None),
+        };
         
-        arr.iter()
-            .filter_map(|d| d.as_number())
-            .min_by(|a, b| a.partial_cmp(b).unwrap())
-            .map(Datum::Number)
-            .ok_or_else(|| anyhow!("MIN on empty sequence"))
+        let (db, table) = self.resolve_table(sequence, ctx).await?;
+        let primary_key = self.databases().get_table_config(&db, &table).await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .map_or_else(|| "id".to_string(), |config| config.primary_key);
+        if primary_key != index {
+            return Ok(None);
+        }
+        
+        self.databases().scan_range(&db, &table, start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice)).await
+            .map_err(|e| anyhow!("Failed to read table: {}", e))?
+            .iter()
+            .map(|doc| {
+                serde_json::from_slice::<serde_json::Value>(doc)
+                    .map(Datum::from)
+                    .map_err(|e| anyhow!("Invalid document: {}", e))
+            })
+            .collect::<Result<_>>()
+            .map(Some)
     }
     
-    async fn max(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let arr = sequence.as_array()
-            .ok_or_else(|| anyhow!("MAX requires sequence"))?;
-        
-        arr.iter()
-            .filter_map(|d| d.as_number())
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .map(Datum::Number)
-            .ok_or_else(|| anyhow!("MAX on empty sequence"))
+    /// Database and table of `sequence` if it is a TABLE term with a
+    /// secondary index named `index`
+    async fn secondary_index(
+        &self,
+        sequence: &Term,
+        index: &str,
+        ctx: &mut ExecutionContext,
+    ) -> Result<Option<(String, String)>> {
+        if sequence.term_type != TermType::Table {
+            return Ok(None);
+        }
+        let (db, table) = self.resolve_table(sequence, ctx).await?;
+        let indexed = self.storage.get_table_info(&format!("{}.{}", db, table)).await
+            .map_err(|e| anyhow!("Failed to get table info: {}", e))?
+            .is_some_and(|info| info.indexes.iter().any(|name| name == index));
+        Ok(indexed.then_some((db, table)))
     }
     
-    async fn group(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement GROUP
-        Ok(Datum::Array(Vec::new()))
+    /// Evaluate a range bound, recognizing `r.minval` and `r.maxval`
+    async fn range_bound(&self, term: Option<&Term>, ctx: &mut ExecutionContext) -> Result<Bound> {
+        let term = term.ok_or_else(|| anyhow!("BETWEEN requires lower and upper bounds"))?;
+        Ok(match term.term_type {
+            TermType::Minval => Bound::MinVal,
+            TermType::Maxval => Bound::MaxVal,
+            _ => Bound::Value(self.execute_term(term, ctx).await?),
+        })
     }
     
-    async fn reduce(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement REDUCE with function evaluation
-        Ok(Datum::Null)
+    /// Whether the `left_bound` / `right_bound` optarg `name` asks for an open bound
+    async fn bound_is_open(&self, term: &Term, name: &str, default: bool, ctx: &mut ExecutionContext) -> Result<bool> {
+        let Some(bound) = term.optarg(name) else {
+            return Ok(default);
+        };
+        match self.execute_term(bound, ctx).await?.as_string() {
+            Some("open") => Ok(true),
+            Some("closed") => Ok(false),
+            _ => Err(anyhow!("BETWEEN {} must be \"open\" or \"closed\"", name)),
+        }
     }
     
     // ========================================================================
-    // Write Operations
+    // Plugins
     // ========================================================================
     
-    async fn insert(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement INSERT properly
-        Ok(Datum::Object({
-            let mut obj = HashMap::new();
-            obj.insert("inserted".to_string(), Datum::Number(1.0));
-            obj.insert("errors".to_string(), Datum::Number(0.0));
-            obj
-        }))
-    }
-    
-    async fn update(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        Ok(Datum::Object({
-            let mut obj = HashMap::new();
-            obj.insert("replaced".to_string(), Datum::Number(0.0));
-            obj.insert("unchanged".to_string(), Datum::Number(0.0));
-            obj.insert("errors".to_string(), Datum::Number(0.0));
-            obj
-        }))
-    }
-    
-    async fn replace(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        Ok(Datum::Object({
-            let mut obj = HashMap::new();
-            obj.insert("replaced".to_string(), Datum::Number(0.0));
-            obj.insert("errors".to_string(), Datum::Number(0.0));
-            obj
-        }))
-    }
-    
-    async fn delete(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        Ok(Datum::Object({
-            let mut obj = HashMap::new();
-            obj.insert("deleted".to_string(), Datum::Number(0.0));
-            obj.insert("errors".to_string(), Datum::Number(0.0));
-            obj
-        }))
+    /// CALL_PLUGIN: run a plugin function on the evaluated arguments
+    ///
+    /// Args are the plugin name, the function name, then the function's
+    /// arguments.
+    async fn call_plugin(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let plugins = self.plugins.as_ref()
+            .ok_or_else(|| anyhow!("CALL_PLUGIN: plugins are not enabled"))?;
+        
+        let mut names = Vec::with_capacity(2);
+        for (i, what) in ["plugin", "function"].iter().enumerate() {
+            let name_term = term.arg(i)
+                .ok_or_else(|| anyhow!("CALL_PLUGIN requires a {} name", what))?;
+            let name = self.execute_term(name_term, ctx).await?;
+            let name = name.as_string()
+                .ok_or_else(|| anyhow!("CALL_PLUGIN {} name must be a string", what))?
+                .to_string();
+            names.push(name);
+        }
+        
+        let mut args = Vec::with_capacity(term.args.len().saturating_sub(2));
+        for arg in term.args.iter().skip(2) {
+            args.push(self.execute_term(arg, ctx).await?);
+        }
+        
+        plugins.execute(&names[0], &names[1], args).await
+            .map_err(|e| anyhow!("{}.{} failed: {}", names[0], names[1], e))
     }
     
     // ========================================================================
-    // Math Operations
+    // Filtering & Selection
     // ========================================================================
     
-    async fn add(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let mut sum = 0.0;
-        for arg in &term.args {
-            let value = self.execute_term(arg, ctx).await?;
-            if let Some(n) = value.as_number() {
-                sum += n;
-            } else {
-                return Err(anyhow!("ADD requires numbers"));
+    async fn filter(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let source = term.arg(0).ok_or_else(|| anyhow!("FILTER requires sequence"))?;
+        let predicate = term.arg(1).ok_or_else(|| anyhow!("FILTER requires predicate"))?;
+        
+        let sequence = match self.filter_candidates(source, predicate, ctx).await? {
+            Some(candidates) => candidates,
+            None => match self.execute_term(source, ctx).await? {
+                Datum::Array(arr) => arr,
+                _ => return Err(anyhow!("FILTER requires sequence")),
+            },
+        };
+        
+        let mut filtered = Vec::new();
+        for item in sequence {
+            if self.filter_matches(predicate, &item, ctx).await? {
+                filtered.push(item);
             }
         }
-        Ok(Datum::Number(sum))
+        
+        Ok(Datum::Array(filtered))
     }
     
-    async fn sub(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.is_empty() {
-            return Err(anyhow!("SUB requires at least one argument"));
+    /// Documents a FILTER over a table has to test, if an index narrows them
+    ///
+    /// When the predicate requires a field to equal a value and that field is
+    /// the primary key or has a secondary index, only the documents holding
+    /// the value are read. Returns `None` when the whole table must be scanned.
+    async fn filter_candidates(
+        &self,
+        source: &Term,
+        predicate: &Term,
+        ctx: &mut ExecutionContext,
+    ) -> Result<Option<Vec<Datum>>> {
+        let fields = equality_fields(predicate);
+        if source.term_type != TermType::Table || fields.is_empty() {
+            return Ok(None);
         }
         
-        let first = self.execute_term(&term.args[0], ctx).await?;
-        let mut result = first.as_number()
-            .ok_or_else(|| anyhow!("SUB requires numbers"))?;
+        let (db, table) = self.resolve_table(source, ctx).await?;
+        let Some(info) = self.storage.get_table_info(&format!("{}.{}", db, table)).await
+            .map_err(|e| anyhow!("Failed to get table info: {}", e))?
+        else {
+            return Ok(None);
+        };
         
-        for arg in &term.args[1..] {
-            let value = self.execute_term(arg, ctx).await?;
-            if let Some(n) = value.as_number() {
-                result -= n;
-            } else {
-                return Err(anyhow!("SUB requires numbers"));
+        if let Some((_, id)) = fields.iter().find(|(field, _)| *field == info.primary_key) {
+            let doc = self.read_document(&Self::document_key(&db, &table, id), ctx).await?;
+            return Ok(Some(doc.into_iter().collect()));
+        }
+        let indexed = |field: &str| {
+            info.indexes.iter().any(|index| index == field) && !info.compound_indexes.contains_key(field)
+        };
+        match fields.iter().find(|(field, _)| indexed(field)) {
+            Some((index, value)) => {
+                let docs = self.storage.index_lookup(&db, &table, index, value).await
+                    .map_err(|e| anyhow!("Failed to read index `{}`: {}", index, e))?;
+                Ok(Some(docs))
             }
+            None => Ok(None),
         }
-        
-        Ok(Datum::Number(result))
     }
     
-    async fn mul(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let mut product = 1.0;
-        for arg in &term.args {
-            let value = self.execute_term(arg, ctx).await?;
-            if let Some(n) = value.as_number() {
-                product *= n;
-            } else {
-                return Err(anyhow!("MUL requires numbers"));
+    /// Whether `item` passes a FILTER predicate
+    ///
+    /// A FUNC is called with the item; a missing field counts as `false`.
+    /// Any other predicate is evaluated once: an object matches items having
+    /// all of its fields, anything else by its truthiness.
+    async fn filter_matches(&self, predicate: &Term, item: &Datum, ctx: &mut ExecutionContext) -> Result<bool> {
+        let result = if predicate.term_type == TermType::Func {
+            match self.call_func(predicate, vec![item.clone()], ctx).await {
+                Ok(result) => result,
+                Err(e) if is_non_existence(&e) => return Ok(false),
+                Err(e) => return Err(e),
             }
-        }
-        Ok(Datum::Number(product))
+        } else {
+            self.execute_term(predicate, ctx).await?
+        };
+        
+        Ok(match (&result, item.as_object()) {
+            (Datum::Object(pred_obj), Some(item_obj)) => {
+                pred_obj.iter().all(|(k, v)| item_obj.get(k) == Some(v))
+            }
+            (Datum::Object(_), None) => false,
+            _ => is_truthy(&result),
+        })
     }
     
-    async fn div(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("DIV requires exactly two arguments"));
-        }
+    async fn nth(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let index = term.arg(1)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .ok_or_else(|| anyhow!("NTH requires index"))? as i64;
         
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("DIV requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("DIV requires numbers"))?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("NTH requires sequence"))?;
         
-        if b == 0.0 {
-            return Err(anyhow!("Division by zero"));
+        let len = arr.len() as i64;
+        let position = if index < 0 { len + index } else { index };
+        if position < 0 || position >= len {
+            return Err(Error::NotFound(format!(
+                "Index out of bounds: {} (sequence has {} elements)",
+                index, len
+            )).into());
         }
         
-        Ok(Datum::Number(a / b))
+        Ok(arr[position as usize].clone())
     }
     
-    async fn mod_op(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("MOD requires exactly two arguments"));
-        }
-        
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("MOD requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("MOD requires numbers"))?;
+    /// BRACKET: NTH for a number, GET_FIELD otherwise
+    ///
+    /// Drivers send `value(key)` as BRACKET; the compiler already resolves
+    /// literal keys, so only computed ones get here.
+    async fn bracket(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let value = term.arg(0)
+            .ok_or_else(|| anyhow!("BRACKET requires a value"))?;
+        let key_term = term.arg(1)
+            .ok_or_else(|| anyhow!("BRACKET requires a field or index"))?;
+        let key = self.execute_term(key_term, ctx).await?;
         
-        Ok(Datum::Number(a % b))
+        let term_type = if key.as_number().is_some() { TermType::Nth } else { TermType::GetField };
+        let lookup = Term::new(term_type).with_args(vec![value.clone(), Term::datum(key)]);
+        self.execute_term(&lookup, ctx).await
     }
     
-    // ========================================================================
-    // Logic Operations
-    // ========================================================================
-    
-    async fn eq(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("EQ requires exactly two arguments"));
-        }
+    async fn limit(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let n = term.arg(1)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .ok_or_else(|| anyhow!("LIMIT requires number"))? as usize;
         
-        let a = self.execute_term(&term.args[0], ctx).await?;
-        let b = self.execute_term(&term.args[1], ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("LIMIT requires sequence"))?;
         
-        Ok(Datum::Boolean(a == b))
+        Ok(Datum::Array(arr.iter().take(n).cloned().collect()))
     }
     
-    async fn ne(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("NE requires exactly two arguments"));
-        }
+    async fn skip(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let n = term.arg(1)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .ok_or_else(|| anyhow!("SKIP requires number"))? as usize;
         
-        let a = self.execute_term(&term.args[0], ctx).await?;
-        let b = self.execute_term(&term.args[1], ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("SKIP requires sequence"))?;
         
-        Ok(Datum::Boolean(a != b))
+        Ok(Datum::Array(arr.iter().skip(n).cloned().collect()))
     }
     
-    async fn lt(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("LT requires exactly two arguments"));
-        }
+    async fn slice(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let start = term.arg(1)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .ok_or_else(|| anyhow!("SLICE requires start"))? as i64;
+        // An omitted end slices to the end of the sequence
+        let end = match term.arg(2) {
+            Some(end) => Some(end.as_datum()
+                .and_then(|d| d.as_number())
+                .ok_or_else(|| anyhow!("SLICE end must be a number"))? as i64),
+            None => None,
+        };
         
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("LT requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("LT requires numbers"))?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("SLICE requires sequence"))?;
+        
+        // Negative indices count from the end; out-of-range bounds are clamped
+        let len = arr.len() as i64;
+        let normalize = |index: i64| {
+            let index = if index < 0 { len + index } else { index };
+            index.clamp(0, len) as usize
+        };
+        let start = normalize(start);
+        let end = end.map_or(arr.len(), normalize);
         
-        Ok(Datum::Boolean(a < b))
+        if start >= end {
+            return Ok(Datum::Array(Vec::new()));
+        }
+        Ok(Datum::Array(arr[start..end].to_vec()))
     }
     
-    async fn le(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("LE requires exactly two arguments"));
-        }
-        
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("LE requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("LE requires numbers"))?;
-        
-        Ok(Datum::Boolean(a <= b))
+    // ========================================================================
+    // Transformations
+    // ========================================================================
+    
+    async fn map(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement MAP with function evaluation
+        Ok(Datum::Array(Vec::new()))
     }
     
-    async fn gt(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("GT requires exactly two arguments"));
+    async fn concat_map(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement CONCAT_MAP
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    /// ORDER_BY: sort a sequence in ascending ReQL order
+    ///
+    /// Each argument after the sequence is a field name or a FUNC of the
+    /// element; later keys break ties of earlier ones. The optarg `index`
+    /// sorts by that field before any keys. Over a TABLE with a secondary
+    /// index of that name, documents are read in index order rather than
+    /// sorted, which leaves out documents without the field.
+    async fn order_by(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence_term = term.arg(0)
+            .ok_or_else(|| anyhow!("ORDER_BY requires a sequence"))?;
+        let index = match term.optarg("index") {
+            Some(index) => Some(self.execute_term(index, ctx).await?
+                .as_string()
+                .ok_or_else(|| anyhow!("ORDER_BY index must be a string"))?
+                .to_string()),
+            None => None,
+        };
+        let keys = &term.args[1..];
+        if index.is_none() && keys.is_empty() {
+            return Err(anyhow!("ORDER_BY requires a field, function or index"));
         }
         
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("GT requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("GT requires numbers"))?;
+        let indexed = match &index {
+            Some(index) => self.secondary_index(sequence_term, index, ctx).await?
+                .map(|table| (index, table)),
+            None => None,
+        };
+        let items = match indexed {
+            Some((index, (db, table))) => {
+                let docs = self.storage.index_range(&db, &table, index, (&Bound::MinVal, false), (&Bound::MaxVal, false)).await
+                    .map_err(|e| anyhow!("Failed to read index `{}`: {}", index, e))?;
+                if keys.is_empty() {
+                    return Ok(Datum::Array(docs));
+                }
+                docs
+            }
+            None => match self.execute_term(sequence_term, ctx).await? {
+                Datum::Array(arr) => arr,
+                other => return Err(anyhow!("ORDER_BY requires sequence, got {}", other)),
+            },
+        };
         
-        Ok(Datum::Boolean(a > b))
-    }
-    
-    async fn ge(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        if term.args.len() != 2 {
-            return Err(anyhow!("GE requires exactly two arguments"));
+        // Field names are evaluated once, functions per element
+        let mut fields = Vec::with_capacity(keys.len());
+        for key in keys {
+            let field = if key.term_type == TermType::Func {
+                None
+            } else {
+                Some(self.execute_term(key, ctx).await?
+                    .as_string()
+                    .ok_or_else(|| anyhow!("ORDER_BY keys must be field names or functions"))?
+                    .to_string())
+            };
+            fields.push(field);
         }
+        let field_of = |item: &Datum, name: &str| {
+            item.as_object().and_then(|obj| obj.get(name)).cloned().unwrap_or(Datum::Null)
+        };
         
-        let a = self.execute_term(&term.args[0], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("GE requires numbers"))?;
-        let b = self.execute_term(&term.args[1], ctx).await?
-            .as_number()
-            .ok_or_else(|| anyhow!("GE requires numbers"))?;
+        let mut keyed = Vec::with_capacity(items.len());
+        for item in items {
+            let mut values = Vec::with_capacity(keys.len() + 1);
+            if let Some(index) = &index {
+                values.push(field_of(&item, index));
+            }
+            for (key, field) in keys.iter().zip(&fields) {
+                values.push(match field {
+                    Some(name) => field_of(&item, name),
+                    None => self.call_func(key, vec![item.clone()], ctx).await?,
+                });
+            }
+            keyed.push((values, item));
+        }
+        keyed.sort_by(|(a, _), (b, _)| {
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| x.reql_cmp(y))
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
         
-        Ok(Datum::Boolean(a >= b))
+        Ok(Datum::Array(keyed.into_iter().map(|(_, item)| item).collect()))
     }
     
-    async fn and(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        for arg in &term.args {
-            let value = self.execute_term(arg, ctx).await?;
-            if let Some(b) = value.as_bool() {
-                if !b {
-                    return Ok(Datum::Boolean(false));
-                }
-            } else {
-                return Err(anyhow!("AND requires booleans"));
+    async fn distinct(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("DISTINCT requires sequence"))?;
+        
+        // Over an index, the distinct values are the indexed field's values
+        let index = match term.optarg("index") {
+            Some(index) => {
+                let index = self.execute_term(index, ctx).await?;
+                let name = index.as_string()
+                    .ok_or_else(|| anyhow!("DISTINCT index must be a string"))?
+                    .to_string();
+                Some(name)
             }
-        }
-        Ok(Datum::Boolean(true))
+            None => None,
+        };
+        let values = arr.iter().filter_map(|item| match &index {
+            Some(name) => item.as_object().and_then(|obj| obj.get(name)),
+            None => Some(item),
+        });
+        
+        // Keep the first occurrence of each value, in sequence order
+        let mut seen = HashSet::with_capacity(arr.len());
+        let distinct = values
+            .filter(|value| seen.insert(value.canonical_key()))
+            .cloned()
+            .collect();
+        
+        Ok(Datum::Array(distinct))
     }
     
-    async fn or(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+    async fn union(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let mut results = Vec::new();
+        
         for arg in &term.args {
-            let value = self.execute_term(arg, ctx).await?;
-            if let Some(b) = value.as_bool() {
-                if b {
-                    return Ok(Datum::Boolean(true));
-                }
-            } else {
-                return Err(anyhow!("OR requires booleans"));
+            // Sequences are concatenated in order; other values join as one element
+            match self.execute_term(arg, ctx).await? {
+                Datum::Array(arr) => results.extend(arr),
+                value => results.push(value),
             }
         }
-        Ok(Datum::Boolean(false))
-    }
-    
-    async fn not(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let value = self.execute_term(term.arg(0).unwrap(), ctx).await?;
-        let b = value.as_bool()
-            .ok_or_else(|| anyhow!("NOT requires boolean"))?;
         
-        Ok(Datum::Boolean(!b))
+        Ok(Datum::Array(results))
     }
     
-    // ========================================================================
-    // Document Manipulation
-    // ========================================================================
-    
-    async fn get_field(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement GET_FIELD (access object field)
-        Ok(Datum::Null)
+    async fn sample(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let n = term.arg(1)
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_number())
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .ok_or_else(|| anyhow!("SAMPLE requires a non-negative integer"))? as usize;
+        
+        let Datum::Array(arr) = sequence else {
+            return Err(anyhow!("SAMPLE requires sequence"));
+        };
+        
+        let mut rng = match self.sample_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        
+        // Reservoir sampling: element i replaces a random slot with probability n / (i + 1)
+        let mut reservoir = Vec::with_capacity(n.min(arr.len()));
+        for (i, item) in arr.into_iter().enumerate() {
+            if i < n {
+                reservoir.push(item);
+            } else {
+                let slot = rng.gen_range(0..=i);
+                if slot < n {
+                    reservoir[slot] = item;
+                }
+            }
+        }
+        reservoir.shuffle(&mut rng);
+        
+        Ok(Datum::Array(reservoir))
     }
     
-    async fn has_fields(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement HAS_FIELDS
-        Ok(Datum::Boolean(false))
+    async fn pluck(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement PLUCK (select specific fields)
+        Ok(Datum::Array(Vec::new()))
     }
     
-    async fn keys(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement KEYS
+    async fn without(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement WITHOUT (remove specific fields)
         Ok(Datum::Array(Vec::new()))
     }
     
-    async fn values(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement VALUES
-        Ok(Datum::Array(Vec::new()))
+    async fn merge(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement MERGE (merge objects)
+        Ok(Datum::Object(HashMap::new()))
     }
     
     // ========================================================================
-    // Array Operations
+    // Aggregations
     // ========================================================================
     
-    async fn append(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement APPEND
-        Ok(Datum::Array(Vec::new()))
-    }
-    
-    async fn prepend(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement PREPEND
-        Ok(Datum::Array(Vec::new()))
+    /// COUNT: number of elements, or of those equal to a value or matching
+    /// a predicate; grouped data is counted per group
+    async fn count(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        if let Some(groups) = grouped_data(&sequence) {
+            let mut data = Vec::with_capacity(groups.len());
+            for pair in groups {
+                let (group, reduction) = group_pair(pair)?;
+                let count = self.count_items(reduction.clone(), term.arg(1), ctx).await?;
+                data.push(Datum::Array(vec![group.clone(), count]));
+            }
+            return Ok(grouped(data));
+        }
+        self.count_items(sequence, term.arg(1), ctx).await
     }
     
-    async fn difference(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement DIFFERENCE
-        Ok(Datum::Array(Vec::new()))
+    async fn count_items(&self, sequence: Datum, selector: Option<&Term>, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let items: Vec<Datum> = match sequence {
+            Datum::Array(arr) => arr,
+            Datum::Object(obj) => obj.into_values().collect(),
+            _ => return Err(anyhow!("COUNT requires sequence")),
+        };
+        
+        let Some(selector) = selector else {
+            return Ok(Datum::Number(items.len() as f64));
+        };
+        
+        let mut count = 0;
+        if selector.term_type == TermType::Func {
+            // Count elements the predicate holds for
+            for item in items {
+                let result = self.call_func(selector, vec![item], ctx).await?;
+                if is_truthy(&result) {
+                    count += 1;
+                }
+            }
+        } else {
+            // Count elements equal to the value
+            let value = self.execute_term(selector, ctx).await?;
+            count = items.iter().filter(|item| **item == value).count();
+        }
+        
+        Ok(Datum::Number(count as f64))
     }
     
-    async fn set_insert(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement SET_INSERT
-        Ok(Datum::Array(Vec::new()))
+    /// IS_EMPTY: whether a sequence has no elements
+    ///
+    /// A TABLE, or a FILTER over one, is read a page at a time and reading
+    /// stops at the first (matching) document, so even huge tables are
+    /// answered cheaply. Other sequences are evaluated in full.
+    async fn is_empty(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let source = term.arg(0).ok_or_else(|| anyhow!("IS_EMPTY requires sequence"))?;
+        let (table, predicate) = match source.term_type {
+            TermType::Table => (source, None),
+            TermType::Filter if source.arg(0).is_some_and(|t| t.term_type == TermType::Table) => {
+                let predicate = source.arg(1).ok_or_else(|| anyhow!("FILTER requires predicate"))?;
+                (&source.args[0], Some(predicate))
+            }
+            _ => {
+                return match self.execute_term(source, ctx).await? {
+                    Datum::Array(arr) => Ok(Datum::Boolean(arr.is_empty())),
+                    _ => Err(anyhow!("IS_EMPTY requires sequence")),
+                };
+            }
+        };
+        
+        if let Some(predicate) = predicate {
+            if let Some(candidates) = self.filter_candidates(table, predicate, ctx).await? {
+                for item in &candidates {
+                    if self.filter_matches(predicate, item, ctx).await? {
+                        return Ok(Datum::Boolean(false));
+                    }
+                }
+                return Ok(Datum::Boolean(true));
+            }
+        }
+        
+        // Without a predicate the first document decides
+        let page_size = if predicate.is_some() { IS_EMPTY_PAGE_SIZE } else { 1 };
+        let (db, table_name) = self.resolve_table(table, ctx).await?;
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let page = self.storage.scan_table_page(&db, &table_name, after.as_deref(), page_size).await
+                .map_err(|e| anyhow!("Failed to scan table: {}", e))?;
+            let exhausted = page.len() < page_size;
+            for (key, doc) in page {
+                let matches = match predicate {
+                    Some(predicate) => self.filter_matches(predicate, &doc, ctx).await?,
+                    None => true,
+                };
+                if matches {
+                    return Ok(Datum::Boolean(false));
+                }
+                after = Some(key);
+            }
+            if exhausted {
+                return Ok(Datum::Boolean(true));
+            }
+        }
     }
     
-    async fn set_union(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement SET_UNION
-        Ok(Datum::Array(Vec::new()))
+    async fn sum(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("SUM requires sequence"))?;
+        
+        let values = self.select_numbers(arr, term.arg(1), "SUM", ctx).await?;
+        
+        // Whole numbers sum exactly to an integer unless that overflows
+        let integers = values.iter()
+            .try_fold(0i64, |sum, (_, n)| sum.checked_add(n.as_integer()?));
+        Ok(match integers {
+            Some(sum) => Datum::Integer(sum),
+            None => Datum::Number(values.iter().filter_map(|(_, n)| n.as_number()).sum()),
+        })
     }
     
-    async fn set_intersection(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement SET_INTERSECTION
-        Ok(Datum::Array(Vec::new()))
+    async fn avg(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("AVG requires sequence"))?;
+        
+        let values = self.select_numbers(arr, term.arg(1), "AVG", ctx).await?;
+        if values.is_empty() {
+            return Err(anyhow!("AVG on empty sequence"));
+        }
+        
+        let sum: f64 = values.iter().filter_map(|(_, n)| n.as_number()).sum();
+        
+        Ok(Datum::Number(sum / values.len() as f64))
     }
     
-    async fn set_difference(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement SET_DIFFERENCE
-        Ok(Datum::Array(Vec::new()))
+    async fn min(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("MIN requires sequence"))?;
+        
+        self.select_numbers(arr, term.arg(1), "MIN", ctx).await?
+            .into_iter()
+            .min_by(|(_, a), (_, b)| a.reql_cmp(b))
+            .map(|(i, _)| arr[i].clone())
+            .ok_or_else(|| anyhow!("MIN on empty sequence"))
     }
     
-    async fn insert_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement INSERT_AT
-        Ok(Datum::Array(Vec::new()))
+    async fn max(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let arr = sequence.as_array()
+            .ok_or_else(|| anyhow!("MAX requires sequence"))?;
+        
+        // max_by keeps the last maximum; RethinkDB returns the first
+        self.select_numbers(arr, term.arg(1), "MAX", ctx).await?
+            .into_iter()
+            .rev()
+            .max_by(|(_, a), (_, b)| a.reql_cmp(b))
+            .map(|(i, _)| arr[i].clone())
+            .ok_or_else(|| anyhow!("MAX on empty sequence"))
     }
     
-    async fn delete_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement DELETE_AT
-        Ok(Datum::Array(Vec::new()))
+    /// Select the value each element of an aggregation contributes
+    ///
+    /// Returns (element index, value) pairs. Without a selector every
+    /// element is the value. A field name skips elements lacking the field;
+    /// a FUNC is applied to every element. Selected values must be numbers,
+    /// and not NaN, which has no place in ReQL's order; integers are kept
+    /// as they are, so they aggregate exactly.
+    async fn select_numbers(
+        &self,
+        arr: &[Datum],
+        selector: Option<&Term>,
+        op: &str,
+        ctx: &mut ExecutionContext,
+    ) -> Result<Vec<(usize, Datum)>> {
+        let number = |value: &Datum| match value.as_number() {
+            Some(n) if n.is_nan() => Err(anyhow!("{} cannot aggregate NaN", op)),
+            Some(_) => Ok(value.clone()),
+            None => Err(anyhow!("{} expected a number, got {}", op, value)),
+        };
+        let Some(selector) = selector else {
+            return arr.iter()
+                .enumerate()
+                .map(|(i, d)| Ok((i, number(d)?)))
+                .collect();
+        };
+        
+        let mut values = Vec::with_capacity(arr.len());
+        if selector.term_type == TermType::Func {
+            for (i, item) in arr.iter().enumerate() {
+                let value = self.call_func(selector, vec![item.clone()], ctx).await?;
+                values.push((i, number(&value)?));
+            }
+        } else {
+            let field = self.execute_term(selector, ctx).await?;
+            let field = field.as_string()
+                .ok_or_else(|| anyhow!("{} selector must be a field name or function", op))?;
+            for (i, item) in arr.iter().enumerate() {
+                let obj = item.as_object()
+                    .ok_or_else(|| anyhow!("{} by field requires objects, got {}", op, item))?;
+                if let Some(value) = obj.get(field) {
+                    let n = number(value).map_err(|e| anyhow!("{} in field `{}`", e, field))?;
+                    values.push((i, n));
+                }
+            }
+        }
+        
+        Ok(values)
     }
     
-    async fn change_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement CHANGE_AT
-        Ok(Datum::Array(Vec::new()))
+    /// GROUP: partition a sequence by fields or functions
+    ///
+    /// Elements with equal keys share a group; with several keys the group
+    /// is the array of their values. The result is grouped data, ordered by
+    /// group, which COUNT reduces per group and UNGROUP turns into an array.
+    async fn group(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let sequence_term = term.arg(0)
+            .ok_or_else(|| anyhow!("GROUP requires a sequence"))?;
+        let items = match self.execute_term(sequence_term, ctx).await? {
+            Datum::Array(arr) => arr,
+            other => return Err(anyhow!("GROUP requires sequence, got {}", other)),
+        };
+        let keys = &term.args[1..];
+        if keys.is_empty() {
+            return Err(anyhow!("GROUP requires a field or function"));
+        }
+        
+        // Field names are evaluated once, functions per element
+        let mut fields = Vec::with_capacity(keys.len());
+        for key in keys {
+            let field = if key.term_type == TermType::Func {
+                None
+            } else {
+                Some(self.execute_term(key, ctx).await?
+                    .as_string()
+                    .ok_or_else(|| anyhow!("GROUP keys must be field names or functions"))?
+                    .to_string())
+            };
+            fields.push(field);
+        }
+        
+        let mut keyed = Vec::with_capacity(items.len());
+        for item in items {
+            let mut values = Vec::with_capacity(keys.len());
+            for (key, field) in keys.iter().zip(&fields) {
+                values.push(match field {
+                    // Elements without the field are grouped under null
+                    Some(name) => item.as_object()
+                        .and_then(|obj| obj.get(name))
+                        .cloned()
+                        .unwrap_or(Datum::Null),
+                    None => self.call_func(key, vec![item.clone()], ctx).await?,
+                });
+            }
+            let group = match values.len() {
+                1 => values.pop().unwrap(),
+                _ => Datum::Array(values),
+            };
+            keyed.push((group, item));
+        }
+        keyed.sort_by(|(a, _), (b, _)| a.reql_cmp(b));
+        
+        let mut groups: Vec<(Datum, Vec<Datum>)> = Vec::new();
+        for (group, item) in keyed {
+            match groups.last_mut() {
+                Some((last, members)) if *last == group => members.push(item),
+                _ => groups.push((group, vec![item])),
+            }
+        }
+        Ok(grouped(groups.into_iter()
+            .map(|(group, members)| Datum::Array(vec![group, Datum::Array(members)]))
+            .collect()))
     }
     
-    async fn splice_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement SPLICE_AT
-        Ok(Datum::Array(Vec::new()))
+    /// UNGROUP: grouped data as an array of `{"group", "reduction"}` objects
+    async fn ungroup(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let grouped_term = term.arg(0)
+            .ok_or_else(|| anyhow!("UNGROUP requires grouped data"))?;
+        let value = self.execute_term(grouped_term, ctx).await?;
+        if grouped_data(&value).is_none() {
+            return Err(anyhow!("UNGROUP requires grouped data, got {}", type_name(&value)));
+        }
+        coerce(value, "ARRAY")
     }
     
-    async fn contains(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement CONTAINS
-        Ok(Datum::Boolean(false))
+    async fn reduce(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement REDUCE with function evaluation
+        Ok(Datum::Null)
     }
     
     // ========================================================================
-    // Control Flow
+    // Write Operations
     // ========================================================================
     
-    async fn branch(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        // IF condition THEN true_branch ELSE false_branch
-        let condition = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+    /// INSERT: add documents to a table
+    ///
+    /// A document whose primary key is taken counts as an error, unless the
+    /// optarg `upsert: true` is given. Then it is merged into the stored
+    /// document like an UPDATE would, and counts as `replaced` (or
+    /// `unchanged`) instead. `upsert` is not a `conflict` mode and cannot be
+    /// combined with one.
+    async fn insert(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        // Checked before anything is written
+        let upsert = match term.optarg("upsert") {
+            Some(upsert) => self.execute_term(upsert, ctx).await?.as_bool()
+                .ok_or_else(|| anyhow!("INSERT upsert must be a boolean"))?,
+            None => false,
+        };
+        if upsert && term.optarg("conflict").is_some() {
+            return Err(anyhow!("INSERT upsert cannot be combined with conflict"));
+        }
+        
+        let table_term = term.arg(0)
+            .ok_or_else(|| anyhow!("INSERT requires table"))?;
+        let (db, table) = self.resolve_table(table_term, ctx).await?;
+        
+        let docs_term = term.arg(1)
+            .ok_or_else(|| anyhow!("INSERT requires documents"))?;
+        let docs = match self.execute_term(docs_term, ctx).await? {
+            Datum::Array(arr) => arr,
+            obj @ Datum::Object(_) => vec![obj],
+            other => return Err(anyhow!("INSERT requires object or array, got {}", other)),
+        };
+        
+        let primary_key = self.storage.get_table_info(&format!("{}.{}", db, table)).await
+            .map_err(|e| anyhow!("Failed to get table info: {}", e))?
+            .map(|info| info.primary_key)
+            .unwrap_or_else(|| "id".to_string());
+        
+        // Optional time-to-live in seconds
+        let ttl = match term.optarg("ttl") {
+            Some(t) => {
+                let ttl = t.as_datum()
+                    .and_then(|d| d.as_number())
+                    .filter(|n| *n > 0.0)
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .ok_or_else(|| anyhow!("INSERT ttl must be a positive number of seconds"))?;
+                Some(ttl)
+            }
+            None => None,
+        };
+        
+        let mut inserted = 0;
+        let mut replaced = 0;
+        let mut unchanged = 0;
+        let mut errors = 0;
+        let mut first_error = None;
+        let mut generated_keys = Vec::new();
+        
+        for doc in docs {
+            if let Err(e) = ttl::check_reserved(&doc) {
+                errors += 1;
+                first_error.get_or_insert_with(|| e.to_string());
+                continue;
+            }
+            let Datum::Object(mut obj) = doc else {
+                errors += 1;
+                first_error.get_or_insert_with(|| "Expected type OBJECT".to_string());
+                continue;
+            };
+            
+            let id = match obj.get(&primary_key) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = Datum::String(uuid::Uuid::new_v4().to_string());
+                    obj.insert(primary_key.clone(), id.clone());
+                    generated_keys.push(id.clone());
+                    id
+                }
+            };
+            
+            let key = Self::document_key(&db, &table, &id);
+            let doc = match self.read_document(&key, ctx).await? {
+                Some(existing) if upsert => {
+                    let merged = Self::merge_fields(existing.clone(), obj);
+                    if merged == existing {
+                        unchanged += 1;
+                        continue;
+                    }
+                    replaced += 1;
+                    merged
+                }
+                Some(_) => {
+                    errors += 1;
+                    first_error.get_or_insert_with(|| format!("Duplicate primary key `{}`: {}", primary_key, id));
+                    continue;
+                }
+                None => {
+                    inserted += 1;
+                    Datum::Object(obj)
+                }
+            };
+            
+            let doc = match ttl {
+                Some(ttl) => ttl::with_expiry(doc, ttl)?,
+                None => doc,
+            };
+            self.write_document(&key, doc, ctx).await?;
+        }
+        
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("inserted".to_string(), Datum::Number(inserted as f64));
+            if upsert {
+                obj.insert("replaced".to_string(), Datum::Number(replaced as f64));
+                obj.insert("unchanged".to_string(), Datum::Number(unchanged as f64));
+            }
+            obj.insert("errors".to_string(), Datum::Number(errors as f64));
+            if !generated_keys.is_empty() {
+                obj.insert("generated_keys".to_string(), Datum::Array(generated_keys));
+            }
+            if let Some(err) = first_error {
+                obj.insert("first_error".to_string(), Datum::String(err));
+            }
+            obj
+        }))
+    }
+    
+    /// UPDATE: change the selected documents
+    ///
+    /// The change is an object merged into each document, or a FUNC called
+    /// with the document returning that object. With the optarg
+    /// `patch_format: "jsonpatch"` it is instead a JSON Patch (RFC 6902)
+    /// applied to each document; a patch that fails, including a failed
+    /// `test` operation, leaves that document unchanged and counts as an
+    /// error.
+    async fn update(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let selection = term.arg(0)
+            .ok_or_else(|| anyhow!("UPDATE requires a selection"))?;
+        let change = term.arg(1)
+            .ok_or_else(|| anyhow!("UPDATE requires an update document"))?;
+        
+        let patch = match term.optarg("patch_format") {
+            Some(format) => match self.execute_term(format, ctx).await?.as_string() {
+                Some("jsonpatch") => Some(JsonPatch::from_datum(&self.execute_term(change, ctx).await?)
+                    .map_err(|e| anyhow!("Invalid JSON Patch: {}", e))?),
+                _ => return Err(anyhow!("UPDATE patch_format must be \"jsonpatch\"")),
+            },
+            None => None,
+        };
+        
+        // The documents belong to the table at the root of the selection
+        let mut table_term = selection;
+        while table_term.term_type != TermType::Table {
+            table_term = table_term.arg(0)
+                .ok_or_else(|| anyhow!("UPDATE requires a selection of a table"))?;
+        }
+        let (db, table) = self.resolve_table(table_term, ctx).await?;
+        let primary_key = self.storage.get_table_info(&format!("{}.{}", db, table)).await
+            .map_err(|e| anyhow!("Failed to get table info: {}", e))?
+            .map(|info| info.primary_key)
+            .unwrap_or_else(|| "id".to_string());
+        
+        let docs = match self.execute_term(selection, ctx).await? {
+            Datum::Array(arr) => arr,
+            obj @ Datum::Object(_) => vec![obj],
+            Datum::Null => Vec::new(),
+            other => return Err(anyhow!("UPDATE requires a selection, got {}", other)),
+        };
+        
+        let mut replaced = 0;
+        let mut unchanged = 0;
+        let mut errors = 0;
+        let mut first_error = None;
+        
+        for doc in docs {
+            let updated = match &patch {
+                Some(patch) => patch.apply(&doc),
+                None => {
+                    let value = if change.term_type == TermType::Func {
+                        self.call_func(change, vec![doc.clone()], ctx).await?
+                    } else {
+                        self.execute_term(change, ctx).await?
+                    };
+                    match value {
+                        Datum::Null => Ok(doc.clone()),
+                        Datum::Object(fields) => Ok(Self::merge_fields(doc.clone(), fields)),
+                        other => Err(anyhow!("Expected type OBJECT but found {}", other)),
+                    }
+                }
+            };
+            let updated = updated.and_then(|updated| {
+                ttl::check_reserved(&updated)?;
+                let id = doc.as_object().and_then(|obj| obj.get(&primary_key));
+                let new_id = updated.as_object().and_then(|obj| obj.get(&primary_key));
+                match (id, new_id) {
+                    (Some(id), Some(new_id)) if id == new_id => Ok((id.clone(), updated)),
+                    (Some(_), _) => Err(anyhow!("Primary key `{}` cannot be changed", primary_key)),
+                    (None, _) => Err(anyhow!("Expected a document with primary key `{}`", primary_key)),
+                }
+            });
+            
+            match updated {
+                Ok((_, updated)) if updated == doc => unchanged += 1,
+                Ok((id, updated)) => {
+                    let key = Self::document_key(&db, &table, &id);
+                    self.write_document(&key, updated, ctx).await?;
+                    replaced += 1;
+                }
+                Err(e) => {
+                    errors += 1;
+                    first_error.get_or_insert_with(|| e.to_string());
+                }
+            }
+        }
+        
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("replaced".to_string(), Datum::Number(replaced as f64));
+            obj.insert("unchanged".to_string(), Datum::Number(unchanged as f64));
+            obj.insert("errors".to_string(), Datum::Number(errors as f64));
+            if let Some(err) = first_error {
+                obj.insert("first_error".to_string(), Datum::String(err));
+            }
+            obj
+        }))
+    }
+    
+    /// Merge `fields` into `doc`, recursing into nested objects
+    fn merge_fields(doc: Datum, fields: HashMap<String, Datum>) -> Datum {
+        let Datum::Object(mut obj) = doc else {
+            return Datum::Object(fields);
+        };
+        for (key, value) in fields {
+            let merged = match (obj.remove(&key), value) {
+                (Some(existing @ Datum::Object(_)), Datum::Object(nested)) => Self::merge_fields(existing, nested),
+                (_, value) => value,
+            };
+            obj.insert(key, merged);
+        }
+        Datum::Object(obj)
+    }
+    
+    async fn replace(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("replaced".to_string(), Datum::Number(0.0));
+            obj.insert("errors".to_string(), Datum::Number(0.0));
+            obj
+        }))
+    }
+    
+    async fn delete(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        Ok(Datum::Object({
+            let mut obj = HashMap::new();
+            obj.insert("deleted".to_string(), Datum::Number(0.0));
+            obj.insert("errors".to_string(), Datum::Number(0.0));
+            obj
+        }))
+    }
+    
+    // ========================================================================
+    // Math Operations
+    // ========================================================================
+    
+    async fn add(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let mut sum = Datum::Integer(0);
+        for arg in &term.args {
+            let value = self.execute_term(arg, ctx).await?;
+            sum = arith(&sum, &value, i64::checked_add, |a, b| a + b)
+                .ok_or_else(|| anyhow!("ADD requires numbers"))?;
+        }
+        Ok(sum)
+    }
+    
+    async fn sub(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        if term.args.is_empty() {
+            return Err(anyhow!("SUB requires at least one argument"));
+        }
+        
+        let mut result = self.execute_term(&term.args[0], ctx).await?;
+        if result.as_number().is_none() {
+            return Err(anyhow!("SUB requires numbers"));
+        }
+        
+        for arg in &term.args[1..] {
+            let value = self.execute_term(arg, ctx).await?;
+            result = arith(&result, &value, i64::checked_sub, |a, b| a - b)
+                .ok_or_else(|| anyhow!("SUB requires numbers"))?;
+        }
+        
+        Ok(result)
+    }
+    
+    async fn mul(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let mut product = Datum::Integer(1);
+        for arg in &term.args {
+            let value = self.execute_term(arg, ctx).await?;
+            product = arith(&product, &value, i64::checked_mul, |a, b| a * b)
+                .ok_or_else(|| anyhow!("MUL requires numbers"))?;
+        }
+        Ok(product)
+    }
+    
+    async fn div(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        if term.args.len() != 2 {
+            return Err(anyhow!("DIV requires exactly two arguments"));
+        }
+        
+        let a = self.execute_term(&term.args[0], ctx).await?
+            .as_number()
+            .ok_or_else(|| anyhow!("DIV requires numbers"))?;
+        let b = self.execute_term(&term.args[1], ctx).await?
+            .as_number()
+            .ok_or_else(|| anyhow!("DIV requires numbers"))?;
+        
+        if b == 0.0 {
+            return Err(anyhow!("Division by zero"));
+        }
+        
+        Ok(Datum::Number(a / b))
+    }
+    
+    async fn mod_op(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        if term.args.len() != 2 {
+            return Err(anyhow!("MOD requires exactly two arguments"));
+        }
+        
+        let a = self.execute_term(&term.args[0], ctx).await?;
+        let b = self.execute_term(&term.args[1], ctx).await?;
+        
+        arith(&a, &b, i64::checked_rem, |a, b| a % b)
+            .ok_or_else(|| anyhow!("MOD requires numbers"))
+    }
+    
+    // ========================================================================
+    // Logic Operations
+    // ========================================================================
+    
+    /// EQ: whether all arguments are equal
+    async fn eq(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let values = self.comparison_args(term, ctx).await?;
+        Ok(Datum::Boolean(values.windows(2).all(|pair| pair[0] == pair[1])))
+    }
+    
+    /// NE: whether the arguments are not all equal
+    async fn ne(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let values = self.comparison_args(term, ctx).await?;
+        Ok(Datum::Boolean(!values.windows(2).all(|pair| pair[0] == pair[1])))
+    }
+    
+    async fn lt(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        self.compare_numbers(term, |a, b| a < b, ctx).await
+    }
+    
+    async fn le(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        self.compare_numbers(term, |a, b| a <= b, ctx).await
+    }
+    
+    async fn gt(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        self.compare_numbers(term, |a, b| a > b, ctx).await
+    }
+    
+    async fn ge(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        self.compare_numbers(term, |a, b| a >= b, ctx).await
+    }
+    
+    /// Evaluated arguments of a comparison, which takes two or more
+    async fn comparison_args(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Vec<Datum>> {
+        if term.args.len() < 2 {
+            return Err(anyhow!("{} requires at least two arguments", term.term_type.name()));
+        }
+        
+        let mut values = Vec::with_capacity(term.args.len());
+        for arg in &term.args {
+            values.push(self.execute_term(arg, ctx).await?);
+        }
+        Ok(values)
+    }
+    
+    /// Whether `holds` for every adjacent pair of numeric arguments, so
+    /// `r.lt(a, b, c)` means `a < b < c`
+    async fn compare_numbers(
+        &self,
+        term: &Term,
+        holds: fn(f64, f64) -> bool,
+        ctx: &mut ExecutionContext,
+    ) -> Result<Datum> {
+        let values = self.comparison_args(term, ctx).await?;
+        let numbers = values.iter()
+            .map(|value| value.as_number()
+                .ok_or_else(|| anyhow!("{} requires numbers", term.term_type.name())))
+            .collect::<Result<Vec<f64>>>()?;
+        
+        Ok(Datum::Boolean(numbers.windows(2).all(|pair| holds(pair[0], pair[1]))))
+    }
+    
+    async fn and(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        for arg in &term.args {
+            let value = self.execute_term(arg, ctx).await?;
+            if let Some(b) = value.as_bool() {
+                if !b {
+                    return Ok(Datum::Boolean(false));
+                }
+            } else {
+                return Err(anyhow!("AND requires booleans"));
+            }
+        }
+        Ok(Datum::Boolean(true))
+    }
+    
+    async fn or(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        for arg in &term.args {
+            let value = self.execute_term(arg, ctx).await?;
+            if let Some(b) = value.as_bool() {
+                if b {
+                    return Ok(Datum::Boolean(true));
+                }
+            } else {
+                return Err(anyhow!("OR requires booleans"));
+            }
+        }
+        Ok(Datum::Boolean(false))
+    }
+    
+    async fn not(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let value = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let b = value.as_bool()
+            .ok_or_else(|| anyhow!("NOT requires boolean"))?;
+        
+        Ok(Datum::Boolean(!b))
+    }
+    
+    // ========================================================================
+    // Document Manipulation
+    // ========================================================================
+    
+    async fn get_field(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let value = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        let field = term.arg(1).ok_or_else(|| anyhow!("GET_FIELD requires field"))?;
+        let field = self.execute_term(field, ctx).await?;
+        let field = field.as_string()
+            .ok_or_else(|| anyhow!("GET_FIELD requires field name"))?;
+        
+        // Null and non-objects have no fields, so DEFAULT catches both
+        value.as_object()
+            .ok_or_else(|| Error::NotFound(format!("Cannot perform get_field on a non-object `{}`", value)))?
+            .get(field)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("No attribute `{}` in object", field)).into())
+    }
+    
+    async fn has_fields(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement HAS_FIELDS
+        Ok(Datum::Boolean(false))
+    }
+    
+    async fn keys(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement KEYS
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn values(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement VALUES
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    // ========================================================================
+    // Array Operations
+    // ========================================================================
+    
+    async fn append(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement APPEND
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn prepend(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement PREPEND
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn difference(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement DIFFERENCE
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn set_insert(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement SET_INSERT
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn set_union(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement SET_UNION
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn set_intersection(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement SET_INTERSECTION
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn set_difference(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement SET_DIFFERENCE
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn insert_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement INSERT_AT
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn delete_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement DELETE_AT
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn change_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement CHANGE_AT
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn splice_at(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement SPLICE_AT
+        Ok(Datum::Array(Vec::new()))
+    }
+    
+    async fn contains(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement CONTAINS
+        Ok(Datum::Boolean(false))
+    }
+    
+    // ========================================================================
+    // Control Flow
+    // ========================================================================
+    
+    async fn branch(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        // BRANCH(c1, v1, c2, v2, ..., default): the value of the first truthy
+        // condition, else the default
+        if term.args.len() < 3 || term.args.len().is_multiple_of(2) {
+            return Err(anyhow!(
+                "BRANCH requires an odd number of arguments (at least 3), got {}",
+                term.args.len()
+            ));
+        }
+        
+        for pair in term.args[..term.args.len() - 1].chunks(2) {
+            let condition = self.execute_term(&pair[0], ctx).await
+                .context("BRANCH condition failed")?;
+            if is_truthy(&condition) {
+                return self.execute_term(&pair[1], ctx).await;
+            }
+        }
+        
+        self.execute_term(term.args.last().unwrap(), ctx).await
+    }
+    
+    async fn for_each(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
+        // TODO: Implement FOR_EACH
+        Ok(Datum::Null)
+    }
+    
+    /// FUNCALL (`r.do`): call the function in the first argument with the
+    /// values of the others
+    ///
+    /// `r.expr(x).do(f)` arrives in the same shape as `r.do(x, f)`. A value
+    /// in place of the function is returned as is.
+    async fn func_call(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let func = term.arg(0)
+            .ok_or_else(|| anyhow!("FUNCALL requires a function"))?;
+        
+        let mut args = Vec::with_capacity(term.args.len() - 1);
+        for arg in &term.args[1..] {
+            args.push(self.execute_term(arg, ctx).await?);
+        }
+        
+        if func.term_type == TermType::Func {
+            self.call_func(func, args, ctx).await
+        } else {
+            self.execute_term(func, ctx).await
+        }
+    }
+    
+    async fn default(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let fallback = term.arg(1).ok_or_else(|| anyhow!("DEFAULT requires fallback"))?;
+        
+        // Only null and non-existence errors fall back; other errors propagate
+        let error = match self.execute_term(term.arg(0).unwrap(), ctx).await {
+            Ok(Datum::Null) => Datum::Null,
+            Ok(value) => return Ok(value),
+            Err(e) if is_non_existence(&e) => Datum::String(e.to_string()),
+            Err(e) => return Err(e),
+        };
+        
+        // A function fallback receives the error message (or null)
+        if fallback.term_type == TermType::Func {
+            self.call_func(fallback, vec![error], ctx).await
+        } else {
+            self.execute_term(fallback, ctx).await
+        }
+    }
+    
+    /// Apply a FUNC term to arguments
+    ///
+    /// FUNC args are the parameter variable ids and the body. Parameters are
+    /// bound for the duration of the body and any shadowed bindings restored.
+    async fn call_func(&self, func: &Term, args: Vec<Datum>, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let params = func.arg(0).ok_or_else(|| anyhow!("FUNC requires parameters"))?;
+        let body = func.arg(1).ok_or_else(|| anyhow!("FUNC requires body"))?;
+        
+        let ids: Option<Vec<f64>> = match params.as_datum() {
+            Some(Datum::Array(ids)) => ids.iter().map(|d| d.as_number()).collect(),
+            Some(_) => None,
+            None => params.args.iter()
+                .map(|t| t.as_datum().and_then(|d| d.as_number()))
+                .collect(),
+        };
+        let ids: Vec<u64> = ids
+            .ok_or_else(|| anyhow!("FUNC parameters must be variable ids"))?
+            .into_iter()
+            .map(|id| id as u64)
+            .collect();
+        
+        if ids.len() != args.len() {
+            return Err(anyhow!(
+                "Expected function with {} arguments but found function with {} arguments",
+                args.len(),
+                ids.len()
+            ));
+        }
+        
+        let shadowed: Vec<(u64, Option<Datum>)> = ids.iter()
+            .zip(args)
+            .map(|(id, arg)| (*id, ctx.variables.insert(*id, arg)))
+            .collect();
+        
+        let result = self.execute_term(body, ctx).await;
+        
+        for (id, previous) in shadowed.into_iter().rev() {
+            match previous {
+                Some(value) => ctx.bind_var(id, value),
+                None => {
+                    ctx.variables.remove(&id);
+                }
+            }
+        }
+        
+        result
+    }
+    
+    // ========================================================================
+    // Type Operations
+    // ========================================================================
+    
+    async fn type_of(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let value = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+        Ok(Datum::String(type_name(&value).to_string()))
+    }
+    
+    /// COERCE_TO: convert a value to the type named by the second argument
+    ///
+    /// Type names are case-insensitive. Objects convert to arrays of
+    /// `[key, value]` pairs and back. Grouped data converts to an array of
+    /// `{"group": ..., "reduction": ...}` objects, or to an object if its
+    /// groups are strings.
+    async fn coerce_to(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
+        let value_term = term.arg(0)
+            .ok_or_else(|| anyhow!("COERCE_TO requires a value"))?;
+        let type_term = term.arg(1)
+            .ok_or_else(|| anyhow!("COERCE_TO requires a type"))?;
+        
+        let value = self.execute_term(value_term, ctx).await?;
+        let target = self.execute_term(type_term, ctx).await?;
+        let target = target.as_string()
+            .ok_or_else(|| anyhow!("COERCE_TO type must be a string, got {}", target))?;
+        
+        coerce(value, &target.to_ascii_uppercase())
+    }
+}
+
+/// Readiness flags of STATUS, from weakest to strongest
+const READINESS_FLAGS: [&str; 4] = [
+    "ready_for_outdated_reads",
+    "ready_for_reads",
+    "ready_for_writes",
+    "all_replicas_ready",
+];
+
+/// How often WAIT re-checks readiness
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// `$reql_type$` of the result of GROUP
+const GROUPED_DATA: &str = "GROUPED_DATA";
+
+/// ReQL type name of a value, as TYPE_OF reports it
+fn type_name(datum: &Datum) -> &'static str {
+    match datum {
+        Datum::Null => "NULL",
+        Datum::Boolean(_) => "BOOL",
+        Datum::Integer(_) | Datum::Number(_) => "NUMBER",
+        Datum::String(_) => "STRING",
+        Datum::Array(_) => "ARRAY",
+        Datum::Object(_) => "OBJECT",
+    }
+}
+
+/// The `[group, reduction]` pairs of grouped data, `None` for anything else
+fn grouped_data(datum: &Datum) -> Option<&Vec<Datum>> {
+    let obj = datum.as_object()?;
+    if obj.get("$reql_type$")?.as_string()? != GROUPED_DATA {
+        return None;
+    }
+    obj.get("data")?.as_array()
+}
+
+/// Grouped data of `[group, reduction]` pairs
+fn grouped(data: Vec<Datum>) -> Datum {
+    Datum::Object(HashMap::from([
+        ("$reql_type$".to_string(), Datum::String(GROUPED_DATA.to_string())),
+        ("data".to_string(), Datum::Array(data)),
+    ]))
+}
+
+/// Convert `value` to the (upper-case) type `target`, see COERCE_TO
+fn coerce(value: Datum, target: &str) -> Result<Datum> {
+    if let Some(pairs) = grouped_data(&value) {
+        return match target {
+            "ARRAY" => pairs.iter()
+                .map(|pair| {
+                    let (group, reduction) = group_pair(pair)?;
+                    // Reductions may be grouped data themselves
+                    let reduction = match grouped_data(reduction) {
+                        Some(_) => coerce(reduction.clone(), "ARRAY")?,
+                        None => reduction.clone(),
+                    };
+                    Ok(Datum::Object(HashMap::from([
+                        ("group".to_string(), group.clone()),
+                        ("reduction".to_string(), reduction),
+                    ])))
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(Datum::Array),
+            "OBJECT" => pairs_to_object(pairs),
+            _ => Err(anyhow!("Cannot coerce {} to {}", GROUPED_DATA, target)),
+        };
+    }
+    
+    let source = type_name(&value);
+    if source == target {
+        return Ok(value);
+    }
+    match (value, target) {
+        (Datum::Object(obj), "ARRAY") => {
+            let mut pairs: Vec<(String, Datum)> = obj.into_iter().collect();
+            pairs.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(Datum::Array(pairs.into_iter()
+                .map(|(key, value)| Datum::Array(vec![Datum::String(key), value]))
+                .collect()))
+        }
+        (Datum::Array(arr), "OBJECT") => pairs_to_object(&arr),
+        (Datum::String(s), "NUMBER") => {
+            let trimmed = s.trim();
+            if let Ok(i) = trimmed.parse::<i64>() {
+                return Ok(Datum::Integer(i));
+            }
+            trimmed.parse::<f64>().ok()
+                .filter(|n| n.is_finite())
+                .map(Datum::Number)
+                .ok_or_else(|| anyhow!("Could not coerce `{}` to NUMBER", s))
+        }
+        // Anything else prints as JSON
+        (value, "STRING") => Ok(Datum::String(serde_json::Value::from(value).to_string())),
+        (_, "NULL" | "BOOL" | "NUMBER" | "ARRAY" | "OBJECT") => {
+            Err(anyhow!("Cannot coerce {} to {}", source, target))
+        }
+        (_, other) => Err(anyhow!("Unknown type `{}` for COERCE_TO", other)),
+    }
+}
+
+/// Split a `[group, reduction]` pair of grouped data
+fn group_pair(pair: &Datum) -> Result<(&Datum, &Datum)> {
+    match pair.as_array().map(Vec::as_slice) {
+        Some([group, reduction]) => Ok((group, reduction)),
+        _ => Err(anyhow!("Expected a [group, reduction] pair in {}, got {}", GROUPED_DATA, pair)),
+    }
+}
+
+/// Build an object from `[key, value]` pairs
+fn pairs_to_object(pairs: &[Datum]) -> Result<Datum> {
+    let mut obj = HashMap::with_capacity(pairs.len());
+    for pair in pairs {
+        let (key, value) = match pair.as_array().map(Vec::as_slice) {
+            Some([key, value]) => (key, value),
+            _ => return Err(anyhow!("Expected an array of two elements as a pair, got {}", pair)),
+        };
+        let key = key.as_string()
+            .ok_or_else(|| anyhow!("Object keys must be strings, got {}", key))?;
+        if obj.insert(key.to_string(), value.clone()).is_some() {
+            return Err(anyhow!("Duplicate key `{}` in coerced object", key));
+        }
+    }
+    Ok(Datum::Object(obj))
+}
+
+/// Whether an error means a value does not exist (e.g. a missing field)
+fn is_non_existence(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<Error>(), Some(Error::NotFound(_)))
+}
+
+/// ReQL truthiness: everything but `false` and `null` is true
+fn is_truthy(datum: &Datum) -> bool {
+    !matches!(datum, Datum::Null | Datum::Boolean(false))
+}
+
+/// Apply an arithmetic operator to two numbers
+///
+/// Two integers give an integer, unless `int_op` fails (e.g. overflows);
+/// anything else is computed as floats. `None` if either is not a number.
+fn arith(
+    a: &Datum,
+    b: &Datum,
+    int_op: fn(i64, i64) -> Option<i64>,
+    float_op: fn(f64, f64) -> f64,
+) -> Option<Datum> {
+    if let (Datum::Integer(x), Datum::Integer(y)) = (a, b) {
+        if let Some(result) = int_op(*x, *y) {
+            return Some(Datum::Integer(result));
+        }
+    }
+    Some(Datum::Number(float_op(a.as_number()?, b.as_number()?)))
+}
+
+/// Fields a FILTER predicate requires to equal a constant
+///
+/// Recognizes object predicates (`{email: "x"}`) and functions whose body is
+/// `row("field").eq(value)`, with the operands in either order. Other
+/// predicates give no fields and are evaluated document by document.
+pub(crate) fn equality_fields(predicate: &Term) -> Vec<(&str, &Datum)> {
+    if let Some(Datum::Object(obj)) = predicate.as_datum() {
+        return obj.iter().map(|(field, value)| (field.as_str(), value)).collect();
+    }
+    if predicate.term_type != TermType::Func {
+        return Vec::new();
+    }
+    
+    let (Some(param), Some(body)) = (func_param(predicate), predicate.arg(1)) else {
+        return Vec::new();
+    };
+    if body.term_type != TermType::Eq || body.args.len() != 2 {
+        return Vec::new();
+    }
+    
+    let (lhs, rhs) = (&body.args[0], &body.args[1]);
+    match (row_field(lhs, param), rhs.as_datum(), row_field(rhs, param), lhs.as_datum()) {
+        (Some(field), Some(value), _, _) | (_, _, Some(field), Some(value)) => vec![(field, value)],
+        _ => Vec::new(),
+    }
+}
+
+/// Fields a compound index function returns
+///
+/// Recognizes functions whose body is an array of `row("field")` terms;
+/// other functions give `None`.
+pub(crate) fn function_fields(func: &Term) -> Option<Vec<String>> {
+    let param = func_param(func)?;
+    let body = func.arg(1)?;
+    if body.term_type != TermType::MakeArray {
+        return None;
+    }
+    body.args.iter()
+        .map(|field| row_field(field, param).map(str::to_string))
+        .collect()
+}
+
+/// Variable id of a one-parameter FUNC
+fn func_param(func: &Term) -> Option<f64> {
+    let params = func.arg(0)?;
+    match params.as_datum() {
+        Some(Datum::Array(ids)) if ids.len() == 1 => ids[0].as_number(),
+        Some(_) => None,
+        None if params.args.len() == 1 => params.args[0].as_datum().and_then(|d| d.as_number()),
+        None => None,
+    }
+}
+
+/// Field of a `GET_FIELD(VAR(param), "field")` term
+fn row_field(term: &Term, param: f64) -> Option<&str> {
+    if term.term_type != TermType::GetField {
+        return None;
+    }
+    let row = term.arg(0)?;
+    let id = row.arg(0)?.as_datum()?.as_number()?;
+    if row.term_type != TermType::Var || id != param {
+        return None;
+    }
+    term.arg(1)?.as_datum()?.as_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn create_test_storage() -> Arc<Storage> {
+        let temp_dir = std::env::temp_dir().join(format!("executor_test_{}", std::process::id()));
+        Arc::new(Storage::new(Box::new(
+            crate::storage::slab::SlabStorageEngine::with_defaults(&temp_dir).unwrap()
+        )))
+    }
+    
+    #[tokio::test]
+    async fn test_db_list() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        let term = Term::db_list();
+        let result = executor.execute(&term).await.unwrap();
+        
+        assert!(matches!(result, Datum::Array(_)));
+    }
+    
+    #[tokio::test]
+    async fn test_db_selects_only_its_table() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        for (db, table) in [("a", "x"), ("a", "y"), (DEFAULT_DB, "y")] {
+            storage.create_database(db).await.ok();
+            storage.create_table(db, table, "id").await.unwrap();
+            let doc = serde_json::json!({"id": format!("{}.{}", db, table)});
+            let key = index::document_key(db, table, &Datum::from(format!("{}.{}", db, table)));
+            storage.set(&key, Datum::from(doc)).await.unwrap();
+        }
+        let executor = QueryExecutor::new(storage);
+        let table = |name: &str| Term::new(TermType::Table).with_arg(Term::datum(Datum::from(name)));
+        
+        // r.db("a").table("x").union(r.table("y"))
+        let qualified = Term::new(TermType::Table).with_args(vec![
+            Term::new(TermType::Db).with_arg(Term::datum(Datum::from("a"))),
+            Term::datum(Datum::from("x")),
+        ]);
+        let union = Term::new(TermType::Union).with_args(vec![qualified, table("y")]);
+        let result = executor.execute(&union).await.unwrap();
+        let mut ids: Vec<&str> = result.as_array().unwrap().iter()
+            .map(|doc| doc.as_object().unwrap().get("id").unwrap().as_string().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a.x", "test.y"]);
+    }
+    
+    #[tokio::test]
+    async fn test_count() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        let arr = Term::datum(Datum::Array(vec![
+            Datum::Number(1.0),
+            Datum::Number(2.0),
+            Datum::Number(3.0),
+        ]));
+        
+        let term = Term::count(arr);
+        let result = executor.execute(&term).await.unwrap();
+        
+        assert_eq!(result.as_number(), Some(3.0));
+    }
+    
+    fn numbers(values: &[f64]) -> Term {
+        Term::datum(Datum::Array(values.iter().map(|n| Datum::Number(*n)).collect()))
+    }
+    
+    fn var(id: u64) -> Term {
+        Term::new(TermType::Var).with_arg(Term::datum(Datum::Number(id as f64)))
+    }
+    
+    fn func(params: &[u64], body: Term) -> Term {
+        let ids = params.iter().map(|id| Term::datum(Datum::Number(*id as f64))).collect();
+        Term::new(TermType::Func).with_args(vec![
+            Term::new(TermType::MakeArray).with_args(ids),
+            body,
+        ])
+    }
+    
+    #[tokio::test]
+    async fn test_count_by_value() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        let term = Term::count(numbers(&[1.0, 2.0, 2.0, 3.0, 2.0]))
+            .with_arg(Term::datum(Datum::Number(2.0)));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(3.0));
+        
+        let term = Term::count(numbers(&[1.0, 2.0]))
+            .with_arg(Term::datum(Datum::String("2".to_string())));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(0.0));
+    }
+    
+    #[tokio::test]
+    async fn test_count_by_predicate() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        // count(x => x > 2)
+        let predicate = func(&[1], Term::gt(var(1), Term::datum(Datum::Number(2.0))));
+        let term = Term::count(numbers(&[1.0, 2.0, 3.0, 4.0, 5.0])).with_arg(predicate);
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(3.0));
+        
+        // Predicates with the wrong arity are rejected
+        let predicate = func(&[1, 2], Term::datum(Datum::Boolean(true)));
+        let term = Term::count(numbers(&[1.0])).with_arg(predicate);
+        assert!(executor.execute(&term).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_count_object_fields() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        let obj = Datum::from(serde_json::json!({"a": 1, "b": 2, "c": 1}));
+        let result = executor.execute(&Term::count(Term::datum(obj.clone()))).await.unwrap();
+        assert_eq!(result.as_number(), Some(3.0));
+        
+        let term = Term::count(Term::datum(obj)).with_arg(Term::datum(Datum::Number(1.0)));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(2.0));
+    }
+    
+    fn docs(values: serde_json::Value) -> Term {
+        Term::datum(Datum::from(values))
+    }
+    
+    #[tokio::test]
+    async fn test_sum_by_field() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let items = serde_json::json!([
+            {"id": 1, "price": 10},
+            {"id": 2, "price": 2.5},
+            {"id": 3},
+            {"id": 4, "price": 7.5},
+        ]);
+        
+        let term = Term::sum(docs(items.clone()), Some("price".to_string()));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(20.0));
+        
+        // Elements without the field are skipped
+        let term = Term::avg(docs(items.clone()), Some("price".to_string()));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(20.0 / 3.0));
+        
+        // sum(doc => doc.id * 2)
+        let double_id = func(&[1], Term::mul(vec![
+            Term::new(TermType::GetField).with_args(vec![var(1), Term::datum(Datum::from("id"))]),
+            Term::datum(Datum::Number(2.0)),
+        ]));
+        let term = Term::new(TermType::Sum).with_args(vec![docs(items), double_id]);
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(20.0));
+        
+        // Bare numbers keep working without a selector
+        let result = executor.execute(&Term::sum(numbers(&[1.0, 2.0, 3.0]), None)).await.unwrap();
+        assert_eq!(result.as_number(), Some(6.0));
+    }
+    
+    #[tokio::test]
+    async fn test_max_document_by_field() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let items = serde_json::json!([
+            {"id": "a", "score": 3},
+            {"id": "b", "score": 9},
+            {"id": "c", "score": 1},
+            {"id": "d", "score": 9},
+        ]);
+        
+        let max = Term::new(TermType::Max)
+            .with_args(vec![docs(items.clone()), Term::datum(Datum::from("score"))]);
+        let result = executor.execute(&max).await.unwrap();
+        assert_eq!(result, Datum::from(serde_json::json!({"id": "b", "score": 9})));
+        
+        // min(doc => 0 - doc.score)
+        let negated = func(&[1], Term::sub(vec![
+            Term::datum(Datum::Number(0.0)),
+            Term::new(TermType::GetField).with_args(vec![var(1), Term::datum(Datum::from("score"))]),
+        ]));
+        let min = Term::new(TermType::Min).with_args(vec![docs(items.clone()), negated]);
+        let result = executor.execute(&min).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("id"), Some(&Datum::from("b")));
+        
+        let result = executor.execute(&Term::new(TermType::Max).with_arg(numbers(&[4.0, 8.0, 2.0])))
+            .await
+            .unwrap();
+        assert_eq!(result.as_number(), Some(8.0));
+        
+        // Non-numeric fields are an error
+        let bad = Term::new(TermType::Max)
+            .with_args(vec![docs(items), Term::datum(Datum::from("id"))]);
+        assert!(executor.execute(&bad).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_aggregate_edge_cases() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let aggregate = |term_type: TermType, values: Vec<Datum>| {
+            Term::new(term_type).with_arg(Term::datum(Datum::Array(values)))
+        };
+        
+        // An empty sum is zero; the others have no answer
+        let result = executor.execute(&aggregate(TermType::Sum, vec![])).await.unwrap();
+        assert_eq!(result, Datum::Integer(0));
+        
+        // Integers beyond f64 precision sum and compare exactly
+        let large = vec![Datum::Integer((1 << 53) + 1), Datum::Integer(2), Datum::Integer(1 << 53)];
+        let result = executor.execute(&aggregate(TermType::Sum, large.clone())).await.unwrap();
+        assert_eq!(result, Datum::Integer((1 << 54) + 3));
+        let result = executor.execute(&aggregate(TermType::Max, large)).await.unwrap();
+        assert_eq!(result, Datum::Integer((1 << 53) + 1));
+        for (term_type, op) in [(TermType::Avg, "AVG"), (TermType::Min, "MIN"), (TermType::Max, "MAX")] {
+            let err = executor.execute(&aggregate(term_type, vec![])).await.unwrap_err();
+            assert_eq!(err.to_string(), format!("{} on empty sequence", op));
+        }
+        
+        // NaN and strings are errors, not skipped
+        let with_nan = vec![Datum::Number(1.0), Datum::Number(f64::NAN), Datum::Number(3.0)];
+        let mixed = vec![Datum::Integer(1), Datum::from("2"), Datum::Integer(3)];
+        for term_type in [TermType::Sum, TermType::Avg, TermType::Min, TermType::Max] {
+            let err = executor.execute(&aggregate(term_type, with_nan.clone())).await.unwrap_err();
+            assert!(err.to_string().contains("NaN"), "{}", err);
+            let err = executor.execute(&aggregate(term_type, mixed.clone())).await.unwrap_err();
+            assert!(err.to_string().contains("expected a number"), "{}", err);
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_distinct_large_sequence() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        // 10k elements cycling through 1000 values, in descending order
+        let values: Vec<f64> = (0..10_000).map(|i| (999 - i % 1000) as f64).collect();
+        let term = Term::new(TermType::Distinct).with_arg(numbers(&values));
+        let result = executor.execute(&term).await.unwrap();
+        
+        let distinct: Vec<f64> = result.as_array().unwrap().iter()
+            .map(|d| d.as_number().unwrap())
+            .collect();
+        let expected: Vec<f64> = (0..1000).rev().map(|i| i as f64).collect();
+        assert_eq!(distinct, expected);
+        
+        // Objects compare by value regardless of field order
+        let items = docs(serde_json::json!([
+            {"a": 1, "b": [1, 2]},
+            {"b": [1, 2], "a": 1},
+            {"a": 1, "b": [2, 1]},
+            0.0,
+            -0.0,
+        ]));
+        let result = executor.execute(&Term::new(TermType::Distinct).with_arg(items)).await.unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn test_distinct_index() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let items = docs(serde_json::json!([
+            {"id": 1, "city": "Berlin"},
+            {"id": 2, "city": "Paris"},
+            {"id": 3},
+            {"id": 4, "city": "Berlin"},
+        ]));
+        
+        let term = Term::new(TermType::Distinct)
+            .with_arg(items)
+            .with_optarg("index", Term::datum(Datum::from("city")));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result, Datum::from(serde_json::json!(["Berlin", "Paris"])));
+    }
+    
+    fn slice(sequence: Term, bounds: &[f64]) -> Term {
+        let mut args = vec![sequence];
+        args.extend(bounds.iter().map(|n| Term::datum(Datum::Number(*n))));
+        Term::new(TermType::Slice).with_args(args)
+    }
+    
+    #[tokio::test]
+    async fn test_slice_negative_and_open_ended() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let items = || numbers(&[0.0, 1.0, 2.0, 3.0, 4.0]);
+        
+        let result = executor.execute(&slice(items(), &[-2.0, -1.0])).await.unwrap();
+        assert_eq!(result, Datum::Array(vec![Datum::Number(3.0)]));
+        
+        let result = executor.execute(&slice(items(), &[1.0])).await.unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 4);
+        assert_eq!(result.as_array().unwrap()[0], Datum::Number(1.0));
+        
+        // Reversed and out-of-range bounds never underflow
+        let result = executor.execute(&slice(items(), &[3.0, 1.0])).await.unwrap();
+        assert_eq!(result, Datum::Array(Vec::new()));
+        let result = executor.execute(&slice(items(), &[-10.0, 10.0])).await.unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 5);
+    }
+    
+    #[tokio::test]
+    async fn test_nth_negative() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let nth = |index: f64| {
+            Term::new(TermType::Nth).with_args(vec![
+                numbers(&[10.0, 20.0, 30.0]),
+                Term::datum(Datum::Number(index)),
+            ])
+        };
+        
+        let result = executor.execute(&nth(-1.0)).await.unwrap();
+        assert_eq!(result.as_number(), Some(30.0));
+        let result = executor.execute(&nth(0.0)).await.unwrap();
+        assert_eq!(result.as_number(), Some(10.0));
+        
+        let err = executor.execute(&nth(-4.0)).await.unwrap_err();
+        assert!(err.to_string().contains("Index out of bounds"));
+        assert!(executor.execute(&nth(3.0)).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_union_arrays() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        
+        let term = Term::new(TermType::Union).with_args(vec![
+            numbers(&[1.0, 2.0]),
+            numbers(&[3.0, 1.0]),
+            Term::datum(Datum::from("tail")),
+        ]);
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result, Datum::from(serde_json::json!([1.0, 2.0, 3.0, 1.0, "tail"])));
+    }
+    
+    #[tokio::test]
+    async fn test_union_tables() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        storage.create_table("test", "archive", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "a"}),
+            serde_json::json!({"id": "b"}),
+        ])).await.unwrap();
+        let archive = Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("archive"))]);
+        let insert_archive = Term::new(TermType::Insert).with_args(vec![
+            archive.clone(),
+            Term::datum(Datum::from(serde_json::json!([{"id": "z"}]))),
+        ]);
+        executor.execute(&insert_archive).await.unwrap();
+        
+        let items = Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        let term = Term::new(TermType::Union).with_args(vec![items, archive]);
+        let result = executor.execute(&term).await.unwrap();
+        
+        let ids: Vec<&str> = result.as_array().unwrap().iter()
+            .map(|doc| doc.as_object().unwrap().get("id").unwrap().as_string().unwrap())
+            .collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids[..2].contains(&"a") && ids[..2].contains(&"b"));
+        assert_eq!(ids[2], "z");
+    }
+    
+    #[tokio::test]
+    async fn test_table_config_and_reconfigure() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "email").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        let users = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("users"))]);
+        let config = || Term::new(TermType::Config).with_arg(users());
+        
+        let result = executor.execute(&config()).await.unwrap();
+        let obj = result.as_object().unwrap();
+        assert_eq!(obj.get("name"), Some(&Datum::from("users")));
+        assert_eq!(obj.get("db"), Some(&Datum::from("test")));
+        assert_eq!(obj.get("primary_key"), Some(&Datum::from("email")));
+        assert_eq!(obj.get("indexes"), Some(&Datum::Array(vec![])));
+        
+        // Add an index, then see it in the config
+        let reconfigure = Term::new(TermType::Reconfigure)
+            .with_arg(users())
+            .with_optarg("add_indexes", Term::datum(Datum::from("age")));
+        let result = executor.execute(&reconfigure).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("reconfigured"), Some(&Datum::Number(1.0)));
+        let result = executor.execute(&config()).await.unwrap();
+        assert_eq!(
+            result.as_object().unwrap().get("indexes"),
+            Some(&Datum::Array(vec![Datum::from("age")]))
+        );
+        
+        // Changing the primary key needs a rebuild and is refused
+        let rekey = Term::new(TermType::Reconfigure)
+            .with_arg(users())
+            .with_optarg("primary_key", Term::datum(Datum::from("id")));
+        let error = executor.execute(&rekey).await.unwrap_err();
+        assert!(error.to_string().contains("Cannot change the primary key"));
+        
+        let missing = Term::new(TermType::Config).with_arg(
+            Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("missing"))]),
+        );
+        assert!(executor.execute(&missing).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_info() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "sku").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        executor.execute(&Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]),
+            Term::datum(Datum::from(serde_json::json!([{"sku": "a"}, {"sku": "b"}]))),
+        ])).await.unwrap();
+        
+        let table = Term::new(TermType::Table).with_args(vec![
+            Term::new(TermType::Db).with_args(vec![Term::datum(Datum::from("test"))]),
+            Term::datum(Datum::from("items")),
+        ]);
+        let result = executor.execute(&Term::new(TermType::Info).with_arg(table)).await.unwrap();
+        let info = result.as_object().unwrap();
+        assert_eq!(info.get("type"), Some(&Datum::from("TABLE")));
+        assert_eq!(info.get("db"), Some(&Datum::from("test")));
+        assert_eq!(info.get("name"), Some(&Datum::from("items")));
+        assert_eq!(info.get("primary_key"), Some(&Datum::from("sku")));
+        assert_eq!(info.get("doc_count"), Some(&Datum::Number(2.0)));
+        assert_eq!(info.get("indexes"), Some(&Datum::Array(vec![])));
+        let table_id = info.get("id").and_then(|id| id.as_string()).unwrap();
+        assert!(uuid::Uuid::parse_str(table_id).is_ok());
+        
+        let db = Term::new(TermType::Db).with_args(vec![Term::datum(Datum::from("test"))]);
+        let result = executor.execute(&Term::new(TermType::Info).with_arg(db)).await.unwrap();
+        let info = result.as_object().unwrap();
+        assert_eq!(info.len(), 3);
+        assert_eq!(info.get("type"), Some(&Datum::from("DB")));
+        assert_eq!(info.get("name"), Some(&Datum::from("test")));
+        assert!(info.get("id").and_then(|id| id.as_string()).is_some());
+        
+        // Ids are stable across calls
+        let again = executor.execute(&Term::new(TermType::Info).with_arg(
+            Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]),
+        )).await.unwrap();
+        assert_eq!(again.as_object().unwrap().get("id"), Some(&Datum::from(table_id)));
+        
+        let number = Term::datum(Datum::Number(1.0));
+        assert!(executor.execute(&Term::new(TermType::Info).with_arg(number)).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_status_and_wait() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let users = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("users"))]);
+        let flags = |result: &Datum| {
+            let status = result.as_object().unwrap().get("status").unwrap().as_object().unwrap();
+            READINESS_FLAGS.iter()
+                .map(|flag| status.get(*flag).and_then(|v| v.as_bool()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        
+        // Standalone: ready as soon as the table exists
+        let standalone = QueryExecutor::new(storage.clone());
+        let result = standalone.execute(&Term::new(TermType::Status).with_arg(users())).await.unwrap();
+        let obj = result.as_object().unwrap();
+        assert_eq!(obj.get("db"), Some(&Datum::from("test")));
+        assert_eq!(obj.get("name"), Some(&Datum::from("users")));
+        assert!(obj.get("id").and_then(|id| id.as_string()).is_some());
+        assert_eq!(flags(&result), vec![true; 4]);
+        let result = standalone.execute(&Term::new(TermType::Wait).with_arg(users())).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("ready"), Some(&Datum::Number(1.0)));
+        
+        // A timeout too long to represent never expires
+        for timeout in [1e300, f64::MAX] {
+            let wait = Term::new(TermType::Wait)
+                .with_arg(users())
+                .with_optarg("timeout", Term::datum(Datum::Number(timeout)));
+            let result = standalone.execute(&wait).await.unwrap();
+            assert_eq!(result.as_object().unwrap().get("ready"), Some(&Datum::Number(1.0)));
+        }
+        
+        // Clustered: not ready for quorum writes until enough shard owners join
+        let cluster = Arc::new(ClusterState::new("local".to_string(), crate::cluster::ReplicationConfig {
+            shard_count: 4,
+            replica_count: 2,
+            write_quorum: 2,
+            read_quorum: 1,
+            ..Default::default()
+        }));
+        let node = |id: &str, port: u16| crate::cluster::Node {
+            id: id.to_string(),
+            addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+            role: crate::cluster::NodeRole::Replica,
+            shard_range: None,
+            last_heartbeat: chrono::Utc::now(),
+        };
+        cluster.add_node(node("a", 9200)).await;
+        let clustered = QueryExecutor::new(storage).with_cluster(cluster.clone());
+        let result = clustered.execute(&Term::new(TermType::Status).with_arg(users())).await.unwrap();
+        assert_eq!(flags(&result), vec![true, true, false, false]);
+        
+        let wait = Term::new(TermType::Wait)
+            .with_arg(users())
+            .with_optarg("wait_for", Term::datum(Datum::from("ready_for_writes")));
+        let timed_out = wait.clone().with_optarg("timeout", Term::datum(Datum::Number(0.1)));
+        assert!(clustered.execute(&timed_out).await.is_err());
+        
+        let becomes_ready = tokio::spawn({
+            let cluster = cluster.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                cluster.add_node(node("b", 9201)).await;
+            }
+        });
+        let wait = wait.with_optarg("timeout", Term::datum(Datum::Number(5.0)));
+        let result = clustered.execute(&wait).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("ready"), Some(&Datum::Number(1.0)));
+        becomes_ready.await.unwrap();
+        
+        let result = clustered.execute(&Term::new(TermType::Status).with_arg(users())).await.unwrap();
+        assert_eq!(flags(&result), vec![true; 4]);
+    }
+    
+    fn get_field(value: Term, field: &str) -> Term {
+        Term::new(TermType::GetField).with_args(vec![value, Term::datum(Datum::from(field))])
+    }
+    
+    fn default(value: Term, fallback: Term) -> Term {
+        Term::new(TermType::Default).with_args(vec![value, fallback])
+    }
+    
+    #[tokio::test]
+    async fn test_default() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let doc = || docs(serde_json::json!({"name": "Alice", "nickname": null}));
         
-        if let Some(true) = condition.as_bool() {
-            self.execute_term(term.arg(1).unwrap(), ctx).await
-        } else {
-            self.execute_term(term.arg(2).unwrap(), ctx).await
+        // A missing field falls back
+        let term = default(get_field(doc(), "age"), Term::datum(Datum::Number(18.0)));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_number(), Some(18.0));
+        
+        // So does null
+        let term = default(get_field(doc(), "nickname"), Term::datum(Datum::from("none")));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_string(), Some("none"));
+        
+        // A present value passes through unchanged
+        let term = default(get_field(doc(), "name"), Term::datum(Datum::from("anonymous")));
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result.as_string(), Some("Alice"));
+        
+        // Function fallbacks receive the error message
+        let term = default(get_field(doc(), "age"), func(&[1], var(1)));
+        let result = executor.execute(&term).await.unwrap();
+        assert!(result.as_string().unwrap().contains("No attribute `age`"));
+        
+        // So do fields of null and of non-objects
+        for value in [Datum::Null, Datum::Number(1.0)] {
+            let term = default(get_field(Term::datum(value), "age"), Term::datum(Datum::Number(18.0)));
+            let result = executor.execute(&term).await.unwrap();
+            assert_eq!(result.as_number(), Some(18.0));
         }
+        
+        // Other errors are not caught
+        let bad_field = Term::new(TermType::GetField)
+            .with_args(vec![doc(), Term::datum(Datum::Number(1.0))]);
+        let term = default(bad_field, Term::datum(Datum::Null));
+        assert!(executor.execute(&term).await.is_err());
     }
     
-    async fn for_each(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement FOR_EACH
-        Ok(Datum::Null)
+    fn sample(sequence: Term, n: f64) -> Term {
+        Term::new(TermType::Sample).with_args(vec![sequence, Term::datum(Datum::Number(n))])
     }
     
-    async fn func_call(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement FUNC_CALL (function invocation)
-        Ok(Datum::Null)
+    #[tokio::test]
+    async fn test_sample_size() {
+        let storage = create_test_storage();
+        let executor = QueryExecutor::new(storage);
+        let values: Vec<f64> = (0..100).map(|i| i as f64).collect();
+        
+        let result = executor.execute(&sample(numbers(&values), 10.0)).await.unwrap();
+        let picked = result.as_array().unwrap();
+        assert_eq!(picked.len(), 10);
+        assert!(picked.iter().all(|d| values.contains(&d.as_number().unwrap())));
+        let distinct = Term::new(TermType::Distinct).with_arg(Term::datum(result.clone()));
+        let distinct = executor.execute(&distinct).await.unwrap();
+        assert_eq!(distinct.as_array().unwrap().len(), 10);
+        
+        // More than available returns every element
+        let result = executor.execute(&sample(numbers(&[1.0, 2.0, 3.0]), 5.0)).await.unwrap();
+        let mut all: Vec<f64> = result.as_array().unwrap().iter()
+            .map(|d| d.as_number().unwrap())
+            .collect();
+        all.sort_by(f64::total_cmp);
+        assert_eq!(all, vec![1.0, 2.0, 3.0]);
+        
+        let result = executor.execute(&sample(numbers(&[]), 5.0)).await.unwrap();
+        assert_eq!(result, Datum::Array(Vec::new()));
+        
+        assert!(executor.execute(&sample(numbers(&[1.0]), -1.0)).await.is_err());
     }
     
-    // ========================================================================
-    // Type Operations
-    // ========================================================================
-    
-    async fn type_of(&self, term: &Term, ctx: &mut ExecutionContext) -> Result<Datum> {
-        let value = self.execute_term(term.arg(0).unwrap(), ctx).await?;
+    #[tokio::test]
+    async fn test_sample_seeded() {
+        let values: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let term = sample(numbers(&values), 20.0);
         
-        let type_name = match value {
-            Datum::Null => "NULL",
-            Datum::Boolean(_) => "BOOL",
-            Datum::Number(_) => "NUMBER",
-            Datum::String(_) => "STRING",
-            Datum::Array(_) => "ARRAY",
-            Datum::Object(_) => "OBJECT",
-        };
+        let first = QueryExecutor::new(create_test_storage()).with_sample_seed(42);
+        let second = QueryExecutor::new(create_test_storage()).with_sample_seed(42);
+        let a = first.execute(&term).await.unwrap();
+        assert_eq!(a, first.execute(&term).await.unwrap());
+        assert_eq!(a, second.execute(&term).await.unwrap());
         
-        Ok(Datum::String(type_name.to_string()))
+        let other = QueryExecutor::new(create_test_storage()).with_sample_seed(7);
+        assert_ne!(a, other.execute(&term).await.unwrap());
     }
     
-    async fn coerce_to(&self, _term: &Term, _ctx: &mut ExecutionContext) -> Result<Datum> {
-        // TODO: Implement COERCE_TO (type conversion)
-        Ok(Datum::Null)
+    fn branch(args: Vec<Term>) -> Term {
+        Term::new(TermType::Branch).with_args(args)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
     
-    fn create_test_storage() -> Arc<Storage> {
-        let temp_dir = std::env::temp_dir().join(format!("executor_test_{}", std::process::id()));
-        Arc::new(Storage::new(Box::new(
-            crate::storage::slab::SlabStorageEngine::with_defaults(&temp_dir).unwrap()
-        )))
+    fn string(s: &str) -> Term {
+        Term::datum(Datum::from(s))
     }
     
     #[tokio::test]
-    async fn test_db_list() {
+    async fn test_branch_truthiness() {
         let storage = create_test_storage();
         let executor = QueryExecutor::new(storage);
+        let pick = |condition: Datum| {
+            branch(vec![Term::datum(condition), string("yes"), string("no")])
+        };
         
-        let term = Term::db_list();
-        let result = executor.execute(&term).await.unwrap();
+        let truthy = [Datum::Number(0.0), Datum::Number(3.0), Datum::from(""), Datum::Array(Vec::new())];
+        for truthy in truthy {
+            let result = executor.execute(&pick(truthy)).await.unwrap();
+            assert_eq!(result.as_string(), Some("yes"));
+        }
+        for falsy in [Datum::Null, Datum::Boolean(false)] {
+            let result = executor.execute(&pick(falsy)).await.unwrap();
+            assert_eq!(result.as_string(), Some("no"));
+        }
         
-        assert!(matches!(result, Datum::Array(_)));
+        // Errors in the condition are reported, not treated as false
+        let missing = get_field(docs(serde_json::json!({})), "x");
+        let failing = branch(vec![missing, string("yes"), string("no")]);
+        let err = executor.execute(&failing).await.unwrap_err();
+        assert!(err.to_string().contains("BRANCH condition failed"));
+        assert!(is_non_existence(&err), "{:#}", err);
+        
+        // ...so DEFAULT around the branch still catches a missing field
+        let caught = default(failing, string("fallback"));
+        let result = executor.execute(&caught).await.unwrap();
+        assert_eq!(result.as_string(), Some("fallback"));
+        
+        assert!(executor.execute(&branch(vec![string("a"), string("b")])).await.is_err());
     }
     
     #[tokio::test]
-    async fn test_count() {
+    async fn test_branch_multi() {
         let storage = create_test_storage();
         let executor = QueryExecutor::new(storage);
+        let grade = |score: f64| {
+            let score = || Term::datum(Datum::Number(score));
+            branch(vec![
+                Term::gt(score(), Term::datum(Datum::Number(89.0))), string("A"),
+                Term::gt(score(), Term::datum(Datum::Number(79.0))), string("B"),
+                string("C"),
+            ])
+        };
         
-        let arr = Term::datum(Datum::Array(vec![
-            Datum::Number(1.0),
-            Datum::Number(2.0),
-            Datum::Number(3.0),
-        ]));
-        
-        let term = Term::count(arr);
-        let result = executor.execute(&term).await.unwrap();
-        
-        assert_eq!(result.as_number(), Some(3.0));
+        for (score, expected) in [(95.0, "A"), (85.0, "B"), (50.0, "C")] {
+            let result = executor.execute(&grade(score)).await.unwrap();
+            assert_eq!(result.as_string(), Some(expected));
+        }
     }
     
     #[tokio::test]
@@ -1123,4 +3747,861 @@ mod tests {
         let result = executor.execute(&gt_term).await.unwrap();
         assert_eq!(result.as_bool(), Some(true));
     }
+    
+    fn insert_term(docs: Vec<serde_json::Value>) -> Term {
+        Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_args(vec![Term::datum(Datum::String("items".to_string()))]),
+            Term::datum(Datum::Array(docs.into_iter().map(Datum::from).collect())),
+        ])
+    }
+    
+    #[tokio::test]
+    async fn test_between_open_ended() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "alice"}),
+            serde_json::json!({"id": "mallory"}),
+            serde_json::json!({"id": "m"}),
+            serde_json::json!({"id": "zoe"}),
+        ])).await.unwrap();
+        
+        let items = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        let ids = |result: Datum| {
+            let mut ids: Vec<String> = result.as_array().unwrap().iter()
+                .map(|doc| doc.as_object().unwrap().get("id").unwrap().as_string().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+        
+        let below = Term::between(items(), Term::minval(), Term::datum(Datum::from("m")));
+        assert_eq!(ids(executor.execute(&below).await.unwrap()), vec!["alice"]);
+        
+        let above = Term::between(items(), Term::datum(Datum::from("m")), Term::maxval());
+        assert_eq!(ids(executor.execute(&above).await.unwrap()), vec!["m", "mallory", "zoe"]);
+        
+        let all = Term::between(items(), Term::minval(), Term::maxval())
+            .with_optarg("left_bound", Term::datum(Datum::from("open")));
+        assert_eq!(ids(executor.execute(&all).await.unwrap()).len(), 4);
+        
+        // Bounds compare in ReQL order: numbers sort before strings
+        let numbers = Term::between(items(), Term::minval(), Term::datum(Datum::Number(0.0)));
+        assert!(ids(executor.execute(&numbers).await.unwrap()).is_empty());
+        
+        // Outside of a range they are errors
+        let err = executor.execute(&Term::minval()).await.unwrap_err();
+        assert!(err.to_string().contains("only valid as a range bound"));
+    }
+    
+    #[tokio::test]
+    async fn test_between_primary_key_range() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "cherry"}),
+            serde_json::json!({"id": "1"}),
+            serde_json::json!({"id": "apple"}),
+            serde_json::json!({"id": "banana"}),
+            serde_json::json!({"id": 5}),
+            serde_json::json!({"id": 40}),
+            serde_json::json!({"id": 10}),
+        ])).await.unwrap();
+        
+        let items = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        let ids = |result: Datum| -> Vec<Datum> {
+            result.as_array().unwrap().iter()
+                .map(|doc| doc.as_object().unwrap()["id"].clone())
+                .collect()
+        };
+        let scans = storage.scan_stats().full_scans;
+        
+        // Ranges of strings are read by key, in key order
+        let range = Term::between(items(), Term::datum(Datum::from("b")), Term::maxval());
+        assert_eq!(ids(executor.execute(&range).await.unwrap()), [Datum::from("banana"), Datum::from("cherry")]);
+        let digits = Term::between(items(), Term::datum(Datum::from("0")), Term::datum(Datum::from("9")));
+        assert_eq!(ids(executor.execute(&digits).await.unwrap()), [Datum::from("1")]);
+        // So are ranges of numbers, which sort numerically rather than as text
+        let numbers = Term::between(items(), Term::datum(Datum::Integer(5)), Term::datum(Datum::Integer(50)));
+        assert_eq!(ids(executor.execute(&numbers).await.unwrap()), [Datum::Integer(5), Datum::Integer(10), Datum::Integer(40)]);
+        assert_eq!(storage.scan_stats().full_scans, scans);
+        
+        // Ranges reaching below strings scan the table
+        let all = Term::between(items(), Term::minval(), Term::datum(Datum::from("b")));
+        assert_eq!(ids(executor.execute(&all).await.unwrap()).len(), 5);
+        assert_eq!(storage.scan_stats().full_scans, scans + 1);
+    }
+    
+    /// Uppercases its string argument
+    struct UppercasePlugin;
+    
+    impl crate::plugin::Plugin for UppercasePlugin {
+        fn metadata(&self) -> crate::plugin::PluginMetadata {
+            crate::plugin::PluginMetadata {
+                name: "text".to_string(),
+                version: "1.0.0".to_string(),
+                abi_version: crate::plugin::PLUGIN_ABI_VERSION,
+                author: "test".to_string(),
+                description: "String helpers".to_string(),
+                capabilities: vec![crate::plugin::PluginCapability::QueryOperations],
+            }
+        }
+        
+        fn execute(
+            &self,
+            function: &str,
+            args: Vec<Datum>,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::error::Result<Datum>> + Send + '_>> {
+            let result = match (function, args.first()) {
+                ("upper", Some(Datum::String(s))) => Ok(Datum::String(s.to_uppercase())),
+                ("upper", _) => Err(Error::Plugin("upper expects a string".to_string())),
+                _ => Err(Error::Plugin(format!("Unknown function: {}", function))),
+            };
+            Box::pin(async move { result })
+        }
+    }
+    
+    #[tokio::test]
+    async fn test_call_plugin() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let plugins = PluginManager::new();
+        plugins.register_plugin(Arc::new(UppercasePlugin)).unwrap();
+        let executor = QueryExecutor::new(storage.clone()).with_plugins(Arc::new(plugins));
+        
+        // Arguments are evaluated before the call
+        let greeting = Term::new(TermType::Branch).with_args(vec![
+            Term::datum(Datum::Boolean(true)),
+            Term::datum(Datum::from("hello, world")),
+            Term::datum(Datum::Null),
+        ]);
+        let term = Term::call_plugin("text", "upper", vec![greeting]);
+        assert_eq!(executor.execute(&term).await.unwrap(), Datum::from("HELLO, WORLD"));
+        
+        // Plugin results feed the rest of the query
+        let nested = Term::new(TermType::MakeArray)
+            .with_arg(Term::call_plugin("text", "upper", vec![Term::datum(Datum::from("a"))]));
+        assert_eq!(
+            executor.execute(&nested).await.unwrap(),
+            Datum::Array(vec![Datum::from("A")])
+        );
+        
+        // Plugin errors become query errors
+        let err = executor
+            .execute(&Term::call_plugin("text", "upper", vec![Term::datum(Datum::Number(1.0))]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("upper expects a string"), "{}", err);
+        assert!(executor.execute(&Term::call_plugin("text", "lower", vec![])).await.is_err());
+        assert!(executor.execute(&Term::call_plugin("geo", "upper", vec![])).await.is_err());
+        
+        let err = QueryExecutor::new(storage)
+            .execute(&Term::call_plugin("text", "upper", vec![]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not enabled"));
+    }
+    
+    #[tokio::test]
+    async fn test_object_dynamic_keys() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let executor = QueryExecutor::new(storage);
+        let doc = || Term::datum(Datum::from(serde_json::json!({"name": "color", "value": "red"})));
+        let field = |name: &str| Term::new(TermType::GetField).with_args(vec![
+            doc(),
+            Term::datum(Datum::from(name)),
+        ]);
+        
+        // r.object(doc("name"), doc("value"), "static", 1)
+        let term = Term::new(TermType::Object).with_args(vec![
+            field("name"),
+            field("value"),
+            Term::datum(Datum::from("static")),
+            Term::datum(Datum::Number(1.0)),
+        ]);
+        let result = executor.execute(&term).await.unwrap();
+        assert_eq!(result, Datum::from(serde_json::json!({"color": "red", "static": 1})));
+        
+        let numeric_key = Term::new(TermType::Object).with_args(vec![
+            Term::datum(Datum::Number(1.0)),
+            Term::datum(Datum::from("one")),
+        ]);
+        let err = executor.execute(&numeric_key).await.unwrap_err();
+        assert!(err.to_string().contains("keys must be strings"), "{}", err);
+        
+        let odd = Term::new(TermType::Object).with_arg(Term::datum(Datum::from("key")));
+        let err = executor.execute(&odd).await.unwrap_err();
+        assert!(err.to_string().contains("even number of arguments"), "{}", err);
+    }
+    
+    #[tokio::test]
+    async fn test_execute_atomic() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        
+        // Both inserts commit together
+        let results = executor.execute_atomic(&[
+            insert_term(vec![serde_json::json!({"id": "a"})]),
+            insert_term(vec![serde_json::json!({"id": "b"})]),
+        ]).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(storage.get(b"doc:test:items:a").await.unwrap().is_some());
+        assert!(storage.get(b"doc:test:items:b").await.unwrap().is_some());
+        
+        // The second insert conflicts with the first, so neither is written
+        let result = executor.execute_atomic(&[
+            insert_term(vec![serde_json::json!({"id": "c"})]),
+            insert_term(vec![serde_json::json!({"id": "c", "dup": true})]),
+        ]).await;
+        assert!(result.unwrap_err().to_string().contains("Duplicate primary key"));
+        assert!(storage.get(b"doc:test:items:c").await.unwrap().is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_insert_upsert() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let upsert = |doc: serde_json::Value| {
+            insert_term(vec![doc]).with_optarg("upsert", Term::datum(Datum::Boolean(true)))
+        };
+        let count = |result: &Datum, field: &str| {
+            result.as_object().unwrap().get(field).and_then(|n| n.as_number())
+        };
+        
+        let result = executor.execute(&upsert(serde_json::json!({"id": "a", "name": "Ann", "age": 30}))).await.unwrap();
+        assert_eq!(count(&result, "inserted"), Some(1.0));
+        assert_eq!(count(&result, "replaced"), Some(0.0));
+        
+        // The same id again merges, keeping fields it does not mention
+        let result = executor.execute(&upsert(serde_json::json!({"id": "a", "age": 31}))).await.unwrap();
+        assert_eq!(count(&result, "inserted"), Some(0.0));
+        assert_eq!(count(&result, "replaced"), Some(1.0));
+        let doc = storage.get(b"doc:test:items:a").await.unwrap().unwrap();
+        assert_eq!(serde_json::Value::from(doc), serde_json::json!({"id": "a", "name": "Ann", "age": 31}));
+        
+        let result = executor.execute(&upsert(serde_json::json!({"id": "a", "age": 31}))).await.unwrap();
+        assert_eq!(count(&result, "unchanged"), Some(1.0));
+        
+        // Without upsert the same document is a duplicate
+        let result = executor.execute(&insert_term(vec![serde_json::json!({"id": "a"})])).await.unwrap();
+        assert_eq!(count(&result, "errors"), Some(1.0));
+        assert_eq!(count(&result, "replaced"), None);
+        
+        let bad = insert_term(vec![serde_json::json!({"id": "b"})])
+            .with_optarg("upsert", Term::datum(Datum::String("yes".to_string())));
+        assert!(executor.execute(&bad).await.is_err());
+        let conflicting = upsert(serde_json::json!({"id": "b"}))
+            .with_optarg("conflict", Term::datum(Datum::String("update".to_string())));
+        assert!(executor.execute(&conflicting).await.is_err());
+        assert!(storage.get(b"doc:test:items:b").await.unwrap().is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_get_integer_primary_key() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage);
+        
+        // Beyond 2^53, where neighbouring integers share a float
+        let big = 9_007_199_254_740_993_i64;
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": big, "name": "big"}),
+            serde_json::json!({"id": big - 1, "name": "smaller"}),
+            serde_json::json!({"id": 42, "name": "small"}),
+        ])).await.unwrap();
+        
+        let get = |id: Datum| Term::new(TermType::Get).with_args(vec![
+            Term::new(TermType::Table).with_args(vec![Term::datum(Datum::String("items".to_string()))]),
+            Term::datum(id),
+        ]);
+        let name = |doc: Datum| doc.as_object().unwrap()["name"].clone();
+        assert_eq!(name(executor.execute(&get(Datum::Integer(big))).await.unwrap()), Datum::from("big"));
+        assert_eq!(name(executor.execute(&get(Datum::Integer(big - 1))).await.unwrap()), Datum::from("smaller"));
+        
+        // 42.0 is the same key as 42
+        let doc = executor.execute(&get(Datum::Number(42.0))).await.unwrap();
+        assert_eq!(name(doc.clone()), Datum::from("small"));
+        assert_eq!(serde_json::Value::from(doc)["id"].to_string(), "42");
+        assert_eq!(executor.execute(&get(Datum::Integer(7))).await.unwrap(), Datum::Null);
+    }
+    
+    #[tokio::test]
+    async fn test_integer_arithmetic() {
+        let executor = QueryExecutor::new(Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new()))));
+        let op = |term_type: TermType, a: Datum, b: Datum| {
+            Term::new(term_type).with_args(vec![Term::datum(a), Term::datum(b)])
+        };
+        let big = 9_007_199_254_740_993_i64;
+        let result = executor.execute(&op(TermType::Add, Datum::Integer(big), Datum::Integer(1))).await.unwrap();
+        assert!(matches!(result, Datum::Integer(n) if n == big + 1));
+        let result = executor.execute(&op(TermType::Mul, Datum::Integer(3), Datum::Number(0.5))).await.unwrap();
+        assert_eq!(result, Datum::Number(1.5));
+        // Overflow promotes to float
+        let result = executor.execute(&op(TermType::Add, Datum::Integer(i64::MAX), Datum::Integer(1))).await.unwrap();
+        assert!(matches!(result, Datum::Number(n) if n > 9.2e18));
+        let result = executor.execute(&op(TermType::Mod, Datum::Integer(7), Datum::Integer(3))).await.unwrap();
+        assert!(matches!(result, Datum::Integer(1)));
+    }
+    
+    #[tokio::test]
+    async fn test_index_range_reads() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let table = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::String("items".to_string()))]);
+        executor.execute(&Term::new(TermType::Reconfigure)
+            .with_arg(table())
+            .with_optarg("add_indexes", Term::datum(Datum::from("age")))).await.unwrap();
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "a", "age": 40}),
+            serde_json::json!({"id": "b", "age": 25}),
+            serde_json::json!({"id": "c", "age": 31}),
+            serde_json::json!({"id": "d", "age": 18}),
+            serde_json::json!({"id": "e", "age": 31}),
+            serde_json::json!({"id": "f"}),
+        ])).await.unwrap();
+        // Moving a document within the index keeps the order right
+        executor.execute(&insert_term(vec![serde_json::json!({"id": "d", "age": 50})])
+            .with_optarg("upsert", Term::datum(Datum::Boolean(true)))).await.unwrap();
+        let ids = |result: Datum| -> Vec<String> {
+            result.as_array().unwrap().iter()
+                .map(|doc| doc.as_object().unwrap()["id"].as_string().unwrap().to_string())
+                .collect()
+        };
+        let scans = storage.scan_stats().full_scans;
+        
+        let between = Term::new(TermType::Between).with_args(vec![
+            table(),
+            Term::datum(Datum::Integer(25)),
+            Term::datum(Datum::Integer(40)),
+        ]).with_optarg("index", Term::datum(Datum::from("age")));
+        assert_eq!(ids(executor.execute(&between).await.unwrap()), ["b", "c", "e"]);
+        let closed = between.clone().with_optarg("right_bound", Term::datum(Datum::from("closed")));
+        assert_eq!(ids(executor.execute(&closed).await.unwrap()), ["b", "c", "e", "a"]);
+        
+        let order_by = Term::order_by(table(), vec![])
+            .with_optarg("index", Term::datum(Datum::from("age")));
+        assert_eq!(ids(executor.execute(&order_by).await.unwrap()), ["b", "c", "e", "a", "d"]);
+        
+        // Neither read the whole table
+        assert_eq!(storage.scan_stats().full_scans, scans);
+        
+        // Without the index, the table is scanned and sorted
+        let sorted = Term::order_by(table(), vec![Term::datum(Datum::from("age")), Term::datum(Datum::from("id"))]);
+        assert_eq!(ids(executor.execute(&sorted).await.unwrap()), ["f", "b", "c", "e", "a", "d"]);
+        assert_eq!(storage.scan_stats().full_scans, scans + 1);
+    }
+    
+    #[tokio::test]
+    async fn test_unreplicated_writes_are_not_stored() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let cluster = Arc::new(ClusterState::new("local".to_string(), Default::default()));
+        let executor = QueryExecutor::new(storage.clone())
+            .with_cluster(cluster.clone())
+            .with_replication(ReplicationManager::new(cluster.clone()));
+        let insert = |id: &str| insert_term(vec![serde_json::json!({"id": id})]);
+        
+        // A replica refuses writes without storing them
+        let error = executor.execute(&insert("a")).await.unwrap_err();
+        assert!(error.to_string().contains("not the master node"), "{}", error);
+        assert!(storage.get(b"doc:test:items:a").await.unwrap().is_none());
+        
+        // A master without a write quorum undoes the local write
+        cluster.init_as_master().await;
+        let error = executor.execute(&insert("b")).await.unwrap_err();
+        assert!(error.to_string().contains("replicate"), "{}", error);
+        assert!(storage.get(b"doc:test:items:b").await.unwrap().is_none());
+        
+        // So does a transaction, restoring what it overwrote
+        storage.set(b"doc:test:items:c", Datum::from(serde_json::json!({"id": "c", "v": 1}))).await.unwrap();
+        let upsert = insert_term(vec![serde_json::json!({"id": "c", "v": 2})])
+            .with_optarg("upsert", Term::datum(Datum::Boolean(true)));
+        let error = executor.execute_atomic_in(&[insert("d"), upsert], "test").await.unwrap_err();
+        assert!(error.to_string().contains("replicate"), "{}", error);
+        assert!(storage.get(b"doc:test:items:d").await.unwrap().is_none());
+        let doc = storage.get(b"doc:test:items:c").await.unwrap().unwrap();
+        assert_eq!(doc.as_object().unwrap().get("v"), Some(&Datum::Number(1.0)));
+    }
+    
+    #[tokio::test]
+    async fn test_insert_with_ttl() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        
+        let insert = insert_term(vec![serde_json::json!({"id": "tmp"})])
+            .with_optarg("ttl", Term::datum(Datum::Number(0.05)));
+        executor.execute(&insert).await.unwrap();
+        executor.execute(&insert_term(vec![serde_json::json!({"id": "kept"})])).await.unwrap();
+        
+        let table = Term::new(TermType::Table).with_args(vec![Term::datum(Datum::String("items".to_string()))]);
+        let doc = storage.get(b"doc:test:items:tmp").await.unwrap().unwrap();
+        assert!(doc.as_object().unwrap().get(ttl::EXPIRES_AT_FIELD).is_none());
+        let docs = executor.execute(&table).await.unwrap();
+        assert_eq!(docs.as_array().unwrap().len(), 2);
+        
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(storage.get(b"doc:test:items:tmp").await.unwrap().is_none());
+        let docs = executor.execute(&table).await.unwrap();
+        assert_eq!(docs.as_array().unwrap().len(), 1);
+        
+        for ttl in [-1.0, f64::INFINITY, 1e300] {
+            let bad = insert_term(vec![serde_json::json!({"id": "x"})])
+                .with_optarg("ttl", Term::datum(Datum::Number(ttl)));
+            let error = executor.execute(&bad).await.unwrap_err();
+            assert!(error.to_string().contains("INSERT ttl must be a positive number of seconds"));
+        }
+        assert!(storage.get(b"doc:test:items:x").await.unwrap().is_none());
+        
+        // Updates keep the TTL, and documents cannot set the expiry field
+        let insert = insert_term(vec![serde_json::json!({"id": "tmp"})])
+            .with_optarg("ttl", Term::datum(Datum::Number(0.05)));
+        executor.execute(&insert).await.unwrap();
+        let get = Term::new(TermType::Get).with_args(vec![table.clone(), Term::datum(Datum::from("tmp"))]);
+        let update = Term::new(TermType::Update).with_args(vec![get, Term::datum(Datum::from(serde_json::json!({"seen": true})))]);
+        let result = executor.execute(&update).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("replaced"), Some(&Datum::Number(1.0)));
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(storage.get(b"doc:test:items:tmp").await.unwrap().is_none());
+        
+        let reserved = serde_json::json!({"id": "y", ttl::EXPIRES_AT_FIELD: 0});
+        let result = executor.execute(&insert_term(vec![reserved])).await.unwrap();
+        assert_eq!(result.as_object().unwrap().get("errors"), Some(&Datum::Number(1.0)));
+        assert!(storage.get(b"doc:test:items:y").await.unwrap().is_none());
+    }
+    
+    #[tokio::test]
+    async fn test_query_metrics() {
+        use crate::cluster::metrics::{init_metrics, MetricsCollector, QUERIES_TOTAL};
+        
+        init_metrics();
+        let executor = QueryExecutor::new(create_test_storage())
+            .with_metrics(Arc::new(MetricsCollector::new()));
+        let count = |term_type: &str, status: &str| {
+            QUERIES_TOTAL.with_label_values(&[term_type, status]).get()
+        };
+        let (adds, db_lists, failed) = (
+            count("ADD", "success"),
+            count("DB_LIST", "success"),
+            count("GET_FIELD", "error"),
+        );
+        
+        let add = Term::new(TermType::Add)
+            .with_args(vec![Term::datum(Datum::Number(1.0)), Term::datum(Datum::Number(2.0))]);
+        executor.execute(&add).await.unwrap();
+        executor.execute(&add).await.unwrap();
+        executor.execute(&Term::db_list()).await.unwrap();
+        let missing = get_field(Term::datum(Datum::Object(HashMap::new())), "a");
+        assert!(executor.execute(&missing).await.is_err());
+        
+        // Counters are global; other tests may add to them concurrently
+        assert!(count("ADD", "success") >= adds + 2);
+        assert!(count("DB_LIST", "success") > db_lists);
+        assert!(count("GET_FIELD", "error") > failed);
+        
+        let exported = crate::cluster::metrics::export_metrics();
+        assert!(exported.contains("rethinkdb_queries_total{status=\"success\",type=\"ADD\"}"));
+        assert!(exported.contains("rethinkdb_query_duration_seconds_count{type=\"DB_LIST\"}"));
+    }
+    
+    #[tokio::test]
+    async fn test_update_json_patch() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "a", "name": "lamp", "price": 10, "tags": ["old"]}),
+        ])).await.unwrap();
+        
+        let items = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        let patch = |ops: serde_json::Value| {
+            Term::update(items(), Datum::from(ops))
+                .with_optarg("patch_format", Term::datum(Datum::from("jsonpatch")))
+        };
+        let field = |result: &Datum, name: &str| result.as_object().unwrap().get(name).cloned();
+        let stored = || async {
+            storage.scan_table("test", "items").await.unwrap().pop().unwrap()
+        };
+        
+        let result = executor.execute(&patch(serde_json::json!([
+            {"op": "add", "path": "/color", "value": "red"},
+            {"op": "remove", "path": "/tags"},
+            {"op": "replace", "path": "/price", "value": 12}
+        ]))).await.unwrap();
+        assert_eq!(field(&result, "replaced"), Some(Datum::Number(1.0)));
+        assert_eq!(field(&result, "errors"), Some(Datum::Number(0.0)));
+        assert_eq!(stored().await, Datum::from(serde_json::json!({
+            "id": "a", "name": "lamp", "price": 12, "color": "red"
+        })));
+        
+        // A failing test op aborts the whole patch for that document
+        let result = executor.execute(&patch(serde_json::json!([
+            {"op": "replace", "path": "/name", "value": "desk"},
+            {"op": "test", "path": "/price", "value": 10}
+        ]))).await.unwrap();
+        assert_eq!(field(&result, "replaced"), Some(Datum::Number(0.0)));
+        assert_eq!(field(&result, "errors"), Some(Datum::Number(1.0)));
+        assert!(field(&result, "first_error").unwrap().as_string().unwrap().contains("test failed"));
+        assert_eq!(field(&stored().await, "name"), Some(Datum::from("lamp")));
+        
+        // The primary key cannot be patched
+        let result = executor.execute(&patch(serde_json::json!([
+            {"op": "replace", "path": "/id", "value": "b"}
+        ]))).await.unwrap();
+        assert_eq!(field(&result, "errors"), Some(Datum::Number(1.0)));
+        
+        // Without patch_format the update document is merged
+        let result = executor.execute(&Term::update(items(), Datum::from(serde_json::json!({"price": 12}))))
+            .await.unwrap();
+        assert_eq!(field(&result, "unchanged"), Some(Datum::Number(1.0)));
+        
+        let bad_format = Term::update(items(), Datum::from(serde_json::json!([])))
+            .with_optarg("patch_format", Term::datum(Datum::from("merge")));
+        assert!(executor.execute(&bad_format).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_filter_uses_index_for_equality() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let items = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        executor.execute(&Term::new(TermType::Reconfigure)
+            .with_arg(items())
+            .with_optarg("add_indexes", Term::datum(Datum::from("email")))).await.unwrap();
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "a", "email": "a@example.com", "name": "ann"}),
+            serde_json::json!({"id": "b", "email": "b@example.com", "name": "bob"}),
+        ])).await.unwrap();
+        
+        // row("field").eq(value)
+        let row_eq = |field: &str, value: &str| Term::new(TermType::Func).with_args(vec![
+            Term::new(TermType::MakeArray).with_arg(Term::datum(Datum::Number(1.0))),
+            Term::new(TermType::Eq).with_args(vec![
+                Term::new(TermType::GetField).with_args(vec![var(1), Term::datum(Datum::from(field))]),
+                Term::datum(Datum::from(value)),
+            ]),
+        ]);
+        
+        let before = storage.scan_stats();
+        let result = executor.execute(&Term::filter(items(), row_eq("email", "b@example.com"))).await.unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(result.as_array().unwrap()[0].as_object().unwrap().get("id"), Some(&Datum::from("b")));
+        let after = storage.scan_stats();
+        assert_eq!(after.index_lookups, before.index_lookups + 1);
+        assert_eq!(after.full_scans, before.full_scans);
+        
+        // Object predicates on an indexed field take the same path
+        let object = Term::datum(Datum::from(serde_json::json!({"email": "a@example.com", "name": "bob"})));
+        let result = executor.execute(&Term::filter(items(), object)).await.unwrap();
+        assert!(result.as_array().unwrap().is_empty());
+        assert_eq!(storage.scan_stats().index_lookups, before.index_lookups + 2);
+        assert_eq!(storage.scan_stats().full_scans, before.full_scans);
+        
+        // A field without an index is scanned and tested
+        let result = executor.execute(&Term::filter(items(), row_eq("name", "ann"))).await.unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 1);
+        assert_eq!(storage.scan_stats().full_scans, before.full_scans + 1);
+        assert_eq!(storage.scan_stats().index_lookups, before.index_lookups + 2);
+    }
+    
+    #[tokio::test]
+    async fn test_compound_index() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let items = || Term::new(TermType::Table).with_args(vec![Term::datum(Datum::from("items"))]);
+        executor.execute(&insert_term(vec![
+            serde_json::json!({"id": "1", "first_name": "Ann", "last_name": "Smith"}),
+            serde_json::json!({"id": "2", "first_name": "Bob", "last_name": "Smith"}),
+            serde_json::json!({"id": "3", "first_name": "Cat", "last_name": "Jones"}),
+            serde_json::json!({"id": "4", "first_name": "Dan", "last_name": "Adams"}),
+            serde_json::json!({"id": "5", "last_name": "Smith"}),
+        ])).await.unwrap();
+        
+        // row => [row("last_name"), row("first_name")]
+        let full_name = func(&[1], Term::new(TermType::MakeArray).with_args(vec![
+            get_field(var(1), "last_name"),
+            get_field(var(1), "first_name"),
+        ]));
+        executor.execute(&Term::new(TermType::Reconfigure)
+            .with_arg(items())
+            .with_optarg("add_indexes", Term::new(TermType::MakeObj).with_optarg("full_name", full_name))).await.unwrap();
+        let key = |last: &str, first: &str| Term::datum(Datum::from(serde_json::json!([last, first])));
+        let ids = |result: Datum| -> Vec<String> {
+            result.as_array().unwrap().iter()
+                .map(|doc| doc.as_object().unwrap().get("id").unwrap().as_string().unwrap().to_string())
+                .collect()
+        };
+        
+        let get_all = Term::new(TermType::GetAll)
+            .with_args(vec![items(), key("Smith", "Bob"), key("Adams", "Dan"), key("Smith", "Eve")])
+            .with_optarg("index", Term::datum(Datum::from("full_name")));
+        assert_eq!(ids(executor.execute(&get_all).await.unwrap()), vec!["2", "4"]);
+        
+        // Ranges order by last name, then first name; documents missing a
+        // field are not indexed
+        let between = |lower: Term, upper: Term| Term::between(items(), lower, upper)
+            .with_optarg("index", Term::datum(Datum::from("full_name")));
+        let range = between(key("Jones", ""), key("Smith", "Bob"));
+        assert_eq!(ids(executor.execute(&range).await.unwrap()), vec!["3", "1"]);
+        let range = between(key("Smith", ""), Term::maxval());
+        assert_eq!(ids(executor.execute(&range).await.unwrap()), vec!["1", "2"]);
+        
+        // Entries follow document writes
+        executor.execute(&Term::new(TermType::Insert).with_args(vec![
+            items(),
+            Term::datum(Datum::from(serde_json::json!({"id": "6", "first_name": "Ada", "last_name": "Smith"}))),
+        ])).await.unwrap();
+        let range = between(key("Smith", ""), key("Smith", "B"));
+        assert_eq!(ids(executor.execute(&range).await.unwrap()), vec!["6", "1"]);
+        
+        // Fields may also be listed by name
+        executor.execute(&Term::new(TermType::Reconfigure)
+            .with_arg(items())
+            .with_optarg("add_indexes", Term::datum(Datum::from(serde_json::json!({"by_first": ["first_name", "last_name"]}))))).await.unwrap();
+        let get_all = Term::new(TermType::GetAll)
+            .with_args(vec![items(), key("Cat", "Jones")])
+            .with_optarg("index", Term::datum(Datum::from("by_first")));
+        assert_eq!(ids(executor.execute(&get_all).await.unwrap()), vec!["3"]);
+        let info = storage.get_table_info("test.items").await.unwrap().unwrap();
+        assert_eq!(info.indexes, vec!["full_name", "by_first"]);
+        assert_eq!(info.index_fields("by_first"), vec!["first_name", "last_name"]);
+        
+        // A single field is not a compound index
+        let single = Term::new(TermType::Reconfigure)
+            .with_arg(items())
+            .with_optarg("add_indexes", Term::datum(Datum::from(serde_json::json!({"by_last": ["last_name"]}))));
+        let err = executor.execute(&single).await.unwrap_err();
+        assert!(err.to_string().contains("needs at least two fields"), "{}", err);
+        
+        // Dropping the index removes its entries
+        executor.execute(&Term::new(TermType::Reconfigure)
+            .with_arg(items())
+            .with_optarg("remove_indexes", Term::datum(Datum::from("full_name")))).await.unwrap();
+        let entry = crate::storage::index::entry_key("test", "items", "full_name", &Datum::from(serde_json::json!(["Smith", "Ann"])), &Datum::from("1"));
+        assert!(storage.get(&entry).await.unwrap().is_none());
+        let info = storage.get_table_info("test.items").await.unwrap().unwrap();
+        assert!(!info.compound_indexes.contains_key("full_name"));
+    }
+    
+    #[tokio::test]
+    async fn test_coerce_to() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let executor = QueryExecutor::new(storage);
+        let coerce = |value: serde_json::Value, target: &str| {
+            Term::new(TermType::CoerceTo).with_args(vec![
+                Term::datum(Datum::from(value)),
+                Term::datum(Datum::from(target)),
+            ])
+        };
+        
+        // Grouped data becomes group/reduction objects, nested groups included
+        let grouped = serde_json::json!({
+            "$reql_type$": "GROUPED_DATA",
+            "data": [
+                ["a", 2],
+                [["b", 1], {"$reql_type$": "GROUPED_DATA", "data": [[true, [1, 2]]]}],
+            ],
+        });
+        let result = executor.execute(&coerce(grouped.clone(), "array")).await.unwrap();
+        assert_eq!(serde_json::Value::from(result), serde_json::json!([
+            {"group": "a", "reduction": 2},
+            {"group": ["b", 1], "reduction": [{"group": true, "reduction": [1, 2]}]},
+        ]));
+        // As an object its groups must be strings
+        let err = executor.execute(&coerce(grouped, "OBJECT")).await.unwrap_err();
+        assert!(err.to_string().contains("keys must be strings"));
+        
+        // Pairs and objects convert into each other
+        let pairs = serde_json::json!([["name", "ann"], ["tags", {"admin": true}]]);
+        let object = executor.execute(&coerce(pairs.clone(), "object")).await.unwrap();
+        assert_eq!(
+            serde_json::Value::from(object.clone()),
+            serde_json::json!({"name": "ann", "tags": {"admin": true}})
+        );
+        let back = executor.execute(&coerce(object.into(), "array")).await.unwrap();
+        assert_eq!(serde_json::Value::from(back), pairs);
+        
+        for malformed in [
+            serde_json::json!([["name"]]),
+            serde_json::json!([["a", 1, 2]]),
+            serde_json::json!(["name"]),
+            serde_json::json!([[1, "one"]]),
+            serde_json::json!([["a", 1], ["a", 2]]),
+        ] {
+            assert!(executor.execute(&coerce(malformed, "object")).await.is_err());
+        }
+        
+        // Scalars
+        let number = executor.execute(&coerce(serde_json::json!(" 42 "), "number")).await.unwrap();
+        assert_eq!(number, Datum::Integer(42));
+        let string = executor.execute(&coerce(serde_json::json!([1, "a"]), "string")).await.unwrap();
+        assert_eq!(string, Datum::from(r#"[1,"a"]"#));
+        assert!(executor.execute(&coerce(serde_json::json!("abc"), "number")).await.is_err());
+        assert!(executor.execute(&coerce(serde_json::json!(1), "table")).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_group_count_ungroup() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let executor = QueryExecutor::new(storage);
+        let users = Term::datum(Datum::from(serde_json::json!([
+            {"name": "ann", "role": "admin"},
+            {"name": "bob", "role": "user"},
+            {"name": "cid", "role": "user"},
+            {"name": "dee", "role": "guest"},
+            {"name": "eve", "role": "user"},
+            {"name": "fay", "role": "admin"},
+            {"name": "gus"},
+        ])));
+        let by_role = Term::new(TermType::Group)
+            .with_args(vec![users, Term::datum(Datum::from("role"))]);
+        
+        // Grouped data holds the members of each group, in group order
+        let groups = executor.execute(&by_role).await.unwrap();
+        let data = grouped_data(&groups).unwrap();
+        let names: Vec<Datum> = data.iter().map(|pair| pair.as_array().unwrap()[0].clone()).collect();
+        assert_eq!(names, [Datum::Null, Datum::from("admin"), Datum::from("guest"), Datum::from("user")]);
+        
+        // Counted per group, ungrouped and sorted by the count
+        let counts = Term::count(by_role);
+        let ungrouped = Term::new(TermType::Ungroup).with_args(vec![counts]);
+        let sorted = Term::order_by(ungrouped, vec![Term::datum(Datum::from("reduction"))]);
+        let result = executor.execute(&sorted).await.unwrap();
+        let rows: Vec<(serde_json::Value, serde_json::Value)> = result.as_array().unwrap().iter()
+            .map(|row| {
+                let row = row.as_object().unwrap();
+                (row["group"].clone().into(), row["reduction"].clone().into())
+            })
+            .collect();
+        assert_eq!(rows, [
+            (serde_json::Value::Null, serde_json::json!(1.0)),
+            (serde_json::json!("guest"), serde_json::json!(1.0)),
+            (serde_json::json!("admin"), serde_json::json!(2.0)),
+            (serde_json::json!("user"), serde_json::json!(3.0)),
+        ]);
+        
+        // Only grouped data can be ungrouped
+        let plain = Term::new(TermType::Ungroup)
+            .with_args(vec![Term::datum(Datum::from(serde_json::json!([1, 2])))]);
+        assert!(executor.execute(&plain).await.is_err());
+        assert!(executor.execute(&Term::new(TermType::Ungroup)).await.is_err());
+        assert_eq!(TermType::from_u64(157), Some(TermType::Ungroup));
+    }
+    
+    #[tokio::test]
+    async fn test_funcall() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let executor = QueryExecutor::new(storage);
+        let number = |n: i64| Term::datum(Datum::Integer(n));
+        
+        // r.do(1, 2, (a, b) => a.add(b))
+        let add = func(&[1, 2], Term::add(vec![var(1), var(2)]));
+        let term = Term::funcall(add.clone(), vec![number(1), number(2)]);
+        assert_eq!(executor.execute(&term).await.unwrap(), Datum::Integer(3));
+        
+        // r.expr(5).do(x => x.mul(x)), with an argument that is computed first
+        let square = func(&[1], Term::mul(vec![var(1), var(1)]));
+        let five = Term::add(vec![number(2), number(3)]);
+        let term = Term::funcall(square, vec![five]);
+        assert_eq!(executor.execute(&term).await.unwrap(), Datum::Integer(25));
+        
+        // Calls nest, and inner parameters shadow outer ones only inside
+        let inner = Term::funcall(func(&[1], var(1)), vec![number(10)]);
+        let outer = func(&[1], Term::add(vec![inner, var(1)]));
+        let term = Term::funcall(outer, vec![number(1)]);
+        assert_eq!(executor.execute(&term).await.unwrap(), Datum::Integer(11));
+        
+        // The number of arguments must match the parameters
+        for args in [vec![number(1)], vec![number(1), number(2), number(3)]] {
+            let err = executor.execute(&Term::funcall(add.clone(), args)).await.unwrap_err();
+            assert!(err.to_string().contains("Expected function with"));
+        }
+        
+        // A function on its own cannot be evaluated
+        assert!(executor.execute(&add).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_is_empty() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "items", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let is_empty = |sequence: Term| {
+            let executor = &executor;
+            async move { executor.execute(&Term::is_empty(sequence)).await.unwrap() }
+        };
+        
+        assert_eq!(is_empty(Term::table("items")).await, Datum::Boolean(true));
+        
+        let docs = (0..500)
+            .map(|n| serde_json::json!({"id": format!("{:03}", n), "n": n, "even": n % 2 == 0}))
+            .collect();
+        executor.execute(&insert_term(docs)).await.unwrap();
+        let scans = storage.scan_stats().full_scans;
+        
+        // Answered from the first page, without reading the whole table
+        assert_eq!(is_empty(Term::table("items")).await, Datum::Boolean(false));
+        let large = func(&[1], Term::gt(get_field(var(1), "n"), Term::datum(Datum::Number(250.0))));
+        assert_eq!(is_empty(Term::filter(Term::table("items"), large)).await, Datum::Boolean(false));
+        let odd = Term::datum(Datum::from(serde_json::json!({"even": false})));
+        assert_eq!(is_empty(Term::filter(Term::table("items"), odd)).await, Datum::Boolean(false));
+        
+        // An empty filter result pages through to the end
+        let huge = func(&[1], Term::gt(get_field(var(1), "n"), Term::datum(Datum::Number(1000.0))));
+        assert_eq!(is_empty(Term::filter(Term::table("items"), huge)).await, Datum::Boolean(true));
+        let missing = Term::datum(Datum::from(serde_json::json!({"id": "999"})));
+        assert_eq!(is_empty(Term::filter(Term::table("items"), missing)).await, Datum::Boolean(true));
+        assert_eq!(storage.scan_stats().full_scans, scans);
+        
+        // Other sequences are evaluated
+        assert_eq!(is_empty(numbers(&[])).await, Datum::Boolean(true));
+        assert_eq!(is_empty(Term::limit(Term::table("items"), 0)).await, Datum::Boolean(true));
+        assert!(executor.execute(&Term::is_empty(Term::datum(Datum::Number(1.0)))).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_variadic_comparisons() {
+        let storage = Arc::new(Storage::new(Box::new(crate::storage::MockStorage::new())));
+        let executor = QueryExecutor::new(storage);
+        let compare = |term_type: TermType, values: &[f64]| {
+            let args = values.iter().map(|n| Term::datum(Datum::Number(*n))).collect();
+            let executor = &executor;
+            async move { executor.execute(&Term::new(term_type).with_args(args)).await.unwrap() }
+        };
+        
+        assert_eq!(compare(TermType::Eq, &[1.0, 1.0, 1.0]).await, Datum::Boolean(true));
+        assert_eq!(compare(TermType::Eq, &[1.0, 1.0, 2.0]).await, Datum::Boolean(false));
+        assert_eq!(compare(TermType::Ne, &[1.0, 1.0, 2.0]).await, Datum::Boolean(true));
+        assert_eq!(compare(TermType::Ne, &[2.0, 2.0, 2.0]).await, Datum::Boolean(false));
+        assert_eq!(compare(TermType::Lt, &[1.0, 2.0, 3.0]).await, Datum::Boolean(true));
+        assert_eq!(compare(TermType::Lt, &[1.0, 3.0, 2.0]).await, Datum::Boolean(false));
+        assert_eq!(compare(TermType::Le, &[1.0, 1.0, 2.0]).await, Datum::Boolean(true));
+        assert_eq!(compare(TermType::Gt, &[3.0, 2.0, 2.0]).await, Datum::Boolean(false));
+        assert_eq!(compare(TermType::Ge, &[3.0, 2.0, 2.0]).await, Datum::Boolean(true));
+        
+        // r.object() is the empty object
+        let empty = executor.execute(&Term::new(TermType::Object)).await.unwrap();
+        assert_eq!(empty, Datum::Object(HashMap::new()));
+        
+        let lone = Term::new(TermType::Lt).with_arg(Term::datum(Datum::Number(1.0)));
+        assert!(executor.execute(&lone).await.is_err());
+    }
 }
@@ -0,0 +1,426 @@
+//! Query plans for EXPLAIN
+//!
+//! Walks a term tree the way the executor would run it, without running it:
+//! nothing is read from or written to tables, only table configurations are
+//! looked up. The result is a datum describing
+//!
+//! - `plan`: the term tree, one node per non-datum term. Nodes that read a
+//!   table carry its `db`, `table`, the access `strategy` and
+//!   `estimated_rows`; nodes consuming such a sequence inherit its
+//!   `strategy`, so a FILTER over a TABLE reads as a `full_scan`, unless it
+//!   tests an indexed field for equality and becomes a lookup itself.
+//!   BETWEEN and ORDER_BY over a TABLE with a secondary `index` read an
+//!   `index_range` instead.
+//! - `accesses`: every table access, in evaluation order.
+//! - `full_scan`: whether any access reads a whole table.
+//! - `writes`: whether the query would modify data or metadata.
+//! - `estimated_rows`: documents read over all accesses, from the cached
+//!   document counts of the tables.
+
+use super::executor::equality_fields;
+use crate::reql::{Datum, Term, TermType};
+use crate::storage::{DatabaseEngine, StorageDatabaseEngine, TableConfig};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+/// How a table is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    /// Every document of the table
+    FullScan,
+    /// Point lookups by primary key
+    PrimaryKeyLookup,
+    /// Point lookups through a secondary index
+    IndexLookup,
+    /// A range of a secondary index, in index order
+    IndexRange,
+}
+
+impl Strategy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::FullScan => "full_scan",
+            Strategy::PrimaryKeyLookup => "primary_key_lookup",
+            Strategy::IndexLookup => "index_lookup",
+            Strategy::IndexRange => "index_range",
+        }
+    }
+}
+
+/// Build the plan of `term`
+pub(crate) async fn explain(
+    databases: &StorageDatabaseEngine,
+    term: &Term,
+    default_db: &str,
+) -> Result<Datum> {
+    let mut planner = Planner {
+        databases,
+        default_db,
+        accesses: Vec::new(),
+        estimated_rows: 0,
+        full_scan: false,
+        writes: false,
+    };
+    let (plan, _) = planner.node(term).await?;
+
+    Ok(object(vec![
+        ("plan", plan),
+        ("accesses", Datum::Array(planner.accesses)),
+        ("full_scan", Datum::Boolean(planner.full_scan)),
+        ("writes", Datum::Boolean(planner.writes)),
+        (
+            "estimated_rows",
+            Datum::Number(planner.estimated_rows as f64),
+        ),
+    ]))
+}
+
+struct Planner<'a> {
+    databases: &'a StorageDatabaseEngine,
+    /// Database of TABLE terms without a DB, as in execution
+    default_db: &'a str,
+    accesses: Vec<Datum>,
+    estimated_rows: u64,
+    full_scan: bool,
+    writes: bool,
+}
+
+type NodeFuture<'b> = Pin<Box<dyn Future<Output = Result<(Datum, Option<Strategy>)>> + Send + 'b>>;
+
+impl<'a> Planner<'a> {
+    /// Plan node of `term` and the strategy of the table it reads, if any
+    fn node<'b>(&'b mut self, term: &'b Term) -> NodeFuture<'b> {
+        Box::pin(async move {
+            let write = Self::is_write(term.term_type);
+            self.writes |= write;
+
+            match term.term_type {
+                TermType::Table => {
+                    let (db, config) = self.table_config(term).await?;
+                    let rows = config.doc_count;
+                    let node = self.access(term, &db, &config, Strategy::FullScan, None, rows);
+                    return Ok((node, Some(Strategy::FullScan)));
+                }
+                TermType::Get if Self::reads_table(term) => {
+                    let (db, config) = self.table_config(&term.args[0]).await?;
+                    let node = self.access(term, &db, &config, Strategy::PrimaryKeyLookup, None, 1);
+                    return Ok((node, Some(Strategy::PrimaryKeyLookup)));
+                }
+                TermType::Filter if Self::reads_table(term) => {
+                    let (db, config) = self.table_config(&term.args[0]).await?;
+                    let fields = term.arg(1).map(equality_fields).unwrap_or_default();
+                    let lookup = if fields.iter().any(|(field, _)| *field == config.primary_key) {
+                        Some((Strategy::PrimaryKeyLookup, config.primary_key.clone()))
+                    } else {
+                        fields
+                            .iter()
+                            .find(|(field, _)| {
+                                config.indexes.iter().any(|index| index == field)
+                                    && !config.compound_indexes.contains_key(*field)
+                            })
+                            .map(|(field, _)| (Strategy::IndexLookup, field.to_string()))
+                    };
+                    if let Some((strategy, index)) = lookup {
+                        let node = self.access(term, &db, &config, strategy, Some(index), 1);
+                        return Ok((node, Some(strategy)));
+                    }
+                }
+                TermType::Between | TermType::OrderBy if Self::reads_table(term) => {
+                    let (db, config) = self.table_config(&term.args[0]).await?;
+                    let index = term
+                        .optarg("index")
+                        .and_then(|index| index.as_datum())
+                        .and_then(|d| d.as_string())
+                        .filter(|index| config.indexes.iter().any(|name| name == index));
+                    if let Some(index) = index {
+                        let (strategy, rows) = (Strategy::IndexRange, config.doc_count);
+                        let index = Some(index.to_string());
+                        let node = self.access(term, &db, &config, strategy, index, rows);
+                        return Ok((node, Some(strategy)));
+                    }
+                }
+                TermType::GetAll if Self::reads_table(term) => {
+                    let (db, config) = self.table_config(&term.args[0]).await?;
+                    let index = match term.optarg("index") {
+                        Some(index) => index
+                            .as_datum()
+                            .and_then(|d| d.as_string())
+                            .ok_or_else(|| anyhow!("GET_ALL index must be a string"))?
+                            .to_string(),
+                        None => config.primary_key.clone(),
+                    };
+                    let strategy = if index == config.primary_key {
+                        Strategy::PrimaryKeyLookup
+                    } else if config.indexes.contains(&index) {
+                        Strategy::IndexLookup
+                    } else {
+                        return Err(anyhow!(
+                            "Index `{}` was not found on table `{}.{}`",
+                            index,
+                            db,
+                            config.name
+                        ));
+                    };
+                    let keys = (term.args.len() - 1) as u64;
+                    let node = self.access(term, &db, &config, strategy, Some(index), keys);
+                    return Ok((node, Some(strategy)));
+                }
+                _ => {}
+            }
+
+            let mut args = Vec::new();
+            let mut strategy = None;
+            for child in term.args.iter().chain(term.optargs.values()) {
+                if child.is_datum() {
+                    continue;
+                }
+                let (node, child_strategy) = self.node(child).await?;
+                strategy = strategy.or(child_strategy);
+                args.push(node);
+            }
+
+            let mut fields = vec![("term", Datum::String(term.term_type.name().to_string()))];
+            if let Some(strategy) = strategy {
+                fields.push(("strategy", Datum::String(strategy.as_str().to_string())));
+            }
+            if write {
+                fields.push(("write", Datum::Boolean(true)));
+            }
+            if !args.is_empty() {
+                fields.push(("args", Datum::Array(args)));
+            }
+            Ok((object(fields), strategy))
+        })
+    }
+
+    /// Whether the first argument of `term` is a TABLE term
+    fn reads_table(term: &Term) -> bool {
+        term.arg(0)
+            .is_some_and(|table| table.term_type == TermType::Table)
+    }
+
+    /// Whether running `term_type` modifies data or metadata
+    fn is_write(term_type: TermType) -> bool {
+        matches!(
+            term_type,
+            TermType::Insert
+                | TermType::Update
+                | TermType::Replace
+                | TermType::Delete
+                | TermType::DbCreate
+                | TermType::DbDrop
+                | TermType::TableCreate
+                | TermType::TableDrop
+                | TermType::Reconfigure
+        )
+    }
+
+    /// Record a table access and return its plan node
+    fn access(
+        &mut self,
+        term: &Term,
+        db: &str,
+        config: &TableConfig,
+        strategy: Strategy,
+        index: Option<String>,
+        rows: u64,
+    ) -> Datum {
+        self.estimated_rows += rows;
+        self.full_scan |= strategy == Strategy::FullScan;
+
+        let mut fields = vec![
+            ("db", Datum::String(db.to_string())),
+            ("table", Datum::String(config.name.clone())),
+            ("strategy", Datum::String(strategy.as_str().to_string())),
+        ];
+        if let Some(index) = index {
+            fields.push(("index", Datum::String(index)));
+        }
+        self.accesses.push(object(fields.clone()));
+
+        fields.push(("term", Datum::String(term.term_type.name().to_string())));
+        fields.push(("estimated_rows", Datum::Number(rows as f64)));
+        object(fields)
+    }
+
+    /// Database and configuration of the table a TABLE term names
+    ///
+    /// Like execution, `TABLE(name)` uses the default database and
+    /// `TABLE(DB(db), name)` the given one.
+    async fn table_config(&self, table: &Term) -> Result<(String, TableConfig)> {
+        if table.term_type != TermType::Table {
+            return Err(anyhow!("Expected TABLE term, got {}", table.term_type));
+        }
+        let (db, name_term) = if table.args.len() > 1 {
+            let db = table.args[0]
+                .arg(0)
+                .and_then(|t| t.as_datum())
+                .and_then(|d| d.as_string())
+                .ok_or_else(|| anyhow!("DB requires database name"))?;
+            (db, table.arg(1))
+        } else {
+            (self.default_db, table.arg(0))
+        };
+        let name = name_term
+            .and_then(|t| t.as_datum())
+            .and_then(|d| d.as_string())
+            .ok_or_else(|| anyhow!("TABLE requires table name"))?;
+
+        let config = self
+            .databases
+            .get_table_config(db, name)
+            .await
+            .map_err(|e| anyhow!("Failed to read table config: {}", e))?
+            .ok_or_else(|| anyhow!("Table `{}.{}` does not exist", db, name))?;
+        Ok((db.to_string(), config))
+    }
+}
+
+fn object(fields: Vec<(&str, Datum)>) -> Datum {
+    Datum::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::QueryExecutor;
+    use crate::reql::{Datum, Term, TermType};
+    use crate::storage::{MockStorage, Storage};
+    use std::sync::Arc;
+
+    async fn executor() -> (Arc<Storage>, QueryExecutor) {
+        let storage = Arc::new(Storage::new(Box::new(MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let executor = QueryExecutor::new(storage.clone());
+        let add_index = Term::new(TermType::Reconfigure)
+            .with_arg(users())
+            .with_optarg("add_indexes", Term::datum(Datum::from("email")));
+        executor.execute(&add_index).await.unwrap();
+        (storage, executor)
+    }
+
+    fn users() -> Term {
+        Term::new(TermType::Table).with_arg(Term::datum(Datum::from("users")))
+    }
+
+    fn field<'a>(datum: &'a Datum, name: &str) -> &'a Datum {
+        datum.as_object().unwrap().get(name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_all_by_index_is_index_lookup() {
+        let (_, executor) = executor().await;
+        let term = Term::get_all(users(), vec![Datum::from("a@example.com")])
+            .with_optarg("index", Term::datum(Datum::from("email")));
+
+        let explained = executor.explain(&term).await.unwrap();
+        let plan = field(&explained, "plan");
+        assert_eq!(field(plan, "term"), &Datum::from("GET_ALL"));
+        assert_eq!(field(plan, "strategy"), &Datum::from("index_lookup"));
+        assert_eq!(field(plan, "index"), &Datum::from("email"));
+        assert_eq!(field(&explained, "full_scan"), &Datum::Boolean(false));
+        assert_eq!(field(&explained, "estimated_rows"), &Datum::Number(1.0));
+
+        // Without an index, GET_ALL goes through the primary key
+        let term = Term::get_all(users(), vec![Datum::from("a"), Datum::from("b")]);
+        let explained = executor.explain(&term).await.unwrap();
+        let plan = field(&explained, "plan");
+        assert_eq!(field(plan, "strategy"), &Datum::from("primary_key_lookup"));
+        assert_eq!(field(plan, "estimated_rows"), &Datum::Number(2.0));
+
+        let missing = Term::get_all(users(), vec![Datum::from("x")])
+            .with_optarg("index", Term::datum(Datum::from("age")));
+        assert!(executor.explain(&missing).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filter_is_full_scan() {
+        let (_, executor) = executor().await;
+        let predicate = Term::new(TermType::Func).with_args(vec![
+            Term::new(TermType::MakeArray).with_arg(Term::datum(Datum::Number(1.0))),
+            Term::datum(Datum::Boolean(true)),
+        ]);
+        let term = Term::filter(users(), predicate);
+
+        let explained = executor.explain(&term).await.unwrap();
+        let plan = field(&explained, "plan");
+        assert_eq!(field(plan, "term"), &Datum::from("FILTER"));
+        assert_eq!(field(plan, "strategy"), &Datum::from("full_scan"));
+        assert_eq!(field(&explained, "full_scan"), &Datum::Boolean(true));
+        assert_eq!(field(&explained, "writes"), &Datum::Boolean(false));
+
+        let accesses = field(&explained, "accesses").as_array().unwrap();
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(field(&accesses[0], "table"), &Datum::from("users"));
+        assert_eq!(field(&accesses[0], "strategy"), &Datum::from("full_scan"));
+    }
+
+    #[tokio::test]
+    async fn test_filter_on_index_is_index_lookup() {
+        let (_, executor) = executor().await;
+        let predicate = Term::datum(Datum::from(serde_json::json!({"email": "a@example.com"})));
+        let term = Term::filter(users(), predicate);
+
+        let explained = executor.explain(&term).await.unwrap();
+        let plan = field(&explained, "plan");
+        assert_eq!(field(plan, "term"), &Datum::from("FILTER"));
+        assert_eq!(field(plan, "strategy"), &Datum::from("index_lookup"));
+        assert_eq!(field(plan, "index"), &Datum::from("email"));
+        assert_eq!(field(&explained, "full_scan"), &Datum::Boolean(false));
+    }
+
+    #[tokio::test]
+    async fn test_index_range_reads() {
+        let (_, executor) = executor().await;
+        let between = Term::new(TermType::Between)
+            .with_args(vec![
+                users(),
+                Term::datum(Datum::from("a")),
+                Term::datum(Datum::from("m")),
+            ])
+            .with_optarg("index", Term::datum(Datum::from("email")));
+        let order_by =
+            Term::order_by(users(), vec![]).with_optarg("index", Term::datum(Datum::from("email")));
+
+        for term in [between, order_by] {
+            let explained = executor.explain(&term).await.unwrap();
+            let plan = field(&explained, "plan");
+            assert_eq!(field(plan, "strategy"), &Datum::from("index_range"));
+            assert_eq!(field(plan, "index"), &Datum::from("email"));
+            assert_eq!(field(&explained, "full_scan"), &Datum::Boolean(false));
+        }
+
+        // Sorting by a field reads the whole table
+        let sorted = Term::order_by(users(), vec![Term::datum(Datum::from("email"))]);
+        let explained = executor.explain(&sorted).await.unwrap();
+        assert_eq!(field(&explained, "full_scan"), &Datum::Boolean(true));
+    }
+
+    #[tokio::test]
+    async fn test_explain_does_not_write() {
+        let (storage, executor) = executor().await;
+        let insert = Term::new(TermType::Insert).with_args(vec![
+            users(),
+            Term::datum(Datum::from(serde_json::json!({"id": "a"}))),
+        ]);
+
+        let explained = executor.explain(&insert).await.unwrap();
+        assert_eq!(field(&explained, "writes"), &Datum::Boolean(true));
+        assert_eq!(
+            field(field(&explained, "plan"), "write"),
+            &Datum::Boolean(true)
+        );
+        assert!(storage
+            .scan_table("test", "users")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -2,6 +2,8 @@
 
 pub mod compiler;
 pub mod executor;
+pub mod explain;
+pub mod patch;
 
 pub use compiler::QueryCompiler;
 pub use executor::QueryExecutor;
@@ -0,0 +1,324 @@
+//! JSON Patch (RFC 6902) for UPDATE
+//!
+//! A patch is an array of operations, each an object with an `op` (`add`,
+//! `remove`, `replace`, `move`, `copy` or `test`), a `path` and, depending on
+//! the operation, a `value` or a `from` path. Paths are JSON Pointers
+//! (RFC 6901): `""` is the whole document, `/a/0` the first element of field
+//! `a`, and `~1` / `~0` escape `/` and `~` in field names.
+//!
+//! Operations apply in order to a copy of the document. If any of them fails,
+//! including a `test` whose value does not match, the whole patch fails and
+//! the document is left as it was.
+
+use crate::reql::Datum;
+use anyhow::{anyhow, Result};
+
+/// A parsed JSON Patch document
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPatch {
+    ops: Vec<PatchOp>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PatchOp {
+    Add { path: Pointer, value: Datum },
+    Remove { path: Pointer },
+    Replace { path: Pointer, value: Datum },
+    Move { from: Pointer, path: Pointer },
+    Copy { from: Pointer, path: Pointer },
+    Test { path: Pointer, value: Datum },
+}
+
+/// Reference tokens of a JSON Pointer
+#[derive(Debug, Clone, PartialEq)]
+struct Pointer(Vec<String>);
+
+impl JsonPatch {
+    /// Parse a patch from its datum form, an array of operation objects
+    pub fn from_datum(patch: &Datum) -> Result<Self> {
+        let ops = patch
+            .as_array()
+            .ok_or_else(|| anyhow!("JSON Patch must be an array of operations"))?;
+        let ops = ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| PatchOp::from_datum(op).map_err(|e| anyhow!("Operation {}: {}", i, e)))
+            .collect::<Result<_>>()?;
+        Ok(Self { ops })
+    }
+
+    /// Apply the patch to `doc`, returning the patched document
+    pub fn apply(&self, doc: &Datum) -> Result<Datum> {
+        let mut doc = doc.clone();
+        for (i, op) in self.ops.iter().enumerate() {
+            op.apply(&mut doc)
+                .map_err(|e| anyhow!("Operation {} ({}): {}", i, op.name(), e))?;
+        }
+        Ok(doc)
+    }
+}
+
+impl PatchOp {
+    fn from_datum(op: &Datum) -> Result<Self> {
+        let obj = op
+            .as_object()
+            .ok_or_else(|| anyhow!("operation must be an object"))?;
+        let pointer = |field: &str| -> Result<Pointer> {
+            let path = obj
+                .get(field)
+                .and_then(|d| d.as_string())
+                .ok_or_else(|| anyhow!("missing string field `{}`", field))?;
+            Pointer::parse(path)
+        };
+        let value = || -> Result<Datum> {
+            obj.get("value")
+                .cloned()
+                .ok_or_else(|| anyhow!("missing field `value`"))
+        };
+
+        let name = obj
+            .get("op")
+            .and_then(|d| d.as_string())
+            .ok_or_else(|| anyhow!("missing string field `op`"))?;
+        Ok(match name {
+            "add" => PatchOp::Add {
+                path: pointer("path")?,
+                value: value()?,
+            },
+            "remove" => PatchOp::Remove {
+                path: pointer("path")?,
+            },
+            "replace" => PatchOp::Replace {
+                path: pointer("path")?,
+                value: value()?,
+            },
+            "move" => PatchOp::Move {
+                from: pointer("from")?,
+                path: pointer("path")?,
+            },
+            "copy" => PatchOp::Copy {
+                from: pointer("from")?,
+                path: pointer("path")?,
+            },
+            "test" => PatchOp::Test {
+                path: pointer("path")?,
+                value: value()?,
+            },
+            other => return Err(anyhow!("unknown op `{}`", other)),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PatchOp::Add { .. } => "add",
+            PatchOp::Remove { .. } => "remove",
+            PatchOp::Replace { .. } => "replace",
+            PatchOp::Move { .. } => "move",
+            PatchOp::Copy { .. } => "copy",
+            PatchOp::Test { .. } => "test",
+        }
+    }
+
+    fn apply(&self, doc: &mut Datum) -> Result<()> {
+        match self {
+            PatchOp::Add { path, value } => add(doc, path, value.clone()),
+            PatchOp::Remove { path } => remove(doc, path).map(|_| ()),
+            PatchOp::Replace { path, value } => {
+                *get_mut(doc, path)? = value.clone();
+                Ok(())
+            }
+            PatchOp::Move { from, path } => {
+                if path.0.len() > from.0.len() && path.0.starts_with(&from.0) {
+                    return Err(anyhow!("cannot move a value into itself"));
+                }
+                let value = remove(doc, from)?;
+                add(doc, path, value)
+            }
+            PatchOp::Copy { from, path } => {
+                let value = get_mut(doc, from)?.clone();
+                add(doc, path, value)
+            }
+            PatchOp::Test { path, value } => {
+                let actual = get_mut(doc, path)?;
+                if actual != value {
+                    return Err(anyhow!(
+                        "test failed at `{}`: expected {}, found {}",
+                        path,
+                        value,
+                        actual
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Pointer {
+    fn parse(path: &str) -> Result<Self> {
+        if path.is_empty() {
+            return Ok(Pointer(Vec::new()));
+        }
+        let tokens = path
+            .strip_prefix('/')
+            .ok_or_else(|| anyhow!("path `{}` must start with `/`", path))?;
+        Ok(Pointer(
+            tokens
+                .split('/')
+                .map(|token| token.replace("~1", "/").replace("~0", "~"))
+                .collect(),
+        ))
+    }
+
+    /// Parent tokens and the last token; `None` for the whole document
+    fn split_last(&self) -> Option<(&[String], &str)> {
+        self.0
+            .split_last()
+            .map(|(last, parent)| (parent, last.as_str()))
+    }
+}
+
+impl std::fmt::Display for Pointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in &self.0 {
+            write!(f, "/{}", token.replace('~', "~0").replace('/', "~1"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Array index of `token`, which may be at most `max`
+fn array_index(token: &str, max: usize) -> Result<usize> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|b| b.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    let index = valid
+        .then(|| token.parse::<usize>().ok())
+        .flatten()
+        .ok_or_else(|| anyhow!("invalid array index `{}`", token))?;
+    if index > max {
+        return Err(anyhow!("array index {} out of bounds", index));
+    }
+    Ok(index)
+}
+
+/// Value at `tokens`, which must exist
+fn resolve<'a>(doc: &'a mut Datum, tokens: &[String]) -> Result<&'a mut Datum> {
+    tokens.iter().try_fold(doc, |value, token| match value {
+        Datum::Object(obj) => obj
+            .get_mut(token)
+            .ok_or_else(|| anyhow!("no field `{}`", token)),
+        Datum::Array(arr) => {
+            let len = arr.len();
+            if len == 0 {
+                return Err(anyhow!("array index `{}` out of bounds", token));
+            }
+            let index = array_index(token, len - 1)?;
+            Ok(&mut arr[index])
+        }
+        _ => Err(anyhow!("cannot index into a scalar with `{}`", token)),
+    })
+}
+
+fn get_mut<'a>(doc: &'a mut Datum, path: &Pointer) -> Result<&'a mut Datum> {
+    resolve(doc, &path.0).map_err(|e| anyhow!("path `{}`: {}", path, e))
+}
+
+fn add(doc: &mut Datum, path: &Pointer, value: Datum) -> Result<()> {
+    let Some((parent, last)) = path.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+    match resolve(doc, parent).map_err(|e| anyhow!("path `{}`: {}", path, e))? {
+        Datum::Object(obj) => {
+            obj.insert(last.to_string(), value);
+        }
+        Datum::Array(arr) => {
+            let index = if last == "-" {
+                arr.len()
+            } else {
+                array_index(last, arr.len())?
+            };
+            arr.insert(index, value);
+        }
+        _ => return Err(anyhow!("path `{}`: parent is not a container", path)),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Datum, path: &Pointer) -> Result<Datum> {
+    let (parent, last) = path
+        .split_last()
+        .ok_or_else(|| anyhow!("cannot remove the whole document"))?;
+    match resolve(doc, parent).map_err(|e| anyhow!("path `{}`: {}", path, e))? {
+        Datum::Object(obj) => obj
+            .remove(last)
+            .ok_or_else(|| anyhow!("path `{}`: no field `{}`", path, last)),
+        Datum::Array(arr) if !arr.is_empty() => {
+            let index = array_index(last, arr.len() - 1)?;
+            Ok(arr.remove(index))
+        }
+        _ => Err(anyhow!("path `{}` does not exist", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(ops: serde_json::Value) -> JsonPatch {
+        JsonPatch::from_datum(&Datum::from(ops)).unwrap()
+    }
+
+    #[test]
+    fn test_move_copy_and_arrays() {
+        let doc = Datum::from(serde_json::json!({
+            "id": 1,
+            "tags": ["a", "c"],
+            "a/b": {"x": 1}
+        }));
+        let patched = patch(serde_json::json!([
+            {"op": "add", "path": "/tags/1", "value": "b"},
+            {"op": "add", "path": "/tags/-", "value": "d"},
+            {"op": "move", "from": "/a~1b", "path": "/moved"},
+            {"op": "copy", "from": "/moved/x", "path": "/x"},
+            {"op": "test", "path": "/tags/3", "value": "d"}
+        ]))
+        .apply(&doc)
+        .unwrap();
+        assert_eq!(
+            patched,
+            Datum::from(serde_json::json!({
+                "id": 1,
+                "tags": ["a", "b", "c", "d"],
+                "moved": {"x": 1},
+                "x": 1
+            }))
+        );
+
+        // A failing operation leaves nothing applied
+        let failed = patch(serde_json::json!([
+            {"op": "remove", "path": "/tags"},
+            {"op": "remove", "path": "/tags/0"}
+        ]))
+        .apply(&doc);
+        assert!(failed.is_err());
+        assert!(
+            patch(serde_json::json!([{"op": "move", "from": "/tags", "path": "/tags/0"}]))
+                .apply(&doc)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        for ops in [
+            serde_json::json!({"op": "add"}),
+            serde_json::json!([{"op": "frobnicate", "path": "/a"}]),
+            serde_json::json!([{"op": "add", "path": "a", "value": 1}]),
+            serde_json::json!([{"op": "replace", "path": "/a"}]),
+        ] {
+            assert!(JsonPatch::from_datum(&Datum::from(ops)).is_err());
+        }
+    }
+}
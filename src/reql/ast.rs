@@ -240,6 +240,40 @@ impl Term {
             .with_args(key_terms)
     }
     
+    /// Documents of `sequence` whose primary key lies in `[lower, upper)`
+    pub fn between(sequence: Term, lower: Term, upper: Term) -> Self {
+        Term::new(TermType::Between)
+            .with_arg(sequence)
+            .with_arg(lower)
+            .with_arg(upper)
+    }
+    
+    /// Call `function` of the plugin named `plugin` with `args`
+    pub fn call_plugin(plugin: &str, function: &str, args: Vec<Term>) -> Self {
+        Term::new(TermType::CallPlugin)
+            .with_arg(Term::datum(Datum::String(plugin.to_string())))
+            .with_arg(Term::datum(Datum::String(function.to_string())))
+            .with_args(args)
+    }
+    
+    /// `r.minval`: a range bound below every value
+    /// `r.do(args..., func)`: call `func` with `args`; on the wire the
+    /// function comes first
+    pub fn funcall(func: Term, args: Vec<Term>) -> Self {
+        Term::new(TermType::Funcall)
+            .with_arg(func)
+            .with_args(args)
+    }
+    
+    pub fn minval() -> Self {
+        Term::new(TermType::Minval)
+    }
+    
+    /// `r.maxval`: a range bound above every value
+    pub fn maxval() -> Self {
+        Term::new(TermType::Maxval)
+    }
+    
     pub fn filter(sequence: Term, predicate: Term) -> Self {
         Term::new(TermType::Filter)
             .with_arg(sequence)
@@ -277,6 +311,11 @@ impl Term {
             .with_arg(sequence)
     }
     
+    pub fn is_empty(sequence: Term) -> Self {
+        Term::new(TermType::IsEmpty)
+            .with_arg(sequence)
+    }
+    
     pub fn sum(sequence: Term, field: Option<String>) -> Self {
         let mut term = Term::new(TermType::Sum)
             .with_arg(sequence);
@@ -7,6 +7,7 @@
 //!
 //! - **Null**: Absence of a value
 //! - **Boolean**: true or false
+//! - **Integer**: Whole numbers, kept exact up to 64 bits
 //! - **Number**: f64 floating point numbers
 //! - **String**: UTF-8 encoded text
 //! - **Array**: Ordered list of datums
@@ -20,6 +21,7 @@
 //!
 //! let null_val = Datum::Null;
 //! let bool_val = Datum::Boolean(true);
+//! let int_val = Datum::Integer(42);
 //! let num_val = Datum::Number(42.5);
 //! let str_val = Datum::String("hello".into());
 //! let arr_val = Datum::Array(vec![num_val.clone(), str_val.clone()]);
@@ -29,19 +31,29 @@
 //! obj.insert("age".to_string(), Datum::Number(30.0));
 //! let obj_val = Datum::Object(obj);
 //! ```
+//!
+//! # Numbers
+//!
+//! Integers read from JSON stay `Integer`, so large ids round-trip exactly;
+//! other numbers are `Number`. The two are one type to queries: `42` and
+//! `42.0` are equal, sort together and make the same key. Arithmetic on
+//! integers stays integral unless it overflows or involves a float.
 
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 /// Datum represents a value in RethinkDB.
 ///
 /// This is the fundamental data type for all values stored and manipulated
 /// in RethinkDB queries. It's JSON-compatible with serde serialization.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Datum {
     Null,
     Boolean(bool),
+    // Before `Number`, so deserializing tries it first
+    Integer(i64),
     Number(f64),
     String(String),
     Array(Vec<Datum>),
@@ -65,11 +77,21 @@ impl Datum {
     /// Get as number
     pub fn as_number(&self) -> Option<f64> {
         match self {
+            Datum::Integer(i) => Some(*i as f64),
             Datum::Number(n) => Some(*n),
             _ => None,
         }
     }
 
+    /// Get as integer, if the datum is a whole number that fits
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Datum::Integer(i) => Some(*i),
+            Datum::Number(n) => exact_integer(*n),
+            _ => None,
+        }
+    }
+
     /// Get as boolean
     pub fn as_bool(&self) -> Option<bool> {
         match self {
@@ -93,6 +115,252 @@ impl Datum {
             _ => None,
         }
     }
+
+    /// Compare two datums in ReQL sort order
+    ///
+    /// Values of different types sort by type: arrays, booleans, null,
+    /// numbers, objects, strings. Arrays compare element by element, objects
+    /// as their fields sorted by name.
+    pub fn reql_cmp(&self, other: &Datum) -> Ordering {
+        match (self, other) {
+            (Datum::Boolean(a), Datum::Boolean(b)) => a.cmp(b),
+            (Datum::Integer(a), Datum::Integer(b)) => a.cmp(b),
+            (Datum::Number(a), Datum::Number(b)) => a.total_cmp(b),
+            (Datum::Integer(a), Datum::Number(b)) => cmp_integer_number(*a, *b),
+            (Datum::Number(a), Datum::Integer(b)) => cmp_integer_number(*b, *a).reverse(),
+            (Datum::String(a), Datum::String(b)) => a.cmp(b),
+            (Datum::Array(a), Datum::Array(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| x.reql_cmp(y))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (Datum::Object(a), Datum::Object(b)) => {
+                let mut a: Vec<_> = a.iter().collect();
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort_unstable_by(|x, y| x.0.cmp(y.0));
+                b.sort_unstable_by(|x, y| x.0.cmp(y.0));
+                a.iter()
+                    .zip(&b)
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.reql_cmp(vb)))
+                    .find(|o| o.is_ne())
+                    .unwrap_or_else(|| a.len().cmp(&b.len()))
+            }
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    /// Position of the datum's type in ReQL sort order
+    fn type_rank(&self) -> u8 {
+        match self {
+            Datum::Array(_) => 0,
+            Datum::Boolean(_) => 1,
+            Datum::Null => 2,
+            Datum::Integer(_) | Datum::Number(_) => 3,
+            Datum::Object(_) => 4,
+            Datum::String(_) => 5,
+        }
+    }
+
+    /// Encode the datum into a hashable key
+    ///
+    /// Equal datums produce equal keys: object fields are encoded in sorted
+    /// order, whole numbers as integers and `-0.0` as `0.0`.
+    pub fn canonical_key(&self) -> Vec<u8> {
+        let mut key = Vec::new();
+        self.write_canonical_key(&mut key);
+        key
+    }
+
+    fn write_canonical_key(&self, key: &mut Vec<u8>) {
+        match self {
+            Datum::Null => key.push(0),
+            Datum::Boolean(b) => key.extend_from_slice(&[1, *b as u8]),
+            Datum::Integer(i) => {
+                key.push(6);
+                key.extend_from_slice(&i.to_be_bytes());
+            }
+            Datum::Number(n) => match exact_integer(*n) {
+                Some(i) => Datum::Integer(i).write_canonical_key(key),
+                None => {
+                    key.push(2);
+                    key.extend_from_slice(&n.to_bits().to_be_bytes());
+                }
+            },
+            Datum::String(s) => {
+                key.push(3);
+                key.extend_from_slice(&(s.len() as u64).to_be_bytes());
+                key.extend_from_slice(s.as_bytes());
+            }
+            Datum::Array(arr) => {
+                key.push(4);
+                key.extend_from_slice(&(arr.len() as u64).to_be_bytes());
+                for item in arr {
+                    item.write_canonical_key(key);
+                }
+            }
+            Datum::Object(obj) => {
+                let mut fields: Vec<_> = obj.iter().collect();
+                fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                key.push(5);
+                key.extend_from_slice(&(fields.len() as u64).to_be_bytes());
+                for (name, value) in fields {
+                    key.extend_from_slice(&(name.len() as u64).to_be_bytes());
+                    key.extend_from_slice(name.as_bytes());
+                    value.write_canonical_key(key);
+                }
+            }
+        }
+    }
+
+    /// Encode the datum so that encodings sort byte by byte in ReQL order
+    ///
+    /// Encodings are self-delimiting: one never is a prefix of another, so
+    /// keys can chain them and still be scanned by a leading datum. They
+    /// are valid UTF-8, with numbers written as hex digits. Whole numbers
+    /// encode like the integers they equal; integers too large for a float
+    /// stay distinct.
+    pub fn ordered_key(&self) -> Vec<u8> {
+        let mut key = Vec::new();
+        self.write_ordered_key(&mut key);
+        key
+    }
+
+    fn write_ordered_key(&self, key: &mut Vec<u8>) {
+        // Type bytes follow `type_rank`; 0 ends arrays, objects and strings
+        match self {
+            Datum::Array(items) => {
+                key.push(b'A');
+                for item in items {
+                    item.write_ordered_key(key);
+                }
+                key.push(0);
+            }
+            Datum::Boolean(b) => key.extend_from_slice(if *b { b"B1" } else { b"B0" }),
+            Datum::Null => key.push(b'C'),
+            Datum::Integer(i) => {
+                // Float order first, then the integer's offset from the
+                // float it rounds to
+                let rounded = *i as f64;
+                let offset = i.saturating_sub(rounded as i64) as i32;
+                write_ordered_number(rounded, offset, key);
+            }
+            Datum::Number(n) => match exact_integer(*n) {
+                Some(i) => Datum::Integer(i).write_ordered_key(key),
+                None => write_ordered_number(*n, 0, key),
+            },
+            Datum::Object(obj) => {
+                let mut fields: Vec<_> = obj.iter().collect();
+                fields.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                key.push(b'E');
+                for (name, value) in fields {
+                    key.push(b'F');
+                    write_ordered_str(name, key);
+                    value.write_ordered_key(key);
+                }
+                key.push(0);
+            }
+            Datum::String(s) => {
+                key.push(b'F');
+                write_ordered_str(s, key);
+            }
+        }
+    }
+}
+
+/// `n` as an integer, if it is a whole number within `i64`
+fn exact_integer(n: f64) -> Option<i64> {
+    // 2^63 itself is out of range, and the largest float below it is not
+    let in_range = (-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&n);
+    (in_range && n.fract() == 0.0).then_some(n as i64)
+}
+
+/// `a` compared with `b` exactly, without rounding `a` to a float
+fn cmp_integer_number(a: i64, b: f64) -> Ordering {
+    if b.is_nan() {
+        // Like `total_cmp`, NaNs sort beyond every number on their sign's side
+        return if b.is_sign_negative() {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        };
+    }
+    // `b` is its floor plus a fraction in [0, 1)
+    let floor = b.floor();
+    match exact_integer(floor) {
+        Some(floor_int) => a.cmp(&floor_int).then(if b > floor {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }),
+        None if floor > 0.0 => Ordering::Less,
+        None => Ordering::Greater,
+    }
+}
+
+/// A number as hex digits that sort like it: the float, then `offset`
+fn write_ordered_number(n: f64, offset: i32, key: &mut Vec<u8>) {
+    let bits = n.to_bits();
+    let ordered = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    let offset = (offset as u32) ^ (1 << 31);
+    key.push(b'D');
+    key.extend_from_slice(format!("{:016x}{:08x}", ordered, offset).as_bytes());
+}
+
+/// `s` terminated by a 0 byte, with bytes 0 and 1 escaped as `1 1` and `1 2`
+fn write_ordered_str(s: &str, key: &mut Vec<u8>) {
+    for &byte in s.as_bytes() {
+        match byte {
+            0 | 1 => key.extend_from_slice(&[1, byte + 1]),
+            byte => key.push(byte),
+        }
+    }
+    key.push(0);
+}
+
+impl PartialEq for Datum {
+    /// Structural equality, except that numbers compare by value
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Datum::Null, Datum::Null) => true,
+            (Datum::Boolean(a), Datum::Boolean(b)) => a == b,
+            (Datum::Integer(a), Datum::Integer(b)) => a == b,
+            (Datum::Number(a), Datum::Number(b)) => a == b,
+            (Datum::Integer(a), Datum::Number(b)) | (Datum::Number(b), Datum::Integer(a)) => {
+                exact_integer(*b) == Some(*a)
+            }
+            (Datum::String(a), Datum::String(b)) => a == b,
+            (Datum::Array(a), Datum::Array(b)) => a == b,
+            (Datum::Object(a), Datum::Object(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Endpoint of a range, e.g. a bound of BETWEEN
+///
+/// `r.minval` and `r.maxval` are not values: they sort before and after
+/// every datum and exist only as range bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bound {
+    MinVal,
+    Value(Datum),
+    MaxVal,
+}
+
+impl Bound {
+    /// Compare the bound with `datum` in ReQL sort order
+    pub fn cmp_datum(&self, datum: &Datum) -> Ordering {
+        match self {
+            Bound::MinVal => Ordering::Less,
+            Bound::Value(value) => value.reql_cmp(datum),
+            Bound::MaxVal => Ordering::Greater,
+        }
+    }
 }
 
 // Conversions
@@ -104,7 +372,13 @@ impl From<bool> for Datum {
 
 impl From<i32> for Datum {
     fn from(n: i32) -> Self {
-        Datum::Number(n as f64)
+        Datum::Integer(n as i64)
+    }
+}
+
+impl From<i64> for Datum {
+    fn from(n: i64) -> Self {
+        Datum::Integer(n)
     }
 }
 
@@ -131,9 +405,10 @@ impl From<serde_json::Value> for Datum {
         match value {
             serde_json::Value::Null => Datum::Null,
             serde_json::Value::Bool(b) => Datum::Boolean(b),
-            serde_json::Value::Number(n) => {
-                Datum::Number(n.as_f64().unwrap_or(0.0))
-            }
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Datum::Integer(i),
+                None => Datum::Number(n.as_f64().unwrap_or(0.0)),
+            },
             serde_json::Value::String(s) => Datum::String(s),
             serde_json::Value::Array(arr) => {
                 Datum::Array(arr.into_iter().map(Datum::from).collect())
@@ -154,6 +429,7 @@ impl From<Datum> for serde_json::Value {
         match datum {
             Datum::Null => serde_json::Value::Null,
             Datum::Boolean(b) => serde_json::Value::Bool(b),
+            Datum::Integer(i) => serde_json::Value::Number(i.into()),
             Datum::Number(n) => {
                 serde_json::Value::Number(
                     serde_json::Number::from_f64(n).unwrap_or_else(|| serde_json::Number::from(0))
@@ -179,6 +455,7 @@ impl std::fmt::Display for Datum {
         match self {
             Datum::Null => write!(f, "null"),
             Datum::Boolean(b) => write!(f, "{}", b),
+            Datum::Integer(i) => write!(f, "{}", i),
             Datum::Number(n) => write!(f, "{}", n),
             Datum::String(s) => write!(f, "\"{}\"", s),
             Datum::Array(arr) => {
@@ -200,3 +477,141 @@ impl std::fmt::Display for Datum {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integers_round_trip() {
+        let big = 9_007_199_254_740_993_i64; // 2^53 + 1, not representable as f64
+        let datum = Datum::from(serde_json::json!({ "id": big }));
+        assert_eq!(datum.as_object().unwrap()["id"], Datum::Integer(big));
+        assert_eq!(
+            serde_json::Value::from(datum.clone()).to_string(),
+            format!(r#"{{"id":{}}}"#, big)
+        );
+        let parsed: Datum = serde_json::from_str(&serde_json::to_string(&datum).unwrap()).unwrap();
+        assert_eq!(parsed.as_object().unwrap()["id"].as_integer(), Some(big));
+
+        // Floats stay floats
+        let float: Datum = serde_json::from_str("2.5").unwrap();
+        assert_eq!(float, Datum::Number(2.5));
+        assert_eq!(serde_json::to_string(&float).unwrap(), "2.5");
+    }
+
+    #[test]
+    fn test_whole_numbers_are_one_value() {
+        let (int, float) = (Datum::Integer(42), Datum::Number(42.0));
+        assert_eq!(int, float);
+        assert_eq!(int.reql_cmp(&float), Ordering::Equal);
+        assert_eq!(int.canonical_key(), float.canonical_key());
+        assert_eq!(int.to_string(), float.to_string());
+        assert_ne!(Datum::Integer(42), Datum::Number(42.5));
+        assert_ne!(Datum::Integer(i64::MAX), Datum::Number(i64::MAX as f64));
+        assert_eq!(
+            Datum::Number(-0.0).canonical_key(),
+            Datum::Integer(0).canonical_key()
+        );
+        assert!(Datum::Integer(2).reql_cmp(&Datum::Number(2.5)).is_lt());
+    }
+
+    #[test]
+    fn test_integers_compare_exactly_with_floats() {
+        let cmp = |a: i64, b: f64| {
+            let ordering = Datum::Integer(a).reql_cmp(&Datum::Number(b));
+            assert_eq!(Datum::Number(b).reql_cmp(&Datum::Integer(a)), ordering.reverse());
+            ordering
+        };
+        let two_53 = 9_007_199_254_740_992_i64;
+
+        // Integers a float cannot hold are not rounded onto their neighbours
+        assert_eq!(cmp(two_53 + 1, two_53 as f64), Ordering::Greater);
+        assert_eq!(cmp(two_53 - 1, two_53 as f64), Ordering::Less);
+        assert_eq!(cmp(two_53, two_53 as f64), Ordering::Equal);
+        assert_eq!(cmp(i64::MAX, i64::MAX as f64), Ordering::Less);
+        assert_eq!(cmp(i64::MIN, i64::MIN as f64), Ordering::Equal);
+        assert_eq!(cmp(i64::MIN, -1e19), Ordering::Greater);
+
+        // Fractions and the edges of the float range
+        assert_eq!(cmp(5, 5.5), Ordering::Less);
+        assert_eq!(cmp(-5, -5.5), Ordering::Greater);
+        assert_eq!(cmp(-6, -5.5), Ordering::Less);
+        assert_eq!(cmp(0, -0.0), Ordering::Equal);
+        assert_eq!(cmp(i64::MAX, f64::INFINITY), Ordering::Less);
+        assert_eq!(cmp(i64::MIN, f64::NEG_INFINITY), Ordering::Greater);
+        assert_eq!(cmp(0, f64::NAN), Ordering::Less);
+    }
+
+    #[test]
+    fn test_ordered_keys_sort_in_reql_order() {
+        let big = 9_007_199_254_740_993_i64; // 2^53 + 1
+        let sorted: Vec<Datum> = [
+            serde_json::json!([]),
+            serde_json::json!([1]),
+            serde_json::json!([1, "a"]),
+            serde_json::json!([2]),
+            serde_json::json!(false),
+            serde_json::json!(true),
+            serde_json::json!(null),
+            serde_json::json!(-1e300),
+            serde_json::json!(-2),
+            serde_json::json!(-1.5),
+            serde_json::json!(0),
+            serde_json::json!(0.5),
+            serde_json::json!(2),
+            serde_json::json!(10),
+            serde_json::json!(big - 1),
+            serde_json::json!(big),
+            serde_json::json!(i64::MAX),
+            serde_json::json!({}),
+            serde_json::json!({"": 1}),
+            serde_json::json!({"a": 1}),
+            serde_json::json!({"a": 1, "b": 0}),
+            serde_json::json!({"a": 2}),
+            serde_json::json!(""),
+            serde_json::json!("a"),
+            serde_json::json!("a\u{0}"),
+            serde_json::json!("a\u{0}b"),
+            serde_json::json!("ab"),
+            serde_json::json!("b"),
+        ]
+        .into_iter()
+        .map(Datum::from)
+        .collect();
+        for pair in sorted.windows(2) {
+            assert!(
+                pair[0].reql_cmp(&pair[1]).is_le(),
+                "{} > {}",
+                pair[0],
+                pair[1]
+            );
+            assert!(
+                pair[0].ordered_key() < pair[1].ordered_key(),
+                "{} sorts after {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        for (i, a) in sorted.iter().enumerate() {
+            assert!(std::str::from_utf8(&a.ordered_key()).is_ok());
+            for b in &sorted[i + 1..] {
+                assert!(
+                    !b.ordered_key().starts_with(&a.ordered_key()),
+                    "{} prefixes {}",
+                    a,
+                    b
+                );
+            }
+        }
+
+        assert_eq!(
+            Datum::Number(10.0).ordered_key(),
+            Datum::Integer(10).ordered_key()
+        );
+        assert_eq!(
+            Datum::Number(-0.0).ordered_key(),
+            Datum::Integer(0).ordered_key()
+        );
+    }
+}
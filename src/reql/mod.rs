@@ -31,10 +31,11 @@
 pub mod ast;
 pub mod datum;
 pub mod protocol;
+pub mod ql2;
 pub mod terms;
 pub mod types;
 
 pub use ast::{Term, TermBuilder};
-pub use datum::Datum;
+pub use datum::{Bound, Datum};
 pub use terms::TermType;
 pub use types::*;
@@ -17,6 +17,7 @@ impl RustDatum {
         match self {
             RustDatum::Null => builder.set_null(()),
             RustDatum::Boolean(b) => builder.set_bool(*b),
+            RustDatum::Integer(i) => builder.set_number(*i as f64),
             RustDatum::Number(n) => builder.set_number(*n),
             RustDatum::String(s) => builder.set_string(s.as_str()),
             RustDatum::Array(arr) => {
@@ -0,0 +1,125 @@
+//! Term ids of RethinkDB's JSON protocol (`ql2.proto`).
+//!
+//! Official drivers number terms as in `ql2.proto`, which differs from the
+//! Cap'n Proto ordinals of [`TermType`]: `r.db_list()` is `[59]` from a
+//! driver but DB_LIST is 79 here. [`QueryCompiler::compile_ql2`] decodes
+//! driver JSON with this table.
+//!
+//! Only terms the executor implements are listed. `BRACKET` (`row("age")`,
+//! `r.expr([1, 2])(0)`) is compiled to GET_FIELD or NTH when its key is a
+//! literal string or number, and dispatched on the key's value otherwise.
+//!
+//! [`QueryCompiler::compile_ql2`]: crate::query::QueryCompiler::compile_ql2
+
+use super::TermType;
+
+/// `ql2.proto` id of DATUM, which the JSON encoding never sends
+pub const DATUM: u64 = 1;
+
+/// `ql2.proto` id of MAKE_ARRAY, the only way to send an array literal
+pub const MAKE_ARRAY: u64 = 2;
+
+/// `ql2.proto` id of BRACKET, a field or element lookup
+pub const BRACKET: u64 = 170;
+
+/// `ql2.proto` ids of the supported terms
+const TERM_IDS: &[(u64, TermType)] = &[
+    (MAKE_ARRAY, TermType::MakeArray),
+    (3, TermType::MakeObj),
+    (10, TermType::Var),
+    (11, TermType::Javascript),
+    (14, TermType::Db),
+    (15, TermType::Table),
+    (16, TermType::Get),
+    (17, TermType::Eq),
+    (18, TermType::Ne),
+    (19, TermType::Lt),
+    (20, TermType::Le),
+    (21, TermType::Gt),
+    (22, TermType::Ge),
+    (23, TermType::Not),
+    (24, TermType::Add),
+    (25, TermType::Sub),
+    (26, TermType::Mul),
+    (27, TermType::Div),
+    (28, TermType::Mod),
+    (29, TermType::Append),
+    (30, TermType::Slice),
+    (31, TermType::GetField),
+    (32, TermType::HasFields),
+    (33, TermType::Pluck),
+    (34, TermType::Without),
+    (35, TermType::Merge),
+    (37, TermType::Reduce),
+    (38, TermType::Map),
+    (39, TermType::Filter),
+    (40, TermType::ConcatMap),
+    (41, TermType::OrderBy),
+    (42, TermType::Distinct),
+    (43, TermType::Count),
+    (44, TermType::Union),
+    (45, TermType::Nth),
+    (51, TermType::CoerceTo),
+    (52, TermType::TypeOf),
+    (53, TermType::Update),
+    (54, TermType::Delete),
+    (55, TermType::Replace),
+    (56, TermType::Insert),
+    (57, TermType::DbCreate),
+    (58, TermType::DbDrop),
+    (59, TermType::DbList),
+    (60, TermType::TableCreate),
+    (61, TermType::TableDrop),
+    (62, TermType::TableList),
+    (64, TermType::Funcall),
+    (65, TermType::Branch),
+    (66, TermType::Or),
+    (67, TermType::And),
+    (68, TermType::ForEach),
+    (69, TermType::Func),
+    (70, TermType::Skip),
+    (71, TermType::Limit),
+    (78, TermType::GetAll),
+    (79, TermType::Info),
+    (80, TermType::Prepend),
+    (81, TermType::Sample),
+    (82, TermType::InsertAt),
+    (83, TermType::DeleteAt),
+    (84, TermType::ChangeAt),
+    (85, TermType::SpliceAt),
+    (86, TermType::IsEmpty),
+    (88, TermType::SetInsert),
+    (89, TermType::SetIntersection),
+    (90, TermType::SetUnion),
+    (91, TermType::SetDifference),
+    (92, TermType::Default),
+    (93, TermType::Contains),
+    (94, TermType::Keys),
+    (95, TermType::Difference),
+    (143, TermType::Object),
+    (144, TermType::Group),
+    (145, TermType::Sum),
+    (146, TermType::Avg),
+    (147, TermType::Min),
+    (148, TermType::Max),
+    (150, TermType::Ungroup),
+    (BRACKET, TermType::Bracket),
+    (174, TermType::Config),
+    (175, TermType::Status),
+    (176, TermType::Reconfigure),
+    (177, TermType::Wait),
+    (180, TermType::Minval),
+    (181, TermType::Maxval),
+    (182, TermType::Between),
+    (186, TermType::Values),
+    // Not in `ql2.proto`; plugin calls keep their native id
+    (273, TermType::CallPlugin),
+];
+
+/// The term type a driver means by `id`
+pub fn term_type(id: u64) -> Option<TermType> {
+    TERM_IDS
+        .iter()
+        .find(|(term_id, _)| *term_id == id)
+        .map(|(_, term_type)| *term_type)
+}
@@ -10,13 +10,13 @@
 //! - **Database Operations**: DB, DB_CREATE, DB_DROP, DB_LIST
 //! - **Table Operations**: TABLE, TABLE_CREATE, TABLE_DROP, TABLE_LIST
 //! - **Data Access**: GET, GET_ALL, BETWEEN
-//! - **Transformations**: FILTER, MAP, CONCAT_MAP, ORDER_BY, DISTINCT
+//! - **Transformations**: FILTER, MAP, CONCAT_MAP, ORDER_BY, DISTINCT, UNION, SAMPLE
 //! - **Aggregations**: COUNT, SUM, AVG, MIN, MAX, GROUP, REDUCE
 //! - **Math Operations**: ADD, SUB, MUL, DIV, MOD
 //! - **Logic Operations**: EQ, NE, LT, LE, GT, GE, AND, OR, NOT
 //! - **Array Operations**: APPEND, PREPEND, SLICE, INSERT_AT, DELETE_AT
 //! - **Object Operations**: GET_FIELD, KEYS, VALUES, PLUCK, WITHOUT, MERGE
-//! - **Control Flow**: BRANCH, FOR_EACH, FUNC
+//! - **Control Flow**: BRANCH, FOR_EACH, FUNC, DEFAULT
 //! - **Type Operations**: TYPE_OF, COERCE_TO
 //!
 //! # Example
@@ -89,6 +89,7 @@ pub enum TermType {
     GetField = 40,
     Keys = 41,
     Values = 42,
+    Object = 43,
     HasFields = 44,
     Pluck = 46,
     Without = 47,
@@ -105,7 +106,10 @@ pub enum TermType {
     OrderBy = 55,
     Distinct = 56,
     Count = 57,
+    IsEmpty = 58,
+    Union = 59,
     Nth = 60,
+    Bracket = 61,
     
     // Array mutations
     InsertAt = 67,
@@ -132,13 +136,21 @@ pub enum TermType {
     TableCreate = 80,
     TableDrop = 81,
     TableList = 82,
+    Config = 83,
+    Status = 84,
+    Wait = 85,
+    Reconfigure = 86,
     
     // Control flow
+    Funcall = 98,
     Branch = 99,
     Or = 100,
     And = 101,
     ForEach = 102,
     Func = 103,  // Renamed from FuncCall to match Cap'n Proto
+    Info = 106,
+    Sample = 110,
+    Default = 111,
     
     // Grouping & aggregations (higher numbers)
     Group = 152,
@@ -146,6 +158,14 @@ pub enum TermType {
     Avg = 154,
     Min = 155,
     Max = 156,
+    Ungroup = 157,
+    
+    // Range bound constants
+    Minval = 177,
+    Maxval = 178,
+    
+    // Plugins
+    CallPlugin = 273,
 }
 
 impl TermType {
@@ -203,6 +223,7 @@ impl TermType {
             40 => Some(TermType::GetField),
             41 => Some(TermType::Keys),
             42 => Some(TermType::Values),
+            43 => Some(TermType::Object),
             44 => Some(TermType::HasFields),
             46 => Some(TermType::Pluck),
             47 => Some(TermType::Without),
@@ -215,7 +236,10 @@ impl TermType {
             55 => Some(TermType::OrderBy),
             56 => Some(TermType::Distinct),
             57 => Some(TermType::Count),
+            58 => Some(TermType::IsEmpty),
+            59 => Some(TermType::Union),
             60 => Some(TermType::Nth),
+            61 => Some(TermType::Bracket),
             67 => Some(TermType::InsertAt),
             68 => Some(TermType::DeleteAt),
             69 => Some(TermType::ChangeAt),
@@ -232,16 +256,28 @@ impl TermType {
             80 => Some(TermType::TableCreate),
             81 => Some(TermType::TableDrop),
             82 => Some(TermType::TableList),
+            83 => Some(TermType::Config),
+            84 => Some(TermType::Status),
+            85 => Some(TermType::Wait),
+            86 => Some(TermType::Reconfigure),
+            98 => Some(TermType::Funcall),
             99 => Some(TermType::Branch),
             100 => Some(TermType::Or),
             101 => Some(TermType::And),
             102 => Some(TermType::ForEach),
             103 => Some(TermType::Func),
+            106 => Some(TermType::Info),
+            110 => Some(TermType::Sample),
+            111 => Some(TermType::Default),
             152 => Some(TermType::Group),
             153 => Some(TermType::Sum),
             154 => Some(TermType::Avg),
             155 => Some(TermType::Min),
             156 => Some(TermType::Max),
+            157 => Some(TermType::Ungroup),
+            177 => Some(TermType::Minval),
+            178 => Some(TermType::Maxval),
+            273 => Some(TermType::CallPlugin),
             _ => None,
         }
     }
@@ -311,6 +347,7 @@ impl TermType {
             TermType::GetField => "GET_FIELD",
             TermType::Keys => "KEYS",
             TermType::Values => "VALUES",
+            TermType::Object => "OBJECT",
             TermType::HasFields => "HAS_FIELDS",
             TermType::Pluck => "PLUCK",
             TermType::Without => "WITHOUT",
@@ -323,7 +360,10 @@ impl TermType {
             TermType::OrderBy => "ORDER_BY",
             TermType::Distinct => "DISTINCT",
             TermType::Count => "COUNT",
+            TermType::IsEmpty => "IS_EMPTY",
+            TermType::Union => "UNION",
             TermType::Nth => "NTH",
+            TermType::Bracket => "BRACKET",
             TermType::InsertAt => "INSERT_AT",
             TermType::DeleteAt => "DELETE_AT",
             TermType::ChangeAt => "CHANGE_AT",
@@ -340,16 +380,28 @@ impl TermType {
             TermType::TableCreate => "TABLE_CREATE",
             TermType::TableDrop => "TABLE_DROP",
             TermType::TableList => "TABLE_LIST",
+            TermType::Config => "CONFIG",
+            TermType::Status => "STATUS",
+            TermType::Wait => "WAIT",
+            TermType::Reconfigure => "RECONFIGURE",
             TermType::Branch => "BRANCH",
             TermType::Or => "OR",
             TermType::And => "AND",
             TermType::ForEach => "FOR_EACH",
+            TermType::Funcall => "FUNCALL",
             TermType::Func => "FUNC",
+            TermType::Info => "INFO",
+            TermType::Sample => "SAMPLE",
+            TermType::Default => "DEFAULT",
+            TermType::Ungroup => "UNGROUP",
             TermType::Group => "GROUP",
             TermType::Sum => "SUM",
             TermType::Avg => "AVG",
             TermType::Min => "MIN",
             TermType::Max => "MAX",
+            TermType::Minval => "MINVAL",
+            TermType::Maxval => "MAXVAL",
+            TermType::CallPlugin => "CALL_PLUGIN",
         }
     }
 }
@@ -385,4 +437,32 @@ mod tests {
         assert_eq!(TermType::Filter.name(), "FILTER");
         assert_eq!(TermType::Insert.name(), "INSERT");
     }
+
+    #[test]
+    fn test_term_ids_match_capnp() {
+        // `funcall @98;` in term.capnp is FUNCALL = 98
+        let capnp_names: std::collections::HashMap<u64, String> =
+            include_str!("../../proto/term.capnp")
+                .lines()
+                .filter_map(|line| {
+                    let (name, id) = line.split_once(';')?.0.trim().split_once(" @")?;
+                    let mut upper = String::new();
+                    for c in name.chars() {
+                        if c.is_ascii_uppercase() {
+                            upper.push('_');
+                        }
+                        upper.push(c.to_ascii_uppercase());
+                    }
+                    Some((id.parse().ok()?, upper))
+                })
+                .collect();
+
+        for id in 0..1000 {
+            if let Some(term_type) = TermType::from_u64(id) {
+                assert_eq!(term_type.to_u64(), id);
+                assert_eq!(capnp_names.get(&id).map(String::as_str), Some(term_type.name()));
+            }
+        }
+        assert_eq!(TermType::from_u64(98), Some(TermType::Funcall));
+    }
 }
@@ -0,0 +1,80 @@
+//! Request correlation ids
+//!
+//! Every HTTP request and every query of a TCP connection gets an id. While
+//! it runs, the id is the current one of its task, see [`current`]; tasks
+//! spawned on its behalf must be wrapped in [`inherit`] to keep it. The
+//! calls a request makes to other nodes send the id along in the
+//! [`REQUEST_ID_HEADER`] header, so their logs carry it as well.
+//!
+//! The HTTP side lives in [`server::request_id`](crate::server::request_id),
+//! the TCP side in [`Connection`](crate::network::Connection).
+
+use std::future::Future;
+use tracing::{Instrument, Span};
+
+/// Header carrying the id in HTTP requests and responses
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-provided id accepted; longer ones are replaced
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request the current task runs for
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Run `fut` with `id` as the current request id
+pub async fn scope<F: Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+
+/// `fut` carrying the request id and span of the caller, for spawning
+pub fn inherit<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let id = current();
+    let fut = fut.instrument(Span::current());
+    async move {
+        match id {
+            Some(id) => scope(id, fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// The client's id if usable, else a new one
+///
+/// Ids must be non-empty printable ASCII of at most [`MAX_LEN`] characters
+/// so they are safe to log and to send on.
+pub fn accept(client_id: Option<&str>) -> String {
+    client_id
+        .filter(|id| !id.is_empty() && id.len() <= MAX_LEN)
+        .filter(|id| id.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_inherit_keeps_id() {
+        assert_eq!(current(), None);
+        let id = scope("abc".to_string(), async {
+            tokio::spawn(inherit(async { current() })).await.unwrap()
+        })
+        .await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_accept() {
+        assert_eq!(accept(Some("req-1")), "req-1");
+        for unusable in [None, Some(""), Some("bad id")] {
+            assert!(uuid::Uuid::parse_str(&accept(unusable)).is_ok());
+        }
+    }
+}
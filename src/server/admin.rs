@@ -0,0 +1,543 @@
+//! Data endpoints behind the admin dashboard
+//!
+//! The dashboard at `/_admin` polls these for its panels:
+//! - GET /_admin/api/overview - Counts, connections, QPS and error rate at a glance
+//! - GET /_admin/api/storage  - Databases with their tables and document counts
+//! - GET /_admin/api/queries  - Connections, query throughput and recent error rate
+//! - GET /_admin/api/cluster  - This node and the cluster members with their roles
+//! - GET /_admin/api/stream   - WebSocket pushing live metric snapshots
+//!
+//! Query rates cover the last [`RATE_WINDOW`](crate::cluster::metrics::RATE_WINDOW)
+//! of queries run by this node. Document counts come from scanning each
+//! table, so the storage and overview endpoints get slower as data grows.
+//!
+//! # Live metrics
+//!
+//! The stream sends a `{"type": "metrics", ...}` snapshot of CPU, memory,
+//! query rates, connections and cluster health right away and then every
+//! `interval` seconds (query parameter, default 2, clamped to 0.05..3600).
+//! Clients pause it with `{"action": "unsubscribe"}` and resume it with
+//! `{"action": "subscribe", "interval": <seconds>}`, the interval being
+//! optional; both are acknowledged. The stream ends when the socket closes.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, Query,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error};
+
+use crate::cluster::metrics::{
+    QueryRates, ACTIVE_CONNECTIONS, CLUSTER_HEALTH, CPU_USAGE, MEMORY_USAGE, MEMORY_USAGE_PERCENT,
+};
+use crate::cluster::{Node, NodeRole};
+use crate::server::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct TableSummary {
+    pub name: String,
+    pub primary_key: String,
+    pub documents: u64,
+    pub indexes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseSummary {
+    pub name: String,
+    pub tables: Vec<TableSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageSummary {
+    pub databases: usize,
+    pub tables: usize,
+    pub documents: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberSummary {
+    pub id: String,
+    /// Unknown for this node, which is not among its own peers
+    pub addr: Option<String>,
+    pub role: NodeRole,
+    pub last_heartbeat: Option<String>,
+    /// Whether calls to the node are currently short-circuited
+    pub degraded: bool,
+    /// Whether this is the node serving the request
+    pub local: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterSummary {
+    pub node_id: String,
+    pub role: NodeRole,
+    pub members: Vec<MemberSummary>,
+    pub masters: usize,
+    pub replicas: usize,
+    pub candidates: usize,
+}
+
+/// Every database with its tables
+async fn databases(state: &AppState) -> anyhow::Result<Vec<DatabaseSummary>> {
+    let mut databases = Vec::new();
+    for db in state.databases.list_databases().await? {
+        let mut tables = Vec::new();
+        for table in state.databases.list_tables(&db).await? {
+            let Some(config) = state.databases.get_table_config(&db, &table).await? else {
+                continue;
+            };
+            let documents = state.storage.count_table(&db, &table).await?;
+            tables.push(TableSummary {
+                name: config.name,
+                primary_key: config.primary_key,
+                documents,
+                indexes: config.indexes,
+            });
+        }
+        databases.push(DatabaseSummary { name: db, tables });
+    }
+    Ok(databases)
+}
+
+fn storage_summary(databases: &[DatabaseSummary]) -> StorageSummary {
+    StorageSummary {
+        databases: databases.len(),
+        tables: databases.iter().map(|db| db.tables.len()).sum(),
+        documents: databases
+            .iter()
+            .flat_map(|db| &db.tables)
+            .map(|table| table.documents)
+            .sum(),
+    }
+}
+
+/// This node followed by its peers
+async fn cluster_summary(state: &AppState) -> ClusterSummary {
+    let node_id = state.cluster.node_id().to_string();
+    let role = state.cluster.get_role().await;
+    let mut peers: Vec<Node> = state.cluster.get_nodes().await;
+    peers.retain(|node| node.id != node_id);
+    peers.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut members = vec![MemberSummary {
+        id: node_id.clone(),
+        addr: None,
+        role,
+        last_heartbeat: None,
+        degraded: false,
+        local: true,
+    }];
+    members.extend(peers.iter().map(|node| MemberSummary {
+        id: node.id.clone(),
+        addr: Some(node.addr.to_string()),
+        role: node.role,
+        last_heartbeat: Some(node.last_heartbeat.to_rfc3339()),
+        degraded: state.cluster.is_degraded(&node.id),
+        local: false,
+    }));
+    let count = |role: NodeRole| members.iter().filter(|m| m.role == role).count();
+
+    ClusterSummary {
+        node_id,
+        role,
+        masters: count(NodeRole::Master),
+        replicas: count(NodeRole::Replica),
+        candidates: count(NodeRole::Candidate),
+        members,
+    }
+}
+
+fn query_summary(state: &AppState) -> (u64, QueryRates) {
+    (ACTIVE_CONNECTIONS.get(), state.metrics.query_rates())
+}
+
+fn internal_error(e: anyhow::Error) -> Response {
+    error!(error = %e, "Failed to collect admin data");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(serde_json::json!({
+            "success": false,
+            "error": format!("{:#}", e),
+        })),
+    )
+        .into_response()
+}
+
+/// Everything the dashboard shows at a glance
+pub async fn overview(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let databases = match databases(&state).await {
+        Ok(databases) => databases,
+        Err(e) => return internal_error(e),
+    };
+    let (connections, rates) = query_summary(&state);
+    let cluster = cluster_summary(&state).await;
+
+    Json(serde_json::json!({
+        "success": true,
+        "version": env!("CARGO_PKG_VERSION"),
+        "storage": storage_summary(&databases),
+        "connections": connections,
+        "qps": rates.qps,
+        "error_rate": rates.error_rate,
+        "cluster": {
+            "node_id": cluster.node_id,
+            "role": cluster.role,
+            "members": cluster.members.len(),
+        },
+    }))
+    .into_response()
+}
+
+/// Databases, tables and document counts
+pub async fn storage(Extension(state): Extension<Arc<AppState>>) -> Response {
+    match databases(&state).await {
+        Ok(databases) => Json(serde_json::json!({
+            "success": true,
+            "totals": storage_summary(&databases),
+            "databases": databases,
+        }))
+        .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Open connections and recent query throughput
+pub async fn queries(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let (connections, rates) = query_summary(&state);
+    Json(serde_json::json!({
+        "success": true,
+        "connections": connections,
+        "queries": rates,
+    }))
+    .into_response()
+}
+
+/// Cluster membership and roles
+pub async fn cluster(Extension(state): Extension<Arc<AppState>>) -> Response {
+    Json(serde_json::json!({
+        "success": true,
+        "cluster": cluster_summary(&state).await,
+    }))
+    .into_response()
+}
+
+/// Time between snapshots of the live metrics stream, by default
+pub const DEFAULT_STREAM_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Shortest time between snapshots a client may ask for
+const MIN_STREAM_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Longest time between snapshots a client may ask for
+const MAX_STREAM_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Live metrics streams currently open
+static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of live metrics streams currently open
+pub fn active_streams() -> usize {
+    ACTIVE_STREAMS.load(Ordering::SeqCst)
+}
+
+/// Counts a stream as open for as long as it lives
+struct StreamGuard;
+
+impl StreamGuard {
+    fn open() -> Self {
+        ACTIVE_STREAMS.fetch_add(1, Ordering::SeqCst);
+        StreamGuard
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        ACTIVE_STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    /// Seconds between snapshots
+    interval: Option<f64>,
+}
+
+/// Message a client sends on the stream
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum StreamCommand {
+    Subscribe { interval: Option<f64> },
+    Unsubscribe,
+}
+
+/// `secs` as a snapshot interval, if usable
+///
+/// Intervals outside [`MIN_STREAM_INTERVAL`, `MAX_STREAM_INTERVAL`] are
+/// clamped to it.
+fn stream_interval(secs: Option<f64>) -> Option<Duration> {
+    secs.filter(|secs| *secs > 0.0).map(|secs| {
+        Duration::try_from_secs_f64(secs)
+            .unwrap_or(MAX_STREAM_INTERVAL)
+            .clamp(MIN_STREAM_INTERVAL, MAX_STREAM_INTERVAL)
+    })
+}
+
+/// Current values of the live metrics
+async fn snapshot(state: &AppState) -> serde_json::Value {
+    let (connections, rates) = query_summary(state);
+    let cluster = cluster_summary(state).await;
+    serde_json::json!({
+        "type": "metrics",
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "cpu_percent": CPU_USAGE.get(),
+        "memory_bytes": MEMORY_USAGE.get(),
+        "memory_percent": MEMORY_USAGE_PERCENT.get(),
+        "connections": connections,
+        "qps": rates.qps,
+        "error_rate": rates.error_rate,
+        "cluster": {
+            "healthy": CLUSTER_HEALTH.get() == 1,
+            "role": cluster.role,
+            "members": cluster.members.len(),
+            "degraded": cluster.members.iter().filter(|m| m.degraded).count(),
+        },
+    })
+}
+
+/// Live metrics pushed over a WebSocket
+pub async fn stream(
+    ws: WebSocketUpgrade,
+    Query(params): Query<StreamParams>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Response {
+    let interval = stream_interval(params.interval).unwrap_or(DEFAULT_STREAM_INTERVAL);
+    ws.on_upgrade(move |socket| stream_metrics(socket, state, interval))
+}
+
+/// Send snapshots until the client goes away
+async fn stream_metrics(mut socket: WebSocket, state: Arc<AppState>, mut interval: Duration) {
+    let _active = StreamGuard::open();
+    let mut ticks = tokio::time::interval(interval);
+    let mut subscribed = true;
+
+    loop {
+        let reply = tokio::select! {
+            _ = ticks.tick(), if subscribed => snapshot(&state).await,
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(StreamCommand::Subscribe { interval: secs }) => {
+                        interval = stream_interval(secs).unwrap_or(interval);
+                        ticks = tokio::time::interval(interval);
+                        subscribed = true;
+                        serde_json::json!({
+                            "type": "subscribed",
+                            "interval": interval.as_secs_f64(),
+                        })
+                    }
+                    Ok(StreamCommand::Unsubscribe) => {
+                        subscribed = false;
+                        serde_json::json!({ "type": "unsubscribed" })
+                    }
+                    Err(e) => serde_json::json!({
+                        "type": "error",
+                        "error": format!("Invalid command: {}", e),
+                    }),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(Message::Text(reply.to_string())).await.is_err() {
+            break;
+        }
+    }
+    debug!("Live metrics stream closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::metrics::CONNECTION_GAUGE_LOCK;
+    use crate::server::{build_router, ServerConfig};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get_json(app: &axum::Router, uri: &str) -> serde_json::Value {
+        let res = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    async fn query(app: &axum::Router, query: serde_json::Value) {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/query")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "query": query }).to_string(),
+            ))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoints() {
+        let _lock = CONNECTION_GAUGE_LOCK.lock().await;
+        let state = AppState::in_memory(ServerConfig::default());
+        state.cluster.init_as_master().await;
+        state.databases.create_database("app").await.unwrap();
+        state.databases.create_table("app", "users").await.unwrap();
+        state.databases.create_table("app", "events").await.unwrap();
+        for id in ["a", "b", "c"] {
+            let doc = serde_json::json!({ "id": id }).to_string().into_bytes();
+            state
+                .databases
+                .set_document("app", "users", id.as_bytes(), doc)
+                .await
+                .unwrap();
+        }
+        let app = build_router(state);
+
+        // One query that succeeds and one creating an existing database
+        query(&app, serde_json::json!([59, []])).await;
+        query(&app, serde_json::json!([57, ["app"]])).await;
+
+        let overview = get_json(&app, "/_admin/api/overview").await;
+        for key in ["storage", "connections", "qps", "error_rate", "cluster"] {
+            assert!(overview.get(key).is_some(), "overview lacks {}", key);
+        }
+        assert_eq!(overview["storage"]["databases"], 1);
+        assert_eq!(overview["storage"]["tables"], 2);
+        assert_eq!(overview["storage"]["documents"], 3);
+        assert!(overview["qps"].as_f64().unwrap() > 0.0);
+        assert_eq!(overview["error_rate"], 0.5);
+        assert_eq!(overview["cluster"]["role"], "Master");
+
+        let storage = get_json(&app, "/_admin/api/storage").await;
+        assert_eq!(storage["totals"]["documents"], 3);
+        let tables = storage["databases"][0]["tables"].as_array().unwrap();
+        let users = tables.iter().find(|t| t["name"] == "users").unwrap();
+        assert_eq!(users["documents"], 3);
+        assert_eq!(users["primary_key"], "id");
+
+        let queries = get_json(&app, "/_admin/api/queries").await;
+        assert!(queries["connections"].is_u64());
+        assert_eq!(queries["queries"]["queries"], 2);
+        assert_eq!(queries["queries"]["errors"], 1);
+        assert!(queries["queries"]["window_secs"].as_f64().unwrap() >= 1.0);
+
+        let cluster = get_json(&app, "/_admin/api/cluster").await;
+        assert_eq!(cluster["cluster"]["node_id"], "local");
+        assert_eq!(cluster["cluster"]["masters"], 1);
+        let members = cluster["cluster"]["members"].as_array().unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0]["role"], "Master");
+        assert_eq!(members[0]["local"], true);
+    }
+
+    #[tokio::test]
+    async fn test_live_metrics_stream() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let state = AppState::in_memory(ServerConfig::default());
+        state.cluster.init_as_master().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+
+        let url = format!("ws://{}/_admin/api/stream?interval=0.05", addr);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        async fn next<S>(ws: &mut S) -> serde_json::Value
+        where
+            S: futures_util::Stream<Item = tokio_tungstenite::tungstenite::Result<WsMessage>>
+                + Unpin,
+        {
+            let message = tokio::time::timeout(Duration::from_secs(5), ws.next());
+            match message.await.unwrap().unwrap().unwrap() {
+                WsMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+
+        // Snapshots keep coming
+        for _ in 0..2 {
+            let snapshot = next(&mut ws).await;
+            assert_eq!(snapshot["type"], "metrics");
+            for key in [
+                "timestamp",
+                "cpu_percent",
+                "memory_bytes",
+                "connections",
+                "qps",
+            ] {
+                assert!(snapshot.get(key).is_some(), "snapshot lacks {}", key);
+            }
+            assert_eq!(snapshot["cluster"]["role"], "Master");
+            assert_eq!(snapshot["cluster"]["members"], 1);
+        }
+        assert_eq!(active_streams(), 1);
+
+        // Unsubscribing stops them until the next subscribe
+        ws.send(WsMessage::Text(r#"{"action": "unsubscribe"}"#.into()))
+            .await
+            .unwrap();
+        while next(&mut ws).await["type"] != "unsubscribed" {}
+        let paused = tokio::time::timeout(Duration::from_millis(200), ws.next()).await;
+        assert!(paused.is_err(), "got {:?} while unsubscribed", paused);
+        ws.send(WsMessage::Text(
+            r#"{"action": "subscribe", "interval": 0.1}"#.into(),
+        ))
+        .await
+        .unwrap();
+        let ack = next(&mut ws).await;
+        assert_eq!(ack["type"], "subscribed");
+        assert_eq!(ack["interval"], 0.1);
+        assert_eq!(next(&mut ws).await["type"], "metrics");
+
+        // Intervals too long to represent are clamped, not fatal
+        ws.send(WsMessage::Text(
+            r#"{"action": "subscribe", "interval": 1e300}"#.into(),
+        ))
+        .await
+        .unwrap();
+        let ack = loop {
+            let reply = next(&mut ws).await;
+            if reply["type"] == "subscribed" {
+                break reply;
+            }
+        };
+        assert_eq!(ack["interval"], MAX_STREAM_INTERVAL.as_secs_f64());
+        ws.send(WsMessage::Text(
+            r#"{"action": "subscribe", "interval": 0.05}"#.into(),
+        ))
+        .await
+        .unwrap();
+        while next(&mut ws).await["type"] != "subscribed" {}
+        assert_eq!(next(&mut ws).await["type"], "metrics");
+
+        // Closing ends the stream's task
+        ws.close(None).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while active_streams() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("stream task still running");
+    }
+}
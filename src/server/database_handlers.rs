@@ -3,14 +3,18 @@
 //! Provides REST API endpoints for database operations:
 //! - GET /api/dbs - List all databases
 //! - POST /api/dbs - Create a database
-//! - DELETE /api/dbs/:name - Drop a database
+//! - DELETE /api/dbs/:name?mode=restrict|cascade - Drop a database
 //! - GET /api/dbs/:name - Get database info
 //! - GET /api/dbs/:name/tables - List tables in database
 //! - POST /api/dbs/:name/tables - Create table in database
 //! - DELETE /api/dbs/:name/tables/:table - Drop table
+//! - POST /api/dbs/:name/tables/:table/documents - Insert a document
+//! - GET /api/dbs/:name/tables/:table/documents/:id - Get a document
+//! - PUT /api/dbs/:name/tables/:table/documents/:id - Replace a document
+//! - DELETE /api/dbs/:name/tables/:table/documents/:id - Delete a document
 
 use axum::{
-    extract::{Extension, Json, Path},
+    extract::{Extension, Json, Path, Query},
     http::StatusCode,
     response::{IntoResponse, Response},
 };
@@ -19,8 +23,7 @@ use std::sync::Arc;
 use tracing::{error, info, instrument};
 
 use crate::server::AppState;
-use crate::storage::engine::StorageEngine;
-use crate::storage::DefaultStorageEngine;
+use crate::storage::DropMode;
 
 // ===== Request/Response Types =====
 
@@ -29,6 +32,18 @@ pub struct CreateDatabaseRequest {
     pub name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DropDatabaseParams {
+    /// `restrict` refuses to drop a database that has tables; defaults to
+    /// `cascade`
+    #[serde(default = "default_drop_mode")]
+    pub mode: DropMode,
+}
+
+fn default_drop_mode() -> DropMode {
+    DropMode::Cascade
+}
+
 #[derive(Debug, Serialize)]
 pub struct DatabaseResponse {
     pub success: bool,
@@ -90,44 +105,90 @@ pub struct TableInfo {
     pub indexes: Vec<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct DocumentResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DocumentResponse {
+    fn ok(id: String, document: Option<serde_json::Value>) -> Response {
+        Json(Self {
+            success: true,
+            id: Some(id),
+            document,
+            error: None,
+        })
+        .into_response()
+    }
+
+    fn err(status: StatusCode, error: String) -> Response {
+        (
+            status,
+            Json(Self {
+                success: false,
+                id: None,
+                document: None,
+                error: Some(error),
+            }),
+        )
+            .into_response()
+    }
+
+    fn from_error(e: crate::error::Error) -> Response {
+        let (status, error) = error_status(e);
+        Self::err(status, error)
+    }
+}
+
+fn error_status(e: crate::error::Error) -> (StatusCode, String) {
+    let status = match e {
+        crate::error::Error::NotFound(_) => StatusCode::NOT_FOUND,
+        crate::error::Error::AlreadyExists(_) => StatusCode::CONFLICT,
+        crate::error::Error::InvalidArgument(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, e.to_string())
+}
+
 // ===== Database Handlers =====
 
 /// List all databases
 ///
 /// GET /api/dbs
-#[instrument]
-pub async fn list_databases(Extension(_state): Extension<Arc<AppState>>) -> Response {
+#[instrument(skip(state))]
+pub async fn list_databases(Extension(state): Extension<Arc<AppState>>) -> Response {
     info!("Listing all databases");
 
-    // TODO: Replace with actual DatabaseEngine from AppState
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(DatabaseListResponse {
-                    success: false,
-                    databases: vec![],
-                    count: 0,
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match engine.list_databases().await {
+    match state.databases.list_databases().await {
         Ok(db_names) => {
             let mut databases = Vec::new();
 
             for name in db_names {
-                // Simplified: Use default values since get_database_config is not available in StorageEngine
-                let table_count = engine.list_tables_in_db(&name).await.unwrap_or_default().len();
+                let config = match state.databases.get_database_config(&name).await {
+                    Ok(Some(config)) => config,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!(error = %e, database = %name, "Failed to get database config");
+                        continue;
+                    }
+                };
+                let table_count = state
+                    .databases
+                    .list_tables(&name)
+                    .await
+                    .unwrap_or_default()
+                    .len();
 
                 databases.push(DatabaseInfo {
-                    name: name.clone(),
-                    id: format!("db_{}", name), // Simplified ID
-                    created_at: 0, // TODO: Store creation timestamp
+                    name: config.name,
+                    id: config.id.to_string(),
+                    created_at: config.created_at,
                     table_count,
                 });
             }
@@ -159,36 +220,19 @@ pub async fn list_databases(Extension(_state): Extension<Arc<AppState>>) -> Resp
 ///
 /// POST /api/dbs
 /// Body: {"name": "my_database"}
-#[instrument(skip(_state, payload))]
+#[instrument(skip(state, payload))]
 pub async fn create_database(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Json(payload): Json<CreateDatabaseRequest>,
 ) -> Response {
     info!(database = %payload.name, "Creating database");
 
-    // TODO: Replace with actual DatabaseEngine from AppState
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(DatabaseResponse {
-                    success: false,
-                    id: None,
-                    error: Some(format!("Failed to open database engine: {}", e)),
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match engine.create_database(&payload.name).await {
-        Ok(()) => {
-            info!(database = %payload.name, "Database created");
+    match state.databases.create_database(&payload.name).await {
+        Ok(id) => {
+            info!(database = %payload.name, id = %id, "Database created");
             Json(DatabaseResponse {
                 success: true,
-                id: Some(format!("db_{}", payload.name)), // Simplified ID
+                id: Some(id.to_string()),
                 error: None,
             })
             .into_response()
@@ -216,42 +260,34 @@ pub async fn create_database(
 /// Get database information
 ///
 /// GET /api/dbs/:name
-#[instrument]
+#[instrument(skip(state))]
 pub async fn get_database(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> Response {
     info!(database = %name, "Getting database info");
 
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to open database engine",
-            )
-                .into_response();
-        }
-    };
-
-    // Check if database exists by trying to list it
-    match engine.list_databases().await {
-        Ok(dbs) if dbs.contains(&name) => {
-            let table_count = engine.list_tables_in_db(&name).await.unwrap_or_default().len();
+    match state.databases.get_database_config(&name).await {
+        Ok(Some(config)) => {
+            let table_count = state
+                .databases
+                .list_tables(&name)
+                .await
+                .unwrap_or_default()
+                .len();
 
             Json(serde_json::json!({
                 "success": true,
                 "database": {
-                    "name": name,
-                    "id": format!("db_{}", name),
-                    "created_at": 0, // TODO: Store creation timestamp
+                    "name": config.name,
+                    "id": config.id.to_string(),
+                    "created_at": config.created_at,
                     "table_count": table_count,
                 }
             }))
             .into_response()
         }
-        Ok(_) => {
+        Ok(None) => {
             error!(database = %name, "Database not found");
             (
                 StatusCode::NOT_FOUND,
@@ -278,27 +314,19 @@ pub async fn get_database(
 
 /// Drop (delete) a database
 ///
-/// DELETE /api/dbs/:name
-#[instrument(skip(_state))]
+/// DELETE /api/dbs/:name?mode=restrict|cascade
+///
+/// With `mode=restrict` a database that still has tables is left alone and
+/// 409 Conflict is returned.
+#[instrument(skip(state))]
 pub async fn drop_database(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Path(name): Path<String>,
+    Query(params): Query<DropDatabaseParams>,
 ) -> Response {
-    info!(database = %name, "Dropping database");
+    info!(database = %name, mode = ?params.mode, "Dropping database");
 
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to open database engine",
-            )
-                .into_response();
-        }
-    };
-
-    match engine.drop_database(&name).await {
+    match state.databases.drop_database_with(&name, params.mode).await {
         Ok(()) => {
             info!(database = %name, "Database dropped");
             Json(DatabaseResponse {
@@ -312,6 +340,7 @@ pub async fn drop_database(
             error!(error = %e, database = %name, "Failed to drop database");
             let status = match e {
                 crate::error::Error::NotFound(_) => StatusCode::NOT_FOUND,
+                crate::error::Error::InvalidArgument(_) => StatusCode::CONFLICT,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             };
             (
@@ -332,45 +361,33 @@ pub async fn drop_database(
 /// List tables in a database
 ///
 /// GET /api/dbs/:db_name/tables
-#[instrument(skip(_state))]
+#[instrument(skip(state))]
 pub async fn list_tables(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Path(db_name): Path<String>,
 ) -> Response {
     info!(database = %db_name, "Listing tables");
 
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(TableListResponse {
-                    success: false,
-                    tables: vec![],
-                    count: 0,
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match engine.list_tables_in_db(&db_name).await {
+    match state.databases.list_tables(&db_name).await {
         Ok(table_names) => {
             let mut tables = Vec::new();
 
             for name in table_names {
-                // Use get_table_info with full table name "db.table"
-                let full_name = format!("{}.{}", db_name, name);
-                match engine.get_table_info(&full_name).await {
-                    Ok(Some(info)) => {
+                match state.databases.get_table_config(&db_name, &name).await {
+                    Ok(Some(config)) => {
+                        let doc_count = state
+                            .databases
+                            .count_documents(&db_name, &name)
+                            .await
+                            .unwrap_or(config.doc_count);
+
                         tables.push(TableInfo {
-                            name: info.name,
-                            id: format!("table_{}", name), // Simplified ID
-                            database_id: format!("db_{}", db_name), // Simplified DB ID
-                            primary_key: info.primary_key,
-                            doc_count: info.doc_count,
-                            indexes: info.indexes,
+                            name: config.name,
+                            id: config.id.to_string(),
+                            database_id: config.database_id.to_string(),
+                            primary_key: config.primary_key,
+                            doc_count,
+                            indexes: config.indexes,
                         });
                     }
                     Ok(None) => {
@@ -413,39 +430,24 @@ pub async fn list_tables(
 ///
 /// POST /api/dbs/:db_name/tables
 /// Body: {"name": "users", "primary_key": "id"}
-#[instrument(skip(_state, payload))]
+#[instrument(skip(state, payload))]
 pub async fn create_table(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Path(db_name): Path<String>,
     Json(payload): Json<CreateTableRequest>,
 ) -> Response {
     info!(database = %db_name, table = %payload.name, "Creating table");
 
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(TableResponse {
-                    success: false,
-                    id: None,
-                    error: Some(format!("Failed to open database engine: {}", e)),
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match engine
-        .create_table(&db_name, &payload.name, &payload.primary_key)
+    match state
+        .databases
+        .create_table_with_pk(&db_name, &payload.name, &payload.primary_key)
         .await
     {
-        Ok(()) => {
-            info!(database = %db_name, table = %payload.name, "Table created");
+        Ok(id) => {
+            info!(database = %db_name, table = %payload.name, id = %id, "Table created");
             Json(TableResponse {
                 success: true,
-                id: Some(format!("table_{}", payload.name)), // Simplified ID
+                id: Some(id.to_string()),
                 error: None,
             })
             .into_response()
@@ -474,26 +476,14 @@ pub async fn create_table(
 /// Drop (delete) a table
 ///
 /// DELETE /api/dbs/:db_name/tables/:table_name
-#[instrument(skip(_state))]
+#[instrument(skip(state))]
 pub async fn drop_table(
-    Extension(_state): Extension<Arc<AppState>>,
+    Extension(state): Extension<Arc<AppState>>,
     Path((db_name, table_name)): Path<(String, String)>,
 ) -> Response {
     info!(database = %db_name, table = %table_name, "Dropping table");
 
-    let engine = match DefaultStorageEngine::with_defaults("./data") {
-        Ok(e) => e,
-        Err(e) => {
-            error!(error = %e, "Failed to open database engine");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to open database engine",
-            )
-                .into_response();
-        }
-    };
-
-    match engine.drop_table(&db_name, &table_name).await {
+    match state.databases.drop_table(&db_name, &table_name).await {
         Ok(()) => {
             info!(database = %db_name, table = %table_name, "Table dropped");
             Json(TableResponse {
@@ -521,3 +511,411 @@ pub async fn drop_table(
         }
     }
 }
+
+// ===== Document Handlers =====
+
+/// Resolve the primary key field of a table (404 if the table is missing)
+async fn primary_key(
+    state: &AppState,
+    db_name: &str,
+    table_name: &str,
+) -> Result<String, (StatusCode, String)> {
+    match state.databases.get_table_config(db_name, table_name).await {
+        Ok(Some(config)) => Ok(config.primary_key),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Table '{}.{}' not found", db_name, table_name),
+        )),
+        Err(e) => Err(error_status(e)),
+    }
+}
+
+/// Encode a document with its primary key set to `id`
+fn encode_document(
+    mut document: serde_json::Value,
+    primary_key: &str,
+    id: &str,
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    let obj = document.as_object_mut().ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Document must be a JSON object".to_string(),
+        )
+    })?;
+    obj.insert(
+        primary_key.to_string(),
+        serde_json::Value::String(id.to_string()),
+    );
+
+    serde_json::to_vec(&document)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Insert a document, generating its primary key if absent
+///
+/// POST /api/dbs/:db_name/tables/:table_name/documents
+#[instrument(skip(state, document))]
+pub async fn insert_document(
+    Extension(state): Extension<Arc<AppState>>,
+    Path((db_name, table_name)): Path<(String, String)>,
+    Json(document): Json<serde_json::Value>,
+) -> Response {
+    let primary_key = match primary_key(&state, &db_name, &table_name).await {
+        Ok(pk) => pk,
+        Err((status, error)) => return DocumentResponse::err(status, error),
+    };
+
+    let id = match document.get(&primary_key) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => uuid::Uuid::new_v4().to_string(),
+        Some(other) => other.to_string(),
+    };
+
+    match state
+        .databases
+        .get_document(&db_name, &table_name, id.as_bytes())
+        .await
+    {
+        Ok(Some(_)) => {
+            return DocumentResponse::err(
+                StatusCode::CONFLICT,
+                format!("Document '{}' already exists", id),
+            )
+        }
+        Ok(None) => {}
+        Err(e) => return DocumentResponse::from_error(e),
+    }
+
+    let bytes = match encode_document(document, &primary_key, &id) {
+        Ok(bytes) => bytes,
+        Err((status, error)) => return DocumentResponse::err(status, error),
+    };
+
+    match state
+        .databases
+        .set_document(&db_name, &table_name, id.as_bytes(), bytes)
+        .await
+    {
+        Ok(()) => {
+            info!(database = %db_name, table = %table_name, id = %id, "Document inserted");
+            DocumentResponse::ok(id, None)
+        }
+        Err(e) => {
+            error!(error = %e, database = %db_name, table = %table_name, "Failed to insert document");
+            DocumentResponse::from_error(e)
+        }
+    }
+}
+
+/// Get a document by primary key
+///
+/// GET /api/dbs/:db_name/tables/:table_name/documents/:id
+#[instrument(skip(state))]
+pub async fn get_document(
+    Extension(state): Extension<Arc<AppState>>,
+    Path((db_name, table_name, id)): Path<(String, String, String)>,
+) -> Response {
+    match state
+        .databases
+        .get_document(&db_name, &table_name, id.as_bytes())
+        .await
+    {
+        Ok(Some(bytes)) => match serde_json::from_slice(&bytes) {
+            Ok(document) => DocumentResponse::ok(id, Some(document)),
+            Err(e) => DocumentResponse::err(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        },
+        Ok(None) => DocumentResponse::err(
+            StatusCode::NOT_FOUND,
+            format!("Document '{}' not found", id),
+        ),
+        Err(e) => DocumentResponse::from_error(e),
+    }
+}
+
+/// Replace (or create) a document by primary key
+///
+/// PUT /api/dbs/:db_name/tables/:table_name/documents/:id
+#[instrument(skip(state, document))]
+pub async fn put_document(
+    Extension(state): Extension<Arc<AppState>>,
+    Path((db_name, table_name, id)): Path<(String, String, String)>,
+    Json(document): Json<serde_json::Value>,
+) -> Response {
+    let primary_key = match primary_key(&state, &db_name, &table_name).await {
+        Ok(pk) => pk,
+        Err((status, error)) => return DocumentResponse::err(status, error),
+    };
+
+    let bytes = match encode_document(document, &primary_key, &id) {
+        Ok(bytes) => bytes,
+        Err((status, error)) => return DocumentResponse::err(status, error),
+    };
+
+    match state
+        .databases
+        .set_document(&db_name, &table_name, id.as_bytes(), bytes)
+        .await
+    {
+        Ok(()) => {
+            info!(database = %db_name, table = %table_name, id = %id, "Document stored");
+            DocumentResponse::ok(id, None)
+        }
+        Err(e) => {
+            error!(error = %e, database = %db_name, table = %table_name, "Failed to store document");
+            DocumentResponse::from_error(e)
+        }
+    }
+}
+
+/// Delete a document by primary key
+///
+/// DELETE /api/dbs/:db_name/tables/:table_name/documents/:id
+#[instrument(skip(state))]
+pub async fn delete_document(
+    Extension(state): Extension<Arc<AppState>>,
+    Path((db_name, table_name, id)): Path<(String, String, String)>,
+) -> Response {
+    match state
+        .databases
+        .get_document(&db_name, &table_name, id.as_bytes())
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return DocumentResponse::err(
+                StatusCode::NOT_FOUND,
+                format!("Document '{}' not found", id),
+            )
+        }
+        Err(e) => return DocumentResponse::from_error(e),
+    }
+
+    match state
+        .databases
+        .delete_document(&db_name, &table_name, id.as_bytes())
+        .await
+    {
+        Ok(()) => {
+            info!(database = %db_name, table = %table_name, id = %id, "Document deleted");
+            DocumentResponse::ok(id, None)
+        }
+        Err(e) => {
+            error!(error = %e, database = %db_name, table = %table_name, "Failed to delete document");
+            DocumentResponse::from_error(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{build_router, ServerConfig};
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn test_state() -> AppState {
+        AppState::in_memory(ServerConfig::default())
+    }
+
+    async fn body_json(res: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn json_request(method: Method, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn empty_request(method: Method, uri: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn router_with_table() -> Router {
+        let state = test_state();
+        state.databases.create_database("shop").await.unwrap();
+        state.databases.create_table("shop", "items").await.unwrap();
+        build_router(state)
+    }
+
+    #[tokio::test]
+    async fn test_created_database_is_listed() {
+        let app = build_router(test_state());
+
+        let res = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/api/dbs")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"name": "shop"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let created = body_json(res).await;
+        let id = created["id"].as_str().unwrap().to_string();
+
+        let res = app
+            .oneshot(Request::builder().uri("/api/dbs").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let listed = body_json(res).await;
+        assert_eq!(listed["count"], 1);
+        assert_eq!(listed["databases"][0]["name"], "shop");
+        assert_eq!(listed["databases"][0]["id"], id);
+    }
+
+    #[tokio::test]
+    async fn test_drop_database_modes() {
+        let app = router_with_table().await;
+
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::DELETE, "/api/dbs/shop?mode=restrict"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::GET, "/api/dbs/shop/tables"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::DELETE, "/api/dbs/shop?mode=cascade"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::GET, "/api/dbs/shop/tables"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = app
+            .oneshot(empty_request(Method::DELETE, "/api/dbs/shop?mode=sideways"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_insert_then_get_document() {
+        let app = router_with_table().await;
+
+        let res = app
+            .clone()
+            .oneshot(json_request(
+                Method::POST,
+                "/api/dbs/shop/tables/items/documents",
+                r#"{"name": "lamp"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let inserted = body_json(res).await;
+        let id = inserted["id"].as_str().unwrap().to_string();
+
+        let res = app
+            .oneshot(empty_request(
+                Method::GET,
+                &format!("/api/dbs/shop/tables/items/documents/{}", id),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let fetched = body_json(res).await;
+        assert_eq!(fetched["success"], true);
+        assert_eq!(fetched["document"]["name"], "lamp");
+        assert_eq!(fetched["document"]["id"], id);
+    }
+
+    #[tokio::test]
+    async fn test_update_document() {
+        let app = router_with_table().await;
+        let uri = "/api/dbs/shop/tables/items/documents/lamp";
+
+        let res = app
+            .clone()
+            .oneshot(json_request(Method::PUT, uri, r#"{"price": 10}"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .clone()
+            .oneshot(json_request(Method::PUT, uri, r#"{"price": 12}"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app.oneshot(empty_request(Method::GET, uri)).await.unwrap();
+        let fetched = body_json(res).await;
+        assert_eq!(fetched["document"]["price"], 12.0);
+        assert_eq!(fetched["document"]["id"], "lamp");
+    }
+
+    #[tokio::test]
+    async fn test_delete_document() {
+        let app = router_with_table().await;
+        let uri = "/api/dbs/shop/tables/items/documents/lamp";
+
+        app.clone()
+            .oneshot(json_request(Method::PUT, uri, r#"{"price": 10}"#))
+            .await
+            .unwrap();
+
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::DELETE, uri))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app
+            .clone()
+            .oneshot(empty_request(Method::GET, uri))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = app.oneshot(empty_request(Method::DELETE, uri)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_missing_document() {
+        let app = router_with_table().await;
+
+        let res = app
+            .oneshot(empty_request(
+                Method::GET,
+                "/api/dbs/shop/tables/items/documents/nope",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = body_json(res).await;
+        assert_eq!(body["success"], false);
+        assert!(body["error"].as_str().unwrap().contains("nope"));
+    }
+}
@@ -1,31 +1,57 @@
 //! HTTP route handlers
 
 use axum::{
+    body::{Body, Bytes},
     extract::{Extension, Json, Path},
-    http::StatusCode,
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{error, info, instrument};
 
-use crate::reql::Datum;
+use crate::query::executor::{QueryExecutor, TableScan, DEFAULT_DB};
+use crate::server::pagination::{CursorError, Page};
 use crate::server::AppState;
 
+/// Media type of query results streamed as newline-delimited JSON
+pub const NDJSON: &str = "application/x-ndjson";
+
 /// Query request
+///
+/// The body is either the ReQL JSON term itself, e.g. `[79]` for `DB_LIST`,
+/// or an object whose `query` field holds the term (or the term as a string).
+/// An object with a `next_token` instead fetches the next page of an earlier
+/// result (see [`pagination`](crate::server::pagination)).
 #[derive(Debug, Deserialize)]
-pub struct QueryRequest {
-    pub query: String,
-    #[serde(default)]
-    pub options: QueryOptions,
+#[serde(untagged)]
+pub enum QueryRequest {
+    Wrapped {
+        query: serde_json::Value,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+    Continue {
+        next_token: String,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+    Term(serde_json::Value),
 }
 
 #[derive(Debug, Deserialize, Default)]
 pub struct QueryOptions {
     #[serde(default)]
     pub timeout_ms: Option<u64>,
+    /// Rows per page of sequence results
     #[serde(default)]
     pub batch_size: Option<usize>,
+    /// Database of tables that do not name one, like the `db` global optarg
+    #[serde(default)]
+    pub db: Option<String>,
 }
 
 /// Query response
@@ -33,88 +59,278 @@ pub struct QueryOptions {
 pub struct QueryResponse {
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub data: Option<Vec<Datum>>,
+    pub result: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Token fetching the next page, if rows are left
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_token: Option<String>,
     pub execution_time_ms: u64,
 }
 
-/// Execute ReQL query
-#[instrument(skip(state, payload))]
+impl QueryResponse {
+    fn error(status: StatusCode, error: String, start: std::time::Instant) -> Response {
+        (
+            status,
+            Json(QueryResponse {
+                success: false,
+                result: None,
+                error: Some(error),
+                next_token: None,
+                execution_time_ms: start.elapsed().as_millis() as u64,
+            }),
+        )
+            .into_response()
+    }
+
+    fn page(page: Page, start: std::time::Instant) -> Response {
+        Json(QueryResponse {
+            success: true,
+            result: Some(serde_json::Value::Array(page.rows)),
+            error: None,
+            next_token: page.next_token,
+            execution_time_ms: start.elapsed().as_millis() as u64,
+        })
+        .into_response()
+    }
+
+    /// Response to a failed query; busy servers ask to be retried shortly
+    fn failed(e: anyhow::Error, start: std::time::Instant) -> Response {
+        error!(
+            error = %e,
+            duration_ms = start.elapsed().as_millis(),
+            "Query failed"
+        );
+
+        let status = match e.downcast_ref::<crate::error::Error>() {
+            Some(crate::error::Error::NotFound(_)) => StatusCode::NOT_FOUND,
+            Some(crate::error::Error::Busy(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let mut response = QueryResponse::error(status, e.to_string(), start);
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        response
+    }
+}
+
+/// Rows of an NDJSON response not sent yet
+enum RowStream {
+    /// Pages of a (filtered) table scan, holding its query slot until done
+    Scan {
+        executor: Arc<QueryExecutor>,
+        scan: Box<TableScan>,
+        page_size: usize,
+    },
+    /// Rows of an executed query, sent `page_size` at a time
+    Rows {
+        rows: std::vec::IntoIter<serde_json::Value>,
+        page_size: usize,
+    },
+    Done,
+}
+
+impl RowStream {
+    /// The next chunk of lines and the rows left after it
+    ///
+    /// A failing scan ends the stream with an error object as its last line.
+    async fn next_chunk(self) -> Option<(Bytes, RowStream)> {
+        match self {
+            RowStream::Scan {
+                executor,
+                mut scan,
+                page_size,
+            } => match executor.next_page(&mut scan, page_size).await {
+                Ok(page) if page.is_empty() => None,
+                Ok(page) => {
+                    let rows = page.iter().map(crate::query::QueryCompiler::datum_to_json);
+                    let next = RowStream::Scan {
+                        executor,
+                        scan,
+                        page_size,
+                    };
+                    Some((ndjson_lines(rows), next))
+                }
+                Err(e) => {
+                    error!(error = %e, "Streamed table scan failed");
+                    let error = serde_json::json!({ "success": false, "error": e.to_string() });
+                    Some((ndjson_lines([error]), RowStream::Done))
+                }
+            },
+            RowStream::Rows {
+                mut rows,
+                page_size,
+            } => {
+                let chunk: Vec<_> = rows.by_ref().take(page_size).collect();
+                if chunk.is_empty() {
+                    return None;
+                }
+                Some((ndjson_lines(chunk), RowStream::Rows { rows, page_size }))
+            }
+            RowStream::Done => None,
+        }
+    }
+
+    /// Chunked response sending the rows one JSON document per line
+    fn into_response(self) -> Response {
+        let chunks = futures_util::stream::unfold(self, |rows| async move {
+            rows.next_chunk()
+                .await
+                .map(|(chunk, rest)| (Ok::<_, std::convert::Infallible>(chunk), rest))
+        });
+        (
+            [(CONTENT_TYPE, HeaderValue::from_static(NDJSON))],
+            Body::from_stream(chunks),
+        )
+            .into_response()
+    }
+}
+
+/// `rows` as newline-terminated JSON documents
+fn ndjson_lines(rows: impl IntoIterator<Item = serde_json::Value>) -> Bytes {
+    let mut lines = Vec::new();
+    for row in rows {
+        // Serializing a JSON value into memory cannot fail
+        serde_json::to_writer(&mut lines, &row).expect("serialize JSON row");
+        lines.push(b'\n');
+    }
+    Bytes::from(lines)
+}
+
+/// Whether the client asked for results as newline-delimited JSON
+fn wants_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let media = media.split(';').next().unwrap_or_default().trim();
+            media.eq_ignore_ascii_case(NDJSON)
+        })
+}
+
+/// Execute a ReQL JSON query
+///
+/// With `Accept: application/x-ndjson`, sequence results are streamed one
+/// document per line instead of paged: a table, or a filter over one, is
+/// read page by page as the response is sent, and a scan failing midway ends
+/// the stream with an `{"success": false, "error": ...}` line.
+#[instrument(skip(state, headers, body))]
 pub async fn execute_query(
     Extension(state): Extension<Arc<AppState>>,
-    Json(payload): Json<QueryRequest>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Response {
-    info!(query = %payload.query, "Executing query");
-
     let start = std::time::Instant::now();
 
-    // Parse query string to JSON Value
-    let query_value: serde_json::Value = match serde_json::from_str(&payload.query) {
-        Ok(v) => v,
+    // Parse the request body
+    let (query_value, options) = match serde_json::from_slice::<QueryRequest>(&body) {
+        Ok(QueryRequest::Continue {
+            next_token,
+            options,
+        }) => {
+            return match state.cursors.next(&next_token, options.batch_size) {
+                Ok(page) => QueryResponse::page(page, start),
+                Err(e @ CursorError::Expired(_)) => {
+                    QueryResponse::error(StatusCode::GONE, e.to_string(), start)
+                }
+                Err(e) => QueryResponse::error(StatusCode::NOT_FOUND, e.to_string(), start),
+            };
+        }
+        Ok(QueryRequest::Wrapped {
+            query: serde_json::Value::String(query),
+            options,
+        }) => match serde_json::from_str(&query) {
+            Ok(v) => (v, options),
+            Err(e) => {
+                return QueryResponse::error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid query JSON: {}", e),
+                    start,
+                );
+            }
+        },
+        Ok(QueryRequest::Wrapped { query, options }) => (query, options),
+        Ok(QueryRequest::Term(query)) => (query, QueryOptions::default()),
         Err(e) => {
-            return (
+            return QueryResponse::error(
                 StatusCode::BAD_REQUEST,
-                Json(QueryResponse {
-                    success: false,
-                    data: None,
-                    error: Some(format!("Invalid query JSON: {}", e)),
-                    execution_time_ms: 0,
-                }),
-            ).into_response();
+                format!("Invalid query JSON: {}", e),
+                start,
+            );
         }
     };
+    info!(query = %query_value, "Executing query");
 
     // Compile query to AST
-    let term = match crate::query::QueryCompiler::compile(&query_value) {
+    let term = match crate::query::QueryCompiler::compile_ql2(&query_value) {
         Ok(t) => t,
         Err(e) => {
-            let duration = start.elapsed();
-            return (
+            return QueryResponse::error(
                 StatusCode::BAD_REQUEST,
-                Json(QueryResponse {
-                    success: false,
-                    data: None,
-                    error: Some(format!("Query compilation error: {}", e)),
-                    execution_time_ms: duration.as_millis() as u64,
-                }),
-            ).into_response();
+                format!("Query compilation error: {}", e),
+                start,
+            );
         }
     };
 
-    // Execute query
-    match state.executor.execute(&term).await {
-        Ok(results) => {
-            let duration = start.elapsed();
-            info!(duration_ms = duration.as_millis(), "Query completed");
-
-            Json(QueryResponse {
-                success: true,
-                data: Some(vec![Datum::String(results.to_string())]),
-                error: None,
-                execution_time_ms: duration.as_millis() as u64,
-            })
-            .into_response()
+    let page_size = options.batch_size.unwrap_or(state.config.page_size).max(1);
+    let db = options.db.as_deref().unwrap_or(DEFAULT_DB);
+    let ndjson = wants_ndjson(&headers);
+    if ndjson {
+        match state.executor.scan_in(&term, db).await {
+            Ok(Some(scan)) => {
+                return RowStream::Scan {
+                    executor: state.executor.clone(),
+                    scan: Box::new(scan),
+                    page_size,
+                }
+                .into_response();
+            }
+            Ok(None) => {}
+            Err(e) => return QueryResponse::failed(e, start),
         }
-        Err(e) => {
-            let duration = start.elapsed();
-            error!(
-                error = %e,
-                duration_ms = duration.as_millis(),
-                "Query failed"
-            );
+    }
 
-            (
-                StatusCode::BAD_REQUEST,
-                Json(QueryResponse {
-                    success: false,
-                    data: None,
-                    error: Some(e.to_string()),
-                    execution_time_ms: duration.as_millis() as u64,
-                }),
-            )
-                .into_response()
+    // Execute query
+    match state.executor.execute_in(&term, db).await {
+        Ok(result) => {
+            info!(duration_ms = start.elapsed().as_millis(), "Query completed");
+
+            // Sequences longer than a page are returned page by page
+            match crate::query::QueryCompiler::datum_to_json(&result) {
+                serde_json::Value::Array(rows) if ndjson => RowStream::Rows {
+                    rows: rows.into_iter(),
+                    page_size,
+                }
+                .into_response(),
+                serde_json::Value::Array(rows) => match state.cursors.start(rows, page_size) {
+                    Ok(page) => QueryResponse::page(page, start),
+                    Err(e) => QueryResponse::failed(
+                        crate::error::Error::Busy(e.to_string()).into(),
+                        start,
+                    ),
+                },
+                result if ndjson => RowStream::Rows {
+                    rows: vec![result].into_iter(),
+                    page_size,
+                }
+                .into_response(),
+                result => Json(QueryResponse {
+                    success: true,
+                    result: Some(result),
+                    error: None,
+                    next_token: None,
+                    execution_time_ms: start.elapsed().as_millis() as u64,
+                })
+                .into_response(),
+            }
         }
+        Err(e) => QueryResponse::failed(e, start),
     }
 }
 
@@ -171,6 +387,26 @@ pub async fn get_table_info(
     }
 }
 
+/// Storage statistics: slot usage per size class, compression and cache
+#[instrument(skip(state))]
+pub async fn storage_stats(Extension(state): Extension<Arc<AppState>>) -> Response {
+    match state.storage.stats().await {
+        Some(stats) => Json(serde_json::json!({
+            "success": true,
+            "stats": stats,
+        }))
+        .into_response(),
+        None => (
+            StatusCode::NOT_IMPLEMENTED,
+            Json(serde_json::json!({
+                "success": false,
+                "error": "Storage engine does not report statistics",
+            })),
+        )
+            .into_response(),
+    }
+}
+
 /// Health check
 pub async fn health_check() -> Response {
     Json(serde_json::json!({
@@ -3,20 +3,63 @@
 //! Endpoints for node-to-node communication:
 //! - POST /internal/replicate - Receive replicated data
 //! - POST /internal/read - Read data from this node
+//! - POST /internal/vote - Answer a candidate's vote request
+//!
+//! Replicated data is a document under its storage key
+//! `doc:{db}:{table}:{id}`, written and read through the local
+//! [`DatabaseEngine`](crate::storage::DatabaseEngine).
+//!
+//! Calls must carry the cluster secret in the
+//! [`PEER_SECRET_HEADER`] header. Without a configured secret the endpoints
+//! are only open while the security middleware is disabled.
 
 use axum::{
-    extract::{Extension, Json},
+    body::Body,
+    extract::{Extension, Json, Request},
     http::StatusCode,
+    middleware::{self, Next},
+    response::Response,
     routing::post,
     Router,
 };
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use tracing::{error, info, instrument};
+use tokio::sync::Mutex;
+use tracing::{error, info, instrument, warn};
 
 use super::AppState;
+use crate::cluster::{version_key, VoteRequest, VoteResponse, PEER_SECRET_HEADER};
+use crate::error::Error;
 use crate::reql::Datum;
+use crate::storage::Storage;
+
+/// Stripes of the locks replicated writes take per key
+const REPLICATE_LOCK_STRIPES: usize = 64;
+
+lazy_static::lazy_static! {
+    /// Held while a replicated write checks and stores its version, so an
+    /// older write of the same key cannot overtake a newer one
+    static ref REPLICATE_LOCKS: Vec<Mutex<()>> =
+        (0..REPLICATE_LOCK_STRIPES).map(|_| Mutex::new(())).collect();
+}
+
+/// The lock guarding replicated writes of `key`
+fn replicate_lock(key: &[u8]) -> &'static Mutex<()> {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    key.hash(&mut hasher);
+    &REPLICATE_LOCKS[hasher.finish() as usize % REPLICATE_LOCK_STRIPES]
+}
+
+/// Replication version stored alongside `key`, 0 if none
+async fn stored_version(storage: &Storage, key: &[u8]) -> crate::error::Result<u64> {
+    Ok(storage
+        .get(&version_key(key))
+        .await?
+        .and_then(|d| d.as_integer())
+        .unwrap_or(0) as u64)
+}
 
 /// Replication request payload
 #[derive(Debug, Deserialize)]
@@ -25,6 +68,10 @@ pub struct ReplicateRequest {
     pub key: String,
     /// Base64-encoded data
     pub data: String,
+    /// Replication version; older versions never overwrite newer ones and
+    /// an unversioned write (0) never overwrites a versioned document
+    #[serde(default)]
+    pub version: u64,
 }
 
 /// Read request payload
@@ -39,6 +86,42 @@ pub struct ReadRequest {
 pub struct ReadResponse {
     /// Base64-encoded data
     pub data: String,
+    /// Replication version of the stored value (0 if unknown)
+    pub version: u64,
+}
+
+/// Database, table and primary key of the document key
+/// `doc:{db}:{table}:{id}`
+fn document_key(key: &[u8]) -> Result<(&str, &str, &[u8]), (StatusCode, String)> {
+    let invalid = || {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Not a document key: {}", String::from_utf8_lossy(key)),
+        )
+    };
+    let rest = key.strip_prefix(b"doc:").ok_or_else(invalid)?;
+    let mut parts = rest.splitn(3, |&b| b == b':');
+    let (Some(db), Some(table), Some(id)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let db = std::str::from_utf8(db).map_err(|_| invalid())?;
+    let table = std::str::from_utf8(table).map_err(|_| invalid())?;
+    Ok((db, table, id))
+}
+
+/// Status and message for a failed storage call
+fn storage_error(e: Error) -> (StatusCode, String) {
+    match e {
+        Error::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+        Error::InvalidArgument(msg) => (StatusCode::BAD_REQUEST, msg),
+        e => {
+            error!(error = %e, "Storage error");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Storage error: {}", e),
+            )
+        }
+    }
 }
 
 /// Internal cluster routes
@@ -46,6 +129,43 @@ pub fn internal_routes() -> Router {
     Router::new()
         .route("/internal/replicate", post(handle_replicate))
         .route("/internal/read", post(handle_read))
+        .route("/internal/vote", post(handle_vote))
+        .route_layer(middleware::from_fn(require_peer))
+}
+
+/// Reject calls that do not come from a cluster peer
+async fn require_peer(
+    Extension(state): Extension<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let secret = req
+        .headers()
+        .get(PEER_SECRET_HEADER)
+        .and_then(|v| v.to_str().ok());
+    let open = !state.cluster.has_secret()
+        && state
+            .security
+            .as_ref()
+            .is_none_or(|security| !security.is_enabled());
+    if open || state.cluster.is_peer(secret) {
+        Ok(next.run(req).await)
+    } else {
+        warn!(
+            path = req.uri().path(),
+            "Rejected internal call without cluster secret"
+        );
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// Handle a vote request from a candidate
+#[instrument(skip(state))]
+async fn handle_vote(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(req): Json<VoteRequest>,
+) -> Json<VoteResponse> {
+    Json(state.cluster.vote(&req).await)
 }
 
 /// Handle replication from another node
@@ -69,23 +189,41 @@ async fn handle_replicate(
         "Receiving replicated data"
     );
 
-    // Convert data to Datum (use String for now, could be enhanced)
-    let datum = Datum::String(String::from_utf8_lossy(&data).to_string());
+    let (db, table, id) = document_key(&key)?;
+    let _guard = replicate_lock(&key).lock().await;
+    let current = stored_version(&state.storage, &key)
+        .await
+        .map_err(storage_error)?;
+    if req.version == 0 && current != 0 {
+        warn!(current = current, "Rejecting unversioned write of a versioned document");
+        return Err((
+            StatusCode::CONFLICT,
+            "Unversioned write of a versioned document".to_string(),
+        ));
+    }
+    if req.version != 0 && req.version < current {
+        info!(
+            version = req.version,
+            current = current,
+            "Ignoring stale replicated write"
+        );
+        return Ok(StatusCode::OK);
+    }
 
-    // Store data in local storage
-    match state.storage.set(&key, datum).await {
-        Ok(_) => {
-            info!(key_size = key.len(), "Replication successful");
-            Ok(StatusCode::OK)
-        }
-        Err(e) => {
-            error!(error = %e, "Failed to store replicated data");
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Storage error: {}", e),
-            ))
-        }
+    state
+        .databases
+        .set_document(db, table, id, data)
+        .await
+        .map_err(storage_error)?;
+    if req.version != 0 {
+        state
+            .storage
+            .set(&version_key(&key), Datum::Integer(req.version as i64))
+            .await
+            .map_err(storage_error)?;
     }
+    info!(db = db, table = table, "Replication successful");
+    Ok(StatusCode::OK)
 }
 
 /// Handle read request from another node
@@ -99,65 +237,23 @@ async fn handle_read(
         .decode(&req.key)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid key encoding: {}", e)))?;
 
-    info!(key_size = key.len(), "Reading data for remote node");
-
-    // Read from local storage
-    match state.storage.get(&key).await {
-        Ok(Some(datum)) => {
-            // Convert Datum to bytes
-            let data = match &datum {
-                Datum::String(s) => s.as_bytes().to_vec(),
-                Datum::Number(n) => n.to_string().into_bytes(),
-                Datum::Boolean(b) => b.to_string().into_bytes(),
-                Datum::Null => vec![],
-                Datum::Array(arr) => {
-                    // Serialize array as JSON
-                    match serde_json::to_vec(arr) {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            error!(error = %e, "Failed to serialize array");
-                            return Err((
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Serialization error: {}", e),
-                            ));
-                        }
-                    }
-                }
-                Datum::Object(obj) => {
-                    // Serialize object as JSON
-                    match serde_json::to_vec(obj) {
-                        Ok(bytes) => bytes,
-                        Err(e) => {
-                            error!(error = %e, "Failed to serialize object");
-                            return Err((
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Serialization error: {}", e),
-                            ));
-                        }
-                    }
-                }
-            };
-            
-            info!(key_size = key.len(), data_size = data.len(), "Read successful");
-            
-            let response = ReadResponse {
-                data: BASE64.encode(&data),
-            };
-            
-            Ok(Json(response))
-        }
-        Ok(None) => {
-            info!(key_size = key.len(), "Key not found");
-            Err((StatusCode::NOT_FOUND, "Key not found".to_string()))
-        }
-        Err(e) => {
-            error!(error = %e, "Failed to read data");
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Storage error: {}", e),
-            ))
-        }
-    }
+    let (db, table, id) = document_key(&key)?;
+    info!(db = db, table = table, "Reading data for remote node");
+
+    let data = state
+        .databases
+        .get_document(db, table, id)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Key not found".to_string()))?;
+    let version = stored_version(&state.storage, &key)
+        .await
+        .map_err(storage_error)?;
+
+    Ok(Json(ReadResponse {
+        data: BASE64.encode(&data),
+        version,
+    }))
 }
 
 #[cfg(test)]
@@ -171,6 +267,7 @@ mod tests {
         
         assert_eq!(req.key, "dGVzdA==");
         assert_eq!(req.data, "dmFsdWU=");
+        assert_eq!(req.version, 0);
     }
 
     #[test]
@@ -181,6 +278,47 @@ mod tests {
         assert_eq!(req.key, "dGVzdA==");
     }
 
+    #[tokio::test]
+    async fn test_replicate_keeps_newest_version() {
+        use crate::server::{build_router, ServerConfig};
+        use tower::ServiceExt;
+
+        let state = AppState::in_memory(ServerConfig::default());
+        state.databases.create_database("test").await.unwrap();
+        state.databases.create_table("test", "users").await.unwrap();
+        let storage = state.storage.clone();
+        let app = build_router(state);
+        let replicate = |version: u64| {
+            let body = serde_json::json!({
+                "key": BASE64.encode(b"doc:test:users:a"),
+                "data": BASE64.encode(serde_json::json!({"id": "a", "v": version}).to_string()),
+                "version": version,
+            });
+            axum::http::Request::builder()
+                .method("POST")
+                .uri("/internal/replicate")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        // Versions above 2^53 stay distinct
+        let newer = (1u64 << 53) + 1;
+        for version in [newer, newer - 1] {
+            let res = app.clone().oneshot(replicate(version)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let version = storage.get(&version_key(b"doc:test:users:a")).await.unwrap();
+        assert_eq!(version, Some(Datum::Integer(newer as i64)));
+        assert_eq!(stored_version(&storage, b"doc:test:users:a").await.unwrap(), newer);
+
+        // An unversioned write cannot replace a versioned document
+        let res = app.clone().oneshot(replicate(0)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let doc = storage.get(b"doc:test:users:a").await.unwrap().unwrap();
+        assert_eq!(serde_json::Value::from(doc)["v"], serde_json::json!(newer));
+    }
+
     #[test]
     fn test_base64_encoding() {
         let key = b"test_key";
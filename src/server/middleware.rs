@@ -2,11 +2,17 @@
 
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{header::RETRY_AFTER, Request, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
-use tracing::{info, Span};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn, Span};
 
 /// Request logging middleware
 pub async fn log_request(req: Request<Body>, next: Next) -> Result<Response, StatusCode> {
@@ -26,8 +32,114 @@ pub async fn log_request(req: Request<Body>, next: Next) -> Result<Response, Sta
     Ok(response)
 }
 
-/// Rate limiting middleware (TODO)
-pub async fn rate_limit(req: Request<Body>, next: Next) -> Result<Response, StatusCode> {
-    // TODO: Implement rate limiting
-    Ok(next.run(req).await)
+/// Token-bucket rate limiter keyed by client identity
+///
+/// Every client gets a bucket of `burst` tokens that refills at
+/// `requests_per_second`; each request takes one token.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Buckets kept before idle, full ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst: burst.max(1) as f64,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Take a token for `key`, or return how long until one is available
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            let (rate, burst) = (self.requests_per_second, self.burst);
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < burst
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.requests_per_second;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+/// Rate limiting middleware
+///
+/// Clients are identified by their `X-API-Key` header, or by source IP when
+/// unauthenticated. Requests over the limit get 429 with `Retry-After`.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let key = match req.headers().get("X-API-Key").and_then(|v| v.to_str().ok()) {
+        Some(api_key) => format!("key:{}", api_key),
+        None => format!("ip:{}", addr.ip()),
+    };
+
+    match limiter.check(&key) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            warn!(client = %key, retry_after, "Rate limit exceeded");
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.to_string())],
+                "Rate limit exceeded",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(10.0, 3);
+
+        for _ in 0..3 {
+            assert!(limiter.check("a").is_ok());
+        }
+        let wait = limiter.check("a").unwrap_err();
+        assert!(wait <= Duration::from_millis(100));
+
+        // Other clients have their own bucket
+        assert!(limiter.check("b").is_ok());
+
+        std::thread::sleep(Duration::from_millis(110));
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+    }
 }
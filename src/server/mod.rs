@@ -2,28 +2,42 @@
 //!
 //! Rust-based web server using axum framework (replaces JavaScript/Node.js)
 
+pub mod admin;
 pub mod database_handlers;
 pub mod handlers;
 pub mod internal;
 pub mod middleware;
+pub mod pagination;
+pub mod request_id;
 pub mod routes;
 pub mod security;
 pub mod websocket;
 
-use axum::{extract::Extension, Router};
+use axum::{
+    extract::{DefaultBodyLimit, Extension},
+    Router,
+};
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
-use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
+use tokio::task::JoinHandle;
+use tower_http::{
+    compression::CompressionLayer, cors::CorsLayer, limit::RequestBodyLimitLayer,
+    trace::TraceLayer,
+};
 use tracing::{error, info, warn};
 
 use crate::cluster::{ClusterState, ReplicationConfig, ReplicationManager};
 use crate::cluster::discovery::{DiscoveryConfig, DiscoveryManager};
-use crate::cluster::health::{HealthChecker, DatabaseHealth, ClusterHealth};
+use crate::cluster::health::{ClusterHealth, ClusterProbe, DatabaseHealth, HealthChecker, StorageProbe};
 use crate::cluster::metrics::MetricsCollector;
 use crate::cluster::scaling::{AutoScaler, ScalingStrategy};
 use crate::query::QueryExecutor;
-use crate::storage::Storage;
+use crate::storage::{changes, DatabaseEngine, OverflowPolicy, Storage, StorageDatabaseEngine};
 
+pub use pagination::QueryCursors;
 pub use security::{SecurityConfig, SecurityState};
 
 /// Server configuration
@@ -39,8 +53,27 @@ pub struct ServerConfig {
     pub max_body_size: usize,
     /// Request timeout (seconds)
     pub timeout_secs: u64,
+    /// Rows per page of HTTP query results
+    pub page_size: usize,
+    /// Seconds an unused HTTP query `next_token` stays valid
+    pub cursor_ttl_secs: u64,
+    /// HTTP query results with rows left kept at once before further ones
+    /// get 503
+    pub max_cursors: usize,
+    /// Queries run at once before further ones get 503 (0 = no limit)
+    pub max_concurrent_queries: usize,
+    /// Changes a changefeed subscriber buffers before it overflows
+    pub changefeed_buffer: usize,
+    /// What happens to a changefeed subscriber whose buffer is full
+    pub changefeed_overflow: OverflowPolicy,
 }
 
+/// Queries the HTTP API runs at once, by default
+pub const DEFAULT_MAX_CONCURRENT_QUERIES: usize = 256;
+
+/// How long driver connections may finish their queries on shutdown
+const TCP_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
@@ -49,6 +82,12 @@ impl Default for ServerConfig {
             enable_cors: true,
             max_body_size: 10 * 1024 * 1024, // 10MB
             timeout_secs: 30,
+            page_size: pagination::DEFAULT_PAGE_SIZE,
+            cursor_ttl_secs: pagination::DEFAULT_CURSOR_TTL.as_secs(),
+            max_cursors: pagination::DEFAULT_MAX_CURSORS,
+            max_concurrent_queries: DEFAULT_MAX_CONCURRENT_QUERIES,
+            changefeed_buffer: changes::DEFAULT_BUFFER_SIZE,
+            changefeed_overflow: OverflowPolicy::default(),
         }
     }
 }
@@ -57,11 +96,49 @@ impl Default for ServerConfig {
 #[derive(Clone)]
 pub struct AppState {
     pub storage: Arc<Storage>,
+    pub databases: Arc<dyn DatabaseEngine>,
     pub executor: Arc<QueryExecutor>,
     pub config: ServerConfig,
     pub security: Option<Arc<SecurityState>>,
     pub cluster: Arc<ClusterState>,
     pub health: Arc<HealthChecker>,
+    /// Rest of HTTP query results fetched page by page
+    pub cursors: Arc<QueryCursors>,
+    /// Query counts behind the admin dashboard
+    pub metrics: Arc<MetricsCollector>,
+}
+
+impl AppState {
+    /// State for a standalone node whose data lives only in memory
+    ///
+    /// Nothing touches disk, so this suits CI and ephemeral caches; all
+    /// data is lost when the state is dropped. Security is disabled.
+    pub fn in_memory(config: ServerConfig) -> Self {
+        let storage = Arc::new(Storage::in_memory());
+        let metrics = Arc::new(MetricsCollector::new());
+
+        Self {
+            executor: Arc::new(
+                QueryExecutor::new(storage.clone())
+                    .with_metrics(metrics.clone())
+                    .with_max_concurrent_queries(config.max_concurrent_queries),
+            ),
+            metrics,
+            databases: Arc::new(StorageDatabaseEngine::new(storage.clone())),
+            storage,
+            cursors: Arc::new(
+                QueryCursors::new(Duration::from_secs(config.cursor_ttl_secs))
+                    .with_max_cursors(config.max_cursors),
+            ),
+            config,
+            security: None,
+            cluster: Arc::new(ClusterState::new(
+                "local".to_string(),
+                ReplicationConfig::default(),
+            )),
+            health: Arc::new(HealthChecker::new()),
+        }
+    }
 }
 
 impl std::fmt::Debug for AppState {
@@ -80,6 +157,8 @@ pub struct ClusterConfig {
     pub mode: String,
     pub peers: Vec<String>,
     pub replication: ReplicationConfig,
+    /// Secret shared by all nodes, authenticating their calls to each other
+    pub secret: Option<String>,
 }
 
 impl ClusterConfig {
@@ -112,6 +191,40 @@ impl ClusterConfig {
             .parse()
             .unwrap_or(16);
 
+        let virtual_nodes = std::env::var("RETHINKDB_VIRTUAL_NODES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::ring::DEFAULT_VIRTUAL_NODES);
+
+        let breaker_failure_threshold = std::env::var("RETHINKDB_BREAKER_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::breaker::DEFAULT_FAILURE_THRESHOLD);
+
+        let breaker_cooldown_ms = std::env::var("RETHINKDB_BREAKER_COOLDOWN_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::breaker::DEFAULT_COOLDOWN.as_millis() as u64);
+
+        let write_timeout_ms = std::env::var("RETHINKDB_WRITE_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::DEFAULT_WRITE_TIMEOUT.as_millis() as u64);
+
+        let write_retries = std::env::var("RETHINKDB_WRITE_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::DEFAULT_WRITE_RETRIES);
+
+        let max_inflight_replications = std::env::var("RETHINKDB_MAX_INFLIGHT_REPLICATIONS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(crate::cluster::DEFAULT_MAX_INFLIGHT_REPLICATIONS);
+
+        let secret = std::env::var("RETHINKDB_CLUSTER_SECRET")
+            .ok()
+            .filter(|s| !s.is_empty());
+
         Self {
             enabled,
             node_id,
@@ -123,16 +236,32 @@ impl ClusterConfig {
                 shard_count,
                 enable_read_replicas: true,
                 write_quorum: (replica_count / 2) + 1,
+                read_quorum: replica_count - (replica_count / 2),
+                virtual_nodes,
+                breaker_failure_threshold,
+                breaker_cooldown_ms,
+                write_timeout_ms,
+                write_retries,
+                write_retry_backoff_ms: crate::cluster::DEFAULT_WRITE_RETRY_BACKOFF.as_millis()
+                    as u64,
+                max_inflight_replications,
             },
+            secret,
         }
     }
 }
 
 /// Start the RethinkDB server
+///
+/// With `protocol` set, the driver protocol server is started as well. It
+/// runs queries on the same executor as the HTTP API, so in a cluster its
+/// writes are replicated too. On shutdown its connections may finish their
+/// queries for up to 30 seconds.
 pub async fn start_server(
     config: ServerConfig,
     storage: Arc<Storage>,
     security_config: Option<SecurityConfig>,
+    protocol: Option<crate::network::ServerConfig>,
 ) -> anyhow::Result<()> {
     info!(
         addr = %config.http_addr,
@@ -159,14 +288,45 @@ pub async fn start_server(
         info!("⚠️  Security middleware disabled (DEV mode)");
     }
 
-    // Create query executor
-    let executor = Arc::new(QueryExecutor::new(storage.clone()));
+    // Initialize metrics; the collector is shared with the executor so
+    // that queries are recorded
+    crate::cluster::metrics::init_metrics();
+    let metrics_collector = Arc::new(MetricsCollector::new());
+
+    // Initialize health checker
+    let health = Arc::new(HealthChecker::new());
+    health.set_ready().await;
+    info!("❤️  Health checker initialized");
 
     // Initialize cluster state
-    let cluster = Arc::new(ClusterState::new(
+    let mut cluster = ClusterState::new(
         cluster_config.node_id.clone(),
         cluster_config.replication.clone(),
-    ));
+    );
+    match &cluster_config.secret {
+        Some(secret) => cluster = cluster.with_secret(secret.clone()),
+        None if cluster_config.enabled && security_state.is_some() => {
+            warn!("RETHINKDB_CLUSTER_SECRET not set; peers cannot call /internal endpoints")
+        }
+        None => {}
+    }
+    let cluster = Arc::new(cluster);
+
+    // Create query executor; in a cluster, writes are replicated and table
+    // STATUS/WAIT follow the owners of the table's shards, standalone
+    // tables are always ready
+    let replication_manager = cluster_config
+        .enabled
+        .then(|| ReplicationManager::new(cluster.clone()));
+    let mut executor = QueryExecutor::new(storage.clone())
+        .with_metrics(metrics_collector.clone())
+        .with_max_concurrent_queries(config.max_concurrent_queries);
+    if let Some(replication_manager) = &replication_manager {
+        executor = executor
+            .with_cluster(cluster.clone())
+            .with_replication(replication_manager.clone());
+    }
+    let executor = Arc::new(executor);
 
     // Initialize as master if in standalone mode
     if cluster_config.mode == "standalone" || cluster_config.mode == "master" {
@@ -176,10 +336,42 @@ pub async fn start_server(
         info!("🟢 Node initialized as REPLICA");
     }
 
+    // Readiness probes the storage engine and the cluster's master live
+    health.add_probe(Arc::new(StorageProbe::new(storage.clone()))).await;
+    health.add_probe(Arc::new(ClusterProbe::new(cluster.clone()))).await;
+
+    // Background tasks, stopped on shutdown
+    let mut background: Vec<JoinHandle<()>> = Vec::new();
+
+    // Set once a shutdown signal arrives
+    let (stopping, mut stopped) = tokio::sync::watch::channel(false);
+
+    // Start driver protocol server
+    let mut tcp_server = None;
+    if let Some(protocol) = protocol {
+        let protocol_server = crate::network::ProtocolServer::new(
+            crate::network::ServerConfig {
+                executor: Some(executor.clone()),
+                ..protocol
+            },
+            storage.clone(),
+        );
+        info!("🔌 TCP protocol server starting on {}", protocol_server.addr());
+        tcp_server = Some(tokio::spawn(async move {
+            // A dropped sender means the HTTP server is gone too
+            let signal = async move {
+                let _ = stopped.wait_for(|stopping| *stopping).await;
+            };
+            if let Err(e) = protocol_server.serve_with_shutdown(signal).await {
+                error!("TCP server error: {}", e);
+            }
+        }));
+    }
+
     // Start replication manager
-    if cluster_config.enabled {
-        let replication_manager = ReplicationManager::new(cluster.clone());
-        replication_manager.start().await;
+    if let Some(replication_manager) = &replication_manager {
+        background.push(replication_manager.start());
+        background.push(replication_manager.start_rebalancing(storage.clone()));
         info!("🔄 Replication manager started");
     }
 
@@ -187,7 +379,7 @@ pub async fn start_server(
     let discovery_config = DiscoveryConfig::from_env();
     if discovery_config.enabled {
         let discovery_manager = DiscoveryManager::new(discovery_config.clone(), cluster.clone());
-        discovery_manager.start().await;
+        background.extend(discovery_manager.start().await);
         info!(
             "🔍 Service discovery started (DNS: {})",
             discovery_config.get_dns_name()
@@ -217,15 +409,10 @@ pub async fn start_server(
         }
     }
 
-    // Initialize health checker
-    let health = Arc::new(HealthChecker::new());
-    health.set_ready().await;
-    info!("❤️  Health checker initialized");
-
-    // Initialize and start metrics collector
-    crate::cluster::metrics::init_metrics();
-    let metrics_collector = MetricsCollector::new();
-    let _metrics_handle = tokio::spawn(async move {
+    // Start metrics collector
+    let resource_metrics = metrics_collector.clone();
+    let metrics_storage = storage.clone();
+    background.push(tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(15));
         loop {
             interval.tick().await;
@@ -238,16 +425,20 @@ pub async fn start_server(
             let memory_percent = (sys.used_memory() as f64 / sys.total_memory() as f64 * 100.0) as f32;
             let disk_bytes = 0; // TODO: Implement disk monitoring
             let disk_percent = 0.0;
-            
-            metrics_collector.update_resource_metrics(
-                cpu,
-                memory_bytes,
-                memory_percent,
-                disk_bytes,
-                disk_percent,
-            ).await;
+
+            resource_metrics
+                .update_resource_metrics(
+                    cpu,
+                    memory_bytes,
+                    memory_percent,
+                    disk_bytes,
+                    disk_percent,
+                )
+                .await;
+            resource_metrics.update_slab_metrics(&metrics_storage.size_class_stats());
+            resource_metrics.update_storage_load(metrics_storage.pending_writes());
         }
-    });
+    }));
     info!("📊 Metrics collector started");
 
     // Start auto-scaler if enabled
@@ -280,12 +471,25 @@ pub async fn start_server(
             }
         };
 
-        let auto_scaler = AutoScaler::new(strategy);
+        let auto_scaler = Arc::new(AutoScaler::new(strategy));
+
+        // Feed the storage write load to the auto-scaler's custom metrics
+        let load_scaler = auto_scaler.clone();
+        let load_storage = storage.clone();
+        background.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(15));
+            loop {
+                interval.tick().await;
+                load_scaler
+                    .update_storage_load(load_storage.pending_writes() as u64)
+                    .await;
+            }
+        }));
         
         // Start auto-scaler in background
-        tokio::spawn(async move {
+        background.push(tokio::spawn(async move {
             auto_scaler.start(60).await;
-        });
+        }));
 
         info!("🔧 Auto-scaler started with {} strategy", strategy_name);
     } else if autoscaling_enabled {
@@ -313,13 +517,143 @@ pub async fn start_server(
 
     // Build application state
     let state = AppState {
-        storage,
+        databases: Arc::new(StorageDatabaseEngine::new(storage.clone())),
+        storage: storage.clone(),
         executor,
         config: config.clone(),
         security: security_state.clone(),
         cluster,
-        health,
+        health: health.clone(),
+        cursors: Arc::new(
+            QueryCursors::new(Duration::from_secs(config.cursor_ttl_secs))
+                .with_max_cursors(config.max_cursors),
+        ),
+        metrics: metrics_collector,
+    };
+
+    let app = build_router(state);
+
+    // Bind and serve
+    let addr = format!("{}:{}", config.http_addr, config.http_port);
+    let listener = TcpListener::bind(&addr).await?;
+
+    info!("Server listening on http://{}", addr);
+    info!("📊 Dashboard: http://{}/_admin", addr);
+    info!("🔍 Metrics: http://{}/_metrics", addr);
+    info!("❤️  Health: http://{}/_health", addr);
+
+    let signal = async move {
+        shutdown_signal().await;
+        stopping.send_replace(true);
     };
+    let mut result =
+        serve_with_shutdown(listener, app, health, storage.clone(), signal).await;
+
+    if let Some(mut tcp_server) = tcp_server {
+        match tokio::time::timeout(TCP_DRAIN_TIMEOUT, &mut tcp_server).await {
+            Ok(_) => info!("TCP connections closed"),
+            Err(_) => {
+                warn!("TCP connections still open after {:?}, closing them", TCP_DRAIN_TIMEOUT);
+                tcp_server.abort();
+            }
+        }
+        // Queries over TCP may have written after the HTTP server flushed
+        if let Err(e) = storage.flush().await {
+            error!(error = %e, "Failed to flush storage on shutdown");
+            result = result.and(Err(anyhow::anyhow!("Failed to flush storage: {}", e)));
+        }
+    }
+
+    for task in background {
+        task.abort();
+    }
+    info!("Background tasks stopped");
+
+    result
+}
+
+/// Serve the router until `signal` resolves, then shut down gracefully
+///
+/// On the signal the node is first marked not ready so load balancers stop
+/// routing to it, then in-flight requests are drained and finally the
+/// storage engine is flushed.
+pub async fn serve_with_shutdown<F>(
+    listener: TcpListener,
+    app: Router,
+    health: Arc<HealthChecker>,
+    storage: Arc<Storage>,
+    signal: F,
+) -> anyhow::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let shutdown_health = health.clone();
+    let served = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        signal.await;
+        info!("Shutdown requested, draining in-flight requests");
+        shutdown_health.set_not_ready().await;
+    })
+    .await;
+
+    if let Err(e) = storage.flush().await {
+        error!(error = %e, "Failed to flush storage on shutdown");
+        return Err(anyhow::anyhow!("Failed to flush storage: {}", e));
+    }
+    info!("Storage flushed");
+
+    served.map_err(|e| {
+        error!(error = %e, "Server error");
+        anyhow::anyhow!("Server failed: {}", e)
+    })
+}
+
+/// Resolve on SIGINT (Ctrl+C) or, on Unix, SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "Failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Build the HTTP router with all routes and layers
+///
+/// The security middleware is layered only when `state.security` is set.
+/// It relies on `ConnectInfo<SocketAddr>`, so the router must be served with
+/// `into_make_service_with_connect_info`.
+///
+/// Request bodies larger than `config.max_body_size` are rejected with 413
+/// before any handler reads them.
+pub fn build_router(state: AppState) -> Router {
+    let security_state = state.security.clone();
+    let enable_cors = state.config.enable_cors;
+    let max_body_size = state.config.max_body_size;
 
     // Build router with all routes
     let app = Router::new()
@@ -329,35 +663,784 @@ pub async fn start_server(
         .merge(routes::health_routes())
         .merge(internal::internal_routes()) // Internal cluster communication
         .layer(Extension(Arc::new(state)))
-        .layer(TraceLayer::new_for_http())
-        .layer(CompressionLayer::new());
+        // Replace axum's 2 MB extractor default with the configured limit
+        .layer(DefaultBodyLimit::max(max_body_size))
+        .layer(RequestBodyLimitLayer::new(max_body_size));
 
     // Add security middleware if enabled
-    if let Some(_sec_state) = security_state {
-        info!("Security state initialized (middleware integration pending)");
-        // Note: Security middleware would be added here
-        // For now, we just store it in AppState
-        // TODO: Integrate security::security_middleware
-    }
+    let app = if let Some(sec_state) = security_state {
+        info!("Security middleware layered on router");
+        let rate_limit = sec_state.rate_limiter();
+        let app = app.layer(axum::middleware::from_fn_with_state(
+            (*sec_state).clone(),
+            security::security_middleware,
+        ));
+
+        // Throttle before authenticating, so credential guessing is limited too
+        match rate_limit {
+            Some(limiter) => app.layer(axum::middleware::from_fn_with_state(
+                limiter,
+                middleware::rate_limit,
+            )),
+            None => app,
+        }
+    } else {
+        app
+    };
+
+    // Outside security, so rejected requests get an id too
+    let app = app
+        .layer(axum::middleware::from_fn(request_id::correlate))
+        .layer(TraceLayer::new_for_http())
+        .layer(CompressionLayer::new());
 
     // Add CORS if enabled
-    let app = if config.enable_cors {
+    if enable_cors {
         app.layer(CorsLayer::permissive())
     } else {
         app
-    };
+    }
+}
 
-    // Bind and serve
-    let addr = format!("{}:{}", config.http_addr, config.http_port);
-    let listener = TcpListener::bind(&addr).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
 
-    info!("Server listening on http://{}", addr);
-    info!("📊 Dashboard: http://{}/_admin", addr);
-    info!("🔍 Metrics: http://{}/_metrics", addr);
-    info!("❤️  Health: http://{}/_health", addr);
+    fn test_state(security: Option<SecurityConfig>) -> AppState {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "server_test_{}_{}",
+            std::process::id(),
+            uuid::Uuid::new_v4()
+        ));
+        let storage = Arc::new(Storage::new(Box::new(
+            crate::storage::slab::SlabStorageEngine::with_defaults(&temp_dir).unwrap(),
+        )));
 
-    axum::serve(listener, app).await.map_err(|e| {
-        error!(error = %e, "Server error");
-        anyhow::anyhow!("Server failed: {}", e)
-    })
+        AppState {
+            executor: Arc::new(QueryExecutor::new(storage.clone())),
+            databases: Arc::new(StorageDatabaseEngine::new(storage.clone())),
+            storage,
+            config: ServerConfig::default(),
+            security: security.map(|cfg| Arc::new(SecurityState::new(cfg))),
+            cluster: Arc::new(ClusterState::new(
+                "test-node".to_string(),
+                ReplicationConfig::default(),
+            )),
+            health: Arc::new(HealthChecker::new()),
+            cursors: Arc::new(QueryCursors::default()),
+            metrics: Arc::new(MetricsCollector::new()),
+        }
+    }
+
+    fn request(path: &str, header: Option<(&str, &str)>) -> Request<Body> {
+        let mut builder = Request::builder().uri(path);
+        if let Some((name, value)) = header {
+            builder = builder.header(name, value);
+        }
+        let mut req = builder.body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_security_middleware_protects_routes() {
+        let secret = "integration-secret";
+        let config = SecurityConfig {
+            honeytrap_enabled: false,
+            jwt_secret: secret.to_string(),
+            api_keys: vec!["test-api-key".to_string()],
+            ..Default::default()
+        };
+        let app = build_router(test_state(Some(config)));
+
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &security::Claims {
+                sub: "tester".to_string(),
+                exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+            },
+            &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap();
+        let bearer = format!("Bearer {}", token);
+
+        // Health stays open
+        let res = app.clone().oneshot(request("/_health", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Protected route without credentials
+        let res = app.clone().oneshot(request("/api/tables", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Invalid token
+        let res = app
+            .clone()
+            .oneshot(request("/api/tables", Some(("Authorization", "Bearer bogus"))))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Valid JWT
+        let res = app
+            .clone()
+            .oneshot(request("/api/tables", Some(("Authorization", &bearer))))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Valid API key
+        let res = app
+            .clone()
+            .oneshot(request("/api/tables", Some(("X-API-Key", "test-api-key"))))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_public_paths_are_configurable() {
+        let config = SecurityConfig {
+            honeytrap_enabled: false,
+            public_paths: vec!["/_health".to_string(), "/api/*".to_string()],
+            ..Default::default()
+        };
+        let app = build_router(test_state(Some(config)));
+
+        let res = app.oneshot(request("/api/tables", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_endpoint_probes_cluster() {
+        let state = test_state(None);
+        state.health.set_ready().await;
+        state.health.update_database_health(DatabaseHealth {
+            status: "healthy".to_string(),
+            tables_count: 0,
+            active_queries: 0,
+            connections: 0,
+        }).await;
+        state.health.update_cluster_health(ClusterHealth {
+            status: "healthy".to_string(),
+            nodes: 1,
+            masters: 1,
+            replicas: 0,
+            replication_lag_ms: 0.0,
+        }).await;
+        state.health.add_probe(Arc::new(StorageProbe::new(state.storage.clone()))).await;
+        state.health.add_probe(Arc::new(ClusterProbe::new(state.cluster.clone()))).await;
+        let app = build_router(state.clone());
+
+        // The cluster has no master yet
+        let res = app.clone().oneshot(request("/_health/ready", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let res = app.clone().oneshot(request("/_health/live", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        state.cluster.init_as_master().await;
+        let res = app.oneshot(request("/_health/ready", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_completes_in_flight_request() {
+        let state = test_state(None);
+        let health = state.health.clone();
+        health.set_ready().await;
+        let started = Arc::new(tokio::sync::Notify::new());
+        let handler_started = started.clone();
+        let app = build_router(state.clone()).route(
+            "/slow",
+            axum::routing::get(move || async move {
+                handler_started.notify_one();
+                tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                "done"
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_shutdown(
+            listener,
+            app,
+            health.clone(),
+            state.storage.clone(),
+            async {
+                stopped.await.ok();
+            },
+        ));
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}/slow", addr)));
+        started.notified().await;
+        stop.send(()).unwrap();
+
+        // The in-flight request still completes
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+        assert!(!health.check_readiness().await);
+
+        // And the server then stops
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not stop")
+            .unwrap()
+            .unwrap();
+        assert!(reqwest::get(format!("http://{}/_health", addr)).await.is_err());
+    }
+
+    fn post_json(path: &str, body: &str) -> Request<Body> {
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(path)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        req
+    }
+
+    async fn json_body(res: axum::response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_endpoint() {
+        let state = test_state(None);
+        state.storage.create_database("test").await.unwrap();
+        let app = build_router(state);
+
+        // DB_LIST
+        let res = app.clone().oneshot(post_json("/api/query", "[59]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = json_body(res).await;
+        assert_eq!(body["success"], true);
+        assert!(body["result"].as_array().unwrap().contains(&serde_json::json!("test")));
+
+        // ADD(2, 3), wrapped in a query object
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", r#"{"query": [24, [2, 3]]}"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json_body(res).await["result"], 5.0);
+
+        // Terms sent as a string keep working
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", r#"{"query": "[26, [4, 3]]"}"#))
+            .await
+            .unwrap();
+        assert_eq!(json_body(res).await["result"], 12.0);
+
+        // Unknown terms fail to compile
+        let res = app.clone().oneshot(post_json("/api/query", "[9999]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = json_body(res).await;
+        assert_eq!(body["success"], false);
+        assert!(body["error"].as_str().unwrap().contains("Unknown term type"));
+
+        // Missing values are reported as not found
+        let res = app
+            .oneshot(post_json("/api/query", r#"[31, [{"a": 1}, "b"]]"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_query_pagination() {
+        use crate::reql::{Datum, Term, TermType};
+
+        let mut state = test_state(None);
+        state.cursors = Arc::new(QueryCursors::new(Duration::from_millis(200)));
+        state.storage.create_database("test").await.unwrap();
+        state
+            .storage
+            .create_table("test", "items", "id")
+            .await
+            .unwrap();
+        let docs = (0..1000)
+            .map(|i| Datum::from(serde_json::json!({ "id": i })))
+            .collect();
+        let insert = Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_arg(Term::datum(Datum::from("items"))),
+            Term::datum(Datum::Array(docs)),
+        ]);
+        state.executor.execute(&insert).await.unwrap();
+        let app = build_router(state);
+
+        let mut body = json_body(
+            app.clone()
+                .oneshot(post_json(
+                    "/api/query",
+                    r#"{"query": [15, ["items"]], "options": {"batch_size": 300}}"#,
+                ))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let mut ids = std::collections::HashSet::new();
+        let mut pages = 0;
+        loop {
+            pages += 1;
+            for row in body["result"].as_array().unwrap() {
+                assert!(ids.insert(row["id"].as_f64().unwrap() as u64));
+            }
+            let Some(token) = body["next_token"].as_str() else {
+                break;
+            };
+            let next = serde_json::json!({ "next_token": token }).to_string();
+            let res = app
+                .clone()
+                .oneshot(post_json("/api/query", &next))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            body = json_body(res).await;
+        }
+        assert_eq!(pages, 4);
+        assert_eq!(ids.len(), 1000);
+
+        // Results within a page carry no token
+        let body = json_body(
+            app.clone()
+                .oneshot(post_json("/api/query", "[59]"))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert!(body.get("next_token").is_none());
+
+        // A token not used within the TTL expires
+        let body = json_body(
+            app.clone()
+                .oneshot(post_json(
+                    "/api/query",
+                    r#"{"query": [15, ["items"]], "options": {"batch_size": 10}}"#,
+                ))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let next = serde_json::json!({ "next_token": body["next_token"] }).to_string();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // Starting another result purges the expired cursor but keeps its token
+        let res = app
+            .clone()
+            .oneshot(post_json(
+                "/api/query",
+                r#"{"query": [15, ["items"]], "options": {"batch_size": 10}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", &next))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::GONE);
+        let body = json_body(res).await;
+        assert_eq!(body["success"], false);
+        assert!(body["error"].as_str().unwrap().contains("has expired"));
+
+        let res = app
+            .oneshot(post_json("/api/query", r#"{"next_token": "bogus"}"#))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_query_cursor_limit() {
+        use crate::reql::{Datum, Term, TermType};
+
+        let mut state = test_state(None);
+        state.cursors = Arc::new(QueryCursors::default().with_max_cursors(1));
+        state.storage.create_database("test").await.unwrap();
+        state
+            .storage
+            .create_table("test", "items", "id")
+            .await
+            .unwrap();
+        let docs = (0..20)
+            .map(|i| Datum::from(serde_json::json!({ "id": i })))
+            .collect();
+        let insert = Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_arg(Term::datum(Datum::from("items"))),
+            Term::datum(Datum::Array(docs)),
+        ]);
+        state.executor.execute(&insert).await.unwrap();
+        let app = build_router(state);
+        let query = r#"{"query": [15, ["items"]], "options": {"batch_size": 15}}"#;
+
+        let body = json_body(
+            app.clone()
+                .oneshot(post_json("/api/query", query))
+                .await
+                .unwrap(),
+        )
+        .await;
+        let next = serde_json::json!({ "next_token": body["next_token"] }).to_string();
+
+        // While the only cursor is open, results larger than a page are refused
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", query))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", "[59]"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Fetching the last page frees the cursor
+        let body = json_body(
+            app.clone()
+                .oneshot(post_json("/api/query", &next))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert!(body.get("next_token").is_none());
+        let res = app.oneshot(post_json("/api/query", query)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_query_ndjson_stream() {
+        use crate::reql::{Datum, Term, TermType};
+        use futures_util::StreamExt;
+
+        let mut state = test_state(None);
+        state.executor =
+            Arc::new(QueryExecutor::new(state.storage.clone()).with_max_concurrent_queries(1));
+        state.storage.create_database("test").await.unwrap();
+        state
+            .storage
+            .create_table("test", "items", "id")
+            .await
+            .unwrap();
+        let docs = (0..2500)
+            .map(|i| Datum::from(serde_json::json!({ "id": i, "even": i % 2 == 0 })))
+            .collect();
+        let insert = Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_arg(Term::datum(Datum::from("items"))),
+            Term::datum(Datum::Array(docs)),
+        ]);
+        state.executor.execute(&insert).await.unwrap();
+        let (storage, executor) = (state.storage.clone(), state.executor.clone());
+        let app = build_router(state);
+        let ndjson = |body: &str| {
+            let mut req = post_json("/api/query", body);
+            req.headers_mut()
+                .insert("Accept", handlers::NDJSON.parse().unwrap());
+            req
+        };
+
+        // The table arrives as a chunked stream, a page per chunk
+        let res = app
+            .clone()
+            .oneshot(ndjson(
+                r#"{"query": [15, ["items"]], "options": {"batch_size": 500}}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], handlers::NDJSON);
+        assert!(res.headers().get("content-length").is_none());
+        let mut chunks = res.into_body().into_data_stream();
+        let mut ids = std::collections::HashSet::new();
+        let mut chunk_count = 0;
+        while let Some(chunk) = chunks.next().await {
+            chunk_count += 1;
+            let chunk = chunk.unwrap();
+            let text = std::str::from_utf8(&chunk).unwrap();
+            assert!(text.ends_with('\n'));
+            for line in text.lines() {
+                let row: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert!(ids.insert(row["id"].as_f64().unwrap() as u64));
+            }
+        }
+        assert_eq!(ids.len(), 2500);
+        assert!(chunk_count >= 5);
+
+        // A stream holds its query slot until it is done
+        let res = app
+            .clone()
+            .oneshot(ndjson(
+                r#"{"query": [15, ["items"]], "options": {"batch_size": 500}}"#,
+            ))
+            .await
+            .unwrap();
+        let busy = app
+            .clone()
+            .oneshot(post_json("/api/query", "[59]"))
+            .await
+            .unwrap();
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", "[59]"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // A filtered table is streamed page by page as well
+        let res = app
+            .clone()
+            .oneshot(ndjson(
+                r#"{"query": [39, [[15, ["items"]], {"even": true}]], "options": {"batch_size": 500}}"#,
+            ))
+            .await
+            .unwrap();
+        let mut chunks = res.into_body().into_data_stream();
+        let mut rows = 0;
+        chunk_count = 0;
+        while let Some(chunk) = chunks.next().await {
+            chunk_count += 1;
+            for line in std::str::from_utf8(&chunk.unwrap()).unwrap().lines() {
+                let row: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(row["even"], true);
+                rows += 1;
+            }
+        }
+        assert_eq!(rows, 1250);
+        assert!(chunk_count >= 5);
+
+        // Other results are streamed too, a value per line
+        let res = app.clone().oneshot(ndjson("[59]")).await.unwrap();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(std::str::from_utf8(&bytes).unwrap(), "\"test\"\n");
+
+        // Failures before the first row keep their status
+        let res = app.clone().oneshot(ndjson("[9999]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Tables are looked up in the requested database
+        storage.create_database("shop").await.unwrap();
+        storage.create_table("shop", "items", "id").await.unwrap();
+        let insert = Term::new(TermType::Insert).with_args(vec![
+            Term::new(TermType::Table).with_arg(Term::datum(Datum::from("items"))),
+            Term::datum(Datum::from(serde_json::json!({"id": "hat"}))),
+        ]);
+        executor.execute_in(&insert, "shop").await.unwrap();
+        let res = app
+            .clone()
+            .oneshot(ndjson(r#"{"query": [15, ["items"]], "options": {"db": "shop"}}"#))
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(std::str::from_utf8(&bytes).unwrap(), "{\"id\":\"hat\"}\n");
+        let res = app
+            .oneshot(post_json(
+                "/api/query",
+                r#"{"query": [15, ["items"]], "options": {"db": "shop"}}"#,
+            ))
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["result"], serde_json::json!([{"id": "hat"}]));
+    }
+
+    #[tokio::test]
+    async fn test_max_body_size() {
+        let mut state = test_state(None);
+        state.config.max_body_size = 64;
+        let app = build_router(state);
+        let padded = |len: usize| format!("{:<len$}", "[59]", len = len);
+
+        // Just under (and at) the limit
+        let res = app.clone().oneshot(post_json("/api/query", &padded(64))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Just over, on any route
+        let res = app.clone().oneshot(post_json("/api/query", &padded(65))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let res = app.oneshot(post_json("/api/dbs", &padded(65))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let config = SecurityConfig {
+            honeytrap_enabled: false,
+            api_keys: vec!["key-a".to_string(), "key-b".to_string()],
+            rate_limit_per_second: 10.0,
+            rate_limit_burst: 2,
+            ..Default::default()
+        };
+        let app = build_router(test_state(Some(config)));
+        let key_a = Some(("X-API-Key", "key-a"));
+
+        for _ in 0..2 {
+            let res = app.clone().oneshot(request("/api/dbs", key_a)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let res = app.clone().oneshot(request("/api/dbs", key_a)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["Retry-After"], "1");
+
+        // Another key from the same address has its own budget
+        let key_b = Some(("X-API-Key", "key-b"));
+        let res = app.clone().oneshot(request("/api/dbs", key_b)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // One token is back after 100ms
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        let res = app.oneshot(request("/api/dbs", key_a)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    /// Plugin whose calls wait until the test lets them return
+    struct GatePlugin {
+        entered: Arc<tokio::sync::Semaphore>,
+        release: Arc<tokio::sync::Semaphore>,
+    }
+
+    impl crate::plugin::Plugin for GatePlugin {
+        fn metadata(&self) -> crate::plugin::PluginMetadata {
+            crate::plugin::PluginMetadata {
+                name: "gate".to_string(),
+                version: "1.0.0".to_string(),
+                abi_version: crate::plugin::PLUGIN_ABI_VERSION,
+                author: "test".to_string(),
+                description: "test plugin".to_string(),
+                capabilities: vec![crate::plugin::PluginCapability::QueryOperations],
+            }
+        }
+
+        fn execute(
+            &self,
+            _function: &str,
+            _args: Vec<crate::reql::Datum>,
+        ) -> std::pin::Pin<
+            Box<
+                dyn std::future::Future<Output = crate::error::Result<crate::reql::Datum>>
+                    + Send
+                    + '_,
+            >,
+        > {
+            Box::pin(async move {
+                self.entered.add_permits(1);
+                self.release.acquire().await.unwrap().forget();
+                Ok(crate::reql::Datum::Null)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_limit() {
+        let entered = Arc::new(tokio::sync::Semaphore::new(0));
+        let release = Arc::new(tokio::sync::Semaphore::new(0));
+        let plugins = crate::plugin::PluginManager::new();
+        plugins
+            .register_plugin(Arc::new(GatePlugin {
+                entered: entered.clone(),
+                release: release.clone(),
+            }))
+            .unwrap();
+        let mut state = test_state(None);
+        state.executor = Arc::new(
+            QueryExecutor::new(state.storage.clone())
+                .with_plugins(Arc::new(plugins))
+                .with_max_concurrent_queries(2),
+        );
+        let app = build_router(state);
+
+        // Two queries fill the pool
+        let running: Vec<_> = (0..2)
+            .map(|_| {
+                let request = post_json("/api/query", r#"[273, ["gate", "wait"]]"#);
+                tokio::spawn(app.clone().oneshot(request))
+            })
+            .collect();
+        entered.acquire_many(2).await.unwrap().forget();
+
+        // A third is turned away rather than queued
+        let res = app
+            .clone()
+            .oneshot(post_json("/api/query", "[59]"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()["Retry-After"], "1");
+        let body = json_body(res).await;
+        assert!(
+            body["error"].as_str().unwrap().contains("Server busy"),
+            "{}",
+            body
+        );
+
+        // Once they finish there is room again
+        release.add_permits(2);
+        for request in running {
+            assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        let res = app.oneshot(post_json("/api/query", "[59]")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_stats_endpoint() {
+        let state = test_state(None);
+        state.storage.create_database("test").await.unwrap();
+        state.storage.create_table("test", "docs", "id").await.unwrap();
+        for i in 0..20 {
+            let doc = serde_json::json!({"id": i, "body": "lorem ipsum ".repeat(20)});
+            let key = format!("doc:test:docs:{}", i);
+            state.storage.set(key.as_bytes(), doc.into()).await.unwrap();
+        }
+        let app = build_router(state);
+
+        let res = app.oneshot(request("/api/stats", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let stats = json_body(res).await["stats"].clone();
+
+        assert!(stats["key_count"].as_u64().unwrap() >= 20);
+        let used: u64 = stats["size_class_stats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|class| class["allocated_slots"].as_u64().unwrap())
+            .sum();
+        assert!(used >= 20);
+
+        // The repetitive documents shrink under zstd
+        let ratio = stats["compression"]["ratio"].as_f64().unwrap();
+        assert!(ratio > 0.0 && ratio < 1.0, "ratio = {}", ratio);
+        assert!(stats["cache"]["capacity"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_security_in_dev_mode() {
+        let app = build_router(test_state(None));
+
+        let res = app.oneshot(request("/api/tables", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,179 @@
+//! Paged query results for the HTTP query API
+//!
+//! HTTP has no connection to hold a cursor, so when a result is larger than
+//! the page size `POST /api/query` returns its first page together with a
+//! `next_token`. The remaining rows stay on the server under that token;
+//! sending the token back fetches the next page. The token stays the same
+//! for all pages of a result and is dropped with the last one, or once it
+//! has not been used for the cursor TTL. An expired token is remembered for
+//! another TTL so it is reported as expired rather than unknown. At most
+//! [`DEFAULT_MAX_CURSORS`] results, unless configured otherwise, wait to be
+//! fetched at once; further results larger than a page are refused until
+//! some are fetched or expire.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Rows per page unless the request asks for another batch size
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// Time an unused token stays valid unless configured otherwise
+pub const DEFAULT_CURSOR_TTL: Duration = Duration::from_secs(60);
+
+/// Results with rows left kept at once unless configured otherwise
+pub const DEFAULT_MAX_CURSORS: usize = 1024;
+
+/// Why a page could not be fetched
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CursorError {
+    #[error("Query token `{0}` has expired; run the query again")]
+    Expired(String),
+    #[error("Unknown query token `{0}`")]
+    Unknown(String),
+    #[error("Too many partially fetched query results ({0}); fetch or abandon some first")]
+    Full(usize),
+}
+
+/// One page of a result and the token for the next, if rows are left
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub rows: Vec<serde_json::Value>,
+    pub next_token: Option<String>,
+}
+
+#[derive(Debug)]
+struct Cursor {
+    /// Rows not sent yet, in order
+    rows: std::vec::IntoIter<serde_json::Value>,
+    page_size: usize,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Cursors {
+    live: HashMap<String, Cursor>,
+    /// Tokens of expired cursors and when to forget them
+    expired: HashMap<String, Instant>,
+}
+
+impl Cursors {
+    /// Turn cursors unused for `ttl` into tombstones, forgetting those
+    /// expired for another `ttl`
+    fn expire(&mut self, now: Instant, ttl: Duration) {
+        self.expired.retain(|_, forget_at| *forget_at > now);
+        let expired: Vec<_> = self
+            .live
+            .iter()
+            .filter(|(_, cursor)| cursor.expires_at <= now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in expired {
+            let cursor = self.live.remove(&token).unwrap();
+            self.expired.insert(token, cursor.expires_at + ttl);
+        }
+    }
+}
+
+/// Server-side state of partially fetched results, keyed by token
+#[derive(Debug)]
+pub struct QueryCursors {
+    ttl: Duration,
+    max_cursors: usize,
+    cursors: Mutex<Cursors>,
+}
+
+impl QueryCursors {
+    /// Cursors dropped after `ttl` without a fetch
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_cursors: DEFAULT_MAX_CURSORS,
+            cursors: Mutex::new(Cursors::default()),
+        }
+    }
+
+    /// Keep at most `max_cursors` results with rows left at once
+    pub fn with_max_cursors(mut self, max_cursors: usize) -> Self {
+        self.max_cursors = max_cursors;
+        self
+    }
+
+    /// First page of `rows`, keeping the rest under a new token
+    ///
+    /// Fails if the rest would need a cursor while the maximum number of
+    /// them is open.
+    pub fn start(
+        &self,
+        rows: Vec<serde_json::Value>,
+        page_size: usize,
+    ) -> Result<Page, CursorError> {
+        let page_size = page_size.max(1);
+        let mut cursors = self.cursors.lock();
+        let now = Instant::now();
+        cursors.expire(now, self.ttl);
+
+        let mut cursor = Cursor {
+            rows: rows.into_iter(),
+            page_size,
+            expires_at: now + self.ttl,
+        };
+        let page: Vec<_> = cursor.rows.by_ref().take(page_size).collect();
+        if cursor.rows.len() == 0 {
+            return Ok(Page {
+                rows: page,
+                next_token: None,
+            });
+        }
+        if cursors.live.len() >= self.max_cursors {
+            return Err(CursorError::Full(self.max_cursors));
+        }
+
+        let token = uuid::Uuid::new_v4().to_string();
+        cursors.live.insert(token.clone(), cursor);
+        Ok(Page {
+            rows: page,
+            next_token: Some(token),
+        })
+    }
+
+    /// Next page under `token`, of `page_size` rows if given
+    pub fn next(&self, token: &str, page_size: Option<usize>) -> Result<Page, CursorError> {
+        let mut cursors = self.cursors.lock();
+        let now = Instant::now();
+        cursors.expire(now, self.ttl);
+        if cursors.expired.contains_key(token) {
+            return Err(CursorError::Expired(token.to_string()));
+        }
+        let cursor = cursors
+            .live
+            .get_mut(token)
+            .ok_or_else(|| CursorError::Unknown(token.to_string()))?;
+
+        let page_size = page_size.map_or(cursor.page_size, |size| size.max(1));
+        let rows: Vec<_> = cursor.rows.by_ref().take(page_size).collect();
+        let next_token = if cursor.rows.len() == 0 {
+            cursors.live.remove(token);
+            None
+        } else {
+            cursor.expires_at = now + self.ttl;
+            Some(token.to_string())
+        };
+        Ok(Page { rows, next_token })
+    }
+
+    /// Number of results with rows left
+    pub fn len(&self) -> usize {
+        self.cursors.lock().live.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for QueryCursors {
+    fn default() -> Self {
+        Self::new(DEFAULT_CURSOR_TTL)
+    }
+}
@@ -0,0 +1,155 @@
+//! Request correlation ids of the HTTP API
+//!
+//! Every HTTP request gets an id, taken from its `X-Request-Id` header or
+//! generated, see [`crate::request_id`]. The id is
+//! - recorded on a `request` span wrapping everything the request runs,
+//! - echoed in the `X-Request-Id` response header,
+//! - added as `request_id` to JSON error bodies, and
+//! - sent along with the replication and read calls the request makes to
+//!   other nodes, which pick it up as their own.
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+use crate::request_id::{accept, scope, REQUEST_ID_HEADER};
+
+/// The id named by the request's header if usable, else a new one
+fn request_id(req: &Request<Body>) -> String {
+    accept(
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok()),
+    )
+}
+
+/// Assign the request its id and run it within the id's span
+pub async fn correlate(req: Request<Body>, next: Next) -> Response {
+    let id = request_id(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = req.uri().path(),
+    );
+
+    let response = scope(id.clone(), next.run(req)).instrument(span).await;
+    let mut response = if response.status().is_client_error() || response.status().is_server_error()
+    {
+        with_error_id(response, &id).await
+    } else {
+        response
+    };
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// `response` with `request_id` added to its body if that is a JSON object
+async fn with_error_id(response: Response, id: &str) -> Response {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut obj)) => {
+            obj.insert("request_id".to_string(), id.into());
+            let body = serde_json::Value::Object(obj).to_string();
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            Body::from(body)
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{build_router, AppState, ServerConfig};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// Records the `request_id` field of every new span
+    #[derive(Clone, Default)]
+    struct SpanIds(Arc<Mutex<Vec<String>>>);
+
+    struct IdVisitor<'a>(&'a mut Option<String>);
+
+    impl Visit for IdVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "request_id" {
+                *self.0 = Some(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SpanIds {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            let mut id = None;
+            attrs.record(&mut IdVisitor(&mut id));
+            self.0.lock().unwrap().extend(id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_id_in_errors_and_spans() {
+        let spans = SpanIds::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+        let app = build_router(AppState::in_memory(ServerConfig::default()));
+
+        // Creating a database a second time fails
+        let query = |id: Option<&str>| {
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/api/query")
+                .header("content-type", "application/json");
+            if let Some(id) = id {
+                req = req.header(REQUEST_ID_HEADER, id);
+            }
+            req.body(Body::from(r#"{"query": [57, ["test"]]}"#))
+                .unwrap()
+        };
+        let res = app.clone().oneshot(query(None)).await.unwrap();
+        assert!(res.status().is_success());
+        assert!(res.headers().contains_key(REQUEST_ID_HEADER));
+        let res = app.clone().oneshot(query(Some("req-1234"))).await.unwrap();
+        assert!(res.status().is_client_error() || res.status().is_server_error());
+        assert_eq!(res.headers()[REQUEST_ID_HEADER], "req-1234");
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["request_id"], "req-1234");
+        assert!(spans.0.lock().unwrap().contains(&"req-1234".to_string()));
+
+        // Unusable ids are replaced by generated ones
+        let res = app.clone().oneshot(query(Some("bad id"))).await.unwrap();
+        let id = res.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+    }
+}
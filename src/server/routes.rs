@@ -7,13 +7,15 @@ use axum::{
 };
 use std::sync::Arc;
 
-use super::{database_handlers, handlers, AppState};
+use super::{admin, database_handlers, handlers, websocket, AppState};
 use crate::cluster::health::HealthStatus;
 
 /// API routes for query execution and legacy table operations
 pub fn api_routes() -> Router {
     Router::new()
         .route("/api/query", post(handlers::execute_query))
+        .route("/api/stats", get(handlers::storage_stats))
+        .route("/api/changes", get(websocket::changes))
         // Legacy table routes (will be deprecated)
         .route("/api/tables", get(handlers::list_tables))
         .route("/api/tables/:name", get(handlers::get_table_info))
@@ -29,6 +31,10 @@ pub fn api_routes() -> Router {
 /// - GET    /api/dbs/:db/tables         - List tables in database
 /// - POST   /api/dbs/:db/tables         - Create table in database
 /// - DELETE /api/dbs/:db/tables/:table  - Drop table
+/// - POST   /api/dbs/:db/tables/:table/documents     - Insert a document
+/// - GET    /api/dbs/:db/tables/:table/documents/:id - Get a document
+/// - PUT    /api/dbs/:db/tables/:table/documents/:id - Replace a document
+/// - DELETE /api/dbs/:db/tables/:table/documents/:id - Delete a document
 pub fn database_routes() -> Router {
     Router::new()
         // Database operations
@@ -49,12 +55,30 @@ pub fn database_routes() -> Router {
             "/api/dbs/:db_name/tables/:table_name",
             delete(database_handlers::drop_table),
         )
+        // Document operations (scoped to table)
+        .route(
+            "/api/dbs/:db_name/tables/:table_name/documents",
+            post(database_handlers::insert_document),
+        )
+        .route(
+            "/api/dbs/:db_name/tables/:table_name/documents/:id",
+            get(database_handlers::get_document)
+                .put(database_handlers::put_document)
+                .delete(database_handlers::delete_document),
+        )
 }
 
 /// Admin routes
+///
+/// The dashboard page and the JSON it is built from, see [`admin`].
 pub fn admin_routes() -> Router {
     Router::new()
         .route("/_admin", get(admin_dashboard))
+        .route("/_admin/api/overview", get(admin::overview))
+        .route("/_admin/api/storage", get(admin::storage))
+        .route("/_admin/api/queries", get(admin::queries))
+        .route("/_admin/api/cluster", get(admin::cluster))
+        .route("/_admin/api/stream", get(admin::stream))
 }
 
 /// Health check routes
@@ -63,6 +87,8 @@ pub fn health_routes() -> Router {
         .route("/_health", get(health_detailed))
         .route("/health", get(health_detailed))
         .route("/_ready", get(health_ready))
+        .route("/_health/ready", get(health_ready))
+        .route("/_health/live", get(health_live))
         .route("/health/ready", get(health_ready))
         .route("/health/live", get(health_live))
         .route("/health/startup", get(health_startup))
@@ -19,6 +19,8 @@ use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 use tokio::sync::RwLock;
 use tracing::{error, info, instrument, warn};
 
+use crate::server::middleware::RateLimiter;
+
 /// OAuth2 provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuth2Provider {
@@ -38,7 +40,17 @@ pub struct SecurityConfig {
     pub honeytrap_url: String,
     pub oauth2_providers: Vec<OAuth2Provider>,
     pub jwt_secret: String,
+    /// Static API keys accepted via the `X-API-Key` header
+    pub api_keys: Vec<String>,
+    /// Paths that do not require authentication.
+    /// Entries ending in `*` match by prefix.
+    pub public_paths: Vec<String>,
     pub max_requests_per_minute: u32,
+    /// Sustained requests per second per client (API key, else IP);
+    /// 0 disables the token-bucket limiter
+    pub rate_limit_per_second: f64,
+    /// Requests a client may burst above the sustained rate
+    pub rate_limit_burst: u32,
 }
 
 impl Default for SecurityConfig {
@@ -83,7 +95,24 @@ impl Default for SecurityConfig {
             ],
             jwt_secret: std::env::var("JWT_SECRET")
                 .unwrap_or_else(|_| "CHANGE_ME_IN_PRODUCTION".to_string()),
+            api_keys: std::env::var("RETHINKDB_API_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+            public_paths: vec![
+                "/_health".to_string(),
+                "/_health/*".to_string(),
+                "/_ready".to_string(),
+                "/_metrics".to_string(),
+                "/health".to_string(),
+                "/health/*".to_string(),
+                "/auth/*".to_string(),
+            ],
             max_requests_per_minute: 100,
+            rate_limit_per_second: 50.0,
+            rate_limit_burst: 100,
         }
     }
 }
@@ -102,17 +131,32 @@ pub struct SecurityState {
     config: Arc<SecurityConfig>,
     blocked_ips: Arc<RwLock<HashMap<String, BlockedIP>>>,
     rate_limits: Arc<RwLock<HashMap<String, Vec<chrono::DateTime<chrono::Utc>>>>>,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl SecurityState {
     pub fn new(config: SecurityConfig) -> Self {
+        let rate_limiter = (config.enabled && config.rate_limit_per_second > 0.0)
+            .then(|| RateLimiter::new(config.rate_limit_per_second, config.rate_limit_burst));
+
         Self {
             config: Arc::new(config),
             blocked_ips: Arc::new(RwLock::new(HashMap::new())),
             rate_limits: Arc::new(RwLock::new(HashMap::new())),
+            rate_limiter,
         }
     }
 
+    /// Token-bucket limiter for the HTTP API, `None` when disabled
+    pub fn rate_limiter(&self) -> Option<RateLimiter> {
+        self.rate_limiter.clone()
+    }
+
+    /// Whether requests are authenticated, i.e. not in development mode
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
     /// Check if IP is blocked
     async fn is_blocked(&self, ip: &str) -> bool {
         let blocked = self.blocked_ips.read().await;
@@ -205,10 +249,21 @@ pub async fn security_middleware(
         return Err(StatusCode::TOO_MANY_REQUESTS);
     }
 
-    // 3. Check JWT token for authenticated endpoints
+    // 3. Check API key or JWT token for authenticated endpoints; cluster
+    // peers authenticate with the cluster secret instead
     let path = req.uri().path();
-    if !is_public_endpoint(path) {
-        if let Some(auth_header) = headers.get("Authorization") {
+    if !is_public_endpoint(path, &state.config.public_paths) && !path.starts_with("/internal/") {
+        if let Some(api_key) = headers.get("X-API-Key") {
+            let valid = api_key
+                .to_str()
+                .map(|key| state.config.api_keys.iter().any(|k| k == key))
+                .unwrap_or(false);
+            if !valid {
+                warn!(ip = %ip, "Invalid API key");
+                state.report_to_honeytrap(&ip, "invalid_api_key").await;
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+        } else if let Some(auth_header) = headers.get("Authorization") {
             if let Ok(auth_str) = auth_header.to_str() {
                 if !validate_jwt_token(auth_str, &state.config.jwt_secret) {
                     warn!(ip = %ip, "Invalid JWT token");
@@ -219,7 +274,7 @@ pub async fn security_middleware(
                 return Err(StatusCode::UNAUTHORIZED);
             }
         } else {
-            // No auth header on protected endpoint
+            // No credentials on protected endpoint
             return Err(StatusCode::UNAUTHORIZED);
         }
     }
@@ -238,23 +293,36 @@ pub async fn security_middleware(
 }
 
 /// Check if endpoint is public (no auth required)
-fn is_public_endpoint(path: &str) -> bool {
-    matches!(
-        path,
-        "/_health" | "/_ready" | "/_metrics" | "/auth/login" | "/auth/callback"
-    ) || path.starts_with("/auth/")
+fn is_public_endpoint(path: &str, public_paths: &[String]) -> bool {
+    public_paths.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == pattern,
+    })
+}
+
+/// JWT claims accepted by the server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
 }
 
 /// Validate JWT token
 fn validate_jwt_token(token: &str, secret: &str) -> bool {
+    // Never accept tokens signed with the placeholder secret
+    if secret == "CHANGE_ME_IN_PRODUCTION" {
+        return false;
+    }
+
     // Strip "Bearer " prefix if present
     let token = token.strip_prefix("Bearer ").unwrap_or(token);
 
-    // TODO: Implement proper JWT validation
-    // For now, just check if token is not empty
-    // In production, use jsonwebtoken crate:
-    // jsonwebtoken::decode::<Claims>(token, secret, &Validation::default())
-    !token.is_empty() && secret != "CHANGE_ME_IN_PRODUCTION"
+    jsonwebtoken::decode::<Claims>(
+        token,
+        &jsonwebtoken::DecodingKey::from_secret(secret.as_bytes()),
+        &jsonwebtoken::Validation::default(),
+    )
+    .is_ok()
 }
 
 /// Detect suspicious request patterns
@@ -291,10 +359,33 @@ mod tests {
 
     #[test]
     fn test_public_endpoints() {
-        assert!(is_public_endpoint("/_health"));
-        assert!(is_public_endpoint("/_ready"));
-        assert!(is_public_endpoint("/auth/login"));
-        assert!(!is_public_endpoint("/api/query"));
+        let public = SecurityConfig::default().public_paths;
+        assert!(is_public_endpoint("/_health", &public));
+        assert!(is_public_endpoint("/_health/ready", &public));
+        assert!(is_public_endpoint("/_health/live", &public));
+        assert!(is_public_endpoint("/_ready", &public));
+        assert!(is_public_endpoint("/auth/login", &public));
+        assert!(!is_public_endpoint("/api/query", &public));
+    }
+
+    #[test]
+    fn test_jwt_validation() {
+        let secret = "test-secret";
+        let claims = Claims {
+            sub: "alice".to_string(),
+            exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap();
+
+        assert!(validate_jwt_token(&format!("Bearer {}", token), secret));
+        assert!(!validate_jwt_token(&token, "other-secret"));
+        assert!(!validate_jwt_token("not-a-jwt", secret));
+        assert!(!validate_jwt_token(&token, "CHANGE_ME_IN_PRODUCTION"));
     }
 
     #[test]
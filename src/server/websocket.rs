@@ -1,10 +1,55 @@
 //! WebSocket support for changefeeds
+//!
+//! A client connects to `/api/changes`, subscribes to a table with
+//! `{"action": "subscribe", "db": "app", "table": "users"}` and then
+//! receives a `{"type": "change", "old_val": ..., "new_val": ...}` message
+//! for every write to it.
+//!
+//! Changes are buffered per connection, see [`changes`]. A client that
+//! falls behind by more than [`ServerConfig::changefeed_buffer`] changes
+//! is disconnected with close code 1013 (try again later) after the
+//! changes already sent, unless the server coalesces changes instead.
+//! Either way writers and other subscribers never wait for it.
+//!
+//! [`changes`]: crate::storage::changes
+//! [`ServerConfig::changefeed_buffer`]: super::ServerConfig::changefeed_buffer
 
-use axum::extract::ws::{Message, WebSocket};
-use tracing::{error, info};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Extension;
+use axum::response::Response;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+use super::AppState;
+use crate::storage::Subscription;
+
+/// Reason sent with the close frame of a subscriber that fell behind
+pub const OVERFLOW_REASON: &str = "changefeed subscriber fell behind";
+
+/// Message a client sends on a changefeed
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ChangefeedCommand {
+    Subscribe { db: String, table: String },
+    Unsubscribe,
+}
+
+/// `GET /api/changes` — upgrade to a changefeed
+pub async fn changes(ws: WebSocketUpgrade, Extension(state): Extension<Arc<AppState>>) -> Response {
+    ws.on_upgrade(move |socket| handle_changefeed(socket, state))
+}
+
+/// The next change of `subscription`, or never without one
+async fn next_change(subscription: &Option<Subscription>) -> Option<crate::storage::Change> {
+    match subscription {
+        Some(subscription) => subscription.recv().await,
+        None => std::future::pending().await,
+    }
+}
 
 /// Handle WebSocket connection for changefeeds
-pub async fn handle_changefeed(mut socket: WebSocket) {
+pub async fn handle_changefeed(mut socket: WebSocket, state: Arc<AppState>) {
     info!("New changefeed connection");
 
     // Send initial connection message
@@ -17,22 +62,133 @@ pub async fn handle_changefeed(mut socket: WebSocket) {
         return;
     }
 
-    // Handle incoming messages
-    while let Some(msg) = socket.recv().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                info!(message = %text, "Received changefeed subscription");
-                // TODO: Subscribe to changefeed
-            }
-            Ok(Message::Close(_)) => {
-                info!("WebSocket connection closed");
-                break;
-            }
-            Err(e) => {
-                error!(error = %e, "WebSocket error");
-                break;
-            }
-            _ => {}
+    let mut subscription: Option<Subscription> = None;
+    loop {
+        let reply = tokio::select! {
+            change = next_change(&subscription) => match change {
+                Some(change) => change.to_json(),
+                None => {
+                    warn!("Closing changefeed of a subscriber that fell behind");
+                    let frame = CloseFrame {
+                        code: close_code::AGAIN,
+                        reason: OVERFLOW_REASON.into(),
+                    };
+                    let _ = socket.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ChangefeedCommand::Subscribe { db, table }) => {
+                        info!(db = %db, table = %table, "Changefeed subscribed");
+                        subscription = Some(state.storage.changes().subscribe(
+                            &db,
+                            &table,
+                            state.config.changefeed_buffer,
+                            state.config.changefeed_overflow,
+                        ));
+                        serde_json::json!({ "type": "subscribed", "db": db, "table": table })
+                    }
+                    Ok(ChangefeedCommand::Unsubscribe) => {
+                        subscription = None;
+                        serde_json::json!({ "type": "unsubscribed" })
+                    }
+                    Err(e) => serde_json::json!({
+                        "type": "error",
+                        "error": format!("Invalid command: {}", e),
+                    }),
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(e)) => {
+                    error!(error = %e, "WebSocket error");
+                    break;
+                }
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(Message::Text(reply.to_string())).await.is_err() {
+            break;
         }
     }
+    debug!("Changefeed connection closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{build_router, ServerConfig};
+    use crate::storage::OverflowPolicy;
+    use futures_util::{SinkExt, StreamExt};
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    type Client = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn next(ws: &mut Client) -> WsMessage {
+        let message = tokio::time::timeout(Duration::from_secs(5), ws.next());
+        message.await.unwrap().unwrap().unwrap()
+    }
+
+    async fn next_json(ws: &mut Client) -> serde_json::Value {
+        match next(ws).await {
+            WsMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    async fn subscribe(url: &str) -> Client {
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        assert_eq!(next_json(&mut ws).await["type"], "connected");
+        let command = r#"{"action":"subscribe","db":"app","table":"users"}"#;
+        ws.send(WsMessage::Text(command.to_string())).await.unwrap();
+        assert_eq!(next_json(&mut ws).await["type"], "subscribed");
+        ws
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_is_disconnected() {
+        let config = ServerConfig {
+            changefeed_buffer: 8,
+            changefeed_overflow: OverflowPolicy::Disconnect,
+            ..ServerConfig::default()
+        };
+        let state = AppState::in_memory(config);
+        let storage = state.storage.clone();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, build_router(state)).await });
+
+        let url = format!("ws://{}/api/changes", addr);
+        let mut slow = subscribe(&url).await;
+        let mut fast = subscribe(&url).await;
+
+        // The slow client reads nothing while the fast one keeps up; the
+        // documents are large enough to fill the slow client's socket
+        let padding = "x".repeat(64 * 1024);
+        for i in 0..512 {
+            let key = format!("doc:app:users:{}", i);
+            let doc = crate::reql::Datum::from(format!("{}:{}", i, padding));
+            storage.set(key.as_bytes(), doc).await.unwrap();
+            let change = next_json(&mut fast).await;
+            assert_eq!(change["type"], "change");
+            assert_eq!(change["old_val"], serde_json::Value::Null);
+            let new_val = change["new_val"].as_str().unwrap();
+            assert!(new_val.starts_with(&format!("{}:", i)));
+        }
+
+        // The changes sent before the overflow are followed by a close
+        // frame saying why
+        let frame = loop {
+            match next(&mut slow).await {
+                WsMessage::Text(_) => continue,
+                WsMessage::Close(frame) => break frame.unwrap(),
+                other => panic!("unexpected message {:?}", other),
+            }
+        };
+        assert_eq!(frame.code, CloseCode::Again);
+        assert_eq!(frame.reason, OVERFLOW_REASON);
+    }
 }
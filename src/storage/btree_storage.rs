@@ -9,6 +9,7 @@ use crate::error::{Error, Result};
 use crate::reql::Datum;
 use crate::storage::engine::{StorageEngine, TableInfo};
 use async_trait::async_trait;
+use std::ops::{Bound, RangeBounds};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use tracing::{debug, info, instrument};
@@ -66,6 +67,33 @@ impl BTreeStorage {
         String::from_utf8(bytes.to_vec())
             .map_err(|e| Error::Storage(format!("Invalid UTF-8 key: {}", e)))
     }
+
+    /// All entries whose key starts with `prefix`, in key order
+    ///
+    /// The tree has no range queries, so this walks every leaf.
+    pub fn entries_with_prefix(&self, prefix: &str) -> Result<Vec<(String, Datum)>> {
+        let pairs = self
+            .tree
+            .lock()
+            .map_err(|e| Error::Storage(format!("Lock poisoned: {}", e)))?
+            .scan()
+            .map_err(|e| Error::Storage(format!("B-Tree scan failed: {:?}", e)))?;
+
+        pairs
+            .into_iter()
+            .filter(|kv| kv.key.starts_with(prefix))
+            .map(|kv| Ok((kv.key, Self::json_to_datum(&kv.value)?)))
+            .collect()
+    }
+
+    /// Key suffixes of all keys starting with `prefix`
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .entries_with_prefix(prefix)?
+            .into_iter()
+            .map(|(key, _)| key[prefix.len()..].to_string())
+            .collect())
+    }
 }
 
 #[async_trait]
@@ -123,18 +151,19 @@ impl StorageEngine for BTreeStorage {
     }
 
     async fn list_tables(&self) -> Result<Vec<String>> {
-        // B-Tree doesn't have built-in table namespace support
-        Ok(Vec::new())
+        self.keys_with_prefix("__meta__:tables:")
     }
 
-    async fn get_table_info(&self, _table_name: &str) -> Result<Option<TableInfo>> {
-        // Not implemented for basic B-Tree storage
-        Ok(None)
+    async fn get_table_info(&self, table_name: &str) -> Result<Option<TableInfo>> {
+        let key = format!("__meta__:tables:{}", table_name);
+        match self.get(key.as_bytes()).await? {
+            Some(datum) => Ok(Some(TableInfo::from_datum(&datum)?)),
+            None => Ok(None),
+        }
     }
 
     async fn list_databases(&self) -> Result<Vec<String>> {
-        // Not implemented for basic B-Tree storage
-        Ok(Vec::new())
+        self.keys_with_prefix("__meta__:databases:")
     }
 
     async fn create_database(&self, _name: &str) -> Result<()> {
@@ -147,9 +176,8 @@ impl StorageEngine for BTreeStorage {
         Ok(())
     }
 
-    async fn list_tables_in_db(&self, _db: &str) -> Result<Vec<String>> {
-        // Not implemented for basic B-Tree storage
-        Ok(Vec::new())
+    async fn list_tables_in_db(&self, db: &str) -> Result<Vec<String>> {
+        self.keys_with_prefix(&format!("__meta__:tables:{}.", db))
     }
 
     async fn create_table(&self, _db: &str, _table: &str, _primary_key: &str) -> Result<()> {
@@ -162,9 +190,44 @@ impl StorageEngine for BTreeStorage {
         Ok(())
     }
 
-    async fn scan_table(&self, _db: &str, _table: &str) -> Result<Vec<Datum>> {
-        // Not implemented for basic B-Tree storage
-        Ok(Vec::new())
+    async fn scan_table(&self, db: &str, table: &str) -> Result<Vec<Datum>> {
+        let prefix = format!("doc:{}:{}:", db, table);
+        Ok(self
+            .entries_with_prefix(&prefix)?
+            .into_iter()
+            .map(|(_, doc)| doc)
+            .collect())
+    }
+
+    async fn scan_table_page(
+        &self,
+        db: &str,
+        table: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let prefix = format!("doc:{}:{}:", db, table);
+        Ok(self
+            .entries_with_prefix(&prefix)?
+            .into_iter()
+            .map(|(key, doc)| (key.into_bytes(), doc))
+            .filter(|(key, _)| after.is_none_or(|after| key.as_slice() > after))
+            .take(limit)
+            .collect())
+    }
+
+    async fn scan_keys(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let range = (start, end);
+        Ok(self
+            .entries_with_prefix("")?
+            .into_iter()
+            .map(|(key, datum)| (key.into_bytes(), datum))
+            .filter(|(key, _)| RangeBounds::<[u8]>::contains(&range, key.as_slice()))
+            .collect())
     }
 }
 
@@ -194,6 +257,37 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_btree_storage_scan_table() -> Result<()> {
+        // Own directory, since the WAL lives next to the tree file
+        let dir = std::env::temp_dir().join(format!("btree_scan_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.btree").to_string_lossy().into_owned();
+        let storage = BTreeStorage::new(path, Some(10))?;
+
+        // Keys and values must fit the 10 byte page slots
+        for i in [3, 1, 2] {
+            let key = format!("doc:a:t:{}", i);
+            storage.set(key.as_bytes(), Datum::Number(i as f64)).await?;
+        }
+        storage.set(b"doc:a:u:1", Datum::Number(9.0)).await?;
+
+        let docs = storage.scan_table("a", "t").await?;
+        assert_eq!(
+            docs,
+            vec![Datum::Number(1.0), Datum::Number(2.0), Datum::Number(3.0)]
+        );
+
+        let page = storage
+            .scan_table_page("a", "t", Some(b"doc:a:t:1"), 10)
+            .await?;
+        let keys: Vec<_> = page.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"doc:a:t:2".to_vec(), b"doc:a:t:3".to_vec()]);
+
+        std::fs::remove_dir_all(dir).ok();
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_btree_storage_complex_datum() -> Result<()> {
         let temp_path = format!("/tmp/rethinkdb_test_complex_{}.btree", std::process::id());
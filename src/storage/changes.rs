@@ -0,0 +1,338 @@
+//! Change notifications for changefeeds
+//!
+//! Every document write that goes through the [`Storage`] wrapper is
+//! published on its [`ChangeBus`] as a [`Change`] to the subscribers of
+//! the document's table.
+//!
+//! # Backpressure
+//!
+//! Each [`Subscription`] buffers at most `capacity` changes. Publishing
+//! never waits: a subscriber that stops reading only fills its own
+//! buffer, and what happens when it is full is its [`OverflowPolicy`]:
+//!
+//! - [`OverflowPolicy::Disconnect`] closes the subscription, dropping the
+//!   buffered changes.
+//! - [`OverflowPolicy::Coalesce`] folds the change into a buffered change
+//!   to the same document, so the subscriber sees the document's latest
+//!   state instead of every step. A change to a document that has nothing
+//!   buffered cannot be folded and closes the subscription as above.
+//!
+//! Either way the subscriber learns it missed changes from
+//! [`Subscription::overflowed`] rather than silently losing them.
+//!
+//! [`Storage`]: crate::storage::Storage
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::reql::Datum;
+
+/// Changes a subscriber buffers before it overflows, by default
+pub const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// A document write, as seen by changefeeds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub db: String,
+    pub table: String,
+    /// The document before the write, `None` if it was inserted
+    pub old_val: Option<Datum>,
+    /// The document after the write, `None` if it was deleted
+    pub new_val: Option<Datum>,
+    /// Storage key of the document, to coalesce changes by
+    key: Vec<u8>,
+}
+
+impl Change {
+    pub(crate) fn new(
+        db: &str,
+        table: &str,
+        key: Vec<u8>,
+        old_val: Option<Datum>,
+        new_val: Option<Datum>,
+    ) -> Self {
+        Self {
+            db: db.to_string(),
+            table: table.to_string(),
+            old_val,
+            new_val,
+            key,
+        }
+    }
+
+    /// The change as a changefeed message
+    pub fn to_json(&self) -> serde_json::Value {
+        let value = |doc: &Option<Datum>| {
+            doc.clone()
+                .map(serde_json::Value::from)
+                .unwrap_or(serde_json::Value::Null)
+        };
+        serde_json::json!({
+            "type": "change",
+            "db": self.db,
+            "table": self.table,
+            "old_val": value(&self.old_val),
+            "new_val": value(&self.new_val),
+        })
+    }
+}
+
+/// What to do with a change for a subscriber whose buffer is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Close the subscription
+    #[default]
+    Disconnect,
+    /// Fold the change into a buffered change to the same document
+    Coalesce,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "disconnect" => Ok(Self::Disconnect),
+            "coalesce" => Ok(Self::Coalesce),
+            other => Err(format!(
+                "Unknown overflow policy '{}', expected 'disconnect' or 'coalesce'",
+                other
+            )),
+        }
+    }
+}
+
+/// Buffered changes of one subscriber
+#[derive(Default)]
+struct Queue {
+    changes: VecDeque<Change>,
+    overflowed: bool,
+}
+
+struct Subscriber {
+    db: String,
+    table: String,
+    capacity: usize,
+    policy: OverflowPolicy,
+    queue: Mutex<Queue>,
+    notify: Notify,
+}
+
+impl Subscriber {
+    /// Buffer `change`, returning `false` once the subscriber overflowed
+    fn push(&self, change: &Change) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.overflowed {
+            return false;
+        }
+        if queue.changes.len() < self.capacity {
+            queue.changes.push_back(change.clone());
+        } else if let Some(at) = self.coalesce_target(&queue, change) {
+            let pending = &mut queue.changes[at];
+            pending.new_val = change.new_val.clone();
+            // An insert followed by a delete leaves nothing to report
+            if pending.old_val.is_none() && pending.new_val.is_none() {
+                queue.changes.remove(at);
+            }
+        } else {
+            queue.changes.clear();
+            queue.overflowed = true;
+        }
+        let overflowed = queue.overflowed;
+        drop(queue);
+        self.notify.notify_one();
+        !overflowed
+    }
+
+    /// Position of the buffered change `change` can be folded into, if the
+    /// policy allows
+    fn coalesce_target(&self, queue: &Queue, change: &Change) -> Option<usize> {
+        match self.policy {
+            OverflowPolicy::Disconnect => None,
+            OverflowPolicy::Coalesce => queue
+                .changes
+                .iter()
+                .rposition(|pending| pending.key == change.key),
+        }
+    }
+}
+
+type Subscribers = Mutex<Vec<Arc<Subscriber>>>;
+
+/// Fans document writes out to changefeed subscribers
+#[derive(Default)]
+pub struct ChangeBus {
+    subscribers: Arc<Subscribers>,
+}
+
+impl ChangeBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the changes of `db.table`, buffering up to `capacity`
+    /// of them
+    pub fn subscribe(
+        &self,
+        db: &str,
+        table: &str,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Subscription {
+        let subscriber = Arc::new(Subscriber {
+            db: db.to_string(),
+            table: table.to_string(),
+            capacity: capacity.max(1),
+            policy,
+            queue: Mutex::new(Queue::default()),
+            notify: Notify::new(),
+        });
+        self.subscribers.lock().unwrap().push(subscriber.clone());
+        Subscription {
+            subscriber,
+            bus: Arc::downgrade(&self.subscribers),
+        }
+    }
+
+    /// Whether anyone subscribed to the changes of `db.table`
+    pub fn is_watched(&self, db: &str, table: &str) -> bool {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|s| s.db == db && s.table == table)
+    }
+
+    /// Hand `change` to the subscribers of its table without waiting on any
+    /// of them; subscribers that overflow are dropped from the bus
+    pub fn publish(&self, change: Change) {
+        self.subscribers.lock().unwrap().retain(|s| {
+            if s.db != change.db || s.table != change.table {
+                return true;
+            }
+            s.push(&change)
+        });
+    }
+}
+
+/// Changes of one table, see [`ChangeBus::subscribe`]
+///
+/// Dropping the subscription unsubscribes.
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+    bus: Weak<Subscribers>,
+}
+
+impl Subscription {
+    /// The next change, or `None` once the subscription overflowed
+    pub async fn recv(&self) -> Option<Change> {
+        loop {
+            {
+                let mut queue = self.subscriber.queue.lock().unwrap();
+                if queue.overflowed {
+                    return None;
+                }
+                if let Some(change) = queue.changes.pop_front() {
+                    return Some(change);
+                }
+            }
+            self.subscriber.notify.notified().await;
+        }
+    }
+
+    /// Whether changes were lost because the buffer was full
+    pub fn overflowed(&self) -> bool {
+        self.subscriber.queue.lock().unwrap().overflowed
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.bus.upgrade() {
+            subscribers
+                .lock()
+                .unwrap()
+                .retain(|s| !Arc::ptr_eq(s, &self.subscriber));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn change(id: &str, old_val: Option<i64>, new_val: Option<i64>) -> Change {
+        Change::new(
+            "app",
+            "users",
+            format!("doc:app:users:{}", id).into_bytes(),
+            old_val.map(|v| Datum::Number(v as f64)),
+            new_val.map(|v| Datum::Number(v as f64)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_does_not_block_others() {
+        let bus = ChangeBus::new();
+        let slow = bus.subscribe("app", "users", 4, OverflowPolicy::Disconnect);
+        let fast = bus.subscribe("app", "users", 4, OverflowPolicy::Disconnect);
+        let other = bus.subscribe("app", "events", 4, OverflowPolicy::Disconnect);
+
+        for i in 0..100 {
+            bus.publish(change(&i.to_string(), None, Some(i)));
+            let received = tokio::time::timeout(Duration::from_secs(1), fast.recv());
+            let received = received.await.unwrap().unwrap();
+            assert_eq!(received.new_val, Some(Datum::Number(i as f64)));
+        }
+
+        // The reader that never read overflowed and was dropped from the bus
+        assert!(slow.overflowed());
+        assert_eq!(slow.recv().await, None);
+        assert!(!fast.overflowed());
+        assert!(!other.overflowed());
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 2);
+
+        drop(fast);
+        assert!(!bus.is_watched("app", "users"));
+        assert!(bus.is_watched("app", "events"));
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_on_overflow() {
+        let bus = ChangeBus::new();
+        let sub = bus.subscribe("app", "users", 2, OverflowPolicy::Coalesce);
+
+        bus.publish(change("a", None, Some(1)));
+        bus.publish(change("b", Some(1), Some(2)));
+        bus.publish(change("b", Some(2), Some(3)));
+        bus.publish(change("a", Some(1), None));
+        assert!(!sub.overflowed());
+
+        // b's steps fold into one change; a's insert and delete cancel out
+        let b = sub.recv().await.unwrap();
+        assert_eq!(b.old_val, Some(Datum::Number(1.0)));
+        assert_eq!(b.new_val, Some(Datum::Number(3.0)));
+        bus.publish(change("c", None, Some(1)));
+        assert_eq!(sub.recv().await.unwrap().key, b"doc:app:users:c");
+
+        // A full buffer with nothing to fold into still overflows
+        bus.publish(change("c", None, Some(1)));
+        bus.publish(change("d", None, Some(1)));
+        bus.publish(change("e", None, Some(1)));
+        assert!(sub.overflowed());
+        assert_eq!(sub.recv().await, None);
+    }
+
+    #[test]
+    fn test_overflow_policy_from_str() {
+        assert_eq!("Coalesce".parse(), Ok(OverflowPolicy::Coalesce));
+        assert_eq!("disconnect".parse(), Ok(OverflowPolicy::Disconnect));
+        assert!("drop".parse::<OverflowPolicy>().is_err());
+    }
+}
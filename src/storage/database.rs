@@ -41,10 +41,13 @@
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::Bound;
 use uuid::Uuid;
 
 use crate::error::{Error, Result};
+use crate::reql::Datum;
 
 /// A unique identifier for a database.
 ///
@@ -337,9 +340,14 @@ impl DatabaseConfig {
 ///   "primary_key": "id",
 ///   "created_at": 1704067200,
 ///   "doc_count": 1523,
-///   "indexes": ["email", "username"]
+///   "indexes": ["email", "username", "full_name"],
+///   "compound_indexes": {"full_name": ["last_name", "first_name"]},
+///   "schema": {"type": "object", "required": ["email"]}
 /// }
 /// ```
+///
+/// `compound_indexes` is left out while the table has none, `schema` while
+/// it is unset.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableConfig {
     /// Unique table identifier (128-bit UUID).
@@ -376,6 +384,20 @@ pub struct TableConfig {
     /// Indexes are stored separately under keys like:
     /// `db:{db_id}:table:{table_id}:idx:{index_name}:{value}`
     pub indexes: Vec<String>,
+
+    /// Fields of the compound indexes among `indexes`, by index name.
+    ///
+    /// A compound index orders documents by the array of these fields'
+    /// values; every other index is built from the field it is named after.
+    #[serde(default)]
+    pub compound_indexes: BTreeMap<String, Vec<String>>,
+
+    /// JSON Schema every document of the table must match.
+    ///
+    /// `None` (the default) accepts any document. Set it with
+    /// [`DatabaseEngine::set_table_schema`].
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
 }
 
 impl TableConfig {
@@ -419,6 +441,8 @@ impl TableConfig {
                 .as_secs(),
             doc_count: 0,
             indexes: Vec::new(),
+            compound_indexes: BTreeMap::new(),
+            schema: None,
         }
     }
 
@@ -465,6 +489,121 @@ impl TableConfig {
         self.primary_key = primary_key;
         self
     }
+
+    /// Converts the configuration to its stored form (see *Storage Format*),
+    /// with the parent database name under `db`.
+    pub fn to_datum(&self, db_name: &str) -> Datum {
+        let compound = (!self.compound_indexes.is_empty()).then(|| {
+            let definitions = self.compound_indexes.iter().map(|(index, fields)| {
+                let fields = fields.iter().cloned().map(Datum::String).collect();
+                (index.clone(), Datum::Array(fields))
+            });
+            (
+                "compound_indexes".to_string(),
+                Datum::Object(definitions.collect()),
+            )
+        });
+        Datum::Object(
+            vec![
+                ("id".to_string(), Datum::String(self.id.to_string())),
+                ("name".to_string(), Datum::String(self.name.clone())),
+                ("db".to_string(), Datum::String(db_name.to_string())),
+                (
+                    "database_id".to_string(),
+                    Datum::String(self.database_id.to_string()),
+                ),
+                (
+                    "primary_key".to_string(),
+                    Datum::String(self.primary_key.clone()),
+                ),
+                (
+                    "created_at".to_string(),
+                    Datum::Number(self.created_at as f64),
+                ),
+                ("doc_count".to_string(), Datum::Number(self.doc_count as f64)),
+                (
+                    "indexes".to_string(),
+                    Datum::Array(self.indexes.iter().cloned().map(Datum::String).collect()),
+                ),
+            ]
+            .into_iter()
+            .chain(compound)
+            .chain(
+                self.schema
+                    .clone()
+                    .map(|schema| ("schema".to_string(), Datum::from(schema))),
+            )
+            .collect(),
+        )
+    }
+}
+
+/// Changes requested by [`DatabaseEngine::reconfigure_table`].
+///
+/// # Examples
+///
+/// ```rust
+/// use rethinkdb::storage::TableReconfigure;
+///
+/// let changes = TableReconfigure {
+///     add_indexes: vec!["email".to_string()],
+///     ..Default::default()
+/// };
+/// assert!(changes.primary_key.is_none());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableReconfigure {
+    /// Requested primary key.
+    ///
+    /// Only accepted when it matches the current one; changing the primary
+    /// key requires rebuilding the table.
+    pub primary_key: Option<String>,
+
+    /// Secondary indexes to create.
+    pub add_indexes: Vec<String>,
+
+    /// Compound indexes to create, by name, with the fields they combine.
+    pub add_compound_indexes: BTreeMap<String, Vec<String>>,
+
+    /// Secondary indexes to drop.
+    pub remove_indexes: Vec<String>,
+}
+
+/// What [`DatabaseEngine::drop_database_with`] does with a database that
+/// still has tables.
+///
+/// # Examples
+///
+/// ```rust
+/// use rethinkdb::storage::DropMode;
+///
+/// assert_eq!("cascade".parse::<DropMode>(), Ok(DropMode::Cascade));
+/// assert_eq!(DropMode::default(), DropMode::Restrict);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DropMode {
+    /// Refuse to drop the database while it has tables.
+    #[default]
+    Restrict,
+
+    /// Drop the database together with all its tables and documents.
+    Cascade,
+}
+
+impl std::str::FromStr for DropMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "restrict" => Ok(DropMode::Restrict),
+            "cascade" => Ok(DropMode::Cascade),
+            other => Err(format!(
+                "Unknown drop mode '{}' (expected restrict or cascade)",
+                other
+            )),
+        }
+    }
 }
 
 /// Database engine trait - manages the database hierarchy.
@@ -639,6 +778,32 @@ pub trait DatabaseEngine: Send + Sync {
     /// ```
     async fn drop_database(&self, name: &str) -> Result<()>;
 
+    /// Drops a database, refusing in [`DropMode::Restrict`] if it still has
+    /// tables.
+    ///
+    /// [`DropMode::Cascade`] is the same as [`drop_database`].
+    ///
+    /// # Errors
+    ///
+    /// - `Error::NotFound` if the database doesn't exist
+    /// - `Error::InvalidArgument` if it has tables and `mode` is `Restrict`;
+    ///   nothing is dropped then
+    ///
+    /// [`drop_database`]: DatabaseEngine::drop_database
+    async fn drop_database_with(&self, name: &str, mode: DropMode) -> Result<()> {
+        if mode == DropMode::Restrict {
+            let tables = self.list_tables(name).await?;
+            if !tables.is_empty() {
+                return Err(Error::InvalidArgument(format!(
+                    "Database '{}' still has {} table(s); drop them first or use CASCADE",
+                    name,
+                    tables.len()
+                )));
+            }
+        }
+        self.drop_database(name).await
+    }
+
     /// Drops a database by its ID.
     ///
     /// # Arguments
@@ -897,6 +1062,93 @@ pub trait DatabaseEngine: Send + Sync {
     /// `Some(TableConfig)` if found, `None` otherwise
     async fn get_table_config_by_id(&self, table_id: TableId) -> Result<Option<TableConfig>>;
 
+    /// Changes the secondary indexes of a table.
+    ///
+    /// Indexes are dropped before new ones are created, so an index can be
+    /// recreated in one call.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_name` - Parent database name
+    /// * `table_name` - Table name
+    /// * `changes` - Indexes to add and remove
+    ///
+    /// # Returns
+    ///
+    /// The updated configuration
+    ///
+    /// # Errors
+    ///
+    /// - `Error::NotFound` if the table or an index to remove doesn't exist
+    /// - `Error::AlreadyExists` if an index to add already exists
+    /// - `Error::InvalidArgument` if `changes.primary_key` differs from the
+    ///   table's primary key, or an index name is invalid
+    async fn reconfigure_table(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        changes: &TableReconfigure,
+    ) -> Result<TableConfig>;
+
+    /// Sets or clears the JSON Schema documents of a table must match.
+    ///
+    /// Once set, every write to the table is validated and documents that
+    /// don't match are rejected with `Error::InvalidArgument`. Documents
+    /// already in the table are not checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_name` - Parent database name
+    /// * `table_name` - Table name
+    /// * `schema` - The schema, or `None` to accept any document again
+    ///
+    /// # Errors
+    ///
+    /// - `Error::NotFound` if the table doesn't exist
+    /// - `Error::InvalidArgument` if `schema` is not a valid JSON Schema
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rethinkdb::storage::{DatabaseEngine, DefaultStorageEngine};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let engine = DefaultStorageEngine::new("test.db").await?;
+    /// # engine.create_database("mydb").await?;
+    /// # engine.create_table("mydb", "users").await?;
+    /// let schema = serde_json::json!({
+    ///     "type": "object",
+    ///     "properties": {"age": {"type": "integer"}},
+    ///     "required": ["age"]
+    /// });
+    /// engine.set_table_schema("mydb", "users", Some(schema)).await?;
+    ///
+    /// let doc = br#"{"id": "alice", "age": "old"}"#.to_vec();
+    /// assert!(engine.set_document("mydb", "users", b"alice", doc).await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn set_table_schema(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        schema: Option<serde_json::Value>,
+    ) -> Result<()>;
+
+    /// Retrieves the JSON Schema of a table.
+    ///
+    /// # Returns
+    ///
+    /// `Some(schema)` if one is set, `None` otherwise
+    ///
+    /// # Errors
+    ///
+    /// `Error::NotFound` if the table doesn't exist
+    async fn get_table_schema(
+        &self,
+        db_name: &str,
+        table_name: &str,
+    ) -> Result<Option<serde_json::Value>>;
+
     /// Checks if a table exists in a database.
     ///
     /// # Arguments
@@ -1004,6 +1256,31 @@ pub trait DatabaseEngine: Send + Sync {
         value: Vec<u8>,
     ) -> Result<()>;
 
+    /// Inserts or updates a document that expires after `ttl_seconds`.
+    ///
+    /// Expired documents are no longer returned by [`get_document`] or
+    /// table scans and are deleted lazily or by the background sweeper.
+    /// `None` behaves like [`set_document`]. Engines without expiry support
+    /// keep the default, which rejects a TTL.
+    ///
+    /// [`get_document`]: DatabaseEngine::get_document
+    /// [`set_document`]: DatabaseEngine::set_document
+    async fn set_document_with_ttl(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        key: &[u8],
+        value: Vec<u8>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        match ttl_seconds {
+            None => self.set_document(db_name, table_name, key, value).await,
+            Some(_) => Err(Error::InvalidArgument(
+                "This engine does not support document TTLs".to_string(),
+            )),
+        }
+    }
+
     /// Deletes a document by its primary key.
     ///
     /// If the document doesn't exist, this is a no-op (no error).
@@ -1028,8 +1305,78 @@ pub trait DatabaseEngine: Send + Sync {
     /// ```
     async fn delete_document(&self, db_name: &str, table_name: &str, key: &[u8]) -> Result<()>;
 
+    /// Reads the documents whose primary keys lie between `start` and `end`.
+    ///
+    /// Keys are compared as bytes, the way [`get_document`] takes them, and
+    /// documents come back in key order.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_name` - Database name
+    /// * `table_name` - Table name
+    /// * `start` - Lower end of the key range
+    /// * `end` - Upper end of the key range
+    ///
+    /// # Returns
+    ///
+    /// The documents in the range, JSON encoded
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rethinkdb::storage::{DatabaseEngine, DefaultStorageEngine};
+    /// # use std::ops::Bound;
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let engine = DefaultStorageEngine::new("test.db").await?;
+    /// # engine.create_database("mydb").await?;
+    /// # engine.create_table("mydb", "users").await?;
+    /// // Users from "a" up to, but not including, "m"
+    /// let docs = engine.scan_range(
+    ///     "mydb",
+    ///     "users",
+    ///     Bound::Included(&b"a"[..]),
+    ///     Bound::Excluded(&b"m"[..]),
+    /// ).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get_document`]: DatabaseEngine::get_document
+    async fn scan_range(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>>;
+
     /// Count documents in a table
     async fn count_documents(&self, db_name: &str, table_name: &str) -> Result<u64>;
+
+    /// Writes several documents of one table together, for bulk loads.
+    ///
+    /// Engines that can commit many writes at once override this so that
+    /// the documents are written in one storage batch, all or none. The
+    /// default writes them one at a time through [`set_document`].
+    ///
+    /// # Arguments
+    ///
+    /// * `db_name` - Database name
+    /// * `table_name` - Table name
+    /// * `docs` - Primary keys and JSON-encoded documents
+    ///
+    /// [`set_document`]: DatabaseEngine::set_document
+    async fn set_documents(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        docs: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        for (key, value) in docs {
+            self.set_document(db_name, table_name, &key, value).await?;
+        }
+        Ok(())
+    }
 }
 
 /// Name validation for databases and tables
@@ -0,0 +1,941 @@
+//! `DatabaseEngine` implementation on top of a shared [`Storage`].
+//!
+//! Catalog entries use the same key layout as the storage engines, so
+//! databases and tables created here are visible to ReQL queries and
+//! vice versa:
+//!
+//! ```text
+//! __meta__:databases:{db}        → {id, name, created_at}
+//! __meta__:tables:{db}.{table}   → {id, name, db, database_id, primary_key, created_at, doc_count, indexes, compound_indexes, schema}
+//! doc:{db}:{table}:{key}         → document
+//! ```
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::cluster::metrics;
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::database::{
+    validate_name, DatabaseConfig, DatabaseEngine, DatabaseId, TableConfig, TableId,
+    TableReconfigure,
+};
+use crate::storage::engine::Storage;
+use crate::storage::{index, schema, ttl};
+
+/// Database hierarchy backed by a [`Storage`] instance.
+///
+/// Cheap to construct; all handles created from the same `Arc<Storage>`
+/// share one underlying engine.
+pub struct StorageDatabaseEngine {
+    storage: Arc<Storage>,
+}
+
+impl StorageDatabaseEngine {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Engine over a fresh [`Storage::in_memory`] store
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(Storage::in_memory()))
+    }
+
+    fn database_key(name: &str) -> String {
+        format!("__meta__:databases:{}", name)
+    }
+
+    fn table_key(db_name: &str, table_name: &str) -> String {
+        format!("__meta__:tables:{}.{}", db_name, table_name)
+    }
+
+    fn document_key(db_name: &str, table_name: &str, key: &[u8]) -> String {
+        format!(
+            "doc:{}:{}:{}",
+            db_name,
+            table_name,
+            String::from_utf8_lossy(key)
+        )
+    }
+
+    fn parse_uuid(obj: &HashMap<String, Datum>, field: &str) -> Option<Uuid> {
+        obj.get(field)
+            .and_then(|d| d.as_string())
+            .and_then(|s| Uuid::parse_str(s).ok())
+    }
+
+    fn database_to_datum(config: &DatabaseConfig) -> Datum {
+        Datum::Object(
+            vec![
+                ("id".to_string(), Datum::String(config.id.to_string())),
+                ("name".to_string(), Datum::String(config.name.clone())),
+                (
+                    "created_at".to_string(),
+                    Datum::Number(config.created_at as f64),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+
+    /// Load a database config, assigning an id to entries written by the
+    /// plain storage engine (which only records the name).
+    async fn load_database(&self, name: &str) -> Result<Option<DatabaseConfig>> {
+        let key = Self::database_key(name);
+        let obj = match self.storage.get(key.as_bytes()).await? {
+            Some(Datum::Object(obj)) => obj,
+            Some(_) => {
+                return Err(Error::Storage(format!(
+                    "Database metadata for '{}' is not an object",
+                    name
+                )))
+            }
+            None => return Ok(None),
+        };
+
+        match Self::parse_uuid(&obj, "id") {
+            Some(id) => Ok(Some(DatabaseConfig {
+                id: DatabaseId::from_uuid(id),
+                name: name.to_string(),
+                created_at: obj
+                    .get("created_at")
+                    .and_then(|d| d.as_number())
+                    .unwrap_or(0.0) as u64,
+            })),
+            None => {
+                let config = DatabaseConfig::new(name.to_string());
+                self.storage
+                    .set(key.as_bytes(), Self::database_to_datum(&config))
+                    .await?;
+                Ok(Some(config))
+            }
+        }
+    }
+
+    /// Load a table config, assigning ids to entries written by the plain
+    /// storage engine.
+    async fn load_table(&self, db_name: &str, table_name: &str) -> Result<Option<TableConfig>> {
+        let key = Self::table_key(db_name, table_name);
+        let obj = match self.storage.get(key.as_bytes()).await? {
+            Some(Datum::Object(obj)) => obj,
+            Some(_) => {
+                return Err(Error::Storage(format!(
+                    "Table metadata for '{}.{}' is not an object",
+                    db_name, table_name
+                )))
+            }
+            None => return Ok(None),
+        };
+
+        let database_id = match self.load_database(db_name).await? {
+            Some(db) => db.id,
+            None => return Ok(None),
+        };
+
+        let config = TableConfig {
+            id: Self::parse_uuid(&obj, "id")
+                .map(TableId::from_uuid)
+                .unwrap_or_default(),
+            name: table_name.to_string(),
+            database_id,
+            primary_key: obj
+                .get("primary_key")
+                .and_then(|d| d.as_string())
+                .unwrap_or("id")
+                .to_string(),
+            created_at: obj
+                .get("created_at")
+                .and_then(|d| d.as_number())
+                .unwrap_or(0.0) as u64,
+            doc_count: obj
+                .get("doc_count")
+                .and_then(|d| d.as_number())
+                .unwrap_or(0.0) as u64,
+            indexes: obj
+                .get("indexes")
+                .and_then(|d| d.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|d| d.as_string().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            compound_indexes: index::compound_from_datum(obj.get("compound_indexes")),
+            schema: obj.get("schema").cloned().map(serde_json::Value::from),
+        };
+
+        let stale = Self::parse_uuid(&obj, "id").is_none()
+            || Self::parse_uuid(&obj, "database_id") != Some(database_id.as_uuid());
+        if stale {
+            self.storage
+                .set(key.as_bytes(), config.to_datum(db_name))
+                .await?;
+        }
+
+        Ok(Some(config))
+    }
+
+    async fn require_table(&self, db_name: &str, table_name: &str) -> Result<TableConfig> {
+        self.load_table(db_name, table_name)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Table '{}.{}' not found", db_name, table_name)))
+    }
+}
+
+#[async_trait]
+impl DatabaseEngine for StorageDatabaseEngine {
+    async fn create_database(&self, name: &str) -> Result<DatabaseId> {
+        validate_name(name)?;
+
+        if self.database_exists(name).await? {
+            return Err(Error::AlreadyExists(format!(
+                "Database '{}' already exists",
+                name
+            )));
+        }
+
+        let config = DatabaseConfig::new(name.to_string());
+        self.storage
+            .set(
+                Self::database_key(name).as_bytes(),
+                Self::database_to_datum(&config),
+            )
+            .await?;
+        Ok(config.id)
+    }
+
+    async fn drop_database(&self, name: &str) -> Result<()> {
+        if !self.database_exists(name).await? {
+            return Err(Error::NotFound(format!("Database '{}' not found", name)));
+        }
+
+        for table in self.list_tables(name).await? {
+            self.drop_table(name, &table).await?;
+        }
+        self.storage
+            .delete(Self::database_key(name).as_bytes())
+            .await
+    }
+
+    async fn drop_database_by_id(&self, id: DatabaseId) -> Result<()> {
+        match self.get_database_config_by_id(id).await? {
+            Some(config) => self.drop_database(&config.name).await,
+            None => Err(Error::NotFound(format!("Database '{}' not found", id))),
+        }
+    }
+
+    async fn list_databases(&self) -> Result<Vec<String>> {
+        let mut names = self.storage.list_databases().await?;
+        names.sort();
+        Ok(names)
+    }
+
+    async fn get_database_config(&self, name: &str) -> Result<Option<DatabaseConfig>> {
+        self.load_database(name).await
+    }
+
+    async fn get_database_config_by_id(&self, id: DatabaseId) -> Result<Option<DatabaseConfig>> {
+        for name in self.storage.list_databases().await? {
+            if let Some(config) = self.load_database(&name).await? {
+                if config.id == id {
+                    return Ok(Some(config));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn database_exists(&self, name: &str) -> Result<bool> {
+        let key = Self::database_key(name);
+        Ok(self.storage.get(key.as_bytes()).await?.is_some())
+    }
+
+    async fn create_table(&self, db_name: &str, table_name: &str) -> Result<TableId> {
+        self.create_table_with_pk(db_name, table_name, "id").await
+    }
+
+    async fn create_table_with_pk(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        primary_key: &str,
+    ) -> Result<TableId> {
+        validate_name(table_name)?;
+
+        let database = self
+            .load_database(db_name)
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Database '{}' not found", db_name)))?;
+
+        if self.table_exists(db_name, table_name).await? {
+            return Err(Error::AlreadyExists(format!(
+                "Table '{}.{}' already exists",
+                db_name, table_name
+            )));
+        }
+
+        let config = TableConfig::new(table_name.to_string(), database.id)
+            .with_primary_key(primary_key.to_string());
+        self.storage
+            .set(
+                Self::table_key(db_name, table_name).as_bytes(),
+                config.to_datum(db_name),
+            )
+            .await?;
+        Ok(config.id)
+    }
+
+    async fn drop_table(&self, db_name: &str, table_name: &str) -> Result<()> {
+        let config = self.require_table(db_name, table_name).await?;
+
+        for doc in self.storage.scan_table(db_name, table_name).await? {
+            let key = match doc.as_object().and_then(|obj| obj.get(&config.primary_key)) {
+                Some(Datum::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => continue,
+            };
+            self.storage
+                .delete(Self::document_key(db_name, table_name, key.as_bytes()).as_bytes())
+                .await?;
+        }
+
+        self.storage
+            .delete(Self::table_key(db_name, table_name).as_bytes())
+            .await?;
+        metrics::forget_table(db_name, table_name);
+        Ok(())
+    }
+
+    async fn list_tables(&self, db_name: &str) -> Result<Vec<String>> {
+        if !self.database_exists(db_name).await? {
+            return Err(Error::NotFound(format!("Database '{}' not found", db_name)));
+        }
+
+        let mut names = self.storage.list_tables_in_db(db_name).await?;
+        names.sort();
+        Ok(names)
+    }
+
+    async fn get_table_config(
+        &self,
+        db_name: &str,
+        table_name: &str,
+    ) -> Result<Option<TableConfig>> {
+        self.load_table(db_name, table_name).await
+    }
+
+    async fn get_table_config_by_id(&self, table_id: TableId) -> Result<Option<TableConfig>> {
+        for db_name in self.storage.list_databases().await? {
+            for table_name in self.storage.list_tables_in_db(&db_name).await? {
+                if let Some(config) = self.load_table(&db_name, &table_name).await? {
+                    if config.id == table_id {
+                        return Ok(Some(config));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn reconfigure_table(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        changes: &TableReconfigure,
+    ) -> Result<TableConfig> {
+        let mut config = self.require_table(db_name, table_name).await?;
+
+        if let Some(primary_key) = &changes.primary_key {
+            if *primary_key != config.primary_key {
+                return Err(Error::InvalidArgument(format!(
+                    "Cannot change the primary key of table '{}.{}' from '{}' to '{}': \
+                     the table must be recreated",
+                    db_name, table_name, config.primary_key, primary_key
+                )));
+            }
+        }
+
+        let mut removed = Vec::with_capacity(changes.remove_indexes.len());
+        for index in &changes.remove_indexes {
+            let position = config
+                .indexes
+                .iter()
+                .position(|existing| existing == index)
+                .ok_or_else(|| {
+                    Error::NotFound(format!(
+                        "Index '{}' does not exist on table '{}.{}'",
+                        index, db_name, table_name
+                    ))
+                })?;
+            config.indexes.remove(position);
+            config.compound_indexes.remove(index);
+            removed.push(index);
+        }
+
+        let added = changes
+            .add_indexes
+            .iter()
+            .chain(changes.add_compound_indexes.keys());
+        for index in added {
+            validate_name(index)?;
+            if config.indexes.contains(index) {
+                return Err(Error::AlreadyExists(format!(
+                    "Index '{}' already exists on table '{}.{}'",
+                    index, db_name, table_name
+                )));
+            }
+            if let Some(fields) = changes.add_compound_indexes.get(index) {
+                if fields.len() < 2 {
+                    return Err(Error::InvalidArgument(format!(
+                        "Compound index '{}' needs at least two fields",
+                        index
+                    )));
+                }
+                config
+                    .compound_indexes
+                    .insert(index.clone(), fields.clone());
+            }
+            config.indexes.push(index.clone());
+        }
+
+        self.storage
+            .set(
+                Self::table_key(db_name, table_name).as_bytes(),
+                config.to_datum(db_name),
+            )
+            .await?;
+
+        for index in removed {
+            self.storage
+                .remove_index(db_name, table_name, index)
+                .await?;
+        }
+        let added = changes
+            .add_indexes
+            .iter()
+            .chain(changes.add_compound_indexes.keys());
+        for index in added {
+            self.storage.build_index(db_name, table_name, index).await?;
+        }
+        Ok(config)
+    }
+
+    async fn set_table_schema(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        schema: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let mut config = self.require_table(db_name, table_name).await?;
+        if let Some(schema) = &schema {
+            schema::compile(schema)?;
+        }
+        config.schema = schema;
+        self.storage
+            .set(
+                Self::table_key(db_name, table_name).as_bytes(),
+                config.to_datum(db_name),
+            )
+            .await
+    }
+
+    async fn get_table_schema(
+        &self,
+        db_name: &str,
+        table_name: &str,
+    ) -> Result<Option<serde_json::Value>> {
+        Ok(self.require_table(db_name, table_name).await?.schema)
+    }
+
+    async fn table_exists(&self, db_name: &str, table_name: &str) -> Result<bool> {
+        let key = Self::table_key(db_name, table_name);
+        Ok(self.storage.get(key.as_bytes()).await?.is_some())
+    }
+
+    async fn get_document(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        self.require_table(db_name, table_name).await?;
+
+        let doc_key = Self::document_key(db_name, table_name, key);
+        let result = self.storage.get(doc_key.as_bytes()).await;
+        metrics::record_table_read(db_name, table_name, result.is_ok());
+        match result? {
+            Some(datum) => {
+                let json: serde_json::Value = datum.into();
+                let bytes = serde_json::to_vec(&json)
+                    .map_err(|e| Error::SerializationError(e.to_string()))?;
+                Ok(Some(bytes))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn set_document(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<()> {
+        self.set_document_with_ttl(db_name, table_name, key, value, None)
+            .await
+    }
+
+    async fn set_document_with_ttl(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        key: &[u8],
+        value: Vec<u8>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        self.require_table(db_name, table_name).await?;
+
+        let json: serde_json::Value = serde_json::from_slice(&value)
+            .map_err(|e| Error::InvalidArgument(format!("Invalid document JSON: {}", e)))?;
+        let doc = Datum::from(json);
+        ttl::check_reserved(&doc)?;
+        let doc_key = Self::document_key(db_name, table_name, key);
+        let result = match ttl_seconds {
+            Some(secs) => {
+                self.storage
+                    .set_with_ttl(doc_key.as_bytes(), doc, Duration::from_secs(secs))
+                    .await
+            }
+            None => self.storage.set(doc_key.as_bytes(), doc).await,
+        };
+        metrics::record_table_write(db_name, table_name, result.is_ok());
+        result
+    }
+
+    async fn delete_document(&self, db_name: &str, table_name: &str, key: &[u8]) -> Result<()> {
+        self.require_table(db_name, table_name).await?;
+
+        let doc_key = Self::document_key(db_name, table_name, key);
+        let result = self.storage.delete(doc_key.as_bytes()).await;
+        metrics::record_table_write(db_name, table_name, result.is_ok());
+        result
+    }
+
+    async fn scan_range(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>> {
+        self.require_table(db_name, table_name).await?;
+
+        let result = self
+            .storage
+            .scan_range(db_name, table_name, start, end)
+            .await;
+        metrics::record_table_read(db_name, table_name, result.is_ok());
+        result?
+            .into_iter()
+            .map(|(_, doc)| {
+                let json: serde_json::Value = doc.into();
+                serde_json::to_vec(&json).map_err(|e| Error::SerializationError(e.to_string()))
+            })
+            .collect()
+    }
+
+    async fn count_documents(&self, db_name: &str, table_name: &str) -> Result<u64> {
+        self.require_table(db_name, table_name).await?;
+
+        let count = self.storage.scan_table(db_name, table_name).await?.len() as u64;
+        metrics::set_table_rows(db_name, table_name, count);
+        Ok(count)
+    }
+
+    async fn set_documents(
+        &self,
+        db_name: &str,
+        table_name: &str,
+        docs: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        self.require_table(db_name, table_name).await?;
+
+        // One transaction per call, so other clients' writes never join it
+        let mut txn = self.storage.transaction();
+        for (key, value) in docs {
+            let json: serde_json::Value = serde_json::from_slice(&value)
+                .map_err(|e| Error::InvalidArgument(format!("Invalid document JSON: {}", e)))?;
+            let doc = Datum::from(json);
+            ttl::check_reserved(&doc)?;
+            let doc_key = Self::document_key(db_name, table_name, &key);
+            txn.set(doc_key.as_bytes(), doc);
+        }
+        let result = txn.commit().await;
+        metrics::record_table_write(db_name, table_name, result.is_ok());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{DropMode, MockStorage};
+
+    fn engine() -> StorageDatabaseEngine {
+        StorageDatabaseEngine::new(Arc::new(Storage::new(Box::new(MockStorage::new()))))
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_table_indexes() {
+        let engine = engine();
+        engine.create_database("app").await.unwrap();
+        engine.create_table("app", "users").await.unwrap();
+
+        let add = |indexes: &[&str]| TableReconfigure {
+            add_indexes: indexes.iter().map(|i| i.to_string()).collect(),
+            ..Default::default()
+        };
+        let config = engine
+            .reconfigure_table("app", "users", &add(&["email", "age"]))
+            .await
+            .unwrap();
+        assert_eq!(config.indexes, vec!["email", "age"]);
+
+        // Changes are persisted and visible through the plain storage view
+        let stored = engine.get_table_config("app", "users").await.unwrap().unwrap();
+        assert_eq!(stored.indexes, vec!["email", "age"]);
+        let info = engine.storage.get_table_info("app.users").await.unwrap().unwrap();
+        assert_eq!(info.indexes, vec!["email", "age"]);
+
+        let config = engine
+            .reconfigure_table(
+                "app",
+                "users",
+                &TableReconfigure {
+                    remove_indexes: vec!["email".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(config.indexes, vec!["age"]);
+
+        assert!(matches!(
+            engine.reconfigure_table("app", "users", &add(&["age"])).await,
+            Err(Error::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            engine.reconfigure_table("app", "users", &add(&["bad-name"])).await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            engine.reconfigure_table("app", "missing", &add(&["age"])).await,
+            Err(Error::NotFound(_))
+        ));
+
+        // The primary key is fixed once the table exists
+        let rekey = |primary_key: &str| TableReconfigure {
+            primary_key: Some(primary_key.to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            engine.reconfigure_table("app", "users", &rekey("email")).await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(engine.reconfigure_table("app", "users", &rekey("id")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_table_schema() {
+        let engine = engine();
+        engine.create_database("app").await.unwrap();
+        engine.create_table("app", "users").await.unwrap();
+        assert_eq!(engine.get_table_schema("app", "users").await.unwrap(), None);
+
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"age": {"type": "integer"}},
+            "required": ["age"]
+        });
+        engine
+            .set_table_schema("app", "users", Some(schema.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.get_table_schema("app", "users").await.unwrap(),
+            Some(schema)
+        );
+
+        let valid = br#"{"id": "a", "age": 30}"#.to_vec();
+        engine
+            .set_document("app", "users", b"a", valid)
+            .await
+            .unwrap();
+
+        let invalid = br#"{"id": "b", "age": "thirty"}"#.to_vec();
+        match engine.set_document("app", "users", b"b", invalid).await {
+            Err(Error::InvalidArgument(message)) => assert!(message.contains("`/age`")),
+            other => panic!("expected a schema violation, got {:?}", other),
+        }
+        assert!(engine
+            .get_document("app", "users", b"b")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Invalid schemas are refused, and clearing the schema lifts the check
+        assert!(matches!(
+            engine
+                .set_table_schema("app", "users", Some(serde_json::json!({"type": 5})))
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+        engine.set_table_schema("app", "users", None).await.unwrap();
+        let untyped = br#"{"id": "b", "age": "thirty"}"#.to_vec();
+        engine
+            .set_document("app", "users", b"b", untyped)
+            .await
+            .unwrap();
+        assert!(matches!(
+            engine.set_table_schema("app", "missing", None).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_engines_are_isolated() {
+        let first = StorageDatabaseEngine::in_memory();
+        let second = StorageDatabaseEngine::in_memory();
+
+        first.create_database("app").await.unwrap();
+        first
+            .create_table_with_pk("app", "users", "email")
+            .await
+            .unwrap();
+        for email in ["a@example.com", "b@example.com"] {
+            let doc = format!(r#"{{"email": "{}"}}"#, email).into_bytes();
+            first
+                .set_document("app", "users", email.as_bytes(), doc)
+                .await
+                .unwrap();
+        }
+        first
+            .delete_document("app", "users", b"b@example.com")
+            .await
+            .unwrap();
+        assert_eq!(first.count_documents("app", "users").await.unwrap(), 1);
+        assert_eq!(
+            first
+                .get_table_config("app", "users")
+                .await
+                .unwrap()
+                .unwrap()
+                .primary_key,
+            "email"
+        );
+
+        assert!(!second.database_exists("app").await.unwrap());
+        assert!(second.list_databases().await.unwrap().is_empty());
+        second.create_database("app").await.unwrap();
+        second.create_table("app", "users").await.unwrap();
+        assert_eq!(second.count_documents("app", "users").await.unwrap(), 0);
+        assert_eq!(first.count_documents("app", "users").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_database_lifecycle() {
+        let engine = engine();
+
+        let id = engine.create_database("app").await.unwrap();
+        assert!(engine.database_exists("app").await.unwrap());
+        assert_eq!(
+            engine.get_database_config("app").await.unwrap().unwrap().id,
+            id
+        );
+        assert_eq!(
+            engine
+                .get_database_config_by_id(id)
+                .await
+                .unwrap()
+                .unwrap()
+                .name,
+            "app"
+        );
+
+        assert!(matches!(
+            engine.create_database("app").await,
+            Err(Error::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            engine.create_database("1bad").await,
+            Err(Error::InvalidArgument(_))
+        ));
+
+        engine.drop_database("app").await.unwrap();
+        assert!(engine.list_databases().await.unwrap().is_empty());
+        assert!(matches!(
+            engine.drop_database("app").await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_drop_database_restrict_and_cascade() {
+        let engine = engine();
+        engine.create_database("app").await.unwrap();
+        engine.create_table("app", "users").await.unwrap();
+        let doc = br#"{"id": "a", "name": "Alice"}"#.to_vec();
+        engine
+            .set_document("app", "users", b"a", doc)
+            .await
+            .unwrap();
+
+        // Restrict refuses and leaves everything in place
+        assert!(matches!(
+            engine.drop_database_with("app", DropMode::Restrict).await,
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(engine.database_exists("app").await.unwrap());
+        assert!(engine.table_exists("app", "users").await.unwrap());
+        assert!(engine
+            .get_document("app", "users", b"a")
+            .await
+            .unwrap()
+            .is_some());
+
+        // Cascade takes the tables and documents along
+        engine
+            .drop_database_with("app", DropMode::Cascade)
+            .await
+            .unwrap();
+        assert!(!engine.database_exists("app").await.unwrap());
+        assert!(!engine.table_exists("app", "users").await.unwrap());
+        assert!(engine
+            .storage
+            .get(StorageDatabaseEngine::document_key("app", "users", b"a").as_bytes())
+            .await
+            .unwrap()
+            .is_none());
+
+        // Empty databases drop either way
+        engine.create_database("empty").await.unwrap();
+        engine
+            .drop_database_with("empty", DropMode::Restrict)
+            .await
+            .unwrap();
+        assert!(matches!(
+            engine.drop_database_with("empty", DropMode::Restrict).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tables_and_documents() {
+        let engine = engine();
+        let db_id = engine.create_database("app").await.unwrap();
+
+        assert!(matches!(
+            engine.create_table("missing", "users").await,
+            Err(Error::NotFound(_))
+        ));
+
+        let table_id = engine
+            .create_table_with_pk("app", "users", "email")
+            .await
+            .unwrap();
+        let config = engine
+            .get_table_config("app", "users")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.id, table_id);
+        assert_eq!(config.database_id, db_id);
+        assert_eq!(config.primary_key, "email");
+
+        let doc = br#"{"email": "a@example.com", "name": "Alice"}"#.to_vec();
+        engine
+            .set_document("app", "users", b"a@example.com", doc)
+            .await
+            .unwrap();
+        let stored = engine
+            .get_document("app", "users", b"a@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        let stored: serde_json::Value = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(stored["name"], "Alice");
+        assert_eq!(engine.count_documents("app", "users").await.unwrap(), 1);
+
+        engine.drop_database("app").await.unwrap();
+        assert!(!engine.table_exists("app", "users").await.unwrap());
+        engine.create_database("app").await.unwrap();
+        engine.create_table("app", "users").await.unwrap();
+        assert_eq!(engine.count_documents("app", "users").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_table_metrics() {
+        use crate::cluster::metrics::{READS_TOTAL, ROWS_COUNT, WRITES_TOTAL};
+
+        let engine = engine();
+        engine.create_database("metrics_db").await.unwrap();
+        engine.create_table("metrics_db", "events").await.unwrap();
+        let counter = |metric: &prometheus::IntCounterVec, status| {
+            metric
+                .with_label_values(&["metrics_db", "events", status])
+                .get()
+        };
+
+        for id in 0..3 {
+            let doc = format!(r#"{{"id": "{}"}}"#, id).into_bytes();
+            engine
+                .set_document("metrics_db", "events", id.to_string().as_bytes(), doc)
+                .await
+                .unwrap();
+        }
+        engine
+            .get_document("metrics_db", "events", b"0")
+            .await
+            .unwrap();
+        engine
+            .get_document("metrics_db", "events", b"missing")
+            .await
+            .unwrap();
+        engine
+            .delete_document("metrics_db", "events", b"2")
+            .await
+            .unwrap();
+
+        assert_eq!(counter(&WRITES_TOTAL, "success"), 4);
+        assert_eq!(counter(&READS_TOTAL, "success"), 2);
+        assert_eq!(counter(&WRITES_TOTAL, "error"), 0);
+
+        let count = engine.count_documents("metrics_db", "events").await.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            ROWS_COUNT
+                .with_label_values(&["metrics_db", "events"])
+                .get(),
+            count as i64
+        );
+
+        // Unknown tables never get a series
+        assert!(engine
+            .get_document("metrics_db", "nope", b"0")
+            .await
+            .is_err());
+        assert_eq!(
+            READS_TOTAL
+                .with_label_values(&["metrics_db", "nope", "error"])
+                .get(),
+            0
+        );
+
+        engine.drop_database("metrics_db").await.unwrap();
+    }
+}
@@ -1,9 +1,21 @@
 //! Storage engine trait
 
-use crate::error::Result;
-use crate::reql::Datum;
+use crate::error::{Error, Result};
+use crate::reql::{Bound, Datum};
+use crate::storage::changes::{Change, ChangeBus};
+use crate::storage::index;
+use crate::storage::mock::MockStorage;
+use crate::storage::schema::Schemas;
+use crate::storage::slab::{SizeClassStats, StorageStats};
+use crate::storage::transaction::Transaction;
+use crate::storage::ttl;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::{Bound as KeyBound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Table metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +25,108 @@ pub struct TableInfo {
     pub primary_key: String,
     pub doc_count: u64,
     pub indexes: Vec<String>,
+    /// Fields of the compound indexes among `indexes`, by index name
+    #[serde(default)]
+    pub compound_indexes: BTreeMap<String, Vec<String>>,
+    /// JSON Schema documents must match, see [`schema`](super::schema)
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+}
+
+impl TableInfo {
+    /// Fields `index` is built from: its own name unless it is compound
+    pub fn index_fields(&self, index: &str) -> Vec<String> {
+        self.compound_indexes
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| vec![index.to_string()])
+    }
+
+    /// Parse table metadata stored as a Datum object
+    pub fn from_datum(datum: &Datum) -> Result<Self> {
+        let obj = datum
+            .as_object()
+            .ok_or_else(|| Error::Storage("Table info is not an object".to_string()))?;
+
+        let field = |name: &str| {
+            obj.get(name)
+                .and_then(|d| d.as_string())
+                .map(|s| s.to_string())
+                .ok_or_else(|| Error::Storage(format!("Missing '{}' field", name)))
+        };
+
+        Ok(Self {
+            name: field("name")?,
+            db: field("db")?,
+            primary_key: field("primary_key")?,
+            doc_count: obj
+                .get("doc_count")
+                .and_then(|d| d.as_number())
+                .unwrap_or(0.0) as u64,
+            indexes: obj
+                .get("indexes")
+                .and_then(|d| d.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|d| d.as_string().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            compound_indexes: index::compound_from_datum(obj.get("compound_indexes")),
+            schema: obj.get("schema").cloned().map(serde_json::Value::from),
+        })
+    }
+}
+
+/// Page size of the default [`StorageEngine::scan_range`]
+const RANGE_PAGE_SIZE: usize = 256;
+
+/// Lower and upper end of a range of keys
+pub(crate) type KeyRange = (KeyBound<Vec<u8>>, KeyBound<Vec<u8>>);
+
+/// Document keys of `table` whose primary keys lie between `start` and `end`
+///
+/// Both ends come back bounded, so the range never runs past the table.
+/// `None` when the range is empty, since `BTreeMap::range` panics on
+/// inverted bounds.
+pub(crate) fn document_range(
+    db: &str,
+    table: &str,
+    start: KeyBound<&[u8]>,
+    end: KeyBound<&[u8]>,
+) -> Option<KeyRange> {
+    let prefix = format!("doc:{}:{}:", db, table).into_bytes();
+    let key = |id: &[u8]| [prefix.as_slice(), id].concat();
+    let start = match start {
+        KeyBound::Included(id) => KeyBound::Included(key(id)),
+        KeyBound::Excluded(id) => KeyBound::Excluded(key(id)),
+        KeyBound::Unbounded => KeyBound::Included(prefix.clone()),
+    };
+    let end = match end {
+        KeyBound::Included(id) => KeyBound::Included(key(id)),
+        KeyBound::Excluded(id) => KeyBound::Excluded(key(id)),
+        // Every key of the table sorts before the prefix ending in ';'
+        KeyBound::Unbounded => {
+            let mut past = prefix.clone();
+            *past.last_mut().unwrap() += 1;
+            KeyBound::Excluded(past)
+        }
+    };
+    non_empty((start, end))
+}
+
+/// `range`, or `None` if no key lies within it
+pub(crate) fn non_empty(range: KeyRange) -> Option<KeyRange> {
+    let (start, end) = range;
+    let empty = match (&start, &end) {
+        (KeyBound::Included(s), KeyBound::Included(e)) => s > e,
+        (
+            KeyBound::Included(s) | KeyBound::Excluded(s),
+            KeyBound::Included(e) | KeyBound::Excluded(e),
+        ) => s >= e,
+        _ => false,
+    };
+    (!empty).then_some((start, end))
 }
 
 /// Storage engine trait
@@ -46,13 +160,153 @@ pub trait StorageEngine: Send + Sync {
     /// Drop a table
     async fn drop_table(&self, db: &str, table: &str) -> Result<()>;
     
-    /// Scan all documents in a table
+    /// Scan all documents in a table, in key order
+    ///
+    /// Document keys end in the primary key, so string primary keys come
+    /// out sorted and repeated scans of an unchanged table agree.
     async fn scan_table(&self, db: &str, table: &str) -> Result<Vec<Datum>>;
+
+    /// Scan up to `limit` documents of a table in key order, starting after `after`
+    ///
+    /// Returns `(key, document)` pairs; pass the last key back as `after`
+    /// to fetch the next page.
+    async fn scan_table_page(
+        &self,
+        db: &str,
+        table: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Datum)>>;
+
+    /// Documents of a table whose primary keys lie between `start` and
+    /// `end`, in key order
+    ///
+    /// Primary keys are compared as the bytes of their document keys.
+    /// Returns `(key, document)` pairs like [`scan_table_page`]. The default
+    /// pages through the table from its start; engines with an ordered key
+    /// index read only the range.
+    ///
+    /// [`scan_table_page`]: StorageEngine::scan_table_page
+    async fn scan_range(
+        &self,
+        db: &str,
+        table: &str,
+        start: KeyBound<&[u8]>,
+        end: KeyBound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let Some(range) = document_range(db, table, start, end) else {
+            return Ok(Vec::new());
+        };
+        let mut docs = Vec::new();
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let page = self
+                .scan_table_page(db, table, after.as_deref(), RANGE_PAGE_SIZE)
+                .await?;
+            let done = page.len() < RANGE_PAGE_SIZE;
+            for (key, doc) in page {
+                after = Some(key.clone());
+                let past_end = match &range.1 {
+                    KeyBound::Included(end) => key > *end,
+                    KeyBound::Excluded(end) => key >= *end,
+                    KeyBound::Unbounded => false,
+                };
+                if past_end {
+                    return Ok(docs);
+                }
+                if RangeBounds::<Vec<u8>>::contains(&range, &key) {
+                    docs.push((key, doc));
+                }
+            }
+            if done {
+                return Ok(docs);
+            }
+        }
+    }
+
+    /// Entries whose keys lie between `start` and `end`, in key order
+    ///
+    /// Reads keys other than documents, e.g. secondary index entries.
+    async fn scan_keys(
+        &self,
+        start: KeyBound<&[u8]>,
+        end: KeyBound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>>;
+
+    /// Number of keys between `start` and `end`
+    ///
+    /// The default reads the entries; engines with a key index override
+    /// this to count without reading any values.
+    async fn count_keys(&self, start: KeyBound<&[u8]>, end: KeyBound<&[u8]>) -> Result<u64> {
+        Ok(self.scan_keys(start, end).await?.len() as u64)
+    }
+
+    /// Apply several writes; `None` deletes the key
+    ///
+    /// The default applies them one by one. Engines that can commit
+    /// atomically override this so that either all writes land or none do.
+    async fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        for (key, value) in writes {
+            match value {
+                Some(value) => self.set(&key, value).await?,
+                None => self.delete(&key).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Slot, compression and cache statistics
+    ///
+    /// Only the slab engine keeps these; the default reports none.
+    async fn stats(&self) -> Option<StorageStats> {
+        None
+    }
+
+    /// Slot usage per slab size class
+    ///
+    /// Unlike [`stats`](Self::stats) this is cheap enough to poll. Only the
+    /// slab engine has size classes; the default reports none.
+    fn size_class_stats(&self) -> Vec<SizeClassStats> {
+        Vec::new()
+    }
+
+    /// Writes queued or being applied, a measure of write load
+    ///
+    /// Engines without a write queue report none; the default is 0.
+    fn pending_writes(&self) -> usize {
+        0
+    }
+
+    /// Make all acknowledged writes durable
+    ///
+    /// Engines that buffer writes override this; the default has nothing to do.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// How often tables were read in full or through an index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub full_scans: u64,
+    pub index_lookups: u64,
 }
 
 /// Main storage interface
+///
+/// Document writes also maintain the table's secondary indexes, see
+/// [`index`](super::index), and are checked against the table's schema, see
+/// [`schema`](super::schema), and are published to changefeeds, see
+/// [`changes`](super::changes).
 pub struct Storage {
     engine: Box<dyn StorageEngine>,
+    full_scans: AtomicU64,
+    index_lookups: AtomicU64,
+    schemas: Schemas,
+    changes: ChangeBus,
+    /// Serializes document writes, so a lazy delete of an expired document
+    /// cannot race a write replacing it
+    writes: tokio::sync::Mutex<()>,
 }
 
 impl std::fmt::Debug for Storage {
@@ -63,19 +317,230 @@ impl std::fmt::Debug for Storage {
 
 impl Storage {
     pub fn new(engine: Box<dyn StorageEngine>) -> Self {
-        Self { engine }
+        Self {
+            engine,
+            full_scans: AtomicU64::new(0),
+            index_lookups: AtomicU64::new(0),
+            schemas: Schemas::default(),
+            changes: ChangeBus::new(),
+            writes: tokio::sync::Mutex::new(()),
+        }
     }
 
+    /// Storage that lives only in memory and is lost when dropped
+    ///
+    /// Every call returns an independent, empty store.
+    pub fn in_memory() -> Self {
+        Self::new(Box::new(MockStorage::new()))
+    }
+
+    /// Read a key; expired documents are deleted and reported as missing
     pub async fn get(&self, key: &[u8]) -> Result<Option<Datum>> {
-        self.engine.get(key).await
+        match self.engine.get(key).await? {
+            Some(doc) if ttl::is_expired(&doc, ttl::now_millis()) => {
+                self.delete_expired(key).await?;
+                Ok(None)
+            }
+            Some(doc) => Ok(Some(ttl::strip_expiry(doc))),
+            None => Ok(None),
+        }
     }
 
     pub async fn set(&self, key: &[u8], value: Datum) -> Result<()> {
-        self.engine.set(key, value).await
+        self.write_indexed(vec![(key.to_vec(), Some(value))]).await
+    }
+
+    /// Write a document that expires `ttl` from now
+    pub async fn set_with_ttl(&self, key: &[u8], value: Datum, ttl: Duration) -> Result<()> {
+        self.write_indexed(vec![(key.to_vec(), Some(ttl::with_expiry(value, ttl)?))])
+            .await
     }
 
     pub async fn delete(&self, key: &[u8]) -> Result<()> {
-        self.engine.delete(key).await
+        self.write_indexed(vec![(key.to_vec(), None)]).await
+    }
+
+    /// Apply `writes` together with the index entries they change
+    ///
+    /// Nothing is written if a document does not match its table's schema.
+    /// A document replacing a live one that expires keeps its expiry time
+    /// unless it brings its own.
+    async fn write_indexed(&self, writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        let _writing = self.writes.lock().await;
+        self.write_locked(writes).await
+    }
+
+    /// [`write_indexed`](Self::write_indexed) with the write lock held
+    async fn write_locked(&self, mut writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        let now = ttl::now_millis();
+        for (key, value) in writes.iter_mut() {
+            if index::parse_document_key(key).is_none() {
+                continue;
+            }
+            let Some(doc) = value.take() else {
+                continue;
+            };
+            let old = match ttl::expires_at(&doc) {
+                None => self.engine.get(key).await?,
+                Some(_) => None,
+            };
+            *value = Some(match old.as_ref().and_then(ttl::expires_at) {
+                Some(at) if at > now => ttl::expiring_at(doc, at),
+                _ => doc,
+            });
+        }
+
+        self.schemas.check(&*self.engine, &writes).await?;
+        let changes = self.pending_changes(&writes).await?;
+        let entries = index::entry_writes(&*self.engine, &writes).await?;
+        if entries.is_empty() && writes.len() == 1 {
+            let (key, value) = writes.pop().unwrap();
+            match value {
+                Some(value) => self.engine.set(&key, value).await?,
+                None => self.engine.delete(&key).await?,
+            }
+        } else {
+            writes.extend(entries);
+            self.engine.write_batch(writes).await?;
+        }
+        for change in changes {
+            self.changes.publish(change);
+        }
+        Ok(())
+    }
+
+    /// Delete the document under `key` if it has expired, through the
+    /// indexed write path so its index entries and changefeeds follow
+    ///
+    /// The expiry is checked again under the write lock, so a document
+    /// written since it was read as expired survives. Returns whether the
+    /// document was deleted.
+    async fn delete_expired(&self, key: &[u8]) -> Result<bool> {
+        let _writing = self.writes.lock().await;
+        match self.engine.get(key).await? {
+            Some(doc) if ttl::is_expired(&doc, ttl::now_millis()) => {
+                self.write_locked(vec![(key.to_vec(), None)]).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The changes `writes` make to documents of watched tables
+    async fn pending_changes(&self, writes: &[(Vec<u8>, Option<Datum>)]) -> Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        for (key, value) in writes {
+            let Some((db, table)) = index::parse_document_key(key) else {
+                continue;
+            };
+            if !self.changes.is_watched(db, table) {
+                continue;
+            }
+            let old_val = self.engine.get(key).await?.map(ttl::strip_expiry);
+            let new_val = value.clone().map(ttl::strip_expiry);
+            if old_val.is_some() || new_val.is_some() {
+                changes.push(Change::new(db, table, key.clone(), old_val, new_val));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Changefeed subscriptions to document writes
+    pub fn changes(&self) -> &ChangeBus {
+        &self.changes
+    }
+
+    /// Live documents of `table` whose value under the secondary index
+    /// `index` equals `value`
+    ///
+    /// For a compound index `value` is the array of the indexed fields.
+    pub async fn index_lookup(
+        &self,
+        db: &str,
+        table: &str,
+        index: &str,
+        value: &Datum,
+    ) -> Result<Vec<Datum>> {
+        self.index_lookups.fetch_add(1, Ordering::Relaxed);
+        let fields = self.index_fields(db, table, index).await?;
+        let mut docs = Vec::new();
+        for id in index::lookup(&*self.engine, db, table, index, value).await? {
+            let key = index::document_key(db, table, &id);
+            // Entries can be stale; only documents still matching count
+            if let Some(doc) = self.get(&key).await? {
+                if index::index_value(&doc, &fields).as_ref() == Some(value) {
+                    docs.push(doc);
+                }
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Live documents of `table` whose value under `index` lies between
+    /// `lower` and `upper`, in index order, read through the secondary index
+    ///
+    /// Open ends exclude documents equal to the bound. Documents sharing a
+    /// value are ordered by primary key. Compound indexes order their arrays
+    /// field by field.
+    pub async fn index_range(
+        &self,
+        db: &str,
+        table: &str,
+        index: &str,
+        lower: (&Bound, bool),
+        upper: (&Bound, bool),
+    ) -> Result<Vec<Datum>> {
+        self.index_lookups.fetch_add(1, Ordering::Relaxed);
+        let fields = self.index_fields(db, table, index).await?;
+        let mut docs = Vec::new();
+        for (entry, id) in
+            index::lookup_between(&*self.engine, db, table, index, lower, upper).await?
+        {
+            let key = index::document_key(db, table, &id);
+            // Entries can be stale; only documents still under the entry's
+            // value count
+            if let Some(doc) = self.get(&key).await? {
+                let current = index::index_value(&doc, &fields)
+                    .map(|value| index::entry_key(db, table, index, &value, &id));
+                if current.as_deref() == Some(entry.as_slice()) {
+                    docs.push(doc);
+                }
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Fields the secondary index `index` of `table` is built from
+    async fn index_fields(&self, db: &str, table: &str, index: &str) -> Result<Vec<String>> {
+        Ok(
+            match self.get_table_info(&format!("{}.{}", db, table)).await? {
+                Some(info) => info.index_fields(index),
+                None => vec![index.to_string()],
+            },
+        )
+    }
+
+    /// Build the entries of a newly added index
+    pub async fn build_index(&self, db: &str, table: &str, index: &str) -> Result<()> {
+        let info = self
+            .get_table_info(&format!("{}.{}", db, table))
+            .await?
+            .ok_or_else(|| Error::NotFound(format!("Table '{}.{}' not found", db, table)))?;
+        let fields = info.index_fields(index);
+        index::build(&*self.engine, db, table, &info.primary_key, index, &fields).await
+    }
+
+    /// Delete the entries of a removed index
+    pub async fn remove_index(&self, db: &str, table: &str, index: &str) -> Result<()> {
+        index::remove(&*self.engine, db, table, index).await
+    }
+
+    /// Table reads since the storage was opened
+    pub fn scan_stats(&self) -> ScanStats {
+        ScanStats {
+            full_scans: self.full_scans.load(Ordering::Relaxed),
+            index_lookups: self.index_lookups.load(Ordering::Relaxed),
+        }
     }
 
     pub async fn list_tables(&self) -> Result<Vec<String>> {
@@ -110,7 +575,273 @@ impl Storage {
         self.engine.drop_table(db, table).await
     }
     
+    /// Scan all live documents in a table in key order, deleting expired ones
     pub async fn scan_table(&self, db: &str, table: &str) -> Result<Vec<Datum>> {
-        self.engine.scan_table(db, table).await
+        self.full_scans.fetch_add(1, Ordering::Relaxed);
+        let now = ttl::now_millis();
+        let (expired, live): (Vec<Datum>, Vec<Datum>) = self
+            .engine
+            .scan_table(db, table)
+            .await?
+            .into_iter()
+            .partition(|doc| ttl::is_expired(doc, now));
+
+        if !expired.is_empty() {
+            let primary_key = self
+                .get_table_info(&format!("{}.{}", db, table))
+                .await?
+                .map(|info| info.primary_key)
+                .unwrap_or_else(|| "id".to_string());
+            for doc in &expired {
+                let Some(id) = doc.as_object().and_then(|obj| obj.get(&primary_key)) else {
+                    continue;
+                };
+                self.delete_expired(&index::document_key(db, table, id))
+                    .await?;
+            }
+        }
+
+        Ok(live.into_iter().map(ttl::strip_expiry).collect())
+    }
+
+    /// Page through live documents of a table, deleting expired ones
+    ///
+    /// Expired documents do not count towards `limit`; a page shorter than
+    /// `limit` still means the table is exhausted.
+    pub async fn scan_table_page(
+        &self,
+        db: &str,
+        table: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let mut cursor = after.map(|key| key.to_vec());
+        let mut live = Vec::new();
+
+        while live.len() < limit {
+            let wanted = limit - live.len();
+            let page = self
+                .engine
+                .scan_table_page(db, table, cursor.as_deref(), wanted)
+                .await?;
+            let exhausted = page.len() < wanted;
+            let now = ttl::now_millis();
+
+            for (key, doc) in page {
+                if ttl::is_expired(&doc, now) {
+                    self.delete_expired(&key).await?;
+                } else {
+                    live.push((key.clone(), ttl::strip_expiry(doc)));
+                }
+                cursor = Some(key);
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(live)
+    }
+
+    /// Number of documents stored in a table, counted from their keys
+    ///
+    /// Expired documents the TTL sweeper has not removed yet are counted.
+    pub async fn count_table(&self, db: &str, table: &str) -> Result<u64> {
+        let Some((start, end)) =
+            document_range(db, table, KeyBound::Unbounded, KeyBound::Unbounded)
+        else {
+            return Ok(0);
+        };
+        self.engine
+            .count_keys(
+                start.as_ref().map(Vec::as_slice),
+                end.as_ref().map(Vec::as_slice),
+            )
+            .await
+    }
+
+    /// Live documents of a table whose primary keys lie between `start`
+    /// and `end`, in key order, deleting expired ones
+    ///
+    /// Counts as an index read, of the primary index.
+    pub async fn scan_range(
+        &self,
+        db: &str,
+        table: &str,
+        start: KeyBound<&[u8]>,
+        end: KeyBound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        self.index_lookups.fetch_add(1, Ordering::Relaxed);
+        let now = ttl::now_millis();
+        let mut live = Vec::new();
+        for (key, doc) in self.engine.scan_range(db, table, start, end).await? {
+            if ttl::is_expired(&doc, now) {
+                self.delete_expired(&key).await?;
+            } else {
+                live.push((key, ttl::strip_expiry(doc)));
+            }
+        }
+        Ok(live)
+    }
+
+    /// Delete every expired document, returning how many were removed
+    pub async fn sweep_expired(&self) -> Result<u64> {
+        let mut removed = 0;
+        for db in self.list_databases().await? {
+            for table in self.list_tables_in_db(&db).await? {
+                let mut after: Option<Vec<u8>> = None;
+                loop {
+                    let page = self
+                        .engine
+                        .scan_table_page(&db, &table, after.as_deref(), ttl::SWEEP_PAGE_SIZE)
+                        .await?;
+                    let done = page.len() < ttl::SWEEP_PAGE_SIZE;
+                    let now = ttl::now_millis();
+
+                    for (key, doc) in page {
+                        if ttl::is_expired(&doc, now) && self.delete_expired(&key).await? {
+                            removed += 1;
+                        }
+                        after = Some(key);
+                    }
+
+                    if done {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    pub async fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        self.write_indexed(writes).await
+    }
+
+    /// Make all acknowledged writes durable, e.g. before shutting down or
+    /// after a bulk load with deferred syncs
+    pub async fn flush(&self) -> Result<()> {
+        self.engine.flush().await
+    }
+
+    /// Storage statistics, or `None` if the engine does not keep any
+    pub async fn stats(&self) -> Option<StorageStats> {
+        self.engine.stats().await
+    }
+
+    /// Slot usage per slab size class, see
+    /// [`StorageEngine::size_class_stats`]
+    pub fn size_class_stats(&self) -> Vec<SizeClassStats> {
+        self.engine.size_class_stats()
+    }
+
+    /// Writes queued or being applied, see
+    /// [`StorageEngine::pending_writes`]
+    pub fn pending_writes(&self) -> usize {
+        self.engine.pending_writes()
+    }
+
+    /// Start a transaction that buffers writes until [`Transaction::commit`]
+    pub fn transaction(self: &Arc<Self>) -> Transaction {
+        Transaction::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BTreeStorage, SlabStorageEngine};
+
+    /// Check `scan_range` of `engine` against the same ranges every engine
+    /// must agree on
+    async fn check_scan_range(engine: &dyn StorageEngine) {
+        // Keys and values are kept short for the B-Tree's small page slots
+        for id in ["c", "a", "d", "b"] {
+            let key = format!("doc:a:t:{}", id);
+            engine.set(key.as_bytes(), Datum::from(id)).await.unwrap();
+        }
+        engine.set(b"doc:a:s:z", Datum::from("z")).await.unwrap();
+        engine.set(b"doc:a:u:b", Datum::from("b")).await.unwrap();
+
+        use KeyBound::{Excluded, Included, Unbounded};
+        let cases: [(KeyBound<&[u8]>, KeyBound<&[u8]>, &[&str]); 8] = [
+            (Included(b"a"), Excluded(b"c"), &["a", "b"]),
+            (Excluded(b"a"), Included(b"c"), &["b", "c"]),
+            (Included(b"b"), Unbounded, &["b", "c", "d"]),
+            (Unbounded, Included(b"b"), &["a", "b"]),
+            (Unbounded, Unbounded, &["a", "b", "c", "d"]),
+            (Included(b"b"), Included(b"b"), &["b"]),
+            (Excluded(b"b"), Excluded(b"b"), &[]),
+            (Included(b"c"), Excluded(b"a"), &[]),
+        ];
+        for (start, end, expected) in cases {
+            let docs = engine.scan_range("a", "t", start, end).await.unwrap();
+            let keys: Vec<_> = docs.iter().map(|(key, _)| key.clone()).collect();
+            let expected_keys: Vec<_> = expected
+                .iter()
+                .map(|id| format!("doc:a:t:{}", id).into_bytes())
+                .collect();
+            assert_eq!(keys, expected_keys, "range {:?}..{:?}", start, end);
+            let values: Vec<_> = docs.into_iter().map(|(_, doc)| doc).collect();
+            let expected: Vec<_> = expected.iter().map(|id| Datum::from(*id)).collect();
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_range_agrees_across_engines() {
+        check_scan_range(&MockStorage::new()).await;
+
+        let dir = std::env::temp_dir().join(format!("scan_range_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.btree").to_string_lossy().into_owned();
+        check_scan_range(&BTreeStorage::new(path, Some(10)).unwrap()).await;
+        check_scan_range(&SlabStorageEngine::with_defaults(dir.join("slab")).unwrap()).await;
+        std::fs::remove_dir_all(dir).ok();
+    }
+    #[tokio::test]
+    async fn test_expired_documents_leave_indexes() {
+        use crate::storage::changes::OverflowPolicy;
+        use crate::storage::{DatabaseEngine, StorageDatabaseEngine, TableReconfigure};
+
+        let storage = Arc::new(Storage::in_memory());
+        storage.create_database("app").await.unwrap();
+        storage.create_table("app", "sessions", "id").await.unwrap();
+        StorageDatabaseEngine::new(storage.clone())
+            .reconfigure_table(
+                "app",
+                "sessions",
+                &TableReconfigure {
+                    add_indexes: vec!["user".to_string()],
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let feed = storage
+            .changes()
+            .subscribe("app", "sessions", 8, OverflowPolicy::Disconnect);
+
+        let key = index::document_key("app", "sessions", &Datum::from("s"));
+        let doc = Datum::from(serde_json::json!({"id": "s", "user": "alice"}));
+        storage
+            .set_with_ttl(&key, doc.clone(), Duration::from_millis(20))
+            .await
+            .unwrap();
+        feed.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        // The lazy delete removes the index entry and reaches changefeeds
+        assert_eq!(storage.get(&key).await.unwrap(), None);
+        let entries = storage
+            .engine
+            .scan_keys(KeyBound::Included(b"idx:"), KeyBound::Excluded(b"idx;"))
+            .await
+            .unwrap();
+        assert!(entries.is_empty());
+        let change = feed.recv().await.unwrap();
+        assert_eq!(change.old_val, Some(doc));
+        assert_eq!(change.new_val, None);
     }
 }
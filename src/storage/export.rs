@@ -0,0 +1,283 @@
+//! Table export to JSON, NDJSON and CSV
+//!
+//! Documents are streamed with [`Storage::scan_table_page`], so a table is
+//! never loaded into memory as a whole. CSV needs the full column set before
+//! the first row is written, so it makes two passes: one collecting the union
+//! of top-level fields, one writing rows.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::engine::Storage;
+
+/// Documents fetched per scan page
+pub const EXPORT_PAGE_SIZE: usize = 1000;
+
+/// Output format for table exports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// JSON array with one document per line
+    Json,
+    /// One JSON document per line
+    Ndjson,
+    /// CSV with a header row
+    Csv,
+}
+
+impl ExportFormat {
+    /// File extension used when exporting several tables
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            other => Err(Error::InvalidArgument(format!(
+                "Unknown export format '{}' (expected json, ndjson or csv)",
+                other
+            ))),
+        }
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Storage(format!("Export write failed: {}", e))
+}
+
+/// Visit every document of a table, one page at a time
+async fn for_each_document<F>(storage: &Storage, db: &str, table: &str, mut f: F) -> Result<()>
+where
+    F: FnMut(Datum) -> Result<()>,
+{
+    let mut after: Option<Vec<u8>> = None;
+    loop {
+        let page = storage
+            .scan_table_page(db, table, after.as_deref(), EXPORT_PAGE_SIZE)
+            .await?;
+        let done = page.len() < EXPORT_PAGE_SIZE;
+
+        for (key, doc) in page {
+            after = Some(key);
+            f(doc)?;
+        }
+
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Export a single table, returning the number of documents written
+pub async fn export_table<W: Write>(
+    storage: &Storage,
+    db: &str,
+    table: &str,
+    format: ExportFormat,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut count = 0u64;
+
+    match format {
+        ExportFormat::Json => {
+            writer.write_all(b"[").map_err(io_error)?;
+            for_each_document(storage, db, table, |doc| {
+                let sep: &[u8] = if count == 0 { b"\n" } else { b",\n" };
+                writer.write_all(sep).map_err(io_error)?;
+                write_json(writer, doc)?;
+                count += 1;
+                Ok(())
+            })
+            .await?;
+            writer.write_all(b"\n]\n").map_err(io_error)?;
+        }
+        ExportFormat::Ndjson => {
+            for_each_document(storage, db, table, |doc| {
+                write_json(writer, doc)?;
+                writer.write_all(b"\n").map_err(io_error)?;
+                count += 1;
+                Ok(())
+            })
+            .await?;
+        }
+        ExportFormat::Csv => {
+            let columns = csv_columns(storage, db, table).await?;
+            write_csv_row(writer, columns.iter().map(|c| c.as_str()))?;
+
+            for_each_document(storage, db, table, |doc| {
+                let obj = doc.as_object();
+                let cells: Vec<String> = columns
+                    .iter()
+                    .map(|col| {
+                        obj.and_then(|o| o.get(col))
+                            .map(csv_cell)
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                write_csv_row(writer, cells.iter().map(|c| c.as_str()))?;
+                count += 1;
+                Ok(())
+            })
+            .await?;
+        }
+    }
+
+    writer.flush().map_err(io_error)?;
+    Ok(count)
+}
+
+fn write_json<W: Write>(writer: &mut W, doc: Datum) -> Result<()> {
+    let json: serde_json::Value = doc.into();
+    serde_json::to_writer(&mut *writer, &json).map_err(|e| Error::SerializationError(e.to_string()))
+}
+
+/// Union of top-level fields, primary key first and the rest sorted
+async fn csv_columns(storage: &Storage, db: &str, table: &str) -> Result<Vec<String>> {
+    let primary_key = storage
+        .get_table_info(&format!("{}.{}", db, table))
+        .await?
+        .map(|info| info.primary_key)
+        .unwrap_or_else(|| "id".to_string());
+
+    let mut fields = BTreeSet::new();
+    let mut has_primary_key = false;
+    for_each_document(storage, db, table, |doc| {
+        if let Some(obj) = doc.as_object() {
+            for key in obj.keys() {
+                if *key == primary_key {
+                    has_primary_key = true;
+                } else {
+                    fields.insert(key.clone());
+                }
+            }
+        }
+        Ok(())
+    })
+    .await?;
+
+    let mut columns = Vec::with_capacity(fields.len() + 1);
+    if has_primary_key {
+        columns.push(primary_key);
+    }
+    columns.extend(fields);
+    Ok(columns)
+}
+
+/// Render a field value as a CSV cell; nested values are written as JSON
+fn csv_cell(value: &Datum) -> String {
+    match value {
+        Datum::Null => String::new(),
+        Datum::Boolean(b) => b.to_string(),
+        Datum::Integer(i) => i.to_string(),
+        Datum::Number(n) => n.to_string(),
+        Datum::String(s) => s.clone(),
+        Datum::Array(_) | Datum::Object(_) => serde_json::Value::from(value.clone()).to_string(),
+    }
+}
+
+fn write_csv_row<'a, W: Write>(writer: &mut W, cells: impl Iterator<Item = &'a str>) -> Result<()> {
+    let line: Vec<String> = cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect();
+    writer
+        .write_all(line.join(",").as_bytes())
+        .map_err(io_error)?;
+    writer.write_all(b"\n").map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+
+    async fn seeded_storage(docs: usize) -> Storage {
+        let storage = Storage::new(Box::new(MockStorage::new()));
+        storage.create_database("shop").await.unwrap();
+        storage.create_table("shop", "items", "id").await.unwrap();
+
+        for i in 0..docs {
+            let doc = serde_json::json!({
+                "id": format!("item{:04}", i),
+                "price": i,
+                "label": format!("Item, \"{}\"", i),
+            });
+            let key = format!("doc:shop:items:item{:04}", i);
+            storage.set(key.as_bytes(), Datum::from(doc)).await.unwrap();
+        }
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_export_json_and_ndjson() {
+        // More than one page
+        let storage = seeded_storage(EXPORT_PAGE_SIZE + 5).await;
+        let path = std::env::temp_dir().join(format!("export_{}.json", uuid::Uuid::new_v4()));
+
+        let mut file = std::fs::File::create(&path).unwrap();
+        let count = export_table(&storage, "shop", "items", ExportFormat::Json, &mut file)
+            .await
+            .unwrap();
+        assert_eq!(count as usize, EXPORT_PAGE_SIZE + 5);
+
+        let docs: Vec<serde_json::Value> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(docs.len(), EXPORT_PAGE_SIZE + 5);
+        assert_eq!(docs[3]["id"], "item0003");
+        assert_eq!(docs[3]["price"], 3.0);
+
+        let mut out = Vec::new();
+        export_table(&storage, "shop", "items", ExportFormat::Ndjson, &mut out)
+            .await
+            .unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(lines.len(), EXPORT_PAGE_SIZE + 5);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["id"], "item0000");
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_export_csv() {
+        let storage = seeded_storage(3).await;
+        storage
+            .set(
+                b"doc:shop:items:extra",
+                Datum::from(serde_json::json!({"id": "extra", "color": "red"})),
+            )
+            .await
+            .unwrap();
+
+        let mut out = Vec::new();
+        let count = export_table(&storage, "shop", "items", ExportFormat::Csv, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(count, 4);
+
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "id,color,label,price");
+        assert_eq!(lines[1], "extra,red,,");
+        assert_eq!(lines[3], "item0001,,\"Item, \"\"1\"\"\",1");
+    }
+}
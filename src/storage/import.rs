@@ -0,0 +1,738 @@
+//! Table import from JSON, NDJSON and CSV
+//!
+//! Reads the formats produced by [`export_table`](crate::storage::export_table)
+//! and writes the rows into it. Rows are parsed one at a time; malformed rows are recorded with their line
+//! number and skipped rather than aborting the import, unless
+//! [`ImportOptions::strict`] is set. All rows are written together through
+//! [`DatabaseEngine::set_documents`], so the import is committed once at the
+//! end instead of once per row.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::BufRead;
+
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::database::DatabaseEngine;
+use crate::storage::export::ExportFormat;
+
+/// How to read and apply the rows of an import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// Leave documents whose primary key is already present untouched
+    /// instead of overwriting them
+    pub skip_existing: bool,
+    /// CSV field separator
+    pub delimiter: char,
+    /// Whether the first CSV record names the columns. Without a header,
+    /// columns are named by their position: `"0"`, `"1"`, ...
+    pub header: bool,
+    /// Turn CSV cells that read as numbers, booleans or JSON objects and
+    /// arrays into those types; otherwise every cell is a string
+    pub type_infer: bool,
+    /// Fail on the first malformed row instead of skipping it
+    pub strict: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            skip_existing: false,
+            delimiter: ',',
+            header: true,
+            type_infer: false,
+            strict: false,
+        }
+    }
+}
+
+/// A row that was skipped because it could not be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    /// Line the row starts on, or its position for a JSON array (1-based)
+    pub line: u64,
+    pub message: String,
+}
+
+/// Outcome of an import run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub inserted: u64,
+    pub skipped: u64,
+    pub errors: u64,
+    /// Malformed rows, in input order; `errors` also counts rows the
+    /// storage rejected
+    pub row_errors: Vec<RowError>,
+}
+
+impl ImportSummary {
+    /// Record a malformed row, or fail with it in strict mode
+    fn reject(&mut self, line: u64, message: String, strict: bool) -> Result<()> {
+        if strict {
+            return Err(Error::InvalidArgument(format!(
+                "Line {}: {}",
+                line, message
+            )));
+        }
+        warn!(line = line, error = %message, "Skipping malformed row");
+        self.errors += 1;
+        self.row_errors.push(RowError { line, message });
+        Ok(())
+    }
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Storage(format!("Import read failed: {}", e))
+}
+
+/// Import rows into an existing table
+///
+/// `ExportFormat::Json` accepts both a JSON array and NDJSON.
+pub async fn import_table<R: BufRead>(
+    engine: &dyn DatabaseEngine,
+    db: &str,
+    table: &str,
+    format: ExportFormat,
+    reader: R,
+    options: &ImportOptions,
+) -> Result<ImportSummary> {
+    if matches!(options.delimiter, '"' | '\r' | '\n') {
+        return Err(Error::InvalidArgument(format!(
+            "Invalid CSV delimiter {:?}",
+            options.delimiter
+        )));
+    }
+
+    let config = engine
+        .get_table_config(db, table)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Table '{}.{}' not found", db, table)))?;
+    let primary_key = config.primary_key;
+
+    let mut rows = BTreeMap::new();
+    let mut summary = import_rows(
+        engine,
+        db,
+        table,
+        &primary_key,
+        format,
+        reader,
+        options,
+        &mut rows,
+    )
+    .await?;
+
+    let docs: Vec<(Vec<u8>, Vec<u8>)> = rows.into_iter().collect();
+    if let Err(e) = engine.set_documents(db, table, docs.clone()).await {
+        if matches!(e, Error::Busy(_)) || options.strict {
+            return Err(e);
+        }
+        // Find the rows the storage rejects, e.g. for not matching the schema
+        warn!(error = %e, "Import batch rejected, writing rows one at a time");
+        for (key, doc) in docs {
+            if let Err(e) = engine.set_document(db, table, &key, doc).await {
+                warn!(key = %String::from_utf8_lossy(&key), error = %e, "Failed to import row");
+                summary.inserted -= 1;
+                summary.errors += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Rows parsed so far, by primary key; a later row replaces an earlier one
+type Rows = BTreeMap<Vec<u8>, Vec<u8>>;
+
+#[allow(clippy::too_many_arguments)]
+async fn import_rows<R: BufRead>(
+    engine: &dyn DatabaseEngine,
+    db: &str,
+    table: &str,
+    primary_key: &str,
+    format: ExportFormat,
+    mut reader: R,
+    options: &ImportOptions,
+    rows: &mut Rows,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    match format {
+        ExportFormat::Json | ExportFormat::Ndjson => {
+            let first = reader
+                .fill_buf()
+                .map_err(io_error)?
+                .iter()
+                .find(|b| !b.is_ascii_whitespace())
+                .copied();
+
+            if first == Some(b'[') {
+                // A JSON array has to be parsed as a whole
+                let docs: Vec<serde_json::Value> = serde_json::from_reader(reader)
+                    .map_err(|e| Error::InvalidArgument(format!("Invalid JSON array: {}", e)))?;
+                for (i, doc) in docs.into_iter().enumerate() {
+                    import_row(
+                        engine,
+                        db,
+                        table,
+                        primary_key,
+                        (i + 1) as u64,
+                        doc,
+                        options,
+                        rows,
+                        &mut summary,
+                    )
+                    .await?;
+                }
+            } else {
+                for (i, line) in reader.lines().enumerate() {
+                    let line_no = (i + 1) as u64;
+                    let line = line.map_err(io_error)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(&line) {
+                        Ok(doc) => {
+                            import_row(
+                                engine,
+                                db,
+                                table,
+                                primary_key,
+                                line_no,
+                                doc,
+                                options,
+                                rows,
+                                &mut summary,
+                            )
+                            .await?
+                        }
+                        Err(e) => summary.reject(
+                            line_no,
+                            format!("Invalid JSON: {}", e),
+                            options.strict,
+                        )?,
+                    }
+                }
+            }
+        }
+        ExportFormat::Csv => {
+            let mut csv = CsvReader::new(reader, options.delimiter);
+            let header = if options.header {
+                match csv.next_record()? {
+                    Some((_, Record::Fields(header))) => Some(header),
+                    Some((line_no, Record::Unterminated)) => {
+                        summary.reject(line_no, UNTERMINATED.to_string(), options.strict)?;
+                        return Ok(summary);
+                    }
+                    None => return Ok(summary),
+                }
+            } else {
+                None
+            };
+            match &header {
+                Some(header) if !header.iter().any(|column| column == primary_key) => {
+                    warn!(primary_key = %primary_key, "CSV header has no primary key column, keys will be generated");
+                }
+                _ => {}
+            }
+
+            while let Some((line_no, record)) = csv.next_record()? {
+                let Record::Fields(record) = record else {
+                    summary.reject(line_no, UNTERMINATED.to_string(), options.strict)?;
+                    continue;
+                };
+                let columns: Vec<String> = match &header {
+                    Some(header) if record.len() != header.len() => {
+                        summary.reject(
+                            line_no,
+                            format!("Expected {} columns, got {}", header.len(), record.len()),
+                            options.strict,
+                        )?;
+                        continue;
+                    }
+                    Some(header) => header.clone(),
+                    None => (0..record.len()).map(|i| i.to_string()).collect(),
+                };
+
+                let doc: serde_json::Map<String, serde_json::Value> = columns
+                    .into_iter()
+                    .zip(record)
+                    .filter(|(_, cell)| !cell.is_empty())
+                    .map(|(column, cell)| {
+                        let value = if options.type_infer {
+                            parse_csv_cell(&cell)
+                        } else {
+                            serde_json::Value::String(cell)
+                        };
+                        (column, value)
+                    })
+                    .collect();
+                import_row(
+                    engine,
+                    db,
+                    table,
+                    primary_key,
+                    line_no,
+                    serde_json::Value::Object(doc),
+                    options,
+                    rows,
+                    &mut summary,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn import_row(
+    engine: &dyn DatabaseEngine,
+    db: &str,
+    table: &str,
+    primary_key: &str,
+    line: u64,
+    mut doc: serde_json::Value,
+    options: &ImportOptions,
+    rows: &mut Rows,
+    summary: &mut ImportSummary,
+) -> Result<()> {
+    let obj = match doc.as_object_mut() {
+        Some(obj) => obj,
+        None => {
+            return summary.reject(line, "Row is not a JSON object".to_string(), options.strict);
+        }
+    };
+
+    // Same key rendering as ReQL inserts: strings verbatim, everything else via Datum
+    let key = match obj.get(primary_key) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => {
+            let id = uuid::Uuid::new_v4().to_string();
+            obj.insert(
+                primary_key.to_string(),
+                serde_json::Value::String(id.clone()),
+            );
+            id
+        }
+        Some(other) => Datum::from(other.clone()).to_string(),
+    };
+
+    let key = key.into_bytes();
+    if options.skip_existing
+        && (rows.contains_key(&key) || engine.get_document(db, table, &key).await?.is_some())
+    {
+        summary.skipped += 1;
+        return Ok(());
+    }
+
+    let bytes = serde_json::to_vec(&doc).map_err(|e| Error::SerializationError(e.to_string()))?;
+    rows.insert(key, bytes);
+    summary.inserted += 1;
+    Ok(())
+}
+
+/// Infer a JSON value from a CSV cell (inverse of the export encoding)
+fn parse_csv_cell(cell: &str) -> serde_json::Value {
+    match cell {
+        "true" => return serde_json::Value::Bool(true),
+        "false" => return serde_json::Value::Bool(false),
+        _ => {}
+    }
+
+    if let Ok(n) = cell.parse::<f64>() {
+        if let Some(n) = serde_json::Number::from_f64(n) {
+            return serde_json::Value::Number(n);
+        }
+    }
+
+    if cell.starts_with('{') || cell.starts_with('[') {
+        if let Ok(value) = serde_json::from_str(cell) {
+            return value;
+        }
+    }
+
+    serde_json::Value::String(cell.to_string())
+}
+
+/// Message for a record whose quoted field is never closed
+const UNTERMINATED: &str = "Unterminated quoted CSV field";
+
+/// A CSV record as read by [`CsvReader::next_record`]
+enum Record {
+    Fields(Vec<String>),
+    /// A quoted field runs to the end of the input
+    Unterminated,
+}
+
+/// RFC 4180 record reader with a configurable delimiter
+struct CsvReader<R> {
+    reader: R,
+    delimiter: char,
+    /// Lines consumed so far
+    line: u64,
+    /// Lines to read again before the rest of the input, after a record
+    /// that never ended was given up
+    pending: VecDeque<String>,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    fn new(reader: R, delimiter: char) -> Self {
+        Self {
+            reader,
+            delimiter,
+            line: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Read the next record and the line it starts on; quoted fields may
+    /// span lines
+    ///
+    /// A record whose quoted field is still open at the end of the input
+    /// comes back as [`Record::Unterminated`], and reading resumes on the
+    /// line after the one it starts on.
+    fn next_record(&mut self) -> Result<Option<(u64, Record)>> {
+        let start = self.line + 1;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut lines = Vec::new();
+
+        loop {
+            let Some(line) = self.next_line()? else {
+                if in_quotes {
+                    self.pending.extend(lines.into_iter().skip(1));
+                    self.line = start;
+                    return Ok(Some((start, Record::Unterminated)));
+                }
+                if fields.is_empty() && field.is_empty() {
+                    return Ok(None);
+                }
+                fields.push(field);
+                return Ok(Some((start, Record::Fields(fields))));
+            };
+
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (c, in_quotes) {
+                    ('"', true) if chars.peek() == Some(&'"') => {
+                        field.push('"');
+                        chars.next();
+                    }
+                    ('"', true) => in_quotes = false,
+                    ('"', false) if field.is_empty() => in_quotes = true,
+                    (c, false) if c == self.delimiter => fields.push(std::mem::take(&mut field)),
+                    ('\n', false) => {
+                        fields.push(field);
+                        return Ok(Some((start, Record::Fields(fields))));
+                    }
+                    ('\r', false) if chars.peek() == Some(&'\n') => {}
+                    (c, _) => field.push(c),
+                }
+            }
+            lines.push(line);
+        }
+    }
+
+    /// The next line of input, including its line break
+    fn next_line(&mut self) -> Result<Option<String>> {
+        let line = match self.pending.pop_front() {
+            Some(line) => line,
+            None => {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line).map_err(io_error)? == 0 {
+                    return Ok(None);
+                }
+                line
+            }
+        };
+        self.line += 1;
+        Ok(Some(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{export_table, MockStorage, Storage, StorageDatabaseEngine};
+    use std::sync::Arc;
+
+    async fn engine_with_docs(docs: &[serde_json::Value]) -> (Arc<Storage>, StorageDatabaseEngine) {
+        let storage = Arc::new(Storage::new(Box::new(MockStorage::new())));
+        let engine = StorageDatabaseEngine::new(storage.clone());
+        engine.create_database("shop").await.unwrap();
+        engine.create_table("shop", "items").await.unwrap();
+        engine.create_table("shop", "restored").await.unwrap();
+
+        for doc in docs {
+            let key = doc["id"].as_str().unwrap();
+            engine
+                .set_document(
+                    "shop",
+                    "items",
+                    key.as_bytes(),
+                    serde_json::to_vec(doc).unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+        (storage, engine)
+    }
+
+    async fn table_docs(storage: &Storage, table: &str) -> Vec<Datum> {
+        storage
+            .scan_table_page("shop", table, None, usize::MAX)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, doc)| doc)
+            .collect()
+    }
+
+    fn sample_docs() -> Vec<serde_json::Value> {
+        vec![
+            serde_json::json!({"id": "a", "price": 3.5, "tags": ["x", "y"], "label": "one, two"}),
+            serde_json::json!({"id": "b", "price": 7.0, "in_stock": true}),
+            serde_json::json!({"id": "c", "meta": {"color": "red"}, "label": "say \"hi\""}),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        for format in [ExportFormat::Json, ExportFormat::Ndjson, ExportFormat::Csv] {
+            let (storage, engine) = engine_with_docs(&sample_docs()).await;
+
+            let mut exported = Vec::new();
+            export_table(&storage, "shop", "items", format, &mut exported)
+                .await
+                .unwrap();
+
+            let summary = import_table(
+                &engine,
+                "shop",
+                "restored",
+                format,
+                exported.as_slice(),
+                // CSV cells only get their types back through inference
+                &ImportOptions {
+                    type_infer: true,
+                    ..ImportOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                summary,
+                ImportSummary {
+                    inserted: 3,
+                    skipped: 0,
+                    errors: 0,
+                    row_errors: Vec::new(),
+                }
+            );
+
+            assert_eq!(
+                table_docs(&storage, "items").await,
+                table_docs(&storage, "restored").await,
+                "{:?} round trip",
+                format
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_existing() {
+        let (storage, engine) = engine_with_docs(&sample_docs()).await;
+        let input = concat!(
+            "{\"id\": \"a\", \"price\": 99}\n",
+            "{\"id\": \"d\", \"price\": 1}\n",
+            "not json\n",
+        );
+
+        let summary = import_table(
+            &engine,
+            "shop",
+            "items",
+            ExportFormat::Ndjson,
+            input.as_bytes(),
+            &ImportOptions {
+                skip_existing: true,
+                ..ImportOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (summary.inserted, summary.skipped, summary.errors),
+            (1, 1, 1)
+        );
+        assert_eq!(summary.row_errors.len(), 1);
+        assert_eq!(summary.row_errors[0].line, 3);
+
+        let a = engine
+            .get_document("shop", "items", b"a")
+            .await
+            .unwrap()
+            .unwrap();
+        let a: serde_json::Value = serde_json::from_slice(&a).unwrap();
+        assert_eq!(a["price"], 3.5);
+        assert_eq!(table_docs(&storage, "items").await.len(), 4);
+
+        // Without skip_existing the row is overwritten
+        let summary = import_table(
+            &engine,
+            "shop",
+            "items",
+            ExportFormat::Ndjson,
+            input.as_bytes(),
+            &ImportOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.inserted, 2);
+        let a = engine
+            .get_document("shop", "items", b"a")
+            .await
+            .unwrap()
+            .unwrap();
+        let a: serde_json::Value = serde_json::from_slice(&a).unwrap();
+        assert_eq!(a["price"], 99.0);
+    }
+
+    async fn import_csv(
+        engine: &StorageDatabaseEngine,
+        input: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary> {
+        import_table(
+            engine,
+            "shop",
+            "restored",
+            ExportFormat::Csv,
+            input.as_bytes(),
+            options,
+        )
+        .await
+    }
+
+    async fn restored_doc(engine: &StorageDatabaseEngine, key: &str) -> serde_json::Value {
+        let doc = engine
+            .get_document("shop", "restored", key.as_bytes())
+            .await
+            .unwrap()
+            .unwrap();
+        serde_json::from_slice(&doc).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_csv_semicolon_delimiter() {
+        let (_, engine) = engine_with_docs(&[]).await;
+        let input = concat!("id;price;label\n", "a;3.5;\"one; two\"\n", "b;7;plain\n",);
+
+        let options = ImportOptions {
+            delimiter: ';',
+            type_infer: false,
+            ..ImportOptions::default()
+        };
+        let summary = import_csv(&engine, input, &options).await.unwrap();
+        assert_eq!(summary.inserted, 2);
+        assert!(summary.row_errors.is_empty());
+
+        // Without type inference every cell stays a string
+        assert_eq!(
+            restored_doc(&engine, "a").await,
+            serde_json::json!({"id": "a", "price": "3.5", "label": "one; two"})
+        );
+        assert_eq!(restored_doc(&engine, "b").await["price"], "7");
+    }
+
+    #[tokio::test]
+    async fn test_csv_headerless_type_infer() {
+        let (storage, engine) = engine_with_docs(&[]).await;
+        let input = concat!("x,42,true\n", "y,-1.5,false\n");
+
+        let options = ImportOptions {
+            header: false,
+            type_infer: true,
+            ..ImportOptions::default()
+        };
+        let summary = import_csv(&engine, input, &options).await.unwrap();
+        assert_eq!(summary.inserted, 2);
+
+        // Columns are named by position and the primary key is generated
+        let docs: Vec<serde_json::Value> = table_docs(&storage, "restored")
+            .await
+            .into_iter()
+            .map(serde_json::Value::from)
+            .collect();
+        let x = docs.iter().find(|doc| doc["0"] == "x").unwrap();
+        assert_eq!(x["1"], 42.0);
+        assert_eq!(x["2"], true);
+        assert!(x["id"].is_string());
+        let y = docs.iter().find(|doc| doc["0"] == "y").unwrap();
+        assert_eq!(y["1"], -1.5);
+        assert_eq!(y["2"], false);
+    }
+
+    #[tokio::test]
+    async fn test_csv_malformed_rows() {
+        let (_, engine) = engine_with_docs(&[]).await;
+        let input = concat!("id,label\n", "a,\"multi\nline\"\n", "b,1,extra\n", "c,ok\n",);
+
+        // Reported with the line the row starts on, the rest is imported
+        let summary = import_csv(&engine, input, &ImportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.inserted, 2);
+        assert_eq!(
+            summary.row_errors,
+            vec![RowError {
+                line: 4,
+                message: "Expected 2 columns, got 3".to_string(),
+            }]
+        );
+
+        let strict = ImportOptions {
+            strict: true,
+            ..ImportOptions::default()
+        };
+        let err = import_csv(&engine, input, &strict).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidArgument(msg) if msg.starts_with("Line 4:")),
+            "{:?}",
+            err
+        );
+    }
+    #[tokio::test]
+    async fn test_csv_unterminated_quote() {
+        let (_, engine) = engine_with_docs(&[]).await;
+        let input = concat!("id,label\n", "a,\"open\n", "b,ok\n", "c,ok\n");
+
+        // The broken row is skipped and reading resumes on the next line
+        let summary = import_csv(&engine, input, &ImportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.inserted, 2);
+        assert_eq!(
+            summary.row_errors,
+            vec![RowError {
+                line: 2,
+                message: "Unterminated quoted CSV field".to_string(),
+            }]
+        );
+        assert_eq!(restored_doc(&engine, "c").await["label"], "ok");
+
+        let strict = ImportOptions {
+            strict: true,
+            ..ImportOptions::default()
+        };
+        let err = import_csv(&engine, input, &strict).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidArgument(msg) if msg.starts_with("Line 2:")),
+            "{:?}",
+            err
+        );
+    }
+}
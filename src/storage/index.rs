@@ -0,0 +1,339 @@
+//! Secondary indexes
+//!
+//! A secondary index maps the value of one document field to the primary
+//! keys of the documents holding it. Each document has one entry:
+//!
+//! ```text
+//! idx:{db}:{table}:{index}:{value}{primary key} → primary key
+//! ```
+//!
+//! Values and primary keys are written with [`Datum::ordered_key`], so the
+//! entries of an index are sorted in ReQL order of their values, and the
+//! entries of one value, like a range of values, are read as one range of
+//! keys. Writing a document only puts or deletes its own entries; it never
+//! rewrites entries shared with other documents.
+//!
+//! A compound index combines several fields; its value is the array of
+//! their values, and only documents holding all of them are indexed.
+//!
+//! [`Storage`](super::Storage) keeps entries up to date on every document
+//! write, in the same batch as the write itself. Entries may still go stale,
+//! e.g. when expired documents are swept or a table is dropped, so lookups
+//! re-check every document they return.
+
+use crate::error::Result;
+use crate::reql::{Bound, Datum};
+use crate::storage::engine::{StorageEngine, TableInfo};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound as KeyBound;
+
+/// Storage key of a document: `doc:{db}:{table}:{primary key}`
+pub fn document_key(db: &str, table: &str, id: &Datum) -> Vec<u8> {
+    [format!("doc:{}:{}:", db, table).into_bytes(), primary_key_bytes(id)].concat()
+}
+
+/// A primary key as written in document keys
+///
+/// Strings are kept verbatim; numbers use [`Datum::ordered_key`] so that
+/// documents keyed by numbers sort numerically rather than as text. A
+/// string that spells out such an encoding shares its document key.
+pub fn primary_key_bytes(id: &Datum) -> Vec<u8> {
+    match id {
+        Datum::String(s) => s.as_bytes().to_vec(),
+        Datum::Integer(_) | Datum::Number(_) => id.ordered_key(),
+        other => other.to_string().into_bytes(),
+    }
+}
+
+/// Common prefix of the entries of `index`
+fn index_prefix(db: &str, table: &str, index: &str) -> Vec<u8> {
+    format!("idx:{}:{}:{}:", db, table, index).into_bytes()
+}
+
+/// Common prefix of the entries for `value` in `index`
+fn value_prefix(db: &str, table: &str, index: &str, value: &Datum) -> Vec<u8> {
+    [index_prefix(db, table, index), value.ordered_key()].concat()
+}
+
+/// Key of the entry for the document `id` under `value` in `index`
+pub fn entry_key(db: &str, table: &str, index: &str, value: &Datum, id: &Datum) -> Vec<u8> {
+    [value_prefix(db, table, index, value), id.ordered_key()].concat()
+}
+
+/// The first key past every key starting with `prefix`
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Bound excluding every key starting with `prefix` and all after them
+fn before_end(prefix: &[u8]) -> KeyBound<Vec<u8>> {
+    prefix_end(prefix).map_or(KeyBound::Unbounded, KeyBound::Excluded)
+}
+
+/// Bound excluding every key starting with `prefix` and all before them
+fn after_end(prefix: &[u8]) -> Option<KeyBound<Vec<u8>>> {
+    prefix_end(prefix).map(KeyBound::Included)
+}
+
+/// Value of `doc` under an index built from `fields`
+///
+/// A single field gives its own value, several the array of theirs.
+pub fn index_value(doc: &Datum, fields: &[String]) -> Option<Datum> {
+    let obj = doc.as_object()?;
+    match fields {
+        [field] => obj.get(field).cloned(),
+        fields => fields
+            .iter()
+            .map(|field| obj.get(field).cloned())
+            .collect::<Option<Vec<_>>>()
+            .map(Datum::Array),
+    }
+}
+
+/// Compound index definitions stored in table metadata as
+/// `{index: [field, ...]}`
+pub(crate) fn compound_from_datum(datum: Option<&Datum>) -> BTreeMap<String, Vec<String>> {
+    let Some(definitions) = datum.and_then(|d| d.as_object()) else {
+        return BTreeMap::new();
+    };
+    definitions
+        .iter()
+        .filter_map(|(index, fields)| {
+            let fields = fields
+                .as_array()?
+                .iter()
+                .map(|f| f.as_string().map(str::to_string))
+                .collect::<Option<Vec<_>>>()?;
+            Some((index.clone(), fields))
+        })
+        .collect()
+}
+
+/// Database and table of a document key
+pub(crate) fn parse_document_key(key: &[u8]) -> Option<(&str, &str)> {
+    let key = std::str::from_utf8(key).ok()?.strip_prefix("doc:")?;
+    let mut parts = key.splitn(3, ':');
+    Some((parts.next()?, parts.next()?))
+}
+
+/// Index entry writes that go with the document `writes`
+///
+/// Documents of tables without indexes need none.
+pub(crate) async fn entry_writes(
+    engine: &dyn StorageEngine,
+    writes: &[(Vec<u8>, Option<Datum>)],
+) -> Result<Vec<(Vec<u8>, Option<Datum>)>> {
+    let mut tables: HashMap<(String, String), Option<TableInfo>> = HashMap::new();
+    let mut entries: BTreeMap<Vec<u8>, Option<Datum>> = BTreeMap::new();
+
+    for (key, new) in writes {
+        let Some((db, table)) = parse_document_key(key) else {
+            continue;
+        };
+        let table_id = (db.to_string(), table.to_string());
+        if !tables.contains_key(&table_id) {
+            let info = engine.get_table_info(&format!("{}.{}", db, table)).await?;
+            tables.insert(table_id.clone(), info);
+        }
+        let Some(info) = tables[&table_id].as_ref().filter(|i| !i.indexes.is_empty()) else {
+            continue;
+        };
+
+        let old = engine.get(key).await?;
+        let value = |doc: &Option<Datum>, fields: &[String]| {
+            doc.as_ref().and_then(|d| index_value(d, fields))
+        };
+        let primary_key = [info.primary_key.clone()];
+        let Some(id) = value(new, &primary_key).or_else(|| value(&old, &primary_key)) else {
+            continue;
+        };
+
+        for index in &info.indexes {
+            let fields = info.index_fields(index);
+            let (before, after) = (value(&old, &fields), value(new, &fields));
+            if before == after {
+                continue;
+            }
+            if let Some(before) = before {
+                entries.insert(entry_key(db, table, index, &before, &id), None);
+            }
+            if let Some(after) = after {
+                entries.insert(entry_key(db, table, index, &after, &id), Some(id.clone()));
+            }
+        }
+    }
+
+    Ok(entries.into_iter().collect())
+}
+
+/// Primary keys listed under `value` in `index`
+pub(crate) async fn lookup(
+    engine: &dyn StorageEngine,
+    db: &str,
+    table: &str,
+    index: &str,
+    value: &Datum,
+) -> Result<Vec<Datum>> {
+    let prefix = value_prefix(db, table, index, value);
+    let end = before_end(&prefix);
+    let entries = engine
+        .scan_keys(KeyBound::Included(&prefix), as_ref(&end))
+        .await?;
+    Ok(entries.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Entries of `index` for the values between `lower` and `upper`, in ReQL
+/// order of the values, with the primary key of each
+///
+/// Open ends exclude values equal to the bound.
+pub(crate) async fn lookup_between(
+    engine: &dyn StorageEngine,
+    db: &str,
+    table: &str,
+    index: &str,
+    (lower, left_open): (&Bound, bool),
+    (upper, right_open): (&Bound, bool),
+) -> Result<Vec<(Vec<u8>, Datum)>> {
+    let prefix = index_prefix(db, table, index);
+    let value_key = |value: &Datum| value_prefix(db, table, index, value);
+    let start = match lower {
+        Bound::MinVal => KeyBound::Included(prefix.clone()),
+        Bound::Value(value) if left_open => match after_end(&value_key(value)) {
+            Some(start) => start,
+            None => return Ok(Vec::new()),
+        },
+        Bound::Value(value) => KeyBound::Included(value_key(value)),
+        Bound::MaxVal => return Ok(Vec::new()),
+    };
+    let end = match upper {
+        Bound::MinVal => return Ok(Vec::new()),
+        Bound::Value(value) if right_open => KeyBound::Excluded(value_key(value)),
+        Bound::Value(value) => before_end(&value_key(value)),
+        Bound::MaxVal => before_end(&prefix),
+    };
+    engine.scan_keys(as_ref(&start), as_ref(&end)).await
+}
+
+fn as_ref(bound: &KeyBound<Vec<u8>>) -> KeyBound<&[u8]> {
+    bound.as_ref().map(Vec::as_slice)
+}
+
+/// Create the entries of a new index over `fields` from the documents
+/// already stored
+pub(crate) async fn build(
+    engine: &dyn StorageEngine,
+    db: &str,
+    table: &str,
+    primary_key: &str,
+    index: &str,
+    fields: &[String],
+) -> Result<()> {
+    let mut writes = Vec::new();
+    for doc in engine.scan_table(db, table).await? {
+        let id = doc.as_object().and_then(|obj| obj.get(primary_key));
+        if let (Some(id), Some(value)) = (id, index_value(&doc, fields)) {
+            writes.push((entry_key(db, table, index, &value, id), Some(id.clone())));
+        }
+    }
+    engine.write_batch(writes).await
+}
+
+/// Delete the entries of a removed index
+pub(crate) async fn remove(
+    engine: &dyn StorageEngine,
+    db: &str,
+    table: &str,
+    index: &str,
+) -> Result<()> {
+    let prefix = index_prefix(db, table, index);
+    let end = before_end(&prefix);
+    let entries = engine
+        .scan_keys(KeyBound::Included(&prefix), as_ref(&end))
+        .await?;
+    engine
+        .write_batch(entries.into_iter().map(|(key, _)| (key, None)).collect())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{
+        DatabaseEngine, MockStorage, Storage, StorageDatabaseEngine, TableReconfigure,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_entries_follow_writes() {
+        let storage = Arc::new(Storage::new(Box::new(MockStorage::new())));
+        storage.create_database("test").await.unwrap();
+        storage.create_table("test", "users", "id").await.unwrap();
+        let user = |email: &str| Datum::from(serde_json::json!({"id": "a", "email": email}));
+        let key = document_key("test", "users", &Datum::from("a"));
+        storage.set(&key, user("x@example.com")).await.unwrap();
+
+        // Documents written before the index are picked up when it is added
+        let engine = StorageDatabaseEngine::new(storage.clone());
+        let changes = |add: &[&str], remove: &[&str]| TableReconfigure {
+            add_indexes: add.iter().map(|i| i.to_string()).collect(),
+            remove_indexes: remove.iter().map(|i| i.to_string()).collect(),
+            ..Default::default()
+        };
+        engine
+            .reconfigure_table("test", "users", &changes(&["email"], &[]))
+            .await
+            .unwrap();
+        let lookup = |email: &'static str| {
+            let storage = storage.clone();
+            async move {
+                storage
+                    .index_lookup("test", "users", "email", &Datum::from(email))
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(lookup("x@example.com").await, vec![user("x@example.com")]);
+
+        // Changing the value moves the document to another entry
+        storage.set(&key, user("y@example.com")).await.unwrap();
+        assert!(lookup("x@example.com").await.is_empty());
+        assert_eq!(lookup("y@example.com").await, vec![user("y@example.com")]);
+
+        storage.delete(&key).await.unwrap();
+        assert!(lookup("y@example.com").await.is_empty());
+        let entry = |email: &str| {
+            entry_key(
+                "test",
+                "users",
+                "email",
+                &Datum::from(email),
+                &Datum::from("a"),
+            )
+        };
+        assert!(storage
+            .get(&entry("y@example.com"))
+            .await
+            .unwrap()
+            .is_none());
+
+        // Removing the index deletes its entries
+        storage.set(&key, user("z@example.com")).await.unwrap();
+        engine
+            .reconfigure_table("test", "users", &changes(&[], &["email"]))
+            .await
+            .unwrap();
+        assert!(storage
+            .get(&entry("z@example.com"))
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(storage.scan_stats().index_lookups, 4);
+    }
+}
@@ -0,0 +1,339 @@
+//! Migration from the legacy B-Tree backend to slab storage
+//!
+//! [`migrate_btree_to_slab`] copies every database, table definition
+//! (primary key and indexes included) and document key by key, so ids and
+//! document keys are unchanged. Keys are copied in byte order and each batch
+//! is written together with a progress marker, so an interrupted migration
+//! resumes after the last committed key instead of starting over.
+//!
+//! The copy goes through the [`StorageEngine`] interface; any engine can be
+//! the source via [`migrate_storage`].
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::btree_storage::BTreeStorage;
+use crate::storage::engine::StorageEngine;
+use crate::storage::slab::SlabStorageEngine;
+
+/// Key of the progress marker in the destination store
+pub const MIGRATION_MARKER_KEY: &str = "__meta__:migration:btree";
+
+/// Keys written per batch (and per progress update)
+pub const MIGRATION_BATCH_SIZE: usize = 500;
+
+/// Progress marker stored under [`MIGRATION_MARKER_KEY`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationMarker {
+    /// Last key committed to the destination
+    pub last_key: Option<String>,
+    /// Whether the whole source has been copied
+    pub complete: bool,
+}
+
+/// What a migration run copied
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationSummary {
+    pub databases: usize,
+    pub tables: usize,
+    pub documents: u64,
+    /// Whether this run continued an interrupted migration
+    pub resumed: bool,
+}
+
+/// Copy a B-Tree store into a slab store
+pub async fn migrate_btree_to_slab(
+    src: &BTreeStorage,
+    dst: &SlabStorageEngine,
+) -> Result<MigrationSummary> {
+    migrate_storage(src, dst).await
+}
+
+/// Copy all databases, tables and documents from `src` into `dst`
+pub async fn migrate_storage(
+    src: &dyn StorageEngine,
+    dst: &dyn StorageEngine,
+) -> Result<MigrationSummary> {
+    let marker = read_marker(dst).await?;
+    let mut summary = MigrationSummary {
+        resumed: marker.last_key.is_some(),
+        ..Default::default()
+    };
+    if marker.complete {
+        info!("Migration already complete, nothing to do");
+        return Ok(summary);
+    }
+    if let Some(last_key) = &marker.last_key {
+        info!(after = %last_key, "Resuming migration");
+    }
+
+    let mut writer = BatchWriter {
+        dst,
+        resume_after: marker.last_key,
+        pending: Vec::new(),
+    };
+
+    // Database and table definitions; "__meta__:" sorts before "doc:"
+    let mut databases = src.list_databases().await?;
+    databases.sort();
+    let mut tables = Vec::new();
+    for db in &databases {
+        let key = format!("__meta__:databases:{}", db);
+        if let Some(value) = src.get(key.as_bytes()).await? {
+            summary.databases += writer.push(key, value).await? as usize;
+        }
+        for table in src.list_tables_in_db(db).await? {
+            tables.push((db.clone(), table));
+        }
+    }
+    tables.sort_by_key(|(db, table)| format!("{}.{}", db, table));
+    for (db, table) in &tables {
+        let key = format!("__meta__:tables:{}.{}", db, table);
+        if let Some(value) = src.get(key.as_bytes()).await? {
+            summary.tables += writer.push(key, value).await? as usize;
+        }
+    }
+
+    // Documents, table by table in key order
+    tables.sort_by_key(|(db, table)| format!("doc:{}:{}:", db, table));
+    for (db, table) in &tables {
+        let mut after = writer.resume_point(&format!("doc:{}:{}:", db, table));
+        loop {
+            let page = src
+                .scan_table_page(db, table, after.as_deref(), MIGRATION_BATCH_SIZE)
+                .await?;
+            let done = page.len() < MIGRATION_BATCH_SIZE;
+
+            for (key, doc) in page {
+                let key = String::from_utf8(key)
+                    .map_err(|e| Error::Storage(format!("Invalid UTF-8 key: {}", e)))?;
+                after = Some(key.clone().into_bytes());
+                summary.documents += writer.push(key, doc).await? as u64;
+            }
+
+            if done {
+                break;
+            }
+        }
+        info!(db = %db, table = %table, documents = summary.documents, "Migrated table");
+    }
+
+    writer.finish().await?;
+    info!(
+        databases = summary.databases,
+        tables = summary.tables,
+        documents = summary.documents,
+        "Migration complete"
+    );
+    Ok(summary)
+}
+
+async fn read_marker(dst: &dyn StorageEngine) -> Result<MigrationMarker> {
+    match dst.get(MIGRATION_MARKER_KEY.as_bytes()).await? {
+        Some(datum) => {
+            let json: serde_json::Value = datum.into();
+            serde_json::from_value(json)
+                .map_err(|e| Error::Storage(format!("Invalid migration marker: {}", e)))
+        }
+        None => Ok(MigrationMarker::default()),
+    }
+}
+
+fn marker_datum(marker: &MigrationMarker) -> Result<Datum> {
+    serde_json::to_value(marker)
+        .map(Datum::from)
+        .map_err(|e| Error::SerializationError(e.to_string()))
+}
+
+/// Buffers writes and commits them in batches together with the marker
+struct BatchWriter<'a> {
+    dst: &'a dyn StorageEngine,
+    /// Keys up to and including this one were copied by an earlier run
+    resume_after: Option<String>,
+    pending: Vec<(String, Datum)>,
+}
+
+impl BatchWriter<'_> {
+    /// Where to start scanning a table whose keys all start with `prefix`
+    fn resume_point(&self, prefix: &str) -> Option<Vec<u8>> {
+        match &self.resume_after {
+            Some(last) if last.as_str() >= prefix => Some(last.clone().into_bytes()),
+            _ => None,
+        }
+    }
+
+    /// Queue a key; returns false if an earlier run already copied it
+    async fn push(&mut self, key: String, value: Datum) -> Result<bool> {
+        if self
+            .resume_after
+            .as_ref()
+            .is_some_and(|last| key.as_str() <= last.as_str())
+        {
+            return Ok(false);
+        }
+
+        self.pending.push((key, value));
+        if self.pending.len() >= MIGRATION_BATCH_SIZE {
+            self.flush(false).await?;
+        }
+        Ok(true)
+    }
+
+    async fn flush(&mut self, complete: bool) -> Result<()> {
+        let last_key = self
+            .pending
+            .last()
+            .map(|(key, _)| key.clone())
+            .or_else(|| self.resume_after.clone());
+        let marker = MigrationMarker {
+            last_key: last_key.clone(),
+            complete,
+        };
+
+        let mut writes: Vec<(Vec<u8>, Option<Datum>)> = self
+            .pending
+            .drain(..)
+            .map(|(key, value)| (key.into_bytes(), Some(value)))
+            .collect();
+        let copied = writes.len();
+        writes.push((
+            MIGRATION_MARKER_KEY.as_bytes().to_vec(),
+            Some(marker_datum(&marker)?),
+        ));
+        self.dst.write_batch(writes).await?;
+
+        self.resume_after = last_key;
+        if copied > 0 {
+            info!(copied, last_key = ?self.resume_after, "Committed migration batch");
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<()> {
+        self.flush(true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::engine::Storage;
+    use crate::storage::MockStorage;
+
+    async fn seeded_source() -> MockStorage {
+        let mock = MockStorage::new();
+        let storage = Storage::new(Box::new(mock.clone()));
+        storage.create_database("app").await.unwrap();
+        storage.create_database("logs").await.unwrap();
+        storage.create_table("app", "users", "email").await.unwrap();
+        storage.create_table("logs", "events", "id").await.unwrap();
+
+        // Secondary index definition on the users table
+        let meta_key = b"__meta__:tables:app.users";
+        let Some(Datum::Object(mut meta)) = storage.get(meta_key).await.unwrap() else {
+            panic!("missing table metadata");
+        };
+        meta.insert(
+            "indexes".to_string(),
+            Datum::Array(vec![Datum::String("name".to_string())]),
+        );
+        storage.set(meta_key, Datum::Object(meta)).await.unwrap();
+
+        for i in 0..(MIGRATION_BATCH_SIZE + 20) {
+            let email = format!("user{:04}@example.com", i);
+            let doc = serde_json::json!({"email": email, "name": format!("User {}", i)});
+            let key = format!("doc:app:users:{}", email);
+            storage.set(key.as_bytes(), Datum::from(doc)).await.unwrap();
+        }
+        for i in 0..5 {
+            let doc = serde_json::json!({"id": i, "level": "info"});
+            let key = format!("doc:logs:events:{}", i);
+            storage.set(key.as_bytes(), Datum::from(doc)).await.unwrap();
+        }
+        mock
+    }
+
+    fn slab_engine(name: &str) -> (SlabStorageEngine, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("migrate_{}_{}", name, uuid::Uuid::new_v4()));
+        (SlabStorageEngine::with_defaults(&dir).unwrap(), dir)
+    }
+
+    #[tokio::test]
+    async fn test_migrate_preserves_tables_and_documents() {
+        let src = seeded_source().await;
+        let (dst, dir) = slab_engine("full");
+
+        let summary = migrate_storage(&src, &dst).await.unwrap();
+        assert_eq!(summary.databases, 2);
+        assert_eq!(summary.tables, 2);
+        assert_eq!(summary.documents as usize, MIGRATION_BATCH_SIZE + 25);
+        assert!(!summary.resumed);
+
+        let mut dbs = dst.list_databases().await.unwrap();
+        dbs.sort();
+        assert_eq!(dbs, vec!["app", "logs"]);
+
+        let users = dst.get_table_info("app.users").await.unwrap().unwrap();
+        assert_eq!(users.primary_key, "email");
+        assert_eq!(users.indexes, vec!["name"]);
+        assert!(dst.get_table_info("logs.events").await.unwrap().is_some());
+
+        for (db, table) in [("app", "users"), ("logs", "events")] {
+            let expected = src
+                .scan_table_page(db, table, None, usize::MAX)
+                .await
+                .unwrap();
+            let migrated = dst
+                .scan_table_page(db, table, None, usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(migrated, expected);
+        }
+
+        // A second run finds the completion marker and copies nothing
+        let again = migrate_storage(&src, &dst).await.unwrap();
+        assert_eq!(again.documents, 0);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_migration_resumes_after_marker() {
+        let src = seeded_source().await;
+        let (dst, dir) = slab_engine("resume");
+
+        // Pretend an earlier run stopped after user0099
+        let marker = MigrationMarker {
+            last_key: Some("doc:app:users:user0099@example.com".to_string()),
+            complete: false,
+        };
+        dst.set(
+            MIGRATION_MARKER_KEY.as_bytes(),
+            marker_datum(&marker).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let summary = migrate_storage(&src, &dst).await.unwrap();
+        assert!(summary.resumed);
+        assert_eq!(summary.databases, 0);
+        assert_eq!(summary.tables, 0);
+        assert_eq!(summary.documents as usize, MIGRATION_BATCH_SIZE + 25 - 100);
+
+        assert!(dst
+            .get(b"doc:app:users:user0099@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(dst
+            .get(b"doc:app:users:user0100@example.com")
+            .await
+            .unwrap()
+            .is_some());
+        assert!(read_marker(&dst).await.unwrap().complete);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
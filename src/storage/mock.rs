@@ -1,26 +1,30 @@
 //! Mock storage for testing
 //!
 //! This module provides a simple in-memory storage implementation
-//! for testing purposes.
+//! for testing purposes. It also backs the server's ephemeral mode
+//! (`rethinkdb serve --storage memory`), see [`Storage::in_memory`].
+//!
+//! [`Storage::in_memory`]: crate::storage::Storage::in_memory
 
 use crate::error::Result;
 use crate::reql::Datum;
-use crate::storage::engine::{StorageEngine, TableInfo};
+use crate::storage::engine::{document_range, non_empty, StorageEngine, TableInfo};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::ops::Bound;
 use std::sync::{Arc, Mutex};
 
 /// In-memory mock storage for testing
 #[derive(Clone, Default)]
 pub struct MockStorage {
-    data: Arc<Mutex<HashMap<Vec<u8>, Datum>>>,
+    data: Arc<Mutex<BTreeMap<Vec<u8>, Datum>>>,
 }
 
 impl MockStorage {
     /// Create a new mock storage instance
     pub fn new() -> Self {
         Self {
-            data: Arc::new(Mutex::new(HashMap::new())),
+            data: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
@@ -38,6 +42,19 @@ impl MockStorage {
     pub fn clear(&self) {
         self.data.lock().unwrap().clear();
     }
+
+    /// Key suffixes (as strings) of all keys starting with `prefix`
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|k| {
+                k.strip_prefix(prefix.as_bytes())
+                    .and_then(|rest| String::from_utf8(rest.to_vec()).ok())
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -56,40 +73,140 @@ impl StorageEngine for MockStorage {
         Ok(())
     }
 
+    async fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        let mut data = self.data.lock().unwrap();
+        for (key, value) in writes {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
     async fn list_tables(&self) -> Result<Vec<String>> {
-        Ok(Vec::new())
+        Ok(self.keys_with_prefix("__meta__:tables:"))
     }
 
-    async fn get_table_info(&self, _table_name: &str) -> Result<Option<TableInfo>> {
-        Ok(None)
+    async fn get_table_info(&self, table_name: &str) -> Result<Option<TableInfo>> {
+        let key = format!("__meta__:tables:{}", table_name);
+        match self.get(key.as_bytes()).await? {
+            Some(datum) => Ok(Some(TableInfo::from_datum(&datum)?)),
+            None => Ok(None),
+        }
     }
 
     async fn list_databases(&self) -> Result<Vec<String>> {
-        Ok(Vec::new())
+        Ok(self.keys_with_prefix("__meta__:databases:"))
     }
 
-    async fn create_database(&self, _name: &str) -> Result<()> {
-        Ok(())
+    async fn create_database(&self, name: &str) -> Result<()> {
+        let key = format!("__meta__:databases:{}", name);
+        let value = Datum::Object(
+            vec![("name".to_string(), Datum::String(name.to_string()))]
+                .into_iter()
+                .collect(),
+        );
+        self.set(key.as_bytes(), value).await
     }
 
-    async fn drop_database(&self, _name: &str) -> Result<()> {
+    async fn drop_database(&self, name: &str) -> Result<()> {
+        let key = format!("__meta__:databases:{}", name);
+        self.delete(key.as_bytes()).await?;
+
+        for table in self.list_tables_in_db(name).await? {
+            self.drop_table(name, &table).await?;
+        }
         Ok(())
     }
 
-    async fn list_tables_in_db(&self, _db: &str) -> Result<Vec<String>> {
-        Ok(Vec::new())
+    async fn list_tables_in_db(&self, db: &str) -> Result<Vec<String>> {
+        Ok(self.keys_with_prefix(&format!("__meta__:tables:{}.", db)))
     }
 
-    async fn create_table(&self, _db: &str, _table: &str, _primary_key: &str) -> Result<()> {
-        Ok(())
+    async fn create_table(&self, db: &str, table: &str, primary_key: &str) -> Result<()> {
+        let key = format!("__meta__:tables:{}.{}", db, table);
+        let datum = Datum::Object(
+            vec![
+                ("name".to_string(), Datum::String(table.to_string())),
+                ("db".to_string(), Datum::String(db.to_string())),
+                ("primary_key".to_string(), Datum::String(primary_key.to_string())),
+                ("doc_count".to_string(), Datum::Number(0.0)),
+                ("indexes".to_string(), Datum::Array(vec![])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        self.set(key.as_bytes(), datum).await
     }
 
-    async fn drop_table(&self, _db: &str, _table: &str) -> Result<()> {
+    async fn drop_table(&self, db: &str, table: &str) -> Result<()> {
+        let meta_key = format!("__meta__:tables:{}.{}", db, table);
+        let doc_prefix = format!("doc:{}:{}:", db, table);
+
+        let mut data = self.data.lock().unwrap();
+        data.remove(meta_key.as_bytes());
+        data.retain(|k, _| !k.starts_with(doc_prefix.as_bytes()));
         Ok(())
     }
 
-    async fn scan_table(&self, _db: &str, _table: &str) -> Result<Vec<Datum>> {
-        Ok(Vec::new())
+    async fn scan_table(&self, db: &str, table: &str) -> Result<Vec<Datum>> {
+        let prefix = format!("doc:{}:{}:", db, table);
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .range(prefix.clone().into_bytes()..)
+            .take_while(|(k, _)| k.starts_with(prefix.as_bytes()))
+            .map(|(_, v)| v.clone())
+            .collect())
+    }
+    async fn scan_table_page(
+        &self,
+        db: &str,
+        table: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let prefix = format!("doc:{}:{}:", db, table);
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .range(prefix.clone().into_bytes()..)
+            .take_while(|(k, _)| k.starts_with(prefix.as_bytes()))
+            .filter(|(k, _)| after.is_none_or(|after| k.as_slice() > after))
+            .take(limit)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    async fn scan_range(
+        &self,
+        db: &str,
+        table: &str,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let Some(range) = document_range(db, table, start, end) else {
+            return Ok(Vec::new());
+        };
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .range(range)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    async fn scan_keys(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let Some(range) = non_empty((start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec))) else {
+            return Ok(Vec::new());
+        };
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .range(range)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
     }
 }
 
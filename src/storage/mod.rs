@@ -25,13 +25,23 @@
 //! - **Key prefixing** for namespace isolation: `db:{db_id}:tables`, `db:{db_id}:table:{table_id}:docs`
 //!
 //! ### Legacy Storage (Deprecated)
-//! - **Sled B-Tree** (deprecated, use migration tools to convert to Slab)
+//! - **Sled B-Tree** (deprecated, convert with [`migrate_btree_to_slab`] or
+//!   `rethinkdb admin migrate-btree`)
 
 pub mod btree_storage;
+pub mod changes;
 pub mod database;
+pub mod database_engine;
 pub mod engine;
+pub mod export;
+pub mod import;
+pub mod index;
+pub mod migrate;
 pub mod mock;
+pub mod schema;
 pub mod slab;
+pub mod transaction;
+pub mod ttl;
 
 // Default storage engine (Phase 5)
 pub use slab::SlabStorageEngine as DefaultStorageEngine;
@@ -41,6 +51,14 @@ pub use slab::{SlabAllocator, SlabStorage, SlabStorageEngine};
 pub use btree_storage::BTreeStorage;
 pub use mock::MockStorage;
 pub use database::{
-    validate_name, DatabaseConfig, DatabaseEngine, DatabaseId, TableConfig, TableId,
+    validate_name, DatabaseConfig, DatabaseEngine, DatabaseId, DropMode, TableConfig, TableId,
+    TableReconfigure,
 };
-pub use engine::{Storage, StorageEngine, TableInfo};
+pub use changes::{Change, ChangeBus, OverflowPolicy, Subscription};
+pub use database_engine::StorageDatabaseEngine;
+pub use engine::{ScanStats, Storage, StorageEngine, TableInfo};
+pub use export::{export_table, ExportFormat};
+pub use import::{import_table, ImportOptions, ImportSummary, RowError};
+pub use migrate::{migrate_btree_to_slab, MigrationSummary};
+pub use transaction::Transaction;
+pub use ttl::spawn_ttl_sweeper;
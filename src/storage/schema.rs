@@ -0,0 +1,91 @@
+//! Per-table JSON Schema validation
+//!
+//! A table can carry a [JSON Schema](https://json-schema.org) in its
+//! config, set with [`DatabaseEngine::set_table_schema`]. Every document
+//! written to such a table is checked against it by the [`Storage`]
+//! wrapper, whichever path the write takes, and rejected with
+//! [`Error::InvalidArgument`] naming the first failing field. Tables
+//! without a schema accept any document.
+//!
+//! Documents are checked without the reserved TTL field, see
+//! [`ttl`](super::ttl).
+//!
+//! [`DatabaseEngine::set_table_schema`]: crate::storage::DatabaseEngine::set_table_schema
+//! [`Storage`]: crate::storage::Storage
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use jsonschema::Validator;
+
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::engine::{StorageEngine, TableInfo};
+use crate::storage::{index, ttl};
+
+/// Compile `schema`, rejecting schemas that are not valid JSON Schema
+pub fn compile(schema: &serde_json::Value) -> Result<Validator> {
+    jsonschema::validator_for(schema)
+        .map_err(|e| Error::InvalidArgument(format!("Invalid JSON schema: {}", e)))
+}
+
+/// Compiled schemas of the tables written to, by `db.table`
+///
+/// A table's entry is recompiled when its schema changes.
+#[derive(Default)]
+pub(crate) struct Schemas {
+    compiled: Mutex<HashMap<String, (serde_json::Value, Arc<Validator>)>>,
+}
+
+impl Schemas {
+    /// Check the documents among `writes` against their tables' schemas
+    pub(crate) async fn check(
+        &self,
+        engine: &dyn StorageEngine,
+        writes: &[(Vec<u8>, Option<Datum>)],
+    ) -> Result<()> {
+        let mut tables: HashMap<String, Option<TableInfo>> = HashMap::new();
+        for (key, value) in writes {
+            let (Some((db, table)), Some(doc)) = (index::parse_document_key(key), value) else {
+                continue;
+            };
+            let name = format!("{}.{}", db, table);
+            if !tables.contains_key(&name) {
+                let info = engine.get_table_info(&name).await?;
+                tables.insert(name.clone(), info);
+            }
+            let Some(schema) = tables[&name].as_ref().and_then(|info| info.schema.as_ref()) else {
+                continue;
+            };
+
+            let validator = self.validator(&name, schema)?;
+            let doc = serde_json::Value::from(ttl::strip_expiry(doc.clone()));
+            let violation = validator.iter_errors(&doc).next().map(|error| {
+                let path = error.instance_path.to_string();
+                format!(
+                    "Document does not match the schema of table '{}' at `{}`: {}",
+                    name,
+                    if path.is_empty() { "/" } else { &path },
+                    error
+                )
+            });
+            if let Some(message) = violation {
+                return Err(Error::InvalidArgument(message));
+            }
+        }
+        Ok(())
+    }
+
+    /// The compiled `schema` of the table `name`
+    fn validator(&self, name: &str, schema: &serde_json::Value) -> Result<Arc<Validator>> {
+        let mut compiled = self.compiled.lock().unwrap();
+        if let Some((cached, validator)) = compiled.get(name) {
+            if cached == schema {
+                return Ok(validator.clone());
+            }
+        }
+        let validator = Arc::new(compile(schema)?);
+        compiled.insert(name.to_string(), (schema.clone(), validator.clone()));
+        Ok(validator)
+    }
+}
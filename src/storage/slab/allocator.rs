@@ -1,8 +1,12 @@
 //! Slab allocator implementation
 
+use super::metadata::MetadataStore;
+use super::overflow::OverflowStore;
 use super::size_class::{calculate_size_classes, SizeClass};
 use super::slot::SlotId;
 use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
@@ -11,7 +15,8 @@ use tracing::{debug, info};
 
 /// Slab allocator for on-disk storage
 ///
-/// Manages multiple size classes, each with its own file.
+/// Manages multiple size classes, each with its own file. Values too big
+/// for the largest class go to the [`OverflowStore`].
 /// Similar to Sled's heap allocator but simplified.
 pub struct SlabAllocator {
     /// Base directory for slab files
@@ -20,6 +25,8 @@ pub struct SlabAllocator {
     size_classes: Vec<Arc<RwLock<SizeClass>>>,
     /// File handles for each size class
     files: Vec<Arc<RwLock<File>>>,
+    /// Values larger than the biggest size class
+    overflow: OverflowStore,
 }
 
 impl SlabAllocator {
@@ -57,7 +64,7 @@ impl SlabAllocator {
             size_classes.push(Arc::new(RwLock::new(sc)));
 
             // Open/create file for this size class
-            let file_path = base_path.join(format!("slab_{:04}_{}.bin", index, size));
+            let file_path = base_path.join(slab_file_name(index, size));
             let file = OpenOptions::new()
                 .read(true)
                 .write(true)
@@ -69,32 +76,34 @@ impl SlabAllocator {
             debug!("Opened slab file: {:?}", file_path);
         }
 
+        let overflow = OverflowStore::open(&base_path)?;
+
         Ok(Self {
             base_path,
             size_classes,
             files,
+            overflow,
         })
     }
 
     /// Allocate space for data of the given size
     ///
     /// Returns a SlotId that can be used to read/write the data.
-    /// Note: Size includes 4-byte length prefix overhead.
+    /// Note: Size includes 4-byte length prefix overhead. Data too large
+    /// for every size class gets an overflow slot.
     pub fn allocate(&self, size: usize) -> Result<SlotId> {
         let total_size = size + 4; // Account for 4-byte length prefix
         
         // Find the smallest size class that can fit this data
-        let size_class_idx = self
+        let Some(size_class_idx) = self
             .size_classes
             .iter()
             .position(|sc| sc.read().unwrap().can_fit(total_size))
-            .ok_or_else(|| {
-                Error::Storage(format!(
-                    "Data size {} (+4 byte prefix = {}) exceeds maximum slab size",
-                    size,
-                    total_size
-                ))
-            })?;
+        else {
+            let slot_id = SlotId::overflow(self.overflow.allocate());
+            debug!("Allocated {} bytes at {}", size, slot_id);
+            return Ok(slot_id);
+        };
 
         // Allocate from that size class
         let mut sc = self.size_classes[size_class_idx].write().unwrap();
@@ -107,7 +116,14 @@ impl SlabAllocator {
     }
 
     /// Free a previously allocated slot
+    ///
+    /// The slot's length prefix is zeroed, so a free slot can be told from
+    /// one holding a value no key refers to, see [`fsck`](super::fsck).
     pub fn free(&self, slot_id: SlotId) -> Result<()> {
+        if slot_id.is_overflow() {
+            return self.overflow.free(slot_id.offset);
+        }
+
         let size_class_idx = slot_id.file_index();
         if size_class_idx >= self.size_classes.len() {
             return Err(Error::Storage(format!(
@@ -117,6 +133,7 @@ impl SlabAllocator {
         }
 
         let mut sc = self.size_classes[size_class_idx].write().unwrap();
+        self.write_unchecked(slot_id, &[])?;
         sc.free(slot_id.offset);
 
         debug!("Freed slot {}", slot_id);
@@ -125,6 +142,10 @@ impl SlabAllocator {
 
     /// Write data to a slot
     pub fn write(&self, slot_id: SlotId, data: &[u8]) -> Result<()> {
+        if slot_id.is_overflow() {
+            return self.overflow.write(slot_id.offset, data);
+        }
+
         let size_class_idx = slot_id.file_index();
         if size_class_idx >= self.files.len() {
             return Err(Error::Storage(format!(
@@ -146,8 +167,12 @@ impl SlabAllocator {
         }
         drop(sc);
 
-        // Write to file
-        let mut file = self.files[size_class_idx].write().unwrap();
+        self.write_unchecked(slot_id, data)
+    }
+
+    /// Write to a slab file without validating the size against the class
+    fn write_unchecked(&self, slot_id: SlotId, data: &[u8]) -> Result<()> {
+        let mut file = self.files[slot_id.file_index()].write().unwrap();
         file.seek(SeekFrom::Start(slot_id.offset))
             .map_err(|e| Error::Storage(format!("Seek failed: {}", e)))?;
 
@@ -164,6 +189,10 @@ impl SlabAllocator {
 
     /// Read data from a slot
     pub fn read(&self, slot_id: SlotId) -> Result<Vec<u8>> {
+        if slot_id.is_overflow() {
+            return self.overflow.read(slot_id.offset);
+        }
+
         let size_class_idx = slot_id.file_index();
         if size_class_idx >= self.files.len() {
             return Err(Error::Storage(format!(
@@ -191,26 +220,95 @@ impl SlabAllocator {
         Ok(data)
     }
 
+    /// Rebuild the free lists of a reopened store from the slots `metadata`
+    /// refers to
+    ///
+    /// Every slot in a slab file that no key maps to is free, whether it was
+    /// freed before or written by a batch that never committed.
+    pub fn recover(&self, metadata: &MetadataStore) -> Result<()> {
+        let mut live: Vec<HashSet<u64>> = vec![HashSet::new(); self.size_classes.len()];
+        for (_, slot_id) in metadata.entries() {
+            if let Some(offsets) = live.get_mut(slot_id.file_index()) {
+                offsets.insert(slot_id.offset);
+            }
+        }
+
+        for (index, class) in self.size_classes.iter().enumerate() {
+            let mut class = class.write().unwrap();
+            let slot_size = class.slot_size as u64;
+            let file_len = self.files[index]
+                .read()
+                .unwrap()
+                .metadata()
+                .map_err(|e| Error::Storage(format!("Failed to stat slab file: {}", e)))?
+                .len();
+            // The last slot is only written up to the end of its value
+            let slot_count = file_len.div_ceil(slot_size);
+            class.reset(slot_count);
+            for offset in (0..slot_count).map(|slot| slot * slot_size) {
+                if !live[index].contains(&offset) {
+                    class.free(offset);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Get statistics about the allocator
     pub fn stats(&self) -> SlabStats {
-        let mut stats = SlabStats::default();
-
-        for (i, sc) in self.size_classes.iter().enumerate() {
-            let sc = sc.read().unwrap();
-            let class_stats = SizeClassStats {
-                index: i,
-                slot_size: sc.slot_size,
-                total_slots: sc.total_slots(),
-                free_slots: sc.free_count() as u64,
-                allocated_slots: sc.total_slots() - sc.free_count() as u64,
-            };
-            stats.size_classes.push(class_stats);
-            stats.total_allocated += class_stats.allocated_slots * sc.slot_size as u64;
+        let mut stats = SlabStats {
+            size_classes: self.size_class_stats(),
+            ..Default::default()
+        };
+        stats.total_allocated = stats
+            .size_classes
+            .iter()
+            .map(|class| class.allocated_slots * class.slot_size as u64)
+            .sum();
+
+        if let Ok((objects, bytes)) = self.overflow.usage() {
+            stats.overflow_objects = objects;
+            stats.overflow_bytes = bytes;
         }
 
         stats
     }
 
+    /// Slot usage of every size class
+    ///
+    /// Each class keeps count of its slots as they are allocated and freed,
+    /// so this takes time proportional to the number of classes only.
+    pub fn size_class_stats(&self) -> Vec<SizeClassStats> {
+        self.size_classes
+            .iter()
+            .enumerate()
+            .map(|(i, sc)| {
+                let sc = sc.read().unwrap();
+                let free_slots = sc.free_count() as u64;
+                SizeClassStats {
+                    index: i,
+                    slot_size: sc.slot_size,
+                    total_slots: sc.total_slots(),
+                    free_slots,
+                    allocated_slots: sc.total_slots() - free_slots,
+                }
+            })
+            .collect()
+    }
+
+    /// Total size of all slab and overflow files on disk (bytes)
+    pub fn disk_usage(&self) -> Result<u64> {
+        let (_, mut total) = self.overflow.usage()?;
+        for file in &self.files {
+            let file = file.read().unwrap();
+            total += file
+                .metadata()
+                .map_err(|e| Error::Storage(format!("Failed to stat slab file: {}", e)))?
+                .len();
+        }
+        Ok(total)
+    }
+
     /// Flush all files to disk
     pub fn flush(&self) -> Result<()> {
         for file in &self.files {
@@ -220,6 +318,122 @@ impl SlabAllocator {
         }
         Ok(())
     }
+
+    /// Move live slots into the gaps left by freed ones and truncate each
+    /// slab file after its last live slot
+    ///
+    /// Live slots are the ones `metadata` maps a key to. Moved values are
+    /// copied and synced first, then one metadata batch points their keys
+    /// at the new slots, so the old slots stay readable until that swap.
+    /// Callers must keep writers out meanwhile: a slot that is allocated
+    /// but not yet committed to `metadata` would be treated as free.
+    pub fn compact(&self, metadata: &MetadataStore) -> Result<SlabCompaction> {
+        let bytes_before = self.disk_usage()?;
+
+        // Nothing may be allocated or freed while slots move
+        let mut classes: Vec<_> = self
+            .size_classes
+            .iter()
+            .map(|sc| sc.write().unwrap())
+            .collect();
+
+        let mut live: Vec<Vec<(Vec<u8>, u64)>> = vec![Vec::new(); classes.len()];
+        for (key, slot_id) in metadata.entries() {
+            // Overflow objects have no slab to compact
+            if let Some(slots) = live.get_mut(slot_id.file_index()) {
+                slots.push((key, slot_id.offset));
+            }
+        }
+
+        // Fill gaps below each class's new end with the slots past it
+        let mut moves = Vec::new();
+        for (index, slots) in live.iter().enumerate() {
+            let slot_size = classes[index].slot_size as u64;
+            let end = slots.len() as u64 * slot_size;
+            let occupied: HashSet<u64> = slots
+                .iter()
+                .map(|(_, offset)| *offset)
+                .filter(|offset| *offset < end)
+                .collect();
+            let mut gaps = (0..slots.len() as u64)
+                .map(|slot| slot * slot_size)
+                .filter(|offset| !occupied.contains(offset));
+
+            for (key, offset) in slots.iter().filter(|(_, offset)| *offset >= end) {
+                let from = SlotId::new(index as u16, *offset);
+                let to = gaps.next().ok_or_else(|| {
+                    Error::Storage(format!("No free slot below {} to move {} into", end, from))
+                })?;
+                let to = SlotId::new(index as u16, to);
+                self.write_unchecked(to, &self.read(from)?)?;
+                moves.push((key.clone(), to));
+            }
+        }
+
+        let slots_moved = moves.len() as u64;
+        if !moves.is_empty() {
+            // Copies must be durable before the metadata points at them,
+            // and the swap before the old slots are truncated
+            self.flush()?;
+            metadata.commit_batch(moves, Vec::new())?;
+            metadata.flush()?;
+        }
+
+        for (index, class) in classes.iter_mut().enumerate() {
+            let slot_count = live[index].len() as u64;
+            class.reset(slot_count);
+            self.files[index]
+                .write()
+                .unwrap()
+                .set_len(slot_count * class.slot_size as u64)
+                .map_err(|e| Error::Storage(format!("Failed to truncate slab file: {}", e)))?;
+        }
+        drop(classes);
+
+        let report = SlabCompaction {
+            slots_moved,
+            bytes_before,
+            bytes_after: self.disk_usage()?,
+        };
+        info!(
+            moved = report.slots_moved,
+            reclaimed = report.bytes_reclaimed(),
+            "Slab compaction complete"
+        );
+        Ok(report)
+    }
+}
+
+/// Name of the slab file of size class `index`
+pub(super) fn slab_file_name(index: usize, slot_size: usize) -> String {
+    format!("slab_{:04}_{}.bin", index, slot_size)
+}
+
+/// Size class index and slot size of a slab file, from its name
+pub(super) fn parse_slab_file_name(name: &str) -> Option<(u16, usize)> {
+    let (index, slot_size) = name
+        .strip_prefix("slab_")?
+        .strip_suffix(".bin")?
+        .split_once('_')?;
+    Some((index.parse().ok()?, slot_size.parse().ok()?))
+}
+
+/// Result of [`SlabAllocator::compact`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlabCompaction {
+    /// Live slots relocated into gaps
+    pub slots_moved: u64,
+    /// Slab and overflow files on disk before compaction (bytes)
+    pub bytes_before: u64,
+    /// Slab and overflow files on disk afterwards (bytes)
+    pub bytes_after: u64,
+}
+
+impl SlabCompaction {
+    /// Bytes freed on disk
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
 }
 
 /// Statistics for the slab allocator
@@ -227,9 +441,13 @@ impl SlabAllocator {
 pub struct SlabStats {
     pub size_classes: Vec<SizeClassStats>,
     pub total_allocated: u64,
+    /// Values stored in the overflow store
+    pub overflow_objects: u64,
+    /// Size of the overflow store on disk (bytes)
+    pub overflow_bytes: u64,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct SizeClassStats {
     pub index: usize,
     pub slot_size: usize,
@@ -291,6 +509,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_allocator_recover() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_test_recover_{}", uuid::Uuid::new_v4()));
+        let metadata = MetadataStore::new(temp_dir.join("metadata"))?;
+        let slots = {
+            let allocator = SlabAllocator::new(temp_dir.join("data"), Some(64), Some(256))?;
+            let slots: Vec<_> = (0..3).map(|_| allocator.allocate(50).unwrap()).collect();
+            for slot in &slots {
+                allocator.write(*slot, b"value")?;
+            }
+            metadata.write_batch(vec![(b"a".to_vec(), slots[0]), (b"c".to_vec(), slots[2])])?;
+            slots
+        };
+
+        // The unreferenced middle slot is reused, then the file grows
+        let allocator = SlabAllocator::new(temp_dir.join("data"), Some(64), Some(256))?;
+        allocator.recover(&metadata)?;
+        assert_eq!(allocator.allocate(50)?, slots[1]);
+        assert!(allocator.allocate(50)?.offset > slots[2].offset);
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
     #[test]
     fn test_allocator_stats() -> Result<()> {
         let temp_dir = std::env::temp_dir().join(format!("slab_test_stats_{}", std::process::id()));
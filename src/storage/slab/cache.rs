@@ -2,6 +2,7 @@
 
 use super::slot::SlotId;
 use lru::LruCache;
+use serde::Serialize;
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 
@@ -85,7 +86,7 @@ impl SlabCache {
 }
 
 /// Cache statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CacheStats {
     pub hits: u64,
     pub misses: u64,
@@ -1,47 +1,100 @@
 //! Compression support for slab storage
+//!
+//! Compressing data that is already compressed (images, archives, random
+//! bytes) costs CPU and saves next to nothing, so under
+//! [`CompressionAlgorithm::Zstd`] a value whose compressed form is not
+//! smaller than [`MAX_COMPRESSED_RATIO`] of the original is stored as is,
+//! behind a one-byte marker. Zstd frames always start with the magic number
+//! `28 b5 2f fd`, so the marker never clashes with compressed values,
+//! including those written before values could be stored raw.
+//!
+//! Zstd frames carry a checksum of their content, so a damaged value fails
+//! to decompress instead of decoding to garbage. Values written before
+//! frames had checksums still decode.
 
 use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
 use std::io::Write;
 
 /// Compression algorithm
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompressionAlgorithm {
     None,
     Zstd,
 }
 
+/// Largest compressed/original size ratio worth storing compressed
+pub const MAX_COMPRESSED_RATIO: f64 = 0.9;
+
+/// First byte of a value stored uncompressed under Zstd
+const RAW_MARKER: u8 = 0x00;
+
+/// How a value ended up stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredMode {
+    Compressed,
+    Raw,
+}
+
 /// Compress data using specified algorithm
+///
+/// With Zstd, data that does not compress below [`MAX_COMPRESSED_RATIO`]
+/// is stored uncompressed instead.
 pub fn compress(data: &[u8], algorithm: CompressionAlgorithm) -> Result<Vec<u8>> {
     match algorithm {
         CompressionAlgorithm::None => Ok(data.to_vec()),
         CompressionAlgorithm::Zstd => {
             let mut encoder = zstd::Encoder::new(Vec::new(), 3)
                 .map_err(|e| Error::Storage(format!("Failed to create zstd encoder: {}", e)))?;
+            encoder
+                .include_checksum(true)
+                .map_err(|e| Error::Storage(format!("Failed to enable zstd checksums: {}", e)))?;
             encoder
                 .write_all(data)
                 .map_err(|e| Error::Storage(format!("Failed to compress: {}", e)))?;
-            encoder
+            let compressed = encoder
                 .finish()
-                .map_err(|e| Error::Storage(format!("Failed to finish compression: {}", e)))
+                .map_err(|e| Error::Storage(format!("Failed to finish compression: {}", e)))?;
+
+            if compressed.len() as f64 <= data.len() as f64 * MAX_COMPRESSED_RATIO {
+                return Ok(compressed);
+            }
+            let mut raw = Vec::with_capacity(data.len() + 1);
+            raw.push(RAW_MARKER);
+            raw.extend_from_slice(data);
+            Ok(raw)
         }
     }
 }
 
 /// Decompress data using specified algorithm
 pub fn decompress(data: &[u8], algorithm: CompressionAlgorithm) -> Result<Vec<u8>> {
-    match algorithm {
-        CompressionAlgorithm::None => Ok(data.to_vec()),
-        CompressionAlgorithm::Zstd => zstd::decode_all(data)
+    match (algorithm, stored_mode(data, algorithm)) {
+        (CompressionAlgorithm::None, _) => Ok(data.to_vec()),
+        (CompressionAlgorithm::Zstd, StoredMode::Raw) => Ok(data[1..].to_vec()),
+        (CompressionAlgorithm::Zstd, StoredMode::Compressed) => zstd::decode_all(data)
             .map_err(|e| Error::Storage(format!("Failed to decompress: {}", e))),
     }
 }
 
+/// Whether `data`, as returned by [`compress`], holds a compressed value
+pub fn stored_mode(data: &[u8], algorithm: CompressionAlgorithm) -> StoredMode {
+    match algorithm {
+        CompressionAlgorithm::Zstd if data.first() != Some(&RAW_MARKER) => StoredMode::Compressed,
+        _ => StoredMode::Raw,
+    }
+}
+
 /// Compression statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CompressionStats {
     pub original_size: usize,
     pub compressed_size: usize,
     pub ratio: f64,
+    /// Values measured
+    pub values: usize,
+    /// Values stored uncompressed because compression did not pay off
+    pub skipped: usize,
 }
 
 impl CompressionStats {
@@ -55,9 +108,18 @@ impl CompressionStats {
             original_size,
             compressed_size,
             ratio,
+            values: 0,
+            skipped: 0,
         }
     }
 
+    /// Set how many of `values` were stored uncompressed
+    pub fn with_skipped(mut self, values: usize, skipped: usize) -> Self {
+        self.values = values;
+        self.skipped = skipped;
+        self
+    }
+
     /// Calculate space saved (percentage)
     pub fn space_saved_percent(&self) -> f64 {
         (1.0 - self.ratio) * 100.0
@@ -92,6 +154,45 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_incompressible_data_stored_raw() -> Result<()> {
+        let text = b"The quick brown fox jumps over the lazy dog. ".repeat(50);
+        let stored = compress(&text, CompressionAlgorithm::Zstd)?;
+        assert_eq!(
+            stored_mode(&stored, CompressionAlgorithm::Zstd),
+            StoredMode::Compressed
+        );
+        assert!(stored.len() < text.len() / 4);
+        assert_eq!(decompress(&stored, CompressionAlgorithm::Zstd)?, text);
+
+        // xorshift noise does not compress
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let stored = compress(&random, CompressionAlgorithm::Zstd)?;
+        assert_eq!(
+            stored_mode(&stored, CompressionAlgorithm::Zstd),
+            StoredMode::Raw
+        );
+        assert_eq!(stored.len(), random.len() + 1);
+        assert_eq!(decompress(&stored, CompressionAlgorithm::Zstd)?, random);
+
+        // Too short to gain anything from a zstd frame
+        let stored = compress(b"", CompressionAlgorithm::Zstd)?;
+        assert_eq!(
+            stored_mode(&stored, CompressionAlgorithm::Zstd),
+            StoredMode::Raw
+        );
+        assert!(decompress(&stored, CompressionAlgorithm::Zstd)?.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_compression_stats() {
         let stats = CompressionStats::new(1000, 250);
@@ -1,19 +1,66 @@
 //! StorageEngine trait implementation for SlabStorage
 
-use super::storage::SlabStorage as InnerSlabStorage;
+use super::allocator::SizeClassStats;
+use super::metadata::SyncPolicy;
+use super::storage::{CompactionReport, SlabStorage as InnerSlabStorage, StorageStats};
+use crate::cluster::metrics::storage_op_timer;
 use crate::error::{Error, Result};
 use crate::reql::Datum;
-use crate::storage::engine::{StorageEngine, TableInfo};
+use crate::storage::engine::{document_range, non_empty, StorageEngine, TableInfo};
 use async_trait::async_trait;
+use std::ops::Bound;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
 use tracing::{debug, warn};
 
+/// Document count for a single table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStats {
+    pub db: String,
+    pub table: String,
+    pub documents: usize,
+}
+
 /// Slab storage engine that implements StorageEngine trait
 ///
 /// This is a wrapper around the core SlabStorage that provides
 /// async compatibility and Datum serialization.
+///
+/// [`StorageEngine::write_batch`] commits its writes as a single metadata
+/// batch with one fsync; bulk loads collect their writes in a
+/// [`Transaction`](crate::storage::Transaction) to get there.
+///
+/// Writes go through a queue: at most a few are applied at
+/// once and the rest wait their turn. With a high-water mark set
+/// ([`SlabStorageEngine::with_max_pending_writes`]) writes are refused with
+/// [`Error::Busy`] while that many are queued or in progress, so the server
+/// sheds load instead of queueing it.
 pub struct SlabStorageEngine {
     inner: InnerSlabStorage,
+    /// Permits to apply a write to `inner`
+    writers: Semaphore,
+    /// Writes waiting for or holding a permit
+    queued: AtomicUsize,
+    /// Queued writes at which new writes are refused, 0 for no limit
+    max_pending_writes: usize,
+}
+
+/// Writes applied to the store at once; further writes wait in the queue
+const WRITE_CONCURRENCY: usize = 4;
+
+/// A write's place in the queue, counted in
+/// [`SlabStorageEngine::pending_writes`] until dropped
+struct QueuedWrite<'a> {
+    counter: &'a AtomicUsize,
+    /// Taken once it is this write's turn
+    _permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for QueuedWrite<'_> {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl SlabStorageEngine {
@@ -24,7 +71,12 @@ impl SlabStorageEngine {
         max_slot_size: Option<usize>,
     ) -> Result<Self> {
         let inner = InnerSlabStorage::new(base_path, min_slot_size, max_slot_size)?;
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            writers: Semaphore::new(WRITE_CONCURRENCY),
+            queued: AtomicUsize::new(0),
+            max_pending_writes: 0,
+        })
     }
 
     /// Create with default settings (64B - 64KB)
@@ -32,6 +84,123 @@ impl SlabStorageEngine {
         Self::new(base_path, None, None)
     }
 
+    /// Defer fsyncs until [`StorageEngine::flush`], see [`InnerSlabStorage::with_sync_writes`]
+    pub fn with_sync_writes(self, sync: bool) -> Self {
+        Self {
+            inner: self.inner.with_sync_writes(sync),
+            ..self
+        }
+    }
+
+    /// Choose when metadata batches are fsynced, see [`SyncPolicy`]
+    pub fn with_sync_policy(self, policy: SyncPolicy) -> Self {
+        Self {
+            inner: self.inner.with_sync_policy(policy),
+            ..self
+        }
+    }
+
+    /// Size the pool for parallel storage work, 0 for one thread per core,
+    /// see [`InnerSlabStorage::with_threads`]
+    pub fn with_threads(self, threads: usize) -> Self {
+        Self {
+            inner: self.inner.with_threads(threads),
+            ..self
+        }
+    }
+
+    /// Refuse writes with [`Error::Busy`] while `max` writes are pending,
+    /// 0 for no limit
+    ///
+    /// Pending writes are those waiting in the write queue plus those being
+    /// applied. A write batch counts once however many keys it holds.
+    pub fn with_max_pending_writes(self, max: usize) -> Self {
+        Self {
+            max_pending_writes: max,
+            ..self
+        }
+    }
+
+    /// Join the write queue and wait for a turn to write, or fail at once
+    /// if the high-water mark is reached
+    async fn enqueue(&self) -> Result<QueuedWrite<'_>> {
+        let pending = self.queued.fetch_add(1, Ordering::SeqCst);
+        let mut queued = QueuedWrite {
+            counter: &self.queued,
+            _permit: None,
+        };
+        if self.max_pending_writes > 0 && pending >= self.max_pending_writes {
+            return Err(Error::Busy(format!(
+                "storage overloaded: {} writes pending",
+                pending
+            )));
+        }
+        let permit = self
+            .writers
+            .acquire()
+            .await
+            .map_err(|e| Error::Storage(format!("Write queue closed: {}", e)))?;
+        queued._permit = Some(permit);
+        Ok(queued)
+    }
+
+    /// Compact the underlying storage
+    pub fn compact(&self) -> Result<CompactionReport> {
+        self.inner.compact()
+    }
+
+    /// Storage, cache and slot statistics
+    pub fn stats(&self) -> StorageStats {
+        self.inner.stats()
+    }
+
+    /// Document counts for every table, counted from the key index
+    /// without reading any documents
+    pub fn table_stats(&self) -> Vec<TableStats> {
+        let keys = self.inner.keys();
+        let table_prefix = b"__meta__:tables:";
+
+        let mut tables: Vec<TableStats> = keys
+            .iter()
+            .filter_map(|k| k.strip_prefix(table_prefix.as_slice()))
+            .filter_map(|name| String::from_utf8(name.to_vec()).ok())
+            .filter_map(|name| {
+                let (db, table) = name.split_once('.')?;
+                Some(TableStats {
+                    db: db.to_string(),
+                    table: table.to_string(),
+                    documents: 0,
+                })
+            })
+            .collect();
+
+        for stats in &mut tables {
+            let prefix = format!("doc:{}:{}:", stats.db, stats.table);
+            stats.documents = keys
+                .iter()
+                .filter(|k| k.starts_with(prefix.as_bytes()))
+                .count();
+        }
+
+        tables.sort_by(|a, b| (&a.db, &a.table).cmp(&(&b.db, &b.table)));
+        tables
+    }
+
+    /// Keys in the store
+    fn keys(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self.inner.keys())
+    }
+
+    /// Keys starting with `prefix` in key order
+    fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Ok(self.inner.keys_with_prefix(prefix))
+    }
+
+    /// Keys within `range` in key order
+    fn keys_in_range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Result<Vec<Vec<u8>>> {
+        Ok(self.inner.keys_in_range(range))
+    }
+
     /// Serialize Datum to bytes
     fn datum_to_bytes(datum: &Datum) -> Result<Vec<u8>> {
         serde_json::to_vec(datum)
@@ -58,6 +227,7 @@ impl StorageEngine for SlabStorageEngine {
     }
 
     async fn set(&self, key: &[u8], value: Datum) -> Result<()> {
+        let _queued = self.enqueue().await?;
         let bytes = Self::datum_to_bytes(&value)?;
         self.inner.set(key, &bytes)?;
         debug!(key_len = key.len(), value_len = bytes.len(), "Set key-value");
@@ -65,14 +235,44 @@ impl StorageEngine for SlabStorageEngine {
     }
 
     async fn delete(&self, key: &[u8]) -> Result<()> {
+        let _queued = self.enqueue().await?;
         self.inner.delete(key)?;
         Ok(())
     }
 
+    async fn write_batch(&self, writes: Vec<(Vec<u8>, Option<Datum>)>) -> Result<()> {
+        let _queued = self.enqueue().await?;
+        let mut sets = Vec::new();
+        let mut deletes = Vec::new();
+        for (key, value) in writes {
+            match value {
+                Some(value) => sets.push((key, Self::datum_to_bytes(&value)?)),
+                None => deletes.push(key),
+            }
+        }
+        self.inner.write_batch(sets, deletes)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    async fn stats(&self) -> Option<StorageStats> {
+        Some(self.inner.stats())
+    }
+
+    fn size_class_stats(&self) -> Vec<SizeClassStats> {
+        self.inner.size_class_stats()
+    }
+
+    fn pending_writes(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
     async fn list_tables(&self) -> Result<Vec<String>> {
         // Scan for keys with prefix "__meta__:tables:"
         let prefix = b"__meta__:tables:";
-        let keys = self.inner.keys();
+        let keys = self.keys()?;
         
         let tables: Vec<String> = keys
             .into_iter()
@@ -88,57 +288,14 @@ impl StorageEngine for SlabStorageEngine {
     async fn get_table_info(&self, table_name: &str) -> Result<Option<TableInfo>> {
         let key = format!("__meta__:tables:{}", table_name);
         match self.get(key.as_bytes()).await? {
-            Some(datum) => {
-                // Manual extraction to handle type conversions
-                if let Datum::Object(ref obj) = datum {
-                    let name = obj.get("name")
-                        .and_then(|d| d.as_string())
-                        .ok_or_else(|| Error::Storage("Missing 'name' field".to_string()))?
-                        .to_string();
-                    
-                    let db = obj.get("db")
-                        .and_then(|d| d.as_string())
-                        .ok_or_else(|| Error::Storage("Missing 'db' field".to_string()))?
-                        .to_string();
-                    
-                    let primary_key = obj.get("primary_key")
-                        .and_then(|d| d.as_string())
-                        .ok_or_else(|| Error::Storage("Missing 'primary_key' field".to_string()))?
-                        .to_string();
-                    
-                    let doc_count = obj.get("doc_count")
-                        .and_then(|d| d.as_number())
-                        .unwrap_or(0.0) as u64;
-                    
-                    let indexes = obj.get("indexes")
-                        .and_then(|d| d.as_array())
-                        .map(|arr| {
-                            arr.iter()
-                                .filter_map(|d| d.as_string().map(|s| s.to_string()))
-                                .collect()
-                        })
-                        .unwrap_or_default();
-                    
-                    let info = TableInfo {
-                        name,
-                        db,
-                        primary_key,
-                        doc_count,
-                        indexes,
-                    };
-                    
-                    Ok(Some(info))
-                } else {
-                    Err(Error::Storage("Table info is not an object".to_string()))
-                }
-            }
+            Some(datum) => Ok(Some(TableInfo::from_datum(&datum)?)),
             None => Ok(None),
         }
     }
 
     async fn list_databases(&self) -> Result<Vec<String>> {
         let prefix = b"__meta__:databases:";
-        let keys = self.inner.keys();
+        let keys = self.keys()?;
         
         let dbs: Vec<String> = keys
             .into_iter()
@@ -181,7 +338,7 @@ impl StorageEngine for SlabStorageEngine {
     async fn list_tables_in_db(&self, db: &str) -> Result<Vec<String>> {
         let prefix_str = format!("__meta__:tables:{}.", db);
         let prefix = prefix_str.as_bytes();
-        let keys = self.inner.keys();
+        let keys = self.keys()?;
         
         let tables: Vec<String> = keys
             .into_iter()
@@ -229,26 +386,100 @@ impl StorageEngine for SlabStorageEngine {
     }
 
     async fn scan_table(&self, db: &str, table: &str) -> Result<Vec<Datum>> {
+        let _timer = storage_op_timer("scan");
         let prefix = format!("doc:{}:{}:", db, table);
-        let keys = self.inner.keys();
-        
+
         let mut docs = Vec::new();
-        for key in keys {
-            if String::from_utf8_lossy(&key).starts_with(&prefix) {
-                if let Some(datum) = self.get(&key).await? {
-                    docs.push(datum);
-                }
+        for key in self.keys_with_prefix(prefix.as_bytes())? {
+            if let Some(datum) = self.get(&key).await? {
+                docs.push(datum);
             }
         }
         
         Ok(docs)
     }
+
+    async fn scan_table_page(
+        &self,
+        db: &str,
+        table: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let _timer = storage_op_timer("scan");
+        let prefix = format!("doc:{}:{}:", db, table);
+        let keys: Vec<Vec<u8>> = self
+            .keys_with_prefix(prefix.as_bytes())?
+            .into_iter()
+            .filter(|key| after.is_none_or(|after| key.as_slice() > after))
+            .collect();
+
+        let mut page = Vec::new();
+        for key in keys.into_iter().take(limit) {
+            if let Some(datum) = self.get(&key).await? {
+                page.push((key, datum));
+            }
+        }
+
+        Ok(page)
+    }
+
+    async fn scan_range(
+        &self,
+        db: &str,
+        table: &str,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let _timer = storage_op_timer("scan");
+        let Some(range) = document_range(db, table, start, end) else {
+            return Ok(Vec::new());
+        };
+
+        let mut docs = Vec::new();
+        for key in self.keys_in_range(range)? {
+            if let Some(datum) = self.get(&key).await? {
+                docs.push((key, datum));
+            }
+        }
+
+        Ok(docs)
+    }
+
+    async fn scan_keys(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> Result<Vec<(Vec<u8>, Datum)>> {
+        let _timer = storage_op_timer("scan");
+        let Some(range) = non_empty((start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec))) else {
+            return Ok(Vec::new());
+        };
+
+        let mut entries = Vec::new();
+        for key in self.keys_in_range(range)? {
+            if let Some(datum) = self.get(&key).await? {
+                entries.push((key, datum));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    async fn count_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<u64> {
+        let Some(range) = non_empty((start.map(<[u8]>::to_vec), end.map(<[u8]>::to_vec))) else {
+            return Ok(0);
+        };
+        Ok(self.inner.count_in_range(range) as u64)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::reql::Datum;
+    use crate::storage::{DatabaseEngine, Storage, StorageDatabaseEngine};
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_slab_engine_basic() -> Result<()> {
@@ -323,4 +554,370 @@ mod tests {
         std::fs::remove_dir_all(temp_dir).ok();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_scan_table_key_order() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_order_{}", std::process::id()));
+        std::fs::remove_dir_all(&temp_dir).ok();
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)?;
+
+        for id in ["m", "c", "x", "a", "q"] {
+            let key = format!("doc:test:users:{}", id);
+            engine.set(key.as_bytes(), Datum::from(id)).await?;
+        }
+        engine
+            .set(b"doc:test:users2:b", Datum::from("other table"))
+            .await?;
+
+        // Repeated scans agree and are sorted by key
+        let sorted: Vec<Datum> = ["a", "c", "m", "q", "x"].map(Datum::from).to_vec();
+        assert_eq!(engine.scan_table("test", "users").await?, sorted);
+        assert_eq!(engine.scan_table("test", "users").await?, sorted);
+        let page = engine.scan_table_page("test", "users", None, 10).await?;
+        let page: Vec<Datum> = page.into_iter().map(|(_, doc)| doc).collect();
+        assert_eq!(page, sorted);
+
+        // The order survives recovering the index from the log
+        drop(engine);
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)?;
+        assert_eq!(engine.scan_table("test", "users").await?, sorted);
+
+        // Tables are counted from the key index alone
+        let storage = Storage::new(Box::new(engine));
+        assert_eq!(storage.count_table("test", "users").await?, 5);
+        assert_eq!(storage.count_table("test", "users2").await?, 1);
+        assert_eq!(storage.count_table("test", "missing").await?, 0);
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slab_engine_compact() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_compact_{}", std::process::id()));
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)?;
+
+        // Repeated overwrites grow the metadata log
+        for i in 0..20 {
+            engine.set(b"doc:test:users:1", Datum::Number(i as f64)).await?;
+        }
+
+        let report = engine.compact()?;
+        assert!(report.metadata_bytes_after < report.metadata_bytes_before);
+        assert!(report.bytes_reclaimed() > 0);
+        assert_eq!(
+            engine.get(b"doc:test:users:1").await?,
+            Some(Datum::Number(19.0))
+        );
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slab_engine_stats() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_stats_{}", std::process::id()));
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)?;
+
+        engine.create_database("test").await?;
+        engine.create_table("test", "users", "id").await?;
+        engine.create_table("test", "posts", "id").await?;
+        for i in 0..3 {
+            let key = format!("doc:test:users:{}", i);
+            engine.set(key.as_bytes(), Datum::Number(i as f64)).await?;
+        }
+
+        let tables = engine.table_stats();
+        assert_eq!(
+            tables,
+            vec![
+                TableStats {
+                    db: "test".to_string(),
+                    table: "posts".to_string(),
+                    documents: 0,
+                },
+                TableStats {
+                    db: "test".to_string(),
+                    table: "users".to_string(),
+                    documents: 3,
+                },
+            ]
+        );
+
+        let stats = engine.stats();
+        assert!(stats.disk_bytes > 0);
+        assert!(stats
+            .size_class_stats
+            .iter()
+            .any(|class| class.allocated_slots > 0));
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flush_makes_deferred_writes_durable() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_flush_{}", std::process::id()));
+        let storage = crate::storage::Storage::new(Box::new(
+            SlabStorageEngine::with_defaults(&temp_dir)?.with_sync_writes(false),
+        ));
+
+        storage.create_database("bulk").await?;
+        storage.create_table("bulk", "items", "id").await?;
+        for i in 0..50 {
+            let key = format!("doc:bulk:items:{}", i);
+            storage.set(key.as_bytes(), Datum::Number(i as f64)).await?;
+        }
+        storage.flush().await?;
+        drop(storage);
+
+        let reopened = SlabStorageEngine::with_defaults(&temp_dir)?;
+        assert_eq!(reopened.scan_table("bulk", "items").await?.len(), 50);
+        assert_eq!(reopened.get(b"doc:bulk:items:49").await?, Some(Datum::Number(49.0)));
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_storage_op_metrics() -> Result<()> {
+        use crate::cluster::metrics::{export_metrics, init_metrics, STORAGE_OP_DURATION};
+
+        init_metrics();
+        let count = |op: &str| STORAGE_OP_DURATION.with_label_values(&[op]).get_sample_count();
+        let [gets, sets, deletes, scans] = ["get", "set", "delete", "scan"].map(count);
+
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_metrics_{}", std::process::id()));
+        let engine = SlabStorageEngine::with_defaults(&temp_dir)?;
+        engine.set(b"doc:db:t:1", Datum::Number(1.0)).await?;
+        engine.set(b"doc:db:t:2", Datum::Number(2.0)).await?;
+        engine.get(b"doc:db:t:1").await?;
+        engine.scan_table("db", "t").await?;
+        engine.delete(b"doc:db:t:2").await?;
+
+        // Other tests share the global histograms, so only check growth
+        assert!(count("set") >= sets + 2);
+        assert!(count("get") > gets);
+        assert!(count("delete") > deletes);
+        assert!(count("scan") > scans);
+
+        let exported = export_metrics();
+        assert!(exported.contains("photondb_storage_op_duration_seconds_bucket{operation=\"get\""));
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slab_slot_metrics() -> Result<()> {
+        use crate::cluster::metrics::{
+            export_metrics, init_metrics, MetricsCollector, SLAB_SLOTS_FREE, SLAB_SLOTS_USED,
+        };
+
+        init_metrics();
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_slot_metrics_{}", uuid::Uuid::new_v4()));
+        let engine = SlabStorageEngine::new(&temp_dir, Some(64), Some(1024))?;
+
+        // Random hex digits compress poorly, so the short values fit the
+        // smallest class and the long ones do not
+        let text = |uuids: usize| {
+            (0..uuids)
+                .map(|_| uuid::Uuid::new_v4().to_string())
+                .collect::<String>()
+        };
+        for i in 0..5 {
+            engine
+                .set(
+                    format!("doc:db:t:small{}", i).as_bytes(),
+                    Datum::from(text(1)),
+                )
+                .await?;
+        }
+        for i in 0..3 {
+            engine
+                .set(
+                    format!("doc:db:t:large{}", i).as_bytes(),
+                    Datum::from(text(12)),
+                )
+                .await?;
+        }
+
+        let classes = engine.size_class_stats();
+        MetricsCollector::new().update_slab_metrics(&classes);
+        let used = |size: usize| {
+            SLAB_SLOTS_USED
+                .with_label_values(&[&size.to_string()])
+                .get()
+        };
+        assert_eq!(used(64), 5);
+        let larger: i64 = classes
+            .iter()
+            .skip(1)
+            .map(|class| used(class.slot_size))
+            .sum();
+        assert_eq!(larger, 3);
+        for class in &classes {
+            assert_eq!(used(class.slot_size), class.allocated_slots as i64);
+            let free = SLAB_SLOTS_FREE
+                .with_label_values(&[&class.slot_size.to_string()])
+                .get();
+            assert_eq!(free, class.free_slots as i64);
+        }
+        assert!(export_metrics().contains("photondb_slab_slots_used{class=\"64\"} 5"));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    /// Insert `count` documents into `test.users` through `set_document`, or
+    /// all at once through `set_documents`, returning how many metadata
+    /// batches and fsyncs that took
+    async fn insert_documents(
+        storage: &Arc<Storage>,
+        count: usize,
+        batched: bool,
+    ) -> Result<(u64, u64)> {
+        let databases = StorageDatabaseEngine::new(storage.clone());
+        let before = storage.stats().await.unwrap();
+        let docs = (0..count).map(|i| {
+            let doc = format!(r#"{{"id":"{}","n":{}}}"#, i, i);
+            (i.to_string().into_bytes(), doc.into_bytes())
+        });
+        if batched {
+            databases
+                .set_documents("test", "users", docs.collect())
+                .await?;
+        } else {
+            for (key, doc) in docs {
+                databases.set_document("test", "users", &key, doc).await?;
+            }
+        }
+        let after = storage.stats().await.unwrap();
+        Ok((
+            after.metadata_batches - before.metadata_batches,
+            after.metadata_syncs - before.metadata_syncs,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_slab_engine_batch_mode() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_batch_{}", std::process::id()));
+        std::fs::remove_dir_all(&temp_dir).ok();
+        let open = |name: &str| -> Result<Arc<Storage>> {
+            let engine = SlabStorageEngine::with_defaults(temp_dir.join(name))?;
+            Ok(Arc::new(Storage::new(Box::new(engine))))
+        };
+
+        let mut counts = Vec::new();
+        for (name, batched) in [("single", false), ("batched", true)] {
+            let storage = open(name)?;
+            let databases = StorageDatabaseEngine::new(storage.clone());
+            databases.create_database("test").await?;
+            databases.create_table("test", "users").await?;
+            counts.push(insert_documents(&storage, 1000, batched).await?);
+        }
+
+        let (single_batches, single_syncs) = counts[0];
+        assert!(single_batches >= 1000, "{} batches", single_batches);
+        assert!(single_syncs >= 1000, "{} fsyncs", single_syncs);
+        assert_eq!(counts[1], (1, 1));
+
+        // Everything committed by the batch survives a reopen
+        let databases = StorageDatabaseEngine::new(open("batched")?);
+        assert_eq!(databases.count_documents("test", "users").await?, 1000);
+
+        // A transaction only holds its owner's writes; other clients write
+        // through while it is open
+        let storage = open("scoped")?;
+        let mut txn = storage.transaction();
+        txn.set(b"mine", Datum::Number(1.0));
+        storage.set(b"theirs", Datum::Number(2.0)).await?;
+        assert_eq!(storage.get(b"theirs").await?, Some(Datum::Number(2.0)));
+        assert_eq!(storage.get(b"mine").await?, None);
+        assert_eq!(txn.get(b"mine").await?, Some(Datum::Number(1.0)));
+        let before = storage.stats().await.unwrap().metadata_syncs;
+        txn.commit().await?;
+        assert_eq!(storage.stats().await.unwrap().metadata_syncs, before + 1);
+        assert_eq!(storage.get(b"mine").await?, Some(Datum::Number(1.0)));
+
+        // Cleanup
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_when_overloaded() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_engine_backpressure_{}", std::process::id()));
+        std::fs::remove_dir_all(&temp_dir).ok();
+        let engine =
+            Arc::new(SlabStorageEngine::with_defaults(&temp_dir)?.with_max_pending_writes(10));
+        let storage = Arc::new(Storage::new(Box::new(
+            SlabStorageEngine::with_defaults(temp_dir.join("txn"))?.with_max_pending_writes(1),
+        )));
+
+        // Hold up the writers so that writes pile up in the queue, more of
+        // them than this single-threaded runtime has threads
+        let held = engine
+            .writers
+            .acquire_many(WRITE_CONCURRENCY as u32)
+            .await
+            .unwrap();
+        let queued: Vec<_> = (0..10u8)
+            .map(|i| {
+                let engine = engine.clone();
+                tokio::spawn(async move { engine.set(&[i], Datum::Number(i as f64)).await })
+            })
+            .collect();
+        while engine.pending_writes() < 10 {
+            tokio::task::yield_now().await;
+        }
+
+        // Further writes are refused as retryable until it drains
+        let busy = |result: Result<()>| matches!(result, Err(Error::Busy(_)));
+        for _ in 0..3 {
+            assert!(busy(engine.set(b"more", Datum::Null).await));
+            assert!(busy(engine.delete(&[0]).await));
+            assert!(busy(
+                engine
+                    .write_batch(vec![(b"more".to_vec(), Some(Datum::Null))])
+                    .await
+            ));
+        }
+        assert_eq!(engine.pending_writes(), 10);
+
+        drop(held);
+        for write in queued {
+            write.await.unwrap()?;
+        }
+        assert_eq!(engine.pending_writes(), 0);
+        assert_eq!(engine.get(&[3]).await?, Some(Datum::Number(3.0)));
+        engine.set(b"more", Datum::Null).await?;
+        assert_eq!(engine.get(b"more").await?, Some(Datum::Null));
+
+        // Writes into an open transaction are never refused, and its commit
+        // is a single queued write however many keys it holds
+        let mut txn = storage.transaction();
+        for i in 0..20u8 {
+            txn.set(&[b'b', i], Datum::Null);
+        }
+        txn.commit().await?;
+        assert_eq!(storage.pending_writes(), 0);
+        assert_eq!(storage.get(&[b'b', 19]).await?, Some(Datum::Null));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
 }
@@ -0,0 +1,521 @@
+//! Offline consistency check for slab stores
+//!
+//! [`fsck`] inspects a store that no server has open: it replays the
+//! metadata log and reads the slab and overflow files directly, so it never
+//! changes anything unless asked to repair.
+//!
+//! # Checks
+//!
+//! - The metadata log replays to its end; bytes after the last valid batch
+//!   (e.g. a batch torn by a crash) are reported.
+//! - Every key points to a slot of an existing size class, aligned to its
+//!   slots, within the slab file, holding a value that fits the slot and
+//!   decodes. Zstd values carry a checksum, see
+//!   [`compression`](super::compression).
+//! - No two keys point to the same slot.
+//! - No slot holds a value that no key refers to. Freed slots have a zero
+//!   length prefix, so these orphans are left by writes that never
+//!   committed. The allocator reuses them when the store is opened.
+//!
+//! # Repair
+//!
+//! With [`FsckOptions::repair`] the log is cut after its last valid batch,
+//! keys pointing to bad slots are removed, and orphaned slots are freed.
+//! Keys sharing a slot are only reported: which one is right cannot be
+//! told.
+
+use super::allocator::parse_slab_file_name;
+use super::compression::{decompress, CompressionAlgorithm};
+use super::metadata::MetadataStore;
+use super::overflow::OverflowStore;
+use super::slot::SlotId;
+use crate::error::{Error, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// How to check a store
+#[derive(Debug, Clone, Copy)]
+pub struct FsckOptions {
+    /// Compression the store was written with
+    pub compression: CompressionAlgorithm,
+    /// Fix what can be fixed instead of only reporting it
+    pub repair: bool,
+}
+
+impl Default for FsckOptions {
+    fn default() -> Self {
+        Self {
+            compression: CompressionAlgorithm::Zstd,
+            repair: false,
+        }
+    }
+}
+
+/// An inconsistency found by [`fsck`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FsckIssue {
+    /// A key points to a slot that does not hold a valid value
+    DanglingEntry {
+        key: Vec<u8>,
+        slot: SlotId,
+        reason: String,
+    },
+    /// A key points to a slot another key already points to
+    SharedSlot {
+        key: Vec<u8>,
+        other_key: Vec<u8>,
+        slot: SlotId,
+    },
+    /// A slot holds a value no key refers to
+    OrphanedSlot { slot: SlotId, bytes: u64 },
+}
+
+impl std::fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DanglingEntry { key, slot, reason } => write!(
+                f,
+                "key '{}' points to {}: {}",
+                String::from_utf8_lossy(key),
+                slot,
+                reason
+            ),
+            Self::SharedSlot {
+                key,
+                other_key,
+                slot,
+            } => write!(
+                f,
+                "keys '{}' and '{}' both point to {}",
+                String::from_utf8_lossy(other_key),
+                String::from_utf8_lossy(key),
+                slot
+            ),
+            Self::OrphanedSlot { slot, bytes } => {
+                write!(f, "{} holds {} bytes no key refers to", slot, bytes)
+            }
+        }
+    }
+}
+
+/// Result of [`fsck`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FsckReport {
+    /// Metadata batches replayed
+    pub batches: u64,
+    /// Bytes after the last valid batch of the metadata log
+    pub torn_log_bytes: u64,
+    /// Keys whose slots were checked
+    pub keys_checked: usize,
+    /// Slab slots and overflow objects on disk
+    pub slots_scanned: u64,
+    pub issues: Vec<FsckIssue>,
+    /// Whether the repairable issues were fixed
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /// Whether nothing is wrong with the store
+    pub fn is_clean(&self) -> bool {
+        self.torn_log_bytes == 0 && self.issues.is_empty()
+    }
+
+    /// Space held by orphaned slots (bytes)
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.issues
+            .iter()
+            .map(|issue| match issue {
+                FsckIssue::OrphanedSlot { bytes, .. } => *bytes,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// A size class's slab file, opened for checking
+struct SlabFile {
+    file: File,
+    slot_size: u64,
+    len: u64,
+}
+
+impl SlabFile {
+    /// Length prefix of the slot at `offset`
+    fn prefix(&mut self, offset: u64) -> std::io::Result<u32> {
+        let mut len_bytes = [0u8; 4];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut len_bytes)?;
+        Ok(u32::from_le_bytes(len_bytes))
+    }
+
+    /// Value stored in the slot at `offset`, or why there is none
+    fn value(&mut self, offset: u64) -> std::result::Result<Vec<u8>, String> {
+        if !offset.is_multiple_of(self.slot_size) {
+            return Err(format!("not aligned to {}-byte slots", self.slot_size));
+        }
+        if offset + 4 > self.len {
+            return Err("past the end of the slab file".to_string());
+        }
+        let len = self
+            .prefix(offset)
+            .map_err(|e| format!("unreadable: {}", e))? as u64;
+        if len == 0 {
+            return Err("slot is free".to_string());
+        }
+        if len + 4 > self.slot_size {
+            return Err(format!(
+                "value of {} bytes does not fit the {}-byte slot",
+                len, self.slot_size
+            ));
+        }
+        let mut data = vec![0u8; len as usize];
+        self.file
+            .read_exact(&mut data)
+            .map_err(|_| "value is truncated".to_string())?;
+        Ok(data)
+    }
+}
+
+/// Check the slab store at `base_path`, see the [module docs](self)
+pub fn fsck<P: AsRef<Path>>(base_path: P, options: FsckOptions) -> Result<FsckReport> {
+    let base_path = base_path.as_ref();
+    let data_path = base_path.join("data");
+    let log_path = base_path.join("metadata").join("metadata.log");
+    if !data_path.is_dir() {
+        return Err(Error::NotFound(format!(
+            "No slab store at {}",
+            base_path.display()
+        )));
+    }
+    info!(path = ?base_path, repair = options.repair, "Checking slab store");
+
+    let replay = MetadataStore::replay(&log_path)?;
+    let mut report = FsckReport {
+        batches: replay.batches,
+        torn_log_bytes: replay.total_len - replay.valid_len,
+        keys_checked: replay.index.len(),
+        ..FsckReport::default()
+    };
+
+    let mut slabs = open_slab_files(&data_path, options.repair)?;
+    let overflow = OverflowStore::open(&data_path)?;
+
+    // Every key's slot must hold a value that decodes
+    let mut owners: HashMap<SlotId, &Vec<u8>> = HashMap::new();
+    for (key, &slot) in &replay.index {
+        if let Some(other_key) = owners.insert(slot, key) {
+            report.issues.push(FsckIssue::SharedSlot {
+                key: key.clone(),
+                other_key: other_key.clone(),
+                slot,
+            });
+            continue;
+        }
+
+        let value = if slot.is_overflow() {
+            overflow
+                .read(slot.offset)
+                .map_err(|_| "overflow object is missing".to_string())
+        } else {
+            match slabs.get_mut(&slot.size_class) {
+                Some(slab) => slab.value(slot.offset),
+                None => Err(format!("no slab file for size class {}", slot.size_class)),
+            }
+        };
+        let decoded = value.and_then(|data| {
+            decompress(&data, options.compression)
+                .map(|_| ())
+                .map_err(|e| format!("value is corrupt: {}", e))
+        });
+        if let Err(reason) = decoded {
+            report.issues.push(FsckIssue::DanglingEntry {
+                key: key.clone(),
+                slot,
+                reason,
+            });
+        }
+    }
+
+    // Every slot holding a value must belong to a key
+    for (&class, slab) in slabs.iter_mut() {
+        let slot_size = slab.slot_size;
+        let slot_count = slab.len.div_ceil(slot_size);
+        report.slots_scanned += slot_count;
+        for offset in (0..slot_count).map(|slot| slot * slot_size) {
+            let slot = SlotId::new(class, offset);
+            if owners.contains_key(&slot) {
+                continue;
+            }
+            // A slot cut short by the end of the file held a partial write
+            let bytes = match slab.prefix(offset) {
+                Ok(0) => continue,
+                Ok(len) => (len as u64 + 4).min(slot_size),
+                Err(_) => slab.len - offset,
+            };
+            report.issues.push(FsckIssue::OrphanedSlot { slot, bytes });
+        }
+    }
+    for id in overflow.ids()? {
+        report.slots_scanned += 1;
+        let slot = SlotId::overflow(id);
+        if !owners.contains_key(&slot) {
+            let bytes = std::fs::metadata(overflow.path(id))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            report.issues.push(FsckIssue::OrphanedSlot { slot, bytes });
+        }
+    }
+
+    for issue in &report.issues {
+        warn!("fsck: {}", issue);
+    }
+    if options.repair && !report.is_clean() {
+        repair(
+            base_path, &log_path, &replay, &mut slabs, &overflow, &report,
+        )?;
+        report.repaired = true;
+    }
+
+    info!(
+        keys = report.keys_checked,
+        slots = report.slots_scanned,
+        issues = report.issues.len(),
+        torn_log_bytes = report.torn_log_bytes,
+        "Slab store check complete"
+    );
+    Ok(report)
+}
+
+/// Fix the issues of `report` that can be fixed
+fn repair(
+    base_path: &Path,
+    log_path: &Path,
+    replay: &super::metadata::LogReplay,
+    slabs: &mut BTreeMap<u16, SlabFile>,
+    overflow: &OverflowStore,
+    report: &FsckReport,
+) -> Result<()> {
+    if report.torn_log_bytes > 0 {
+        OpenOptions::new()
+            .write(true)
+            .open(log_path)
+            .and_then(|log| log.set_len(replay.valid_len).and_then(|_| log.sync_all()))
+            .map_err(|e| Error::Storage(format!("Failed to cut metadata log: {}", e)))?;
+    }
+
+    let dangling: Vec<_> = report
+        .issues
+        .iter()
+        .filter_map(|issue| match issue {
+            FsckIssue::DanglingEntry { key, .. } => Some(key.clone()),
+            _ => None,
+        })
+        .collect();
+    if !dangling.is_empty() {
+        let metadata = MetadataStore::new(base_path.join("metadata"))?;
+        metadata.commit_batch(Vec::new(), dangling)?;
+        metadata.flush()?;
+    }
+
+    for issue in &report.issues {
+        let FsckIssue::OrphanedSlot { slot, .. } = issue else {
+            continue;
+        };
+        if slot.is_overflow() {
+            overflow.free(slot.offset)?;
+        } else if let Some(slab) = slabs.get_mut(&slot.size_class) {
+            // Zero the length prefix, as freeing a slot does
+            slab.file
+                .seek(SeekFrom::Start(slot.offset))
+                .and_then(|_| slab.file.write_all(&0u32.to_le_bytes()))
+                .map_err(|e| Error::Storage(format!("Failed to free {}: {}", slot, e)))?;
+        }
+    }
+    for slab in slabs.values() {
+        slab.file
+            .sync_all()
+            .map_err(|e| Error::Storage(format!("Flush failed: {}", e)))?;
+    }
+
+    info!(path = ?base_path, "Slab store repaired");
+    Ok(())
+}
+
+/// The slab files in `dir`, by size class
+fn open_slab_files(dir: &Path, writable: bool) -> Result<BTreeMap<u16, SlabFile>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| Error::Storage(format!("Failed to list slab files: {}", e)))?;
+
+    let mut slabs = BTreeMap::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let Some((class, slot_size)) = entry.file_name().to_str().and_then(parse_slab_file_name)
+        else {
+            continue;
+        };
+        let path: PathBuf = entry.path();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .open(&path)
+            .map_err(|e| Error::Storage(format!("Failed to open {}: {}", path.display(), e)))?;
+        let len = file
+            .metadata()
+            .map_err(|e| Error::Storage(format!("Failed to stat slab file: {}", e)))?
+            .len();
+        slabs.insert(
+            class,
+            SlabFile {
+                file,
+                slot_size: slot_size as u64,
+                len,
+            },
+        );
+    }
+    Ok(slabs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::slab::compression::compress;
+    use crate::storage::slab::{SlabAllocator, SlabStorage};
+
+    fn temp_store(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("slab_fsck_{}_{}", name, uuid::Uuid::new_v4()))
+    }
+
+    fn repair() -> FsckOptions {
+        FsckOptions {
+            repair: true,
+            ..FsckOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_fsck_healthy_store() -> Result<()> {
+        let path = temp_store("healthy");
+        {
+            let storage = SlabStorage::new(&path, Some(64), Some(512))?;
+            for i in 0..20 {
+                storage.set(
+                    format!("key{}", i).as_bytes(),
+                    format!("value {}", i).as_bytes(),
+                )?;
+            }
+            storage.set(b"key3", &b"updated".repeat(10))?;
+            storage.delete(b"key4")?;
+            storage.write_batch(
+                vec![(b"key5".to_vec(), b"batched".to_vec())],
+                vec![b"key6".to_vec()],
+            )?;
+        }
+        // Writes after a reopen reuse the freed slots
+        {
+            let storage = SlabStorage::new(&path, Some(64), Some(512))?;
+            storage.set(b"key20", b"value 20")?;
+            assert_eq!(storage.get(b"key0")?, Some(b"value 0".to_vec()));
+        }
+
+        let report = fsck(&path, FsckOptions::default())?;
+        assert!(report.is_clean(), "{:?}", report.issues);
+        assert_eq!(report.keys_checked, 19);
+        assert!(report.batches > 0);
+        assert!(report.slots_scanned >= 19);
+        assert!(!report.repaired);
+
+        std::fs::remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_fsck_dangling_entry() -> Result<()> {
+        let path = temp_store("dangling");
+        {
+            let storage = SlabStorage::new(&path, Some(64), Some(512))?;
+            storage.set(b"good", b"value")?;
+        }
+        // A key pointing past the end of its slab file, then a torn batch
+        let ghost = SlotId::new(0, 64 * 100);
+        MetadataStore::new(path.join("metadata"))?.write_batch(vec![(b"ghost".to_vec(), ghost)])?;
+        let log_path = path.join("metadata").join("metadata.log");
+        OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap()
+            .write_all(&[7, 0, 0])
+            .unwrap();
+
+        let report = fsck(&path, FsckOptions::default())?;
+        assert_eq!(report.torn_log_bytes, 3);
+        assert_eq!(
+            report.issues,
+            vec![FsckIssue::DanglingEntry {
+                key: b"ghost".to_vec(),
+                slot: ghost,
+                reason: "past the end of the slab file".to_string(),
+            }]
+        );
+
+        let report = fsck(&path, repair())?;
+        assert!(report.repaired);
+        assert!(fsck(&path, FsckOptions::default())?.is_clean());
+
+        let storage = SlabStorage::new(&path, Some(64), Some(512))?;
+        assert_eq!(storage.get(b"ghost")?, None);
+        assert_eq!(storage.get(b"good")?, Some(b"value".to_vec()));
+
+        std::fs::remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_fsck_orphaned_slot() -> Result<()> {
+        let path = temp_store("orphan");
+        {
+            let storage = SlabStorage::new(&path, Some(64), Some(512))?;
+            storage.set(b"a", b"value a")?;
+            storage.set(b"b", b"value b")?;
+        }
+        // A value written to a fresh slot whose metadata batch never made it
+        let data = compress(b"lost value", CompressionAlgorithm::Zstd)?;
+        let orphan = {
+            let allocator = SlabAllocator::new(path.join("data"), Some(64), Some(512))?;
+            allocator.recover(&MetadataStore::new(path.join("metadata"))?)?;
+            let slot = allocator.allocate(data.len())?;
+            allocator.write(slot, &data)?;
+            slot
+        };
+
+        let report = fsck(&path, FsckOptions::default())?;
+        assert_eq!(report.issues.len(), 1);
+        assert!(matches!(
+            report.issues[0],
+            FsckIssue::OrphanedSlot { slot, bytes } if slot == orphan && bytes > 4
+        ));
+        assert!(report.reclaimable_bytes() > 0);
+
+        fsck(&path, repair())?;
+        assert!(fsck(&path, FsckOptions::default())?.is_clean());
+
+        // The slot is handed out again
+        let allocator = SlabAllocator::new(path.join("data"), Some(64), Some(512))?;
+        allocator.recover(&MetadataStore::new(path.join("metadata"))?)?;
+        assert_eq!(allocator.allocate(data.len())?, orphan);
+
+        std::fs::remove_dir_all(path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_fsck_missing_store() {
+        assert!(matches!(
+            fsck(temp_store("missing"), FsckOptions::default()),
+            Err(Error::NotFound(_))
+        ));
+    }
+}
@@ -15,18 +15,47 @@
 //! ```
 //!
 //! Recovery: Read all batches sequentially, last write wins.
+//!
+//! The in-memory index keeps keys sorted, so keys sharing a prefix (e.g.
+//! the documents of a table) are read as one range, in key order.
+//!
+//! # Durability
+//!
+//! By default every batch is fsynced before it is acknowledged. A
+//! [`SyncPolicy`] can trade a bounded window of acknowledged-but-lost writes
+//! for throughput: with `EveryN` or `IntervalMs` a background flusher
+//! thread fsyncs the log for many batches at once. [`MetadataStore::flush`]
+//! is the barrier that makes everything written so far durable.
 
 use super::slot::SlotId;
 use crate::error::{Error, Result};
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Read, Write};
+use std::ops::Bound;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
+/// When metadata batches are fsynced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Before each batch is acknowledged
+    #[default]
+    Always,
+    /// In the background, once every `n` batches
+    EveryN(u64),
+    /// In the background, at most this many milliseconds after a batch
+    IntervalMs(u64),
+    /// Only on [`MetadataStore::flush`]
+    Manual,
+}
+
 /// A batch of metadata updates
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetadataBatch {
@@ -36,6 +65,9 @@ pub struct MetadataBatch {
     pub timestamp: u64,
     /// Key-to-slot mappings
     pub mappings: Vec<(Vec<u8>, SlotId)>,
+    /// Keys removed by this batch (applied after the mappings)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removals: Vec<Vec<u8>>,
 }
 
 impl MetadataBatch {
@@ -48,9 +80,16 @@ impl MetadataBatch {
                 .unwrap()
                 .as_millis() as u64,
             mappings,
+            removals: Vec::new(),
         }
     }
 
+    /// Also remove `removals` when this batch is applied
+    pub fn with_removals(mut self, removals: Vec<Vec<u8>>) -> Self {
+        self.removals = removals;
+        self
+    }
+
     /// Serialize to bytes with length prefix
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
         let json = serde_json::to_vec(self)
@@ -104,6 +143,19 @@ impl MetadataBatch {
     }
 }
 
+/// Outcome of replaying a metadata log, see [`MetadataStore::replay`]
+#[derive(Debug, Default)]
+pub struct LogReplay {
+    /// Key→slot mappings after the last valid batch
+    pub index: BTreeMap<Vec<u8>, SlotId>,
+    /// Valid batches replayed
+    pub batches: u64,
+    /// Length of the log up to the end of the last valid batch
+    pub valid_len: u64,
+    /// Length of the whole log
+    pub total_len: u64,
+}
+
 /// Atomic metadata store
 ///
 /// Stores key→slot mappings with atomic batch writes.
@@ -111,10 +163,123 @@ impl MetadataBatch {
 pub struct MetadataStore {
     /// Path to metadata log
     log_path: PathBuf,
-    /// In-memory index (key → slot)
-    index: Arc<RwLock<HashMap<Vec<u8>, SlotId>>>,
+    /// In-memory index (key → slot), sorted by key
+    index: Arc<RwLock<BTreeMap<Vec<u8>, SlotId>>>,
     /// Next sequence number
     next_sequence: Arc<RwLock<u64>>,
+    /// When batches are fsynced
+    policy: RwLock<SyncPolicy>,
+    /// Fsync state of the log, shared with the flusher
+    log: Arc<LogSync>,
+    /// Background fsyncs for `EveryN` and `IntervalMs`
+    flusher: Mutex<Option<Flusher>>,
+}
+
+/// Fsync bookkeeping of the metadata log
+///
+/// Batches are counted as they are appended; a sync records how many of
+/// them it made durable.
+struct LogSync {
+    log_path: PathBuf,
+    /// Batches appended since the store was opened
+    written: AtomicU64,
+    /// Batches known to be durable
+    synced: AtomicU64,
+    /// Held across an fsync, so that a sync finding another one under way
+    /// waits for it instead of returning early
+    syncing: Mutex<()>,
+    /// Fsyncs of the log since the store was opened
+    syncs: AtomicU64,
+}
+
+impl LogSync {
+    /// Count a batch appended to the log, returning how many are unsynced
+    fn appended(&self) -> u64 {
+        let written = self.written.fetch_add(1, Ordering::AcqRel) + 1;
+        written.saturating_sub(self.synced.load(Ordering::Acquire))
+    }
+
+    /// Batches appended but not known to be durable
+    fn unsynced(&self) -> u64 {
+        let synced = self.synced.load(Ordering::Acquire);
+        self.written.load(Ordering::Acquire).saturating_sub(synced)
+    }
+
+    /// Fsync the log if any batch is waiting for it
+    ///
+    /// Returns once every batch appended before the call is durable.
+    fn sync(&self) -> Result<()> {
+        self.sync_with(|| File::open(&self.log_path).and_then(|file| file.sync_all()))
+    }
+
+    /// [`LogSync::sync`] through the already open log `file`
+    fn sync_file(&self, file: &File) -> Result<()> {
+        self.sync_with(|| file.sync_all())
+    }
+
+    fn sync_with(&self, fsync: impl FnOnce() -> std::io::Result<()>) -> Result<()> {
+        let _syncing = self.syncing.lock().unwrap();
+        // Everything counted here was appended before the fsync starts
+        let written = self.written.load(Ordering::Acquire);
+        let synced = self.synced.load(Ordering::Acquire);
+        if written <= synced {
+            return Ok(());
+        }
+
+        fsync().map_err(|e| Error::Storage(format!("Failed to sync log: {}", e)))?;
+        self.synced.store(written, Ordering::Release);
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+        debug!(batches = written - synced, "Synced metadata log");
+        Ok(())
+    }
+}
+
+/// Background thread fsyncing the log when woken or every `interval`
+///
+/// Dropping it stops the thread after a last fsync.
+struct Flusher {
+    wake: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Flusher {
+    fn spawn(log: Arc<LogSync>, interval: Option<Duration>) -> Self {
+        let (wake, signals) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("metadata-flusher".to_string())
+            .spawn(move || loop {
+                let signal = match interval {
+                    Some(interval) => signals.recv_timeout(interval),
+                    None => signals.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                if let Err(e) = log.sync() {
+                    warn!("Background metadata sync failed: {}", e);
+                }
+                if signal == Err(RecvTimeoutError::Disconnected) {
+                    break;
+                }
+            })
+            .expect("failed to spawn metadata flusher thread");
+        Self {
+            wake: Some(wake),
+            thread: Some(thread),
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(wake) = &self.wake {
+            let _ = wake.send(());
+        }
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        self.wake.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 impl MetadataStore {
@@ -127,9 +292,18 @@ impl MetadataStore {
         let log_path = base_path.join("metadata.log");
 
         let mut store = Self {
-            log_path,
-            index: Arc::new(RwLock::new(HashMap::new())),
+            log_path: log_path.clone(),
+            index: Arc::new(RwLock::new(BTreeMap::new())),
             next_sequence: Arc::new(RwLock::new(0)),
+            policy: RwLock::new(SyncPolicy::Always),
+            log: Arc::new(LogSync {
+                log_path,
+                written: AtomicU64::new(0),
+                synced: AtomicU64::new(0),
+                syncing: Mutex::new(()),
+                syncs: AtomicU64::new(0),
+            }),
+            flusher: Mutex::new(None),
         };
 
         // Recover from existing log
@@ -151,7 +325,7 @@ impl MetadataStore {
             .map_err(|e| Error::Storage(format!("Failed to open log: {}", e)))?;
         let mut reader = BufReader::new(file);
 
-        let mut index = HashMap::new();
+        let mut index = BTreeMap::new();
         let mut max_sequence = 0u64;
         let mut batches_recovered = 0;
         let mut keys_recovered = 0;
@@ -184,6 +358,9 @@ impl MetadataStore {
                         index.insert(key, slot);
                         keys_recovered += 1;
                     }
+                    for key in batch.removals {
+                        index.remove(&key);
+                    }
                     max_sequence = max_sequence.max(batch.sequence);
                     batches_recovered += 1;
                 }
@@ -207,11 +384,58 @@ impl MetadataStore {
         Ok(())
     }
 
-    /// Write a batch of updates atomically
+    /// Replay the log at `log_path` without opening a store on it
     ///
-    /// Phase 4: Uses Rayon for parallel processing of large batches (>100 entries)
+    /// Replay stops at the first batch that is incomplete or fails its
+    /// checksum; whatever follows is left out of [`LogReplay::valid_len`].
+    /// A missing log replays as empty.
+    pub fn replay(log_path: &Path) -> Result<LogReplay> {
+        let bytes = match std::fs::read(log_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(LogReplay::default()),
+            Err(e) => return Err(Error::Storage(format!("Failed to read log: {}", e))),
+        };
+
+        let mut replay = LogReplay {
+            total_len: bytes.len() as u64,
+            ..LogReplay::default()
+        };
+        let mut offset = 0;
+        while let Some(len_bytes) = bytes.get(offset..offset + 4) {
+            let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            let Some(Ok(batch)) = bytes
+                .get(offset..offset + len + 8)
+                .map(MetadataBatch::from_bytes)
+            else {
+                break;
+            };
+            for (key, slot) in batch.mappings {
+                replay.index.insert(key, slot);
+            }
+            for key in batch.removals {
+                replay.index.remove(&key);
+            }
+            replay.batches += 1;
+            offset += len + 8;
+        }
+        replay.valid_len = offset as u64;
+        Ok(replay)
+    }
+
+    /// Write a batch of updates atomically
     pub fn write_batch(&self, mappings: Vec<(Vec<u8>, SlotId)>) -> Result<()> {
-        if mappings.is_empty() {
+        self.commit_batch(mappings, Vec::new())
+    }
+
+    /// Write updates and removals as one atomic batch
+    ///
+    /// Either every change is in the log and the index afterwards, or none is.
+    pub fn commit_batch(
+        &self,
+        mappings: Vec<(Vec<u8>, SlotId)>,
+        removals: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        if mappings.is_empty() && removals.is_empty() {
             return Ok(());
         }
 
@@ -223,14 +447,7 @@ impl MetadataStore {
             s
         };
 
-        // Parallel processing for large batches
-        let processed_mappings: Vec<_> = if mappings.len() > 100 {
-            mappings.into_par_iter().collect()
-        } else {
-            mappings
-        };
-
-        let batch = MetadataBatch::new(sequence, processed_mappings.clone());
+        let batch = MetadataBatch::new(sequence, mappings.clone()).with_removals(removals.clone());
         let bytes = batch.to_bytes()?;
 
         // Append to log file
@@ -242,23 +459,88 @@ impl MetadataStore {
 
         file.write_all(&bytes)
             .map_err(|e| Error::Storage(format!("Failed to write batch: {}", e)))?;
-
-        // Fsync for durability
-        file.sync_all()
-            .map_err(|e| Error::Storage(format!("Failed to sync log: {}", e)))?;
+        let pending = self.log.appended();
+
+        // Fsync for durability, unless the policy defers it
+        let policy = *self.policy.read().unwrap();
+        if policy == SyncPolicy::Always {
+            self.log.sync_file(&file)?;
+        } else if matches!(policy, SyncPolicy::EveryN(n) if pending >= n.max(1)) {
+            if let Some(flusher) = self.flusher.lock().unwrap().as_ref() {
+                flusher.wake();
+            }
+        }
 
         // Update in-memory index
         {
             let mut index = self.index.write().unwrap();
-            for (key, slot) in processed_mappings {
+            for (key, slot) in mappings {
                 index.insert(key, slot);
             }
+            for key in &removals {
+                index.remove(key);
+            }
         }
 
-        debug!(sequence, entries = batch.mappings.len(), "Wrote metadata batch");
+        debug!(
+            sequence,
+            entries = batch.mappings.len(),
+            removals = removals.len(),
+            "Wrote metadata batch"
+        );
         Ok(())
     }
 
+    /// Choose when batches are fsynced
+    ///
+    /// Batches not yet fsynced are still visible to reads, but can be lost
+    /// on a crash until the flusher or [`MetadataStore::flush`] syncs them.
+    pub fn set_sync_policy(&self, policy: SyncPolicy) {
+        let flusher = match policy {
+            SyncPolicy::EveryN(_) => Some(Flusher::spawn(self.log.clone(), None)),
+            SyncPolicy::IntervalMs(ms) => Some(Flusher::spawn(
+                self.log.clone(),
+                Some(Duration::from_millis(ms.max(1))),
+            )),
+            SyncPolicy::Always | SyncPolicy::Manual => None,
+        };
+        *self.policy.write().unwrap() = policy;
+        // Replacing a flusher syncs what it was responsible for
+        let previous = std::mem::replace(&mut *self.flusher.lock().unwrap(), flusher);
+        drop(previous);
+    }
+
+    /// Current fsync policy
+    pub fn sync_policy(&self) -> SyncPolicy {
+        *self.policy.read().unwrap()
+    }
+
+    /// Fsync all batches written so far
+    pub fn flush(&self) -> Result<()> {
+        self.log.sync()
+    }
+
+    /// Whether batches are waiting to be fsynced
+    pub fn has_unsynced(&self) -> bool {
+        self.log.unsynced() > 0
+    }
+
+    /// Batches appended to the log since the store was opened
+    pub fn batches_written(&self) -> u64 {
+        self.log.written.load(Ordering::Acquire)
+    }
+
+    /// Batches known to be durable, counted like
+    /// [`MetadataStore::batches_written`]
+    pub fn batches_synced(&self) -> u64 {
+        self.log.synced.load(Ordering::Acquire)
+    }
+
+    /// Fsyncs of the log since the store was opened
+    pub fn sync_count(&self) -> u64 {
+        self.log.syncs.load(Ordering::Relaxed)
+    }
+
     /// Get slot for a key
     pub fn get(&self, key: &[u8]) -> Option<SlotId> {
         self.index.read().unwrap().get(key).copied()
@@ -273,11 +555,48 @@ impl MetadataStore {
         Ok(())
     }
 
-    /// Get all keys
+    /// Get all key→slot mappings
+    pub fn entries(&self) -> Vec<(Vec<u8>, SlotId)> {
+        self.index
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, slot)| (key.clone(), *slot))
+            .collect()
+    }
+
+    /// Get all keys, in key order
     pub fn keys(&self) -> Vec<Vec<u8>> {
         self.index.read().unwrap().keys().cloned().collect()
     }
 
+    /// Keys starting with `prefix`, in key order
+    pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        self.index
+            .read()
+            .unwrap()
+            .range(prefix.to_vec()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Keys within `range`, in key order
+    pub fn keys_in_range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Vec<Vec<u8>> {
+        self.index
+            .read()
+            .unwrap()
+            .range(range)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Number of keys within `range`
+    pub fn count_in_range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> usize {
+        self.index.read().unwrap().range(range).count()
+    }
+
     /// Get number of keys
     pub fn len(&self) -> usize {
         self.index.read().unwrap().len()
@@ -288,31 +607,65 @@ impl MetadataStore {
         self.index.read().unwrap().is_empty()
     }
 
-    /// Compact the log (remove duplicates, keep only latest)
-    pub fn compact(&self) -> Result<()> {
-        info!("Compacting metadata log");
+    /// Current size of the metadata log in bytes
+    pub fn log_size(&self) -> u64 {
+        std::fs::metadata(&self.log_path)
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
+    /// Sequence number of the last batch written (0 if none)
+    pub fn last_sequence(&self) -> u64 {
+        self.next_sequence.read().unwrap().saturating_sub(1)
+    }
 
+    /// Write the current index as a single batch to a new log at `path`
+    ///
+    /// Unlike copying the log, this also captures removals that were only
+    /// applied in memory. Returns the sequence number of the last batch
+    /// the snapshot includes.
+    pub fn write_snapshot(&self, path: &Path) -> Result<u64> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| Error::Storage(format!("Failed to create metadata dir: {}", e)))?;
+        }
+        let sequence = self.last_sequence();
+        self.write_index_to(path, sequence)?;
+        Ok(sequence)
+    }
+
+    /// Write the whole index as one batch to `path`, replacing its contents
+    fn write_index_to(&self, path: &Path, sequence: u64) -> Result<()> {
         // Read current state
         let index = self.index.read().unwrap().clone();
 
-        // Write to temp file
-        let temp_path = self.log_path.with_extension("log.tmp");
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(&temp_path)
-            .map_err(|e| Error::Storage(format!("Failed to create temp log: {}", e)))?;
+            .open(path)
+            .map_err(|e| {
+                Error::Storage(format!("Failed to create log {}: {}", path.display(), e))
+            })?;
 
         // Write as single batch
         let mappings: Vec<_> = index.into_iter().collect();
-        let batch = MetadataBatch::new(0, mappings);
+        let batch = MetadataBatch::new(sequence, mappings);
         let bytes = batch.to_bytes()?;
 
         file.write_all(&bytes)
-            .map_err(|e| Error::Storage(format!("Failed to write compacted log: {}", e)))?;
+            .map_err(|e| Error::Storage(format!("Failed to write index: {}", e)))?;
         file.sync_all()
-            .map_err(|e| Error::Storage(format!("Failed to sync compacted log: {}", e)))?;
+            .map_err(|e| Error::Storage(format!("Failed to sync index: {}", e)))
+    }
+
+    /// Compact the log (remove duplicates, keep only latest)
+    pub fn compact(&self) -> Result<()> {
+        info!("Compacting metadata log");
+
+        // Write to temp file
+        let temp_path = self.log_path.with_extension("log.tmp");
+        self.write_index_to(&temp_path, 0)?;
 
         // Replace old log with compacted version
         std::fs::rename(&temp_path, &self.log_path)
@@ -401,6 +754,134 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_commit_batch_removals_survive_recovery() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("metadata_removals_{}", std::process::id()));
+
+        {
+            let store = MetadataStore::new(&temp_dir)?;
+            store.write_batch(vec![
+                (b"key1".to_vec(), SlotId::new(0, 0)),
+                (b"key2".to_vec(), SlotId::new(1, 64)),
+            ])?;
+            store.commit_batch(
+                vec![(b"key3".to_vec(), SlotId::new(0, 64))],
+                vec![b"key1".to_vec()],
+            )?;
+            assert_eq!(store.get(b"key1"), None);
+        }
+
+        {
+            let store = MetadataStore::new(&temp_dir)?;
+            assert_eq!(store.len(), 2);
+            assert_eq!(store.get(b"key1"), None);
+            assert_eq!(store.get(b"key3"), Some(SlotId::new(0, 64)));
+        }
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    /// Wait for the background flusher to reach `syncs`
+    fn wait_for_syncs(store: &MetadataStore, syncs: u64) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while store.sync_count() < syncs && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(store.sync_count(), syncs);
+    }
+
+    #[test]
+    fn test_sync_every_n_batches() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("metadata_every_n_{}", std::process::id()));
+        let store = MetadataStore::new(&temp_dir)?;
+        store.set_sync_policy(SyncPolicy::EveryN(10));
+
+        for round in 1..=3 {
+            for i in 0..9 {
+                store.write_batch(vec![(vec![round, i], SlotId::new(0, i as u64 * 64))])?;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+            assert_eq!(store.sync_count(), round as u64 - 1);
+            assert!(store.has_unsynced());
+
+            store.write_batch(vec![(vec![round, 9], SlotId::new(0, 9 * 64))])?;
+            wait_for_syncs(&store, round as u64);
+        }
+        assert_eq!(store.batches_written(), 30);
+        assert!(!store.has_unsynced());
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_deferred_policies_recover_after_flush() -> Result<()> {
+        for (name, policy) in [
+            ("every_n", SyncPolicy::EveryN(1000)),
+            ("interval", SyncPolicy::IntervalMs(60_000)),
+            ("manual", SyncPolicy::Manual),
+        ] {
+            let temp_dir = std::env::temp_dir().join(format!(
+                "metadata_flush_{}_{}",
+                name,
+                std::process::id()
+            ));
+            let store = MetadataStore::new(&temp_dir)?;
+            store.set_sync_policy(policy);
+            for i in 0..20 {
+                store.write_batch(vec![(vec![i], SlotId::new(0, i as u64 * 64))])?;
+            }
+            assert_eq!(store.sync_count(), 0);
+
+            store.flush()?;
+            assert_eq!(store.sync_count(), 1);
+            assert!(!store.has_unsynced());
+
+            // Crash: no destructor, so no final sync by the flusher
+            std::mem::forget(store);
+            let store = MetadataStore::new(&temp_dir)?;
+            assert_eq!(store.len(), 20);
+            assert_eq!(store.get(&[19]), Some(SlotId::new(0, 19 * 64)));
+            assert_eq!(store.sync_policy(), SyncPolicy::Always);
+
+            std::fs::remove_dir_all(temp_dir).ok();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_waits_for_sync_under_way() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("metadata_flush_barrier_{}", std::process::id()));
+        let store = MetadataStore::new(&temp_dir)?;
+        store.set_sync_policy(SyncPolicy::Manual);
+        store.write_batch(vec![(vec![1], SlotId::new(0, 0))])?;
+
+        // A slow background fsync is under way when the flush comes in
+        let log = store.log.clone();
+        let (started, fsyncing) = mpsc::channel();
+        let background = std::thread::spawn(move || {
+            log.sync_with(|| {
+                started.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+                Ok(())
+            })
+        });
+        fsyncing.recv().unwrap();
+
+        // The flush returns only once that fsync has made the batch durable
+        store.flush()?;
+        assert!(!store.has_unsynced());
+        assert_eq!(store.sync_count(), 1);
+        background.join().unwrap()?;
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
     #[test]
     fn test_metadata_store_compaction() -> Result<()> {
         let temp_dir =
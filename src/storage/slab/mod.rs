@@ -10,7 +10,8 @@
 //!   ├─→ SizeClass(64B)   → Free: [3, 7, 12]
 //!   ├─→ SizeClass(128B)  → Free: [1, 4]
 //!   ├─→ SizeClass(256B)  → Free: [2, 9]
-//!   └─→ SizeClass(512B)  → Free: []
+//!   ├─→ SizeClass(512B)  → Free: []
+//!   └─→ OverflowStore    → one file per value larger than any class
 //!
 //! MetadataStore (Atomic, No WAL)
 //!   └─→ key1 → SlotId(class=3, offset=128)
@@ -30,17 +31,25 @@ pub mod bench;
 pub mod cache;
 pub mod compression;
 pub mod engine;
+pub mod fsck;
 pub mod metadata;
+pub mod overflow;
 pub mod production_tests;
 pub mod size_class;
 pub mod slot;
+pub mod snapshot;
 pub mod storage;
 
-pub use allocator::SlabAllocator;
+pub use allocator::{SizeClassStats, SlabAllocator};
 pub use cache::{CacheStats, SlabCache};
-pub use compression::{compress, decompress, CompressionAlgorithm, CompressionStats};
-pub use engine::SlabStorageEngine;
-pub use metadata::{MetadataBatch, MetadataStore};
+pub use compression::{
+    compress, decompress, stored_mode, CompressionAlgorithm, CompressionStats, StoredMode,
+};
+pub use engine::{SlabStorageEngine, TableStats};
+pub use fsck::{fsck, FsckIssue, FsckOptions, FsckReport};
+pub use metadata::{LogReplay, MetadataBatch, MetadataStore, SyncPolicy};
+pub use overflow::OverflowStore;
 pub use size_class::SizeClass;
-pub use slot::{Slot, SlotId};
-pub use storage::{SlabStorage, StorageStats};
+pub use slot::{Slot, SlotId, OVERFLOW_CLASS};
+pub use snapshot::{SnapshotManifest, SNAPSHOT_FORMAT_VERSION};
+pub use storage::{CompactionReport, SlabStorage, StorageStats};
@@ -0,0 +1,167 @@
+//! Overflow store for values larger than the biggest size class
+//!
+//! Each large object lives in its own file next to the slab files, so
+//! freeing one returns its space to the filesystem immediately instead of
+//! leaving a huge hole in a slab.
+//!
+//! ```text
+//! data/
+//!   slab_0000_64.bin
+//!   ...
+//!   overflow_00000000000000000007.bin  ← SlotId(class=OVERFLOW, offset=7)
+//! ```
+
+use crate::error::{Error, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::debug;
+
+const FILE_PREFIX: &str = "overflow_";
+const FILE_SUFFIX: &str = ".bin";
+
+/// File-per-object store for oversized values
+#[derive(Debug)]
+pub struct OverflowStore {
+    dir: PathBuf,
+    next_id: AtomicU64,
+}
+
+impl OverflowStore {
+    /// Open the overflow objects in `dir`
+    ///
+    /// New ids continue after the highest one found, so existing objects
+    /// are never overwritten.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let next_id = Self::object_ids(&dir)?
+            .into_iter()
+            .max()
+            .map_or(0, |id| id + 1);
+
+        Ok(Self {
+            dir,
+            next_id: AtomicU64::new(next_id),
+        })
+    }
+
+    /// Reserve an id for a new object
+    pub fn allocate(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Write an object, replacing any previous contents
+    ///
+    /// The file is synced before returning: large objects are rare enough
+    /// that paying for durability here is cheaper than tracking them for
+    /// a later flush.
+    pub fn write(&self, id: u64, data: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.path(id))
+            .map_err(|e| Error::Storage(format!("Failed to open overflow object: {}", e)))?;
+        file.write_all(data)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::Storage(format!("Overflow write failed: {}", e)))?;
+
+        debug!("Wrote {} bytes to overflow object {}", data.len(), id);
+        Ok(())
+    }
+
+    /// Read a whole object
+    pub fn read(&self, id: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        File::open(self.path(id))
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| Error::Storage(format!("Overflow read failed for {}: {}", id, e)))?;
+        Ok(data)
+    }
+
+    /// Delete an object and release its disk space
+    pub fn free(&self, id: u64) -> Result<()> {
+        match std::fs::remove_file(self.path(id)) {
+            Ok(()) => {}
+            // Allocated but never written
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::Storage(format!(
+                    "Failed to free overflow object {}: {}",
+                    id, e
+                )))
+            }
+        }
+        debug!("Freed overflow object {}", id);
+        Ok(())
+    }
+
+    /// Number of objects and their total size on disk (bytes)
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        let mut objects = 0;
+        let mut bytes = 0;
+        for id in Self::object_ids(&self.dir)? {
+            if let Ok(metadata) = std::fs::metadata(self.path(id)) {
+                objects += 1;
+                bytes += metadata.len();
+            }
+        }
+        Ok((objects, bytes))
+    }
+
+    /// Ids of the objects on disk
+    pub fn ids(&self) -> Result<Vec<u64>> {
+        Self::object_ids(&self.dir)
+    }
+
+    /// File holding object `id`
+    pub(super) fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}{:020}{}", FILE_PREFIX, id, FILE_SUFFIX))
+    }
+
+    fn object_ids(dir: &Path) -> Result<Vec<u64>> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| Error::Storage(format!("Failed to list overflow objects: {}", e)))?;
+
+        Ok(entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                name.to_str()?
+                    .strip_prefix(FILE_PREFIX)?
+                    .strip_suffix(FILE_SUFFIX)?
+                    .parse()
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_store() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("overflow_test_{}", std::process::id()));
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let store = OverflowStore::open(&temp_dir)?;
+
+        let id = store.allocate();
+        store.write(id, b"large object")?;
+        assert_eq!(store.read(id)?, b"large object");
+        assert_eq!(store.usage()?, (1, 12));
+
+        // Reopening continues after existing ids
+        let reopened = OverflowStore::open(&temp_dir)?;
+        assert!(reopened.allocate() > id);
+
+        store.free(id)?;
+        assert!(store.read(id).is_err());
+        assert_eq!(store.usage()?, (0, 0));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+}
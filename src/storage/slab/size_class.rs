@@ -59,6 +59,12 @@ impl SizeClass {
         self.next_offset / self.slot_size as u64
     }
 
+    /// Forget all free slots and treat the first `slot_count` as allocated
+    pub fn reset(&mut self, slot_count: u64) {
+        self.free_slots.clear();
+        self.next_offset = slot_count * self.slot_size as u64;
+    }
+
     /// Check if a given size fits in this size class
     pub fn can_fit(&self, size: usize) -> bool {
         size <= self.slot_size
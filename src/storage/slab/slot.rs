@@ -3,6 +3,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// Size class marker for values kept in the overflow store
+pub const OVERFLOW_CLASS: u16 = u16::MAX;
+
 /// Unique identifier for a slot in the heap
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct SlotId {
@@ -18,6 +21,16 @@ impl SlotId {
         Self { size_class, offset }
     }
 
+    /// Reference a large object in the overflow store
+    pub fn overflow(id: u64) -> Self {
+        Self::new(OVERFLOW_CLASS, id)
+    }
+
+    /// Whether this slot lives in the overflow store rather than a size class
+    pub fn is_overflow(&self) -> bool {
+        self.size_class == OVERFLOW_CLASS
+    }
+
     /// Get the file index for this slot
     pub fn file_index(&self) -> usize {
         self.size_class as usize
@@ -26,7 +39,11 @@ impl SlotId {
 
 impl fmt::Display for SlotId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Slot(class={}, offset={})", self.size_class, self.offset)
+        if self.is_overflow() {
+            write!(f, "Overflow(id={})", self.offset)
+        } else {
+            write!(f, "Slot(class={}, offset={})", self.size_class, self.offset)
+        }
     }
 }
 
@@ -72,6 +89,8 @@ mod tests {
         assert_eq!(id.size_class, 5);
         assert_eq!(id.offset, 1024);
         assert_eq!(id.file_index(), 5);
+        assert!(!id.is_overflow());
+        assert!(SlotId::overflow(3).is_overflow());
     }
 
     #[test]
@@ -0,0 +1,107 @@
+//! Point-in-time snapshots of a slab store
+//!
+//! A snapshot directory has the same layout as a live store, plus a
+//! manifest describing it:
+//!
+//! ```text
+//! snapshot/
+//!   ├─→ snapshot.json        (format version, sequence, slot sizes, ...)
+//!   ├─→ data/slab_*.bin      (copies of the slab files)
+//!   └─→ metadata/metadata.log (live key→slot mappings as a single batch)
+//! ```
+//!
+//! See [`SlabStorage::snapshot`](super::SlabStorage::snapshot) and
+//! [`SlabStorage::restore`](super::SlabStorage::restore).
+
+use super::compression::CompressionAlgorithm;
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Current snapshot format version
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Name of the manifest file inside a snapshot directory
+pub const MANIFEST_FILE: &str = "snapshot.json";
+
+/// Description of a snapshot, stored as `snapshot.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// Snapshot format version
+    pub format_version: u32,
+    /// Sequence number of the last metadata batch included
+    pub sequence: u64,
+    /// Creation time (milliseconds since epoch)
+    pub created_at: u64,
+    /// Number of keys in the snapshot
+    pub key_count: usize,
+    /// Smallest slot size of the source store
+    pub min_slot_size: usize,
+    /// Largest slot size of the source store
+    pub max_slot_size: usize,
+    /// Compression used for stored values
+    pub compression: CompressionAlgorithm,
+}
+
+impl SnapshotManifest {
+    /// Write the manifest into a snapshot directory
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::SerializationError(e.to_string()))?;
+        std::fs::write(dir.join(MANIFEST_FILE), json)
+            .map_err(|e| Error::Storage(format!("Failed to write snapshot manifest: {}", e)))
+    }
+
+    /// Read and validate the manifest of a snapshot directory
+    pub fn read(dir: &Path) -> Result<Self> {
+        let bytes = std::fs::read(dir.join(MANIFEST_FILE)).map_err(|e| {
+            Error::Storage(format!(
+                "Failed to read snapshot manifest in {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        let manifest: Self = serde_json::from_slice(&bytes)
+            .map_err(|e| Error::Storage(format!("Invalid snapshot manifest: {}", e)))?;
+
+        if manifest.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(Error::Storage(format!(
+                "Unsupported snapshot format version {} (expected {})",
+                manifest.format_version, SNAPSHOT_FORMAT_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Whether `dir` is missing or empty
+pub(crate) fn is_empty_dir(dir: &Path) -> Result<bool> {
+    match std::fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(Error::Storage(format!(
+            "Failed to read {}: {}",
+            dir.display(),
+            e
+        ))),
+    }
+}
+
+/// Copy every regular file of `from` into `to`, fsyncing each copy
+pub(crate) fn copy_files(from: &Path, to: &Path) -> Result<()> {
+    let io_error = |e: std::io::Error| Error::Storage(format!("Snapshot copy failed: {}", e));
+
+    std::fs::create_dir_all(to).map_err(io_error)?;
+    for entry in std::fs::read_dir(from).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if !entry.file_type().map_err(io_error)?.is_file() {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        std::fs::copy(entry.path(), &target).map_err(io_error)?;
+        std::fs::File::open(&target)
+            .and_then(|f| f.sync_all())
+            .map_err(io_error)?;
+    }
+    Ok(())
+}
@@ -7,15 +7,37 @@
 //! - Transparent zstd compression
 //! - LRU cache for hot data
 //! - Cache statistics
-
-use super::allocator::SlabAllocator;
-use super::cache::SlabCache;
-use super::compression::{compress, decompress, CompressionAlgorithm};
-use super::metadata::MetadataStore;
-use crate::error::Result;
-use std::path::Path;
-use std::sync::Arc;
-use tracing::{debug, info};
+//!
+//! # Parallelism
+//!
+//! Large write batches are compressed in parallel on a Rayon pool owned by
+//! the store, so storage work neither waits for nor crowds out other users
+//! of Rayon's global pool. The pool is sized with
+//! [`SlabStorage::with_threads`] and started on first use.
+
+use super::allocator::{SizeClassStats, SlabAllocator};
+use super::cache::{CacheStats, SlabCache};
+use super::compression::{
+    compress, decompress, stored_mode, CompressionAlgorithm, CompressionStats, StoredMode,
+};
+use super::metadata::{MetadataStore, SyncPolicy};
+use super::slot::SlotId;
+use super::snapshot::{copy_files, is_empty_dir, SnapshotManifest, SNAPSHOT_FORMAT_VERSION};
+use crate::cluster::metrics::storage_op_timer;
+use crate::error::{Error, Result};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::Serialize;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tracing::{debug, info, warn};
+
+/// Default number of values kept in the LRU cache
+const DEFAULT_CACHE_CAPACITY: usize = 1000;
+
+/// Sets in a write batch above which values are compressed in parallel
+const PARALLEL_BATCH_THRESHOLD: usize = 100;
 
 /// Complete slab-based storage engine
 ///
@@ -25,10 +47,24 @@ use tracing::{debug, info};
 /// - Compression for space efficiency
 /// - LRU cache for performance
 pub struct SlabStorage {
+    base_path: PathBuf,
     allocator: Arc<SlabAllocator>,
     metadata: Arc<MetadataStore>,
     cache: SlabCache,
     compression: CompressionAlgorithm,
+    /// Writers hold this shared; [`SlabStorage::snapshot`] holds it
+    /// exclusively to freeze the store while copying
+    write_gate: RwLock<()>,
+    /// Threads of `pool`, 0 for one per core
+    threads: usize,
+    /// Pool for parallel work, started on first use
+    pool: OnceLock<ThreadPool>,
+    /// Slots no longer referenced, each with the number of metadata
+    /// batches that must be durable before it is freed
+    ///
+    /// Until the batch dropping a slot is fsynced, a crash brings back the
+    /// mapping to it, so the slot keeps its value until then.
+    deferred_frees: Mutex<Vec<(u64, SlotId)>>,
 }
 
 impl SlabStorage {
@@ -48,7 +84,7 @@ impl SlabStorage {
             min_slot_size,
             max_slot_size,
             CompressionAlgorithm::Zstd,
-            1000,
+            DEFAULT_CACHE_CAPACITY,
         )
     }
 
@@ -70,32 +106,44 @@ impl SlabStorage {
 
         let allocator = Arc::new(SlabAllocator::new(&data_path, min_slot_size, max_slot_size)?);
         let metadata = Arc::new(MetadataStore::new(&meta_path)?);
+        allocator.recover(&metadata)?;
         let cache = SlabCache::new(cache_capacity);
 
         Ok(Self {
+            base_path: base_path.to_path_buf(),
             allocator,
             metadata,
             cache,
             compression,
+            write_gate: RwLock::new(()),
+            threads: 0,
+            pool: OnceLock::new(),
+            deferred_frees: Mutex::new(Vec::new()),
         })
     }
 
     /// Get value for a key (with caching)
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let _timer = storage_op_timer("get");
+
         // Check cache first
         if let Some((_slot_id, cached_data)) = self.cache.get(key) {
             return Ok(Some(cached_data));
         }
 
-        // Cache miss: Look up slot in metadata
-        let slot_id = match self.metadata.get(key) {
-            Some(id) => id,
-            None => return Ok(None),
+        // Cache miss: Look up slot in metadata and read compressed data,
+        // again if compaction moved the value in the meantime
+        let (slot_id, compressed) = loop {
+            let slot_id = match self.metadata.get(key) {
+                Some(id) => id,
+                None => return Ok(None),
+            };
+            let read = self.allocator.read(slot_id);
+            if self.metadata.get(key) == Some(slot_id) {
+                break (slot_id, read?);
+            }
         };
 
-        // Read compressed data from allocator
-        let compressed = self.allocator.read(slot_id)?;
-
         // Decompress
         let data = decompress(&compressed, self.compression)?;
 
@@ -107,14 +155,14 @@ impl SlabStorage {
 
     /// Set key-value pair (with compression)
     pub fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let _timer = storage_op_timer("set");
+        let _gate = self.write_gate.read().unwrap();
+
         // Compress value
         let compressed = compress(value, self.compression)?;
 
-        // Check if key already exists
-        if let Some(old_slot) = self.metadata.get(key) {
-            // Free old slot
-            self.allocator.free(old_slot)?;
-        }
+        // The old value stays in place until the new one is committed
+        let old_slot = self.metadata.get(key);
 
         // Allocate new slot for compressed data
         let slot_id = self.allocator.allocate(compressed.len())?;
@@ -126,6 +174,8 @@ impl SlabStorage {
         self.metadata
             .write_batch(vec![(key.to_vec(), slot_id)])?;
 
+        self.free_after_sync(old_slot)?;
+
         // Invalidate cache
         self.cache.remove(key);
 
@@ -133,19 +183,76 @@ impl SlabStorage {
         Ok(())
     }
 
+    /// Apply several sets and deletes atomically
+    ///
+    /// New values are written to fresh slots first; the switch to them and
+    /// all deletions then happen in a single metadata batch. If anything
+    /// fails before that batch is durable, the fresh slots are released and
+    /// the store is left unchanged. Old slots are freed only after commit.
+    pub fn write_batch(
+        &self,
+        sets: Vec<(Vec<u8>, Vec<u8>)>,
+        deletes: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let _gate = self.write_gate.read().unwrap();
+        let compress_value = |(_, value): &(Vec<u8>, Vec<u8>)| compress(value, self.compression);
+        let compressed: Vec<Vec<u8>> = if sets.len() > PARALLEL_BATCH_THRESHOLD {
+            self.in_pool(|| sets.par_iter().map(compress_value).collect::<Result<_>>())??
+        } else {
+            sets.iter().map(compress_value).collect::<Result<_>>()?
+        };
+
+        let mut mappings = Vec::with_capacity(sets.len());
+        let staged = sets
+            .iter()
+            .zip(&compressed)
+            .try_for_each(|((key, _), compressed)| {
+                let slot_id = self.allocator.allocate(compressed.len())?;
+                mappings.push((key.clone(), slot_id));
+                self.allocator.write(slot_id, compressed)
+            });
+
+        let old_slots: Vec<_> = sets
+            .iter()
+            .map(|(key, _)| key)
+            .chain(deletes.iter())
+            .filter_map(|key| self.metadata.get(key))
+            .collect();
+
+        let committed =
+            staged.and_then(|_| self.metadata.commit_batch(mappings.clone(), deletes.clone()));
+        if let Err(e) = committed {
+            for (_, slot_id) in mappings {
+                if let Err(free_err) = self.allocator.free(slot_id) {
+                    warn!(slot = %slot_id, error = %free_err, "Failed to release staged slot");
+                }
+            }
+            return Err(e);
+        }
+
+        self.free_after_sync(old_slots)?;
+        for key in sets.iter().map(|(key, _)| key).chain(deletes.iter()) {
+            self.cache.remove(key);
+        }
+
+        debug!(sets = sets.len(), deletes = deletes.len(), "Committed write batch");
+        Ok(())
+    }
+
     /// Delete a key
     pub fn delete(&self, key: &[u8]) -> Result<bool> {
+        let _timer = storage_op_timer("delete");
+        let _gate = self.write_gate.read().unwrap();
+
         // Look up slot
         let slot_id = match self.metadata.get(key) {
             Some(id) => id,
             None => return Ok(false),
         };
 
-        // Free slot
-        self.allocator.free(slot_id)?;
-
-        // Remove from metadata
-        self.metadata.remove(key)?;
+        // Remove from metadata, then free the slot
+        self.metadata.commit_batch(Vec::new(), vec![key.to_vec()])?;
+        self.free_after_sync(Some(slot_id))?;
 
         // Invalidate cache
         self.cache.remove(key);
@@ -154,11 +261,26 @@ impl SlabStorage {
         Ok(true)
     }
 
-    /// List all keys
+    /// List all keys, in key order
     pub fn keys(&self) -> Vec<Vec<u8>> {
         self.metadata.keys()
     }
 
+    /// List the keys starting with `prefix`, in key order
+    pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        self.metadata.keys_with_prefix(prefix)
+    }
+
+    /// List the keys within `range`, in key order
+    pub fn keys_in_range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Vec<Vec<u8>> {
+        self.metadata.keys_in_range(range)
+    }
+
+    /// Count the keys within `range` without reading their values
+    pub fn count_in_range(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> usize {
+        self.metadata.count_in_range(range)
+    }
+
     /// Check if key exists
     pub fn contains_key(&self, key: &[u8]) -> bool {
         self.metadata.get(key).is_some()
@@ -174,21 +296,218 @@ impl SlabStorage {
         self.metadata.is_empty()
     }
 
+    /// Defer fsyncing metadata batches to [`SlabStorage::flush`]
+    ///
+    /// Bulk loads get much faster, but writes acknowledged since the last
+    /// flush can be lost on a crash.
+    pub fn with_sync_writes(self, sync: bool) -> Self {
+        self.with_sync_policy(if sync {
+            SyncPolicy::Always
+        } else {
+            SyncPolicy::Manual
+        })
+    }
+
+    /// Choose when metadata batches are fsynced, see [`SyncPolicy`]
+    pub fn with_sync_policy(self, policy: SyncPolicy) -> Self {
+        self.metadata.set_sync_policy(policy);
+        self
+    }
+
+    /// Size the pool for parallel work, 0 for one thread per core
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self.pool = OnceLock::new();
+        self
+    }
+
+    /// Run `op` on the store's pool, so that parallel iterators in it use
+    /// the pool's threads rather than the global pool
+    fn in_pool<R: Send>(&self, op: impl FnOnce() -> R + Send) -> Result<R> {
+        let pool = match self.pool.get() {
+            Some(pool) => pool,
+            None => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(self.threads)
+                    .thread_name(|i| format!("slab-worker-{}", i))
+                    .build()
+                    .map_err(|e| Error::Storage(format!("Failed to start thread pool: {}", e)))?;
+                debug!(
+                    threads = pool.current_num_threads(),
+                    "Started slab thread pool"
+                );
+                // Another thread may have won the race; its pool is as good
+                self.pool.get_or_init(|| pool)
+            }
+        };
+        Ok(pool.install(op))
+    }
+
     /// Flush all data to disk
+    ///
+    /// Returns once every acknowledged write is durable: slab files are
+    /// fsynced, then any metadata batches written in deferred mode.
     pub fn flush(&self) -> Result<()> {
         self.allocator.flush()?;
-        // Metadata is already fsynced on write
+        self.metadata.flush()?;
+        self.free_synced()
+    }
+
+    /// Free `slots`, dropped by the metadata batch just written, once that
+    /// batch is durable
+    fn free_after_sync(&self, slots: impl IntoIterator<Item = SlotId>) -> Result<()> {
+        let batch = self.metadata.batches_written();
+        self.deferred_frees
+            .lock()
+            .unwrap()
+            .extend(slots.into_iter().map(|slot_id| (batch, slot_id)));
+        self.free_synced()
+    }
+
+    /// Free the deferred slots whose batches have been fsynced
+    fn free_synced(&self) -> Result<()> {
+        let synced = self.metadata.batches_synced();
+        let ready: Vec<SlotId> = {
+            let mut deferred = self.deferred_frees.lock().unwrap();
+            let (ready, waiting) = deferred.drain(..).partition(|(batch, _)| *batch <= synced);
+            *deferred = waiting;
+            ready.into_iter().map(|(_, slot_id)| slot_id).collect()
+        };
+        for slot_id in ready {
+            self.allocator.free(slot_id)?;
+        }
         Ok(())
     }
 
     /// Compact metadata log
     pub fn compact_metadata(&self) -> Result<()> {
+        let _gate = self.write_gate.read().unwrap();
         self.metadata.compact()
     }
 
+    /// Compact on-disk structures and report the space reclaimed
+    ///
+    /// Rewrites the metadata log with only the live key→slot mappings,
+    /// then defragments the
+    /// slab files (see [`SlabAllocator::compact`]). Writes are blocked
+    /// throughout; reads are not.
+    pub fn compact(&self) -> Result<CompactionReport> {
+        let _gate = self.write_gate.write().unwrap();
+        // Compaction only keeps slots the metadata refers to
+        self.metadata.flush()?;
+        self.free_synced()?;
+        let metadata_bytes_before = self.metadata.log_size();
+        self.metadata.compact()?;
+        let metadata_bytes_after = self.metadata.log_size();
+
+        let slab = self.allocator.compact(&self.metadata)?;
+
+        let report = CompactionReport {
+            metadata_bytes_before,
+            metadata_bytes_after,
+            slab_bytes_before: slab.bytes_before,
+            slab_bytes_after: slab.bytes_after,
+            slots_moved: slab.slots_moved,
+        };
+        info!(reclaimed = report.bytes_reclaimed(), "Storage compaction complete");
+        Ok(report)
+    }
+
+    /// Write a consistent point-in-time copy of the store to `path`
+    ///
+    /// Writes are blocked while the slab files are flushed and copied and
+    /// the live key→slot mappings are written as a fresh metadata log.
+    /// `path` must not exist or be empty.
+    pub fn snapshot<P: AsRef<Path>>(&self, path: P) -> Result<SnapshotManifest> {
+        let path = path.as_ref();
+        if !is_empty_dir(path)? {
+            return Err(Error::AlreadyExists(format!(
+                "Snapshot directory {} is not empty",
+                path.display()
+            )));
+        }
+
+        let _frozen = self.write_gate.write().unwrap();
+        self.allocator.flush()?;
+
+        copy_files(&self.base_path.join("data"), &path.join("data"))?;
+        let sequence = self
+            .metadata
+            .write_snapshot(&path.join("metadata").join("metadata.log"))?;
+
+        let size_classes = self.allocator.stats().size_classes;
+        let manifest = SnapshotManifest {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            sequence,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            key_count: self.metadata.len(),
+            min_slot_size: size_classes.first().map_or(0, |sc| sc.slot_size),
+            max_slot_size: size_classes.last().map_or(0, |sc| sc.slot_size),
+            compression: self.compression,
+        };
+        // The manifest goes last: a directory without one is incomplete
+        manifest.write(path)?;
+
+        info!(path = ?path, sequence, keys = manifest.key_count, "Snapshot written");
+        Ok(manifest)
+    }
+
+    /// Rebuild a store at `base_path` from a snapshot directory
+    ///
+    /// `base_path` must not already contain a store.
+    pub fn restore<S: AsRef<Path>, P: AsRef<Path>>(snapshot_path: S, base_path: P) -> Result<Self> {
+        let snapshot_path = snapshot_path.as_ref();
+        let base_path = base_path.as_ref();
+        let manifest = SnapshotManifest::read(snapshot_path)?;
+
+        for dir in ["data", "metadata"] {
+            if !is_empty_dir(&base_path.join(dir))? {
+                return Err(Error::AlreadyExists(format!(
+                    "Cannot restore into {}: a store already exists there",
+                    base_path.display()
+                )));
+            }
+        }
+
+        copy_files(&snapshot_path.join("data"), &base_path.join("data"))?;
+        copy_files(&snapshot_path.join("metadata"), &base_path.join("metadata"))?;
+
+        let storage = Self::with_options(
+            base_path,
+            Some(manifest.min_slot_size),
+            Some(manifest.max_slot_size),
+            manifest.compression,
+            DEFAULT_CACHE_CAPACITY,
+        )?;
+        if storage.len() != manifest.key_count {
+            return Err(Error::Storage(format!(
+                "Restored {} keys but snapshot manifest lists {}",
+                storage.len(),
+                manifest.key_count
+            )));
+        }
+
+        info!(path = ?base_path, sequence = manifest.sequence, "Restored from snapshot");
+        Ok(storage)
+    }
+
+    /// Slot usage per size class, cheap enough to poll unlike
+    /// [`stats`](Self::stats)
+    pub fn size_class_stats(&self) -> Vec<SizeClassStats> {
+        self.allocator.size_class_stats()
+    }
+
     /// Get storage statistics including cache metrics
+    ///
+    /// Measuring compression reads every stored value, so this costs time
+    /// proportional to the size of the store.
     pub fn stats(&self) -> StorageStats {
         let slab_stats = self.allocator.stats();
+        let slab_bytes = self.allocator.disk_usage().unwrap_or(0);
+        let metadata_bytes = self.metadata.log_size();
         let cache_stats = self.cache.stats();
         StorageStats {
             key_count: self.len(),
@@ -197,19 +516,97 @@ impl SlabStorage {
             cache_hits: cache_stats.hits,
             cache_misses: cache_stats.misses,
             cache_hit_rate: cache_stats.hit_rate,
+            cache: cache_stats,
+            compression: self.compression_stats(),
+            disk_bytes: slab_bytes + metadata_bytes,
+            metadata_bytes,
+            overflow_objects: slab_stats.overflow_objects,
+            overflow_bytes: slab_stats.overflow_bytes,
+            size_class_stats: slab_stats.size_classes,
+            metadata_batches: self.metadata.batches_written(),
+            metadata_syncs: self.metadata.sync_count(),
+        }
+    }
+
+    /// Total value bytes before and after compression, and how many values
+    /// are stored uncompressed
+    fn compression_stats(&self) -> CompressionStats {
+        let (mut original, mut compressed) = (0, 0);
+        let (mut values, mut skipped) = (0, 0);
+        for key in self.metadata.keys() {
+            let Some(slot_id) = self.metadata.get(&key) else {
+                continue;
+            };
+            // Keys deleted since listing them are skipped
+            let Ok(data) = self.allocator.read(slot_id) else {
+                continue;
+            };
+            if let Ok(value) = decompress(&data, self.compression) {
+                original += value.len();
+                compressed += data.len();
+                values += 1;
+                if self.compression != CompressionAlgorithm::None
+                    && stored_mode(&data, self.compression) == StoredMode::Raw
+                {
+                    skipped += 1;
+                }
+            }
         }
+        CompressionStats::new(original, compressed).with_skipped(values, skipped)
     }
 }
 
 /// Storage statistics with cache metrics
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct StorageStats {
     pub key_count: usize,
     pub total_allocated: u64,
     pub size_classes: usize,
+    #[serde(skip)]
     pub cache_hits: u64,
+    #[serde(skip)]
     pub cache_misses: u64,
+    #[serde(skip)]
     pub cache_hit_rate: f64,
+    /// The cache figures above, together with the cache's size
+    pub cache: CacheStats,
+    /// Stored value bytes vs. their compressed size in the slabs
+    pub compression: CompressionStats,
+    /// Slab files plus metadata log (bytes)
+    pub disk_bytes: u64,
+    /// Metadata log alone (bytes)
+    pub metadata_bytes: u64,
+    /// Values too large for any size class
+    pub overflow_objects: u64,
+    /// Overflow store on disk (bytes)
+    pub overflow_bytes: u64,
+    /// Slot utilization per size class
+    pub size_class_stats: Vec<SizeClassStats>,
+    /// Metadata batches written since the store was opened
+    pub metadata_batches: u64,
+    /// Metadata log fsyncs since the store was opened
+    pub metadata_syncs: u64,
+}
+
+/// Result of [`SlabStorage::compact`]
+#[derive(Debug, Clone, Copy)]
+pub struct CompactionReport {
+    pub metadata_bytes_before: u64,
+    pub metadata_bytes_after: u64,
+    /// Slab and overflow files (bytes)
+    pub slab_bytes_before: u64,
+    pub slab_bytes_after: u64,
+    /// Live values relocated to fill gaps
+    pub slots_moved: u64,
+}
+
+impl CompactionReport {
+    /// Bytes freed on disk
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.metadata_bytes_before
+            .saturating_sub(self.metadata_bytes_after)
+            + self.slab_bytes_before.saturating_sub(self.slab_bytes_after)
+    }
 }
 
 #[cfg(test)]
@@ -331,6 +728,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_snapshot_and_restore() -> Result<()> {
+        let root = std::env::temp_dir().join(format!("slab_snapshot_{}", uuid::Uuid::new_v4()));
+        let storage = SlabStorage::new(root.join("live"), Some(64), Some(512))?;
+
+        storage.set(b"key1", b"value1")?;
+        storage.set(b"key2", b"value2")?;
+        storage.set(b"key3", b"value3")?;
+        storage.delete(b"key3")?;
+
+        let manifest = storage.snapshot(root.join("snap"))?;
+        assert_eq!(manifest.format_version, SNAPSHOT_FORMAT_VERSION);
+        assert_eq!(manifest.key_count, 2);
+        assert!(storage.snapshot(root.join("snap")).is_err());
+
+        // Changes after the snapshot are not part of it
+        storage.set(b"key1", b"changed")?;
+        storage.set(b"key4", b"value4")?;
+        storage.delete(b"key2")?;
+
+        let restored = SlabStorage::restore(root.join("snap"), root.join("restored"))?;
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.get(b"key1")?, Some(b"value1".to_vec()));
+        assert_eq!(restored.get(b"key2")?, Some(b"value2".to_vec()));
+        assert_eq!(restored.get(b"key3")?, None);
+        assert_eq!(restored.get(b"key4")?, None);
+
+        // Refuses to overwrite an existing store
+        assert!(SlabStorage::restore(root.join("snap"), root.join("live")).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+        Ok(())
+    }
+
     #[test]
     fn test_slab_storage_stats() -> Result<()> {
         let temp_dir =
@@ -344,9 +775,203 @@ mod tests {
         assert_eq!(stats.key_count, 2);
         assert!(stats.total_allocated > 0);
         assert!(stats.size_classes > 0);
+        assert_eq!(stats.compression.original_size, 12);
+
+        // Repetitive values compress well
+        storage.set(b"key3", &b"abcdefgh".repeat(40))?;
+        let stats = storage.stats();
+        assert_eq!(stats.compression.original_size, 332);
+        assert!(stats.compression.ratio > 0.0 && stats.compression.ratio < 1.0);
+        // The short values gain nothing from compression
+        assert_eq!(stats.compression.values, 3);
+        assert_eq!(stats.compression.skipped, 2);
+        let used: u64 = stats.size_class_stats.iter().map(|c| c.allocated_slots).sum();
+        assert_eq!(used, 3);
 
         // Cleanup
         std::fs::remove_dir_all(temp_dir).ok();
         Ok(())
     }
+
+    #[test]
+    fn test_slab_storage_overflow() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_storage_overflow_{}", std::process::id()));
+        let storage = SlabStorage::new(&temp_dir, None, None)?;
+
+        // 2 MB of pseudo-random bytes stays far above the 64 KB size class
+        // limit even after compression
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let large: Vec<u8> = (0..2 * 1024 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        storage.set(b"blob", &large)?;
+        storage.set(b"small", b"value")?;
+        let stats = storage.stats();
+        assert_eq!(stats.overflow_objects, 1);
+        assert!(stats.overflow_bytes > 2 * 1024 * 1024);
+
+        // Read back intact, bypassing the cache
+        storage.cache.clear();
+        assert_eq!(storage.get(b"blob")?, Some(large.clone()));
+
+        // Survives a reopen
+        drop(storage);
+        let storage = SlabStorage::new(&temp_dir, None, None)?;
+        assert_eq!(storage.get(b"blob")?, Some(large));
+
+        // Overwriting with a small value reclaims the overflow space
+        storage.set(b"blob", b"tiny")?;
+        assert_eq!(storage.get(b"blob")?, Some(b"tiny".to_vec()));
+        let stats = storage.stats();
+        assert_eq!(stats.overflow_objects, 0);
+        assert_eq!(stats.overflow_bytes, 0);
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_deferred_sync_until_flush() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_storage_deferred_{}", std::process::id()));
+        let storage = SlabStorage::new(&temp_dir, Some(64), Some(512))?.with_sync_writes(false);
+
+        storage.set(b"key1", b"value1")?;
+        storage.write_batch(vec![(b"key2".to_vec(), b"value2".to_vec())], vec![])?;
+        assert!(storage.metadata.has_unsynced());
+
+        storage.flush()?;
+        assert!(!storage.metadata.has_unsynced());
+
+        drop(storage);
+        let storage = SlabStorage::new(&temp_dir, Some(64), Some(512))?;
+        assert_eq!(storage.get(b"key1")?, Some(b"value1".to_vec()));
+        assert_eq!(storage.get(b"key2")?, Some(b"value2".to_vec()));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_replaced_slots_kept_until_sync() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_storage_deferred_free_{}", std::process::id()));
+        let storage = SlabStorage::new(&temp_dir, Some(64), Some(512))?.with_sync_writes(false);
+        storage.set(b"key1", b"old")?;
+        storage.set(b"key2", b"gone")?;
+        storage.flush()?;
+        let old = storage.metadata.get(b"key1").unwrap();
+        let deleted = storage.metadata.get(b"key2").unwrap();
+
+        // A crash before the next fsync brings back the old mappings, so
+        // their slots must still hold the values
+        storage.set(b"key1", b"new")?;
+        storage.delete(b"key2")?;
+        assert!(!storage.allocator.read(old)?.is_empty());
+        assert!(!storage.allocator.read(deleted)?.is_empty());
+
+        storage.flush()?;
+        assert!(storage.allocator.read(old)?.is_empty());
+        assert!(storage.allocator.read(deleted)?.is_empty());
+        assert_eq!(storage.get(b"key1")?, Some(b"new".to_vec()));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_compaction_reclaims_slab_space() -> Result<()> {
+        let temp_dir =
+            std::env::temp_dir().join(format!("slab_storage_compact_{}", std::process::id()));
+        let storage = Arc::new(SlabStorage::new(&temp_dir, Some(64), Some(512))?);
+        let value = |i: usize| format!("value-{:04}", i).repeat(4).into_bytes();
+
+        for i in 0..200 {
+            storage.set(format!("key{:04}", i).as_bytes(), &value(i))?;
+        }
+        for i in (0..200).step_by(2) {
+            storage.delete(format!("key{:04}", i).as_bytes())?;
+        }
+
+        // Survivors stay readable while their slots move
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader = {
+            let (storage, done) = (storage.clone(), done.clone());
+            std::thread::spawn(move || -> Result<()> {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    storage.cache.clear();
+                    for i in (1..200).step_by(2) {
+                        assert_eq!(storage.get(format!("key{:04}", i).as_bytes())?, Some(value(i)));
+                    }
+                }
+                Ok(())
+            })
+        };
+
+        let before = storage.allocator.disk_usage()?;
+        let report = storage.compact()?;
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        reader.join().unwrap()?;
+
+        assert_eq!(report.slots_moved, 50);
+        assert_eq!(report.slab_bytes_before, before);
+        assert!(report.slab_bytes_after < before);
+        assert_eq!(storage.allocator.disk_usage()?, report.slab_bytes_after);
+
+        // New writes go after the live slots instead of over them
+        storage.set(b"fresh", b"fresh value")?;
+        storage.cache.clear();
+        for i in (1..200).step_by(2) {
+            assert_eq!(storage.get(format!("key{:04}", i).as_bytes())?, Some(value(i)));
+        }
+        assert_eq!(storage.get(b"fresh")?, Some(b"fresh value".to_vec()));
+
+        // The relocations are durable
+        drop(storage);
+        let storage = SlabStorage::new(&temp_dir, Some(64), Some(512))?;
+        assert_eq!(storage.len(), 101);
+        assert_eq!(storage.get(b"key0199")?, Some(value(199)));
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_batch_on_own_pool() -> Result<()> {
+        let temp_dir = std::env::temp_dir().join(format!("slab_pool_{}", uuid::Uuid::new_v4()));
+        let storage = SlabStorage::new(&temp_dir, Some(64), Some(4096))?.with_threads(2);
+
+        let value = |i: usize| format!("value {} {}", i, "x".repeat(i % 500)).into_bytes();
+        let sets: Vec<_> = (0..1000)
+            .map(|i| (format!("key{:04}", i).into_bytes(), value(i)))
+            .collect();
+        storage.write_batch(sets, Vec::new())?;
+
+        // Parallel work runs on the store's two workers, not the global pool
+        let (threads, worker) = storage.in_pool(|| {
+            let name = std::thread::current().name().map(str::to_string);
+            (rayon::current_num_threads(), name)
+        })?;
+        assert_eq!(threads, 2);
+        assert!(worker.unwrap().starts_with("slab-worker-"));
+
+        storage.cache.clear();
+        assert_eq!(storage.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(
+                storage.get(format!("key{:04}", i).as_bytes())?,
+                Some(value(i))
+            );
+        }
+
+        std::fs::remove_dir_all(temp_dir).ok();
+        Ok(())
+    }
 }
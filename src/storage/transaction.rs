@@ -0,0 +1,140 @@
+//! Multi-document transactions
+//!
+//! A [`Transaction`] buffers sets and deletes in memory and hands them to
+//! [`StorageEngine::write_batch`](crate::storage::StorageEngine::write_batch)
+//! on commit. On the slab engine that becomes a single metadata batch, so
+//! either every write is visible afterwards or none is. Dropping a
+//! transaction without committing discards its writes.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::reql::Datum;
+use crate::storage::engine::Storage;
+
+/// Buffered writes against a [`Storage`]
+pub struct Transaction {
+    storage: Arc<Storage>,
+    /// Pending writes by key; `None` marks a delete. Later writes to the
+    /// same key replace earlier ones.
+    writes: BTreeMap<Vec<u8>, Option<Datum>>,
+}
+
+impl Transaction {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self {
+            storage,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Read a key, seeing this transaction's own uncommitted writes
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Datum>> {
+        match self.writes.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => self.storage.get(key).await,
+        }
+    }
+
+    pub fn set(&mut self, key: &[u8], value: Datum) {
+        self.writes.insert(key.to_vec(), Some(value));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Number of keys written
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Apply all buffered writes atomically
+    pub async fn commit(self) -> Result<()> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+        self.storage
+            .write_batch(self.writes.into_iter().collect())
+            .await
+    }
+
+    /// Discard all buffered writes
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SlabStorageEngine;
+
+    fn slab_storage(name: &str) -> (Arc<Storage>, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("txn_{}_{}", name, uuid::Uuid::new_v4()));
+        // Small maximum slot size so large values go to the overflow store
+        let engine = SlabStorageEngine::new(&dir, None, Some(1024)).unwrap();
+        (Arc::new(Storage::new(Box::new(engine))), dir)
+    }
+
+    #[tokio::test]
+    async fn test_commit_applies_all_writes() {
+        let (storage, dir) = slab_storage("commit");
+        storage.set(b"gone", Datum::Boolean(true)).await.unwrap();
+
+        let mut tx = storage.transaction();
+        tx.set(b"a", Datum::Number(1.0));
+        tx.set(b"b", Datum::Number(2.0));
+        tx.delete(b"gone");
+        assert_eq!(tx.get(b"a").await.unwrap(), Some(Datum::Number(1.0)));
+        assert_eq!(tx.get(b"gone").await.unwrap(), None);
+
+        // Nothing is visible before commit
+        assert_eq!(storage.get(b"a").await.unwrap(), None);
+        assert!(storage.get(b"gone").await.unwrap().is_some());
+
+        tx.commit().await.unwrap();
+        assert_eq!(storage.get(b"a").await.unwrap(), Some(Datum::Number(1.0)));
+        assert_eq!(storage.get(b"b").await.unwrap(), Some(Datum::Number(2.0)));
+        assert_eq!(storage.get(b"gone").await.unwrap(), None);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_leaves_store_unchanged() {
+        let (storage, dir) = slab_storage("abort");
+        storage.set(b"a", Datum::Number(1.0)).await.unwrap();
+
+        // A directory where the next overflow object goes makes its write fail
+        let blocked = dir.join("data").join(format!("overflow_{:020}.bin", 0));
+        std::fs::create_dir(&blocked).unwrap();
+
+        // Incompressible value larger than the biggest slot
+        let oversized: String = (0..200)
+            .map(|_| uuid::Uuid::new_v4().simple().to_string())
+            .collect();
+
+        let mut tx = storage.transaction();
+        tx.set(b"a", Datum::Number(2.0));
+        tx.set(b"b", Datum::Number(3.0));
+        tx.set(b"c", Datum::String(oversized));
+        assert!(tx.commit().await.is_err());
+
+        assert_eq!(storage.get(b"a").await.unwrap(), Some(Datum::Number(1.0)));
+        assert_eq!(storage.get(b"b").await.unwrap(), None);
+        assert_eq!(storage.get(b"c").await.unwrap(), None);
+
+        // The store still accepts writes afterwards
+        std::fs::remove_dir(&blocked).unwrap();
+        let mut tx = storage.transaction();
+        tx.set(b"b", Datum::Number(3.0));
+        tx.commit().await.unwrap();
+        assert_eq!(storage.get(b"b").await.unwrap(), Some(Datum::Number(3.0)));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
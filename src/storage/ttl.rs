@@ -0,0 +1,233 @@
+//! Document expiration (TTL)
+//!
+//! A document written with a time-to-live carries its expiry time in the
+//! reserved field [`EXPIRES_AT_FIELD`] (milliseconds since the epoch). The
+//! [`Storage`] wrapper hides that field from readers, skips expired documents
+//! on reads and scans and deletes them lazily. [`spawn_ttl_sweeper`] removes
+//! expired documents nobody reads.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::reql::Datum;
+use crate::storage::engine::Storage;
+
+/// Reserved document field holding the expiry time
+pub const EXPIRES_AT_FIELD: &str = "$expires_at$";
+
+/// Default interval between sweeps
+pub const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Documents examined per scan page while sweeping
+pub const SWEEP_PAGE_SIZE: usize = 1000;
+
+/// Milliseconds since the Unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Stamp a document with an expiry `ttl` from now
+pub fn with_expiry(doc: Datum, ttl: Duration) -> Result<Datum> {
+    match doc {
+        Datum::Object(_) => Ok(expiring_at(
+            doc,
+            now_millis().saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX)),
+        )),
+        _ => Err(Error::InvalidArgument(
+            "Only objects can have a TTL".to_string(),
+        )),
+    }
+}
+
+/// Stamp a document with the expiry time `at`; other values are returned
+/// unchanged
+pub(crate) fn expiring_at(doc: Datum, at: u64) -> Datum {
+    match doc {
+        Datum::Object(mut obj) => {
+            obj.insert(EXPIRES_AT_FIELD.to_string(), Datum::Number(at as f64));
+            Datum::Object(obj)
+        }
+        other => other,
+    }
+}
+
+/// Reject a document written by a user that sets the reserved expiry field
+///
+/// Expiry times are only set through a TTL, never as document content.
+pub fn check_reserved(doc: &Datum) -> Result<()> {
+    match doc.as_object() {
+        Some(obj) if obj.contains_key(EXPIRES_AT_FIELD) => Err(Error::InvalidArgument(format!(
+            "Field `{}` is reserved",
+            EXPIRES_AT_FIELD
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Expiry time of a document, if it has one
+pub fn expires_at(doc: &Datum) -> Option<u64> {
+    doc.as_object()?
+        .get(EXPIRES_AT_FIELD)?
+        .as_number()
+        .map(|n| n as u64)
+}
+
+/// Whether a document has expired at `now` (milliseconds since the epoch)
+pub fn is_expired(doc: &Datum, now: u64) -> bool {
+    expires_at(doc).is_some_and(|at| at <= now)
+}
+
+/// Remove the expiry field before handing a document to readers
+pub fn strip_expiry(doc: Datum) -> Datum {
+    match doc {
+        Datum::Object(mut obj) => {
+            obj.remove(EXPIRES_AT_FIELD);
+            Datum::Object(obj)
+        }
+        other => other,
+    }
+}
+
+/// Periodically delete expired documents from every table
+pub fn spawn_ttl_sweeper(storage: Arc<Storage>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match storage.sweep_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!(removed, "Swept expired documents"),
+                Err(e) => warn!(error = %e, "TTL sweep failed"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+
+    async fn storage_with_table() -> Arc<Storage> {
+        let storage = Arc::new(Storage::new(Box::new(MockStorage::new())));
+        storage.create_database("app").await.unwrap();
+        storage.create_table("app", "sessions", "id").await.unwrap();
+        storage
+    }
+
+    fn session(id: &str) -> Datum {
+        Datum::from(serde_json::json!({"id": id, "user": "alice"}))
+    }
+
+    #[tokio::test]
+    async fn test_expired_document_disappears() {
+        let storage = storage_with_table().await;
+        storage
+            .set_with_ttl(
+                b"doc:app:sessions:short",
+                session("short"),
+                Duration::from_millis(50),
+            )
+            .await
+            .unwrap();
+        storage
+            .set_with_ttl(
+                b"doc:app:sessions:long",
+                session("long"),
+                Duration::from_secs(3600),
+            )
+            .await
+            .unwrap();
+        storage
+            .set(b"doc:app:sessions:forever", session("forever"))
+            .await
+            .unwrap();
+
+        // Live documents are returned without the expiry field
+        let doc = storage.get(b"doc:app:sessions:short").await.unwrap();
+        assert_eq!(doc, Some(session("short")));
+        assert_eq!(
+            storage.scan_table("app", "sessions").await.unwrap().len(),
+            3
+        );
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+
+        assert_eq!(storage.get(b"doc:app:sessions:short").await.unwrap(), None);
+        let mut ids: Vec<String> = storage
+            .scan_table("app", "sessions")
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|d| d.as_object()?.get("id")?.as_string().map(String::from))
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["forever", "long"]);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_and_paged_scan() {
+        let storage = storage_with_table().await;
+        for i in 0..5 {
+            let key = format!("doc:app:sessions:s{}", i);
+            let ttl = if i % 2 == 0 {
+                Duration::from_millis(10)
+            } else {
+                Duration::from_secs(3600)
+            };
+            storage
+                .set_with_ttl(key.as_bytes(), session(&format!("s{}", i)), ttl)
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        // Pages stay full even when expired documents are skipped
+        let page = storage
+            .scan_table_page("app", "sessions", None, 2)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 2);
+
+        assert_eq!(storage.sweep_expired().await.unwrap(), 1);
+        assert_eq!(
+            storage.scan_table("app", "sessions").await.unwrap().len(),
+            2
+        );
+    }
+    #[tokio::test]
+    async fn test_rewrite_keeps_expiry() {
+        let storage = storage_with_table().await;
+        let key = b"doc:app:sessions:s";
+        storage
+            .set_with_ttl(key, session("s"), Duration::from_millis(50))
+            .await
+            .unwrap();
+
+        // Replacing the document keeps its expiry time
+        let renamed = Datum::from(serde_json::json!({"id": "s", "user": "bob"}));
+        storage.set(key, renamed.clone()).await.unwrap();
+        assert_eq!(storage.get(key).await.unwrap(), Some(renamed));
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(storage.get(key).await.unwrap(), None);
+
+        // A document written after the expired one is deleted does not expire
+        storage.set(key, session("s")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(storage.get(key).await.unwrap(), Some(session("s")));
+    }
+
+    #[test]
+    fn test_expiry_field_is_reserved() {
+        assert!(check_reserved(&session("s")).is_ok());
+        let doc = Datum::from(serde_json::json!({"id": "s", EXPIRES_AT_FIELD: 0}));
+        assert!(check_reserved(&doc).is_err());
+    }
+}
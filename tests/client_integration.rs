@@ -0,0 +1,69 @@
+//! End-to-end tests for the TCP server through the in-process client
+
+use photondb::network::{ConnectOptions, Connection, ProtocolServer, ServerConfig};
+use photondb::reql::Datum;
+use photondb::storage::slab::SlabStorageEngine;
+use photondb::storage::Storage;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Helper to start a server on a random port
+async fn start_server(name: &str) -> (std::net::SocketAddr, Arc<Storage>, std::path::PathBuf) {
+    let temp_dir =
+        std::env::temp_dir().join(format!("rethinkdb_client_{}_{}", name, std::process::id()));
+    let storage = Arc::new(Storage::new(Box::new(
+        SlabStorageEngine::with_defaults(&temp_dir).expect("Failed to create storage"),
+    )));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = ProtocolServer::new(ServerConfig::default(), storage.clone());
+    tokio::spawn(async move {
+        let _ = server.serve_listener(listener).await;
+    });
+
+    (addr, storage, temp_dir)
+}
+
+#[tokio::test]
+async fn test_client_db_list() {
+    let (addr, storage, temp_dir) = start_server("db_list").await;
+    storage.create_database("app").await.unwrap();
+
+    let options = ConnectOptions {
+        auth_key: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let conn = Connection::connect(addr, options)
+        .await
+        .expect("Failed to connect");
+
+    // DB_LIST: [79]
+    let result = conn.run(serde_json::json!([59])).await.unwrap();
+    let Datum::Array(dbs) = result else {
+        panic!("DB_LIST should return an array, got {:?}", result);
+    };
+    assert!(dbs.contains(&Datum::String("app".to_string())));
+
+    // The same result through a cursor
+    let cursor = conn.run_cursor(serde_json::json!([59])).await.unwrap();
+    assert_eq!(cursor.collect().await.unwrap(), dbs);
+
+    std::fs::remove_dir_all(temp_dir).ok();
+}
+
+#[tokio::test]
+async fn test_client_reports_query_errors() {
+    let (addr, _storage, temp_dir) = start_server("errors").await;
+    let conn = Connection::connect(addr, ConnectOptions::default())
+        .await
+        .expect("Failed to connect");
+
+    let error = conn.run(serde_json::json!([999999])).await;
+    assert!(error.is_err());
+
+    // The connection stays usable after an error
+    assert!(conn.run(serde_json::json!([59])).await.is_ok());
+
+    std::fs::remove_dir_all(temp_dir).ok();
+}
@@ -144,10 +144,10 @@ async fn test_db_list_query() {
     stream.read_exact(&mut response).await.unwrap();
 
     // Send DB_LIST query
-    // Query format: [79] (DB_LIST has term_type 79)
+    // Query format: [59] (DB_LIST has ql2 term id 59)
     let query_json = serde_json::json!({
         "type": "START",
-        "query": [79] // DB_LIST
+        "query": [59] // DB_LIST
     });
 
     let query_str = serde_json::to_string(&query_json).unwrap();